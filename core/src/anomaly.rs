@@ -0,0 +1,186 @@
+//! Steps between consecutive rows of a swap that its state machine doesn't
+//! allow. ASB records every state it passes through, so a gap or a step
+//! backwards usually means a damaged database, a clock that jumped, or a
+//! database written by an xmr-btc-swap version this monitor doesn't know.
+
+use serde::{Deserialize, Serialize};
+use std::collections::{HashSet, VecDeque};
+
+use crate::model::{Role, SwapRow};
+use crate::state::SwapState;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AnomalyKind {
+    /// The new state is reachable, but only through states that weren't
+    /// recorded
+    Skipped,
+    /// No path through the state machine leads to the new state
+    Impossible,
+}
+
+impl AnomalyKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            AnomalyKind::Skipped => "skipped",
+            AnomalyKind::Impossible => "impossible",
+        }
+    }
+
+    /// One line for alerts.
+    pub fn explain(self) -> &'static str {
+        match self {
+            AnomalyKind::Skipped => "the states in between were never recorded",
+            AnomalyKind::Impossible => "the state machine has no way there",
+        }
+    }
+}
+
+/// One unexpected step in a swap's recorded states.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Anomaly {
+    pub swap_id: String,
+    pub from: SwapState,
+    pub to: SwapState,
+    /// When the swap entered `to`
+    pub entered_at: String,
+    pub kind: AnomalyKind,
+    /// Swap amounts, for alert rules
+    #[serde(skip)]
+    pub btc_sats: Option<u64>,
+    #[serde(skip)]
+    pub xmr_piconero: Option<u64>,
+}
+
+impl Anomaly {
+    /// Check the step from a swap's `previous` row to its `next` one.
+    /// Unknown states can't be judged and pass.
+    pub fn check(previous: &SwapRow, next: &SwapRow, role: Role) -> Option<Self> {
+        let kind = classify(&previous.state, &next.state, role)?;
+        Some(Self {
+            swap_id: next.swap_id.clone(),
+            from: previous.state.clone(),
+            to: next.state.clone(),
+            entered_at: next.entered_at.clone(),
+            kind,
+            btc_sats: next.details.btc_sats.or(previous.details.btc_sats),
+            xmr_piconero: next.details.xmr_piconero.or(previous.details.xmr_piconero),
+        })
+    }
+}
+
+fn classify(from: &SwapState, to: &SwapState, role: Role) -> Option<AnomalyKind> {
+    let unknown = |state: &SwapState| matches!(state, SwapState::Unknown(_));
+    if from == to || unknown(from) || unknown(to) || from.next_states(role).contains(to) {
+        return None;
+    }
+    Some(if reachable(from, to, role) {
+        AnomalyKind::Skipped
+    } else {
+        AnomalyKind::Impossible
+    })
+}
+
+/// Whether some sequence of allowed steps leads from `from` to `to`.
+fn reachable(from: &SwapState, to: &SwapState, role: Role) -> bool {
+    let mut seen = HashSet::new();
+    let mut queue = VecDeque::from([from.clone()]);
+    while let Some(state) = queue.pop_front() {
+        for next in state.next_states(role) {
+            if next == *to {
+                return true;
+            }
+            if seen.insert(next.clone()) {
+                queue.push_back(next);
+            }
+        }
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::SwapDetails;
+
+    fn row(state: &str, btc_sats: Option<u64>) -> SwapRow {
+        SwapRow {
+            swap_id: "aaaa1111".into(),
+            state: state.into(),
+            entered_at: "2026-01-01 00:00:00".into(),
+            started_at: "2026-01-01 00:00:00".into(),
+            peer_id: None,
+            details: SwapDetails {
+                btc_sats,
+                ..SwapDetails::default()
+            },
+        }
+    }
+
+    fn kind(from: &str, to: &str, role: Role) -> Option<AnomalyKind> {
+        classify(&from.into(), &to.into(), role)
+    }
+
+    #[test]
+    fn allowed_steps_pass() {
+        assert_eq!(
+            kind("BtcLocked", "XmrLockTransactionSent", Role::Maker),
+            None
+        );
+        assert_eq!(
+            kind("CancelTimelockExpired", "BtcRefunded", Role::Maker),
+            None
+        );
+        assert_eq!(kind("EncSigSent", "BtcRedeemed", Role::Taker), None);
+        assert_eq!(kind("XmrLocked", "XmrLocked", Role::Maker), None);
+    }
+
+    #[test]
+    fn gaps_are_skips() {
+        assert_eq!(
+            kind("BtcLocked", "BtcRedeemed", Role::Maker),
+            Some(AnomalyKind::Skipped)
+        );
+        assert_eq!(
+            kind("Started", "XmrRedeemed", Role::Taker),
+            Some(AnomalyKind::Skipped)
+        );
+    }
+
+    #[test]
+    fn backwards_and_foreign_steps_are_impossible() {
+        assert_eq!(
+            kind("XmrLocked", "BtcLocked", Role::Maker),
+            Some(AnomalyKind::Impossible)
+        );
+        assert_eq!(
+            kind("BtcRedeemed", "BtcPunished", Role::Maker),
+            Some(AnomalyKind::Impossible)
+        );
+        // A taker's state in a maker's database
+        assert_eq!(
+            kind("BtcLocked", "XmrLockProofReceived", Role::Maker),
+            Some(AnomalyKind::Impossible)
+        );
+    }
+
+    #[test]
+    fn unknown_states_pass() {
+        assert_eq!(kind("BtcLocked", "BtcSomethingNew", Role::Maker), None);
+        assert_eq!(kind("BtcSomethingNew", "Started", Role::Maker), None);
+    }
+
+    #[test]
+    fn check_keeps_the_amounts_from_either_row() {
+        let anomaly = Anomaly::check(
+            &row("BtcLocked", Some(50_000)),
+            &row("BtcRedeemed", None),
+            Role::Maker,
+        )
+        .unwrap();
+        assert_eq!(anomaly.kind, AnomalyKind::Skipped);
+        assert_eq!(anomaly.from, SwapState::BtcLocked);
+        assert_eq!(anomaly.to, SwapState::BtcRedeemed);
+        assert_eq!(anomaly.btc_sats, Some(50_000));
+    }
+}
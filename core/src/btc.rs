@@ -0,0 +1,390 @@
+//! Recovering txids and scripts from the serialized state, with the
+//! `bitcoin` crate doing the encoding.
+//!
+//! ASB stores the BTC lock transaction as a PSBT (`tx_lock.inner`) rather
+//! than by txid, so the unsigned transaction is rebuilt from its JSON form
+//! to get one.
+//!
+//! The transactions that spend the lock aren't stored at all, but they are
+//! fully determined by it: xmr-btc-swap builds each one with a single input
+//! and output, from the lock output, the timelocks, the fees and the
+//! addresses in the state. Their txids don't depend on the signatures.
+//!
+//! The same goes for wallet scripts: watch-only balances only need the
+//! P2WPKH script of each derived key.
+
+use bitcoin::absolute::LockTime;
+use bitcoin::address::NetworkUnchecked;
+use bitcoin::hashes::{hash160, sha256, Hash};
+use bitcoin::transaction::Version;
+use bitcoin::{
+    Address, Amount, OutPoint, ScriptBuf, Sequence, Transaction, TxIn, TxOut, Txid, WPubkeyHash,
+};
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+/// Txid of the PSBT under a `tx_lock` value, if it has the expected shape.
+pub fn lock_txid(tx_lock: &Value) -> Option<String> {
+    txid(tx_lock.get("inner")?.get("unsigned_tx")?)
+}
+
+/// Txid of a transaction in rust-bitcoin's serde form.
+pub fn txid(tx: &Value) -> Option<String> {
+    Some(transaction(tx)?.compute_txid().to_string())
+}
+
+/// The parts of a transaction in rust-bitcoin's serde form that its txid
+/// covers; witnesses are left out.
+fn transaction(tx: &Value) -> Option<Transaction> {
+    let input = tx
+        .get("input")?
+        .as_array()?
+        .iter()
+        .map(|input| {
+            Some(TxIn {
+                previous_output: outpoint(input.get("previous_output")?)?,
+                script_sig: script(input.get("script_sig")?)?,
+                sequence: Sequence(input.get("sequence")?.as_u64()?.try_into().ok()?),
+                witness: Default::default(),
+            })
+        })
+        .collect::<Option<_>>()?;
+    let output = tx
+        .get("output")?
+        .as_array()?
+        .iter()
+        .map(|output| {
+            Some(TxOut {
+                value: Amount::from_sat(output.get("value")?.as_u64()?),
+                script_pubkey: script(output.get("script_pubkey")?)?,
+            })
+        })
+        .collect::<Option<_>>()?;
+    Some(Transaction {
+        version: Version(tx.get("version")?.as_i64()?.try_into().ok()?),
+        lock_time: LockTime::from_consensus(tx.get("lock_time")?.as_u64()?.try_into().ok()?),
+        input,
+        output,
+    })
+}
+
+/// Txids of the lock's redeem, cancel, refund and punish transactions, as
+/// far as `state` (the object holding `tx_lock`) has what they are built
+/// from. Keyed `btc_redeem` and so on; whether they were ever published is
+/// up to the swap's state.
+pub fn spend_txids(state: &Value) -> BTreeMap<String, String> {
+    let mut txids = BTreeMap::new();
+    let Some((lock, lock_value, lock_script)) = lock_output(state) else {
+        return txids;
+    };
+    let number = |key: &str| state.get(key).and_then(Value::as_u64);
+    let address = |key: &str| state.get(key)?.as_str().and_then(address_script);
+
+    if let (Some(fee), Some(script)) = (number("tx_redeem_fee"), address("redeem_address")) {
+        if let Some(value) = lock_value.checked_sub(fee) {
+            let txid = spend_txid(lock, Sequence::MAX, value, script.into());
+            txids.insert("btc_redeem".to_string(), txid.to_string());
+        }
+    }
+
+    // The cancel output goes back to the same 2-of-2 as the lock
+    let (Some(fee), Some(timelock)) = (number("tx_cancel_fee"), number("cancel_timelock")) else {
+        return txids;
+    };
+    let Some(cancel_value) = lock_value.checked_sub(fee) else {
+        return txids;
+    };
+    let cancel_txid = spend_txid(lock, Sequence(timelock as u32), cancel_value, lock_script);
+    let cancel = OutPoint::new(cancel_txid, 0);
+    let spends = [
+        ("btc_refund", "tx_refund_fee", "refund_address", None),
+        (
+            "btc_punish",
+            "tx_punish_fee",
+            "punish_address",
+            number("punish_timelock"),
+        ),
+    ];
+    for (label, fee, to, timelock) in spends {
+        let (Some(fee), Some(script)) = (number(fee), address(to)) else {
+            continue;
+        };
+        let Some(value) = cancel_value.checked_sub(fee) else {
+            continue;
+        };
+        let sequence = timelock.map_or(Sequence::MAX, |blocks| Sequence(blocks as u32));
+        let txid = spend_txid(cancel, sequence, value, script.into());
+        txids.insert(label.to_string(), txid.to_string());
+    }
+    txids.insert("btc_cancel".to_string(), cancel_txid.to_string());
+    txids
+}
+
+/// The lock transaction's 2-of-2 output: where it is, its value and its
+/// script. That's the P2WSH output, or the one carrying the swap amount if
+/// there are several.
+fn lock_output(state: &Value) -> Option<(OutPoint, u64, ScriptBuf)> {
+    let tx = transaction(state.get("tx_lock")?.get("inner")?.get("unsigned_tx")?)?;
+    let amount = state.get("btc").and_then(Value::as_u64);
+    let (vout, output) = tx
+        .output
+        .iter()
+        .enumerate()
+        .filter(|(_, output)| output.script_pubkey.is_p2wsh())
+        .min_by_key(|(_, output)| Some(output.value.to_sat()) != amount)?;
+    let lock = OutPoint::new(tx.compute_txid(), vout as u32);
+    Some((lock, output.value.to_sat(), output.script_pubkey.clone()))
+}
+
+/// Txid of a spend the way xmr-btc-swap builds them: version 2, no
+/// locktime, one input and one output.
+fn spend_txid(from: OutPoint, sequence: Sequence, value: u64, script_pubkey: ScriptBuf) -> Txid {
+    Transaction {
+        version: Version::TWO,
+        lock_time: LockTime::ZERO,
+        input: vec![TxIn {
+            previous_output: from,
+            script_sig: ScriptBuf::new(),
+            sequence,
+            witness: Default::default(),
+        }],
+        output: vec![TxOut {
+            value: Amount::from_sat(value),
+            script_pubkey,
+        }],
+    }
+    .compute_txid()
+}
+
+/// Output script of an address on any network: segwit, taproot or legacy.
+pub fn address_script(address: &str) -> Option<Vec<u8>> {
+    let address: Address<NetworkUnchecked> = address.trim().parse().ok()?;
+    Some(address.assume_checked().script_pubkey().into_bytes())
+}
+
+/// Script of the first output of a raw transaction (hex), segwit or not.
+pub fn first_output_script(raw_hex: &str) -> Option<Vec<u8>> {
+    let tx: Transaction = bitcoin::consensus::encode::deserialize_hex(raw_hex).ok()?;
+    let output = tx.output.into_iter().next()?;
+    Some(output.script_pubkey.into_bytes())
+}
+
+/// Electrum's script hash: SHA-256 of the script, byte-reversed, as hex.
+pub fn script_hash(script: &[u8]) -> String {
+    let mut hash = sha256::Hash::hash(script).to_byte_array();
+    hash.reverse();
+    hex::encode(hash)
+}
+
+/// P2WPKH output script for a compressed public key.
+pub fn p2wpkh_script(public_key: &[u8; 33]) -> Vec<u8> {
+    let hash = WPubkeyHash::from_raw_hash(hash160::Hash::hash(public_key));
+    ScriptBuf::new_p2wpkh(&hash).into_bytes()
+}
+
+/// `"txid:vout"` in human-readable serde, `{txid, vout}` otherwise.
+fn outpoint(value: &Value) -> Option<OutPoint> {
+    match value {
+        Value::String(s) => s.parse().ok(),
+        Value::Object(_) => Some(OutPoint::new(
+            value.get("txid")?.as_str()?.parse().ok()?,
+            value.get("vout")?.as_u64()?.try_into().ok()?,
+        )),
+        _ => None,
+    }
+}
+
+fn script(hex_script: &Value) -> Option<ScriptBuf> {
+    ScriptBuf::from_hex(hex_script.as_str()?).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::absolute::LockTime;
+    use bitcoin::psbt::Psbt;
+    use bitcoin::script::Builder;
+    use bitcoin::transaction::Version;
+    use bitcoin::{opcodes, Amount, ScriptBuf, Sequence, Transaction, TxIn, TxOut};
+    use serde_json::json;
+
+    const KEY_A: &str = "0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798";
+    const KEY_B: &str = "02c6047f9441ed7d6d3045406e95c07cd85c778e4b8cef3ca7abac09b95c709ee5";
+
+    /// A swap state as ASB stores it once the BTC lock is known: the lock
+    /// PSBT with a 2-of-2 P2WSH output and change, then what the spends are
+    /// built from.
+    fn state() -> (Value, Transaction) {
+        let multisig = Builder::new()
+            .push_opcode(opcodes::all::OP_PUSHNUM_2)
+            .push_slice(<[u8; 33]>::try_from(hex::decode(KEY_A).unwrap()).unwrap())
+            .push_slice(<[u8; 33]>::try_from(hex::decode(KEY_B).unwrap()).unwrap())
+            .push_opcode(opcodes::all::OP_PUSHNUM_2)
+            .push_opcode(opcodes::all::OP_CHECKMULTISIG)
+            .into_script();
+        let lock = Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::from_consensus(840_000),
+            input: vec![TxIn {
+                previous_output:
+                    "0e3e2357e806b6cdb1f70b54c3a3a17b6714ee1f0e68bebb44a74b1efd512098:1"
+                        .parse()
+                        .unwrap(),
+                script_sig: ScriptBuf::new(),
+                sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+                witness: Default::default(),
+            }],
+            output: vec![
+                TxOut {
+                    value: Amount::from_sat(4_321_000),
+                    script_pubkey: address_script("bc1qar0srrr7xfkvy5l643lydnw9re59gtzzwf5mdq")
+                        .unwrap()
+                        .into(),
+                },
+                TxOut {
+                    value: Amount::from_sat(1_000_000),
+                    script_pubkey: ScriptBuf::new_p2wsh(&multisig.wscript_hash()),
+                },
+            ],
+        };
+        let psbt = Psbt::from_unsigned_tx(lock.clone()).unwrap();
+        let state = json!({
+            "btc": 1_000_000,
+            "tx_lock": {"inner": serde_json::to_value(&psbt).unwrap()},
+            "cancel_timelock": 72,
+            "punish_timelock": 144,
+            "tx_redeem_fee": 1_500,
+            "tx_cancel_fee": 2_000,
+            "tx_refund_fee": 1_600,
+            "tx_punish_fee": 1_700,
+            "redeem_address": "bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t4",
+            "refund_address": "bc1p0xlxvlhemja6c4dqv22uapctqupfhlxm9h8z3k2e72q4k9hcz7vqzk5jj0",
+            "punish_address": "bc1qrp33g0q5c5txsp9arysrx4k6zdkfs4nce4xj0gdcccefvpysxf3qccfmv3",
+        });
+        (state, lock)
+    }
+
+    #[test]
+    fn spends_follow_from_the_lock() {
+        let (state, lock) = state();
+        let txid = lock.compute_txid();
+        assert_eq!(lock_txid(&state["tx_lock"]), Some(txid.to_string()));
+
+        // Worked out independently, by serializing the transactions by hand
+        let expected = [
+            (
+                "btc_cancel",
+                "022af7d3520cbff7bfc813e9dd90290819fe64574301630b8c1c98e65371001e",
+            ),
+            (
+                "btc_punish",
+                "9b2679e7c19aaff501a5525cadd0fd33719cc5d3ebe244c1d96216eaa6cd238f",
+            ),
+            (
+                "btc_redeem",
+                "41608a78aacc4f020fa304d6697824f7adbd5dc4cef0e7ec4bd49ba1505315c3",
+            ),
+            (
+                "btc_refund",
+                "15d0602682fa0861e9c3e334702225cc858bfc427009e119dac015dcfdc5e487",
+            ),
+        ];
+        let txids = spend_txids(&state);
+        let txids: Vec<_> = txids
+            .iter()
+            .map(|(k, v)| (k.as_str(), v.as_str()))
+            .collect();
+        assert_eq!(txids, expected);
+
+        // Cancel spends the lock's 2-of-2 output, after the cancel timelock,
+        // back to the same script
+        let cancel = Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: bitcoin::OutPoint::new(txid, 1),
+                script_sig: ScriptBuf::new(),
+                sequence: Sequence(72),
+                witness: Default::default(),
+            }],
+            output: vec![TxOut {
+                value: Amount::from_sat(1_000_000 - 2_000),
+                script_pubkey: lock.output[1].script_pubkey.clone(),
+            }],
+        };
+        assert_eq!(cancel.compute_txid().to_string(), expected[0].1);
+    }
+
+    #[test]
+    fn missing_parts_leave_spends_out() {
+        let (mut state, _) = state();
+        state["punish_address"] = json!("not an address");
+        state["tx_refund_fee"] = json!(2_000_000);
+        let txids = spend_txids(&state);
+        assert_eq!(
+            txids.keys().collect::<Vec<_>>(),
+            ["btc_cancel", "btc_redeem"]
+        );
+
+        state.as_object_mut().unwrap().remove("cancel_timelock");
+        assert_eq!(
+            spend_txids(&state).keys().collect::<Vec<_>>(),
+            ["btc_redeem"]
+        );
+        assert!(spend_txids(&json!({"tx_lock": {}})).is_empty());
+    }
+
+    #[test]
+    fn addresses_give_their_scripts() {
+        let cases = [
+            // BIP 173 and BIP 350
+            (
+                "bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t4",
+                "0014751e76e8199196d454941c45d1b3a323f1433bd6",
+            ),
+            (
+                "BC1QW508D6QEJXTDG4Y5R3ZARVARY0C5XW7KV8F3T4",
+                "0014751e76e8199196d454941c45d1b3a323f1433bd6",
+            ),
+            (
+                "bc1qrp33g0q5c5txsp9arysrx4k6zdkfs4nce4xj0gdcccefvpysxf3qccfmv3",
+                "00201863143c14c5166804bd19203356da136c985678cd4d27a1b8c6329604903262",
+            ),
+            (
+                "bc1p0xlxvlhemja6c4dqv22uapctqupfhlxm9h8z3k2e72q4k9hcz7vqzk5jj0",
+                "512079be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798",
+            ),
+            // Legacy P2PKH
+            (
+                "1BvBMSEYstWetqTFn5Au4m4GFg7xJaNVN2",
+                "76a91477bff20c60e522dfaa3350c39b030a5d004e839a88ac",
+            ),
+        ];
+        for (address, script) in cases {
+            assert_eq!(
+                address_script(address).map(hex::encode).as_deref(),
+                Some(script),
+                "{address}"
+            );
+        }
+        // One character off
+        assert_eq!(
+            address_script("bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t5"),
+            None
+        );
+
+        let key = <[u8; 33]>::try_from(hex::decode(KEY_A).unwrap()).unwrap();
+        assert_eq!(hex::encode(p2wpkh_script(&key)), cases[0].1);
+    }
+
+    #[test]
+    fn raw_transactions_give_their_first_output() {
+        // The genesis block's coinbase
+        let raw = "01000000010000000000000000000000000000000000000000000000000000000000000000ffffffff4d04ffff001d0104455468652054696d65732030332f4a616e2f32303039204368616e63656c6c6f72206f6e206272696e6b206f66207365636f6e64206261696c6f757420666f722062616e6b73ffffffff0100f2052a01000000434104678afdb0fe5548271967f1a67130b7105cd6a828e03909a67962e0ea1f61deb649f6bc3f4cef38c4f35504e51ec112de5c384df7ba0b8d578a4c702b6bf11d5fac00000000";
+        let script = first_output_script(raw).unwrap();
+        assert_eq!(script.len(), 67);
+        assert!(script.starts_with(&[0x41, 0x04, 0x67, 0x8a]));
+        assert_eq!(script.last(), Some(&0xac));
+        assert_eq!(first_output_script("0100"), None);
+    }
+}
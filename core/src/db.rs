@@ -0,0 +1,1203 @@
+//! Read-only access to the ASB sqlite database.
+
+use anyhow::{bail, Context, Result};
+use dirs::home_dir;
+use serde::Deserialize;
+use sqlx::sqlite::{SqliteConnectOptions, SqlitePoolOptions, SqliteRow};
+use sqlx::{Row, SqlitePool};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+use tracing::field::{display, Empty};
+use tracing::{info, info_span, instrument, warn, Instrument, Span};
+
+use crate::anomaly::Anomaly;
+use crate::eta::StateDurations;
+use crate::model::{decode_state, HistoryRow, Network, Role, SwapRow};
+use crate::schema::Schema;
+
+/// An ASB to monitor alongside others, from `--instance` or the config
+/// file's `[[instances]]` tables.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct InstanceSpec {
+    /// Defaults to the network name
+    pub name: Option<String>,
+    pub network: Network,
+    /// Defaults to `--role`
+    pub role: Option<Role>,
+    /// Defaults to the data directory for `network` and the role
+    pub db_path: Option<PathBuf>,
+    /// Defaults to `--immutable`
+    pub immutable: Option<bool>,
+    /// `user@host` to read the database from over SSH; defaults to
+    /// `--remote`
+    pub remote: Option<String>,
+}
+
+impl FromStr for InstanceSpec {
+    type Err = anyhow::Error;
+
+    /// `NETWORK` or `NETWORK:PATH`
+    fn from_str(s: &str) -> Result<Self> {
+        let (network, db_path) = match s.split_once(':') {
+            Some((network, path)) => (network, Some(PathBuf::from(path))),
+            None => (s, None),
+        };
+        Ok(InstanceSpec {
+            name: None,
+            network: network.parse()?,
+            role: None,
+            db_path,
+            immutable: None,
+            remote: None,
+        })
+    }
+}
+
+/// Default database location for ASB (`asb/`) or the swap CLI (`cli/`).
+pub fn resolve_db_path(network: Network, role: Role) -> Option<PathBuf> {
+    let home = home_dir()?;
+    #[cfg(target_os = "macos")]
+    let data_dir = home.join("Library/Application Support/xmr-btc-swap");
+    #[cfg(not(target_os = "macos"))]
+    let data_dir = home.join(".local/share/xmr-btc-swap");
+
+    Some(data_dir.join(relative_db_path(network, role)))
+}
+
+fn relative_db_path(network: Network, role: Role) -> PathBuf {
+    Path::new(role.dir_name())
+        .join(network.dir_name())
+        .join("sqlite")
+}
+
+/// Default database location on a Linux server, relative to the remote
+/// user's home directory.
+pub fn remote_db_path(network: Network, role: Role) -> PathBuf {
+    Path::new(".local/share/xmr-btc-swap").join(relative_db_path(network, role))
+}
+
+/// Where the local copy of a remote instance's database is kept.
+fn mirror_path(name: &str) -> Option<PathBuf> {
+    Some(
+        dirs::cache_dir()?
+            .join("wraithswap/remote")
+            .join(format!("{name}.sqlite")),
+    )
+}
+
+/// The settings that apply to every instance that doesn't set its own.
+#[derive(Debug, Clone, Default)]
+pub struct InstanceDefaults {
+    pub role: Role,
+    pub immutable: bool,
+    pub remote: Option<String>,
+}
+
+/// Turn instance specs into concrete instances with a database path and a
+/// unique name. With no specs the single `db_path`/`network` pair from the
+/// command line is the only instance.
+///
+/// A remote instance's `db_path` is on the remote host, relative to the
+/// login's home directory unless absolute; the instance then reads a local
+/// copy in the cache directory.
+pub fn resolve_instances(
+    specs: Vec<InstanceSpec>,
+    db_path: Option<PathBuf>,
+    network: Network,
+    defaults: &InstanceDefaults,
+) -> Vec<Instance> {
+    let specs = if specs.is_empty() {
+        vec![InstanceSpec {
+            network,
+            db_path,
+            ..InstanceSpec::default()
+        }]
+    } else {
+        specs
+    };
+
+    let mut instances: Vec<Instance> = Vec::new();
+    for spec in specs {
+        let role = spec.role.unwrap_or(defaults.role);
+        let base = spec
+            .name
+            .unwrap_or_else(|| default_name(spec.network, role));
+        let mut name = base.clone();
+        let mut n = 2;
+        while instances.iter().any(|i| i.name == name) {
+            name = format!("{base}-{n}");
+            n += 1;
+        }
+
+        let (db_path, remote) = match spec.remote.or_else(|| defaults.remote.clone()) {
+            Some(host) => (
+                mirror_path(&name),
+                Some(Remote {
+                    host,
+                    db_path: spec
+                        .db_path
+                        .unwrap_or_else(|| remote_db_path(spec.network, role)),
+                }),
+            ),
+            None => (
+                spec.db_path.or_else(|| resolve_db_path(spec.network, role)),
+                None,
+            ),
+        };
+        instances.push(Instance {
+            name,
+            db_path,
+            network: spec.network,
+            role,
+            immutable: spec.immutable.unwrap_or(defaults.immutable),
+            remote,
+        });
+    }
+    instances
+}
+
+fn default_name(network: Network, role: Role) -> String {
+    match role {
+        Role::Maker => network.dir_name().to_string(),
+        Role::Taker => format!("{}-cli", network.dir_name()),
+    }
+}
+
+/// One monitored ASB: its database and the network it serves.
+#[derive(Debug, Clone)]
+pub struct Instance {
+    pub name: String,
+    pub db_path: Option<PathBuf>,
+    pub network: Network,
+    pub role: Role,
+    /// Open the database with sqlite's `immutable` flag
+    pub immutable: bool,
+    /// Where the database really is when `db_path` is a local copy
+    pub remote: Option<Remote>,
+}
+
+/// A database on another machine, read over SSH.
+#[derive(Debug, Clone)]
+pub struct Remote {
+    /// `user@host`, an `ssh://` URL or a `~/.ssh/config` alias
+    pub host: String,
+    /// On the remote host
+    pub db_path: PathBuf,
+}
+
+/// How long a query waits for ASB to release a lock before giving up. Long
+/// enough for its usual writes, short enough not to hold up a poll.
+const BUSY_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Why a poll came back empty-handed. Everything but `NoDataDir` can clear
+/// up on a later poll.
+#[derive(Debug)]
+pub enum PollError {
+    NoDataDir,
+    NotFound(PathBuf),
+    Connect(anyhow::Error),
+    /// The file isn't an xmr-btc-swap database this monitor can read
+    Schema(anyhow::Error),
+    Query(anyhow::Error),
+}
+
+impl fmt::Display for PollError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PollError::NoDataDir => write!(f, "Could not resolve ASB data directory for this OS."),
+            PollError::NotFound(path) => write!(f, "Database not found yet: {}", path.display()),
+            PollError::Connect(err) => write!(f, "Failed to connect (read-only): {err}"),
+            PollError::Schema(err) => write!(f, "Unsupported database: {err}"),
+            PollError::Query(err) => write!(f, "Failed to query swaps: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for PollError {}
+
+/// Owns the read-only pool for one database and reconnects lazily: the pool
+/// is opened on first use and dropped after a failed query so the next poll
+/// starts fresh.
+///
+/// The latest row per swap is kept in memory. After a first full read, each
+/// poll only fetches rows appended since the highest id seen, which keeps
+/// polling cheap on databases with a long history. A reconnect triggers a
+/// full refresh.
+///
+/// With a page size, the full read skips all but the newest finished swaps:
+/// every swap still in flight is read, then `page_size` finished ones, and
+/// [`SwapSource::load_more`] asks for the next page. Finished swaps never
+/// get another row, so the incremental polls that follow stay complete.
+///
+/// When ASB holds a lock for longer than [`BUSY_TIMEOUT`], the poll reads
+/// from a copy of the database and its WAL instead. When the file itself is
+/// replaced, say by a fresh ASB install or a restored backup, the pool is
+/// reopened on the new one.
+pub struct SwapSource {
+    pub path: Option<PathBuf>,
+    pub pool: Option<SqlitePool>,
+    /// Detected along with each new pool
+    schema: Option<Schema>,
+    /// The file the pool was opened on, as of the last poll
+    identity: Option<FileIdentity>,
+    latest: HashMap<String, (i64, SwapRow)>,
+    /// Whose state machine the rows follow, for anomaly checks
+    role: Role,
+    /// See [`open_read_only_pool`]
+    immutable: bool,
+    /// Time spent per state by every swap so far, learned from the rows
+    durations: StateDurations,
+    /// Unexpected steps between rows, in the order they were read
+    anomalies: Vec<Anomaly>,
+    /// Highest `swap_states.id` merged so far; `None` forces a full refresh
+    last_id: Option<i64>,
+    /// Finished swaps read per page; `None` reads them all
+    page_size: Option<usize>,
+    /// Pages of finished swaps read so far after the first
+    pages: usize,
+    /// Whether the next poll reads another page
+    page_wanted: bool,
+    /// Latest row id of the oldest finished swap read; the next page is
+    /// older than that
+    boundary: Option<i64>,
+    /// Finished swaps older than the pages read so far
+    unloaded: usize,
+    /// Swaps the last poll read from an extra page, and how many of the
+    /// anomalies came with them
+    paged_in: (Vec<SwapRow>, usize),
+}
+
+impl SwapSource {
+    pub fn new(path: Option<PathBuf>) -> Self {
+        Self {
+            path,
+            pool: None,
+            schema: None,
+            identity: None,
+            latest: HashMap::new(),
+            role: Role::default(),
+            immutable: false,
+            durations: StateDurations::default(),
+            anomalies: Vec::new(),
+            last_id: None,
+            page_size: None,
+            pages: 0,
+            page_wanted: false,
+            boundary: None,
+            unloaded: 0,
+            paged_in: (Vec::new(), 0),
+        }
+    }
+
+    /// Check steps against `role`'s state machine rather than the maker's.
+    pub fn with_role(mut self, role: Role) -> Self {
+        self.role = role;
+        self
+    }
+
+    /// Open the database with sqlite's `immutable` flag.
+    pub fn with_immutable(mut self, immutable: bool) -> Self {
+        self.immutable = immutable;
+        self
+    }
+
+    /// Read finished swaps `size` at a time, newest first. `None` reads
+    /// them all.
+    pub fn with_page_size(mut self, size: Option<usize>) -> Self {
+        self.page_size = size;
+        self
+    }
+
+    /// Read the next page of finished swaps on the next poll. Does nothing
+    /// when they are all read.
+    pub fn load_more(&mut self) {
+        if self.unloaded > 0 && !self.page_wanted {
+            self.page_wanted = true;
+            self.pages += 1;
+        }
+    }
+
+    /// Finished swaps not read yet.
+    pub fn unloaded(&self) -> usize {
+        self.unloaded
+    }
+
+    /// The swaps the last poll read from an extra page, and how many of the
+    /// latest anomalies came with them: old news, not news.
+    pub fn take_paged_in(&mut self) -> (Vec<SwapRow>, usize) {
+        std::mem::take(&mut self.paged_in)
+    }
+
+    pub async fn poll(&mut self) -> std::result::Result<Vec<SwapRow>, PollError> {
+        let span = info_span!(
+            "poll",
+            db = Empty,
+            rows = Empty,
+            from_copy = Empty,
+            error = Empty,
+            otel.status_code = Empty
+        );
+        if let Some(ref path) = self.path {
+            span.record("db", display(path.display()));
+        }
+        let result = self.refresh().instrument(span.clone()).await;
+        match result {
+            Ok(ref rows) => span.record("rows", rows.len()),
+            Err(ref err) => span
+                .record("error", display(err))
+                .record("otel.status_code", "error"),
+        };
+        result
+    }
+
+    async fn refresh(&mut self) -> std::result::Result<Vec<SwapRow>, PollError> {
+        let path = self.path.as_ref().ok_or(PollError::NoDataDir)?;
+        if !path.exists() {
+            return Err(PollError::NotFound(path.clone()));
+        }
+
+        let identity = FileIdentity::of(path);
+        if let (Some(before), Some(now)) = (self.identity, identity) {
+            if now.replaces(&before) {
+                if let Some(pool) = self.pool.take() {
+                    info!(db = %path.display(), "database file replaced; reconnecting");
+                    pool.close().await;
+                }
+            }
+        }
+        self.identity = identity;
+
+        let (pool, schema) = match (&self.pool, &self.schema) {
+            (Some(pool), Some(schema)) => (pool, schema),
+            _ => {
+                let pool = open_read_only_pool(path, self.immutable)
+                    .await
+                    .map_err(PollError::Connect)?;
+                let schema = match Schema::detect(&pool).await {
+                    Err(err) if is_busy(&err) => {
+                        let (copy, _snapshot) =
+                            open_snapshot(path).await.map_err(PollError::Connect)?;
+                        let schema = Schema::detect(&copy).await;
+                        copy.close().await;
+                        schema
+                    }
+                    schema => schema,
+                }
+                .map_err(PollError::Schema)?;
+                self.last_id = None;
+                self.schema = Some(schema);
+                self.pool = Some(pool);
+                (
+                    self.pool.as_ref().expect("just set"),
+                    self.schema.as_ref().expect("just set"),
+                )
+            }
+        };
+
+        let full = self.last_id.is_none();
+        let read = match (self.last_id, self.page_size) {
+            (Some(after_id), _) => Read::Since(after_id),
+            (None, None) => Read::Since(0),
+            // A reconnect reads again as many pages as were read before
+            (None, Some(size)) => Read::Page {
+                before: i64::MAX,
+                take: size * (self.pages + 1),
+                in_flight: true,
+                up_to: i64::MAX,
+            },
+        };
+        // An extra page is read up to what the last poll read, so the rows
+        // after it come in order with the rest
+        let page = match (self.page_wanted && !full, self.page_size, self.boundary) {
+            (true, Some(take), Some(before)) => Some(Read::Page {
+                before,
+                take,
+                in_flight: false,
+                up_to: self.last_id.unwrap_or(0),
+            }),
+            _ => None,
+        };
+        self.page_wanted = false;
+
+        let mut reads = Vec::new();
+        for read in page.iter().chain([&read]) {
+            match fetch_or_copy(path, pool, schema, read).await {
+                Ok(fetched) => reads.push(fetched),
+                Err(err) => {
+                    self.pool = None;
+                    self.schema = None;
+                    return Err(PollError::Query(err));
+                }
+            }
+        }
+        let fetched = reads.pop().expect("read above");
+        let paged = reads.pop();
+
+        if full {
+            self.latest.clear();
+            self.durations.clear();
+            self.anomalies.clear();
+        }
+        let role = self.role;
+        // The page goes first: its anomalies are older than this poll's
+        if let Some(paged) = paged {
+            let mut ids: Vec<String> = paged
+                .rows
+                .iter()
+                .map(|(_, row)| row.swap_id.clone())
+                .collect();
+            ids.sort();
+            ids.dedup();
+            let before = self.anomalies.len();
+            merge_rows(&mut self.latest, paged.rows, |previous, row| {
+                self.durations.record(previous, row);
+                self.anomalies.extend(Anomaly::check(previous, row, role));
+            });
+            let mut rows: Vec<SwapRow> = ids
+                .iter()
+                .filter_map(|id| self.latest.get(id))
+                .map(|(_, row)| row.clone())
+                .collect();
+            rows.sort_by(|a, b| b.entered_at.cmp(&a.entered_at));
+            self.paged_in = (rows, self.anomalies.len() - before);
+            self.boundary = paged.boundary.or(self.boundary);
+            self.unloaded = paged.unloaded;
+        }
+        if full {
+            self.boundary = fetched.boundary;
+            self.unloaded = fetched.unloaded;
+        }
+        let last_id = fetched.rows.last().map(|(id, _)| *id);
+        merge_rows(&mut self.latest, fetched.rows, |previous, row| {
+            self.durations.record(previous, row);
+            self.anomalies.extend(Anomaly::check(previous, row, role));
+        });
+        self.last_id = self.last_id.max(last_id).or(Some(0));
+
+        Ok(sorted_latest(&self.latest))
+    }
+
+    /// How long swaps have spent in each state, as of the last poll.
+    pub fn durations(&mut self) -> &mut StateDurations {
+        &mut self.durations
+    }
+
+    /// The database's layout, as detected on the last connect.
+    pub fn schema(&self) -> Option<&Schema> {
+        self.schema.as_ref()
+    }
+
+    /// Every unexpected step found so far, oldest first. A reconnect reads
+    /// the database again and finds the same ones in the same order.
+    pub fn anomalies(&self) -> &[Anomaly] {
+        &self.anomalies
+    }
+
+    /// Every recorded state for one swap, oldest first.
+    pub async fn history(
+        &mut self,
+        swap_id: &str,
+    ) -> std::result::Result<Vec<HistoryRow>, PollError> {
+        let (Some(pool), Some(schema)) = (&self.pool, &self.schema) else {
+            return Err(match self.path {
+                Some(ref path) => PollError::NotFound(path.clone()),
+                None => PollError::NoDataDir,
+            });
+        };
+
+        match history_rows(pool, schema, swap_id).await {
+            Err(err) if is_busy(&err) => {
+                let path = self.path.as_ref().ok_or(PollError::NoDataDir)?;
+                let (copy, _snapshot) = open_snapshot(path).await.map_err(PollError::Query)?;
+                let history = history_rows(&copy, schema, swap_id).await;
+                copy.close().await;
+                history
+            }
+            history => history,
+        }
+        .map_err(PollError::Query)
+    }
+}
+
+/// Enough of a file's metadata to tell when it was swapped for another.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct FileIdentity {
+    /// Device and inode, or the creation time where there are no inodes
+    id: (u64, u64),
+    len: u64,
+}
+
+impl FileIdentity {
+    fn of(path: &Path) -> Option<Self> {
+        let meta = fs::metadata(path).ok()?;
+        #[cfg(unix)]
+        let id = {
+            use std::os::unix::fs::MetadataExt;
+            (meta.dev(), meta.ino())
+        };
+        #[cfg(not(unix))]
+        let id = meta
+            .created()
+            .ok()
+            .and_then(|created| created.duration_since(std::time::UNIX_EPOCH).ok())
+            .map(|since| (since.as_secs(), u64::from(since.subsec_nanos())))
+            .unwrap_or_default();
+        Some(Self {
+            id,
+            len: meta.len(),
+        })
+    }
+
+    /// Whether this is another file than `before`, or the same one
+    /// overwritten with something shorter. ASB's database only grows, so
+    /// shrinking means a copy landed on top of it.
+    fn replaces(&self, before: &Self) -> bool {
+        self.id != before.id || self.len < before.len
+    }
+}
+
+/// A pool that can't write to the database, so ASB's own writes are never
+/// at risk.
+///
+/// With `immutable`, sqlite also takes no locks and never creates the
+/// `-shm`, `-wal` or `-journal` files next to the database, which is what
+/// read-only mounts and backups need. It then reads the database file
+/// alone: anything ASB has only written to its WAL so far stays invisible
+/// until ASB checkpoints it. Nor does sqlite notice when the file changes,
+/// so every connection is closed after use and each query reads afresh.
+/// That no journal file appeared is checked after connecting.
+#[instrument(skip_all)]
+pub async fn open_read_only_pool(db_path: &Path, immutable: bool) -> Result<SqlitePool> {
+    let opts = SqliteConnectOptions::from_str(&format!("sqlite:{}", db_path.display()))?
+        .read_only(true)
+        .immutable(immutable)
+        .create_if_missing(false)
+        .busy_timeout(BUSY_TIMEOUT);
+
+    let before = journal_files(db_path);
+    let mut pool_opts = SqlitePoolOptions::new();
+    if immutable {
+        pool_opts = pool_opts.after_release(|_, _| Box::pin(async { Ok(false) }));
+    }
+    let pool = pool_opts
+        .connect_with(opts)
+        .await
+        .with_context(|| format!("open database at {}", db_path.display()))?;
+    if !immutable {
+        return Ok(pool);
+    }
+
+    if let Some(created) = journal_files(db_path)
+        .into_iter()
+        .find(|file| !before.contains(file))
+    {
+        pool.close().await;
+        bail!(
+            "{} appeared while opening {} immutable; is ASB still writing to it?",
+            created.display(),
+            db_path.display()
+        );
+    }
+    let wal = with_suffix(db_path, "-wal");
+    if fs::metadata(&wal).is_ok_and(|meta| meta.len() > 0) {
+        warn!(
+            wal = %wal.display(),
+            "immutable mode ignores the WAL; swaps may look older than they are"
+        );
+    }
+    Ok(pool)
+}
+
+/// The journal, WAL and shared-memory files that exist next to `db_path`.
+fn journal_files(db_path: &Path) -> Vec<PathBuf> {
+    ["-journal", "-wal", "-shm"]
+        .into_iter()
+        .map(|suffix| with_suffix(db_path, suffix))
+        .filter(|path| path.exists())
+        .collect()
+}
+
+/// Whether `err` is sqlite giving up on a lock another connection holds
+/// (`SQLITE_BUSY` or `SQLITE_LOCKED`, in any of their extended forms).
+fn is_busy(err: &anyhow::Error) -> bool {
+    err.chain()
+        .filter_map(|err| err.downcast_ref::<sqlx::Error>())
+        .filter_map(|err| err.as_database_error()?.code()?.parse::<i32>().ok())
+        .any(|code| matches!(code & 0xff, 5 | 6))
+}
+
+/// A private copy of a database and its journal, removed again on drop.
+struct Snapshot {
+    dir: PathBuf,
+}
+
+impl Snapshot {
+    fn take(db_path: &Path) -> Result<Self> {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+        let dir = std::env::temp_dir().join(format!(
+            "wraithswap-{}-{}",
+            std::process::id(),
+            NEXT.fetch_add(1, Ordering::Relaxed)
+        ));
+        fs::create_dir_all(&dir).with_context(|| format!("create {}", dir.display()))?;
+        let snapshot = Self { dir };
+
+        // A WAL holds whatever ASB committed since its last checkpoint, and
+        // a rollback journal lets sqlite undo a write caught halfway. The
+        // shared-memory index isn't copied; sqlite rebuilds it.
+        fs::copy(db_path, snapshot.db_path())
+            .with_context(|| format!("copy {}", db_path.display()))?;
+        for suffix in ["-wal", "-journal"] {
+            let journal = with_suffix(db_path, suffix);
+            if journal.exists() {
+                fs::copy(&journal, with_suffix(&snapshot.db_path(), suffix))
+                    .with_context(|| format!("copy {}", journal.display()))?;
+            }
+        }
+        Ok(snapshot)
+    }
+
+    fn db_path(&self) -> PathBuf {
+        self.dir.join("sqlite")
+    }
+}
+
+impl Drop for Snapshot {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+/// Copy the database at `db_path` and open the copy. The copy has to be
+/// writable for sqlite to replay its WAL; ASB's own file isn't touched.
+/// Close the pool before dropping the snapshot.
+async fn open_snapshot(db_path: &Path) -> Result<(SqlitePool, Snapshot)> {
+    let snapshot = Snapshot::take(db_path).context("database is locked, and copying it failed")?;
+    let opts = SqliteConnectOptions::new()
+        .filename(snapshot.db_path())
+        .create_if_missing(false);
+    let pool = SqlitePoolOptions::new()
+        .max_connections(1)
+        .connect_with(opts)
+        .await
+        .context("database is locked, and opening a copy failed")?;
+    Ok((pool, snapshot))
+}
+
+/// Latest state per swap, newest first.
+pub async fn fetch_swaps(pool: &SqlitePool) -> Result<Vec<SwapRow>> {
+    let schema = Schema::detect(pool).await?;
+    let mut latest = HashMap::new();
+    merge_rows(
+        &mut latest,
+        fetch_rows_since(pool, &schema, 0).await?,
+        |_, _| {},
+    );
+    Ok(sorted_latest(&latest))
+}
+
+/// What one full read of a database tells a one-shot command.
+pub struct Scan {
+    /// Latest state per swap, newest first
+    pub rows: Vec<SwapRow>,
+    pub durations: StateDurations,
+    pub anomalies: Vec<Anomaly>,
+    /// Finished swaps left out by `page_size`
+    pub unloaded: usize,
+}
+
+/// Read every row once, checking steps against `role`'s state machine.
+/// With `page_size`, only the newest that many finished swaps are read
+/// along with every swap in flight.
+pub async fn scan_swaps(pool: &SqlitePool, role: Role, page_size: Option<usize>) -> Result<Scan> {
+    let schema = Schema::detect(pool).await?;
+    let read = match page_size {
+        Some(take) => Read::Page {
+            before: i64::MAX,
+            take,
+            in_flight: true,
+            up_to: i64::MAX,
+        },
+        None => Read::Since(0),
+    };
+    let fetched = fetch(pool, &schema, &read).await?;
+    let mut latest = HashMap::new();
+    let mut durations = StateDurations::default();
+    let mut anomalies = Vec::new();
+    merge_rows(&mut latest, fetched.rows, |previous, row| {
+        durations.record(previous, row);
+        anomalies.extend(Anomaly::check(previous, row, role));
+    });
+    Ok(Scan {
+        rows: sorted_latest(&latest),
+        durations,
+        anomalies,
+        unloaded: fetched.unloaded,
+    })
+}
+
+/// Fold rows (oldest first) into the latest row per swap, calling `step`
+/// with each swap's previous row and the one that follows it. End states
+/// don't repeat the amounts, so details carry over from the swap's earlier
+/// rows.
+fn merge_rows(
+    latest: &mut HashMap<String, (i64, SwapRow)>,
+    rows: Vec<(i64, SwapRow)>,
+    mut step: impl FnMut(&SwapRow, &SwapRow),
+) {
+    for (id, mut row) in rows {
+        if let Some((_, previous)) = latest.remove(&row.swap_id) {
+            step(&previous, &row);
+            let mut details = previous.details;
+            details.merge(row.details);
+            details.settle(&row.state);
+            row.details = details;
+            row.started_at = previous.started_at;
+            row.peer_id = row.peer_id.or(previous.peer_id);
+        }
+        latest.insert(row.swap_id.clone(), (id, row));
+    }
+}
+
+fn sorted_latest(latest: &HashMap<String, (i64, SwapRow)>) -> Vec<SwapRow> {
+    let mut rows: Vec<SwapRow> = latest.values().map(|(_, row)| row.clone()).collect();
+    rows.sort_by(|a, b| b.entered_at.cmp(&a.entered_at));
+    rows
+}
+
+/// Every recorded state of one swap, oldest first.
+pub async fn fetch_history(pool: &SqlitePool, swap_id: &str) -> Result<Vec<HistoryRow>> {
+    let schema = Schema::detect(pool).await?;
+    history_rows(pool, &schema, swap_id).await
+}
+
+#[instrument(name = "fetch_history", skip_all)]
+async fn history_rows(
+    pool: &SqlitePool,
+    schema: &Schema,
+    swap_id: &str,
+) -> Result<Vec<HistoryRow>> {
+    let rows = sqlx::query(&format!(
+        "SELECT {} FROM swap_states WHERE swap_id = ? ORDER BY {} ASC",
+        schema.columns(),
+        schema.id_column()
+    ))
+    .bind(swap_id)
+    .fetch_all(pool)
+    .await?;
+
+    // Spends are rebuilt from earlier rows than the one that publishes them
+    let mut spends = BTreeMap::new();
+    Ok(rows
+        .iter()
+        .map(|r| {
+            let (state, mut details) = decode_state(r.get("state"));
+            spends.extend(std::mem::take(&mut details.spends));
+            details.spends = spends.clone();
+            details.settle(&state);
+            HistoryRow {
+                state,
+                entered_at: r.get("entered_at"),
+                peer_id: r.get("peer_id"),
+                details,
+            }
+        })
+        .collect())
+}
+
+/// What one read of `swap_states` covers.
+enum Read {
+    /// Every row after this id
+    Since(i64),
+    /// The rows of the `take` newest finished swaps whose latest row is
+    /// before `before`, and with `in_flight` of every unfinished swap, up
+    /// to row `up_to`
+    Page {
+        before: i64,
+        take: usize,
+        in_flight: bool,
+        up_to: i64,
+    },
+}
+
+struct Fetched {
+    /// Oldest first
+    rows: Vec<(i64, SwapRow)>,
+    /// Latest row id of the oldest finished swap in a page
+    boundary: Option<i64>,
+    /// Finished swaps older than a page
+    unloaded: usize,
+}
+
+/// Read from `pool`, or from a copy of the database when ASB holds its
+/// lock for too long.
+async fn fetch_or_copy(
+    path: &Path,
+    pool: &SqlitePool,
+    schema: &Schema,
+    read: &Read,
+) -> Result<Fetched> {
+    match fetch(pool, schema, read).await {
+        Err(err) if is_busy(&err) => {
+            Span::current().record("from_copy", true);
+            let (copy, _snapshot) = open_snapshot(path).await?;
+            let fetched = fetch(&copy, schema, read).await;
+            copy.close().await;
+            fetched
+        }
+        fetched => fetched,
+    }
+}
+
+async fn fetch(pool: &SqlitePool, schema: &Schema, read: &Read) -> Result<Fetched> {
+    let (before, take, in_flight, up_to) = match *read {
+        Read::Since(after_id) => {
+            return Ok(Fetched {
+                rows: fetch_rows_since(pool, schema, after_id).await?,
+                boundary: None,
+                unloaded: 0,
+            })
+        }
+        Read::Page {
+            before,
+            take,
+            in_flight,
+            up_to,
+        } => (before, take, in_flight, up_to),
+    };
+
+    let id = schema.id_column();
+    // The latest row of each finished swap; ASB wraps end states in `Done`
+    let finished = format!(
+        "SELECT swap_id, {id} AS last_id FROM swap_states \
+         WHERE {id} IN (SELECT MAX({id}) FROM swap_states GROUP BY swap_id) \
+         AND state LIKE '%\"Done\"%'"
+    );
+    let take = i64::try_from(take).unwrap_or(i64::MAX);
+    let page: Vec<i64> = sqlx::query_scalar(&format!(
+        "SELECT last_id FROM ({finished}) WHERE last_id < ? ORDER BY last_id DESC LIMIT ?"
+    ))
+    .bind(before)
+    .bind(take)
+    .fetch_all(pool)
+    .await?;
+    let boundary = page.last().copied();
+
+    let in_flight = match in_flight {
+        true => format!("OR swap_id NOT IN (SELECT swap_id FROM ({finished}))"),
+        false => String::new(),
+    };
+    let rows = sqlx::query(&format!(
+        "SELECT {} FROM swap_states WHERE {id} <= ? AND (swap_id IN \
+         (SELECT swap_id FROM ({finished}) WHERE last_id < ? ORDER BY last_id DESC LIMIT ?) \
+         {in_flight}) ORDER BY {id} ASC",
+        schema.columns()
+    ))
+    .bind(up_to)
+    .bind(before)
+    .bind(take)
+    .fetch_all(pool)
+    .await?;
+
+    let unloaded: i64 = match boundary {
+        Some(boundary) => {
+            sqlx::query_scalar(&format!(
+                "SELECT COUNT(*) FROM ({finished}) WHERE last_id < ?"
+            ))
+            .bind(boundary)
+            .fetch_one(pool)
+            .await?
+        }
+        None => 0,
+    };
+    Ok(Fetched {
+        rows: rows.iter().map(id_and_row).collect(),
+        boundary,
+        unloaded: usize::try_from(unloaded).unwrap_or_default(),
+    })
+}
+
+/// Rows appended after `after_id`, oldest first.
+#[instrument(skip(pool, schema))]
+async fn fetch_rows_since(
+    pool: &SqlitePool,
+    schema: &Schema,
+    after_id: i64,
+) -> Result<Vec<(i64, SwapRow)>> {
+    let rows = sqlx::query(&format!(
+        "SELECT {} FROM swap_states WHERE {id} > ? ORDER BY {id} ASC",
+        schema.columns(),
+        id = schema.id_column()
+    ))
+    .bind(after_id)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows.iter().map(id_and_row).collect())
+}
+
+fn id_and_row(r: &SqliteRow) -> (i64, SwapRow) {
+    (r.get("id"), swap_row(r))
+}
+
+fn swap_row(r: &SqliteRow) -> SwapRow {
+    let (state, details) = decode_state(r.get("state"));
+    let entered_at: String = r.get("entered_at");
+    SwapRow {
+        swap_id: r.get("swap_id"),
+        state,
+        started_at: entered_at.clone(),
+        entered_at,
+        peer_id: r.get("peer_id"),
+        details,
+    }
+}
+
+/// Every address recorded per peer, in the order ASB added them. Empty for
+/// databases without `peer_addresses`.
+#[instrument(skip_all)]
+pub async fn fetch_peer_addresses(pool: &SqlitePool) -> Result<HashMap<String, Vec<String>>> {
+    let schema = Schema::detect(pool).await?;
+    let mut addresses: HashMap<String, Vec<String>> = HashMap::new();
+    if !schema.has_peer_addresses() {
+        return Ok(addresses);
+    }
+    let rows = sqlx::query("SELECT peer_id, address FROM peer_addresses ORDER BY rowid ASC")
+        .fetch_all(pool)
+        .await?;
+    for row in rows {
+        let list = addresses.entry(row.get("peer_id")).or_default();
+        let address: String = row.get("address");
+        if !list.contains(&address) {
+            list.push(address);
+        }
+    }
+    Ok(addresses)
+}
+
+/// Every row of `swap_states` in insertion order.
+#[instrument(skip_all)]
+pub async fn fetch_all_states(pool: &SqlitePool) -> Result<Vec<SwapRow>> {
+    let schema = Schema::detect(pool).await?;
+    let rows = sqlx::query(&format!(
+        "SELECT {} FROM swap_states ORDER BY {} ASC",
+        schema.columns(),
+        schema.id_column()
+    ))
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows.iter().map(swap_row).collect())
+}
+
+/// Number of rows in `swap_states`, across all swaps.
+#[instrument(skip_all)]
+pub async fn count_state_rows(pool: &SqlitePool) -> Result<i64> {
+    Schema::detect(pool).await?;
+    let row = sqlx::query("SELECT COUNT(*) AS n FROM swap_states")
+        .fetch_one(pool)
+        .await?;
+    Ok(row.get("n"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::SwapState;
+    use sqlx::sqlite::SqliteJournalMode;
+    use tempfile::TempDir;
+
+    const REDEEMED: &str = r#"{"Alice":{"Done":"BtcRedeemed"}}"#;
+
+    /// A database with ASB's `swap_states` table, writable unlike the
+    /// monitor's.
+    async fn create(path: &Path) -> SqlitePool {
+        let opts = SqliteConnectOptions::new()
+            .filename(path)
+            .create_if_missing(true)
+            .journal_mode(SqliteJournalMode::Delete);
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect_with(opts)
+            .await
+            .unwrap();
+        sqlx::query(
+            "CREATE TABLE swap_states (id INTEGER PRIMARY KEY AUTOINCREMENT, \
+             swap_id TEXT NOT NULL, entered_at TEXT NOT NULL, state TEXT NOT NULL)",
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+        pool
+    }
+
+    /// Rows are entered a minute apart, in the order inserted.
+    async fn insert(pool: &SqlitePool, swap_id: &str, state: &str) {
+        sqlx::query(
+            "INSERT INTO swap_states (swap_id, entered_at, state) VALUES (?, \
+             datetime('2026-01-01', '+' || (SELECT COUNT(*) FROM swap_states) || ' minutes'), ?)",
+        )
+        .bind(swap_id)
+        .bind(state)
+        .execute(pool)
+        .await
+        .unwrap();
+    }
+
+    fn ids(rows: &[SwapRow]) -> Vec<&str> {
+        rows.iter().map(|row| row.swap_id.as_str()).collect()
+    }
+
+    #[tokio::test]
+    async fn polls_pick_up_new_rows() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("sqlite");
+        let db = create(&path).await;
+        insert(&db, "a", "Started").await;
+        insert(&db, "a", "BtcLocked").await;
+
+        let mut source = SwapSource::new(Some(path));
+        let rows = source.poll().await.unwrap();
+        assert_eq!(ids(&rows), ["a"]);
+        assert_eq!(rows[0].state, SwapState::BtcLocked);
+        assert_eq!(rows[0].started_at, "2026-01-01 00:00:00");
+
+        insert(&db, "b", "Started").await;
+        // Skips XmrLockTransactionSent
+        insert(&db, "a", "XmrLocked").await;
+        let rows = source.poll().await.unwrap();
+        assert_eq!(ids(&rows), ["a", "b"]);
+        assert_eq!(rows[0].state, SwapState::XmrLocked);
+        assert_eq!(source.anomalies().len(), 1);
+
+        // Nothing new: same rows, no anomaly found twice
+        assert_eq!(source.poll().await.unwrap().len(), 2);
+        assert_eq!(source.anomalies().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn missing_databases_are_reported() {
+        let dir = TempDir::new().unwrap();
+        let mut source = SwapSource::new(Some(dir.path().join("sqlite")));
+        assert!(matches!(source.poll().await, Err(PollError::NotFound(_))));
+        let mut source = SwapSource::new(None);
+        assert!(matches!(source.poll().await, Err(PollError::NoDataDir)));
+    }
+
+    /// Five finished swaps, `f1` oldest, and `live` in flight since before
+    /// all of them.
+    async fn finished_swaps(db: &SqlitePool) {
+        insert(db, "live", "Started").await;
+        for n in 1..=5 {
+            insert(db, &format!("f{n}"), "BtcLocked").await;
+            insert(db, &format!("f{n}"), REDEEMED).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn pages_read_finished_swaps_newest_first() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("sqlite");
+        let db = create(&path).await;
+        finished_swaps(&db).await;
+
+        let mut source = SwapSource::new(Some(path)).with_page_size(Some(2));
+        let rows = source.poll().await.unwrap();
+        assert_eq!(ids(&rows), ["f5", "f4", "live"]);
+        assert_eq!(source.unloaded(), 3);
+        // A swap starts with its first row, not the one it ended on
+        assert_eq!(rows[0].started_at, "2026-01-01 00:09:00");
+
+        source.load_more();
+        // Asking twice before the poll reads one page, not two
+        source.load_more();
+        insert(&db, "live", "BtcLocked").await;
+        let rows = source.poll().await.unwrap();
+        assert_eq!(ids(&rows), ["live", "f5", "f4", "f3", "f2"]);
+        assert_eq!(rows[0].state, SwapState::BtcLocked);
+        assert_eq!(source.unloaded(), 1);
+        let (paged, anomalies) = source.take_paged_in();
+        assert_eq!(ids(&paged), ["f3", "f2"]);
+        // Each skips from BtcLocked to the end, which is old news too
+        assert_eq!(anomalies, 2);
+        assert!(source.take_paged_in().0.is_empty());
+
+        source.load_more();
+        assert_eq!(source.poll().await.unwrap().len(), 6);
+        assert_eq!(source.unloaded(), 0);
+        assert_eq!(ids(&source.take_paged_in().0), ["f1"]);
+        // Nothing left to load
+        source.load_more();
+        source.poll().await.unwrap();
+        assert!(source.take_paged_in().0.is_empty());
+    }
+
+    #[tokio::test]
+    async fn scans_take_one_page() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("sqlite");
+        let db = create(&path).await;
+        finished_swaps(&db).await;
+
+        let scan = scan_swaps(&db, Role::Maker, Some(3)).await.unwrap();
+        assert_eq!(ids(&scan.rows), ["f5", "f4", "f3", "live"]);
+        assert_eq!(scan.unloaded, 2);
+        let scan = scan_swaps(&db, Role::Maker, None).await.unwrap();
+        assert_eq!(scan.rows.len(), 6);
+        assert_eq!(scan.unloaded, 0);
+    }
+
+    #[test]
+    fn identities_tell_replaced_files() {
+        let file = FileIdentity {
+            id: (1, 100),
+            len: 4096,
+        };
+        let grown = FileIdentity { len: 8192, ..file };
+        let shrunk = FileIdentity { len: 1024, ..file };
+        let other = FileIdentity {
+            id: (1, 101),
+            ..grown
+        };
+        assert!(!grown.replaces(&file));
+        assert!(!file.replaces(&file));
+        assert!(shrunk.replaces(&file));
+        assert!(other.replaces(&file));
+    }
+
+    #[tokio::test]
+    async fn a_replaced_database_is_read_afresh() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("sqlite");
+        let db = create(&path).await;
+        insert(&db, "old", "Started").await;
+        insert(&db, "old", "BtcLocked").await;
+        db.close().await;
+
+        let mut source = SwapSource::new(Some(path.clone()));
+        assert_eq!(ids(&source.poll().await.unwrap()), ["old"]);
+
+        // A restored backup: another file, with fewer rows than were seen
+        let restored = dir.path().join("restored");
+        let db = create(&restored).await;
+        insert(&db, "new", "Started").await;
+        db.close().await;
+        fs::rename(&restored, &path).unwrap();
+
+        let rows = source.poll().await.unwrap();
+        assert_eq!(ids(&rows), ["new"]);
+        assert_eq!(rows[0].state, SwapState::Started);
+    }
+}
@@ -0,0 +1,150 @@
+//! How long swaps usually spend in each state, learned from the database's
+//! own history, and what that predicts for the swaps still in flight.
+//!
+//! Every row of `swap_states` after a swap's first one closes a spell in the
+//! previous state, so [`SwapSource`](crate::SwapSource) collects the spell
+//! lengths as it reads rows. An in-flight swap on the happy path is then
+//! expected to finish in the time it typically still has to wait in its
+//! current state plus the median of each state after it.
+
+use chrono::Utc;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::time::Duration;
+
+use crate::model::{parse_entered_at, Role, SwapRow, SwapView};
+use crate::state::SwapState;
+
+/// Fewer spells than this in a state and its numbers aren't trusted
+const MIN_SAMPLES: usize = 5;
+
+/// Time spent in each state by the swaps that left it, in seconds.
+#[derive(Debug, Clone, Default)]
+pub struct StateDurations {
+    samples: HashMap<SwapState, Vec<u64>>,
+    /// Whether `samples` are sorted, which the percentiles need
+    sorted: bool,
+}
+
+/// What the history says about one in-flight swap.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct Eta {
+    /// Expected time until the swap completes; `None` off the happy path or
+    /// without enough history for every state still ahead
+    #[serde(rename = "eta_secs", skip_serializing_if = "Option::is_none")]
+    #[serde(serialize_with = "as_secs")]
+    pub remaining: Option<Duration>,
+    /// Longer in the current state than 95% of the swaps before it
+    #[serde(skip_serializing_if = "is_false")]
+    pub overdue: bool,
+}
+
+fn is_false(value: &bool) -> bool {
+    !value
+}
+
+fn as_secs<S: serde::Serializer>(value: &Option<Duration>, s: S) -> Result<S::Ok, S::Error> {
+    match value {
+        Some(duration) => s.serialize_u64(duration.as_secs()),
+        None => s.serialize_none(),
+    }
+}
+
+impl StateDurations {
+    /// Note that a swap moved from `previous` to `next`.
+    pub fn record(&mut self, previous: &SwapRow, next: &SwapRow) {
+        if previous.state == next.state {
+            return;
+        }
+        let (Some(from), Some(to)) = (
+            parse_entered_at(&previous.entered_at),
+            parse_entered_at(&next.entered_at),
+        ) else {
+            return;
+        };
+        // Clock steps can put a row before its predecessor
+        let Ok(spent) = (to - from).to_std() else {
+            return;
+        };
+        self.samples
+            .entry(previous.state.clone())
+            .or_default()
+            .push(spent.as_secs());
+        self.sorted = false;
+    }
+
+    pub fn clear(&mut self) {
+        self.samples.clear();
+    }
+
+    /// The typical time spent in `state`.
+    pub fn median(&mut self, state: &SwapState) -> Option<Duration> {
+        self.percentile(state, 50)
+    }
+
+    /// The time 95% of the swaps left `state` within.
+    pub fn p95(&mut self, state: &SwapState) -> Option<Duration> {
+        self.percentile(state, 95)
+    }
+
+    fn percentile(&mut self, state: &SwapState, pct: usize) -> Option<Duration> {
+        self.sort();
+        let samples = self.samples.get(state).filter(|s| s.len() >= MIN_SAMPLES)?;
+        // Nearest rank
+        let rank = (samples.len() * pct).div_ceil(100).max(1);
+        Some(Duration::from_secs(samples[rank - 1]))
+    }
+
+    /// The typical time still to wait in `state` after `elapsed` in it:
+    /// the median of the spells that lasted longer, minus `elapsed`. `None`
+    /// once every recorded spell was shorter.
+    fn remaining_in(&mut self, state: &SwapState, elapsed: Duration) -> Option<Duration> {
+        self.sort();
+        let samples = self.samples.get(state).filter(|s| s.len() >= MIN_SAMPLES)?;
+        let elapsed_secs = elapsed.as_secs();
+        let first_longer = samples.partition_point(|&secs| secs <= elapsed_secs);
+        let longer = &samples[first_longer..];
+        let median = *longer.get(longer.len().saturating_sub(1) / 2)?;
+        Some(Duration::from_secs(median - elapsed_secs))
+    }
+
+    fn sort(&mut self) {
+        if !self.sorted {
+            for samples in self.samples.values_mut() {
+                samples.sort_unstable();
+            }
+            self.sorted = true;
+        }
+    }
+
+    /// The outlook for a swap that has been in its current state for
+    /// `elapsed`. `None` for finished swaps.
+    pub fn estimate(&mut self, state: &SwapState, elapsed: Duration, role: Role) -> Option<Eta> {
+        if state.is_terminal(role) {
+            return None;
+        }
+        let overdue = self.p95(state).is_some_and(|p95| elapsed > p95);
+        let remaining = state.progress(role).and_then(|(step, _)| {
+            let ahead = &SwapState::happy_path(role)[step + 1..];
+            // The success state itself is never left, so it adds nothing
+            let ahead = &ahead[..ahead.len().saturating_sub(1)];
+            let mut total = self.remaining_in(state, elapsed)?;
+            for next in ahead {
+                total += self.median(next)?;
+            }
+            Some(total)
+        });
+        Some(Eta { remaining, overdue })
+    }
+
+    /// Set `eta` on every in-flight view.
+    pub fn annotate(&mut self, views: &mut [SwapView], role: Role) {
+        let now = Utc::now();
+        for view in views {
+            let elapsed = parse_entered_at(&view.entered_at)
+                .and_then(|entered| (now - entered).to_std().ok())
+                .unwrap_or_default();
+            view.eta = self.estimate(&view.state, elapsed, role);
+        }
+    }
+}
@@ -0,0 +1,57 @@
+//! The monitor's logic without the CLI: reading an ASB (or swap CLI)
+//! database, turning its rows into swaps, noticing what changed between
+//! polls, and deciding which changes deserve an alert.
+//!
+//! One poll, as a bot would run it on a timer:
+//!
+//! ```no_run
+//! use wraithswap_core::{db, Network, Role, SwapSource, SwapTracker};
+//!
+//! # async fn run() -> anyhow::Result<()> {
+//! let path = db::resolve_db_path(Network::Mainnet, Role::Maker);
+//! let mut source = SwapSource::new(path);
+//! // Keep the tracker across polls; it remembers what it has seen
+//! let mut tracker = SwapTracker::new(Network::Mainnet, None);
+//!
+//! let rows = source.poll().await?;
+//! let (_swaps, transitions) = tracker.update(rows);
+//! for t in transitions {
+//!     println!("{} {:?} -> {}", t.swap_id, t.old_state, t.new_state);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! - [`db`] opens the database read-only and polls it incrementally.
+//! - [`schema`] checks which layout of `swap_states` a database has, so
+//!   queries adapt to it.
+//! - [`model`] decodes stored states into [`SwapRow`]s and [`SwapView`]s,
+//!   and [`SwapTracker`] diffs successive polls into [`Transition`]s,
+//!   including stuck alerts.
+//! - [`state`] classifies the [`SwapState`]s of both state machines.
+//! - [`eta`] learns how long each state usually takes and predicts when
+//!   in-flight swaps will finish.
+//! - [`anomaly`] flags steps between recorded states that the state
+//!   machine doesn't allow.
+//! - [`rules`] matches transitions against alert [`Rule`]s.
+//! - [`btc`] recovers txids and scripts from the stored transactions.
+
+pub mod anomaly;
+pub mod btc;
+pub mod db;
+pub mod eta;
+pub mod model;
+pub mod rules;
+pub mod schema;
+pub mod state;
+
+pub use anomaly::{Anomaly, AnomalyKind};
+pub use db::{Instance, InstanceSpec, PollError, SwapSource};
+pub use eta::{Eta, StateDurations};
+pub use model::{
+    DateRange, HistoryRow, Network, Outcome, Role, StuckRules, SwapDetails, SwapRow, SwapTracker,
+    SwapView, TimeBound, Transition,
+};
+pub use rules::{Rule, Severity};
+pub use schema::Schema;
+pub use state::{StateSelection, SwapState};
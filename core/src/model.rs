@@ -0,0 +1,1719 @@
+//! Swap data as read from the ASB database and the views derived from it.
+
+use chrono::{DateTime, Local, NaiveDateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fmt;
+use std::time::Duration;
+
+use crate::anomaly::{Anomaly, AnomalyKind};
+use crate::btc;
+use crate::eta::Eta;
+use crate::schema::Schema;
+use crate::state::{SwapState, STATE_NAMES};
+
+/// A swap's latest row in `swap_states`.
+#[derive(Debug, Clone, Serialize)]
+pub struct SwapRow {
+    pub swap_id: String,
+    /// Decoded from the stored state blob
+    pub state: SwapState,
+    pub entered_at: String,
+    /// When the swap's first state was recorded
+    #[serde(skip)]
+    pub started_at: String,
+    /// libp2p id of the counterparty, from `peers`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub peer_id: Option<String>,
+    #[serde(flatten)]
+    pub details: SwapDetails,
+}
+
+/// One recorded state of a swap, for its history.
+#[derive(Debug, Clone, Serialize)]
+pub struct HistoryRow {
+    pub state: SwapState,
+    pub entered_at: String,
+    /// The swap's counterparty; the same on every row, so only the swap
+    /// views serialize it
+    #[serde(skip)]
+    pub peer_id: Option<String>,
+    #[serde(flatten)]
+    pub details: SwapDetails,
+}
+
+/// Amounts and transaction ids pulled out of the serialized state.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct SwapDetails {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub btc_sats: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub xmr_piconero: Option<u64>,
+    /// Keyed by what the transaction is, e.g. `xmr_lock`
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub txids: BTreeMap<String, String>,
+    /// Blocks after the BTC lock confirms until either side may cancel
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cancel_timelock: Option<u32>,
+    /// Blocks after the cancel transaction confirms until ASB may punish
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub punish_timelock: Option<u32>,
+    /// Txids of the transactions that would spend the lock, rebuilt from
+    /// the state; they move to `txids` once the state says they were
+    /// published
+    #[serde(skip)]
+    pub spends: BTreeMap<String, String>,
+}
+
+impl SwapDetails {
+    /// Layer a newer row's details over these.
+    pub fn merge(&mut self, newer: SwapDetails) {
+        self.btc_sats = newer.btc_sats.or(self.btc_sats);
+        self.xmr_piconero = newer.xmr_piconero.or(self.xmr_piconero);
+        self.txids.extend(newer.txids);
+        self.cancel_timelock = newer.cancel_timelock.or(self.cancel_timelock);
+        self.punish_timelock = newer.punish_timelock.or(self.punish_timelock);
+        self.spends.extend(newer.spends);
+    }
+
+    /// Record the spends a swap in `state` has published. Finished swaps
+    /// no longer store the lock, so this needs the spends merged in from
+    /// earlier rows.
+    pub fn settle(&mut self, state: &SwapState) {
+        for &label in state.published_spends() {
+            if let Some(txid) = self.spends.get(label) {
+                self.txids.insert(label.to_string(), txid.clone());
+            }
+        }
+    }
+
+    /// The most advanced BTC transaction: anything past the lock wins over
+    /// the lock itself, and what spends the cancel over the cancel.
+    pub fn latest_btc_tx(&self) -> Option<(&str, &str)> {
+        self.txids
+            .iter()
+            .filter_map(|(label, txid)| Some((btc_tx_rank(label)?, label, txid)))
+            .max_by_key(|&(rank, _, _)| rank)
+            .map(|(_, label, txid)| (label.as_str(), txid.as_str()))
+    }
+}
+
+/// How far along a BTC transaction is; `None` for the XMR lock.
+fn btc_tx_rank(label: &str) -> Option<u8> {
+    match label {
+        "xmr_lock" => None,
+        "btc_lock" => Some(0),
+        "btc_cancel" => Some(1),
+        _ => Some(2),
+    }
+}
+
+/// Split a stored state into its variant name and details.
+///
+/// xmr-btc-swap serializes the whole state machine, e.g.
+/// `{"Alice":{"BtcLocked":{"state3":{...}}}}` or
+/// `{"Bob":{"Done":"XmrRedeemed"}}`. Values that aren't JSON (older
+/// databases, test fixtures) are taken as the variant name as-is.
+pub fn decode_state(raw: &str) -> (SwapState, SwapDetails) {
+    let Ok(value) = serde_json::from_str::<Value>(raw) else {
+        return (SwapState::from(raw), SwapDetails::default());
+    };
+
+    // Peel off the role wrapper and the `Done` end-state wrapper
+    let mut inner = &value;
+    let name = loop {
+        match inner {
+            Value::String(name) => break Some(name.clone()),
+            Value::Object(map) if map.len() == 1 => {
+                let (key, next) = map.iter().next().expect("map has one entry");
+                match key.as_str() {
+                    "Alice" | "Bob" | "Done" => inner = next,
+                    _ => break Some(key.clone()),
+                }
+            }
+            _ => break None,
+        }
+    };
+    let Some(name) = name else {
+        return (SwapState::from(raw), SwapDetails::default());
+    };
+
+    let mut details = SwapDetails::default();
+    collect_details(&value, &mut details);
+    let state = SwapState::from(name.as_str());
+    details.settle(&state);
+    (state, details)
+}
+
+fn collect_details(value: &Value, details: &mut SwapDetails) {
+    match value {
+        Value::Object(map) => {
+            if map.contains_key("tx_lock") {
+                details.spends.extend(btc::spend_txids(value));
+            }
+            for (key, value) in map {
+                match (key.as_str(), value) {
+                    ("btc" | "btc_amount", Value::Number(n)) if details.btc_sats.is_none() => {
+                        details.btc_sats = n.as_u64();
+                    }
+                    ("xmr", Value::Number(n)) if details.xmr_piconero.is_none() => {
+                        details.xmr_piconero = n.as_u64();
+                    }
+                    // Monero transfer proofs carry the XMR lock transaction
+                    ("tx_hash", Value::String(hash)) => {
+                        details.txids.insert("xmr_lock".to_string(), hash.clone());
+                    }
+                    // The BTC lock is stored as a PSBT, not by txid
+                    ("tx_lock", tx_lock) => match btc::lock_txid(tx_lock) {
+                        Some(txid) => {
+                            details.txids.insert("btc_lock".to_string(), txid);
+                        }
+                        None => collect_details(tx_lock, details),
+                    },
+                    ("cancel_timelock", Value::Number(n)) => {
+                        details.cancel_timelock = n.as_u64().map(|n| n as u32);
+                    }
+                    ("punish_timelock", Value::Number(n)) => {
+                        details.punish_timelock = n.as_u64().map(|n| n as u32);
+                    }
+                    (key, Value::String(txid)) if key.ends_with("txid") => {
+                        details.txids.insert(key.to_string(), txid.clone());
+                    }
+                    _ => collect_details(value, details),
+                }
+            }
+        }
+        Value::Array(items) => {
+            for item in items {
+                collect_details(item, details);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// A swap as displayed and served: its latest row plus what the monitor
+/// worked out about it.
+#[derive(Debug, Clone, Serialize)]
+pub struct SwapView {
+    pub swap_id: String,
+    pub state: SwapState,
+    pub entered_at: String,
+    #[serde(skip)]
+    pub started_at: String,
+    /// The state differs from the previous poll's
+    pub changed: bool,
+    /// How much the state stands out for a recent change
+    #[serde(skip)]
+    pub highlight: Highlight,
+    /// Sitting in a non-terminal state past its stuck threshold
+    #[serde(skip_serializing_if = "is_false")]
+    pub stuck: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub peer_id: Option<String>,
+    /// The counterparty is on the watchlist
+    #[serde(skip_serializing_if = "is_false")]
+    pub watched: bool,
+    /// Pinned to the top of the table
+    #[serde(skip_serializing_if = "is_false")]
+    pub pinned: bool,
+    /// Tags from the monitor's notes database
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    #[serde(flatten)]
+    pub details: SwapDetails,
+    /// Expected time to completion, from how long earlier swaps took
+    #[serde(flatten, skip_serializing_if = "Option::is_none")]
+    pub eta: Option<Eta>,
+    /// Filled in when an Electrum server is configured
+    #[serde(skip_serializing_if = "ChainStatus::is_empty")]
+    pub chain: ChainStatus,
+    /// The BTC amount in fiat, with `--fiat`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fiat: Option<FiatValue>,
+    /// Instance name, only set when more than one ASB is monitored
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub instance: Option<String>,
+}
+
+/// An amount at the current BTC price.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FiatValue {
+    pub value: f64,
+    pub currency: String,
+}
+
+fn is_false(value: &bool) -> bool {
+    !value
+}
+
+/// One observed state change, as emitted by `tail`. Stuck alerts reuse the
+/// shape with `old_state == new_state` and `stuck_for_secs` set.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Transition {
+    pub swap_id: String,
+    /// `None` when the swap first appeared while we were watching
+    pub old_state: Option<SwapState>,
+    pub new_state: SwapState,
+    pub entered_at: String,
+    pub observed_at: String,
+    /// Set on stuck alerts: how long the swap has been in `new_state`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stuck_for_secs: Option<u64>,
+    /// Set on anomaly alerts: the step from `old_state` isn't one the state
+    /// machine allows
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub anomaly: Option<AnomalyKind>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub peer_id: Option<String>,
+    /// The counterparty is on the watchlist. A new swap from one is alerted
+    /// on whatever its state.
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub watched: bool,
+    /// The swap is pinned. Its transitions are alerted on whatever the
+    /// state, and through quiet hours.
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub pinned: bool,
+    /// Instance name, only set when more than one ASB is monitored
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub instance: Option<String>,
+    #[serde(skip)]
+    pub network: Network,
+    /// Swap amounts, for alert rules
+    #[serde(skip)]
+    pub btc_sats: Option<u64>,
+    #[serde(skip)]
+    pub xmr_piconero: Option<u64>,
+}
+
+/// A changed state starts out bright and fades to dim, then back to
+/// normal, so a change made while nobody was looking still shows.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Highlight {
+    #[default]
+    None,
+    Dim,
+    Bright,
+}
+
+impl Transition {
+    /// A swap from a watched peer that just appeared.
+    pub fn is_watched_arrival(&self) -> bool {
+        self.watched && self.old_state.is_none() && self.stuck_for_secs.is_none()
+    }
+}
+
+/// Remembers the last seen state per swap between polls, which is what drives
+/// the `changed` flag and transition detection.
+#[derive(Debug, Default)]
+pub struct SwapTracker {
+    previous_states: HashMap<String, SwapState>,
+    /// Swaps present on the first poll are the baseline, not transitions
+    primed: bool,
+    network: Network,
+    /// Stamped onto views and transitions when monitoring several ASBs
+    instance: Option<String>,
+    role: Role,
+    stuck: StuckRules,
+    watchlist: Watchlist,
+    pins: PinnedSwaps,
+    /// Swap id to the state it was last reported stuck in and for how long,
+    /// so a stuck spell alerts once and again each time it doubles
+    alerted: HashMap<String, (SwapState, Duration)>,
+    /// How many of the source's anomalies have been seen; `None` until the
+    /// first call, whose anomalies are the baseline
+    anomalies_seen: Option<usize>,
+}
+
+impl SwapTracker {
+    pub fn new(network: Network, instance: Option<String>) -> Self {
+        Self {
+            network,
+            instance,
+            ..Self::default()
+        }
+    }
+
+    /// Flag swaps that sit in a non-terminal state for too long and emit a
+    /// stuck alert alongside the transitions, repeated whenever the time
+    /// stuck has doubled so alert rules can escalate.
+    pub fn with_stuck_rules(mut self, role: Role, rules: StuckRules) -> Self {
+        self.role = role;
+        self.stuck = rules;
+        self
+    }
+
+    /// Mark swaps with a counterparty on `watchlist`, in views and
+    /// transitions alike.
+    pub fn with_watchlist(mut self, watchlist: Watchlist) -> Self {
+        self.watchlist = watchlist;
+        self
+    }
+
+    /// Keep `pins` at the top of the table and alert on them sooner.
+    pub fn with_pins(mut self, pins: PinnedSwaps) -> Self {
+        self.pins = pins;
+        self
+    }
+
+    /// The pinned swaps, to pin or unpin one between polls.
+    pub fn pins(&mut self) -> &mut PinnedSwaps {
+        &mut self.pins
+    }
+
+    /// Start from states seen by an earlier run instead of treating the
+    /// first poll as the baseline. An empty map changes nothing.
+    pub fn restore(&mut self, states: HashMap<String, SwapState>) {
+        if !states.is_empty() {
+            self.previous_states = states;
+            self.primed = true;
+        }
+    }
+
+    /// Take `rows` as already seen, along with the next `anomalies` of the
+    /// source's: older swaps read in on request rather than news.
+    pub fn baseline(&mut self, rows: &[SwapRow], anomalies: usize) {
+        for row in rows {
+            self.previous_states
+                .entry(row.swap_id.clone())
+                .or_insert_with(|| row.state.clone());
+        }
+        if let Some(ref mut seen) = self.anomalies_seen {
+            *seen += anomalies;
+        }
+    }
+
+    pub fn update(&mut self, rows: Vec<SwapRow>) -> (Vec<SwapView>, Vec<Transition>) {
+        let mut transitions = if self.primed {
+            collect_transitions(&rows, &self.previous_states)
+        } else {
+            Vec::new()
+        };
+        let mut views = build_views(rows, &mut self.previous_states);
+        self.primed = true;
+        // Before the stuck check, which is quicker for pinned swaps
+        self.pins.mark(&mut views);
+
+        let observed_at = Utc::now().to_rfc3339();
+        for view in &mut views {
+            let Some(elapsed) = self.stuck.stuck_for(view, self.role) else {
+                self.alerted.remove(&view.swap_id);
+                continue;
+            };
+            view.stuck = true;
+            if let Some((state, reported)) = self.alerted.get(&view.swap_id) {
+                if *state == view.state && elapsed < *reported * 2 {
+                    continue;
+                }
+            }
+            self.alerted
+                .insert(view.swap_id.clone(), (view.state.clone(), elapsed));
+            transitions.push(Transition {
+                swap_id: view.swap_id.clone(),
+                old_state: Some(view.state.clone()),
+                new_state: view.state.clone(),
+                entered_at: view.entered_at.clone(),
+                observed_at: observed_at.clone(),
+                stuck_for_secs: Some(elapsed.as_secs()),
+                anomaly: None,
+                peer_id: view.peer_id.clone(),
+                watched: false,
+                pinned: false,
+                instance: None,
+                network: Network::default(),
+                btc_sats: view.details.btc_sats,
+                xmr_piconero: view.details.xmr_piconero,
+            });
+        }
+
+        for transition in &mut transitions {
+            transition.network = self.network;
+            transition.instance = self.instance.clone();
+            transition.watched = self.watchlist.contains(transition.peer_id.as_deref());
+            transition.pinned = self.pins.contains(&transition.swap_id);
+        }
+        for view in &mut views {
+            view.instance = self.instance.clone();
+        }
+        self.watchlist.mark(&mut views);
+        (views, transitions)
+    }
+
+    /// Alerts for the anomalies found since the last call, given all that
+    /// the source has found so far. Like the first poll, the first call
+    /// only takes note.
+    pub fn anomalies(&mut self, found: &[Anomaly]) -> Vec<Transition> {
+        let seen = self
+            .anomalies_seen
+            .replace(found.len())
+            .unwrap_or(found.len());
+        let observed_at = Utc::now().to_rfc3339();
+        found
+            .get(seen..)
+            .unwrap_or_default()
+            .iter()
+            .map(|anomaly| Transition {
+                swap_id: anomaly.swap_id.clone(),
+                old_state: Some(anomaly.from.clone()),
+                new_state: anomaly.to.clone(),
+                entered_at: anomaly.entered_at.clone(),
+                observed_at: observed_at.clone(),
+                stuck_for_secs: None,
+                anomaly: Some(anomaly.kind),
+                peer_id: None,
+                watched: false,
+                pinned: self.pins.contains(&anomaly.swap_id),
+                instance: self.instance.clone(),
+                network: self.network,
+                btc_sats: anomaly.btc_sats,
+                xmr_piconero: anomaly.xmr_piconero,
+            })
+            .collect()
+    }
+}
+
+/// Aggregate counts for the `stats` command.
+#[derive(Debug, Clone, Serialize)]
+pub struct SwapStats {
+    pub total_swaps: usize,
+    /// Every row in `swap_states`, i.e. all recorded state entries
+    pub total_state_rows: i64,
+    #[serde(flatten)]
+    pub summary: SwapSummary,
+    pub by_state: BTreeMap<String, usize>,
+    /// The database's layout, when it was read directly
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub schema: Option<Schema>,
+    /// Swaps started per hour or day, with `--chart`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub activity: Option<Activity>,
+}
+
+impl SwapStats {
+    pub fn from_latest(rows: &[SwapRow], total_state_rows: i64, role: Role) -> Self {
+        let mut by_state = BTreeMap::new();
+        for row in rows {
+            *by_state.entry(row.state.to_string()).or_insert(0) += 1;
+        }
+
+        Self {
+            total_swaps: rows.len(),
+            total_state_rows,
+            summary: SwapSummary::from_states(
+                rows.iter().map(|r| (&r.state, r.started_at.as_str())),
+                role,
+            ),
+            by_state,
+            schema: None,
+            activity: None,
+        }
+    }
+}
+
+/// Failed swaps after which a peer is flagged. A taker who keeps letting
+/// swaps run into refunds ties up the maker's XMR each time, which is what
+/// griefing looks like from ASB's side.
+pub const FLAG_AFTER_FAILURES: usize = 2;
+
+/// One counterparty in the `peers` listing.
+#[derive(Debug, Clone, Serialize)]
+pub struct PeerStats {
+    pub peer_id: String,
+    pub swaps: usize,
+    #[serde(flatten)]
+    pub summary: SwapSummary,
+    /// Failed swaps by how they ended; early refunds count as refunds
+    pub refunded: usize,
+    pub punished: usize,
+    pub aborted: usize,
+    /// Shares of the peer's finished swaps, to three places; `None` while
+    /// none has finished
+    pub completion_rate: Option<f64>,
+    pub refund_rate: Option<f64>,
+    /// At least [`FLAG_AFTER_FAILURES`] failed swaps
+    #[serde(skip_serializing_if = "is_false")]
+    pub flagged: bool,
+    /// When its first and its latest swap started
+    pub first_swap: String,
+    pub last_swap: String,
+    /// Where ASB last reached it, from `peer_addresses`
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub addresses: Vec<String>,
+}
+
+impl PeerStats {
+    /// Group swaps by counterparty, flagged peers first, then by most
+    /// swaps. Swaps without a recorded peer are left out.
+    pub fn from_latest(rows: &[SwapRow], role: Role) -> Vec<Self> {
+        let mut by_peer: BTreeMap<&str, Vec<&SwapRow>> = BTreeMap::new();
+        for row in rows {
+            if let Some(ref peer_id) = row.peer_id {
+                by_peer.entry(peer_id).or_default().push(row);
+            }
+        }
+
+        let mut peers: Vec<Self> = by_peer
+            .into_iter()
+            .map(|(peer_id, swaps)| {
+                let started = swaps.iter().map(|r| r.started_at.as_str());
+                let summary = SwapSummary::from_states(
+                    swaps.iter().map(|r| (&r.state, r.started_at.as_str())),
+                    role,
+                );
+                let count = |wanted: &[SwapState]| {
+                    swaps
+                        .iter()
+                        .filter(|r| r.state.outcome(role) == Outcome::Failed)
+                        .filter(|r| wanted.contains(&r.state))
+                        .count()
+                };
+                let refunded = count(&[
+                    SwapState::XmrRefunded,
+                    SwapState::BtcRefunded,
+                    SwapState::BtcEarlyRefunded,
+                ]);
+                let finished = summary.completed + summary.failed;
+                let rate = |n: usize| {
+                    (finished > 0).then(|| (n as f64 / finished as f64 * 1000.0).round() / 1000.0)
+                };
+                Self {
+                    peer_id: peer_id.to_string(),
+                    swaps: swaps.len(),
+                    refunded,
+                    punished: count(&[SwapState::BtcPunished]),
+                    aborted: count(&[SwapState::SafelyAborted]),
+                    completion_rate: rate(summary.completed),
+                    refund_rate: rate(refunded),
+                    flagged: summary.failed >= FLAG_AFTER_FAILURES,
+                    summary,
+                    first_swap: started.clone().min().unwrap_or_default().to_string(),
+                    last_swap: started.max().unwrap_or_default().to_string(),
+                    addresses: Vec::new(),
+                }
+            })
+            .collect();
+        peers.sort_by(|a, b| {
+            (b.flagged, b.swaps, &b.last_swap).cmp(&(a.flagged, a.swaps, &a.last_swap))
+        });
+        peers
+    }
+}
+
+/// Bucket size for the `earnings` report.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Period {
+    Day,
+    Week,
+    #[default]
+    Month,
+}
+
+impl std::str::FromStr for Period {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s {
+            "day" => Ok(Period::Day),
+            "week" => Ok(Period::Week),
+            "month" => Ok(Period::Month),
+            other => anyhow::bail!("unknown period: {other} (expected day, week or month)"),
+        }
+    }
+}
+
+impl Period {
+    /// `2026-10-15`, `2026-W42` (ISO week) or `2026-10`, in UTC.
+    pub fn label(self, at: DateTime<Utc>) -> String {
+        let format = match self {
+            Period::Day => "%Y-%m-%d",
+            Period::Week => "%G-W%V",
+            Period::Month => "%Y-%m",
+        };
+        at.format(format).to_string()
+    }
+}
+
+/// How `earnings` turns completed swaps into profit.
+#[derive(Debug, Clone)]
+pub struct EarningsOptions {
+    pub period: Period,
+    /// ASB's `ask_spread`: the markup over the market price, e.g. 0.02
+    pub spread: f64,
+    /// Fiat value of one BTC, for the fiat column
+    pub btc_price: Option<f64>,
+    pub currency: String,
+}
+
+impl Default for EarningsOptions {
+    fn default() -> Self {
+        Self {
+            period: Period::default(),
+            // ASB's default ask_spread
+            spread: 0.02,
+            btc_price: None,
+            currency: "USD".to_string(),
+        }
+    }
+}
+
+/// Realized earnings over one period, or the total.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct EarningsRow {
+    pub period: String,
+    pub swaps: usize,
+    pub btc_received_sats: u64,
+    pub xmr_sent_piconero: u64,
+    /// The spread's share of the BTC received
+    pub earnings_sats: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub earnings_fiat: Option<f64>,
+}
+
+impl EarningsRow {
+    fn add(&mut self, btc_sats: u64, xmr_piconero: u64, spread: f64) {
+        self.swaps += 1;
+        self.btc_received_sats += btc_sats;
+        self.xmr_sent_piconero += xmr_piconero;
+        // ASB quotes market × (1 + spread), so that fraction of the BTC is
+        // the markup
+        self.earnings_sats += (btc_sats as f64 * spread / (1.0 + spread)).round() as u64;
+    }
+}
+
+/// Per-period earnings of the completed swaps in `rows`, newest period
+/// first, followed by the total. Only the maker earns a spread.
+pub fn earnings(rows: &[SwapRow], options: &EarningsOptions) -> Vec<EarningsRow> {
+    let mut periods: BTreeMap<String, EarningsRow> = BTreeMap::new();
+    let mut total = EarningsRow {
+        period: "Total".to_string(),
+        ..EarningsRow::default()
+    };
+
+    for row in rows {
+        if row.state.outcome(Role::Maker) != Outcome::Completed {
+            continue;
+        }
+        let (Some(btc), Some(xmr), Some(at)) = (
+            row.details.btc_sats,
+            row.details.xmr_piconero,
+            parse_entered_at(&row.entered_at),
+        ) else {
+            continue;
+        };
+        let label = options.period.label(at);
+        periods
+            .entry(label.clone())
+            .or_insert_with(|| EarningsRow {
+                period: label,
+                ..EarningsRow::default()
+            })
+            .add(btc, xmr, options.spread);
+        total.add(btc, xmr, options.spread);
+    }
+
+    let mut report: Vec<EarningsRow> = periods.into_values().rev().collect();
+    report.push(total);
+    if let Some(price) = options.btc_price {
+        for row in &mut report {
+            row.earnings_fiat = Some(row.earnings_sats as f64 / 100_000_000.0 * price);
+        }
+    }
+    report
+}
+
+/// The rate one swap was agreed at, against the market when it started.
+#[derive(Debug, Clone, Serialize)]
+pub struct RateRow {
+    pub swap_id: String,
+    pub state: SwapState,
+    pub started_at: String,
+    pub btc_sats: u64,
+    pub xmr_piconero: u64,
+    /// BTC per XMR
+    pub rate: f64,
+    /// BTC per XMR on the market, when the price source knows it
+    pub market_rate: Option<f64>,
+    /// How far above the market the swap was agreed, e.g. 0.02 for 2%
+    pub premium: Option<f64>,
+}
+
+impl RateRow {
+    /// The `limit` most recently started swaps in `rows` whose amounts are
+    /// known, newest first.
+    pub fn recent(rows: &[SwapRow], limit: usize) -> Vec<Self> {
+        let mut recent: Vec<(DateTime<Utc>, Self)> = rows
+            .iter()
+            .filter_map(|row| {
+                let btc_sats = row.details.btc_sats.filter(|&n| n > 0)?;
+                let xmr_piconero = row.details.xmr_piconero.filter(|&n| n > 0)?;
+                let started = parse_entered_at(&row.started_at)?;
+                let rate = (btc_sats as f64 / 1e8) / (xmr_piconero as f64 / 1e12);
+                Some((
+                    started,
+                    Self {
+                        swap_id: row.swap_id.clone(),
+                        state: row.state.clone(),
+                        started_at: row.started_at.clone(),
+                        btc_sats,
+                        xmr_piconero,
+                        rate,
+                        market_rate: None,
+                        premium: None,
+                    },
+                ))
+            })
+            .collect();
+        recent.sort_by_key(|(started, _)| std::cmp::Reverse(*started));
+        recent.into_iter().take(limit).map(|(_, row)| row).collect()
+    }
+
+    /// When the swap started, which is when its rate was agreed.
+    pub fn started(&self) -> Option<DateTime<Utc>> {
+        parse_entered_at(&self.started_at)
+    }
+
+    pub fn set_market_rate(&mut self, market_rate: Option<f64>) {
+        self.market_rate = market_rate.filter(|&rate| rate > 0.0);
+        self.premium = self.market_rate.map(|market| self.rate / market - 1.0);
+    }
+}
+
+/// One completed swap as a trade, for `export --format accounting`.
+#[derive(Debug, Clone)]
+pub struct AccountingRow {
+    pub swap_id: String,
+    /// Which side of the trade the instance was on
+    pub role: Role,
+    pub completed_at: DateTime<Utc>,
+    pub started_at: DateTime<Utc>,
+    pub btc_sats: u64,
+    pub xmr_piconero: u64,
+    /// The BTC transaction the trade settled in: the redeem for the maker,
+    /// who receives BTC, the lock for the taker, who sends it
+    pub txid: Option<String>,
+    /// BTC per XMR on the market when the swap started
+    pub market_rate: Option<f64>,
+}
+
+impl AccountingRow {
+    /// The completed swaps in `rows` whose amounts are known, oldest
+    /// first. A swap completed when it entered its final state.
+    pub fn completed(rows: &[SwapRow], role: Role) -> Vec<Self> {
+        let mut completed: Vec<Self> = rows
+            .iter()
+            .filter(|row| row.state.outcome(role) == Outcome::Completed)
+            .filter_map(|row| {
+                let txids = &row.details.txids;
+                let txid = match role {
+                    Role::Maker => txids.get("btc_redeem").or(txids.get("btc_lock")),
+                    Role::Taker => txids.get("btc_lock"),
+                };
+                Some(Self {
+                    swap_id: row.swap_id.clone(),
+                    role,
+                    completed_at: parse_entered_at(&row.entered_at)?,
+                    started_at: parse_entered_at(&row.started_at)?,
+                    btc_sats: row.details.btc_sats.filter(|&n| n > 0)?,
+                    xmr_piconero: row.details.xmr_piconero.filter(|&n| n > 0)?,
+                    txid: txid.cloned(),
+                    market_rate: None,
+                })
+            })
+            .collect();
+        completed.sort_by_key(|row| row.completed_at);
+        completed
+    }
+
+    /// How much more BTC changed hands than the XMR was worth on the
+    /// market, in satoshis: what the maker earned or the taker paid.
+    pub fn spread_sats(&self) -> Option<i64> {
+        let market = self.market_rate.filter(|&rate| rate > 0.0)?;
+        let market_sats = self.xmr_piconero as f64 / 1e12 * market * 1e8;
+        Some(self.btc_sats as i64 - market_sats.round() as i64)
+    }
+}
+
+/// Mean premium of the rows the market rate is known for.
+pub fn average_premium(rows: &[RateRow]) -> Option<f64> {
+    let premiums: Vec<f64> = rows.iter().filter_map(|row| row.premium).collect();
+    (!premiums.is_empty()).then(|| premiums.iter().sum::<f64>() / premiums.len() as f64)
+}
+
+/// How a swap ended up, from the operator's side.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    Completed,
+    Failed,
+    InProgress,
+}
+
+/// How long a swap may sit in a non-terminal state before it counts as
+/// stuck. A per-state threshold beats the default; with neither set the
+/// state is never flagged.
+#[derive(Debug, Clone, Default)]
+pub struct StuckRules {
+    pub default: Option<Duration>,
+    pub states: HashMap<String, Duration>,
+}
+
+impl StuckRules {
+    /// `None` sets the default threshold. A state this version doesn't know
+    /// is an error, as a mistyped name would never be flagged.
+    pub fn set(&mut self, state: Option<&str>, threshold: Duration) -> anyhow::Result<()> {
+        match state.map(str::trim) {
+            None | Some("default") => self.default = Some(threshold),
+            Some(state) => {
+                if let SwapState::Unknown(_) = SwapState::from(state) {
+                    anyhow::bail!(
+                        "unknown state {state:?} (expected default or one of {})",
+                        STATE_NAMES.join(", ")
+                    );
+                }
+                self.states.insert(state.to_string(), threshold);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn threshold(&self, state: &str) -> Option<Duration> {
+        self.states.get(state).copied().or(self.default)
+    }
+
+    /// Time spent in the current state, when that is past the threshold.
+    /// A pinned swap is stuck after half of it.
+    pub fn stuck_for(&self, view: &SwapView, role: Role) -> Option<Duration> {
+        if view.state.is_terminal(role) {
+            return None;
+        }
+        let threshold = self.threshold(view.state.as_str())?;
+        let threshold = match view.pinned {
+            true => threshold / 2,
+            false => threshold,
+        };
+        let elapsed = (Utc::now() - parse_entered_at(&view.entered_at)?)
+            .to_std()
+            .ok()?;
+        (elapsed >= threshold).then_some(elapsed)
+    }
+
+    /// Set `stuck` on views for one-shot output, without alerting.
+    pub fn mark(&self, views: &mut [SwapView], role: Role) {
+        for view in views {
+            view.stuck = self.stuck_for(view, role).is_some();
+        }
+    }
+}
+
+/// `--since` and `--until`: which swaps count, by when they entered their
+/// latest state. A side that isn't set is open.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DateRange {
+    pub since: Option<TimeBound>,
+    /// Exclusive
+    pub until: Option<TimeBound>,
+}
+
+/// A point in time, fixed or relative to now, so that `--since 24h` keeps
+/// sliding in a long watch.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimeBound {
+    At(DateTime<Utc>),
+    Ago(Duration),
+}
+
+impl TimeBound {
+    pub fn resolve(&self, now: DateTime<Utc>) -> DateTime<Utc> {
+        match *self {
+            TimeBound::At(at) => at,
+            TimeBound::Ago(ago) => now - chrono::Duration::from_std(ago).unwrap_or_default(),
+        }
+    }
+}
+
+impl fmt::Display for TimeBound {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            TimeBound::At(at) => write!(f, "{}", at.with_timezone(&Local).format("%Y-%m-%d %H:%M")),
+            TimeBound::Ago(ago) => {
+                let secs = ago.as_secs();
+                match secs {
+                    s if s > 0 && s % 86_400 == 0 => write!(f, "{}d ago", s / 86_400),
+                    s if s > 0 && s % 3600 == 0 => write!(f, "{}h ago", s / 3600),
+                    s if s > 0 && s % 60 == 0 => write!(f, "{}m ago", s / 60),
+                    s => write!(f, "{s}s ago"),
+                }
+            }
+        }
+    }
+}
+
+impl DateRange {
+    pub fn is_empty(&self) -> bool {
+        self.since.is_none() && self.until.is_none()
+    }
+
+    /// Whether `entered_at` falls in the range. A time that can't be read
+    /// only passes an open range.
+    pub fn contains(&self, entered_at: &str) -> bool {
+        if self.is_empty() {
+            return true;
+        }
+        let Some(at) = parse_entered_at(entered_at) else {
+            return false;
+        };
+        let now = Utc::now();
+        self.since.is_none_or(|since| at >= since.resolve(now))
+            && self.until.is_none_or(|until| at < until.resolve(now))
+    }
+}
+
+impl fmt::Display for DateRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.since, self.until) {
+            (Some(since), Some(until)) => write!(f, "since {since} until {until}"),
+            (Some(since), None) => write!(f, "since {since}"),
+            (None, Some(until)) => write!(f, "until {until}"),
+            (None, None) => f.write_str("any time"),
+        }
+    }
+}
+
+/// Peers to keep an eye on, such as a taker that griefed before. Their
+/// swaps are highlighted, and a new one raises an alert.
+#[derive(Debug, Clone, Default)]
+pub struct Watchlist(HashSet<String>);
+
+impl Watchlist {
+    pub fn new(peer_ids: impl IntoIterator<Item = String>) -> Self {
+        Self(peer_ids.into_iter().collect())
+    }
+
+    pub fn contains(&self, peer_id: Option<&str>) -> bool {
+        peer_id.is_some_and(|id| self.0.contains(id))
+    }
+
+    pub fn mark(&self, views: &mut [SwapView]) {
+        for view in views {
+            view.watched = self.contains(view.peer_id.as_deref());
+        }
+    }
+}
+
+/// Swaps to babysit, from `--pin`, the config file or `p` in the
+/// full-screen view: kept at the top of the table whatever the filter and
+/// order, and alerted on sooner and louder. A pin is a swap id or the
+/// start of one.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PinnedSwaps(BTreeSet<String>);
+
+impl PinnedSwaps {
+    pub fn new(ids: impl IntoIterator<Item = String>) -> Self {
+        Self(ids.into_iter().map(|id| normalize_pin(&id)).collect())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &str> {
+        self.0.iter().map(String::as_str)
+    }
+
+    pub fn contains(&self, swap_id: &str) -> bool {
+        let id = normalize_pin(swap_id);
+        self.0.iter().any(|pin| id.starts_with(pin.as_str()))
+    }
+
+    /// Unpin `swap_id` if any pin covers it, or pin it. Returns whether it
+    /// is pinned now.
+    pub fn toggle(&mut self, swap_id: &str) -> bool {
+        let id = normalize_pin(swap_id);
+        let before = self.0.len();
+        self.0.retain(|pin| !id.starts_with(pin.as_str()));
+        if self.0.len() < before {
+            return false;
+        }
+        self.0.insert(id);
+        true
+    }
+
+    pub fn mark(&self, views: &mut [SwapView]) {
+        for view in views {
+            view.pinned = self.contains(&view.swap_id);
+        }
+    }
+}
+
+fn normalize_pin(id: &str) -> String {
+    id.trim().to_ascii_lowercase()
+}
+
+/// Outcome counts plus today's new swaps, for `stats` and the watch-mode
+/// summary strip.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct SwapSummary {
+    pub completed: usize,
+    pub failed: usize,
+    pub in_progress: usize,
+    /// Swaps whose first state was recorded today, local time
+    pub new_today: usize,
+}
+
+impl SwapSummary {
+    /// Build from `(current state, started_at)` pairs.
+    pub fn from_states<'a>(
+        swaps: impl IntoIterator<Item = (&'a SwapState, &'a str)>,
+        role: Role,
+    ) -> Self {
+        let today = Local::now().date_naive();
+        let mut summary = Self::default();
+
+        for (state, started_at) in swaps {
+            match state.outcome(role) {
+                Outcome::Completed => summary.completed += 1,
+                Outcome::Failed => summary.failed += 1,
+                Outcome::InProgress => summary.in_progress += 1,
+            }
+            let started = parse_entered_at(started_at).map(|t| t.with_timezone(&Local));
+            if started.is_some_and(|t| t.date_naive() == today) {
+                summary.new_today += 1;
+            }
+        }
+        summary
+    }
+}
+
+/// Windows up to this long are counted per hour, longer ones per day.
+pub const HOURLY_UP_TO: Duration = Duration::from_secs(48 * 3600);
+
+/// Swaps started per hour or per day, local time, over a window ending
+/// now: `stats --chart` and the activity line above the watch table.
+#[derive(Debug, Clone, Serialize)]
+pub struct Activity {
+    pub per: ActivityUnit,
+    /// Oldest first; the last is the hour or day under way
+    pub buckets: Vec<ActivityBucket>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ActivityUnit {
+    Hour,
+    Day,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ActivityBucket {
+    /// `2026-10-15` per day, `2026-10-15 14:00` per hour
+    pub start: String,
+    pub started: usize,
+}
+
+impl Activity {
+    /// Count the swaps started in each hour or day of `window`, from each
+    /// swap's `started_at`.
+    pub fn new<'a>(started_at: impl IntoIterator<Item = &'a str>, window: Duration) -> Self {
+        let now = Local::now();
+        let (per, size) = match window <= HOURLY_UP_TO {
+            true => (ActivityUnit::Hour, 3600),
+            false => (ActivityUnit::Day, 86_400),
+        };
+        let len = window.as_secs().div_ceil(size).max(1) as usize;
+        let today = now.date_naive();
+        let hour = now.naive_local()
+            - chrono::Duration::seconds(now.naive_local().and_utc().timestamp() % 3600);
+
+        let mut counts = vec![0; len];
+        for started in started_at.into_iter().filter_map(parse_entered_at) {
+            let started = started.with_timezone(&Local).naive_local();
+            // Hours or days before the current one; a clock ahead of ours
+            // counts as now
+            let back = match per {
+                ActivityUnit::Hour => ((hour - started).num_seconds().max(0) + 3599) / 3600,
+                ActivityUnit::Day => (today - started.date()).num_days().max(0),
+            } as usize;
+            if let Some(i) = (len - 1).checked_sub(back) {
+                counts[i] += 1;
+            }
+        }
+
+        let buckets = counts
+            .into_iter()
+            .enumerate()
+            .map(|(i, started)| {
+                let back = (len - 1 - i) as i64;
+                let start = match per {
+                    ActivityUnit::Hour => (hour - chrono::Duration::hours(back))
+                        .format("%Y-%m-%d %H:%M")
+                        .to_string(),
+                    ActivityUnit::Day => (today - chrono::Duration::days(back))
+                        .format("%Y-%m-%d")
+                        .to_string(),
+                };
+                ActivityBucket { start, started }
+            })
+            .collect();
+        Self { per, buckets }
+    }
+
+    pub fn total(&self) -> usize {
+        self.buckets.iter().map(|b| b.started).sum()
+    }
+
+    /// Swaps started in the hour or day under way.
+    pub fn current(&self) -> usize {
+        self.buckets.last().map_or(0, |b| b.started)
+    }
+}
+
+/// Which chains a database's swaps run on.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Network {
+    Mainnet,
+    /// BTC testnet paired with XMR stagenet; ASB calls this "testnet"
+    #[default]
+    #[serde(alias = "stagenet")]
+    Testnet,
+}
+
+/// Which side of the swap a database belongs to.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    /// ASB, keeping its data under `asb/`
+    #[default]
+    Maker,
+    /// The swap CLI, keeping its data under `cli/`
+    Taker,
+}
+
+impl std::str::FromStr for Role {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s {
+            "maker" | "asb" => Ok(Role::Maker),
+            "taker" | "cli" => Ok(Role::Taker),
+            other => anyhow::bail!("unknown role: {other} (expected maker or taker)"),
+        }
+    }
+}
+
+impl Role {
+    pub fn dir_name(self) -> &'static str {
+        match self {
+            Role::Maker => "asb",
+            Role::Taker => "cli",
+        }
+    }
+
+    pub fn title(self) -> &'static str {
+        match self {
+            Role::Maker => "WraithSwap ASB Monitor",
+            Role::Taker => "WraithSwap Swap CLI Monitor",
+        }
+    }
+
+    /// What to do when the database doesn't exist yet.
+    pub fn start_hint(self, network: Network) -> String {
+        match self {
+            Role::Maker => format!("Start ASB first: {}", network.asb_start_hint()),
+            Role::Taker => match network {
+                Network::Mainnet => "Start a swap first: ./bin/swap buy-xmr ...".to_string(),
+                Network::Testnet => {
+                    "Start a swap first: ./bin/swap --testnet buy-xmr ...".to_string()
+                }
+            },
+        }
+    }
+}
+
+impl std::str::FromStr for Network {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s {
+            "mainnet" => Ok(Network::Mainnet),
+            "testnet" | "stagenet" => Ok(Network::Testnet),
+            other => anyhow::bail!("unknown network: {other} (expected mainnet or testnet)"),
+        }
+    }
+}
+
+impl Network {
+    pub fn dir_name(self) -> &'static str {
+        match self {
+            Network::Mainnet => "mainnet",
+            Network::Testnet => "testnet",
+        }
+    }
+
+    pub fn asb_start_hint(self) -> &'static str {
+        match self {
+            Network::Mainnet => "./bin/asb start",
+            Network::Testnet => "./bin/asb --testnet start",
+        }
+    }
+}
+
+/// Views for a poll's rows, flagging swaps whose state differs from `prev`
+/// and updating `prev` to match.
+pub fn build_views(rows: Vec<SwapRow>, prev: &mut HashMap<String, SwapState>) -> Vec<SwapView> {
+    rows.into_iter()
+        .map(|row| {
+            let changed = prev
+                .get(&row.swap_id)
+                .is_some_and(|prev| *prev != row.state);
+            prev.insert(row.swap_id.clone(), row.state.clone());
+
+            SwapView {
+                swap_id: row.swap_id,
+                state: row.state,
+                entered_at: row.entered_at,
+                started_at: row.started_at,
+                changed,
+                highlight: match changed {
+                    true => Highlight::Bright,
+                    false => Highlight::None,
+                },
+                stuck: false,
+                peer_id: row.peer_id,
+                watched: false,
+                pinned: false,
+                tags: Vec::new(),
+                details: row.details,
+                eta: None,
+                chain: ChainStatus::default(),
+                fiat: None,
+                instance: None,
+            }
+        })
+        .collect()
+}
+
+/// Compare freshly fetched rows against the last seen state per swap.
+pub fn collect_transitions(rows: &[SwapRow], prev: &HashMap<String, SwapState>) -> Vec<Transition> {
+    let observed_at = Utc::now().to_rfc3339();
+
+    rows.iter()
+        .filter_map(|row| {
+            let old_state = prev.get(&row.swap_id);
+            if old_state == Some(&row.state) {
+                return None;
+            }
+
+            Some(Transition {
+                swap_id: row.swap_id.clone(),
+                old_state: old_state.cloned(),
+                new_state: row.state.clone(),
+                entered_at: row.entered_at.clone(),
+                observed_at: observed_at.clone(),
+                stuck_for_secs: None,
+                anomaly: None,
+                peer_id: row.peer_id.clone(),
+                watched: false,
+                pinned: false,
+                instance: None,
+                network: Network::default(),
+                btc_sats: row.details.btc_sats,
+                xmr_piconero: row.details.xmr_piconero,
+            })
+        })
+        .collect()
+}
+
+/// Parse ASB's `entered_at` text. Depending on the release it is written as
+/// RFC 3339, `YYYY-MM-DD HH:MM:SS.ffffff UTC`, or the `time` crate's
+/// `YYYY-MM-DD H:MM:SS.ffffff +HH:MM:SS` display form.
+pub fn parse_entered_at(raw: &str) -> Option<DateTime<Utc>> {
+    let raw = raw.trim();
+    if let Ok(dt) = DateTime::parse_from_rfc3339(raw) {
+        return Some(dt.with_timezone(&Utc));
+    }
+
+    let (stamp, zone) = match raw.rsplit_once(' ') {
+        Some((stamp, zone)) if stamp.contains(' ') => (stamp, zone),
+        _ => (raw, "UTC"),
+    };
+    let naive = NaiveDateTime::parse_from_str(stamp, "%Y-%m-%d %H:%M:%S%.f").ok()?;
+
+    let offset_secs = match zone {
+        "UTC" | "Z" => 0,
+        _ => {
+            let sign = match zone.chars().next()? {
+                '+' => 1,
+                '-' => -1,
+                _ => return None,
+            };
+            let mut parts = zone[1..].split(':').map(|p| p.parse::<i64>().ok());
+            let hours = parts.next().flatten()?;
+            let minutes = parts.next().flatten().unwrap_or(0);
+            let seconds = parts.next().flatten().unwrap_or(0);
+            sign * (hours * 3600 + minutes * 60 + seconds)
+        }
+    };
+
+    Some(naive.and_utc() - chrono::Duration::seconds(offset_secs))
+}
+
+/// Where a swap stands relative to its timelocks, in blocks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case", tag = "phase", content = "blocks_left")]
+pub enum Timelock {
+    /// Blocks until either side may publish the cancel transaction
+    Cancel(u32),
+    /// Blocks until ASB may punish, assuming cancel confirms right away
+    Punish(u32),
+    /// Both timelocks have expired
+    Punishable,
+}
+
+impl Timelock {
+    /// `lock_confirmations` counts confirmations of the BTC lock transaction.
+    pub fn from_details(details: &SwapDetails, lock_confirmations: u32) -> Option<Self> {
+        let cancel = details.cancel_timelock?;
+        if lock_confirmations < cancel {
+            return Some(Timelock::Cancel(cancel - lock_confirmations));
+        }
+        let punish_at = cancel + details.punish_timelock?;
+        Some(match punish_at.checked_sub(lock_confirmations) {
+            Some(left) if left > 0 => Timelock::Punish(left),
+            _ => Timelock::Punishable,
+        })
+    }
+}
+
+/// Chain facts attached to a view.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ChainStatus {
+    /// Confirmations per BTC transaction, keyed like `SwapDetails::txids`;
+    /// 0 while in the mempool
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub confirmations: BTreeMap<String, u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timelock: Option<Timelock>,
+    /// The XMR lock as seen by monero-wallet-rpc
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub xmr_lock: Option<XmrTransfer>,
+}
+
+impl ChainStatus {
+    pub fn is_empty(&self) -> bool {
+        self.confirmations.is_empty() && self.timelock.is_none() && self.xmr_lock.is_none()
+    }
+
+    /// The most advanced BTC transaction with known confirmations: anything
+    /// past the lock (redeem, cancel, refund) wins over the lock itself, and
+    /// a refund or punish over the cancel.
+    pub fn latest(&self) -> Option<(&str, u32)> {
+        self.confirmations
+            .iter()
+            .filter_map(|(label, count)| Some((btc_tx_rank(label)?, label, *count)))
+            .max_by_key(|&(rank, _, _)| rank)
+            .map(|(_, label, count)| (label.as_str(), count))
+    }
+}
+
+/// What monero-wallet-rpc knows about one transfer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct XmrTransfer {
+    pub confirmations: u64,
+    /// Blocks until the outputs are spendable; 0 once unlocked
+    pub unlocks_in: u64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stuck_rules_take_known_states() {
+        let mut rules = StuckRules::default();
+        rules.set(None, Duration::from_secs(3600)).unwrap();
+        rules
+            .set(Some("XmrLocked"), Duration::from_secs(60))
+            .unwrap();
+        assert_eq!(rules.threshold("XmrLocked"), Some(Duration::from_secs(60)));
+        assert_eq!(
+            rules.threshold("BtcLocked"),
+            Some(Duration::from_secs(3600))
+        );
+        rules.set(Some("default"), Duration::from_secs(5)).unwrap();
+        assert_eq!(rules.threshold("BtcLocked"), Some(Duration::from_secs(5)));
+    }
+
+    #[test]
+    fn stuck_rules_reject_unknown_states() {
+        let mut rules = StuckRules::default();
+        let err = rules
+            .set(Some("BtcLockd"), Duration::from_secs(7200))
+            .unwrap_err();
+        assert!(err.to_string().contains("BtcLockd"));
+        assert!(err.to_string().contains("BtcLocked"));
+        assert!(rules.states.is_empty());
+    }
+
+    #[test]
+    fn decode_plain_names() {
+        let (state, details) = decode_state("BtcLocked");
+        assert_eq!(state, SwapState::BtcLocked);
+        assert_eq!(details, SwapDetails::default());
+        let (state, _) = decode_state("SomethingNew");
+        assert_eq!(state, SwapState::Unknown("SomethingNew".into()));
+    }
+
+    #[test]
+    fn decode_unwraps_role_and_done() {
+        let (state, _) = decode_state(r#"{"Bob":{"Done":"XmrRedeemed"}}"#);
+        assert_eq!(state, SwapState::XmrRedeemed);
+        let (state, _) = decode_state(r#"{"Alice":{"Done":{"BtcPunished":{}}}}"#);
+        assert_eq!(state, SwapState::BtcPunished);
+        let (state, _) = decode_state(r#"{"Alice":{"XmrLocked":{"state3":{}}}}"#);
+        assert_eq!(state, SwapState::XmrLocked);
+    }
+
+    #[test]
+    fn decode_collects_details() {
+        let raw = r#"{"Alice":{"XmrLockTransferProofSent":{"state3":{
+            "btc":150000,"xmr":250000000000,
+            "cancel_timelock":72,"punish_timelock":144,
+            "transfer_proof":{"tx_hash":"abcd","tx_key":"00"},
+            "nested":[{"redeem_txid":"ef01"}]
+        }}}}"#;
+        let (state, details) = decode_state(raw);
+        assert_eq!(state, SwapState::XmrLockTransferProofSent);
+        assert_eq!(details.btc_sats, Some(150_000));
+        assert_eq!(details.xmr_piconero, Some(250_000_000_000));
+        assert_eq!(details.cancel_timelock, Some(72));
+        assert_eq!(details.punish_timelock, Some(144));
+        assert_eq!(details.txids["xmr_lock"], "abcd");
+        assert_eq!(details.txids["redeem_txid"], "ef01");
+    }
+
+    #[test]
+    fn decode_keeps_the_first_amount() {
+        let raw = r#"{"Alice":{"BtcLocked":{"btc":1,"inner":{"btc":2,"btc_amount":3}}}}"#;
+        assert_eq!(decode_state(raw).1.btc_sats, Some(1));
+    }
+
+    #[test]
+    fn decode_falls_back_on_odd_json() {
+        let (state, details) = decode_state("[1,2]");
+        assert_eq!(state, SwapState::Unknown("[1,2]".into()));
+        assert_eq!(details, SwapDetails::default());
+    }
+
+    #[test]
+    fn merge_prefers_newer_details() {
+        let mut older = SwapDetails {
+            btc_sats: Some(1),
+            xmr_piconero: Some(2),
+            ..SwapDetails::default()
+        };
+        older.txids.insert("btc_lock".into(), "aa".into());
+        let mut newer = SwapDetails {
+            btc_sats: Some(10),
+            ..SwapDetails::default()
+        };
+        newer.txids.insert("btc_redeem".into(), "bb".into());
+        older.merge(newer);
+        assert_eq!(older.btc_sats, Some(10));
+        assert_eq!(older.xmr_piconero, Some(2));
+        assert_eq!(older.txids.len(), 2);
+    }
+
+    #[test]
+    fn date_ranges_include_since_and_exclude_until() {
+        let at = |s: &str| TimeBound::At(parse_entered_at(s).unwrap());
+        let range = DateRange {
+            since: Some(at("2024-05-01 00:00:00")),
+            until: Some(at("2024-05-02 00:00:00")),
+        };
+        assert!(range.contains("2024-05-01 00:00:00"));
+        assert!(range.contains("2024-05-01 23:59:59.999"));
+        assert!(!range.contains("2024-05-02 00:00:00"));
+        assert!(!range.contains("2024-04-30 23:59:59"));
+        assert!(!range.contains("garbage"));
+        assert!(DateRange::default().contains("garbage"));
+    }
+
+    #[test]
+    fn relative_bounds_read_naturally() {
+        let ago = |secs| TimeBound::Ago(Duration::from_secs(secs)).to_string();
+        assert_eq!(ago(86_400 * 7), "7d ago");
+        assert_eq!(ago(7200), "2h ago");
+        assert_eq!(ago(90 * 60), "90m ago");
+        assert_eq!(ago(45), "45s ago");
+        assert_eq!(DateRange::default().to_string(), "any time");
+        let range = DateRange {
+            since: Some(TimeBound::Ago(Duration::from_secs(86_400))),
+            until: None,
+        };
+        assert_eq!(range.to_string(), "since 1d ago");
+    }
+
+    /// A swap that entered `state` `ago` seconds before now.
+    fn swap(id: &str, state: &str, ago: i64) -> SwapRow {
+        let entered_at = (Utc::now() - chrono::Duration::seconds(ago))
+            .format("%Y-%m-%d %H:%M:%S")
+            .to_string();
+        SwapRow {
+            swap_id: id.into(),
+            state: state.into(),
+            entered_at: entered_at.clone(),
+            started_at: entered_at,
+            peer_id: None,
+            details: SwapDetails::default(),
+        }
+    }
+
+    #[test]
+    fn the_first_poll_is_the_baseline() {
+        let mut tracker = SwapTracker::new(Network::Testnet, None);
+        let (views, transitions) = tracker.update(vec![swap("a", "BtcLocked", 60)]);
+        assert_eq!(views.len(), 1);
+        assert!(!views[0].changed);
+        assert!(transitions.is_empty());
+    }
+
+    #[test]
+    fn changes_after_the_baseline_are_transitions() {
+        let mut tracker = SwapTracker::new(Network::Testnet, Some("asb".into()));
+        tracker.update(vec![swap("a", "BtcLocked", 60)]);
+        let (views, transitions) = tracker.update(vec![
+            swap("a", "XmrLockTransactionSent", 0),
+            swap("b", "Started", 0),
+        ]);
+        assert!(views[0].changed);
+        assert!(!views[1].changed);
+        assert_eq!(transitions.len(), 2);
+        assert_eq!(transitions[0].old_state, Some(SwapState::BtcLocked));
+        assert_eq!(transitions[0].new_state, SwapState::XmrLockTransactionSent);
+        assert_eq!(transitions[0].network, Network::Testnet);
+        assert_eq!(transitions[0].instance.as_deref(), Some("asb"));
+        // A swap we hadn't seen yet
+        assert_eq!(transitions[1].old_state, None);
+
+        let (views, transitions) = tracker.update(vec![swap("a", "XmrLockTransactionSent", 0)]);
+        assert!(!views[0].changed);
+        assert!(transitions.is_empty());
+    }
+
+    #[test]
+    fn restored_states_replace_the_baseline() {
+        let mut tracker = SwapTracker::new(Network::Mainnet, None);
+        tracker.restore(HashMap::from([("a".to_string(), SwapState::BtcLocked)]));
+        let (_, transitions) = tracker.update(vec![swap("a", "XmrLocked", 0)]);
+        assert_eq!(transitions.len(), 1);
+    }
+
+    fn hour_rules() -> StuckRules {
+        let mut rules = StuckRules::default();
+        rules.set(None, Duration::from_secs(3600)).unwrap();
+        rules
+    }
+
+    #[test]
+    fn swaps_are_stuck_past_the_threshold() {
+        let rules = hour_rules();
+        let mut views = build_views(
+            vec![
+                swap("a", "BtcLocked", 3000),
+                swap("b", "BtcLocked", 4000),
+                swap("c", "BtcRedeemed", 9000),
+            ],
+            &mut HashMap::new(),
+        );
+        rules.mark(&mut views, Role::Maker);
+        assert!(!views[0].stuck);
+        assert!(views[1].stuck);
+        // Finished swaps aren't stuck, however old
+        assert!(!views[2].stuck);
+        assert!(StuckRules::default()
+            .stuck_for(&views[1], Role::Maker)
+            .is_none());
+    }
+
+    #[test]
+    fn stuck_alerts_repeat_when_the_time_doubles() {
+        let mut tracker =
+            SwapTracker::new(Network::Testnet, None).with_stuck_rules(Role::Maker, hour_rules());
+        let stuck = |transitions: &[Transition]| {
+            transitions
+                .iter()
+                .filter_map(|t| t.stuck_for_secs)
+                .collect::<Vec<_>>()
+        };
+
+        let (views, transitions) = tracker.update(vec![swap("a", "BtcLocked", 7200)]);
+        assert!(views[0].stuck);
+        let first = stuck(&transitions);
+        assert_eq!(first.len(), 1);
+        assert!(first[0] >= 7200);
+
+        // Still stuck, but not twice as long yet
+        let (views, transitions) = tracker.update(vec![swap("a", "BtcLocked", 10_000)]);
+        assert!(views[0].stuck);
+        assert!(stuck(&transitions).is_empty());
+
+        // Twice the time first reported
+        let (_, transitions) = tracker.update(vec![swap("a", "BtcLocked", 15_000)]);
+        assert_eq!(stuck(&transitions).len(), 1);
+
+        // A new state starts over
+        let (views, transitions) = tracker.update(vec![swap("a", "XmrLockTransactionSent", 0)]);
+        assert!(!views[0].stuck);
+        assert!(stuck(&transitions).is_empty());
+        let (_, transitions) = tracker.update(vec![swap("a", "XmrLockTransactionSent", 3600)]);
+        assert_eq!(stuck(&transitions).len(), 1);
+    }
+
+    #[test]
+    fn pins_match_id_prefixes() {
+        let mut pins = PinnedSwaps::new(["AAAA1111".to_string()]);
+        assert!(pins.contains("aaaa1111-2222-3333"));
+        assert!(!pins.contains("bbbb"));
+        // Toggling a covered swap drops the pin that covers it
+        assert!(!pins.toggle("aaaa1111-2222-3333"));
+        assert!(pins.is_empty());
+        assert!(pins.toggle("bbbb2222"));
+        assert!(pins.contains("BBBB2222"));
+    }
+
+    #[test]
+    fn pinned_swaps_are_stuck_after_half_the_threshold() {
+        let rules = hour_rules();
+        let pins = PinnedSwaps::new(["a".to_string()]);
+        let mut views = build_views(
+            vec![
+                swap("a", "BtcLocked", 2000),
+                swap("b", "BtcLocked", 2000),
+                swap("a2", "BtcLocked", 1000),
+            ],
+            &mut HashMap::new(),
+        );
+        pins.mark(&mut views);
+        rules.mark(&mut views, Role::Maker);
+        assert!(views[0].pinned && views[0].stuck);
+        assert!(!views[1].pinned && !views[1].stuck);
+        assert!(views[2].pinned && !views[2].stuck);
+    }
+
+    #[test]
+    fn pinned_swaps_alert_sooner() {
+        let mut tracker = SwapTracker::new(Network::Testnet, None)
+            .with_stuck_rules(Role::Maker, hour_rules())
+            .with_pins(PinnedSwaps::new(["a".to_string()]));
+        let (_, transitions) = tracker.update(vec![
+            swap("a", "BtcLocked", 2000),
+            swap("b", "BtcLocked", 2000),
+        ]);
+        assert_eq!(transitions.len(), 1);
+        assert_eq!(transitions[0].swap_id, "a");
+        assert!(transitions[0].pinned);
+        assert!(transitions[0].stuck_for_secs.is_some());
+    }
+}
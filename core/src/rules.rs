@@ -0,0 +1,119 @@
+//! Alert rules (`[[rules]]` in the config file): which transitions, stuck
+//! alerts and anomaly alerts deserve a notification, which notifiers deliver them, and at what
+//! severity.
+//!
+//! Without rules, every notifier gets every event its own state filter lets
+//! through. Once rules exist, a swap event only goes out if a rule matches
+//! it. Rules are tried in order, and each notifier gets an event at most
+//! once, from the first matching rule that routes to it. Liquidity alerts and
+//! digests aren't about a single swap and bypass the rules.
+//!
+//! A rule's `cooldown` limits how often the same alert, such as the stuck
+//! reminder for one swap, is repeated at the same severity. Quiet hours
+//! mute everything below critical regardless of the rules.
+
+use chrono::NaiveTime;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+use crate::model::Transition;
+
+/// How urgent an alert is, lowest first.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Info,
+    #[default]
+    Warning,
+    Critical,
+}
+
+/// One `[[rules]]` entry: what it matches, where it routes and at what
+/// severity.
+#[derive(Debug, Clone, Default)]
+pub struct Rule {
+    pub name: String,
+    /// Old states to match; empty means any, including new swaps
+    pub from: Vec<String>,
+    /// New states to match; empty means any
+    pub to: Vec<String>,
+    /// Match stuck alerts at least this old instead of transitions
+    pub stuck_for: Option<Duration>,
+    /// Match anomaly alerts instead of transitions
+    pub anomaly: bool,
+    /// Only match swaps with a counterparty on the watchlist
+    pub watched: bool,
+    /// Only match pinned swaps
+    pub pinned: bool,
+    pub min_btc_sats: Option<u64>,
+    pub min_xmr_piconero: Option<u64>,
+    /// Notifier kinds to deliver to, e.g. `telegram`, or `kind:name` for
+    /// one named notifier such as `discord:ops`; empty means all
+    pub notify: Vec<String>,
+    pub severity: Severity,
+    /// How soon a repeat of the same alert may go out at the same severity
+    pub cooldown: Option<Duration>,
+}
+
+impl Rule {
+    pub fn matches(&self, transition: &Transition) -> bool {
+        match (self.stuck_for, transition.stuck_for_secs) {
+            (None, None) => {}
+            (Some(min), Some(secs)) if secs >= min.as_secs() => {}
+            _ => return false,
+        }
+        if self.anomaly != transition.anomaly.is_some()
+            || self.watched && !transition.watched
+            || self.pinned && !transition.pinned
+        {
+            return false;
+        }
+        listed(&self.to, Some(transition.new_state.as_str()))
+            && listed(
+                &self.from,
+                transition.old_state.as_ref().map(|s| s.as_str()),
+            )
+            && at_least(self.min_btc_sats, transition.btc_sats)
+            && at_least(self.min_xmr_piconero, transition.xmr_piconero)
+    }
+
+    pub fn routes_to(&self, kind: &str, name: Option<&str>) -> bool {
+        self.notify.is_empty() || self.notify.iter().any(|n| targets(n, kind, name))
+    }
+}
+
+/// Whether a `notify` entry picks the notifier of `kind` called `name`.
+pub fn targets(entry: &str, kind: &str, name: Option<&str>) -> bool {
+    match entry.split_once(':') {
+        Some((k, n)) => k == kind && name == Some(n),
+        None => entry == kind,
+    }
+}
+
+/// A daily window, in local time, during which only critical alerts go out
+/// and the rest wait for the next digest. Wraps past midnight when `start`
+/// is later than `end`, as in `23:00-07:00`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QuietHours {
+    pub start: NaiveTime,
+    pub end: NaiveTime,
+}
+
+impl QuietHours {
+    pub fn contains(&self, time: NaiveTime) -> bool {
+        if self.start <= self.end {
+            self.start <= time && time < self.end
+        } else {
+            time >= self.start || time < self.end
+        }
+    }
+}
+
+fn listed(states: &[String], state: Option<&str>) -> bool {
+    states.is_empty() || state.is_some_and(|state| states.iter().any(|s| s == state))
+}
+
+/// A swap whose amount isn't known yet never meets a minimum.
+fn at_least(min: Option<u64>, amount: Option<u64>) -> bool {
+    min.is_none_or(|min| amount.is_some_and(|amount| amount >= min))
+}
@@ -0,0 +1,183 @@
+//! Which layout of the database a query can rely on.
+//!
+//! xmr-btc-swap has managed its sqlite database with sqlx migrations since
+//! it stopped using sled, recording each applied one in `_sqlx_migrations`;
+//! databases made by hand or by other tools may only carry
+//! `PRAGMA user_version`. The monitor only ever reads `swap_states`, so
+//! rather than trusting a version number it looks at the table itself:
+//! `swap_id` and `state` are required, the row id falls back to sqlite's
+//! `rowid` and a missing `entered_at` reads as empty. Anything else is
+//! reported in plain words instead of as the sqlx error it would cause.
+//!
+//! Each swap's counterparty comes from `peers`, and what ASB knows about
+//! how to reach it from `peer_addresses`. Older databases have neither, and
+//! their swaps just have no peer.
+
+use anyhow::{bail, Context, Result};
+use serde::Serialize;
+use sqlx::{Row, SqlitePool};
+use std::fmt;
+
+/// sqlite's result code for a file that isn't a database
+const SQLITE_NOTADB: &str = "26";
+
+/// What `swap_states` looks like in one database, and which migration made
+/// it so.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Schema {
+    /// Latest migration sqlx applied, a timestamp like `20210903050345`;
+    /// `None` for databases sqlx never migrated
+    pub migration: Option<i64>,
+    /// Its description, e.g. `create swaps table`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// `PRAGMA user_version`, which sqlx leaves at 0
+    pub user_version: i64,
+    /// `id`, or `rowid` for tables without one
+    #[serde(skip)]
+    id_column: &'static str,
+    #[serde(skip)]
+    has_entered_at: bool,
+    /// `peers` with its `swap_id` and `peer_id` columns
+    #[serde(skip)]
+    has_peers: bool,
+    #[serde(skip)]
+    has_peer_addresses: bool,
+}
+
+impl Schema {
+    /// Inspect the database behind `pool`.
+    pub async fn detect(pool: &SqlitePool) -> Result<Self> {
+        let tables: Vec<String> =
+            match sqlx::query_scalar("SELECT name FROM sqlite_master WHERE type = 'table'")
+                .fetch_all(pool)
+                .await
+            {
+                Ok(tables) => tables,
+                Err(err) if is_not_a_database(&err) => bail!(
+                    "not an sqlite database; xmr-btc-swap releases that kept a sled \
+                     database can't be read"
+                ),
+                Err(err) => return Err(err).context("list tables"),
+            };
+        if tables.is_empty() {
+            bail!("the database has no tables yet; ASB creates them on its first start");
+        }
+        if !tables.iter().any(|t| t == "swap_states") {
+            bail!(
+                "no swap_states table, so this isn't an xmr-btc-swap database (tables: {})",
+                tables.join(", ")
+            );
+        }
+
+        let columns: Vec<String> =
+            sqlx::query_scalar("SELECT name FROM pragma_table_info('swap_states')")
+                .fetch_all(pool)
+                .await
+                .context("list swap_states columns")?;
+        for required in ["swap_id", "state"] {
+            if !columns.iter().any(|c| c == required) {
+                bail!(
+                    "swap_states has no {required} column (columns: {}); \
+                     this xmr-btc-swap version isn't supported",
+                    columns.join(", ")
+                );
+            }
+        }
+
+        let (migration, description) = if tables.iter().any(|t| t == "_sqlx_migrations") {
+            sqlx::query(
+                "SELECT version, description FROM _sqlx_migrations \
+                 WHERE success ORDER BY version DESC LIMIT 1",
+            )
+            .fetch_optional(pool)
+            .await
+            .context("read _sqlx_migrations")?
+            .map(|row| (Some(row.get("version")), Some(row.get("description"))))
+            .unwrap_or_default()
+        } else {
+            (None, None)
+        };
+        let has_peers = tables.iter().any(|t| t == "peers")
+            && has_columns(pool, "peers", &["swap_id", "peer_id"]).await?;
+        let has_peer_addresses = tables.iter().any(|t| t == "peer_addresses")
+            && has_columns(pool, "peer_addresses", &["peer_id", "address"]).await?;
+        let user_version = sqlx::query_scalar("PRAGMA user_version")
+            .fetch_one(pool)
+            .await
+            .context("read user_version")?;
+
+        Ok(Self {
+            migration,
+            description,
+            user_version,
+            id_column: if columns.iter().any(|c| c == "id") {
+                "id"
+            } else {
+                "rowid"
+            },
+            has_entered_at: columns.iter().any(|c| c == "entered_at"),
+            has_peers,
+            has_peer_addresses,
+        })
+    }
+
+    /// The `swap_states` columns every query reads, and the swap's peer,
+    /// under the names the rest of the crate expects:
+    /// `id, swap_id, state, entered_at, peer_id`.
+    pub(crate) fn columns(&self) -> String {
+        let entered_at = if self.has_entered_at {
+            "entered_at"
+        } else {
+            "''"
+        };
+        let peer_id = if self.has_peers {
+            "(SELECT peer_id FROM peers WHERE peers.swap_id = swap_states.swap_id LIMIT 1)"
+        } else {
+            "NULL"
+        };
+        format!(
+            "{} AS id, swap_id, state, {entered_at} AS entered_at, {peer_id} AS peer_id",
+            self.id_column
+        )
+    }
+
+    /// Whether `peer_addresses` can be read.
+    pub(crate) fn has_peer_addresses(&self) -> bool {
+        self.has_peer_addresses
+    }
+
+    /// The column rows are ordered by.
+    pub(crate) fn id_column(&self) -> &'static str {
+        self.id_column
+    }
+}
+
+impl fmt::Display for Schema {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.migration, &self.description) {
+            (Some(version), Some(description)) => write!(f, "migration {version} ({description})")?,
+            (Some(version), None) => write!(f, "migration {version}")?,
+            (None, _) => write!(f, "no sqlx migrations, user_version {}", self.user_version)?,
+        }
+        if !self.has_entered_at {
+            write!(f, ", no entered_at")?;
+        }
+        Ok(())
+    }
+}
+
+async fn has_columns(pool: &SqlitePool, table: &str, wanted: &[&str]) -> Result<bool> {
+    let columns: Vec<String> = sqlx::query_scalar("SELECT name FROM pragma_table_info(?)")
+        .bind(table)
+        .fetch_all(pool)
+        .await
+        .with_context(|| format!("list {table} columns"))?;
+    Ok(wanted.iter().all(|w| columns.iter().any(|c| c == w)))
+}
+
+fn is_not_a_database(err: &sqlx::Error) -> bool {
+    err.as_database_error()
+        .and_then(|err| err.code())
+        .is_some_and(|code| code == SQLITE_NOTADB)
+}
@@ -0,0 +1,535 @@
+//! The states of xmr-btc-swap's state machines, as stored in the database.
+//!
+//! ASB (Alice) and the swap CLI (Bob) each have their own state machine, but
+//! they share many names, so one enum covers both and the methods that
+//! depend on the side take a [`Role`]. Names this version doesn't know,
+//! e.g. from a newer xmr-btc-swap, end up in [`SwapState::Unknown`] and
+//! count as in progress.
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::convert::Infallible;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::model::{Outcome, Role};
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum SwapState {
+    Started,
+    /// Bob: the swap was negotiated with the maker
+    SwapSetupCompleted,
+    /// Bob: the BTC lock transaction is signed, waiting to be published
+    BtcLockReadyToPublish,
+    /// Alice: the BTC lock transaction is in the mempool
+    BtcLockTransactionSeen,
+    BtcLocked,
+    /// Alice: the XMR lock transfer was sent
+    XmrLockTransactionSent,
+    /// Bob: Alice's proof of the XMR lock arrived
+    XmrLockProofReceived,
+    XmrLocked,
+    /// Alice: Bob was told where the XMR lock is
+    XmrLockTransferProofSent,
+    /// Bob: the encrypted signature was sent to Alice
+    EncSigSent,
+    /// Alice: Bob's encrypted signature arrived
+    EncSigLearned,
+    BtcRedeemTransactionPublished,
+    BtcRedeemed,
+    XmrRedeemed,
+    /// Alice: waiting out the cancel timelock without Bob's signature
+    WaitingForCancelTimelockExpiration,
+    CancelTimelockExpired,
+    BtcCancelled,
+    BtcRefundPublished,
+    BtcRefunded,
+    XmrRefunded,
+    /// Alice: the punish timelock expired without a refund
+    BtcPunishable,
+    BtcPunished,
+    /// Alice: Bob's BTC may be refunded before the XMR is locked
+    BtcEarlyRefundable,
+    BtcEarlyRefundPublished,
+    BtcEarlyRefunded,
+    /// Ended before any funds were locked
+    SafelyAborted,
+    Unknown(String),
+}
+
+/// The maker's states when a swap goes to plan, in order.
+const MAKER_PATH: &[SwapState] = &[
+    SwapState::Started,
+    SwapState::BtcLockTransactionSeen,
+    SwapState::BtcLocked,
+    SwapState::XmrLockTransactionSent,
+    SwapState::XmrLocked,
+    SwapState::XmrLockTransferProofSent,
+    SwapState::EncSigLearned,
+    SwapState::BtcRedeemTransactionPublished,
+    SwapState::BtcRedeemed,
+];
+
+/// The taker's states when a swap goes to plan, in order.
+const TAKER_PATH: &[SwapState] = &[
+    SwapState::Started,
+    SwapState::SwapSetupCompleted,
+    SwapState::BtcLockReadyToPublish,
+    SwapState::BtcLocked,
+    SwapState::XmrLockProofReceived,
+    SwapState::XmrLocked,
+    SwapState::EncSigSent,
+    SwapState::BtcRedeemed,
+    SwapState::XmrRedeemed,
+];
+
+impl SwapState {
+    /// `role`'s states from `Started` to success, when nothing goes wrong.
+    pub fn happy_path(role: Role) -> &'static [SwapState] {
+        match role {
+            Role::Maker => MAKER_PATH,
+            Role::Taker => TAKER_PATH,
+        }
+    }
+
+    /// How far along the happy path this state is, as `(step, steps)`:
+    /// `Started` is step 0 and success is the last. `None` off the happy
+    /// path, i.e. on the cancel and refund path or in an unknown state.
+    pub fn progress(&self, role: Role) -> Option<(usize, usize)> {
+        let path = Self::happy_path(role);
+        let step = path.iter().position(|state| state == self)?;
+        Some((step, path.len() - 1))
+    }
+
+    pub fn as_str(&self) -> &str {
+        match self {
+            SwapState::Started => "Started",
+            SwapState::SwapSetupCompleted => "SwapSetupCompleted",
+            SwapState::BtcLockReadyToPublish => "BtcLockReadyToPublish",
+            SwapState::BtcLockTransactionSeen => "BtcLockTransactionSeen",
+            SwapState::BtcLocked => "BtcLocked",
+            SwapState::XmrLockTransactionSent => "XmrLockTransactionSent",
+            SwapState::XmrLockProofReceived => "XmrLockProofReceived",
+            SwapState::XmrLocked => "XmrLocked",
+            SwapState::XmrLockTransferProofSent => "XmrLockTransferProofSent",
+            SwapState::EncSigSent => "EncSigSent",
+            SwapState::EncSigLearned => "EncSigLearned",
+            SwapState::BtcRedeemTransactionPublished => "BtcRedeemTransactionPublished",
+            SwapState::BtcRedeemed => "BtcRedeemed",
+            SwapState::XmrRedeemed => "XmrRedeemed",
+            SwapState::WaitingForCancelTimelockExpiration => "WaitingForCancelTimelockExpiration",
+            SwapState::CancelTimelockExpired => "CancelTimelockExpired",
+            SwapState::BtcCancelled => "BtcCancelled",
+            SwapState::BtcRefundPublished => "BtcRefundPublished",
+            SwapState::BtcRefunded => "BtcRefunded",
+            SwapState::XmrRefunded => "XmrRefunded",
+            SwapState::BtcPunishable => "BtcPunishable",
+            SwapState::BtcPunished => "BtcPunished",
+            SwapState::BtcEarlyRefundable => "BtcEarlyRefundable",
+            SwapState::BtcEarlyRefundPublished => "BtcEarlyRefundPublished",
+            SwapState::BtcEarlyRefunded => "BtcEarlyRefunded",
+            SwapState::SafelyAborted => "SafelyAborted",
+            SwapState::Unknown(name) => name,
+        }
+    }
+
+    /// How a swap in this state ended up, from `role`'s side.
+    pub fn outcome(&self, role: Role) -> Outcome {
+        match (role, self) {
+            (Role::Maker, SwapState::BtcRedeemed) | (Role::Taker, SwapState::XmrRedeemed) => {
+                Outcome::Completed
+            }
+            // A maker's BtcRefunded is followed by XmrRefunded; for the
+            // taker it is the end of the road
+            (Role::Maker, SwapState::XmrRefunded)
+            | (Role::Taker, SwapState::BtcRefunded)
+            | (
+                _,
+                SwapState::BtcEarlyRefunded | SwapState::BtcPunished | SwapState::SafelyAborted,
+            ) => Outcome::Failed,
+            _ => Outcome::InProgress,
+        }
+    }
+
+    /// Whether the swap is over for `role`: nothing follows this state.
+    pub fn is_terminal(&self, role: Role) -> bool {
+        self.outcome(role) != Outcome::InProgress
+    }
+
+    /// Off the happy path: cancelled, refunding or facing a punish.
+    pub fn is_cancel_path(&self) -> bool {
+        matches!(
+            self,
+            SwapState::WaitingForCancelTimelockExpiration
+                | SwapState::CancelTimelockExpired
+                | SwapState::BtcCancelled
+                | SwapState::BtcRefundPublished
+                | SwapState::BtcPunishable
+                | SwapState::BtcEarlyRefundable
+                | SwapState::BtcEarlyRefundPublished
+        ) || self.is_refund()
+            || self.is_punish()
+    }
+
+    /// The BTC or XMR went back to where it came from.
+    pub fn is_refund(&self) -> bool {
+        matches!(
+            self,
+            SwapState::BtcRefunded | SwapState::XmrRefunded | SwapState::BtcEarlyRefunded
+        )
+    }
+
+    /// The taker lost the BTC for not refunding in time.
+    pub fn is_punish(&self) -> bool {
+        *self == SwapState::BtcPunished
+    }
+
+    /// The BTC transactions spending the lock that must have been published
+    /// for a swap to get here, labelled like `SwapDetails::txids`.
+    pub fn published_spends(&self) -> &'static [&'static str] {
+        match self {
+            SwapState::BtcRedeemTransactionPublished
+            | SwapState::BtcRedeemed
+            | SwapState::XmrRedeemed => &["btc_redeem"],
+            SwapState::BtcCancelled | SwapState::BtcPunishable => &["btc_cancel"],
+            SwapState::BtcRefundPublished | SwapState::BtcRefunded | SwapState::XmrRefunded => {
+                &["btc_cancel", "btc_refund"]
+            }
+            SwapState::BtcPunished => &["btc_cancel", "btc_punish"],
+            _ => &[],
+        }
+    }
+
+    /// The states `role`'s state machine can move to from here, happy path
+    /// first. Empty for terminal and unknown states.
+    pub fn next_states(&self, role: Role) -> Vec<SwapState> {
+        use SwapState::*;
+        match (role, self) {
+            (Role::Maker, Started) => vec![BtcLockTransactionSeen, BtcLocked, SafelyAborted],
+            (Role::Maker, BtcLockTransactionSeen) => vec![BtcLocked, SafelyAborted],
+            (Role::Maker, BtcLocked) => {
+                vec![XmrLockTransactionSent, BtcEarlyRefundable, SafelyAborted]
+            }
+            (Role::Maker, BtcEarlyRefundable) => {
+                vec![XmrLockTransactionSent, BtcEarlyRefunded]
+            }
+            (Role::Maker, XmrLockTransactionSent) => vec![XmrLocked, CancelTimelockExpired],
+            (Role::Maker, XmrLocked) => vec![XmrLockTransferProofSent, CancelTimelockExpired],
+            (Role::Maker, XmrLockTransferProofSent) => {
+                vec![EncSigLearned, WaitingForCancelTimelockExpiration]
+            }
+            (Role::Maker, EncSigLearned) => vec![
+                BtcRedeemTransactionPublished,
+                WaitingForCancelTimelockExpiration,
+                CancelTimelockExpired,
+            ],
+            (Role::Maker, BtcRedeemTransactionPublished) => vec![BtcRedeemed],
+            (Role::Maker, WaitingForCancelTimelockExpiration) => vec![CancelTimelockExpired],
+            // A refund or punish can land before the cancel is recorded
+            (Role::Maker, CancelTimelockExpired) => vec![BtcCancelled, BtcRefunded, BtcPunishable],
+            (Role::Maker, BtcCancelled) => vec![BtcRefunded, BtcPunishable],
+            (Role::Maker, BtcPunishable) => vec![BtcPunished, BtcRefunded],
+            (Role::Maker, BtcRefunded) => vec![XmrRefunded],
+
+            (Role::Taker, Started) => vec![SwapSetupCompleted, SafelyAborted],
+            (Role::Taker, SwapSetupCompleted) => {
+                vec![BtcLockReadyToPublish, BtcLocked, SafelyAborted]
+            }
+            (Role::Taker, BtcLockReadyToPublish) => vec![BtcLocked, SafelyAborted],
+            (Role::Taker, BtcLocked) => vec![
+                XmrLockProofReceived,
+                CancelTimelockExpired,
+                BtcEarlyRefundPublished,
+            ],
+            (Role::Taker, XmrLockProofReceived) => vec![XmrLocked, CancelTimelockExpired],
+            (Role::Taker, XmrLocked) => vec![EncSigSent, CancelTimelockExpired],
+            (Role::Taker, EncSigSent) => vec![BtcRedeemed, CancelTimelockExpired],
+            (Role::Taker, BtcRedeemed) => vec![XmrRedeemed],
+            (Role::Taker, CancelTimelockExpired) => vec![BtcCancelled],
+            (Role::Taker, BtcCancelled) => vec![BtcRefundPublished, BtcRefunded, BtcPunished],
+            (Role::Taker, BtcRefundPublished) => vec![BtcRefunded],
+            (Role::Taker, BtcEarlyRefundPublished) => vec![BtcEarlyRefunded],
+            _ => Vec::new(),
+        }
+    }
+}
+
+/// Every state name this version knows, as ASB writes them.
+pub const STATE_NAMES: [&str; 26] = [
+    "Started",
+    "SwapSetupCompleted",
+    "BtcLockReadyToPublish",
+    "BtcLockTransactionSeen",
+    "BtcLocked",
+    "XmrLockTransactionSent",
+    "XmrLockProofReceived",
+    "XmrLocked",
+    "XmrLockTransferProofSent",
+    "EncSigSent",
+    "EncSigLearned",
+    "BtcRedeemTransactionPublished",
+    "BtcRedeemed",
+    "XmrRedeemed",
+    "WaitingForCancelTimelockExpiration",
+    "CancelTimelockExpired",
+    "BtcCancelled",
+    "BtcRefundPublished",
+    "BtcRefunded",
+    "XmrRefunded",
+    "BtcPunishable",
+    "BtcPunished",
+    "BtcEarlyRefundable",
+    "BtcEarlyRefundPublished",
+    "BtcEarlyRefunded",
+    "SafelyAborted",
+];
+
+impl From<&str> for SwapState {
+    fn from(name: &str) -> Self {
+        match name {
+            "Started" => SwapState::Started,
+            "SwapSetupCompleted" => SwapState::SwapSetupCompleted,
+            "BtcLockReadyToPublish" => SwapState::BtcLockReadyToPublish,
+            "BtcLockTransactionSeen" => SwapState::BtcLockTransactionSeen,
+            "BtcLocked" => SwapState::BtcLocked,
+            "XmrLockTransactionSent" => SwapState::XmrLockTransactionSent,
+            "XmrLockProofReceived" => SwapState::XmrLockProofReceived,
+            "XmrLocked" => SwapState::XmrLocked,
+            "XmrLockTransferProofSent" => SwapState::XmrLockTransferProofSent,
+            "EncSigSent" => SwapState::EncSigSent,
+            "EncSigLearned" => SwapState::EncSigLearned,
+            "BtcRedeemTransactionPublished" => SwapState::BtcRedeemTransactionPublished,
+            "BtcRedeemed" => SwapState::BtcRedeemed,
+            "XmrRedeemed" => SwapState::XmrRedeemed,
+            "WaitingForCancelTimelockExpiration" => SwapState::WaitingForCancelTimelockExpiration,
+            "CancelTimelockExpired" => SwapState::CancelTimelockExpired,
+            "BtcCancelled" => SwapState::BtcCancelled,
+            "BtcRefundPublished" => SwapState::BtcRefundPublished,
+            "BtcRefunded" => SwapState::BtcRefunded,
+            "XmrRefunded" => SwapState::XmrRefunded,
+            "BtcPunishable" => SwapState::BtcPunishable,
+            "BtcPunished" => SwapState::BtcPunished,
+            "BtcEarlyRefundable" => SwapState::BtcEarlyRefundable,
+            "BtcEarlyRefundPublished" => SwapState::BtcEarlyRefundPublished,
+            "BtcEarlyRefunded" => SwapState::BtcEarlyRefunded,
+            "SafelyAborted" => SwapState::SafelyAborted,
+            other => SwapState::Unknown(other.to_string()),
+        }
+    }
+}
+
+impl FromStr for SwapState {
+    type Err = Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(SwapState::from(s))
+    }
+}
+
+impl fmt::Display for SwapState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Serialized as the plain variant name, as ASB writes it.
+impl Serialize for SwapState {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for SwapState {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Ok(SwapState::from(String::deserialize(deserializer)?.as_str()))
+    }
+}
+
+/// Which swaps a table shows (`--state`): state names and the groups
+/// `active`, `completed` and `failed`, any of which may match. An empty
+/// selection, or `all`, shows every swap.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StateSelection(Vec<Selector>);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Selector {
+    Outcome(Outcome),
+    State(SwapState),
+}
+
+impl StateSelection {
+    /// Every swap that ended up as `outcome`.
+    pub fn only(outcome: Outcome) -> Self {
+        Self(vec![Selector::Outcome(outcome)])
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn matches(&self, state: &SwapState, role: Role) -> bool {
+        self.0.is_empty()
+            || self.0.iter().any(|selector| match selector {
+                Selector::Outcome(outcome) => state.outcome(role) == *outcome,
+                Selector::State(selected) => selected == state,
+            })
+    }
+}
+
+impl FromStr for StateSelection {
+    type Err = anyhow::Error;
+
+    /// `active`, `failed,BtcPunishable`, ...
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        let mut selectors = Vec::new();
+        for name in s.split(',').map(str::trim).filter(|name| !name.is_empty()) {
+            selectors.push(match name.to_ascii_lowercase().as_str() {
+                "all" => return Ok(Self::default()),
+                "active" => Selector::Outcome(Outcome::InProgress),
+                "completed" => Selector::Outcome(Outcome::Completed),
+                "failed" => Selector::Outcome(Outcome::Failed),
+                _ => match SwapState::from(name) {
+                    SwapState::Unknown(_) => anyhow::bail!(
+                        "unknown state {name:?} (expected a state like BtcLocked, or active, \
+                         completed, failed or all)"
+                    ),
+                    state => Selector::State(state),
+                },
+            });
+        }
+        Ok(Self(selectors))
+    }
+}
+
+impl fmt::Display for StateSelection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.0.is_empty() {
+            return f.write_str("all");
+        }
+        let names: Vec<&str> = self
+            .0
+            .iter()
+            .map(|selector| match selector {
+                Selector::Outcome(Outcome::InProgress) => "active",
+                Selector::Outcome(Outcome::Completed) => "completed",
+                Selector::Outcome(Outcome::Failed) => "failed",
+                Selector::State(state) => state.as_str(),
+            })
+            .collect();
+        f.write_str(&names.join(","))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_round_trip() {
+        for name in STATE_NAMES {
+            let state: SwapState = name.parse().unwrap();
+            assert!(!matches!(state, SwapState::Unknown(_)), "{name}");
+            assert_eq!(state.as_str(), name);
+            assert_eq!(state.to_string(), name);
+        }
+    }
+
+    #[test]
+    fn unknown_names_are_kept() {
+        let state = SwapState::from("BtcSomethingNew");
+        assert_eq!(state, SwapState::Unknown("BtcSomethingNew".into()));
+        assert_eq!(state.as_str(), "BtcSomethingNew");
+        assert_eq!(state.outcome(Role::Maker), Outcome::InProgress);
+        assert!(state.next_states(Role::Maker).is_empty());
+        // Names are case-sensitive, as ASB writes them
+        assert!(matches!(
+            SwapState::from("btclocked"),
+            SwapState::Unknown(_)
+        ));
+    }
+
+    #[test]
+    fn serializes_as_the_plain_name() {
+        let json = serde_json::to_string(&SwapState::BtcLocked).unwrap();
+        assert_eq!(json, "\"BtcLocked\"");
+        let state: SwapState = serde_json::from_str("\"XmrRedeemed\"").unwrap();
+        assert_eq!(state, SwapState::XmrRedeemed);
+        let state: SwapState = serde_json::from_str("\"Mystery\"").unwrap();
+        assert_eq!(state, SwapState::Unknown("Mystery".into()));
+    }
+
+    #[test]
+    fn outcomes_depend_on_the_side() {
+        assert_eq!(
+            SwapState::BtcRedeemed.outcome(Role::Maker),
+            Outcome::Completed
+        );
+        assert_eq!(
+            SwapState::BtcRedeemed.outcome(Role::Taker),
+            Outcome::InProgress
+        );
+        assert_eq!(
+            SwapState::XmrRedeemed.outcome(Role::Taker),
+            Outcome::Completed
+        );
+        assert_eq!(
+            SwapState::BtcRefunded.outcome(Role::Maker),
+            Outcome::InProgress
+        );
+        assert_eq!(SwapState::BtcRefunded.outcome(Role::Taker), Outcome::Failed);
+        assert_eq!(SwapState::XmrRefunded.outcome(Role::Maker), Outcome::Failed);
+        assert_eq!(SwapState::BtcPunished.outcome(Role::Taker), Outcome::Failed);
+        assert!(SwapState::BtcPunished.is_punish());
+        assert!(SwapState::BtcPunished.is_cancel_path());
+        assert!(!SwapState::XmrLocked.is_cancel_path());
+    }
+
+    #[test]
+    fn progress_follows_the_happy_path() {
+        for role in [Role::Maker, Role::Taker] {
+            let path = SwapState::happy_path(role);
+            let last = path.len() - 1;
+            assert_eq!(path[0].progress(role), Some((0, last)));
+            assert_eq!(path[last].progress(role), Some((last, last)));
+            assert_eq!(path[last].outcome(role), Outcome::Completed);
+        }
+        assert_eq!(SwapState::BtcCancelled.progress(Role::Maker), None);
+        assert_eq!(SwapState::EncSigSent.progress(Role::Maker), None);
+    }
+
+    #[test]
+    fn the_happy_path_only_takes_allowed_steps() {
+        for role in [Role::Maker, Role::Taker] {
+            for step in SwapState::happy_path(role).windows(2) {
+                let next = step[0].next_states(role);
+                // The happy path comes first
+                assert_eq!(next.first(), Some(&step[1]), "{role:?} {}", step[0]);
+            }
+        }
+    }
+
+    #[test]
+    fn ends_lead_nowhere() {
+        for role in [Role::Maker, Role::Taker] {
+            for name in STATE_NAMES {
+                let state = SwapState::from(name);
+                let next = state.next_states(role);
+                if state.is_terminal(role) {
+                    assert!(next.is_empty(), "{role:?} {name} -> {next:?}");
+                }
+                for state in next {
+                    assert!(!matches!(state, SwapState::Unknown(_)));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn spends_follow_the_cancel_path() {
+        assert_eq!(SwapState::BtcRedeemed.published_spends(), &["btc_redeem"]);
+        assert_eq!(
+            SwapState::BtcPunished.published_spends(),
+            &["btc_cancel", "btc_punish"]
+        );
+        assert!(SwapState::XmrLocked.published_spends().is_empty());
+    }
+}
@@ -0,0 +1,453 @@
+//! `agent`: watch mode for an ASB whose dashboard lives elsewhere. The agent
+//! runs next to the ASB and polls its database like `--daemon` does, logging
+//! and notifying the same way, and reports to a central `serve` started with
+//! `--agent-token`, which shows every agent's ASBs in one API.
+//!
+//! Each report is a POST to `/agent/push` carrying every instance's swaps
+//! and stats and the transitions since the last report the central instance
+//! accepted. One goes out after every poll that saw a transition or a change
+//! in health, and at least every [`KEEPALIVE`] otherwise, so the central
+//! instance can tell a quiet agent from a dead one. Transitions that
+//! couldn't be delivered are sent again with the next report, keeping at
+//! most [`MAX_PENDING`].
+//!
+//! Reports are signed with HMAC-SHA256 under the shared token instead of
+//! carrying it, over a millisecond timestamp that has to be recent and
+//! increase from one report to the next, so a captured report can't be
+//! replayed. The report itself isn't encrypted; across untrusted networks
+//! the central URL should be `https://`.
+
+use anyhow::{bail, Context, Result};
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::Sha256;
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+use tokio::time::sleep;
+use tracing::{error, info, warn};
+
+use crate::daemon::{
+    log_asb_down, log_fee_spike, log_liquidity, log_log_alert, log_quote, log_transition,
+    log_unreachable,
+};
+use crate::db::{count_state_rows, PollError};
+use crate::digest;
+use crate::http::{self, Request};
+use crate::model::{Network, Role, SwapRow, SwapStats, Transition};
+use crate::watch::{Monitor, WatchContext};
+
+/// Carries `t=<unix millis>,v1=<hex HMAC of "<t>.<body>">`
+pub const SIGNATURE_HEADER: &str = "X-Wraithswap-Signature";
+
+/// Longest gap between two reports
+pub const KEEPALIVE: Duration = Duration::from_secs(30);
+
+/// How far a report's timestamp may be from the central instance's clock
+pub const MAX_CLOCK_SKEW: Duration = Duration::from_secs(300);
+
+/// Undelivered transitions kept for the next report; older ones are dropped
+pub const MAX_PENDING: usize = 1000;
+
+pub struct AgentOptions {
+    /// Base URL of the central `serve`
+    pub central: String,
+    /// Unique among the agents reporting to `central`
+    pub name: String,
+    pub token: String,
+}
+
+/// One report, as `POST /agent/push` receives it.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Push {
+    pub agent: String,
+    pub instances: Vec<InstanceReport>,
+    pub transitions: Vec<PushedTransition>,
+}
+
+/// What the agent currently sees of one ASB.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct InstanceReport {
+    pub name: String,
+    pub network: Network,
+    pub role: Role,
+    /// As `GET /swaps` shows them
+    pub swaps: Vec<Value>,
+    /// As `GET /stats` shows them; null until the first poll worked
+    pub stats: Value,
+    /// Set while the latest poll failed
+    pub error: Option<String>,
+}
+
+/// A transition with the fields `tail` leaves out but alert rules need.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PushedTransition {
+    #[serde(flatten)]
+    pub transition: Transition,
+    pub network: Network,
+    pub btc_sats: Option<u64>,
+    pub xmr_piconero: Option<u64>,
+}
+
+impl PushedTransition {
+    fn new(mut transition: Transition, instance: &str) -> Self {
+        transition.instance = Some(instance.to_string());
+        Self {
+            network: transition.network,
+            btc_sats: transition.btc_sats,
+            xmr_piconero: transition.xmr_piconero,
+            transition,
+        }
+    }
+
+    pub fn into_transition(self) -> Transition {
+        Transition {
+            network: self.network,
+            btc_sats: self.btc_sats,
+            xmr_piconero: self.xmr_piconero,
+            ..self.transition
+        }
+    }
+}
+
+/// The signature header for `body`, sent at `timestamp` (unix millis).
+pub fn sign(token: &str, timestamp: i64, body: &[u8]) -> String {
+    format!(
+        "t={timestamp},v1={}",
+        hex::encode(mac(token, timestamp, body).finalize().into_bytes())
+    )
+}
+
+/// Check a signature header against `body`, returning its timestamp.
+/// Ordering against earlier reports is up to the caller.
+pub fn verify(token: &str, header: &str, body: &[u8], now: i64) -> Result<i64> {
+    let mut timestamp = None;
+    let mut signature = None;
+    for part in header.split(',') {
+        match part.trim().split_once('=') {
+            Some(("t", value)) => timestamp = value.parse::<i64>().ok(),
+            Some(("v1", value)) => signature = hex::decode(value).ok(),
+            _ => {}
+        }
+    }
+    let (Some(timestamp), Some(signature)) = (timestamp, signature) else {
+        bail!("malformed {SIGNATURE_HEADER} header");
+    };
+    if timestamp.abs_diff(now) > MAX_CLOCK_SKEW.as_millis() as u64 {
+        bail!("signature timestamp too far from the server's clock");
+    }
+    mac(token, timestamp, body)
+        .verify_slice(&signature)
+        .ok()
+        .context("wrong signature")?;
+    Ok(timestamp)
+}
+
+fn mac(token: &str, timestamp: i64, body: &[u8]) -> Hmac<Sha256> {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(token.trim().as_bytes()).expect("HMAC takes any key length");
+    mac.update(format!("{timestamp}.").as_bytes());
+    mac.update(body);
+    mac
+}
+
+/// This machine's host name, the default agent name.
+pub fn hostname() -> Option<String> {
+    let mut buf = [0u8; 256];
+    if unsafe { libc::gethostname(buf.as_mut_ptr().cast(), buf.len()) } != 0 {
+        return None;
+    }
+    let len = buf.iter().position(|&b| b == 0).unwrap_or(buf.len());
+    let name = String::from_utf8_lossy(&buf[..len]).into_owned();
+    (!name.is_empty()).then_some(name)
+}
+
+pub async fn run_agent(mut ctx: WatchContext, options: AgentOptions) -> Result<()> {
+    let mut monitors = Monitor::for_instances(
+        &ctx.instances,
+        &ctx.stuck,
+        &ctx.watchlist,
+        &ctx.pins,
+        ctx.history.as_ref(),
+    );
+    let mut watcher = ctx.watcher();
+    ctx.chain.start();
+    let url = format!("{}/agent/push", options.central.trim_end_matches('/'));
+
+    info!(agent = %options.name, central = %http::redact(&options.central), "reporting");
+    for monitor in &monitors {
+        let instance = &monitor.instance;
+        info!(
+            instance = %instance.name,
+            db_path = %instance.db_path.as_deref().unwrap_or("?".as_ref()).display(),
+            network = ?instance.network,
+            role = ?instance.role,
+            remote = instance.remote.as_ref().map(|r| format!("{}:{}", r.host, r.db_path.display())),
+            "monitoring"
+        );
+    }
+
+    let mut reports: Vec<InstanceReport> = monitors
+        .iter()
+        .map(|monitor| InstanceReport {
+            name: monitor.instance.name.clone(),
+            network: monitor.instance.network,
+            role: monitor.instance.role,
+            swaps: Vec::new(),
+            stats: Value::Null,
+            error: None,
+        })
+        .collect();
+    let mut latest: Vec<Vec<SwapRow>> = vec![Vec::new(); monitors.len()];
+    let mut pending: VecDeque<PushedTransition> = VecDeque::new();
+    let mut dirty = true;
+    let mut last_push: Option<Instant> = None;
+    let mut push_error: Option<String> = None;
+    // Signature timestamps must increase even if the clock steps back
+    let mut signed_at = 0;
+    let mut chain_error: Option<String> = None;
+    loop {
+        let mut activity = false;
+        let mut any_ok = false;
+        for ((monitor, report), rows_seen) in monitors.iter_mut().zip(&mut reports).zip(&mut latest)
+        {
+            let name = monitor.instance.name.clone();
+            let role = monitor.instance.role;
+            match monitor.poll().await {
+                Ok(rows) => {
+                    if report.error.take().is_some() {
+                        info!(instance = %name, swaps = rows.len(), "database readable again");
+                        dirty = true;
+                    }
+                    *rows_seen = rows.clone();
+                    let (mut views, transitions) = monitor.update(rows).await;
+                    for transition in &transitions {
+                        log_transition(&name, transition);
+                    }
+                    activity |= !transitions.is_empty();
+                    ctx.notifiers.dispatch(&transitions);
+                    ctx.record_metrics(&monitor.instance, &views, &transitions);
+                    ctx.chain.annotate(&name, &mut views, role);
+                    report.swaps = views
+                        .iter()
+                        .map(serde_json::to_value)
+                        .collect::<Result<_, _>>()?;
+                    pending.extend(
+                        transitions
+                            .into_iter()
+                            .map(|transition| PushedTransition::new(transition, &name)),
+                    );
+                    any_ok = true;
+                }
+                Err(PollError::NoDataDir) => bail!("{}", PollError::NoDataDir),
+                Err(err) => {
+                    let message = err.to_string();
+                    if report.error.as_ref() != Some(&message) {
+                        warn!(instance = %name, error = %message, "poll failed");
+                        dirty = true;
+                    }
+                    report.error = Some(message);
+                }
+            }
+        }
+        if any_ok {
+            ctx.schedule.record(activity);
+        }
+        if pending.len() > MAX_PENDING {
+            let dropped = pending.len() - MAX_PENDING;
+            pending.drain(..dropped);
+            warn!(
+                dropped,
+                "central instance unreachable; dropping the oldest transitions"
+            );
+        }
+
+        dirty |= !pending.is_empty();
+        if dirty || last_push.is_none_or(|at| at.elapsed() >= KEEPALIVE) {
+            for ((monitor, report), rows) in monitors.iter().zip(&mut reports).zip(&latest) {
+                if report.error.is_none() {
+                    report.stats = stats(monitor, rows).await?;
+                }
+            }
+            match push(&url, &options, &reports, &pending, &mut signed_at).await {
+                Ok(()) => {
+                    if push_error.take().is_some() {
+                        info!("central instance reachable again");
+                    }
+                    pending.clear();
+                    dirty = false;
+                    last_push = Some(Instant::now());
+                }
+                Err(err) => {
+                    let message = format!("{err:#}");
+                    if push_error.as_ref() != Some(&message) {
+                        warn!(error = %message, pending = pending.len(), "push failed");
+                    }
+                    push_error = Some(message);
+                }
+            }
+        }
+
+        let alerts = ctx.chain.take_alerts();
+        for alert in &alerts {
+            log_liquidity(alert);
+        }
+        ctx.notifiers.dispatch_liquidity(&alerts);
+        let spikes = ctx.chain.take_fee_alerts();
+        for spike in &spikes {
+            log_fee_spike(spike);
+        }
+        ctx.notifiers.dispatch_fees(&spikes);
+        let downs = ctx.chain.take_asb_alerts();
+        for down in &downs {
+            log_asb_down(down);
+        }
+        ctx.notifiers.dispatch_asb(&downs);
+        let unreachable = ctx.chain.take_reach_alerts();
+        for alert in &unreachable {
+            log_unreachable(alert);
+        }
+        ctx.notifiers.dispatch_reach(&unreachable);
+        let quotes = ctx.chain.take_quote_alerts();
+        for alert in &quotes {
+            log_quote(alert);
+        }
+        ctx.notifiers.dispatch_quotes(&quotes);
+        let log_alerts = ctx.take_log_alerts();
+        for alert in &log_alerts {
+            log_log_alert(alert);
+        }
+        ctx.notifiers.dispatch_log(&log_alerts);
+        digest::send_if_due(&mut ctx);
+        ctx.flush_metrics();
+        let failures: Vec<String> = reports
+            .iter()
+            .filter_map(|report| Some(format!("{}: {}", report.name, report.error.as_ref()?)))
+            .chain(push_error.iter().map(|err| format!("push: {err}")))
+            .collect();
+        ctx.beat(&failures);
+        if let Some(err) = ctx.notifiers.take_error() {
+            error!(error = %err, "notification failed");
+        }
+        if let Some(err) = ctx.history_error() {
+            error!(error = %err, "history write failed");
+        }
+        if let Some(err) = ctx.metrics_error() {
+            error!(error = %err, "metrics write failed");
+        }
+        if let Some(err) = ctx.heartbeat_error() {
+            error!(error = %err, "heartbeat ping failed");
+        }
+        let error = ctx.chain.error();
+        if error != chain_error {
+            match error {
+                Some(ref err) => warn!(error = %err, "chain lookup failed"),
+                None => info!("chain lookups working again"),
+            }
+            chain_error = error;
+        }
+
+        // Polls have to come often enough for the keepalive
+        let wait = ctx.schedule.next_wait(&watcher).min(KEEPALIVE);
+        tokio::select! {
+            _ = sleep(wait) => {}
+            _ = watcher.changed() => {}
+            _ = ctx.chain.updated() => {}
+        }
+    }
+}
+
+/// `stats --output json` for one instance's latest rows.
+async fn stats(monitor: &Monitor, rows: &[SwapRow]) -> Result<Value> {
+    let total_state_rows = match monitor.source.pool {
+        Some(ref pool) => count_state_rows(pool).await.unwrap_or(0),
+        None => 0,
+    };
+    let stats = SwapStats {
+        schema: monitor.source.schema().cloned(),
+        ..SwapStats::from_latest(rows, total_state_rows, monitor.instance.role)
+    };
+    Ok(serde_json::to_value(stats)?)
+}
+
+async fn push(
+    url: &str,
+    options: &AgentOptions,
+    reports: &[InstanceReport],
+    pending: &VecDeque<PushedTransition>,
+    signed_at: &mut i64,
+) -> Result<()> {
+    #[derive(Serialize)]
+    struct Outgoing<'a> {
+        agent: &'a str,
+        instances: &'a [InstanceReport],
+        transitions: &'a VecDeque<PushedTransition>,
+    }
+    let body = serde_json::to_vec(&Outgoing {
+        agent: &options.name,
+        instances: reports,
+        transitions: pending,
+    })?;
+    *signed_at = Utc::now().timestamp_millis().max(*signed_at + 1);
+    let signature = sign(&options.token, *signed_at, &body);
+    Request::post(url)
+        .header(SIGNATURE_HEADER, signature)
+        .body("application/json", body)
+        .send()
+        .await?
+        .error_for_status()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW: i64 = 1_760_000_000_000;
+    const BODY: &[u8] = br#"{"agent":"a"}"#;
+
+    #[test]
+    fn signatures_are_hmac_sha256_of_timestamp_and_body() {
+        // As `openssl dgst -sha256 -hmac secret` computes it
+        assert_eq!(
+            sign("secret", NOW, BODY),
+            "t=1760000000000,v1=8c0052501162f43db49c3546f6e1f09e06500b41b671419e2de529813c4e1ead"
+        );
+        // A token read from a file keeps its newline
+        assert_eq!(sign("secret\n", NOW, BODY), sign("secret", NOW, BODY));
+    }
+
+    #[test]
+    fn valid_signatures_verify() {
+        let header = sign("secret", NOW, BODY);
+        assert_eq!(verify("secret", &header, BODY, NOW).unwrap(), NOW);
+        let skewed = NOW + MAX_CLOCK_SKEW.as_millis() as i64;
+        assert_eq!(verify("secret", &header, BODY, skewed).unwrap(), NOW);
+        // Order and spacing don't matter
+        let (t, v1) = header.split_once(',').unwrap();
+        assert!(verify("secret", &format!("{v1}, {t}"), BODY, NOW).is_ok());
+    }
+
+    #[test]
+    fn tampering_is_caught() {
+        let header = sign("secret", NOW, BODY);
+        let wrong = |token: &str, header: &str, body: &[u8], now: i64| {
+            verify(token, header, body, now).unwrap_err().to_string()
+        };
+        assert_eq!(wrong("other", &header, BODY, NOW), "wrong signature");
+        assert_eq!(
+            wrong("secret", &header, br#"{"agent":"b"}"#, NOW),
+            "wrong signature"
+        );
+        // The timestamp is signed too
+        let moved = header.replace("t=1760000000000", "t=1760000000001");
+        assert_eq!(wrong("secret", &moved, BODY, NOW), "wrong signature");
+
+        let stale = NOW + MAX_CLOCK_SKEW.as_millis() as i64 + 1;
+        assert!(wrong("secret", &header, BODY, stale).contains("too far"));
+        for malformed in ["", "t=1760000000000", "v1=00", "t=soon,v1=00", "t=1,v1=xyz"] {
+            assert!(wrong("secret", malformed, BODY, NOW).contains("malformed"));
+        }
+    }
+}
@@ -0,0 +1,470 @@
+//! ASB's own `config.toml` (`--asb-config`), so the monitor can take the
+//! data directory, network, Electrum server, monero-wallet-rpc, rendezvous
+//! points, listen and external addresses and spread from it instead of
+//! guessing or repeating them.
+//!
+//! Only the keys the monitor has a use for are read; the rest of ASB's
+//! settings are ignored. Both the single `electrum_rpc_url` of older
+//! releases and the `electrum_rpc_urls` list of newer ones are understood.
+//! What the file sets is a starting point: the monitor's own config file,
+//! environment variables and flags all override it.
+//!
+//! `check-config` reads the same file and looks for the mistakes that keep
+//! ASB from starting or from trading: servers it can't reach, networks that
+//! don't match, and spreads no one would accept.
+
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+use crate::asblog;
+use crate::cli::{parse_spread, Args};
+use crate::config::{amount, expand_home, one_or_many};
+use crate::electrum::{ElectrumServer, Session, DEFAULT_TIMEOUT};
+use crate::http;
+use crate::liveness::listen_address;
+use crate::model::{Network, Role};
+use crate::monero::MoneroRpc;
+use crate::toml;
+
+/// Bitcoin mainnet's genesis block; every test network has another
+const MAINNET_GENESIS: &str = "000000000019d6689c085ae165831e934ff763ae46a2a6c172b3f1b60a8ce26f";
+
+/// An ask spread above this makes ASB the most expensive maker around
+const HIGH_SPREAD: f64 = 0.1;
+
+/// What ASB's config says about itself.
+#[derive(Debug, Clone, Default)]
+pub struct AsbConfig {
+    /// Where ASB keeps this network's data; the database is `sqlite` in it
+    pub data_dir: Option<PathBuf>,
+    /// `bitcoin.network`; older configs leave it to ASB's `--testnet`
+    pub network: Option<Network>,
+    /// `monero.network` as written, e.g. `Stagenet`
+    pub monero_network: Option<String>,
+    /// Electrum servers in ASB's order; the monitor uses the first
+    pub electrum: Vec<String>,
+    /// monero-wallet-rpc, without ASB's `/json_rpc` suffix
+    pub monero_rpc: Option<String>,
+    /// monerod, for releases that run their own wallet instead
+    pub monero_daemon: Option<String>,
+    /// Multiaddresses ASB registers at
+    pub rendezvous_points: Vec<String>,
+    /// Multiaddresses ASB accepts peers on
+    pub listen: Vec<String>,
+    /// Multiaddresses ASB tells takers to dial
+    pub external_addresses: Vec<String>,
+    /// `ask_spread`, as written
+    pub ask_spread: Option<String>,
+    /// Swap size limits in BTC, as written
+    pub min_buy_btc: Option<String>,
+    pub max_buy_btc: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Level {
+    Ok,
+    Warning,
+    Error,
+}
+
+/// One `check-config` result.
+#[derive(Debug, Clone, Serialize)]
+pub struct Finding {
+    pub check: &'static str,
+    pub level: Level,
+    pub message: String,
+}
+
+impl Finding {
+    fn new(check: &'static str, level: Level, message: impl Into<String>) -> Self {
+        Self {
+            check,
+            level,
+            message: message.into(),
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct File {
+    data: DataSection,
+    network: NetworkSection,
+    bitcoin: BitcoinSection,
+    monero: MoneroSection,
+    maker: MakerSection,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct DataSection {
+    dir: Option<PathBuf>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct NetworkSection {
+    /// One address in older releases, a list in newer ones
+    #[serde(deserialize_with = "one_or_many")]
+    rendezvous_point: Vec<String>,
+    #[serde(deserialize_with = "one_or_many")]
+    listen: Vec<String>,
+    external_addresses: Vec<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct BitcoinSection {
+    electrum_rpc_url: Option<String>,
+    electrum_rpc_urls: Vec<String>,
+    /// `Mainnet`/`Testnet`, or `bitcoin`/`testnet` in newer releases
+    network: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct MoneroSection {
+    wallet_rpc_url: Option<String>,
+    daemon_url: Option<String>,
+    network: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct MakerSection {
+    #[serde(deserialize_with = "amount")]
+    ask_spread: Option<String>,
+    #[serde(deserialize_with = "amount")]
+    min_buy_btc: Option<String>,
+    #[serde(deserialize_with = "amount")]
+    max_buy_btc: Option<String>,
+}
+
+impl AsbConfig {
+    /// Read `path`, ASB's `config.toml` or the directory holding it.
+    pub fn load(path: &Path) -> Result<Self> {
+        let path = match path.is_dir() {
+            true => path.join("config.toml"),
+            false => path.to_path_buf(),
+        };
+        let text = std::fs::read_to_string(&path)
+            .with_context(|| format!("read ASB config {}", path.display()))?;
+        let value =
+            toml::parse(&text).with_context(|| format!("parse ASB config {}", path.display()))?;
+        let file: File = serde_json::from_value(value)
+            .with_context(|| format!("invalid ASB config {}", path.display()))?;
+
+        let network = match file.bitcoin.network {
+            Some(network) => Some(parse_network(&network).with_context(|| {
+                format!("invalid bitcoin.network in ASB config {}", path.display())
+            })?),
+            None => None,
+        };
+        let mut electrum = file.bitcoin.electrum_rpc_urls;
+        electrum.extend(file.bitcoin.electrum_rpc_url);
+
+        Ok(Self {
+            data_dir: file.data.dir.map(expand_home),
+            network,
+            monero_network: file.monero.network,
+            electrum,
+            monero_rpc: file.monero.wallet_rpc_url.map(|url| {
+                let url = url.trim_end_matches('/');
+                url.strip_suffix("/json_rpc").unwrap_or(url).to_string()
+            }),
+            monero_daemon: file.monero.daemon_url,
+            rendezvous_points: file.network.rendezvous_point,
+            listen: file.network.listen,
+            external_addresses: file.network.external_addresses,
+            ask_spread: file.maker.ask_spread,
+            min_buy_btc: file.maker.min_buy_btc,
+            max_buy_btc: file.maker.max_buy_btc,
+        })
+    }
+
+    /// The network ASB's `--testnet` flag picked, going by the data
+    /// directory's name.
+    pub fn data_dir_network(&self) -> Option<Network> {
+        let name = self.data_dir.as_ref()?.file_name()?.to_str()?;
+        name.parse().ok()
+    }
+
+    /// ASB's database, in its data directory.
+    pub fn db_path(&self) -> Option<PathBuf> {
+        self.data_dir.as_ref().map(|dir| dir.join("sqlite"))
+    }
+
+    /// Use what the file sets as the starting point for `args`.
+    pub fn apply(self, args: &mut Args) -> Result<()> {
+        args.role = Role::Maker;
+        if let Some(path) = self.db_path() {
+            args.db_path = Some(path);
+        }
+        // Older configs leave the network to ASB's --testnet flag, which
+        // also names the data directory
+        if let Some(network) = self.network.or(self.data_dir_network()) {
+            args.network = network;
+        }
+        if let Some(server) = self.electrum.first() {
+            args.electrum = Some(
+                server
+                    .parse()
+                    .context("invalid Electrum server in ASB config")?,
+            );
+        }
+        if let Some(url) = self.monero_rpc {
+            args.monero_rpc = Some(url);
+        }
+        if let Some(spread) = self.ask_spread {
+            args.earnings.spread =
+                parse_spread(&spread).context("invalid maker.ask_spread in ASB config")?;
+        }
+        if let Some(address) = self.listen.iter().find_map(|addr| listen_address(addr)) {
+            args.asb.address = Some(address);
+        }
+        // Relayed addresses and the like can't be checked
+        args.external_addresses = self
+            .external_addresses
+            .iter()
+            .filter_map(|addr| addr.parse().ok())
+            .collect();
+        args.rendezvous_points = self.rendezvous_points;
+        // Older releases only log to the terminal
+        if let Some(log) = self.data_dir.as_deref().map(asblog::default_path) {
+            if log.exists() {
+                args.asb_log = Some(log);
+            }
+        }
+        Ok(())
+    }
+
+    /// Everything `check-config` looks at, in order. Servers are contacted,
+    /// so this takes as long as the slowest of them.
+    pub async fn check(&self) -> Vec<Finding> {
+        let mut findings = self.check_networks();
+        findings.extend(self.check_data_dir());
+        for url in &self.electrum {
+            findings.push(self.check_electrum(url).await);
+        }
+        if self.electrum.is_empty() {
+            findings.push(Finding::new(
+                "electrum",
+                Level::Error,
+                "no bitcoin.electrum_rpc_url",
+            ));
+        }
+        findings.push(self.check_monero().await);
+        findings.extend(self.check_spread());
+        findings.extend(self.check_limits());
+        findings
+    }
+
+    fn check_networks(&self) -> Vec<Finding> {
+        let mut findings = Vec::new();
+        let network = self.network.or(self.data_dir_network());
+        if let (Some(network), Some(dir)) = (self.network, self.data_dir_network()) {
+            if network != dir {
+                findings.push(Finding::new(
+                    "network",
+                    Level::Error,
+                    format!(
+                        "bitcoin.network is {} but the data directory is ASB's {} one",
+                        network.dir_name(),
+                        dir.dir_name()
+                    ),
+                ));
+            }
+        }
+        if let (Some(network), Some(monero)) = (network, &self.monero_network) {
+            let expected = match network {
+                Network::Mainnet => &["mainnet"][..],
+                Network::Testnet => &["stagenet", "testnet"][..],
+            };
+            if !expected.contains(&monero.to_lowercase().as_str()) {
+                findings.push(Finding::new(
+                    "network",
+                    Level::Error,
+                    format!(
+                        "monero.network is {monero} but Bitcoin is on {}",
+                        network.dir_name()
+                    ),
+                ));
+            }
+        }
+        if findings.is_empty() {
+            let message = match network {
+                Some(network) => network.dir_name().to_string(),
+                None => "not set; ASB picks it with --testnet".to_string(),
+            };
+            findings.push(Finding::new("network", Level::Ok, message));
+        }
+        findings
+    }
+
+    fn check_data_dir(&self) -> Option<Finding> {
+        let dir = self.data_dir.as_ref()?;
+        let display = dir.display();
+        Some(if dir.join("sqlite").exists() {
+            Finding::new("data dir", Level::Ok, display.to_string())
+        } else if dir.exists() {
+            Finding::new("data dir", Level::Ok, format!("{display}, no database yet"))
+        } else {
+            Finding::new(
+                "data dir",
+                Level::Warning,
+                format!("{display} doesn't exist; ASB creates it on first start"),
+            )
+        })
+    }
+
+    async fn check_electrum(&self, url: &str) -> Finding {
+        let server: ElectrumServer = match url.parse() {
+            Ok(server) => server,
+            Err(err) => return Finding::new("electrum", Level::Error, format!("{err:#}")),
+        };
+        let probe = async {
+            let mut session = Session::connect(&server, DEFAULT_TIMEOUT).await?;
+            let tip = session.tip_height().await?;
+            let genesis = session.genesis_hash().await?;
+            Ok::<_, anyhow::Error>((tip, genesis))
+        };
+        let (tip, genesis) = match probe.await {
+            Ok(probe) => probe,
+            Err(err) => {
+                return Finding::new(
+                    "electrum",
+                    Level::Error,
+                    format!("{server} unreachable: {err:#}"),
+                )
+            }
+        };
+        let served = match genesis == MAINNET_GENESIS {
+            true => Network::Mainnet,
+            false => Network::Testnet,
+        };
+        match self.network.or(self.data_dir_network()) {
+            Some(network) if network != served => Finding::new(
+                "electrum",
+                Level::Error,
+                format!(
+                    "{server} serves {}, not {}",
+                    served.dir_name(),
+                    network.dir_name()
+                ),
+            ),
+            _ => Finding::new("electrum", Level::Ok, format!("{server}, at block {tip}")),
+        }
+    }
+
+    async fn check_monero(&self) -> Finding {
+        let Some(ref url) = self.monero_rpc else {
+            return match self.monero_daemon {
+                Some(ref daemon) => Finding::new(
+                    "monero",
+                    Level::Ok,
+                    format!("ASB runs its own wallet against {daemon}"),
+                ),
+                None => Finding::new("monero", Level::Error, "no monero.wallet_rpc_url"),
+            };
+        };
+        let rpc = MoneroRpc {
+            url: url.clone(),
+            login: None,
+            timeout: http::DEFAULT_TIMEOUT,
+        };
+        match rpc.version().await {
+            Ok(version) => Finding::new(
+                "monero",
+                Level::Ok,
+                format!("monero-wallet-rpc {version} at {url}"),
+            ),
+            Err(err) => Finding::new(
+                "monero",
+                Level::Error,
+                format!("monero-wallet-rpc at {url} unreachable: {err:#}"),
+            ),
+        }
+    }
+
+    fn check_spread(&self) -> Option<Finding> {
+        let raw = self.ask_spread.as_ref()?;
+        let Ok(spread) = raw.trim().parse::<f64>() else {
+            return Some(Finding::new(
+                "spread",
+                Level::Error,
+                format!("ask_spread {raw:?} isn't a number"),
+            ));
+        };
+        let percent = (spread * 10_000.0).round() / 100.0;
+        Some(if spread < 0.0 {
+            Finding::new(
+                "spread",
+                Level::Error,
+                format!("ask_spread {raw} sells XMR below the market"),
+            )
+        } else if spread >= 1.0 {
+            Finding::new(
+                "spread",
+                Level::Error,
+                format!("ask_spread {raw} is {percent}%; a fraction is expected, 0.02 for 2%"),
+            )
+        } else if spread == 0.0 {
+            Finding::new(
+                "spread",
+                Level::Warning,
+                "ask_spread 0 sells at the market price and earns nothing",
+            )
+        } else if spread > HIGH_SPREAD {
+            Finding::new(
+                "spread",
+                Level::Warning,
+                format!("ask_spread {raw} is {percent}%; takers will likely pick cheaper makers"),
+            )
+        } else {
+            Finding::new("spread", Level::Ok, format!("{percent}%"))
+        })
+    }
+
+    fn check_limits(&self) -> Option<Finding> {
+        let parse = |raw: &Option<String>| raw.as_ref().and_then(|v| v.trim().parse::<f64>().ok());
+        let (min, max) = (parse(&self.min_buy_btc), parse(&self.max_buy_btc));
+        Some(match (min, max) {
+            (_, Some(max)) if max <= 0.0 => Finding::new(
+                "limits",
+                Level::Error,
+                "max_buy_btc is 0; ASB won't accept any swap",
+            ),
+            (Some(min), Some(max)) if min > max => Finding::new(
+                "limits",
+                Level::Error,
+                format!("min_buy_btc {min} is above max_buy_btc {max}"),
+            ),
+            (Some(min), Some(max)) => {
+                Finding::new("limits", Level::Ok, format!("{min} to {max} BTC per swap"))
+            }
+            _ => return None,
+        })
+    }
+}
+
+/// Bitcoin's network as ASB writes it. XMR stagenet runs alongside BTC
+/// testnet, under the same data directory.
+fn parse_network(value: &str) -> Result<Network> {
+    match value.to_lowercase().as_str() {
+        "mainnet" | "bitcoin" => Ok(Network::Mainnet),
+        "testnet" | "testnet3" | "testnet4" => Ok(Network::Testnet),
+        other => bail!("unsupported network: {other} (expected mainnet or testnet)"),
+    }
+}
+
+/// The host of a multiaddress like `/dns4/host/tcp/8888/p2p/12D3...`, for
+/// display; the address itself when it has none.
+pub fn multiaddr_host(addr: &str) -> &str {
+    let mut parts = addr.split('/').skip(1);
+    match (parts.next(), parts.next()) {
+        (Some("dns" | "dns4" | "dns6" | "ip4" | "ip6"), Some(host)) => host,
+        _ => addr,
+    }
+}
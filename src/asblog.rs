@@ -0,0 +1,375 @@
+//! ASB's own log (`--asb-log`), tailed so a swap's detail view can show
+//! what ASB said about it between its state changes. The database says what
+//! changed; the log says why.
+//!
+//! ASB writes JSON lines to `logs/swap-all.log` in its data directory, or
+//! plain text when told to; both are read. A line belongs to a swap when it
+//! mentions the swap's id, which ASB's spans put on everything logged while
+//! handling it.
+//!
+//! Log alert rules (`--log-alert`, `[[log_alerts]]`) watch the new lines for
+//! a keyword or pattern, since plenty goes wrong without the database ever
+//! noticing: a wallet out of funds, Tor failing to start, a rendezvous
+//! point refusing registration. Each rule alerts at most once per window.
+
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use crate::pattern::Pattern;
+use crate::rules::Severity;
+
+/// Only this much of an existing log is read at first; a long-running
+/// ASB's can be gigabytes
+const TAIL_BYTES: u64 = 16 << 20;
+
+/// Lines kept in memory, oldest dropped first
+const MAX_LINES: usize = 20_000;
+
+/// How often a rule may alert when it doesn't say
+pub const DEFAULT_WINDOW: Duration = Duration::from_secs(3600);
+
+/// Levels in the order of how much they matter
+const LEVELS: [&str; 5] = ["TRACE", "DEBUG", "INFO", "WARN", "ERROR"];
+
+/// Where ASB logs, in its data directory.
+pub fn default_path(data_dir: &Path) -> PathBuf {
+    data_dir.join("logs").join("swap-all.log")
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogLine {
+    pub at: Option<DateTime<Utc>>,
+    /// `INFO`, `WARN`, ...; empty when the line doesn't say
+    pub level: String,
+    /// The message, followed by the event's other fields as `key=value`
+    pub message: String,
+    /// The line as written, searched for swap ids
+    raw: String,
+}
+
+/// A log alert rule.
+#[derive(Debug, Clone)]
+pub struct LogRule {
+    pub name: String,
+    pub matcher: LogMatch,
+    /// Only lines at least this severe, e.g. `WARN`
+    pub level: Option<String>,
+    pub severity: Severity,
+    /// At most one alert per window; `DEFAULT_WINDOW` or `--log-alert-window`
+    /// when unset
+    pub window: Option<Duration>,
+}
+
+#[derive(Debug, Clone)]
+pub enum LogMatch {
+    /// Anywhere in the line, ignoring case
+    Keyword(String),
+    Pattern(Pattern),
+}
+
+/// A new log line matched a rule.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct LogAlert {
+    pub rule: String,
+    pub level: String,
+    pub message: String,
+    /// Matches since the rule's previous alert that were held back
+    pub held_back: usize,
+    #[serde(skip)]
+    pub severity: Severity,
+}
+
+/// A rule and when it last alerted.
+struct Armed {
+    rule: LogRule,
+    window: Duration,
+    last: Option<Instant>,
+    held_back: usize,
+}
+
+/// The end of ASB's log, kept up to date by `poll`.
+pub struct AsbLog {
+    path: PathBuf,
+    /// How far the file has been read
+    offset: u64,
+    /// A line ASB is still writing
+    partial: String,
+    lines: VecDeque<LogLine>,
+    error: Option<String>,
+    /// What was there before the first read isn't news
+    started: bool,
+    rules: Vec<Armed>,
+    alerts: Vec<LogAlert>,
+}
+
+impl AsbLog {
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            offset: 0,
+            partial: String::new(),
+            lines: VecDeque::new(),
+            error: None,
+            started: false,
+            rules: Vec::new(),
+            alerts: Vec::new(),
+        }
+    }
+
+    /// Also watch new lines for `rules`, each alerting at most once per its
+    /// window or `window`.
+    pub fn with_alerts(mut self, rules: Vec<LogRule>, window: Duration) -> Self {
+        self.rules = rules
+            .into_iter()
+            .map(|rule| Armed {
+                window: rule.window.unwrap_or(window),
+                rule,
+                last: None,
+                held_back: 0,
+            })
+            .collect();
+        self
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Read whatever was appended since the last call. A file that got
+    /// shorter was replaced or truncated, and is read from the start again.
+    pub fn poll(&mut self) {
+        match self.read_new() {
+            Ok(added) => {
+                self.error = None;
+                if std::mem::replace(&mut self.started, true) {
+                    self.check(added);
+                }
+            }
+            Err(err) => self.error = Some(format!("{err:#}")),
+        }
+    }
+
+    /// Alerts raised since the last call.
+    pub fn take_alerts(&mut self) -> Vec<LogAlert> {
+        std::mem::take(&mut self.alerts)
+    }
+
+    /// Why the last read failed, if it did.
+    pub fn error(&self) -> Option<&str> {
+        self.error.as_deref()
+    }
+
+    /// Every kept line that mentions `swap_id`, oldest first.
+    pub fn for_swap<'a>(&'a self, swap_id: &'a str) -> impl Iterator<Item = &'a LogLine> {
+        self.lines
+            .iter()
+            .filter(move |line| line.raw.contains(swap_id))
+    }
+
+    /// Run the newest `added` lines past the rules.
+    fn check(&mut self, added: usize) {
+        let now = Instant::now();
+        let start = self.lines.len().saturating_sub(added);
+        for line in self.lines.range(start..) {
+            for armed in &mut self.rules {
+                if !armed.rule.matches(line) {
+                    continue;
+                }
+                if armed.last.is_some_and(|last| now - last < armed.window) {
+                    armed.held_back += 1;
+                    continue;
+                }
+                armed.last = Some(now);
+                self.alerts.push(LogAlert {
+                    rule: armed.rule.name.clone(),
+                    level: line.level.clone(),
+                    message: line.message.clone(),
+                    held_back: std::mem::take(&mut armed.held_back),
+                    severity: armed.rule.severity,
+                });
+            }
+        }
+    }
+
+    /// How many lines were added.
+    fn read_new(&mut self) -> Result<usize> {
+        let mut file = File::open(&self.path)
+            .with_context(|| format!("open ASB log {}", self.path.display()))?;
+        let len = file.metadata()?.len();
+        if len < self.offset {
+            self.offset = 0;
+            self.partial.clear();
+        }
+        // Starting in the middle of a big file lands inside a line
+        let mut skip_first = false;
+        if self.offset == 0 && len > TAIL_BYTES {
+            self.offset = len - TAIL_BYTES;
+            skip_first = true;
+        }
+        file.seek(SeekFrom::Start(self.offset))?;
+        let mut buf = Vec::new();
+        file.take(len - self.offset).read_to_end(&mut buf)?;
+        self.offset += buf.len() as u64;
+
+        let text = std::mem::take(&mut self.partial) + &String::from_utf8_lossy(&buf);
+        let mut pieces: Vec<&str> = text.split('\n').collect();
+        self.partial = pieces.pop().unwrap_or_default().to_string();
+        if skip_first && !pieces.is_empty() {
+            pieces.remove(0);
+        }
+        let mut added = 0;
+        for piece in pieces {
+            if !piece.trim().is_empty() {
+                self.lines.push_back(LogLine::parse(piece));
+                added += 1;
+            }
+        }
+        let excess = self.lines.len().saturating_sub(MAX_LINES);
+        self.lines.drain(..excess);
+        Ok(added)
+    }
+}
+
+impl LogRule {
+    /// Against the level and message, as the detail view shows them.
+    fn matches(&self, line: &LogLine) -> bool {
+        if let Some(ref min) = self.level {
+            if level_rank(&line.level) < level_rank(min) {
+                return false;
+            }
+        }
+        let text = format!("{} {}", line.level, line.message);
+        match self.matcher {
+            LogMatch::Keyword(ref keyword) => text.to_lowercase().contains(&keyword.to_lowercase()),
+            LogMatch::Pattern(ref pattern) => pattern.is_match(&text),
+        }
+    }
+}
+
+/// Where a level sits in `LEVELS`; lines without one rank lowest.
+fn level_rank(level: &str) -> Option<usize> {
+    LEVELS.iter().position(|l| l.eq_ignore_ascii_case(level))
+}
+
+/// `WARN`, `warn` or `warning`, as one of `LEVELS`.
+pub fn parse_level(level: &str) -> Result<String> {
+    let level = match level.to_ascii_uppercase().as_str() {
+        "WARNING" => "WARN".to_string(),
+        other => other.to_string(),
+    };
+    if level_rank(&level).is_none() {
+        bail!("unknown level {level:?} (expected {})", LEVELS.join(", "));
+    }
+    Ok(level)
+}
+
+impl LogLine {
+    fn parse(raw: &str) -> Self {
+        let raw = strip_ansi(raw.trim_end_matches('\r'));
+        match serde_json::from_str::<Value>(&raw) {
+            Ok(Value::Object(event)) => {
+                let mut message = String::new();
+                if let Some(Value::Object(fields)) = event.get("fields") {
+                    if let Some(text) = fields.get("message").and_then(Value::as_str) {
+                        message.push_str(text);
+                    }
+                    for (key, value) in fields.iter().filter(|(key, _)| *key != "message") {
+                        let value = match value {
+                            Value::String(text) => text.clone(),
+                            other => other.to_string(),
+                        };
+                        message.push_str(&format!(" {key}={value}"));
+                    }
+                }
+                Self {
+                    at: event
+                        .get("timestamp")
+                        .and_then(Value::as_str)
+                        .and_then(parse_time),
+                    level: event
+                        .get("level")
+                        .and_then(Value::as_str)
+                        .unwrap_or_default()
+                        .to_string(),
+                    message: message.trim().to_string(),
+                    raw,
+                }
+            }
+            // `2024-05-01T12:00:00.123456Z  INFO swap{id=...}: message`
+            _ => {
+                let mut rest = raw.as_str();
+                let at = rest.split_whitespace().next().and_then(parse_time);
+                if at.is_some() {
+                    rest = rest
+                        .trim_start()
+                        .split_once(char::is_whitespace)
+                        .map_or("", |(_, r)| r);
+                }
+                let mut level = String::new();
+                if let Some((word, after)) = rest.trim_start().split_once(char::is_whitespace) {
+                    if matches!(word, "TRACE" | "DEBUG" | "INFO" | "WARN" | "ERROR") {
+                        level = word.to_string();
+                        rest = after;
+                    }
+                }
+                Self {
+                    at,
+                    level,
+                    message: strip_spans(rest.trim()).to_string(),
+                    raw,
+                }
+            }
+        }
+    }
+}
+
+fn parse_time(text: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(text)
+        .ok()
+        .map(|at| at.with_timezone(&Utc))
+}
+
+/// `swap{swap_id=...}:`, and any spans nested in it, say what the swap's
+/// id already does.
+fn strip_spans(mut message: &str) -> &str {
+    loop {
+        let Some(open) = message.find('{') else {
+            return message;
+        };
+        let name = &message[..open];
+        if name.is_empty() || !name.chars().all(|c| c.is_alphanumeric() || c == '_') {
+            return message;
+        }
+        let Some(close) = message.find('}') else {
+            return message;
+        };
+        message = message[close + 1..].trim_start_matches(':').trim_start();
+    }
+}
+
+/// Colours are written to the file too when ASB logs to a terminal and a
+/// file at once.
+fn strip_ansi(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1B' {
+            // CSI sequences end with a letter
+            for c in chars.by_ref() {
+                if c.is_ascii_alphabetic() {
+                    break;
+                }
+            }
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
@@ -0,0 +1,126 @@
+//! Audible alerts in watch mode (`--bell`, `--bell-sound`): ring the
+//! terminal bell, or play a sound with a command of your choosing, when a
+//! swap reaches one of the given states. Meant for a monitor left running on
+//! a second screen, where a punish would otherwise go unseen until someone
+//! looks.
+//!
+//! A sound command runs through `sh -c` with `SWAP_ID` and `NEW_STATE` in
+//! its environment; one that outlives [`SOUND_TIMEOUT`] is killed. Stuck and
+//! anomaly alerts don't ring.
+
+use anyhow::{bail, Context, Result};
+use std::process::Stdio;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::process::Command;
+
+use crate::model::{Role, Transition};
+use crate::state::StateSelection;
+
+const SOUND_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// States to ring for, each with the command that plays its sound, or
+/// `None` for the terminal bell.
+#[derive(Debug, Clone, Default)]
+pub struct Bell {
+    rules: Vec<(StateSelection, Option<String>)>,
+    last_error: Arc<Mutex<Option<String>>>,
+}
+
+impl Bell {
+    /// The first sound whose states match a transition plays, in the
+    /// order given; the terminal bell rings when only bell states do.
+    pub fn new(mut rules: Vec<(StateSelection, Option<String>)>) -> Self {
+        rules.sort_by_key(|(_, command)| command.is_none());
+        Self {
+            rules,
+            last_error: Arc::default(),
+        }
+    }
+
+    /// Play the sounds for a poll's transitions, each command once however
+    /// many swaps called for it. Whether the terminal bell should ring.
+    pub fn ring(&self, transitions: &[Transition], role: Role) -> bool {
+        let mut bell = false;
+        let mut played: Vec<&str> = Vec::new();
+        for transition in transitions {
+            if transition.stuck_for_secs.is_some() || transition.anomaly.is_some() {
+                continue;
+            }
+            let sound = self
+                .rules
+                .iter()
+                .find(|(states, _)| states.matches(&transition.new_state, role));
+            match sound {
+                Some((_, None)) => bell = true,
+                Some((_, Some(command))) if !played.contains(&command.as_str()) => {
+                    played.push(command);
+                    self.play(command, transition);
+                }
+                _ => {}
+            }
+        }
+        bell
+    }
+
+    /// Start `command` in the background; failures end up in `last_error`.
+    fn play(&self, command: &str, transition: &Transition) {
+        let mut child = Command::new("sh");
+        child
+            .arg("-c")
+            .arg(command)
+            .env("SWAP_ID", &transition.swap_id)
+            .env("NEW_STATE", transition.new_state.as_str())
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .kill_on_drop(true);
+        let command = command.to_string();
+        let last_error = Arc::clone(&self.last_error);
+        tokio::spawn(async move {
+            if let Err(err) = play(child, &command).await {
+                *last_error.lock().unwrap() = Some(format!("{err:#}"));
+            }
+        });
+    }
+
+    /// The most recent sound command failure, cleared on read.
+    pub fn take_error(&self) -> Option<String> {
+        self.last_error.lock().unwrap().take()
+    }
+}
+
+async fn play(mut child: Command, command: &str) -> Result<()> {
+    let status = tokio::time::timeout(SOUND_TIMEOUT, child.status())
+        .await
+        .with_context(|| format!("{command:?} ran for over {}s", SOUND_TIMEOUT.as_secs()))?
+        .with_context(|| format!("run {command:?}"))?;
+    if !status.success() {
+        bail!("{command:?} exited with {status}");
+    }
+    Ok(())
+}
+
+/// `STATES=COMMAND`, as `--bell-sound` takes it.
+pub fn parse_sound(value: &str) -> Result<(StateSelection, Option<String>)> {
+    let Some((states, command)) = value.split_once('=') else {
+        bail!("expected STATES=COMMAND, got {value:?}");
+    };
+    Ok((parse_states(states)?, Some(parse_command(command)?)))
+}
+
+/// States to ring for, like `--state` takes them; `all` rings for every
+/// transition, but nothing named is a mistake.
+pub fn parse_states(value: &str) -> Result<StateSelection> {
+    if value.split(',').all(|name| name.trim().is_empty()) {
+        bail!("name the states to ring for, like BtcPunished, failed or all");
+    }
+    value.parse()
+}
+
+fn parse_command(command: &str) -> Result<String> {
+    match command.trim() {
+        "" => bail!("a bell sound needs a command"),
+        command => Ok(command.to_string()),
+    }
+}
@@ -0,0 +1,230 @@
+//! BIP32 extended keys: parsing `xpub`/`tpub`/`xprv`/`tprv` (and the
+//! SLIP-132 `zpub`/`vpub` spellings) and child derivation, on the `bitcoin`
+//! crate's `bip32` module and libsecp256k1.
+
+use anyhow::{bail, ensure, Context, Result};
+use bitcoin::bip32::{ChildNumber, Xpriv, Xpub};
+use bitcoin::secp256k1::{All, Secp256k1};
+use std::str::FromStr;
+use std::sync::OnceLock;
+
+/// Children at or above this index are hardened
+pub const HARDENED: u32 = 1 << 31;
+
+#[derive(Clone)]
+enum Key {
+    Private(Xpriv),
+    Public(Xpub),
+}
+
+#[derive(Clone)]
+pub struct ExtendedKey {
+    key: Key,
+    /// Parsed from a testnet version (`tpub`, `vpub`…)
+    pub testnet: bool,
+}
+
+/// Never prints private material.
+impl std::fmt::Debug for ExtendedKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let kind = match self.key {
+            Key::Private(_) => "private",
+            Key::Public(_) => "public",
+        };
+        write!(f, "ExtendedKey({kind}, testnet: {})", self.testnet)
+    }
+}
+
+/// Contexts are costly to set up, and derivation runs for every address
+fn secp() -> &'static Secp256k1<All> {
+    static SECP: OnceLock<Secp256k1<All>> = OnceLock::new();
+    SECP.get_or_init(Secp256k1::new)
+}
+
+impl FromStr for ExtendedKey {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut data = bitcoin::base58::decode_check(s).context("invalid extended key encoding")?;
+        ensure!(data.len() == 78, "extended key has the wrong length");
+
+        // SLIP-132 versions only say which script the key is meant for, so
+        // they become the BIP32 versions the `bitcoin` crate reads
+        let version = u32::from_be_bytes(data[0..4].try_into().unwrap());
+        let (standard, private, testnet): (u32, _, _) = match version {
+            // xpub, zpub
+            0x0488_B21E | 0x04B2_4746 => (0x0488_B21E, false, false),
+            // xprv, zprv
+            0x0488_ADE4 | 0x04B2_430C => (0x0488_ADE4, true, false),
+            // tpub, vpub
+            0x0435_87CF | 0x045F_1CF6 => (0x0435_87CF, false, true),
+            // tprv, vprv
+            0x0435_8394 | 0x045F_18BC => (0x0435_8394, true, true),
+            _ => bail!("unsupported extended key version {version:08x}"),
+        };
+        data[0..4].copy_from_slice(&standard.to_be_bytes());
+
+        let key = match private {
+            true => Key::Private(Xpriv::decode(&data).context("invalid private key")?),
+            false => Key::Public(Xpub::decode(&data).context("invalid public key")?),
+        };
+        Ok(ExtendedKey { key, testnet })
+    }
+}
+
+impl ExtendedKey {
+    /// SEC1 compressed public key.
+    pub fn public_key(&self) -> Result<[u8; 33]> {
+        Ok(self.xpub().public_key.serialize())
+    }
+
+    fn xpub(&self) -> Xpub {
+        match self.key {
+            Key::Private(ref xpriv) => Xpub::from_priv(secp(), xpriv),
+            Key::Public(xpub) => xpub,
+        }
+    }
+
+    /// The same key without its private half.
+    pub fn neuter(&self) -> Result<ExtendedKey> {
+        Ok(ExtendedKey {
+            key: Key::Public(self.xpub()),
+            testnet: self.testnet,
+        })
+    }
+
+    /// Child `index`; hardened indexes need a private key.
+    pub fn child(&self, index: u32) -> Result<ExtendedKey> {
+        self.derive(&[index])
+    }
+
+    pub fn derive(&self, path: &[u32]) -> Result<ExtendedKey> {
+        let path: Vec<ChildNumber> = path.iter().map(|&index| index.into()).collect();
+        let key = match self.key {
+            Key::Private(ref xpriv) => Key::Private(
+                xpriv
+                    .derive_priv(secp(), &path)
+                    .context("invalid child key")?,
+            ),
+            Key::Public(ref xpub) => {
+                if path.iter().any(ChildNumber::is_hardened) {
+                    bail!("hardened derivation needs a private key");
+                }
+                Key::Public(
+                    xpub.derive_pub(secp(), &path)
+                        .context("invalid child key")?,
+                )
+            }
+        };
+        Ok(ExtendedKey {
+            key,
+            testnet: self.testnet,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(s: &str) -> ExtendedKey {
+        s.parse().unwrap()
+    }
+
+    /// Same public key and chain code; the latter shows in the children.
+    fn assert_same(derived: &ExtendedKey, expected: &ExtendedKey) {
+        assert_eq!(
+            derived.public_key().unwrap(),
+            expected.public_key().unwrap()
+        );
+        assert_eq!(
+            derived.child(0).unwrap().public_key().unwrap(),
+            expected.child(0).unwrap().public_key().unwrap()
+        );
+    }
+
+    // BIP32 test vector 1
+    const V1_M_PRV: &str = "xprv9s21ZrQH143K3QTDL4LXw2F7HEK3wJUD2nW2nRk4stbPy6cq3jPPqjiChkVvvNKmPGJxWUtg6LnF5kejMRNNU3TGtRBeJgk33yuGBxrMPHi";
+    const V1_M_PUB: &str = "xpub661MyMwAqRbcFtXgS5sYJABqqG9YLmC4Q1Rdap9gSE8NqtwybGhePY2gZ29ESFjqJoCu1Rupje8YtGqsefD265TMg7usUDFdp6W1EGMcet8";
+    const V1_0H_PUB: &str = "xpub68Gmy5EdvgibQVfPdqkBBCHxA5htiqg55crXYuXoQRKfDBFA1WEjWgP6LHhwBZeNK1VTsfTFUHCdrfp1bgwQ9xv5ski8PX9rL2dZXvgGDnw";
+    const V1_0H_1_PUB: &str = "xpub6ASuArnXKPbfEwhqN6e3mwBcDTgzisQN1wXN9BJcM47sSikHjJf3UFHKkNAWbWMiGj7Wf5uMash7SyYq527Hqck2AxYysAA7xmALppuCkwQ";
+    const V1_LEAF_PUB: &str = "xpub6H1LXWLaKsWFhvm6RVpEL9P4KfRZSW7abD2ttkWP3SSQvnyA8FSVqNTEcYFgJS2UaFcxupHiYkro49S8yGasTvXEYBVPamhGW6cFJodrTHy";
+
+    #[test]
+    fn vector_1() {
+        let m = key(V1_M_PRV);
+        assert_eq!(
+            hex::encode(key(V1_M_PUB).public_key().unwrap()),
+            "0339a36013301597daef41fbe593a02cc513d0b55527ec2df1050e2e8ff49c85c2"
+        );
+        assert_same(&m, &key(V1_M_PUB));
+        assert_same(&m.derive(&[HARDENED]).unwrap(), &key(V1_0H_PUB));
+
+        let m_0h_1 = m.derive(&[HARDENED, 1]).unwrap();
+        assert_eq!(
+            hex::encode(m_0h_1.public_key().unwrap()),
+            "03501e454bf00751f24b1b489aa925215d66af2234e3891c3b21a52bedb3cd711c"
+        );
+        assert_same(&m_0h_1, &key(V1_0H_1_PUB));
+        // The same child from the public parent
+        assert_same(&key(V1_0H_PUB).child(1).unwrap(), &key(V1_0H_1_PUB));
+
+        let leaf = m.derive(&[HARDENED, 1, 2 + HARDENED, 2, 1_000_000_000]);
+        assert_same(&leaf.unwrap(), &key(V1_LEAF_PUB));
+    }
+
+    #[test]
+    fn vector_2() {
+        let m = key("xprv9s21ZrQH143K31xYSDQpPDxsXRTUcvj2iNHm5NUtrGiGG5e2DtALGdso3pGz6ssrdK4PFmM8NSpSBHNqPqm55Qn3LqFtT2emdEXVYsCzC2U");
+        assert_same(
+            &m,
+            &key("xpub661MyMwAqRbcFW31YEwpkMuc5THy2PSt5bDMsktWQcFF8syAmRUapSCGu8ED9W6oDMSgv6Zz8idoc4a6mr8BDzTJY47LJhkJ8UB7WEGuduB"),
+        );
+        let path = [0, 2_147_483_647 + HARDENED, 1, 2_147_483_646 + HARDENED, 2];
+        assert_same(
+            &m.derive(&path).unwrap(),
+            &key("xpub6FnCn6nSzZAw5Tw7cgR9bi15UV96gLZhjDstkXXxvCLsUXBGXPdSnLFbdpq8p9HmGsApME5hQTZ3emM2rnY5agb9rXpVGyy3bdW6EEgAtqt"),
+        );
+    }
+
+    #[test]
+    fn vector_3_keeps_leading_zeros() {
+        let m = key("xprv9s21ZrQH143K25QhxbucbDDuQ4naNntJRi4KUfWT7xo4EKsHt2QJDu7KXp1A3u7Bi1j8ph3EGsZ9Xvz9dGuVrtHHs7pXeTzjuxBrCmmhgC6");
+        assert_same(
+            &m.derive(&[HARDENED]).unwrap(),
+            &key("xpub68NZiKmJWnxxS6aaHmn81bvJeTESw724CRDs6HbuccFQN9Ku14VQrADWgqbhhTHBaohPX4CjNLf9fq9MYo6oDaPPLPxSb7gwQN3ih19Zm4Y"),
+        );
+    }
+
+    #[test]
+    fn slip132_versions() {
+        // Vector 1's m/0h under other versions
+        let zpub = key("zpub6mwJaQaUE3oZ763dJZKRbNUxW1znc5f4uqty7hKaAS5RKNscWpZrkohNNhd7BNxD8Hj5NceNPbujdF3935mRkSHHcS6yZLnpsUkrK1XoMLr");
+        let vpub = key("vpub5UcFMjtodKddhuH9y8Avm26wp9Qzqbh5FPp5z7k2eQZu6ychWBucGZ4pHsnmBkLXVjFrNiG8YxVY66atAJ7NZVYt95KHDhWsnaWGkhF4DrT");
+        let tpub = key("tpubD8eQVK4Kdxg3gHrF62jGP7dKVCoYiEB8dFSpuTawkL5YxTus5j5pf83vaKnii4bc6v2NVEy81P2gYrJczYne3QNNwMTS53p5uzDyHvnw2jm");
+        assert!(!zpub.testnet);
+        assert!(vpub.testnet && tpub.testnet);
+        for other in [&zpub, &vpub, &tpub] {
+            assert_same(other, &key(V1_0H_PUB));
+        }
+    }
+
+    #[test]
+    fn bad_keys_are_rejected() {
+        // Last character changed, so the checksum fails
+        let mut bad = V1_M_PUB.to_string();
+        bad.pop();
+        bad.push('9');
+        let err = bad.parse::<ExtendedKey>().unwrap_err();
+        assert!(err.to_string().contains("encoding"), "{err}");
+        assert!("xpub0OIl".parse::<ExtendedKey>().is_err());
+        assert!("".parse::<ExtendedKey>().is_err());
+    }
+
+    #[test]
+    fn public_keys_cannot_derive_hardened_children() {
+        assert!(key(V1_M_PUB).child(HARDENED).is_err());
+        assert!(key(V1_M_PRV).neuter().unwrap().child(HARDENED).is_err());
+        assert!(key(V1_M_PRV).child(HARDENED).is_ok());
+    }
+}
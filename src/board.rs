@@ -0,0 +1,249 @@
+//! The full-screen view's board layout (`b`, or `--board`): swaps as cards
+//! in a column per state, like a kanban board, so the flow through the
+//! protocol shows at a glance. In-flight swaps get a column for each step
+//! of the happy path; the cancel path and finished swaps have their own.
+
+use colored::Colorize;
+
+use crate::model::{Network, Outcome, Role, SwapView};
+use crate::render::{format_duration, format_state, short_id, time_in_state};
+use crate::state::SwapState;
+use crate::theme::Paint;
+use crate::tui::truncate_visible;
+
+/// Narrower than this and a card can't show its id and time in state.
+const MIN_WIDTH: usize = 15;
+
+pub struct Board<'a> {
+    lanes: Vec<Lane<'a>>,
+}
+
+struct Lane<'a> {
+    title: &'static str,
+    /// Cards show the state rather than the time in it, in lanes that
+    /// hold more than one
+    mixed: bool,
+    finished: bool,
+    views: Vec<&'a SwapView>,
+}
+
+impl<'a> Lane<'a> {
+    fn new(title: &'static str) -> Self {
+        Self {
+            title,
+            mixed: false,
+            finished: false,
+            views: Vec::new(),
+        }
+    }
+}
+
+impl<'a> Board<'a> {
+    /// Lay out `shown`, keeping its order within each column.
+    pub fn new(shown: &[&'a SwapView], role: Role) -> Self {
+        let path = SwapState::happy_path(role);
+        let steps = &path[..path.len() - 1];
+        let mut lanes: Vec<Lane> = steps.iter().map(|s| Lane::new(s.as_str())).collect();
+        for (title, mixed, finished) in [
+            ("Cancel path", true, false),
+            ("Other", true, false),
+            ("Completed", false, true),
+            ("Failed", true, true),
+        ] {
+            lanes.push(Lane {
+                mixed,
+                finished,
+                ..Lane::new(title)
+            });
+        }
+
+        let off_path = steps.len();
+        for view in shown {
+            let lane = match view.state.outcome(role) {
+                Outcome::Completed => lanes.len() - 2,
+                Outcome::Failed => lanes.len() - 1,
+                Outcome::InProgress => match steps.iter().position(|s| *s == view.state) {
+                    Some(step) => step,
+                    None if view.state.is_cancel_path() => off_path,
+                    None => off_path + 1,
+                },
+            };
+            lanes[lane].views.push(view);
+        }
+        // States no column is for are rare; their column only shows when
+        // one is there
+        if lanes[off_path + 1].views.is_empty() {
+            lanes.remove(off_path + 1);
+        }
+        Self { lanes }
+    }
+
+    /// Column and row of the card for `swap_id`.
+    fn find(&self, swap_id: &str) -> Option<(usize, usize)> {
+        self.lanes.iter().enumerate().find_map(|(i, lane)| {
+            let row = lane.views.iter().position(|v| v.swap_id == swap_id)?;
+            Some((i, row))
+        })
+    }
+
+    /// The card `lanes` columns across and `rows` rows down from
+    /// `selected`, skipping empty columns and stopping at the edges. The
+    /// first card when nothing on the board is selected.
+    pub fn moved(&self, selected: Option<&str>, lanes: isize, rows: isize) -> Option<&'a str> {
+        let Some((mut lane, row)) = selected.and_then(|id| self.find(id)) else {
+            let lane = self.lanes.iter().find(|lane| !lane.views.is_empty())?;
+            return Some(&lane.views[0].swap_id);
+        };
+        let step = lanes.signum();
+        for _ in 0..lanes.unsigned_abs() {
+            let next = (1..self.lanes.len()).find_map(|n| {
+                let i = lane.checked_add_signed(step * n as isize)?;
+                (!self.lanes.get(i)?.views.is_empty()).then_some(i)
+            });
+            match next {
+                Some(next) => lane = next,
+                None => break,
+            }
+        }
+        let views = &self.lanes[lane].views;
+        let row = row.saturating_add_signed(rows).min(views.len() - 1);
+        Some(&views[row].swap_id)
+    }
+
+    /// Whether `selected` is among the last `rows` cards of a finished
+    /// column, where scrolling on reads older swaps.
+    pub fn near_end(&self, selected: Option<&str>, rows: usize) -> bool {
+        selected
+            .and_then(|id| self.find(id))
+            .is_some_and(|(lane, row)| {
+                let lane = &self.lanes[lane];
+                lane.finished && row + rows >= lane.views.len()
+            })
+    }
+
+    /// A title row, a count row and a rule, then `rows` rows of cards. The
+    /// selected card's column scrolls to keep it in view; the others show
+    /// their first cards and how many more there are. Empty columns give
+    /// way first when not all fit in `width`.
+    pub fn lines(
+        &self,
+        width: usize,
+        rows: usize,
+        selected: Option<&str>,
+        network: Network,
+        role: Role,
+    ) -> Vec<String> {
+        let mut lanes: Vec<&Lane> = self.lanes.iter().collect();
+        let fits = |n: usize| n * (MIN_WIDTH + 1) <= width + 1;
+        while !fits(lanes.len()) {
+            let Some(empty) = lanes.iter().rposition(|lane| lane.views.is_empty()) else {
+                break;
+            };
+            lanes.remove(empty);
+        }
+        let lane_width = ((width + 1) / lanes.len().max(1))
+            .saturating_sub(1)
+            .max(MIN_WIDTH);
+        let at = selected.and_then(|id| self.find(id));
+
+        let mut titles = Vec::new();
+        let mut counts = Vec::new();
+        for lane in &lanes {
+            titles.push(fit(
+                &lane.title.bold().to_string(),
+                lane.title.len(),
+                lane_width,
+            ));
+            let count = match lane.views.len() {
+                1 => "1 swap".to_string(),
+                n => format!("{n} swaps"),
+            };
+            counts.push(fit(&count.dimmed().to_string(), count.len(), lane_width));
+        }
+        let mut lines = vec![
+            titles.join("│"),
+            counts.join("│"),
+            vec!["─".repeat(lane_width); lanes.len()].join("┼"),
+        ];
+
+        let rows = rows.max(1);
+        let scrolls: Vec<usize> = lanes
+            .iter()
+            .map(|lane| match at {
+                // Clear of the last row, which counts what is below
+                Some((i, row)) if std::ptr::eq(&self.lanes[i], *lane) => (row + 2)
+                    .saturating_sub(rows)
+                    .min(lane.views.len().saturating_sub(rows)),
+                _ => 0,
+            })
+            .collect();
+        for r in 0..rows {
+            let cells: Vec<String> = lanes
+                .iter()
+                .zip(&scrolls)
+                .map(|(lane, &scroll)| {
+                    let left = lane.views.len().saturating_sub(scroll + r);
+                    // The last row says how many more there are instead
+                    if r + 1 == rows && left > 1 {
+                        let more = format!("+{left} more");
+                        return fit(&more.dimmed().to_string(), more.len(), lane_width);
+                    }
+                    match lane.views.get(scroll + r) {
+                        Some(view) => {
+                            let chosen = selected == Some(view.swap_id.as_str());
+                            card(view, lane.mixed, chosen, lane_width, network, role)
+                        }
+                        None => " ".repeat(lane_width),
+                    }
+                })
+                .collect();
+            lines.push(cells.join("│"));
+        }
+        lines
+    }
+}
+
+/// `a1b2c3.. 3h 4m`, or the state instead of the time in columns of
+/// several states, in the colours of the table row.
+fn card(
+    view: &SwapView,
+    mixed: bool,
+    selected: bool,
+    width: usize,
+    network: Network,
+    role: Role,
+) -> String {
+    let swap_id = format!("{:<8}", short_id(&view.swap_id));
+    let swap_id = if selected {
+        swap_id.reversed()
+    } else if view.pinned {
+        swap_id.warn().bold()
+    } else if view.watched {
+        swap_id.accent().bold()
+    } else {
+        swap_id.normal()
+    };
+    let detail = if mixed {
+        format_state(&view.state, view.highlight, network, role)
+    } else {
+        let in_state = time_in_state(&view.entered_at)
+            .map(format_duration)
+            .unwrap_or_else(|| "?".to_string());
+        match view.stuck {
+            true => in_state.bad().bold(),
+            false => in_state.dimmed(),
+        }
+    };
+    let room = width.saturating_sub(9);
+    format!(
+        "{swap_id} {}",
+        fit(&detail.to_string(), detail.chars().count(), room)
+    )
+}
+
+/// `text` cut or padded to `width` columns; `visible` is its length
+/// without colour.
+fn fit(text: &str, visible: usize, width: usize) -> String {
+    let pad = width.saturating_sub(visible);
+    format!("{}{}", truncate_visible(text, width), " ".repeat(pad))
+}
@@ -0,0 +1,883 @@
+//! On-chain status for swaps: BTC and XMR confirmations and how far each
+//! in-flight swap is from its cancel and punish timelocks, plus the ASB's
+//! wallet balances, the fee environment, the market rate, the BTC price
+//! in fiat, whether ASB itself is running, whether takers can reach it and
+//! whether it answers them with a sensible quote.
+//!
+//! Lookups run on a background task against the configured Electrum server,
+//! monero-wallet-rpc, mempool instance, price sources and ASB, and land in a
+//! shared cache, so a slow or unreachable server never stalls the poll
+//! loop. Views are annotated from whatever the cache holds.
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::{BTreeSet, HashMap, HashSet, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::Notify;
+use tokio::time::sleep;
+
+use crate::electrum::{ElectrumServer, Session, DEFAULT_TIMEOUT};
+use crate::fiat::{FiatPrice, FiatRates};
+use crate::liveness::{AsbDown, AsbProbe, AsbStatus};
+use crate::mempool::{FeeEnvironment, FeeSpike, Mempool};
+use crate::model::{
+    parse_entered_at, ChainStatus, FiatValue, Network, Role, SwapDetails, SwapView, Timelock,
+    XmrTransfer,
+};
+use crate::monero::{MoneroRpc, XmrBalance};
+use crate::price::PriceSource;
+use crate::quote::{Quote, QuoteAlert, QuoteCheck, QuoteProbe};
+use crate::reachability::{Reach, Target, Unreachable};
+use crate::wallet::{BtcBalance, BtcWallet};
+
+/// How often confirmations are refreshed; Bitcoin blocks are ~10 minutes
+const REFRESH: Duration = Duration::from_secs(30);
+
+/// How often advertised addresses are connected to; onion circuits are
+/// slow to build and Tor shouldn't be asked every refresh
+const REACHABILITY_EVERY: Duration = Duration::from_secs(300);
+
+/// How often ASB is asked for a quote; each one makes the swap CLI look
+/// up every maker at the rendezvous point
+const QUOTE_EVERY: Duration = Duration::from_secs(600);
+
+/// Finished swaps keep being looked up for this long, so the redeem or
+/// refund can be watched until it is buried
+const FINISHED_GRACE: chrono::Duration = chrono::Duration::hours(24);
+
+/// Confirmations after which a finished swap's transactions are settled
+const SETTLED: u32 = 6;
+
+/// Balance samples kept for the panel's graph: a day at the refresh rate
+const BALANCE_HISTORY: usize = 2880;
+
+/// Bitcoin transactions among a swap's txids; everything but the XMR lock.
+pub fn btc_txids(details: &SwapDetails) -> impl Iterator<Item = (&String, &String)> {
+    details
+        .txids
+        .iter()
+        .filter(|(label, _)| label.as_str() != "xmr_lock")
+}
+
+/// Confirmations for a set of transactions, keyed by txid, for the
+/// per-swap detail view.
+#[derive(Debug, Default)]
+pub struct TxConfirmations {
+    pub btc: HashMap<String, u32>,
+    pub xmr: HashMap<String, XmrTransfer>,
+}
+
+impl TxConfirmations {
+    /// `3 conf` or `3 conf, unlocks in 7 blocks`.
+    pub fn describe(&self, txid: &str) -> Option<String> {
+        if let Some(count) = self.btc.get(txid) {
+            return Some(format!("{count} conf"));
+        }
+        let transfer = self.xmr.get(txid)?;
+        Some(match transfer.unlocks_in {
+            0 => format!("{} conf", transfer.confirmations),
+            n => format!("{} conf, unlocks in {n} blocks", transfer.confirmations),
+        })
+    }
+}
+
+/// Wallet balances at one refresh; a side is `None` when it isn't
+/// configured or its lookup failed.
+#[derive(Debug, Clone, Copy)]
+pub struct BalanceSample {
+    pub at: DateTime<Utc>,
+    pub btc: Option<BtcBalance>,
+    pub xmr: Option<XmrBalance>,
+}
+
+/// Balance thresholds that trigger a liquidity alert.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LiquidityRules {
+    /// Alert when unlocked XMR, in piconero, drops below this
+    pub min_xmr: Option<u64>,
+    /// Alert when the BTC wallet, in satoshis, grows beyond this
+    pub max_btc: Option<u64>,
+}
+
+impl LiquidityRules {
+    pub fn is_empty(&self) -> bool {
+        self.min_xmr.is_none() && self.max_btc.is_none()
+    }
+}
+
+/// A balance crossed one of the `LiquidityRules` thresholds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case", tag = "kind")]
+pub enum LiquidityAlert {
+    /// Not enough XMR left to lock for the next swap
+    XmrLow {
+        unlocked_piconero: u64,
+        threshold_piconero: u64,
+    },
+    /// BTC from redeemed swaps is piling up in the hot wallet
+    BtcHigh {
+        balance_sats: u64,
+        threshold_sats: u64,
+    },
+}
+
+#[derive(Debug, Default)]
+struct Wanted {
+    btc: BTreeSet<String>,
+    xmr: BTreeSet<String>,
+}
+
+#[derive(Debug, Default)]
+struct Cache {
+    /// Txids the views currently care about, per instance
+    wanted: HashMap<String, Wanted>,
+    confirmations: HashMap<String, u32>,
+    xmr_transfers: HashMap<String, XmrTransfer>,
+    balances: VecDeque<BalanceSample>,
+    /// Thresholds currently crossed, so each crossing alerts once
+    xmr_low: bool,
+    btc_high: bool,
+    alerts: Vec<LiquidityAlert>,
+    fees: Option<FeeEnvironment>,
+    /// The fee threshold is crossed, so a spike alerts once
+    fee_spike: bool,
+    fee_alerts: Vec<FeeSpike>,
+    /// BTC per XMR
+    market: Option<f64>,
+    fiat: Option<FiatPrice>,
+    asb: Option<AsbStatus>,
+    /// ASB is down and that was alerted, so it alerts once per outage
+    asb_alerted: bool,
+    asb_alerts: Vec<AsbDown>,
+    /// In-flight swaps per instance, as of the last poll
+    in_flight: HashMap<String, usize>,
+    reach: Vec<Reach>,
+    reach_checked: Option<Instant>,
+    /// Addresses found unreachable and alerted, so each outage alerts once
+    unreachable: HashSet<String>,
+    reach_alerts: Vec<Unreachable>,
+    quote: Option<QuoteCheck>,
+    quote_checked: Option<Instant>,
+    /// A failure or drift was alerted, so each alerts once until it clears
+    quote_failing: bool,
+    quote_drifting: bool,
+    quote_alerts: Vec<QuoteAlert>,
+    electrum_error: Option<String>,
+    monero_error: Option<String>,
+    mempool_error: Option<String>,
+    price_error: Option<String>,
+    fiat_error: Option<String>,
+}
+
+/// Handle to the chain lookups. Cheap to clone; a monitor without backends
+/// never annotates anything.
+#[derive(Clone, Default)]
+pub struct ChainMonitor {
+    electrum: Option<ElectrumServer>,
+    monero: Option<MoneroRpc>,
+    wallet: Option<BtcWallet>,
+    /// Show wallet balances in the panel
+    balances: bool,
+    liquidity: LiquidityRules,
+    mempool: Option<Mempool>,
+    /// Next-block fee rate, in sat/vB, that raises a fee spike alert
+    max_fee_rate: Option<f64>,
+    price: Option<PriceSource>,
+    fiat: Option<FiatRates>,
+    asb: AsbProbe,
+    /// Advertised addresses to check, and Tor's SOCKS proxy for onions
+    reachability: Vec<Target>,
+    tor_socks: String,
+    quotes: Option<QuoteProbe>,
+    /// ASB's ask spread, which quotes should carry over the market rate
+    spread: f64,
+    quote_tolerance: f64,
+    cache: Arc<Mutex<Cache>>,
+    wake: Arc<Notify>,
+    updated: Arc<Notify>,
+}
+
+impl ChainMonitor {
+    pub fn new(electrum: Option<ElectrumServer>, monero: Option<MoneroRpc>) -> Self {
+        Self {
+            electrum,
+            monero,
+            ..Self::default()
+        }
+    }
+
+    /// Also sample wallet balances on every refresh when `show` is set.
+    pub fn with_balances(mut self, show: bool, wallet: Option<BtcWallet>) -> Self {
+        self.balances = show;
+        self.wallet = wallet;
+        self
+    }
+
+    /// Sample balances for liquidity alerts too, with or without the panel.
+    pub fn with_liquidity(mut self, rules: LiquidityRules) -> Self {
+        self.liquidity = rules;
+        self
+    }
+
+    /// Also look up the fee environment, alerting above `max_fee_rate`.
+    pub fn with_fees(mut self, mempool: Option<Mempool>, max_fee_rate: Option<f64>) -> Self {
+        self.mempool = mempool;
+        self.max_fee_rate = max_fee_rate;
+        self
+    }
+
+    /// Also look up the XMR/BTC market rate.
+    pub fn with_price(mut self, source: Option<PriceSource>) -> Self {
+        self.price = source;
+        self
+    }
+
+    /// Also look up the BTC price in fiat, to value swaps with.
+    pub fn with_fiat(mut self, rates: Option<FiatRates>) -> Self {
+        self.fiat = rates;
+        self
+    }
+
+    /// Also check whether ASB is running, alerting when it stops with
+    /// swaps in flight.
+    pub fn with_asb(mut self, probe: AsbProbe) -> Self {
+        self.asb = probe;
+        self
+    }
+
+    /// Also check now and then that takers can connect to `targets`.
+    pub fn with_reachability(mut self, targets: Vec<Target>, tor_socks: String) -> Self {
+        self.reachability = targets;
+        self.tor_socks = tor_socks;
+        self
+    }
+
+    /// Also ask ASB for a quote now and then, alerting when none comes
+    /// back or it is more than `tolerance` from market × (1 + `spread`).
+    pub fn with_quotes(mut self, probe: Option<QuoteProbe>, spread: f64, tolerance: f64) -> Self {
+        self.quotes = probe;
+        self.spread = spread;
+        self.quote_tolerance = tolerance;
+        self
+    }
+
+    pub fn has_electrum(&self) -> bool {
+        self.electrum.is_some()
+    }
+
+    pub fn has_monero(&self) -> bool {
+        self.monero.is_some()
+    }
+
+    pub fn has_fiat(&self) -> bool {
+        self.fiat.is_some()
+    }
+
+    fn is_enabled(&self) -> bool {
+        self.has_electrum() || self.has_monero()
+    }
+
+    fn samples_balances(&self) -> bool {
+        self.balances || !self.liquidity.is_empty()
+    }
+
+    /// Whether there is anything to look up beyond transactions.
+    fn has_feeds(&self) -> bool {
+        self.samples_balances()
+            || self.mempool.is_some()
+            || self.price.is_some()
+            || self.fiat.is_some()
+            || !self.asb.is_empty()
+            || !self.reachability.is_empty()
+            || self.quotes.is_some()
+    }
+
+    /// Which balance panel lines to show: BTC, XMR.
+    pub fn balance_sources(&self) -> (bool, bool) {
+        if !self.balances {
+            return (false, false);
+        }
+        (
+            self.wallet.is_some() && self.has_electrum(),
+            self.has_monero(),
+        )
+    }
+
+    /// Keep the cache fresh on a background task, for the watch loops.
+    pub fn start(&self) {
+        if !self.is_enabled() && !self.has_feeds() {
+            return;
+        }
+        let monitor = self.clone();
+        tokio::spawn(async move {
+            loop {
+                monitor.refresh().await;
+                tokio::select! {
+                    _ = sleep(REFRESH) => {}
+                    _ = monitor.wake.notified() => {}
+                }
+            }
+        });
+    }
+
+    /// Look up every wanted transaction once.
+    pub async fn refresh(&self) {
+        let (btc, xmr): (Vec<String>, Vec<String>) = {
+            let cache = self.cache.lock().unwrap();
+            let btc: BTreeSet<&String> = cache.wanted.values().flat_map(|w| &w.btc).collect();
+            let xmr: BTreeSet<&String> = cache.wanted.values().flat_map(|w| &w.xmr).collect();
+            (
+                btc.into_iter().cloned().collect(),
+                xmr.into_iter().cloned().collect(),
+            )
+        };
+        if btc.is_empty() && xmr.is_empty() && !self.has_feeds() {
+            return;
+        }
+
+        let btc_result = self.lookup_now(&btc).await;
+        let xmr_result = self.xmr_lookup_now(&xmr).await;
+        let (btc_balance, xmr_balance) = match self.samples_balances() {
+            true => self.balances_now().await,
+            false => (Ok(None), Ok(None)),
+        };
+        let fees = match self.mempool {
+            Some(ref mempool) => Some(mempool.fees().await),
+            None => None,
+        };
+        let market = match self.price {
+            Some(ref source) => Some(source.current().await),
+            None => None,
+        };
+        let fiat = match self.fiat {
+            Some(ref rates) => Some(rates.price().await),
+            None => None,
+        };
+        let asb = match self.asb.is_empty() {
+            true => None,
+            false => Some(self.asb.check().await),
+        };
+        let reach = self.reach_now().await;
+        let quote = self.quote_now().await;
+
+        let mut cache = self.cache.lock().unwrap();
+        cache.electrum_error = None;
+        cache.monero_error = None;
+        match btc_result {
+            Ok(confirmations) => cache.confirmations.extend(confirmations),
+            Err(err) => cache.electrum_error = Some(format!("{err:#}")),
+        }
+        match xmr_result {
+            Ok(transfers) => cache.xmr_transfers.extend(transfers),
+            Err(err) => cache.monero_error = Some(format!("{err:#}")),
+        }
+        if self.samples_balances() {
+            let sample = BalanceSample {
+                at: Utc::now(),
+                btc: btc_balance
+                    .map_err(|err| cache.electrum_error = Some(format!("{err:#}")))
+                    .unwrap_or_default(),
+                xmr: xmr_balance
+                    .map_err(|err| cache.monero_error = Some(format!("{err:#}")))
+                    .unwrap_or_default(),
+            };
+            if sample.btc.is_some() || sample.xmr.is_some() {
+                if cache.balances.len() == BALANCE_HISTORY {
+                    cache.balances.pop_front();
+                }
+                cache.balances.push_back(sample);
+            }
+            self.check_liquidity(&mut cache, &sample);
+        }
+        cache.mempool_error = None;
+        match fees {
+            Some(Ok(fees)) => {
+                self.check_fees(&mut cache, &fees);
+                cache.fees = Some(fees);
+            }
+            Some(Err(err)) => cache.mempool_error = Some(format!("{err:#}")),
+            None => {}
+        }
+        cache.price_error = None;
+        match market {
+            Some(Ok(market)) => cache.market = Some(market),
+            Some(Err(err)) => cache.price_error = Some(format!("{err:#}")),
+            None => {}
+        }
+        cache.fiat_error = None;
+        match fiat {
+            Some(Ok(price)) => cache.fiat = Some(price),
+            Some(Err(err)) => cache.fiat_error = Some(format!("{err:#}")),
+            None => {}
+        }
+        if let Some(status) = asb {
+            self.check_asb(&mut cache, &status);
+            cache.asb = Some(status);
+        }
+        if let Some(reach) = reach {
+            self.check_reach(&mut cache, &reach);
+            cache.reach = reach;
+        }
+        if let Some(quote) = quote {
+            let check = QuoteCheck {
+                drift: match (&quote, cache.market) {
+                    (Ok(quote), Some(market)) => {
+                        let expected = market * (1.0 + self.spread);
+                        Some(quote.price / expected - 1.0)
+                    }
+                    _ => None,
+                },
+                quote: quote.map_err(|err| format!("{err:#}")),
+            };
+            self.check_quote(&mut cache, &check);
+            cache.quote = Some(check);
+        }
+        self.updated.notify_waiters();
+    }
+
+    /// Queue an alert for every threshold `sample` newly crosses. A
+    /// threshold re-arms once the balance is back on the right side.
+    fn check_liquidity(&self, cache: &mut Cache, sample: &BalanceSample) {
+        if let (Some(threshold), Some(xmr)) = (self.liquidity.min_xmr, sample.xmr) {
+            let low = xmr.unlocked < threshold;
+            if low && !cache.xmr_low {
+                cache.alerts.push(LiquidityAlert::XmrLow {
+                    unlocked_piconero: xmr.unlocked,
+                    threshold_piconero: threshold,
+                });
+            }
+            cache.xmr_low = low;
+        }
+        if let (Some(threshold), Some(btc)) = (self.liquidity.max_btc, sample.btc) {
+            let high = btc.total() > threshold;
+            if high && !cache.btc_high {
+                cache.alerts.push(LiquidityAlert::BtcHigh {
+                    balance_sats: btc.total(),
+                    threshold_sats: threshold,
+                });
+            }
+            cache.btc_high = high;
+        }
+    }
+
+    /// Queue a fee spike alert when the next-block rate newly goes above
+    /// the threshold; it re-arms once fees are back at or below it.
+    fn check_fees(&self, cache: &mut Cache, fees: &FeeEnvironment) {
+        let Some(threshold) = self.max_fee_rate else {
+            return;
+        };
+        let spike = fees.fastest > threshold;
+        if spike && !cache.fee_spike {
+            cache.fee_alerts.push(FeeSpike {
+                fastest_sat_vb: fees.fastest,
+                threshold_sat_vb: threshold,
+                mempool_vsize: fees.mempool_vsize,
+            });
+        }
+        cache.fee_spike = spike;
+    }
+
+    /// Queue an alert when ASB is down with swaps in flight, once per
+    /// outage; it re-arms once ASB is back.
+    fn check_asb(&self, cache: &mut Cache, status: &AsbStatus) {
+        let AsbStatus::Down { reason } = status else {
+            cache.asb_alerted = false;
+            return;
+        };
+        let in_flight = cache.in_flight.values().sum();
+        if in_flight > 0 && !cache.asb_alerted {
+            cache.asb_alerts.push(AsbDown {
+                reason: reason.clone(),
+                in_flight,
+            });
+            cache.asb_alerted = true;
+        }
+    }
+
+    /// Queue an alert for every address that newly can't be reached; each
+    /// re-arms once a connection gets through again.
+    fn check_reach(&self, cache: &mut Cache, reach: &[Reach]) {
+        for Reach { target, error } in reach {
+            let address = target.to_string();
+            match error {
+                Some(reason) if !cache.unreachable.contains(&address) => {
+                    cache.reach_alerts.push(Unreachable {
+                        address: address.clone(),
+                        via_tor: target.is_onion(),
+                        reason: reason.clone(),
+                    });
+                    cache.unreachable.insert(address);
+                }
+                Some(_) => {}
+                None => {
+                    cache.unreachable.remove(&address);
+                }
+            }
+        }
+    }
+
+    /// Queue an alert when the self-check newly fails or the quote newly
+    /// drifts too far; each re-arms once it clears.
+    fn check_quote(&self, cache: &mut Cache, check: &QuoteCheck) {
+        match check.quote {
+            Err(ref reason) => {
+                if !cache.quote_failing {
+                    cache.quote_alerts.push(QuoteAlert::Failed {
+                        reason: reason.clone(),
+                    });
+                }
+                cache.quote_failing = true;
+            }
+            Ok(quote) => {
+                cache.quote_failing = false;
+                let Some(drift) = check.drift else {
+                    return;
+                };
+                let drifting = drift.abs() > self.quote_tolerance;
+                if drifting && !cache.quote_drifting {
+                    cache.quote_alerts.push(QuoteAlert::Drift {
+                        quoted: quote.price,
+                        expected: quote.price / (1.0 + drift),
+                        drift,
+                        tolerance: self.quote_tolerance,
+                    });
+                }
+                cache.quote_drifting = drifting;
+            }
+        }
+    }
+
+    /// Ask ASB for a quote, unless that was done recently.
+    async fn quote_now(&self) -> Option<anyhow::Result<Quote>> {
+        let probe = self.quotes.as_ref()?;
+        let checked = self.cache.lock().unwrap().quote_checked;
+        if checked.is_some_and(|at| at.elapsed() < QUOTE_EVERY) {
+            return None;
+        }
+        self.cache.lock().unwrap().quote_checked = Some(Instant::now());
+        Some(probe.request().await)
+    }
+
+    /// Connect to every advertised address, unless that was done recently.
+    async fn reach_now(&self) -> Option<Vec<Reach>> {
+        if self.reachability.is_empty() {
+            return None;
+        }
+        let checked = self.cache.lock().unwrap().reach_checked;
+        if checked.is_some_and(|at| at.elapsed() < REACHABILITY_EVERY) {
+            return None;
+        }
+        self.cache.lock().unwrap().reach_checked = Some(Instant::now());
+        let mut reach = Vec::with_capacity(self.reachability.len());
+        for target in &self.reachability {
+            reach.push(Reach {
+                target: target.clone(),
+                error: target
+                    .check(&self.tor_socks)
+                    .await
+                    .err()
+                    .map(|err| format!("{err:#}")),
+            });
+        }
+        Some(reach)
+    }
+
+    /// Liquidity alerts raised since the last call.
+    pub fn take_alerts(&self) -> Vec<LiquidityAlert> {
+        std::mem::take(&mut self.cache.lock().unwrap().alerts)
+    }
+
+    /// Fee spike alerts raised since the last call.
+    pub fn take_fee_alerts(&self) -> Vec<FeeSpike> {
+        std::mem::take(&mut self.cache.lock().unwrap().fee_alerts)
+    }
+
+    /// ASB down alerts raised since the last call.
+    pub fn take_asb_alerts(&self) -> Vec<AsbDown> {
+        std::mem::take(&mut self.cache.lock().unwrap().asb_alerts)
+    }
+
+    /// Unreachable address alerts raised since the last call.
+    pub fn take_reach_alerts(&self) -> Vec<Unreachable> {
+        std::mem::take(&mut self.cache.lock().unwrap().reach_alerts)
+    }
+
+    /// Quote self-check alerts raised since the last call.
+    pub fn take_quote_alerts(&self) -> Vec<QuoteAlert> {
+        std::mem::take(&mut self.cache.lock().unwrap().quote_alerts)
+    }
+
+    /// The latest fee environment on `network`, if the mempool instance
+    /// serves it and has answered.
+    pub fn fees(&self, network: Network) -> Option<FeeEnvironment> {
+        let mempool = self.mempool.as_ref()?;
+        if mempool.network() != network {
+            return None;
+        }
+        self.cache.lock().unwrap().fees
+    }
+
+    /// The latest market rate, BTC per XMR, and where it came from, once
+    /// the price source has answered.
+    pub fn market(&self) -> Option<(f64, &PriceSource)> {
+        let source = self.price.as_ref()?;
+        let market = self.cache.lock().unwrap().market?;
+        Some((market, source))
+    }
+
+    /// The latest BTC price and its currency, once known; possibly a
+    /// stale one from the cache while the source is unreachable.
+    pub fn fiat(&self) -> Option<(FiatPrice, &str)> {
+        let rates = self.fiat.as_ref()?;
+        let price = self.cache.lock().unwrap().fiat?;
+        Some((price, rates.currency()))
+    }
+
+    /// Whether ASB was running at the last check, if it is checked.
+    pub fn asb(&self) -> Option<AsbStatus> {
+        self.cache.lock().unwrap().asb.clone()
+    }
+
+    /// How each advertised address fared at the last check.
+    pub fn reach(&self) -> Vec<Reach> {
+        self.cache.lock().unwrap().reach.clone()
+    }
+
+    /// The last quote self-check, if one ran.
+    pub fn quote(&self) -> Option<QuoteCheck> {
+        self.cache.lock().unwrap().quote.clone()
+    }
+
+    /// How far a quote may drift before it counts as wrong.
+    pub fn quote_tolerance(&self) -> f64 {
+        self.quote_tolerance
+    }
+
+    /// Next-block fee rate above which fees count as spiking.
+    pub fn max_fee_rate(&self) -> Option<f64> {
+        self.max_fee_rate
+    }
+
+    /// Resolves after the next round of lookups; never without a backend.
+    pub async fn updated(&self) {
+        self.updated.notified().await
+    }
+
+    /// Fill in `chain` on views from the cache and queue lookups for
+    /// transactions not seen before. In-flight swaps are always watched;
+    /// finished ones until their transactions settle or the grace period
+    /// runs out. `scope` names the instance the views came from. With a
+    /// fiat price, `fiat` is filled in too.
+    pub fn annotate(&self, scope: &str, views: &mut [SwapView], role: Role) {
+        self.count_in_flight(scope, views, role);
+        if let Some((price, currency)) = self.fiat() {
+            for view in views.iter_mut() {
+                view.fiat = view.details.btc_sats.map(|sats| FiatValue {
+                    value: sats as f64 / 100_000_000.0 * price.per_btc,
+                    currency: currency.to_string(),
+                });
+            }
+        }
+        if !self.is_enabled() {
+            return;
+        }
+
+        let mut cache = self.cache.lock().unwrap();
+        let mut wanted = Wanted::default();
+        let now = Utc::now();
+        for view in views.iter_mut() {
+            let in_flight = !view.state.is_terminal(role);
+            let recent =
+                parse_entered_at(&view.entered_at).is_some_and(|t| now - t < FINISHED_GRACE);
+
+            let mut status = ChainStatus::default();
+            if self.has_electrum() {
+                for (label, txid) in btc_txids(&view.details) {
+                    let count = cache.confirmations.get(txid).copied();
+                    if in_flight || (recent && count.is_none_or(|c| c < SETTLED)) {
+                        wanted.btc.insert(txid.clone());
+                    }
+                    if let Some(count) = count {
+                        status.confirmations.insert(label.clone(), count);
+                    }
+                }
+            }
+            if in_flight {
+                status.timelock = status
+                    .confirmations
+                    .get("btc_lock")
+                    .and_then(|&c| Timelock::from_details(&view.details, c));
+            }
+            if let (true, Some(txid)) = (self.has_monero(), view.details.txids.get("xmr_lock")) {
+                let transfer = cache.xmr_transfers.get(txid).copied();
+                if in_flight || (recent && transfer.is_none_or(|t| t.unlocks_in > 0)) {
+                    wanted.xmr.insert(txid.clone());
+                }
+                status.xmr_lock = transfer;
+            }
+            view.chain = status;
+        }
+
+        let grew = match cache.wanted.get(scope) {
+            Some(previous) => {
+                !wanted.btc.is_subset(&previous.btc) || !wanted.xmr.is_subset(&previous.xmr)
+            }
+            None => !wanted.btc.is_empty() || !wanted.xmr.is_empty(),
+        };
+        cache.wanted.insert(scope.to_string(), wanted);
+        if grew {
+            self.wake.notify_one();
+        }
+    }
+
+    /// Remember how many of `scope`'s swaps are in flight, for ASB down
+    /// alerts. `annotate` does this too.
+    pub fn count_in_flight(&self, scope: &str, views: &[SwapView], role: Role) {
+        if self.asb.is_empty() {
+            return;
+        }
+        let count = views.iter().filter(|v| !v.state.is_terminal(role)).count();
+        let previous = self
+            .cache
+            .lock()
+            .unwrap()
+            .in_flight
+            .insert(scope.to_string(), count);
+        // The first check ran before anything was counted
+        if previous.is_none() && count > 0 {
+            self.wake.notify_one();
+        }
+    }
+
+    /// Whatever the cache knows about `txids`.
+    pub fn cached<'a>(&self, txids: impl IntoIterator<Item = &'a String>) -> TxConfirmations {
+        let cache = self.cache.lock().unwrap();
+        let mut known = TxConfirmations::default();
+        for txid in txids {
+            if let Some(&count) = cache.confirmations.get(txid) {
+                known.btc.insert(txid.clone(), count);
+            }
+            if let Some(&transfer) = cache.xmr_transfers.get(txid) {
+                known.xmr.insert(txid.clone(), transfer);
+            }
+        }
+        known
+    }
+
+    /// Look up one swap's transactions right away, for `show`.
+    pub async fn lookup_details(&self, details: &SwapDetails) -> anyhow::Result<TxConfirmations> {
+        let btc: Vec<String> = btc_txids(details).map(|(_, txid)| txid.clone()).collect();
+        let xmr: Vec<String> = details.txids.get("xmr_lock").cloned().into_iter().collect();
+        Ok(TxConfirmations {
+            btc: self.lookup_now(&btc).await?,
+            xmr: self.xmr_lookup_now(&xmr).await?,
+        })
+    }
+
+    /// Look up BTC `txids` right away, bypassing the cache. For one-shot
+    /// commands; an empty map without a server.
+    pub async fn lookup_now(&self, txids: &[String]) -> anyhow::Result<HashMap<String, u32>> {
+        match self.electrum {
+            Some(ref server) if !txids.is_empty() => lookup(server, txids).await,
+            _ => Ok(HashMap::new()),
+        }
+    }
+
+    /// Same for XMR transfers, via monero-wallet-rpc.
+    pub async fn xmr_lookup_now(
+        &self,
+        txids: &[String],
+    ) -> anyhow::Result<HashMap<String, XmrTransfer>> {
+        let mut transfers = HashMap::new();
+        if let Some(ref rpc) = self.monero {
+            for txid in txids {
+                if let Some(transfer) = rpc.transfer(txid).await? {
+                    transfers.insert(txid.clone(), transfer);
+                }
+            }
+        }
+        Ok(transfers)
+    }
+
+    /// Current wallet balances, each `Ok(None)` when not configured.
+    pub async fn balances_now(
+        &self,
+    ) -> (
+        anyhow::Result<Option<BtcBalance>>,
+        anyhow::Result<Option<XmrBalance>>,
+    ) {
+        let btc = async {
+            match (&self.electrum, &self.wallet) {
+                (Some(server), Some(wallet)) => {
+                    let mut session = Session::connect(server, DEFAULT_TIMEOUT).await?;
+                    wallet.balance(&mut session).await.map(Some)
+                }
+                _ => Ok(None),
+            }
+        };
+        let xmr = async {
+            match self.monero {
+                Some(ref rpc) => rpc.balance().await.map(Some),
+                None => Ok(None),
+            }
+        };
+        tokio::join!(btc, xmr)
+    }
+
+    /// Balance samples so far, oldest first.
+    pub fn balance_history(&self) -> Vec<BalanceSample> {
+        self.cache
+            .lock()
+            .unwrap()
+            .balances
+            .iter()
+            .copied()
+            .collect()
+    }
+
+    /// Most recent lookup failures, one per line, if the last attempt
+    /// failed.
+    pub fn error(&self) -> Option<String> {
+        let cache = self.cache.lock().unwrap();
+        let errors: Vec<String> = [
+            cache
+                .electrum_error
+                .as_ref()
+                .map(|err| format!("Electrum: {err}")),
+            cache
+                .monero_error
+                .as_ref()
+                .map(|err| format!("monero-wallet-rpc: {err}")),
+            cache
+                .mempool_error
+                .as_ref()
+                .map(|err| format!("mempool: {err}")),
+            cache
+                .price_error
+                .as_ref()
+                .map(|err| format!("price source: {err}")),
+            cache
+                .fiat_error
+                .as_ref()
+                .map(|err| format!("fiat price: {err}")),
+        ]
+        .into_iter()
+        .flatten()
+        .collect();
+        (!errors.is_empty()).then(|| errors.join("; "))
+    }
+}
+
+async fn lookup(server: &ElectrumServer, txids: &[String]) -> anyhow::Result<HashMap<String, u32>> {
+    let mut session = Session::connect(server, DEFAULT_TIMEOUT).await?;
+    let tip = session.tip_height().await?;
+
+    let mut confirmations = HashMap::new();
+    for txid in txids {
+        if let Some(count) = session.confirmations(txid, tip).await? {
+            confirmations.insert(txid.clone(), count);
+        }
+    }
+    Ok(confirmations)
+}
@@ -0,0 +1,2981 @@
+//! Command-line parsing.
+//!
+//! clap reads the command line into [`Cli`]. The database, config and
+//! display options are global, so they go before or after the command; the
+//! rest belong to the commands that read them, with watch's also taken
+//! without a command. [`parse_args`] lays them over the config files and
+//! checks what spans several options, wherever each came from.
+
+use anyhow::{bail, Context, Result};
+use chrono::{Local, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Utc};
+use clap::builder::PossibleValuesParser;
+use clap::error::ErrorKind;
+use clap::parser::ValueSource;
+use clap::{ArgMatches, CommandFactory, FromArgMatches, Parser, Subcommand};
+use serde::Deserialize;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
+
+use crate::agent;
+use crate::asb::AsbConfig;
+use crate::asblog::{LogMatch, LogRule, DEFAULT_WINDOW};
+use crate::bell;
+use crate::chain::LiquidityRules;
+use crate::columns::{self, TableColumn};
+use crate::config;
+use crate::csv::Column;
+use crate::db::InstanceSpec;
+use crate::electrum::ElectrumServer;
+use crate::explorer::{DEFAULT_EXPLORER, TX_NAMES};
+use crate::hooks::{DEFAULT_HOOK_CONCURRENCY, DEFAULT_HOOK_TIMEOUT};
+use crate::liveness::AsbProbe;
+use crate::logging::LogFormat;
+use crate::mail;
+use crate::model::{DateRange, EarningsOptions, Network, Period, Role, StuckRules, TimeBound};
+use crate::mqtt::{MqttBroker, QoS};
+use crate::notes;
+use crate::notify::{MQTT_DEFAULT_TOPIC, NTFY_DEFAULT_SERVER, OPSGENIE_DEFAULT_API_URL};
+use crate::otel;
+use crate::price::PriceSource;
+use crate::quote::{DEFAULT_SWAP_BIN, DEFAULT_TOLERANCE};
+use crate::reachability::{Target, DEFAULT_TOR_SOCKS};
+use crate::rules::{QuietHours, Rule, Severity};
+use crate::search::SwapSearch;
+use crate::sort::SortOrder;
+use crate::state::StateSelection;
+use crate::theme::{self, Style, ThemeName};
+use crate::tls::Fingerprint;
+use crate::wallet::BtcWallet;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+    #[default]
+    Table,
+    Json,
+    /// A Nagios/Icinga check result; `list` only
+    Nagios,
+}
+
+impl FromStr for OutputFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "table" => Ok(OutputFormat::Table),
+            "json" => Ok(OutputFormat::Json),
+            "nagios" => Ok(OutputFormat::Nagios),
+            other => bail!("unknown output format: {other} (expected table, json or nagios)"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ExportFormat {
+    #[default]
+    Json,
+    Csv,
+    Parquet,
+    /// Completed swaps as trades, for tax tools
+    Accounting,
+}
+
+impl FromStr for ExportFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "json" => Ok(ExportFormat::Json),
+            "csv" => Ok(ExportFormat::Csv),
+            "parquet" => Ok(ExportFormat::Parquet),
+            "accounting" => Ok(ExportFormat::Accounting),
+            other => {
+                bail!("unknown export format: {other} (expected json, csv, parquet or accounting)")
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ColorChoice {
+    /// Let `colored` decide from the terminal and NO_COLOR/CLICOLOR
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+impl FromStr for ColorChoice {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "auto" => Ok(ColorChoice::Auto),
+            "always" => Ok(ColorChoice::Always),
+            "never" => Ok(ColorChoice::Never),
+            other => bail!("unknown color choice: {other} (expected auto, always or never)"),
+        }
+    }
+}
+
+/// How timestamps are shown (`--time`).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TimeFormat {
+    /// In the local timezone
+    #[default]
+    Local,
+    /// In UTC, as ASB stores them
+    Utc,
+    /// How long ago, like `3m ago`
+    Relative,
+}
+
+impl TimeFormat {
+    /// The next one `T` switches to in the full-screen view.
+    pub fn next(self) -> Self {
+        match self {
+            TimeFormat::Local => TimeFormat::Relative,
+            TimeFormat::Relative => TimeFormat::Utc,
+            TimeFormat::Utc => TimeFormat::Local,
+        }
+    }
+}
+
+impl FromStr for TimeFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "local" => Ok(TimeFormat::Local),
+            "utc" => Ok(TimeFormat::Utc),
+            "relative" => Ok(TimeFormat::Relative),
+            other => bail!("unknown time format: {other} (expected local, utc or relative)"),
+        }
+    }
+}
+
+/// 24-hour or 12-hour clock times (`--clock`).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+pub enum Clock {
+    #[default]
+    #[serde(rename = "24h")]
+    TwentyFour,
+    #[serde(rename = "12h")]
+    Twelve,
+}
+
+impl FromStr for Clock {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "24h" | "24" => Ok(Clock::TwentyFour),
+            "12h" | "12" => Ok(Clock::Twelve),
+            other => bail!("unknown clock: {other} (expected 24h or 12h)"),
+        }
+    }
+}
+
+/// What to run, once the command line and config files are read.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum Command {
+    #[default]
+    Watch,
+    List,
+    Show {
+        swap_id: String,
+    },
+    Open {
+        swap_id: String,
+        tx: Option<String>,
+    },
+    Stats,
+    Export,
+    Tail,
+    Earnings,
+    Rates,
+    Peers,
+    Serve,
+    Agent,
+    History {
+        swap_id: Option<String>,
+    },
+    Note {
+        swap_id: String,
+        text: Vec<String>,
+    },
+    Tag {
+        swap_id: String,
+        tags: Vec<String>,
+    },
+    Untag {
+        swap_id: String,
+        tags: Vec<String>,
+    },
+    Notes,
+    CheckConfig {
+        path: PathBuf,
+    },
+    NotifyTest {
+        channel: Option<String>,
+    },
+}
+
+/// The commands as clap parses them, each with the options it reads.
+#[derive(Debug, Subcommand)]
+pub enum CliCommand {
+    /// Redraw the swap table on every poll (default)
+    Watch(WatchOptions),
+    /// Print the swap table once and exit
+    List {
+        #[command(flatten)]
+        table: TableOptions,
+        #[command(flatten)]
+        tracking: TrackingOptions,
+        #[command(flatten)]
+        chain: ChainOptions,
+        #[command(flatten)]
+        notes: NotesOptions,
+    },
+    /// Full state history for one swap
+    Show {
+        swap_id: String,
+        #[command(flatten)]
+        chain: ChainOptions,
+        #[command(flatten)]
+        asb_log: AsbLogOptions,
+        #[command(flatten)]
+        notes: NotesOptions,
+        #[command(flatten)]
+        explorer: ExplorerOptions,
+    },
+    /// Open a BTC transaction of the swap in the block explorer
+    Open {
+        swap_id: String,
+        /// The latest when unset
+        #[arg(value_parser = PossibleValuesParser::new(TX_NAMES))]
+        tx: Option<String>,
+        #[command(flatten)]
+        explorer: ExplorerOptions,
+    },
+    /// Swap counts per state
+    Stats {
+        #[command(flatten)]
+        range: RangeOptions,
+        #[command(flatten)]
+        chart: ChartOptions,
+    },
+    /// Dump recorded state rows as JSON, CSV or Parquet, or completed swaps
+    /// as accounting trades
+    Export(ExportOptions),
+    /// Print one JSON line per state transition
+    Tail {
+        #[command(flatten)]
+        poll: PollOptions,
+        #[command(flatten)]
+        tracking: TrackingOptions,
+        #[command(flatten)]
+        history: HistoryOptions,
+    },
+    /// Realized spread per day, week or month
+    Earnings {
+        /// Earnings buckets: day, week or month [default: month]
+        #[arg(long)]
+        period: Option<Period>,
+        #[command(flatten)]
+        price: PriceOptions,
+    },
+    /// Recent swaps' rates against the market when they started
+    Rates {
+        #[arg(
+            long,
+            value_name = "N",
+            help = format!("Swaps to compare [default: {DEFAULT_RATES_LIMIT}]")
+        )]
+        limit: Option<usize>,
+        #[command(flatten)]
+        price: PriceOptions,
+    },
+    /// Counterparties and how many swaps each did
+    Peers,
+    /// Read-only JSON API over HTTP
+    Serve(ServeOptions),
+    /// Watch like --daemon and report to a central serve
+    Agent(AgentOptions),
+    /// Transitions recorded in the history database
+    History {
+        swap_id: Option<String>,
+        #[command(flatten)]
+        history: HistoryOptions,
+    },
+    /// Add a note to a swap, shown with its history
+    Note {
+        swap_id: String,
+        /// Joined with spaces, so quoting is optional
+        #[arg(required = true, num_args = 1..)]
+        text: Vec<String>,
+        #[command(flatten)]
+        notes: NotesOptions,
+    },
+    /// Tag a swap, e.g. support-ticket-123 or suspicious
+    Tag {
+        swap_id: String,
+        #[arg(required = true, value_name = "TAG", value_parser = notes::parse_tag)]
+        tags: Vec<String>,
+        #[command(flatten)]
+        notes: NotesOptions,
+    },
+    /// Take tags off a swap
+    Untag {
+        swap_id: String,
+        #[arg(required = true, value_name = "TAG", value_parser = notes::parse_tag)]
+        tags: Vec<String>,
+        #[command(flatten)]
+        notes: NotesOptions,
+    },
+    /// Every swap with notes or tags
+    Notes {
+        /// Only swaps with this tag (repeatable)
+        #[arg(long, value_parser = notes::parse_tag)]
+        tag: Vec<String>,
+        #[command(flatten)]
+        notes: NotesOptions,
+    },
+    /// Look for mistakes in ASB's config.toml before starting it
+    CheckConfig { path: PathBuf },
+    /// Send a test alert through each notifier and report what failed
+    NotifyTest {
+        /// Only this notifier kind, e.g. telegram or discord:ops
+        #[arg(long, value_name = "NAME")]
+        channel: Option<String>,
+        #[command(flatten)]
+        notifiers: NotifierOptions,
+    },
+}
+
+#[derive(Debug)]
+pub struct Args {
+    pub command: Command,
+    pub db_path: Option<PathBuf>,
+    pub network: Network,
+    pub role: Role,
+    /// Several ASBs at once; when empty, `db_path`/`network` is the only one
+    pub instances: Vec<InstanceSpec>,
+    pub interval: Duration,
+    pub adaptive: bool,
+    /// Refresh on database file changes, polling only as a heartbeat
+    pub fs_watch: bool,
+    /// Open databases with sqlite's `immutable` flag
+    pub immutable: bool,
+    /// `user@host` to read the databases from over SSH
+    pub remote: Option<String>,
+    pub color: ColorChoice,
+    pub theme: ThemeName,
+    /// States drawn in a color of their own, first match wins
+    pub state_colors: Vec<(StateSelection, Style)>,
+    pub time: TimeFormat,
+    pub clock: Clock,
+    /// Whole swap ids and timestamps, however wide the terminal
+    pub wide: bool,
+    /// Mask swap ids, peer ids, amounts and txids on screen
+    pub redact: bool,
+    /// Plain ASCII instead of box drawing, marks, arrows and bars
+    pub ascii: bool,
+    pub output: OutputFormat,
+    /// Which swaps `list` and `watch` show; empty shows all
+    pub states: StateSelection,
+    /// Leave finished swaps out of `list` and `watch` unless `states` picks
+    /// them
+    pub hide_finished: bool,
+    /// Start the full-screen view on the board rather than the table
+    pub board: bool,
+    /// `--chart`: swaps started per hour or day, in `stats` and above the
+    /// watch table
+    pub chart: bool,
+    /// How far back the chart goes; per hour up to two days, per day beyond
+    pub chart_window: Duration,
+    /// Latest transitions listed under the full-screen table; 0 hides them
+    pub ticker: usize,
+    /// How long a changed state stays highlighted in the full-screen view,
+    /// bright for the first half and dim for the second
+    pub fade: Duration,
+    /// `--since` and `--until`, for the tables, `stats` and `export`
+    pub range: DateRange,
+    /// `--swap`: part of a swap id the tables are narrowed to
+    pub swap_search: Option<SwapSearch>,
+    /// `--tag`: the tables and `notes` only show swaps with one of these
+    pub tags: Vec<String>,
+    /// The table's order; newest first, or as last picked in the
+    /// full-screen view, when unset
+    pub sort: Option<SortOrder>,
+    /// `--table-columns`: the table's columns in order; the usual ones, or
+    /// as last picked in the full-screen view, when unset
+    pub table_columns: Option<Vec<TableColumn>>,
+    /// `--page-size`: finished swaps read at a time; `watch` defaults to
+    /// [`DEFAULT_PAGE_SIZE`] and `list` to all of them. 0 reads them all.
+    pub page_size: Option<usize>,
+    pub export_format: ExportFormat,
+    /// Export destination; stdout when unset
+    pub file: Option<PathBuf>,
+    /// Export each swap's latest state instead of every recorded row
+    pub latest: bool,
+    /// CSV and Parquet columns; empty means `csv::DEFAULT_COLUMNS`
+    pub columns: Vec<Column>,
+    pub notify: bool,
+    /// Only notify on transitions into these states; empty means all
+    pub notify_states: Vec<String>,
+    pub webhooks: Vec<String>,
+    /// Discord channel webhooks as `(name, URL)`
+    pub discord_webhooks: Vec<(Option<String>, String)>,
+    pub webhook_timeout: Duration,
+    pub telegram_token: Option<String>,
+    pub telegram_chat_id: Option<String>,
+    pub ntfy_topic: Option<String>,
+    /// ntfy.sh unless set
+    pub ntfy_server: Option<String>,
+    pub matrix_homeserver: Option<String>,
+    pub matrix_token: Option<String>,
+    pub matrix_room: Option<String>,
+    pub pushover_token: Option<String>,
+    pub pushover_user: Option<String>,
+    pub gotify_server: Option<String>,
+    pub gotify_token: Option<String>,
+    /// PagerDuty Events API v2 routing key
+    pub pagerduty_key: Option<String>,
+    pub opsgenie_key: Option<String>,
+    /// Opsgenie's API, for EU accounts
+    pub opsgenie_api_url: Option<String>,
+    /// `smtps://host:465` or `smtp://host:587` (STARTTLS)
+    pub smtp_server: Option<String>,
+    pub smtp_user: Option<String>,
+    pub smtp_password: Option<String>,
+    pub email_from: Option<String>,
+    pub email_to: Vec<String>,
+    /// Subject and body templates; the defaults when unset
+    pub email_subject: Option<String>,
+    pub email_body: Option<String>,
+    /// Collect non-critical alerts for this long into one mail
+    pub email_batch: Option<Duration>,
+    pub mqtt_broker: Option<MqttBroker>,
+    /// Topic template; the default when unset
+    pub mqtt_topic: Option<String>,
+    pub mqtt_qos: QoS,
+    pub mqtt_retain: bool,
+    pub mqtt_user: Option<String>,
+    pub mqtt_password: Option<String>,
+    /// Line-protocol write endpoint for metrics
+    pub influx_url: Option<String>,
+    pub influx_token: Option<String>,
+    /// Healthchecks-style ping URL for the monitor's own liveness
+    pub heartbeat_url: Option<String>,
+    /// OTLP/HTTP collector that receives the monitor's own traces
+    pub otlp_endpoint: Option<String>,
+    /// Extra headers for the collector, e.g. an API key
+    pub otlp_headers: Vec<(String, String)>,
+    /// Thresholds for flagging and alerting on swaps that stop moving
+    pub stuck: StuckRules,
+    /// Peer ids whose swaps are highlighted and alerted on
+    pub watch_peers: Vec<String>,
+    /// Swap ids, or starts of them, kept at the top of the table and
+    /// alerted on sooner
+    pub pins: Vec<String>,
+    /// Source of BTC confirmations and block height
+    pub electrum: Option<ElectrumServer>,
+    /// Certificate the Electrum server is pinned to; moved into `electrum`
+    /// once every source is read
+    pub electrum_fingerprint: Option<Fingerprint>,
+    /// Base URL of a mempool or esplora instance for transaction links
+    pub explorer: Option<String>,
+    /// Where ASB registers; shown in the header
+    pub rendezvous_points: Vec<String>,
+    /// How to tell whether ASB is running
+    pub asb: AsbProbe,
+    /// Addresses ASB advertises, checked for reachability
+    pub external_addresses: Vec<Target>,
+    /// Tor's SOCKS proxy, for onion addresses
+    pub tor_socks: String,
+    /// ASB's peer id, to ask for a quote like a taker
+    pub quote_peer: Option<String>,
+    /// The swap CLI, which asks for the quote
+    pub swap_bin: String,
+    /// How far a quote may drift from market × (1 + spread)
+    pub quote_tolerance: f64,
+    /// ASB's log file, shown with a swap's history
+    pub asb_log: Option<PathBuf>,
+    /// What in ASB's log raises an alert
+    pub log_alerts: Vec<LogRule>,
+    /// How often each log alert rule may fire, unless it says otherwise
+    pub log_alert_window: Duration,
+    /// monero-wallet-rpc base URL, for XMR lock confirmations
+    pub monero_rpc: Option<String>,
+    /// `user:password` for monero-wallet-rpc's digest auth
+    pub monero_rpc_login: Option<String>,
+    /// ASB's Bitcoin wallet, watched through Electrum for the balance panel
+    pub btc_wallet: Option<BtcWallet>,
+    /// Show wallet balances in watch mode
+    pub balance: bool,
+    /// Balance thresholds for liquidity alerts
+    pub liquidity: LiquidityRules,
+    /// Show the fee environment
+    pub fees: bool,
+    /// mempool instance for fee rates, mempool.space without one
+    pub mempool_url: Option<String>,
+    /// Next-block fee rate, in sat/vB, that raises a fee spike alert
+    pub max_fee_rate: Option<f64>,
+    pub earnings: EarningsOptions,
+    /// Value swaps and earnings in `earnings.currency`
+    pub fiat: bool,
+    /// Where the XMR/BTC market rate comes from; shown in the header when set
+    pub price_source: Option<PriceSource>,
+    /// How many recent swaps `rates` compares
+    pub limit: usize,
+    /// Address `serve` binds to
+    pub listen: SocketAddr,
+    /// PEM certificate chain and private key `serve` speaks HTTPS with
+    pub tls_cert: Option<PathBuf>,
+    pub tls_key: Option<PathBuf>,
+    /// HTTPS with a certificate made up at startup
+    pub tls_self_signed: bool,
+    /// Bearer token `serve` requires on every request
+    pub api_token: Option<String>,
+    /// Shared secret signing agent reports; enables `/agent/push` in `serve`
+    pub agent_token: Option<String>,
+    /// `serve` without local databases, showing only what agents report
+    pub agents_only: bool,
+    /// Base URL of the `serve` an agent reports to
+    pub central: Option<String>,
+    /// How an agent identifies itself; the host name when unset
+    pub agent_name: Option<String>,
+    /// Log instead of drawing, for running as a service
+    pub daemon: bool,
+    /// `None` picks journald's format under systemd, text otherwise
+    pub log_format: Option<LogFormat>,
+    /// Daemon log destination; stdout when unset
+    pub log_file: Option<PathBuf>,
+    /// Side database recording every observed transition
+    pub history_db: Option<PathBuf>,
+    /// Where notes and tags are kept; `NotesStore::default_path` when unset
+    pub notes_db: Option<PathBuf>,
+    /// How often to send a digest through the notifiers, if at all
+    pub digest: Option<Period>,
+    /// Local time digests go out at
+    pub digest_at: NaiveTime,
+    /// Alert routing from the config file; empty keeps the per-notifier filters
+    pub rules: Vec<Rule>,
+    /// How soon a repeated alert may go out again at the same severity
+    pub alert_cooldown: Option<Duration>,
+    /// Windows in which only critical alerts are delivered
+    pub quiet_hours: Vec<QuietHours>,
+    /// Shell command to run on every transition
+    pub on_transition: Option<String>,
+    /// States that ring the terminal bell in watch mode, or play the sound
+    /// command given with them
+    pub bell: Vec<(StateSelection, Option<String>)>,
+    pub hook_timeout: Duration,
+    /// How many hook commands may run at once
+    pub hook_concurrency: usize,
+}
+
+impl Default for Args {
+    fn default() -> Self {
+        Self {
+            command: Command::default(),
+            db_path: None,
+            network: Network::default(),
+            role: Role::default(),
+            instances: Vec::new(),
+            interval: DEFAULT_INTERVAL,
+            adaptive: false,
+            fs_watch: true,
+            immutable: false,
+            remote: None,
+            color: ColorChoice::default(),
+            theme: ThemeName::default(),
+            state_colors: Vec::new(),
+            time: TimeFormat::default(),
+            clock: Clock::default(),
+            wide: false,
+            redact: false,
+            ascii: false,
+            output: OutputFormat::default(),
+            states: StateSelection::default(),
+            hide_finished: false,
+            board: false,
+            chart: false,
+            chart_window: DEFAULT_CHART_WINDOW,
+            ticker: DEFAULT_TICKER,
+            fade: DEFAULT_FADE,
+            range: DateRange::default(),
+            swap_search: None,
+            tags: Vec::new(),
+            sort: None,
+            table_columns: None,
+            page_size: None,
+            export_format: ExportFormat::default(),
+            file: None,
+            latest: false,
+            columns: Vec::new(),
+            notify: false,
+            notify_states: Vec::new(),
+            webhooks: Vec::new(),
+            discord_webhooks: Vec::new(),
+            webhook_timeout: crate::http::DEFAULT_TIMEOUT,
+            telegram_token: None,
+            telegram_chat_id: None,
+            ntfy_topic: None,
+            ntfy_server: None,
+            matrix_homeserver: None,
+            matrix_token: None,
+            matrix_room: None,
+            pushover_token: None,
+            pushover_user: None,
+            gotify_server: None,
+            gotify_token: None,
+            pagerduty_key: None,
+            opsgenie_key: None,
+            opsgenie_api_url: None,
+            smtp_server: None,
+            smtp_user: None,
+            smtp_password: None,
+            email_from: None,
+            email_to: Vec::new(),
+            email_subject: None,
+            email_body: None,
+            email_batch: None,
+            mqtt_broker: None,
+            mqtt_topic: None,
+            mqtt_qos: QoS::default(),
+            mqtt_retain: false,
+            mqtt_user: None,
+            mqtt_password: None,
+            influx_url: None,
+            influx_token: None,
+            heartbeat_url: None,
+            otlp_endpoint: None,
+            otlp_headers: Vec::new(),
+            stuck: StuckRules::default(),
+            watch_peers: Vec::new(),
+            pins: Vec::new(),
+            electrum: None,
+            electrum_fingerprint: None,
+            explorer: None,
+            rendezvous_points: Vec::new(),
+            asb: AsbProbe::default(),
+            external_addresses: Vec::new(),
+            tor_socks: DEFAULT_TOR_SOCKS.to_string(),
+            quote_peer: None,
+            swap_bin: DEFAULT_SWAP_BIN.to_string(),
+            quote_tolerance: DEFAULT_TOLERANCE,
+            asb_log: None,
+            log_alerts: Vec::new(),
+            log_alert_window: DEFAULT_WINDOW,
+            monero_rpc: None,
+            monero_rpc_login: None,
+            btc_wallet: None,
+            balance: false,
+            liquidity: LiquidityRules::default(),
+            fees: false,
+            mempool_url: None,
+            max_fee_rate: None,
+            earnings: EarningsOptions::default(),
+            fiat: false,
+            price_source: None,
+            limit: DEFAULT_RATES_LIMIT,
+            listen: DEFAULT_LISTEN,
+            tls_cert: None,
+            tls_key: None,
+            tls_self_signed: false,
+            api_token: None,
+            agent_token: None,
+            agents_only: false,
+            central: None,
+            agent_name: None,
+            daemon: false,
+            log_format: None,
+            log_file: None,
+            history_db: None,
+            notes_db: None,
+            digest: None,
+            digest_at: DEFAULT_DIGEST_AT,
+            rules: Vec::new(),
+            alert_cooldown: None,
+            quiet_hours: Vec::new(),
+            on_transition: None,
+            bell: Vec::new(),
+            hook_timeout: DEFAULT_HOOK_TIMEOUT,
+            hook_concurrency: DEFAULT_HOOK_CONCURRENCY,
+        }
+    }
+}
+
+pub const DB_PATH_ENV: &str = "WRAITHSWAP_DB_PATH";
+pub const TELEGRAM_TOKEN_ENV: &str = "WRAITHSWAP_TELEGRAM_TOKEN";
+pub const TELEGRAM_CHAT_ID_ENV: &str = "WRAITHSWAP_TELEGRAM_CHAT_ID";
+pub const NTFY_TOPIC_ENV: &str = "WRAITHSWAP_NTFY_TOPIC";
+pub const MATRIX_TOKEN_ENV: &str = "WRAITHSWAP_MATRIX_TOKEN";
+pub const PUSHOVER_TOKEN_ENV: &str = "WRAITHSWAP_PUSHOVER_TOKEN";
+pub const GOTIFY_TOKEN_ENV: &str = "WRAITHSWAP_GOTIFY_TOKEN";
+pub const SMTP_PASSWORD_ENV: &str = "WRAITHSWAP_SMTP_PASSWORD";
+pub const MQTT_PASSWORD_ENV: &str = "WRAITHSWAP_MQTT_PASSWORD";
+pub const INFLUX_TOKEN_ENV: &str = "WRAITHSWAP_INFLUX_TOKEN";
+pub const PAGERDUTY_KEY_ENV: &str = "WRAITHSWAP_PAGERDUTY_KEY";
+pub const OPSGENIE_KEY_ENV: &str = "WRAITHSWAP_OPSGENIE_KEY";
+pub const MONERO_RPC_LOGIN_ENV: &str = "WRAITHSWAP_MONERO_RPC_LOGIN";
+pub const BTC_WALLET_ENV: &str = "WRAITHSWAP_BTC_WALLET";
+pub const API_TOKEN_ENV: &str = "WRAITHSWAP_API_TOKEN";
+pub const AGENT_TOKEN_ENV: &str = "WRAITHSWAP_AGENT_TOKEN";
+
+pub const DEFAULT_INTERVAL: Duration = Duration::from_secs(2);
+
+pub const DEFAULT_DIGEST_AT: NaiveTime = match NaiveTime::from_hms_opt(8, 0, 0) {
+    Some(time) => time,
+    None => unreachable!(),
+};
+
+pub const DEFAULT_LISTEN: SocketAddr = SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 8080);
+
+pub const DEFAULT_RATES_LIMIT: usize = 20;
+
+pub const DEFAULT_PAGE_SIZE: usize = 500;
+
+pub const DEFAULT_CHART_WINDOW: Duration = Duration::from_secs(14 * 86_400);
+
+pub const DEFAULT_TICKER: usize = 5;
+
+pub const DEFAULT_FADE: Duration = Duration::from_secs(60);
+
+/// Watch an eigenwallet ASB's swaps, with alerts.
+#[derive(Debug, Parser)]
+#[command(
+    name = "wraithswap-poc",
+    version,
+    args_override_self = true,
+    after_help = "Every option can also be set in the config file; options given here win \
+                  over environment variables, which win over the file."
+)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<CliCommand>,
+    /// Watch runs without a command, so its options go here too
+    #[command(flatten)]
+    pub watch: WatchOptions,
+    #[command(flatten)]
+    pub global: GlobalOptions,
+}
+
+/// Flags, then environment variables, then the config file, then ASB's own
+/// config: each fills in what the ones before it left unset.
+pub fn parse_args() -> Result<Args> {
+    let matches = Cli::command().get_matches();
+    let cli = Cli::from_matches(&matches).unwrap_or_else(|err| err.exit());
+
+    let mut args = Args::default();
+    let config = config::load(cli.global.config.as_deref())?;
+    // ASB's own config goes underneath the monitor's
+    let asb_config = cli
+        .global
+        .asb_config
+        .clone()
+        .or_else(|| config.asb_config.clone().map(config::expand_home));
+    if let Some(path) = asb_config {
+        AsbConfig::load(&path)?.apply(&mut args)?;
+    }
+    config.apply(&mut args)?;
+
+    // Secrets without a flag, so they stay out of shell history and `ps`
+    if let Some(password) = std::env::var(SMTP_PASSWORD_ENV)
+        .ok()
+        .filter(|v| !v.is_empty())
+    {
+        args.smtp_password = Some(password);
+    }
+    if let Some(password) = std::env::var(MQTT_PASSWORD_ENV)
+        .ok()
+        .filter(|v| !v.is_empty())
+    {
+        args.mqtt_password = Some(password);
+    }
+    if let Some(token) = std::env::var(INFLUX_TOKEN_ENV)
+        .ok()
+        .filter(|v| !v.is_empty())
+    {
+        args.influx_token = Some(token);
+    }
+    if let Ok(headers) = std::env::var(otel::HEADERS_ENV) {
+        let headers = otel::parse_headers(&headers)
+            .with_context(|| format!("invalid {}", otel::HEADERS_ENV))?;
+        args.otlp_headers.extend(headers);
+    }
+
+    // Naming a single database or network on the command line replaces the
+    // config file's instances, as does --instance
+    if matches.value_source("db_path") == Some(ValueSource::CommandLine)
+        || cli.global.mainnet
+        || cli.global.testnet
+    {
+        args.instances.clear();
+    }
+    cli.apply(&mut args)?;
+
+    if let Command::Note { ref text, .. } = args.command {
+        if text.iter().all(|word| word.trim().is_empty()) {
+            bail!("note requires some text: wraithswap-poc note <SWAP_ID> <TEXT>");
+        }
+    }
+
+    if args.telegram_token.is_some() != args.telegram_chat_id.is_some() {
+        bail!("Telegram alerts need both a bot token and a chat id");
+    }
+    if args.opsgenie_api_url.is_some() && args.opsgenie_key.is_none() {
+        bail!("--opsgenie-api-url needs an API key (--opsgenie-key)");
+    }
+    if let Some(ref server) = args.smtp_server {
+        if !server.starts_with("smtps://") && !server.starts_with("smtp://") {
+            bail!("--smtp-server must be an smtps:// or smtp:// URL, got {server:?}");
+        }
+        if args.email_from.is_none() || args.email_to.is_empty() {
+            bail!("email alerts need a sender (--email-from) and a recipient (--email-to)");
+        }
+        for address in args.email_from.iter().chain(&args.email_to) {
+            mail::mailbox(address)?;
+        }
+    } else if args.email_from.is_some() || !args.email_to.is_empty() {
+        bail!("email alerts need an SMTP server (--smtp-server)");
+    }
+    if args.smtp_password.is_some() && args.smtp_user.is_none() {
+        bail!("an SMTP password needs a user name (--smtp-user)");
+    }
+    if args.mqtt_password.is_some() && args.mqtt_user.is_none() {
+        bail!("an MQTT password needs a user name (--mqtt-user)");
+    }
+    if let Some(ref topic) = args.mqtt_topic {
+        if topic.is_empty() || topic.contains(['+', '#']) {
+            bail!("--mqtt-topic must be a topic without wildcards, got {topic:?}");
+        }
+    }
+    if let Some(ref url) = args.influx_url {
+        if !url.starts_with("http://") && !url.starts_with("https://") {
+            bail!("--influx-url must be an http:// or https:// URL, got {url:?}");
+        }
+    } else if args.influx_token.is_some() {
+        bail!("an InfluxDB token needs a write URL (--influx-url)");
+    }
+    if let Some(ref url) = args.heartbeat_url {
+        if !url.starts_with("http://") && !url.starts_with("https://") {
+            bail!("--heartbeat-url must be an http:// or https:// URL, got {url:?}");
+        }
+    }
+    if let Some(ref endpoint) = args.otlp_endpoint {
+        if !endpoint.starts_with("http://") && !endpoint.starts_with("https://") {
+            bail!("--otlp-endpoint must be an http:// or https:// URL, got {endpoint:?}");
+        }
+    } else if !args.otlp_headers.is_empty() {
+        bail!("--otlp-header needs a collector (--otlp-endpoint)");
+    }
+    if args.pushover_token.is_some() != args.pushover_user.is_some() {
+        bail!("Pushover alerts need both an application token and a user key");
+    }
+    if args.gotify_server.is_some() != args.gotify_token.is_some() {
+        bail!("Gotify alerts need both a server and an application token");
+    }
+    let matrix = [
+        args.matrix_homeserver.is_some(),
+        args.matrix_token.is_some(),
+        args.matrix_room.is_some(),
+    ];
+    if matrix.contains(&true) && matrix.contains(&false) {
+        bail!("Matrix alerts need a homeserver, an access token and a room id");
+    }
+    let mut discord_names: Vec<&str> = args
+        .discord_webhooks
+        .iter()
+        .filter_map(|(name, _)| name.as_deref())
+        .collect();
+    discord_names.sort_unstable();
+    if let Some(pair) = discord_names.windows(2).find(|pair| pair[0] == pair[1]) {
+        bail!("two Discord webhooks are named {:?}", pair[0]);
+    }
+    if args.ntfy_server.is_some() && args.ntfy_topic.is_none() {
+        bail!("--ntfy-server needs a topic (--ntfy-topic)");
+    }
+    if let Some(fingerprint) = args.electrum_fingerprint {
+        match args.electrum {
+            Some(ref mut server) if server.tls => server.fingerprint = Some(fingerprint),
+            Some(_) => bail!("--electrum-fingerprint needs an ssl:// Electrum server"),
+            None => bail!("--electrum-fingerprint needs an Electrum server (--electrum)"),
+        }
+    }
+    if args.btc_wallet.is_some() && args.electrum.is_none() {
+        bail!("--btc-wallet needs an Electrum server (--electrum)");
+    }
+    if args.balance && args.btc_wallet.is_none() && args.monero_rpc.is_none() {
+        bail!("--balance needs --btc-wallet or --monero-rpc");
+    }
+    if args.liquidity.min_xmr.is_some() && args.monero_rpc.is_none() {
+        bail!("--min-xmr needs monero-wallet-rpc (--monero-rpc)");
+    }
+    if args.liquidity.max_btc.is_some() && args.btc_wallet.is_none() {
+        bail!("--max-btc needs the BTC wallet (--btc-wallet)");
+    }
+    if matches!(args.command, Command::History { .. }) && args.history_db.is_none() {
+        bail!("the history command needs a history database (--history-db)");
+    }
+    if args.digest.is_some() && args.history_db.is_none() {
+        bail!("--digest needs a history database (--history-db)");
+    }
+    if !args.quiet_hours.is_empty() && args.digest.is_none() {
+        bail!("--quiet-hours needs a digest (--digest) to deliver the alerts it holds back");
+    }
+    if args.daemon && args.command != Command::Watch {
+        bail!("--daemon only applies to watch mode");
+    }
+    if args.output == OutputFormat::Nagios && args.command != Command::List {
+        bail!("--output nagios only applies to the list command (or --once)");
+    }
+    if args
+        .api_token
+        .as_deref()
+        .is_some_and(|token| token.trim().is_empty())
+    {
+        bail!("--api-token must not be empty");
+    }
+    if args
+        .agent_token
+        .as_deref()
+        .is_some_and(|token| token.trim().is_empty())
+    {
+        bail!("--agent-token must not be empty");
+    }
+    match (&args.tls_cert, &args.tls_key) {
+        (Some(_), None) => bail!("--tls-cert needs the certificate's private key (--tls-key)"),
+        (None, Some(_)) => bail!("--tls-key needs a certificate to go with it (--tls-cert)"),
+        (Some(_), Some(_)) if args.tls_self_signed => {
+            bail!("--tls-self-signed and --tls-cert both pick a certificate; use one")
+        }
+        _ => {}
+    }
+    if args.command == Command::Serve && args.agents_only && args.agent_token.is_none() {
+        bail!("--agents-only needs agents to report to it (--agent-token)");
+    }
+    if args.command == Command::Agent {
+        match args.central {
+            Some(ref url) if !url.starts_with("http://") && !url.starts_with("https://") => {
+                bail!("--central must be an http:// or https:// URL, got {url:?}")
+            }
+            Some(_) => {}
+            None => bail!("the agent command needs the central serve's URL (--central)"),
+        }
+        if args.agent_token.is_none() {
+            bail!("the agent command needs the token the central serve expects (--agent-token)");
+        }
+        if args.agent_name.is_none() {
+            args.agent_name = agent::hostname();
+        }
+        match args.agent_name.as_deref() {
+            None | Some("") => bail!("no host name to go by; name the agent (--agent-name)"),
+            Some(name) if name.contains('/') => {
+                bail!("--agent-name must not contain '/', got {name:?}")
+            }
+            Some(_) => {}
+        }
+    }
+    if let Some(id) = args
+        .watch_peers
+        .iter()
+        .find(|id| id.is_empty() || id.contains(char::is_whitespace))
+    {
+        bail!("--watch-peer needs a peer id like 12D3KooW..., got {id:?}");
+    }
+    if let Some(ref url) = args.explorer {
+        if !url.starts_with("http://") && !url.starts_with("https://") {
+            bail!("--explorer must be an http:// or https:// URL, got {url:?}");
+        }
+    }
+    if let Some(ref url) = args.mempool_url {
+        if !url.starts_with("http://") && !url.starts_with("https://") {
+            bail!("--mempool-url must be an http:// or https:// URL, got {url:?}");
+        }
+    }
+    if args.asb.process.is_some() && !cfg!(target_os = "linux") {
+        bail!("--asb-process looks through /proc, which only Linux has; use --asb-pid-file");
+    }
+    if let Some(ref address) = args.asb.address {
+        if !is_host_port(address) {
+            bail!("--asb-address must be host:port, got {address:?}");
+        }
+    }
+    if let Some(ref peer) = args.quote_peer {
+        if peer.is_empty() || peer.contains('/') {
+            bail!("--quote-peer must be a peer id like 12D3KooW..., got {peer:?}");
+        }
+        if args.rendezvous_points.is_empty() {
+            bail!("--quote-peer needs a rendezvous point to find ASB at: --rendezvous-point or --asb-config");
+        }
+    }
+    if !args.log_alerts.is_empty() && args.asb_log.is_none() {
+        bail!("log alerts need ASB's log file: --asb-log, or --asb-config when ASB logs to one");
+    }
+    if !is_host_port(&args.tor_socks) {
+        bail!("--tor-socks must be host:port, got {:?}", args.tor_socks);
+    }
+    if args
+        .max_fee_rate
+        .is_some_and(|rate| !rate.is_finite() || rate <= 0.0)
+    {
+        bail!("--max-fee-rate must be a positive rate in sat/vB");
+    }
+    if args.limit == 0 {
+        bail!("--limit must be at least 1");
+    }
+    if args.chart_window < Duration::from_secs(3600) {
+        bail!("--chart-window must be at least an hour");
+    }
+    if !args.columns.is_empty()
+        && matches!(
+            args.export_format,
+            ExportFormat::Json | ExportFormat::Accounting
+        )
+    {
+        bail!("--columns only applies to --format csv or parquet");
+    }
+    if let DateRange {
+        since: Some(since),
+        until: Some(until),
+    } = args.range
+    {
+        let now = Utc::now();
+        if since.resolve(now) >= until.resolve(now) {
+            bail!("--since {since} is not before --until {until}");
+        }
+    }
+
+    Ok(args)
+}
+
+impl Cli {
+    /// Watch's options go before no command at all, as watch runs without
+    /// one; before any other command they would go unread, so they're
+    /// refused instead.
+    fn from_matches(matches: &ArgMatches) -> Result<Self, clap::Error> {
+        if let Some(name) = matches.subcommand_name() {
+            let watch = <WatchOptions as clap::Args>::augment_args(clap::Command::new("watch"));
+            let misplaced = watch.get_arguments().find(|arg| {
+                matches.value_source(arg.get_id().as_str()) == Some(ValueSource::CommandLine)
+            });
+            if let Some(arg) = misplaced {
+                let flag = arg.get_long().unwrap_or_default();
+                return Err(Cli::command().error(
+                    ErrorKind::ArgumentConflict,
+                    format!(
+                        "--{flag} goes after the command; put it after `{name}` if {name} takes it"
+                    ),
+                ));
+            }
+        }
+        Self::from_arg_matches(matches)
+    }
+
+    /// Lay the command line over `args`: options given replace what the
+    /// config file set, and repeatable ones add to it where the config file
+    /// can list them too.
+    fn apply(self, args: &mut Args) -> Result<()> {
+        self.global.apply(args)?;
+        let command = self.command.unwrap_or(CliCommand::Watch(self.watch));
+        args.command = command.apply(args)?;
+        Ok(())
+    }
+}
+
+impl CliCommand {
+    fn apply(self, args: &mut Args) -> Result<Command> {
+        let command = match self {
+            CliCommand::Watch(options) => {
+                let once = options.once;
+                options.apply(args)?;
+                match once {
+                    true => Command::List,
+                    false => Command::Watch,
+                }
+            }
+            CliCommand::List {
+                table,
+                tracking,
+                chain,
+                notes,
+            } => {
+                table.apply(args)?;
+                tracking.apply(args)?;
+                chain.apply(args)?;
+                notes.apply(args);
+                Command::List
+            }
+            CliCommand::Show {
+                swap_id,
+                chain,
+                asb_log,
+                notes,
+                explorer,
+            } => {
+                chain.apply(args)?;
+                asb_log.apply(args);
+                notes.apply(args);
+                explorer.apply(args);
+                Command::Show { swap_id }
+            }
+            CliCommand::Open {
+                swap_id,
+                tx,
+                explorer,
+            } => {
+                explorer.apply(args);
+                Command::Open { swap_id, tx }
+            }
+            CliCommand::Stats { range, chart } => {
+                range.apply(args);
+                chart.apply(args);
+                Command::Stats
+            }
+            CliCommand::Export(options) => {
+                options.apply(args);
+                Command::Export
+            }
+            CliCommand::Tail {
+                poll,
+                tracking,
+                history,
+            } => {
+                poll.apply(args);
+                tracking.apply(args)?;
+                history.apply(args);
+                Command::Tail
+            }
+            CliCommand::Earnings { period, price } => {
+                if let Some(period) = period {
+                    args.earnings.period = period;
+                }
+                price.apply(args);
+                Command::Earnings
+            }
+            CliCommand::Rates { limit, price } => {
+                if let Some(limit) = limit {
+                    args.limit = limit;
+                }
+                price.apply(args);
+                Command::Rates
+            }
+            CliCommand::Peers => Command::Peers,
+            CliCommand::Serve(options) => {
+                options.apply(args)?;
+                Command::Serve
+            }
+            CliCommand::Agent(options) => {
+                options.apply(args)?;
+                Command::Agent
+            }
+            CliCommand::History { swap_id, history } => {
+                history.apply(args);
+                Command::History { swap_id }
+            }
+            CliCommand::Note {
+                swap_id,
+                text,
+                notes,
+            } => {
+                notes.apply(args);
+                Command::Note { swap_id, text }
+            }
+            CliCommand::Tag {
+                swap_id,
+                tags,
+                notes,
+            } => {
+                notes.apply(args);
+                Command::Tag { swap_id, tags }
+            }
+            CliCommand::Untag {
+                swap_id,
+                tags,
+                notes,
+            } => {
+                notes.apply(args);
+                Command::Untag { swap_id, tags }
+            }
+            CliCommand::Notes { tag, notes } => {
+                if !tag.is_empty() {
+                    args.tags = tag;
+                }
+                notes.apply(args);
+                Command::Notes
+            }
+            CliCommand::CheckConfig { path } => Command::CheckConfig { path },
+            CliCommand::NotifyTest { channel, notifiers } => {
+                notifiers.apply(args);
+                Command::NotifyTest { channel }
+            }
+        };
+        Ok(command)
+    }
+}
+
+/// Options every command reads: where the swaps are and how they are shown.
+#[derive(Debug, clap::Args)]
+#[command(next_help_heading = "Global options")]
+pub struct GlobalOptions {
+    /// Config file [default: ~/.config/wraithswap/config.toml]
+    #[arg(long, global = true, value_name = "PATH")]
+    pub config: Option<PathBuf>,
+    /// ASB's config.toml, or its directory: database, network, Electrum,
+    /// monero-wallet-rpc, spread and listen port come from there
+    #[arg(long, global = true, value_name = "PATH")]
+    pub asb_config: Option<PathBuf>,
+    /// Path to the ASB sqlite database
+    #[arg(long, global = true, value_name = "PATH", env = DB_PATH_ENV)]
+    pub db_path: Option<PathBuf>,
+    /// maker (ASB) or taker (swap CLI) [default: maker]
+    #[arg(long, global = true)]
+    pub role: Option<Role>,
+    /// Monitor several ASBs at once (repeatable)
+    #[arg(long, global = true, value_name = "NETWORK[:PATH]")]
+    pub instance: Vec<InstanceSpec>,
+    /// Monitor the testnet ASB (default); XMR stagenet pairs with BTC
+    /// testnet
+    #[arg(
+        long,
+        global = true,
+        visible_alias = "stagenet",
+        conflicts_with = "mainnet"
+    )]
+    pub testnet: bool,
+    /// Monitor the mainnet ASB
+    #[arg(long, global = true)]
+    pub mainnet: bool,
+    /// Open the database without locks or -wal/-shm files, for read-only
+    /// mounts and backups (ignores an unmerged WAL)
+    #[arg(long, global = true)]
+    pub immutable: bool,
+    /// Read the database over SSH; --db-path is then remote
+    #[arg(long, global = true, value_name = "USER@HOST")]
+    pub remote: Option<String>,
+    /// auto, always or never [default: auto]
+    #[arg(long, global = true, value_name = "WHEN")]
+    pub color: Option<ColorChoice>,
+    /// default, high-contrast, colorblind-safe or monochrome [default:
+    /// default; monochrome with NO_COLOR set]
+    #[arg(long, global = true)]
+    pub theme: Option<ThemeName>,
+    /// Draw these states in a color of their own, like
+    /// BtcPunished='bold white on red' (repeatable)
+    #[arg(long, global = true, value_name = "STATES=STYLE", value_parser = theme::parse_state_color)]
+    pub state_color: Vec<(StateSelection, Style)>,
+    /// Timestamps in local, utc or relative time [default: local]
+    #[arg(long, global = true, value_name = "FORMAT")]
+    pub time: Option<TimeFormat>,
+    /// 24h or 12h clock times [default: 24h]
+    #[arg(long, global = true)]
+    pub clock: Option<Clock>,
+    /// Whole swap ids and timestamps, however wide the terminal
+    #[arg(long, global = true)]
+    pub wide: bool,
+    /// Mask swap ids, peer ids, amounts and txids on screen, for
+    /// screenshots and screen sharing
+    #[arg(long, global = true)]
+    pub redact: bool,
+    /// Plain ASCII instead of box drawing, check marks and bars
+    #[arg(long, global = true)]
+    pub ascii: bool,
+    /// Export polls, queries and deliveries as OpenTelemetry traces to this
+    /// OTLP/HTTP collector
+    #[arg(long, global = true, value_name = "URL", env = otel::ENDPOINT_ENV)]
+    pub otlp_endpoint: Option<String>,
+    #[arg(
+        long,
+        global = true,
+        value_name = "KEY=VALUE",
+        help = format!("Header for the collector, repeatable [env: {}]", otel::HEADERS_ENV)
+    )]
+    pub otlp_header: Vec<String>,
+    /// table, json or nagios (list only) [default: table]
+    #[arg(long, global = true, value_name = "FORMAT")]
+    pub output: Option<OutputFormat>,
+}
+
+/// How often the database is read.
+#[derive(Debug, clap::Args)]
+#[command(next_help_heading = "Polling")]
+pub struct PollOptions {
+    #[arg(
+        long,
+        value_name = "SECS",
+        value_parser = parse_interval,
+        help = format!("Seconds between polls [default: {}]", DEFAULT_INTERVAL.as_secs())
+    )]
+    pub interval: Option<Duration>,
+    /// Back off while idle, return to --interval on change
+    #[arg(long)]
+    pub adaptive: bool,
+    /// Poll every --interval instead of waiting for file changes
+    #[arg(long)]
+    pub no_fs_watch: bool,
+}
+
+/// Swaps and peers to keep an eye on.
+#[derive(Debug, clap::Args)]
+#[command(next_help_heading = "Tracking")]
+pub struct TrackingOptions {
+    /// Flag and alert on swaps idle this long, e.g. 2h or EncSigSent=30m
+    /// (repeatable)
+    #[arg(long, value_name = "[STATE=]DURATION", value_parser = parse_stuck_after)]
+    pub stuck_after: Vec<(Option<String>, Duration)>,
+    /// Highlight this peer's swaps and alert when it starts a new one
+    /// (repeatable)
+    #[arg(long, value_name = "PEER_ID")]
+    pub watch_peer: Vec<String>,
+    /// Keep this swap at the top of the table and alert on every step and
+    /// sooner when it stalls (repeatable)
+    #[arg(long, value_name = "SWAP_ID", value_parser = parse_pin)]
+    pub pin: Vec<String>,
+}
+
+/// Market prices and the fiat columns.
+#[derive(Debug, clap::Args)]
+#[command(next_help_heading = "Prices")]
+pub struct PriceOptions {
+    /// XMR/BTC market rate from kraken, binance or a price server URL, shown
+    /// in the header [default for rates: kraken]
+    #[arg(long, value_name = "SOURCE")]
+    pub price_source: Option<PriceSource>,
+    /// ASB ask_spread, e.g. 0.02 or 2% [default: 0.02]
+    #[arg(long, value_parser = parse_spread)]
+    pub spread: Option<f64>,
+    /// Value swaps and earnings in --currency, at the BTC price from
+    /// --price-source [default: kraken]
+    #[arg(long)]
+    pub fiat: bool,
+    /// BTC price for the fiat columns, instead of looking it up
+    #[arg(long, value_parser = parse_price)]
+    pub btc_price: Option<f64>,
+    /// Fiat currency [default: USD]
+    #[arg(long, value_name = "CODE")]
+    pub currency: Option<String>,
+}
+
+/// Chain backends and ASB probes for the columns and alerts that need them.
+#[derive(Debug, clap::Args)]
+#[command(next_help_heading = "Chain and ASB")]
+pub struct ChainOptions {
+    /// Show ASB as running only while the pid in PATH is alive; alerts when
+    /// it stops with swaps in flight
+    #[arg(long, value_name = "PATH")]
+    pub asb_pid_file: Option<PathBuf>,
+    /// Same, by looking for a process called NAME (Linux only)
+    #[arg(long, value_name = "NAME")]
+    pub asb_process: Option<String>,
+    /// Same, by connecting to the port ASB accepts peers on
+    #[arg(long, value_name = "HOST:PORT")]
+    pub asb_address: Option<String>,
+    /// Check that takers can connect to this host:port, onion address or
+    /// multiaddress, and alert when not (repeatable)
+    #[arg(long, value_name = "ADDR")]
+    pub external_address: Vec<Target>,
+    #[arg(
+        long,
+        value_name = "HOST:PORT",
+        help = format!("Tor SOCKS proxy for onion addresses [default: {DEFAULT_TOR_SOCKS}]")
+    )]
+    pub tor_socks: Option<String>,
+    /// Where ASB registers (repeatable) [default: from --asb-config]
+    #[arg(long, value_name = "MULTIADDR")]
+    pub rendezvous_point: Vec<String>,
+    /// Ask ASB for a quote like a taker every 10 minutes, through the swap
+    /// CLI, and alert when none comes back
+    #[arg(long, value_name = "PEER_ID")]
+    pub quote_peer: Option<String>,
+    #[arg(
+        long,
+        value_name = "PATH",
+        help = format!("The swap CLI [default: {DEFAULT_SWAP_BIN}]")
+    )]
+    pub swap_bin: Option<String>,
+    /// Alert when the quote is this far from market x (1 + spread)
+    /// [default: 2%]
+    #[arg(long, value_name = "SPREAD", value_parser = parse_spread)]
+    pub quote_tolerance: Option<f64>,
+    /// Electrum server for timelock countdowns, e.g.
+    /// ssl://electrum.blockstream.info:60002
+    #[arg(long, value_name = "URL")]
+    pub electrum: Option<ElectrumServer>,
+    /// SHA-256 fingerprint of a self-signed Electrum server's certificate,
+    /// trusted instead of a CA
+    #[arg(long, value_name = "SHA256")]
+    pub electrum_fingerprint: Option<Fingerprint>,
+    /// monero-wallet-rpc for XMR lock confirmations, e.g.
+    /// http://127.0.0.1:18083
+    #[arg(long, value_name = "URL")]
+    pub monero_rpc: Option<String>,
+    /// monero-wallet-rpc --rpc-login
+    #[arg(
+        long,
+        value_name = "USER:PASSWORD",
+        env = MONERO_RPC_LOGIN_ENV,
+        hide_env_values = true
+    )]
+    pub monero_rpc_login: Option<String>,
+    /// ASB's BTC wallet, watch-only
+    #[arg(
+        long,
+        value_name = "XPUB|DESCRIPTOR",
+        env = BTC_WALLET_ENV,
+        hide_env_values = true
+    )]
+    pub btc_wallet: Option<String>,
+    /// Show BTC and XMR wallet balances in watch mode
+    #[arg(long)]
+    pub balance: bool,
+    /// Alert when unlocked XMR drops below this
+    #[arg(long, value_name = "XMR", value_parser = parse_xmr)]
+    pub min_xmr: Option<u64>,
+    /// Alert when the BTC wallet grows beyond this
+    #[arg(long, value_name = "BTC", value_parser = parse_btc)]
+    pub max_btc: Option<u64>,
+    /// Show fee rates and mempool depth in the header
+    #[arg(long)]
+    pub fees: bool,
+    #[arg(
+        long,
+        value_name = "URL",
+        help = format!(
+            "mempool instance for --fees, e.g. a self-hosted one [default: {DEFAULT_EXPLORER}]"
+        )
+    )]
+    pub mempool_url: Option<String>,
+    /// Alert when the next-block fee rate goes above this
+    #[arg(long, value_name = "SAT/VB")]
+    pub max_fee_rate: Option<f64>,
+    #[command(flatten)]
+    pub price: PriceOptions,
+}
+
+/// ASB's log, for swap histories and log alerts.
+#[derive(Debug, clap::Args)]
+#[command(next_help_heading = "ASB log")]
+pub struct AsbLogOptions {
+    /// ASB's log file, to show a swap's log lines with its history
+    #[arg(long, value_name = "PATH")]
+    pub asb_log: Option<PathBuf>,
+    /// Alert when a new line in ASB's log contains this, ignoring case
+    /// (repeatable; patterns go in the config file)
+    #[arg(long, value_name = "KEYWORD")]
+    pub log_alert: Vec<String>,
+    /// Alert at most once per window for each keyword [default: 1h]
+    #[arg(long, value_name = "DURATION", value_parser = parse_duration)]
+    pub log_alert_window: Option<Duration>,
+}
+
+/// Where alerts go.
+#[derive(Debug, clap::Args)]
+#[command(next_help_heading = "Notifiers")]
+pub struct NotifierOptions {
+    /// Desktop notification on each state transition
+    #[arg(long)]
+    pub notify: bool,
+    /// Only notify for these states (comma-separated)
+    #[arg(
+        long,
+        value_name = "S",
+        value_delimiter = ',',
+        value_parser = list_entry
+    )]
+    pub notify_states: Vec<String>,
+    /// POST each transition as JSON to URL (repeatable)
+    #[arg(long, value_name = "URL")]
+    pub webhook: Vec<String>,
+    /// Post alerts to a Discord channel webhook (repeatable); rules can pick
+    /// it by NAME as discord:NAME
+    #[arg(long, value_name = "[NAME=]URL", value_parser = parse_discord_webhook)]
+    pub discord_webhook: Vec<(Option<String>, String)>,
+    /// Per-request webhook timeout [default: 10]
+    #[arg(long, value_name = "SECS")]
+    pub webhook_timeout: Option<u64>,
+    /// Telegram bot token
+    #[arg(long, value_name = "TOKEN", env = TELEGRAM_TOKEN_ENV, hide_env_values = true)]
+    pub telegram_token: Option<String>,
+    /// Telegram chat to alert
+    #[arg(long, value_name = "ID", env = TELEGRAM_CHAT_ID_ENV)]
+    pub telegram_chat_id: Option<String>,
+    /// Push alerts to an ntfy topic
+    #[arg(long, value_name = "TOPIC", env = NTFY_TOPIC_ENV, hide_env_values = true)]
+    pub ntfy_topic: Option<String>,
+    #[arg(
+        long,
+        value_name = "URL",
+        help = format!("ntfy server [default: {NTFY_DEFAULT_SERVER}]")
+    )]
+    pub ntfy_server: Option<String>,
+    /// Matrix homeserver to post alerts through
+    #[arg(long, value_name = "URL")]
+    pub matrix_homeserver: Option<String>,
+    /// Access token of the posting user
+    #[arg(long, value_name = "TOKEN", env = MATRIX_TOKEN_ENV, hide_env_values = true)]
+    pub matrix_token: Option<String>,
+    /// Room to post alerts to, e.g. !abc:example.org
+    #[arg(long, value_name = "ID")]
+    pub matrix_room: Option<String>,
+    /// Pushover application token
+    #[arg(long, value_name = "TOKEN", env = PUSHOVER_TOKEN_ENV, hide_env_values = true)]
+    pub pushover_token: Option<String>,
+    /// Pushover user or group key to alert
+    #[arg(long, value_name = "KEY")]
+    pub pushover_user: Option<String>,
+    /// Gotify server to push alerts through
+    #[arg(long, value_name = "URL")]
+    pub gotify_server: Option<String>,
+    /// Gotify application token
+    #[arg(long, value_name = "TOKEN", env = GOTIFY_TOKEN_ENV, hide_env_values = true)]
+    pub gotify_token: Option<String>,
+    /// Open PagerDuty incidents for critical alerts, via this Events API v2
+    /// routing key
+    #[arg(long, value_name = "KEY", env = PAGERDUTY_KEY_ENV, hide_env_values = true)]
+    pub pagerduty_key: Option<String>,
+    /// Open Opsgenie alerts for critical alerts
+    #[arg(long, value_name = "KEY", env = OPSGENIE_KEY_ENV, hide_env_values = true)]
+    pub opsgenie_key: Option<String>,
+    #[arg(
+        long,
+        value_name = "URL",
+        help = format!("Opsgenie API [default: {OPSGENIE_DEFAULT_API_URL}]")
+    )]
+    pub opsgenie_api_url: Option<String>,
+    /// Mail alerts through smtps://HOST:465 or smtp://HOST:587
+    #[arg(long, value_name = "URL")]
+    pub smtp_server: Option<String>,
+    #[arg(
+        long,
+        value_name = "USER",
+        help = format!("SMTP login; password from {SMTP_PASSWORD_ENV}")
+    )]
+    pub smtp_user: Option<String>,
+    /// Sender address of alert mails
+    #[arg(long, value_name = "ADDR")]
+    pub email_from: Option<String>,
+    /// Recipient of alert mails (repeatable)
+    #[arg(long, value_name = "ADDR")]
+    pub email_to: Vec<String>,
+    /// Mail non-critical alerts together, at most once per DURATION
+    #[arg(long, value_name = "DURATION", value_parser = parse_duration)]
+    pub email_batch: Option<Duration>,
+    /// Publish transitions to mqtts://HOST[:PORT] or mqtt://HOST[:PORT]
+    #[arg(long, value_name = "URL")]
+    pub mqtt_broker: Option<MqttBroker>,
+    #[arg(
+        long,
+        value_name = "TOPIC",
+        help = format!("Topic template [default: {MQTT_DEFAULT_TOPIC}]")
+    )]
+    pub mqtt_topic: Option<String>,
+    /// 0, 1 or 2 [default: 0]
+    #[arg(long)]
+    pub mqtt_qos: Option<QoS>,
+    /// Have the broker keep each topic's last message
+    #[arg(long)]
+    pub mqtt_retain: bool,
+    #[arg(
+        long,
+        value_name = "USER",
+        help = format!("MQTT login; password from {MQTT_PASSWORD_ENV}")
+    )]
+    pub mqtt_user: Option<String>,
+}
+
+/// When alerts go out, and what else hears of each poll.
+#[derive(Debug, clap::Args)]
+#[command(next_help_heading = "Alerts")]
+pub struct AlertOptions {
+    #[arg(
+        long,
+        value_name = "URL",
+        help = format!(
+            "Write swap counts, balances and transitions as line protocol to this InfluxDB \
+             write endpoint [token: {INFLUX_TOKEN_ENV}]"
+        )
+    )]
+    pub influx_url: Option<String>,
+    /// Ping this Healthchecks.io check after every poll, and at <URL>/fail
+    /// while a database can't be read
+    #[arg(long, value_name = "URL")]
+    pub heartbeat_url: Option<String>,
+    /// Send a day, week or month summary via the notifiers
+    #[arg(long, value_name = "PERIOD")]
+    pub digest: Option<Period>,
+    /// Local time the digest goes out [default: 08:00]
+    #[arg(long, value_name = "HH:MM", value_parser = parse_time)]
+    pub digest_at: Option<NaiveTime>,
+    /// Repeat an unchanged stuck or liquidity alert at most this often,
+    /// e.g. 6h [default: never for stuck swaps]
+    #[arg(long, value_name = "DURATION", value_parser = parse_duration)]
+    pub alert_cooldown: Option<Duration>,
+    /// Only send critical alerts in this local time window and put the rest
+    /// in the next digest (repeatable)
+    #[arg(long, value_name = "HH:MM-HH:MM", value_parser = parse_quiet_hours)]
+    pub quiet_hours: Vec<QuietHours>,
+    /// Run COMMAND via sh on each transition, with SWAP_ID, OLD_STATE,
+    /// NEW_STATE and ENTERED_AT set
+    #[arg(long, value_name = "COMMAND")]
+    pub on_transition: Option<String>,
+    #[arg(
+        long,
+        value_name = "DURATION",
+        value_parser = parse_duration,
+        help = format!(
+            "Kill a hook command after this long [default: {}s]",
+            DEFAULT_HOOK_TIMEOUT.as_secs()
+        )
+    )]
+    pub hook_timeout: Option<Duration>,
+    #[arg(
+        long,
+        value_name = "N",
+        help = format!("Hook commands running at once [default: {DEFAULT_HOOK_CONCURRENCY}]")
+    )]
+    pub hook_concurrency: Option<usize>,
+}
+
+/// The database of observed transitions.
+#[derive(Debug, clap::Args)]
+#[command(next_help_heading = "Databases")]
+pub struct HistoryOptions {
+    /// Record observed transitions in PATH and resume from it
+    #[arg(long, value_name = "PATH")]
+    pub history_db: Option<PathBuf>,
+}
+
+/// The database of notes and tags.
+#[derive(Debug, clap::Args)]
+#[command(next_help_heading = "Databases")]
+pub struct NotesOptions {
+    /// Where notes and tags are kept [default:
+    /// ~/.local/share/wraithswap/notes.sqlite]
+    #[arg(long, value_name = "PATH")]
+    pub notes_db: Option<PathBuf>,
+}
+
+/// The block explorer transaction links point at.
+#[derive(Debug, clap::Args)]
+#[command(next_help_heading = "Chain and ASB")]
+pub struct ExplorerOptions {
+    #[arg(
+        long,
+        value_name = "URL",
+        help = format!(
+            "Block explorer for transaction links, mempool or esplora [default: \
+             {DEFAULT_EXPLORER}]"
+        )
+    )]
+    pub explorer: Option<String>,
+}
+
+/// The daemon's and agent's own log.
+#[derive(Debug, clap::Args)]
+#[command(next_help_heading = "Daemon logs")]
+pub struct LogOptions {
+    /// Daemon log lines: text, json or journald [default: journald under
+    /// systemd, text otherwise]
+    #[arg(long, value_name = "FMT")]
+    pub log_format: Option<LogFormat>,
+    /// Append daemon logs to PATH instead of stdout
+    #[arg(long, value_name = "PATH")]
+    pub log_file: Option<PathBuf>,
+}
+
+/// Limits on when swaps entered their state.
+#[derive(Debug, clap::Args)]
+#[command(next_help_heading = "Time range")]
+pub struct RangeOptions {
+    /// Only swaps that entered their state since WHEN: a date like
+    /// 2024-05-01 (local time) or a duration ago like 24h or 7d
+    #[arg(long, value_name = "WHEN", value_parser = parse_since)]
+    pub since: Option<TimeBound>,
+    /// Only swaps that entered their state before WHEN; a date includes
+    /// that day
+    #[arg(long, value_name = "WHEN", value_parser = parse_until)]
+    pub until: Option<TimeBound>,
+}
+
+/// Which swaps the table shows, and how.
+#[derive(Debug, clap::Args)]
+#[command(next_help_heading = "Table")]
+pub struct TableOptions {
+    /// Only show swaps in these states, or active, completed or failed ones
+    /// (comma-separated)
+    #[arg(long, value_name = "STATES")]
+    pub state: Option<StateSelection>,
+    /// Collapse completed and failed swaps into one line
+    #[arg(long)]
+    pub hide_finished: bool,
+    /// Only swaps whose id starts with or contains ID, or nearly does
+    #[arg(long, value_name = "ID", value_parser = parse_swap_search)]
+    pub swap: Option<SwapSearch>,
+    /// Only swaps with this tag (repeatable)
+    #[arg(long, value_parser = notes::parse_tag)]
+    pub tag: Vec<String>,
+    /// Order the table by entered, state, in_state or amount, asc or desc
+    /// [default: entered:desc]
+    #[arg(long, value_name = "KEY[:DIR]")]
+    pub sort: Option<SortOrder>,
+    /// The table's columns in order (comma-separated): id, state, progress,
+    /// entered_at, duration, eta, btc, xmr, value, peer, txid, btc_conf,
+    /// timelock, xmr_conf
+    #[arg(
+        long,
+        value_name = "COLS",
+        value_delimiter = ',',
+        value_parser = list_entry
+    )]
+    pub table_columns: Vec<String>,
+    #[arg(
+        long,
+        value_name = "N",
+        help = format!(
+            "Finished swaps read at a time; the full-screen view reads more on scrolling, 0 \
+             reads all [default: {DEFAULT_PAGE_SIZE} for watch]"
+        )
+    )]
+    pub page_size: Option<usize>,
+    #[command(flatten)]
+    pub range: RangeOptions,
+}
+
+/// The activity chart.
+#[derive(Debug, clap::Args)]
+#[command(next_help_heading = "Chart")]
+pub struct ChartOptions {
+    /// Chart the swaps started per hour or day (above the full-screen table,
+    /// c switches)
+    #[arg(long)]
+    pub chart: bool,
+    /// How far back the chart goes, per hour up to 48h and per day beyond
+    /// [default: 14d]
+    #[arg(long, value_name = "DURATION", value_parser = parse_duration)]
+    pub chart_window: Option<Duration>,
+}
+
+/// Everything a polling loop reads: watch, serve and agent share these.
+#[derive(Debug, clap::Args)]
+pub struct MonitorOptions {
+    #[command(flatten)]
+    pub poll: PollOptions,
+    #[command(flatten)]
+    pub tracking: TrackingOptions,
+    #[command(flatten)]
+    pub chain: ChainOptions,
+    #[command(flatten)]
+    pub asb_log: AsbLogOptions,
+    #[command(flatten)]
+    pub notifiers: NotifierOptions,
+    #[command(flatten)]
+    pub alerts: AlertOptions,
+    #[command(flatten)]
+    pub history: HistoryOptions,
+}
+
+/// Options of the watch command.
+#[derive(Debug, clap::Args)]
+pub struct WatchOptions {
+    /// Same as the list command
+    #[arg(long)]
+    pub once: bool,
+    /// Log transitions and alerts instead of drawing (for services)
+    #[arg(long)]
+    pub daemon: bool,
+    /// Start the full-screen view with swaps in a column per state instead
+    /// of the table (b switches)
+    #[arg(long)]
+    pub board: bool,
+    #[arg(
+        long,
+        value_name = "N",
+        help = format!(
+            "Latest transitions listed under the full-screen table, 0 hides them \
+             [default: {DEFAULT_TICKER}]"
+        )
+    )]
+    pub ticker: Option<usize>,
+    /// How long a changed state stays highlighted in the full-screen view,
+    /// 0 for one refresh [default: 60s]
+    #[arg(long, value_name = "DURATION", value_parser = parse_duration)]
+    pub fade: Option<Duration>,
+    /// Ring the terminal bell in watch mode when a swap reaches one of these
+    /// states or groups, or play a sound with COMMAND via sh instead
+    /// (repeatable)
+    #[arg(
+        long,
+        visible_alias = "bell-sound",
+        value_name = "STATES[=COMMAND]",
+        value_parser = parse_bell
+    )]
+    pub bell: Vec<(StateSelection, Option<String>)>,
+    #[command(flatten)]
+    pub monitor: MonitorOptions,
+    #[command(flatten)]
+    pub table: TableOptions,
+    #[command(flatten)]
+    pub chart: ChartOptions,
+    #[command(flatten)]
+    pub notes: NotesOptions,
+    #[command(flatten)]
+    pub log: LogOptions,
+    #[command(flatten)]
+    pub explorer: ExplorerOptions,
+}
+
+/// Options of the export command.
+#[derive(Debug, clap::Args)]
+pub struct ExportOptions {
+    /// Export format: json, csv, parquet or accounting (completed swaps as
+    /// trades for tax tools) [default: json]
+    #[arg(long)]
+    pub format: Option<ExportFormat>,
+    /// Write export to PATH instead of stdout
+    #[arg(long, value_name = "PATH")]
+    pub file: Option<PathBuf>,
+    /// Export each swap's latest state instead of every row
+    #[arg(long)]
+    pub latest: bool,
+    /// CSV/Parquet columns (comma-separated) [default:
+    /// swap_id,state,entered_at]
+    #[arg(
+        long,
+        value_name = "COLS",
+        value_delimiter = ',',
+        value_parser = parse_column
+    )]
+    pub columns: Vec<Column>,
+    #[command(flatten)]
+    pub range: RangeOptions,
+    #[command(flatten)]
+    pub price: PriceOptions,
+}
+
+/// Options of the serve command.
+#[derive(Debug, clap::Args)]
+pub struct ServeOptions {
+    #[arg(
+        long,
+        value_name = "ADDR",
+        help = format!("Address for the serve command [default: {DEFAULT_LISTEN}]")
+    )]
+    pub listen: Option<SocketAddr>,
+    /// PEM certificate chain; serve speaks HTTPS with it
+    #[arg(long, value_name = "PATH")]
+    pub tls_cert: Option<PathBuf>,
+    /// PEM private key for --tls-cert
+    #[arg(long, value_name = "PATH")]
+    pub tls_key: Option<PathBuf>,
+    /// Serve HTTPS with a certificate made up at startup
+    #[arg(long)]
+    pub tls_self_signed: bool,
+    /// Bearer token serve requires
+    #[arg(long, value_name = "TOKEN", env = API_TOKEN_ENV, hide_env_values = true)]
+    pub api_token: Option<String>,
+    /// Secret agents sign their reports with; serve accepts them at
+    /// /agent/push when set
+    #[arg(long, value_name = "TOKEN", env = AGENT_TOKEN_ENV, hide_env_values = true)]
+    pub agent_token: Option<String>,
+    /// Serve only what agents report, no local database
+    #[arg(long)]
+    pub agents_only: bool,
+    #[command(flatten)]
+    pub monitor: MonitorOptions,
+    #[command(flatten)]
+    pub notes: NotesOptions,
+}
+
+/// Options of the agent command.
+#[derive(Debug, clap::Args)]
+pub struct AgentOptions {
+    /// Secret agents sign their reports with; serve accepts them at
+    /// /agent/push when set
+    #[arg(long, value_name = "TOKEN", env = AGENT_TOKEN_ENV, hide_env_values = true)]
+    pub agent_token: Option<String>,
+    /// The serve an agent reports to, e.g. https://dash:8080
+    #[arg(long, value_name = "URL")]
+    pub central: Option<String>,
+    /// How the agent shows up centrally [default: host name]
+    #[arg(long, value_name = "NAME")]
+    pub agent_name: Option<String>,
+    #[command(flatten)]
+    pub monitor: MonitorOptions,
+    #[command(flatten)]
+    pub log: LogOptions,
+}
+
+impl GlobalOptions {
+    fn apply(self, args: &mut Args) -> Result<()> {
+        if let Some(path) = self.db_path {
+            args.db_path = Some(path);
+        }
+        if let Some(role) = self.role {
+            args.role = role;
+        }
+        if !self.instance.is_empty() {
+            args.instances = self.instance;
+        }
+        if self.mainnet {
+            args.network = Network::Mainnet;
+        }
+        // ASB has no separate stagenet data dir: XMR stagenet runs under testnet
+        if self.testnet {
+            args.network = Network::Testnet;
+        }
+        args.immutable |= self.immutable;
+        if let Some(remote) = self.remote {
+            args.remote = Some(remote);
+        }
+        if let Some(color) = self.color {
+            args.color = color;
+        }
+        if let Some(theme) = self.theme {
+            args.theme = theme;
+        }
+        if !self.state_color.is_empty() {
+            args.state_colors = self.state_color;
+        }
+        if let Some(time) = self.time {
+            args.time = time;
+        }
+        if let Some(clock) = self.clock {
+            args.clock = clock;
+        }
+        args.wide |= self.wide;
+        args.redact |= self.redact;
+        args.ascii |= self.ascii;
+        if let Some(output) = self.output {
+            args.output = output;
+        }
+        if let Some(endpoint) = self.otlp_endpoint {
+            args.otlp_endpoint = Some(endpoint);
+        }
+        for header in self.otlp_header {
+            args.otlp_headers.extend(otel::parse_headers(&header)?);
+        }
+        Ok(())
+    }
+}
+
+impl PollOptions {
+    fn apply(self, args: &mut Args) {
+        if let Some(interval) = self.interval {
+            args.interval = interval;
+        }
+        args.adaptive |= self.adaptive;
+        args.fs_watch &= !self.no_fs_watch;
+    }
+}
+
+impl TrackingOptions {
+    fn apply(self, args: &mut Args) -> Result<()> {
+        for (state, threshold) in self.stuck_after {
+            args.stuck.set(state.as_deref(), threshold)?;
+        }
+        args.watch_peers.extend(self.watch_peer);
+        args.pins.extend(self.pin);
+        Ok(())
+    }
+}
+
+impl PriceOptions {
+    fn apply(self, args: &mut Args) {
+        if let Some(source) = self.price_source {
+            args.price_source = Some(source);
+        }
+        if let Some(spread) = self.spread {
+            args.earnings.spread = spread;
+        }
+        if let Some(price) = self.btc_price {
+            args.earnings.btc_price = Some(price);
+        }
+        args.fiat |= self.fiat;
+        if let Some(currency) = self.currency {
+            args.earnings.currency = currency.to_uppercase();
+        }
+    }
+}
+
+impl ChainOptions {
+    fn apply(self, args: &mut Args) -> Result<()> {
+        if let Some(server) = self.electrum {
+            args.electrum = Some(server);
+        }
+        if let Some(fingerprint) = self.electrum_fingerprint {
+            args.electrum_fingerprint = Some(fingerprint);
+        }
+        if let Some(url) = self.monero_rpc {
+            args.monero_rpc = Some(url);
+        }
+        if let Some(login) = self.monero_rpc_login {
+            args.monero_rpc_login = Some(login);
+        }
+        // Parsed here rather than by clap, whose errors would echo the
+        // value: descriptors from `asb export-bitcoin-wallet` hold the
+        // private key
+        if let Some(wallet) = self.btc_wallet {
+            args.btc_wallet = Some(wallet.parse().context("invalid --btc-wallet")?);
+        }
+        args.balance |= self.balance;
+        if let Some(min) = self.min_xmr {
+            args.liquidity.min_xmr = Some(min);
+        }
+        if let Some(max) = self.max_btc {
+            args.liquidity.max_btc = Some(max);
+        }
+        if let Some(path) = self.asb_pid_file {
+            args.asb.pid_file = Some(path);
+        }
+        if let Some(name) = self.asb_process {
+            args.asb.process = Some(name);
+        }
+        if let Some(address) = self.asb_address {
+            args.asb.address = Some(address);
+        }
+        if !self.external_address.is_empty() {
+            args.external_addresses = self.external_address;
+        }
+        if let Some(proxy) = self.tor_socks {
+            args.tor_socks = proxy;
+        }
+        if !self.rendezvous_point.is_empty() {
+            args.rendezvous_points = self.rendezvous_point;
+        }
+        if let Some(peer) = self.quote_peer {
+            args.quote_peer = Some(peer);
+        }
+        if let Some(bin) = self.swap_bin {
+            args.swap_bin = bin;
+        }
+        if let Some(tolerance) = self.quote_tolerance {
+            args.quote_tolerance = tolerance;
+        }
+        args.fees |= self.fees;
+        if let Some(url) = self.mempool_url {
+            args.mempool_url = Some(url);
+        }
+        if let Some(rate) = self.max_fee_rate {
+            args.max_fee_rate = Some(rate);
+        }
+        self.price.apply(args);
+        Ok(())
+    }
+}
+
+impl AsbLogOptions {
+    fn apply(self, args: &mut Args) {
+        if let Some(path) = self.asb_log {
+            args.asb_log = Some(path);
+        }
+        if !self.log_alert.is_empty() {
+            args.log_alerts = self
+                .log_alert
+                .into_iter()
+                .map(|keyword| LogRule {
+                    name: keyword.clone(),
+                    matcher: LogMatch::Keyword(keyword),
+                    level: None,
+                    severity: Severity::Warning,
+                    window: None,
+                })
+                .collect();
+        }
+        if let Some(window) = self.log_alert_window {
+            args.log_alert_window = window;
+        }
+    }
+}
+
+impl NotifierOptions {
+    fn apply(self, args: &mut Args) {
+        args.notify |= self.notify;
+        if !self.notify_states.is_empty() {
+            args.notify_states = self.notify_states;
+        }
+        args.webhooks.extend(self.webhook);
+        args.discord_webhooks.extend(self.discord_webhook);
+        if let Some(secs) = self.webhook_timeout {
+            args.webhook_timeout = Duration::from_secs(secs.max(1));
+        }
+        if let Some(token) = self.telegram_token {
+            args.telegram_token = Some(token);
+        }
+        if let Some(chat_id) = self.telegram_chat_id {
+            args.telegram_chat_id = Some(chat_id);
+        }
+        if let Some(topic) = self.ntfy_topic {
+            args.ntfy_topic = Some(topic);
+        }
+        if let Some(server) = self.ntfy_server {
+            args.ntfy_server = Some(server);
+        }
+        if let Some(homeserver) = self.matrix_homeserver {
+            args.matrix_homeserver = Some(homeserver);
+        }
+        if let Some(token) = self.matrix_token {
+            args.matrix_token = Some(token);
+        }
+        if let Some(room) = self.matrix_room {
+            args.matrix_room = Some(room);
+        }
+        if let Some(token) = self.pushover_token {
+            args.pushover_token = Some(token);
+        }
+        if let Some(user) = self.pushover_user {
+            args.pushover_user = Some(user);
+        }
+        if let Some(server) = self.gotify_server {
+            args.gotify_server = Some(server);
+        }
+        if let Some(token) = self.gotify_token {
+            args.gotify_token = Some(token);
+        }
+        if let Some(key) = self.pagerduty_key {
+            args.pagerduty_key = Some(key);
+        }
+        if let Some(key) = self.opsgenie_key {
+            args.opsgenie_key = Some(key);
+        }
+        if let Some(url) = self.opsgenie_api_url {
+            args.opsgenie_api_url = Some(url);
+        }
+        if let Some(server) = self.smtp_server {
+            args.smtp_server = Some(server);
+        }
+        if let Some(user) = self.smtp_user {
+            args.smtp_user = Some(user);
+        }
+        if let Some(from) = self.email_from {
+            args.email_from = Some(from);
+        }
+        args.email_to.extend(self.email_to);
+        if let Some(batch) = self.email_batch {
+            args.email_batch = Some(batch);
+        }
+        if let Some(broker) = self.mqtt_broker {
+            args.mqtt_broker = Some(broker);
+        }
+        if let Some(topic) = self.mqtt_topic {
+            args.mqtt_topic = Some(topic);
+        }
+        if let Some(qos) = self.mqtt_qos {
+            args.mqtt_qos = qos;
+        }
+        args.mqtt_retain |= self.mqtt_retain;
+        if let Some(user) = self.mqtt_user {
+            args.mqtt_user = Some(user);
+        }
+    }
+}
+
+impl AlertOptions {
+    fn apply(self, args: &mut Args) {
+        if let Some(period) = self.digest {
+            args.digest = Some(period);
+        }
+        if let Some(at) = self.digest_at {
+            args.digest_at = at;
+        }
+        if let Some(command) = self.on_transition {
+            args.on_transition = Some(command);
+        }
+        if let Some(timeout) = self.hook_timeout {
+            args.hook_timeout = timeout.max(Duration::from_secs(1));
+        }
+        if let Some(concurrency) = self.hook_concurrency {
+            args.hook_concurrency = concurrency;
+        }
+        if let Some(url) = self.influx_url {
+            args.influx_url = Some(url);
+        }
+        if let Some(url) = self.heartbeat_url {
+            args.heartbeat_url = Some(url);
+        }
+        if let Some(cooldown) = self.alert_cooldown {
+            args.alert_cooldown = Some(cooldown);
+        }
+        args.quiet_hours.extend(self.quiet_hours);
+    }
+}
+
+impl HistoryOptions {
+    fn apply(self, args: &mut Args) {
+        if let Some(path) = self.history_db {
+            args.history_db = Some(path);
+        }
+    }
+}
+
+impl NotesOptions {
+    fn apply(self, args: &mut Args) {
+        if let Some(path) = self.notes_db {
+            args.notes_db = Some(path);
+        }
+    }
+}
+
+impl ExplorerOptions {
+    fn apply(self, args: &mut Args) {
+        if let Some(url) = self.explorer {
+            args.explorer = Some(url);
+        }
+    }
+}
+
+impl LogOptions {
+    fn apply(self, args: &mut Args) {
+        if let Some(format) = self.log_format {
+            args.log_format = Some(format);
+        }
+        if let Some(path) = self.log_file {
+            args.log_file = Some(path);
+        }
+    }
+}
+
+impl RangeOptions {
+    fn apply(self, args: &mut Args) {
+        if let Some(since) = self.since {
+            args.range.since = Some(since);
+        }
+        if let Some(until) = self.until {
+            args.range.until = Some(until);
+        }
+    }
+}
+
+impl TableOptions {
+    fn apply(self, args: &mut Args) -> Result<()> {
+        if let Some(states) = self.state {
+            args.states = states;
+        }
+        args.hide_finished |= self.hide_finished;
+        if let Some(sort) = self.sort {
+            args.sort = Some(sort);
+        }
+        if let Some(page_size) = self.page_size {
+            args.page_size = Some(page_size);
+        }
+        if let Some(search) = self.swap {
+            args.swap_search = Some(search);
+        }
+        if !self.tag.is_empty() {
+            args.tags = self.tag;
+        }
+        if !self.table_columns.is_empty() {
+            args.table_columns = Some(
+                columns::parse_list(&self.table_columns)
+                    .context("invalid --table-columns value")?,
+            );
+        }
+        self.range.apply(args);
+        Ok(())
+    }
+}
+
+impl ChartOptions {
+    fn apply(self, args: &mut Args) {
+        args.chart |= self.chart;
+        if let Some(window) = self.chart_window {
+            args.chart_window = window;
+        }
+    }
+}
+
+impl MonitorOptions {
+    fn apply(self, args: &mut Args) -> Result<()> {
+        self.poll.apply(args);
+        self.tracking.apply(args)?;
+        self.chain.apply(args)?;
+        self.asb_log.apply(args);
+        self.notifiers.apply(args);
+        self.alerts.apply(args);
+        self.history.apply(args);
+        Ok(())
+    }
+}
+
+impl WatchOptions {
+    fn apply(self, args: &mut Args) -> Result<()> {
+        args.daemon |= self.daemon;
+        args.board |= self.board;
+        if let Some(ticker) = self.ticker {
+            args.ticker = ticker;
+        }
+        if let Some(fade) = self.fade {
+            args.fade = fade;
+        }
+        if !self.bell.is_empty() {
+            args.bell = self.bell;
+        }
+        self.monitor.apply(args)?;
+        self.table.apply(args)?;
+        self.chart.apply(args);
+        self.notes.apply(args);
+        self.log.apply(args);
+        self.explorer.apply(args);
+        Ok(())
+    }
+}
+
+impl ExportOptions {
+    fn apply(self, args: &mut Args) {
+        if let Some(format) = self.format {
+            args.export_format = format;
+        }
+        if let Some(path) = self.file {
+            args.file = Some(path);
+        }
+        args.latest |= self.latest;
+        if !self.columns.is_empty() {
+            args.columns = self.columns;
+        }
+        self.range.apply(args);
+        self.price.apply(args);
+    }
+}
+
+impl ServeOptions {
+    fn apply(self, args: &mut Args) -> Result<()> {
+        if let Some(listen) = self.listen {
+            args.listen = listen;
+        }
+        if let Some(path) = self.tls_cert {
+            args.tls_cert = Some(path);
+        }
+        if let Some(path) = self.tls_key {
+            args.tls_key = Some(path);
+        }
+        args.tls_self_signed |= self.tls_self_signed;
+        if let Some(token) = self.api_token {
+            args.api_token = Some(token);
+        }
+        if let Some(token) = self.agent_token {
+            args.agent_token = Some(token);
+        }
+        args.agents_only |= self.agents_only;
+        self.monitor.apply(args)?;
+        self.notes.apply(args);
+        Ok(())
+    }
+}
+
+impl AgentOptions {
+    fn apply(self, args: &mut Args) -> Result<()> {
+        if let Some(token) = self.agent_token {
+            args.agent_token = Some(token);
+        }
+        if let Some(url) = self.central {
+            args.central = Some(url);
+        }
+        if let Some(name) = self.agent_name {
+            args.agent_name = Some(name);
+        }
+        self.monitor.apply(args)?;
+        self.log.apply(args);
+        Ok(())
+    }
+}
+
+fn is_host_port(address: &str) -> bool {
+    address
+        .rsplit_once(':')
+        .is_some_and(|(host, port)| !host.is_empty() && port.parse::<u16>().is_ok())
+}
+
+/// `90`, `90s`, `30m`, `2h` or `1d`; a bare number is seconds.
+pub fn parse_duration(value: &str) -> Result<Duration> {
+    let value = value.trim();
+    let (number, unit) = match value.find(|c: char| !c.is_ascii_digit()) {
+        Some(i) => value.split_at(i),
+        None => (value, "s"),
+    };
+    let number: u64 = number
+        .parse()
+        .with_context(|| format!("expected a duration like 30m or 2h, got {value:?}"))?;
+    let secs = match unit {
+        "s" => Some(number),
+        "m" => number.checked_mul(60),
+        "h" => number.checked_mul(3600),
+        "d" => number.checked_mul(86_400),
+        other => bail!("unknown duration unit {other:?} (expected s, m, h or d)"),
+    };
+    match secs {
+        Some(secs) => Ok(Duration::from_secs(secs)),
+        None => bail!("duration too large: {value}"),
+    }
+}
+
+/// `24h` or `7d` ago, or a local date like `2024-05-01`, optionally with a
+/// time (`2024-05-01 14:30`), or an RFC 3339 timestamp. A bare date means
+/// its start, or with `end_of_day` the start of the next day, so that
+/// `--until` includes it.
+pub fn parse_time_bound(value: &str, end_of_day: bool) -> Result<TimeBound> {
+    let value = value.trim();
+    if value.starts_with(|c: char| c.is_ascii_digit()) && !value.contains('-') {
+        return Ok(TimeBound::Ago(parse_duration(value)?));
+    }
+    if let Ok(at) = chrono::DateTime::parse_from_rfc3339(value) {
+        return Ok(TimeBound::At(at.with_timezone(&Utc)));
+    }
+    let naive = ["%Y-%m-%d %H:%M:%S", "%Y-%m-%d %H:%M", "%Y-%m-%dT%H:%M"]
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(value, format).ok())
+        .or_else(|| {
+            let date = NaiveDate::parse_from_str(value, "%Y-%m-%d").ok()?;
+            let date = match end_of_day {
+                true => date.succ_opt()?,
+                false => date,
+            };
+            date.and_hms_opt(0, 0, 0)
+        })
+        .with_context(|| {
+            format!("expected a date like 2024-05-01 or a duration like 24h, got {value:?}")
+        })?;
+    let at = Local
+        .from_local_datetime(&naive)
+        .earliest()
+        .with_context(|| format!("{value:?} doesn't exist in local time"))?;
+    Ok(TimeBound::At(at.with_timezone(&Utc)))
+}
+
+/// `08:00` or `8:00`, 24-hour clock.
+pub fn parse_time(value: &str) -> Result<NaiveTime> {
+    NaiveTime::parse_from_str(value.trim(), "%H:%M")
+        .with_context(|| format!("expected a time like 08:00, got {value:?}"))
+}
+
+/// `NAME=URL` or a bare URL. The name can't contain `:` or `/`, so an `=`
+/// in the URL's query isn't mistaken for one.
+fn parse_discord_webhook(value: &str) -> Result<(Option<String>, String)> {
+    let (name, url) = match value.split_once('=') {
+        Some((name, url)) if !name.is_empty() && !name.contains([':', '/']) => {
+            (Some(name.to_string()), url)
+        }
+        _ => (None, value),
+    };
+    if url.is_empty() {
+        bail!("expected a webhook URL");
+    }
+    Ok((name, url.to_string()))
+}
+
+/// `HH:MM-HH:MM` in local time, e.g. `23:00-07:00`.
+pub fn parse_quiet_hours(value: &str) -> Result<QuietHours> {
+    let (start, end) = value
+        .split_once('-')
+        .with_context(|| format!("expected a time range like 23:00-07:00, got {value:?}"))?;
+    let hours = QuietHours {
+        start: parse_time(start)?,
+        end: parse_time(end)?,
+    };
+    if hours.start == hours.end {
+        bail!("quiet hours {value:?} start and end at the same time");
+    }
+    Ok(hours)
+}
+
+pub const BTC_DECIMALS: u32 = 8;
+pub const XMR_DECIMALS: u32 = 12;
+
+/// A decimal amount like `0.5` or `12` in atomic units (satoshis or
+/// piconero), without going through floating point.
+pub fn parse_amount(value: &str, decimals: u32) -> Result<u64> {
+    let value = value.trim();
+    let (whole, frac) = value.split_once('.').unwrap_or((value, ""));
+    if whole.is_empty() && frac.is_empty()
+        || !whole
+            .bytes()
+            .chain(frac.bytes())
+            .all(|b| b.is_ascii_digit())
+    {
+        bail!("expected an amount like 0.5, got {value:?}");
+    }
+    if frac.len() > decimals as usize {
+        bail!("at most {decimals} decimal places, got {value:?}");
+    }
+
+    let scale = 10u64.pow(decimals);
+    let whole: u64 = match whole {
+        "" => 0,
+        digits => digits.parse().context("amount too large")?,
+    };
+    let frac: u64 = format!("{frac:0<width$}", width = decimals as usize)
+        .parse()
+        .unwrap_or(0);
+    whole
+        .checked_mul(scale)
+        .and_then(|n| n.checked_add(frac))
+        .context("amount too large")
+}
+
+/// `0.02` or `2%`.
+pub fn parse_spread(value: &str) -> Result<f64> {
+    let value = value.trim();
+    let spread = match value.strip_suffix('%') {
+        Some(percent) => percent.trim().parse::<f64>().map(|p| p / 100.0),
+        None => value.parse::<f64>(),
+    }
+    .ok()
+    .filter(|s| s.is_finite() && *s >= 0.0)
+    .with_context(|| format!("expected a spread like 0.02 or 2%, got {value:?}"))?;
+    Ok(spread)
+}
+
+fn parse_interval(value: &str) -> Result<Duration> {
+    match value.trim().parse() {
+        Ok(0) => bail!("must be at least 1 second"),
+        Ok(secs) => Ok(Duration::from_secs(secs)),
+        Err(_) => bail!("expected a number of seconds, got {value:?}"),
+    }
+}
+
+fn parse_since(value: &str) -> Result<TimeBound> {
+    parse_time_bound(value, false)
+}
+
+fn parse_until(value: &str) -> Result<TimeBound> {
+    parse_time_bound(value, true)
+}
+
+fn parse_xmr(value: &str) -> Result<u64> {
+    parse_amount(value, XMR_DECIMALS)
+}
+
+fn parse_btc(value: &str) -> Result<u64> {
+    parse_amount(value, BTC_DECIMALS)
+}
+
+fn parse_price(value: &str) -> Result<f64> {
+    value
+        .trim()
+        .parse()
+        .ok()
+        .filter(|price: &f64| price.is_finite() && *price >= 0.0)
+        .with_context(|| format!("expected a price like 65000, got {value:?}"))
+}
+
+fn parse_swap_search(value: &str) -> Result<SwapSearch> {
+    let search = SwapSearch::new(value);
+    if search.is_empty() {
+        bail!("expected part of a swap id");
+    }
+    Ok(search)
+}
+
+fn parse_pin(value: &str) -> Result<String> {
+    if value.trim().is_empty() {
+        bail!("expected a swap id");
+    }
+    Ok(value.to_string())
+}
+
+/// `STATES`, ringing the bell, or `STATES=COMMAND`, playing a sound.
+fn parse_bell(value: &str) -> Result<(StateSelection, Option<String>)> {
+    match value.contains('=') {
+        true => bell::parse_sound(value),
+        false => Ok((bell::parse_states(value)?, None)),
+    }
+}
+
+/// `DURATION` for every state or `STATE=DURATION` for one.
+fn parse_stuck_after(value: &str) -> Result<(Option<String>, Duration)> {
+    let (state, duration) = match value.split_once('=') {
+        Some((state, duration)) => (Some(state.trim().to_string()), duration),
+        None => (None, value),
+    };
+    let threshold = parse_duration(duration)?;
+    // Catches a mistyped state while clap can still point at the flag
+    StuckRules::default().set(state.as_deref(), threshold)?;
+    Ok((state, threshold))
+}
+
+/// A comma-separated list entry, without the spaces around it.
+fn list_entry(value: &str) -> Result<String> {
+    match value.trim() {
+        "" => bail!("empty entry in a comma-separated list"),
+        entry => Ok(entry.to_string()),
+    }
+}
+
+fn parse_column(value: &str) -> Result<Column> {
+    value.trim().parse()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(argv: &[&str]) -> Result<Args, clap::Error> {
+        let matches = Cli::command().try_get_matches_from(["wraithswap-poc"].iter().chain(argv))?;
+        let cli = Cli::from_matches(&matches)?;
+        let mut args = Args::default();
+        cli.apply(&mut args).unwrap();
+        Ok(args)
+    }
+
+    #[test]
+    fn cli_is_well_formed() {
+        Cli::command().debug_assert();
+    }
+
+    #[test]
+    fn commands_default_to_watch_or_list() {
+        assert_eq!(parse(&[]).unwrap().command, Command::Watch);
+        assert_eq!(parse(&["--once"]).unwrap().command, Command::List);
+        assert_eq!(parse(&["watch", "--once"]).unwrap().command, Command::List);
+        assert!(parse(&["stats", "--once"]).is_err());
+    }
+
+    #[test]
+    fn options_go_before_or_after_the_command() {
+        for argv in [
+            ["--mainnet", "list", "--db-path", "a.sqlite"],
+            ["list", "--db-path", "a.sqlite", "--mainnet"],
+        ] {
+            let matches =
+                Cli::command().try_get_matches_from(["wraithswap-poc"].iter().chain(&argv));
+            let matches = matches.unwrap();
+            assert_eq!(
+                matches.value_source("db_path"),
+                Some(ValueSource::CommandLine)
+            );
+            let args = parse(&argv).unwrap();
+            assert_eq!(args.command, Command::List);
+            assert_eq!(args.network, Network::Mainnet);
+            assert_eq!(args.db_path, Some(PathBuf::from("a.sqlite")));
+        }
+    }
+
+    #[test]
+    fn command_options_go_after_the_command() {
+        assert_eq!(
+            parse(&["list", "--state", "active"]).unwrap().states,
+            "active".parse().unwrap()
+        );
+        assert!(parse(&["--daemon", "--webhook", "https://a.example"]).is_ok());
+        assert!(parse(&["watch", "--daemon"]).unwrap().daemon);
+        for argv in [
+            &["--state", "active", "list"][..],
+            &["--daemon", "watch"],
+            &["--once", "stats"],
+        ] {
+            let err = parse(argv).unwrap_err();
+            assert!(
+                err.to_string().contains("goes after the command"),
+                "{argv:?}: {err}"
+            );
+        }
+        // Each command only takes the options it reads
+        for argv in [
+            &["list", "--daemon"][..],
+            &["stats", "--webhook", "https://a.example"],
+            &["export", "--electrum", "ssl://e.example:50002"],
+            &["serve", "--format", "csv"],
+            &["tag", "abc", "x", "--interval", "5"],
+        ] {
+            assert!(parse(argv).is_err(), "{argv:?}");
+        }
+    }
+
+    #[test]
+    fn command_arguments() {
+        assert_eq!(
+            parse(&["open", "abc", "refund"]).unwrap().command,
+            Command::Open {
+                swap_id: "abc".into(),
+                tx: Some("refund".into())
+            }
+        );
+        assert!(parse(&["open", "abc", "deposit"]).is_err());
+        assert_eq!(
+            parse(&["note", "abc", "asked", "for", "a", "refund"])
+                .unwrap()
+                .command,
+            Command::Note {
+                swap_id: "abc".into(),
+                text: vec!["asked", "for", "a", "refund"]
+                    .into_iter()
+                    .map(String::from)
+                    .collect(),
+            }
+        );
+        assert!(parse(&["note", "abc"]).is_err());
+        assert!(parse(&["tag", "abc"]).is_err());
+        assert!(parse(&["show"]).is_err());
+        assert!(parse(&["list", "--channel", "telegram"]).is_err());
+        assert!(parse(&["notify-test", "--channel", "telegram"]).is_ok());
+    }
+
+    #[test]
+    fn bad_values_are_rejected_by_flag() {
+        for argv in [
+            &["--interval", "0"][..],
+            &["--mainnet", "--testnet"],
+            &["--stuck-after", "Waiting=2h"],
+            &["--swap", " "],
+            &["--pin", ""],
+            &["export", "--columns", "swap_id,nope"],
+            &["--bell", "=afplay ping.aiff"],
+            &["--btc-price=-1"],
+            &["--color", "sometimes"],
+            &["--electrum-fingerprint", "AB:CD"],
+        ] {
+            let err = parse(argv).unwrap_err();
+            let flag = argv.iter().find(|arg| arg.starts_with("--")).unwrap();
+            let flag = flag.split('=').next().unwrap();
+            assert!(err.to_string().contains(flag), "{argv:?}: {err}");
+        }
+    }
+
+    #[test]
+    fn values_are_parsed() {
+        let args = parse(&[
+            "--stagenet",
+            "--interval",
+            "5",
+            "--notify-states",
+            "BtcRedeemed,BtcPunished",
+            "--stuck-after",
+            "2h",
+            "--stuck-after",
+            "EncSigSent=30m",
+            "--hook-timeout",
+            "0",
+            "--currency",
+            "eur",
+            "--min-xmr",
+            "1.5",
+            "--discord-webhook",
+            "ops=https://discord.com/api/webhooks/1/a?x=y",
+            "--electrum-fingerprint",
+            "ab:cd:ef:01:23:45:67:89:ab:cd:ef:01:23:45:67:89:ab:cd:ef:01:23:45:67:89:ab:cd:ef:01:23:45:67:89",
+        ])
+        .unwrap();
+        assert_eq!(args.network, Network::Testnet);
+        assert_eq!(args.interval, Duration::from_secs(5));
+        assert_eq!(args.notify_states, ["BtcRedeemed", "BtcPunished"]);
+        assert_eq!(args.stuck.default, Some(Duration::from_secs(7200)));
+        assert_eq!(
+            args.stuck.threshold("EncSigSent"),
+            Some(Duration::from_secs(1800))
+        );
+        assert_eq!(args.hook_timeout, Duration::from_secs(1));
+        assert_eq!(args.earnings.currency, "EUR");
+        assert_eq!(args.liquidity.min_xmr, Some(1_500_000_000_000));
+        assert_eq!(
+            args.discord_webhooks,
+            [(
+                Some("ops".to_string()),
+                "https://discord.com/api/webhooks/1/a?x=y".to_string()
+            )]
+        );
+        assert_eq!(
+            args.electrum_fingerprint.unwrap().to_string(),
+            ["AB:CD:EF:01:23:45:67:89"; 4].join(":")
+        );
+    }
+
+    #[test]
+    fn export_options_are_parsed() {
+        let args = parse(&[
+            "export",
+            "--format",
+            "csv",
+            "--columns",
+            "swap_id, btc_sats",
+            "--since",
+            "7d",
+        ])
+        .unwrap();
+        assert_eq!(args.command, Command::Export);
+        assert_eq!(args.export_format, ExportFormat::Csv);
+        assert_eq!(args.columns, [Column::SwapId, Column::BtcSats]);
+        assert_eq!(
+            args.range.since,
+            Some(TimeBound::Ago(Duration::from_secs(604_800)))
+        );
+    }
+
+    #[test]
+    fn export_help_names_every_format() {
+        let cli = Cli::command();
+        let export = cli.find_subcommand("export").unwrap();
+        let about = export.get_about().unwrap().to_string().to_lowercase();
+        for format in ["json", "csv", "parquet", "accounting"] {
+            assert!(format.parse::<ExportFormat>().is_ok());
+            assert!(about.contains(format), "{format}: {about}");
+        }
+    }
+
+    #[test]
+    fn bells_keep_their_order() {
+        let args = parse(&[
+            "--bell",
+            "failed",
+            "--bell-sound",
+            "BtcRedeemed=paplay done.oga",
+            "--bell",
+            "all",
+        ])
+        .unwrap();
+        let commands: Vec<_> = args.bell.iter().map(|(_, c)| c.as_deref()).collect();
+        assert_eq!(commands, [None, Some("paplay done.oga"), None]);
+    }
+
+    #[test]
+    fn flags_replace_or_add_to_the_config_file() {
+        let cli = Cli::try_parse_from([
+            "wraithswap-poc",
+            "--webhook",
+            "https://b.example",
+            "--rendezvous-point",
+            "/dns4/b/tcp/1",
+        ])
+        .unwrap();
+        let mut args = Args {
+            webhooks: vec!["https://a.example".into()],
+            rendezvous_points: vec!["/dns4/a/tcp/1".into()],
+            ..Args::default()
+        };
+        cli.apply(&mut args).unwrap();
+        assert_eq!(args.webhooks, ["https://a.example", "https://b.example"]);
+        assert_eq!(args.rendezvous_points, ["/dns4/b/tcp/1"]);
+    }
+
+    #[test]
+    fn duration_units() {
+        assert_eq!(parse_duration("45").unwrap(), Duration::from_secs(45));
+        assert_eq!(parse_duration("45s").unwrap(), Duration::from_secs(45));
+        assert_eq!(parse_duration("30m").unwrap(), Duration::from_secs(1800));
+        assert_eq!(parse_duration("2h").unwrap(), Duration::from_secs(7200));
+        assert_eq!(
+            parse_duration(" 7d ").unwrap(),
+            Duration::from_secs(604_800)
+        );
+    }
+
+    #[test]
+    fn duration_rejects_garbage() {
+        assert!(parse_duration("").is_err());
+        assert!(parse_duration("h").is_err());
+        assert!(parse_duration("2w").is_err());
+        assert!(parse_duration("-5m").is_err());
+    }
+
+    #[test]
+    fn duration_overflow_is_an_error() {
+        for value in [
+            "9999999999999999999d",
+            "999999999999999999d",
+            "999999999999999999h",
+        ] {
+            let err = parse_duration(value).unwrap_err();
+            assert!(
+                err.to_string().contains("duration too large"),
+                "{value}: {err}"
+            );
+        }
+        assert!(parse_duration("99999999999999999999").is_err());
+        let max = u64::MAX / 60;
+        assert!(parse_duration(&format!("{max}m")).is_ok());
+        assert!(parse_duration(&format!("{}m", max + 1)).is_err());
+    }
+
+    #[test]
+    fn amounts_in_atomic_units() {
+        assert_eq!(parse_amount("0.5", BTC_DECIMALS).unwrap(), 50_000_000);
+        assert_eq!(parse_amount("12", BTC_DECIMALS).unwrap(), 1_200_000_000);
+        assert_eq!(parse_amount(".00000001", BTC_DECIMALS).unwrap(), 1);
+        assert_eq!(parse_amount("1.", BTC_DECIMALS).unwrap(), 100_000_000);
+        assert_eq!(
+            parse_amount(" 2.5 ", XMR_DECIMALS).unwrap(),
+            2_500_000_000_000
+        );
+        assert_eq!(parse_amount("0", XMR_DECIMALS).unwrap(), 0);
+    }
+
+    #[test]
+    fn amounts_reject_garbage_and_excess_precision() {
+        for value in ["", ".", "-1", "1e3", "0,5", "1.2.3", "abc"] {
+            assert!(parse_amount(value, BTC_DECIMALS).is_err(), "{value:?}");
+        }
+        let err = parse_amount("0.000000001", BTC_DECIMALS).unwrap_err();
+        assert!(err.to_string().contains("8 decimal places"));
+        assert!(parse_amount("0.0000000000001", XMR_DECIMALS).is_err());
+    }
+
+    #[test]
+    fn amounts_that_overflow_are_errors() {
+        assert!(parse_amount("184467440737.09551615", BTC_DECIMALS).is_ok());
+        assert!(parse_amount("184467440737.09551616", BTC_DECIMALS).is_err());
+        assert!(parse_amount("99999999999999999999", BTC_DECIMALS).is_err());
+    }
+
+    fn local(date: &str) -> TimeBound {
+        let naive = NaiveDateTime::parse_from_str(date, "%Y-%m-%d %H:%M:%S").unwrap();
+        let at = Local.from_local_datetime(&naive).earliest().unwrap();
+        TimeBound::At(at.with_timezone(&Utc))
+    }
+
+    #[test]
+    fn time_bounds_ago() {
+        assert_eq!(
+            parse_time_bound("24h", false).unwrap(),
+            TimeBound::Ago(Duration::from_secs(86_400))
+        );
+        assert_eq!(
+            parse_time_bound(" 7d", true).unwrap(),
+            TimeBound::Ago(Duration::from_secs(604_800))
+        );
+    }
+
+    #[test]
+    fn time_bounds_at_dates_and_times() {
+        assert_eq!(
+            parse_time_bound("2024-05-01", false).unwrap(),
+            local("2024-05-01 00:00:00")
+        );
+        // `--until` takes in the whole day
+        assert_eq!(
+            parse_time_bound("2024-05-01", true).unwrap(),
+            local("2024-05-02 00:00:00")
+        );
+        assert_eq!(
+            parse_time_bound("2024-05-01 14:30", true).unwrap(),
+            local("2024-05-01 14:30:00")
+        );
+        assert_eq!(
+            parse_time_bound("2024-05-01T14:30", false).unwrap(),
+            local("2024-05-01 14:30:00")
+        );
+        assert_eq!(
+            parse_time_bound("2024-05-01T14:30:00+02:00", false).unwrap(),
+            TimeBound::At(Utc.with_ymd_and_hms(2024, 5, 1, 12, 30, 0).unwrap())
+        );
+    }
+
+    #[test]
+    fn time_bounds_reject_garbage() {
+        for value in ["", "yesterday", "2024-13-01", "2024-05-01 25:00", "5w"] {
+            assert!(parse_time_bound(value, false).is_err(), "{value:?}");
+        }
+    }
+
+    #[test]
+    fn relative_bounds_slide_with_now() {
+        let now = Utc.with_ymd_and_hms(2024, 5, 2, 0, 0, 0).unwrap();
+        let bound = parse_time_bound("1d", false).unwrap();
+        assert_eq!(
+            bound.resolve(now),
+            Utc.with_ymd_and_hms(2024, 5, 1, 0, 0, 0).unwrap()
+        );
+    }
+}
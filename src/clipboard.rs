@@ -0,0 +1,86 @@
+//! Copying swap ids and txids from the full-screen view (`y`, `Y`), since
+//! the table's truncated ids can't be pasted into ASB commands or an
+//! explorer.
+//!
+//! Locally the text goes to the desktop's clipboard through arboard:
+//! AppKit on macOS, X11 or Wayland's data-control protocol on Linux. Over
+//! SSH, or where there's no clipboard to connect to, the caller asks the
+//! terminal instead with an OSC 52 sequence (see
+//! [`crate::tui::Terminal::copy`]), which reaches the clipboard of the
+//! machine the terminal runs on in most terminals.
+
+use anyhow::{Context, Result};
+use std::ffi::OsString;
+
+/// The desktop's clipboard, connected on first use and kept for as long as
+/// the view runs: on X11 the copied text is served by this process and
+/// would go away with the connection.
+#[derive(Default)]
+pub struct Clipboard {
+    desktop: Option<arboard::Clipboard>,
+    /// Connecting failed or would reach the wrong machine, so the terminal
+    /// does all the copying
+    unavailable: bool,
+}
+
+impl Clipboard {
+    /// Put `text` on the desktop's clipboard. False when there is none to
+    /// use, and the terminal has to do it.
+    pub fn copy(&mut self, text: &str) -> Result<bool> {
+        if self.desktop.is_none() && !self.unavailable {
+            self.desktop = match over_ssh(|name| std::env::var_os(name)) {
+                true => None,
+                false => arboard::Clipboard::new().ok(),
+            };
+            self.unavailable = self.desktop.is_none();
+        }
+        let Some(ref mut desktop) = self.desktop else {
+            return Ok(false);
+        };
+        desktop
+            .set_text(text)
+            .context("copy to the desktop's clipboard")?;
+        Ok(true)
+    }
+}
+
+/// Over SSH a clipboard connection would reach the remote machine's, if it
+/// has one, rather than the user's.
+fn over_ssh(var: impl Fn(&str) -> Option<OsString>) -> bool {
+    ["SSH_CONNECTION", "SSH_TTY"]
+        .into_iter()
+        .any(|name| var(name).is_some_and(|value| !value.is_empty()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn env<'a>(vars: &'a [(&str, &str)]) -> impl Fn(&str) -> Option<OsString> + 'a {
+        |name| {
+            vars.iter()
+                .find(|(var, _)| *var == name)
+                .map(|(_, value)| value.into())
+        }
+    }
+
+    #[test]
+    fn ssh_sessions_leave_copying_to_the_terminal() {
+        assert!(over_ssh(env(&[(
+            "SSH_CONNECTION",
+            "10.0.0.2 50022 10.0.0.1 22"
+        )])));
+        assert!(over_ssh(env(&[("SSH_TTY", "/dev/pts/3")])));
+        assert!(!over_ssh(env(&[("SSH_TTY", ""), ("DISPLAY", ":0")])));
+        assert!(!over_ssh(env(&[])));
+    }
+
+    #[test]
+    fn without_a_clipboard_the_terminal_copies() {
+        let mut clipboard = Clipboard {
+            desktop: None,
+            unavailable: true,
+        };
+        assert!(!clipboard.copy("abc").unwrap());
+    }
+}
@@ -0,0 +1,164 @@
+//! Which columns the swap table shows, and in what order
+//! (`--table-columns`, `C` in the full-screen view). Operators watching
+//! liquidity care about amounts, those chasing a stuck swap about its
+//! transactions and counterparty; the rest can go.
+//!
+//! Like the sort order, the full-screen view remembers the last choice made
+//! there, in the cache directory, for the next start without
+//! `--table-columns`.
+
+use anyhow::{bail, Result};
+use std::fmt;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TableColumn {
+    Id,
+    State,
+    /// Steps taken along the happy path
+    Progress,
+    EnteredAt,
+    /// Time in the current state
+    Duration,
+    Eta,
+    Btc,
+    Xmr,
+    /// The BTC amount in fiat, with `--fiat`
+    Value,
+    Peer,
+    /// The swap's most advanced BTC transaction
+    Txid,
+    /// Confirmations of that transaction, with an Electrum server
+    BtcConf,
+    Timelock,
+    /// XMR lock confirmations, with monero-wallet-rpc
+    XmrConf,
+}
+
+pub const ALL: [TableColumn; 14] = [
+    TableColumn::Id,
+    TableColumn::State,
+    TableColumn::Progress,
+    TableColumn::EnteredAt,
+    TableColumn::Duration,
+    TableColumn::Eta,
+    TableColumn::Btc,
+    TableColumn::Xmr,
+    TableColumn::Value,
+    TableColumn::Peer,
+    TableColumn::Txid,
+    TableColumn::BtcConf,
+    TableColumn::Timelock,
+    TableColumn::XmrConf,
+];
+
+impl TableColumn {
+    pub fn name(self) -> &'static str {
+        match self {
+            TableColumn::Id => "id",
+            TableColumn::State => "state",
+            TableColumn::Progress => "progress",
+            TableColumn::EnteredAt => "entered_at",
+            TableColumn::Duration => "duration",
+            TableColumn::Eta => "eta",
+            TableColumn::Btc => "btc",
+            TableColumn::Xmr => "xmr",
+            TableColumn::Value => "value",
+            TableColumn::Peer => "peer",
+            TableColumn::Txid => "txid",
+            TableColumn::BtcConf => "btc_conf",
+            TableColumn::Timelock => "timelock",
+            TableColumn::XmrConf => "xmr_conf",
+        }
+    }
+
+    /// The column's heading in the table.
+    pub fn title(self) -> &'static str {
+        match self {
+            TableColumn::Id => "Swap ID",
+            TableColumn::State => "State",
+            TableColumn::Progress => "Progress",
+            TableColumn::EnteredAt => "Entered At",
+            TableColumn::Duration => "In State",
+            TableColumn::Eta => "ETA",
+            TableColumn::Btc => "BTC",
+            TableColumn::Xmr => "XMR",
+            TableColumn::Value => "Value",
+            TableColumn::Peer => "Peer",
+            TableColumn::Txid => "Txid",
+            TableColumn::BtcConf => "BTC Conf",
+            TableColumn::Timelock => "Timelock",
+            TableColumn::XmrConf => "XMR Conf",
+        }
+    }
+}
+
+impl FromStr for TableColumn {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let name = s.trim().to_ascii_lowercase().replace('-', "_");
+        let alias = match name.as_str() {
+            "swap_id" => "id",
+            "entered" => "entered_at",
+            "in_state" => "duration",
+            "amount_btc" => "btc",
+            "amount_xmr" => "xmr",
+            "peer_id" => "peer",
+            other => other,
+        };
+        match ALL.into_iter().find(|column| column.name() == alias) {
+            Some(column) => Ok(column),
+            None => {
+                let names: Vec<&str> = ALL.iter().map(|column| column.name()).collect();
+                bail!("unknown column: {s} (expected {})", names.join(", "))
+            }
+        }
+    }
+}
+
+impl fmt::Display for TableColumn {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Comma-separated column names, in the order to show them.
+pub fn parse_list(names: &[String]) -> Result<Vec<TableColumn>> {
+    let mut columns = Vec::new();
+    for name in names {
+        let column: TableColumn = name.parse()?;
+        if columns.contains(&column) {
+            bail!("column {column} is listed twice");
+        }
+        columns.push(column);
+    }
+    if columns.is_empty() {
+        bail!("name at least one column to show");
+    }
+    Ok(columns)
+}
+
+/// The columns last picked in the full-screen view, if any.
+pub fn remembered() -> Option<Vec<TableColumn>> {
+    let body = std::fs::read_to_string(remember_path()?).ok()?;
+    let names: Vec<String> = body.split(',').map(|s| s.trim().to_string()).collect();
+    parse_list(&names).ok()
+}
+
+/// Best effort, like the remembered sort order.
+pub fn remember(columns: &[TableColumn]) {
+    let Some(path) = remember_path() else {
+        return;
+    };
+    if let Some(dir) = path.parent() {
+        let _ = std::fs::create_dir_all(dir);
+    }
+    let names: Vec<&str> = columns.iter().map(|column| column.name()).collect();
+    let _ = std::fs::write(path, format!("{}\n", names.join(",")));
+}
+
+fn remember_path() -> Option<PathBuf> {
+    Some(dirs::cache_dir()?.join("wraithswap/columns"))
+}
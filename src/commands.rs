@@ -0,0 +1,1047 @@
+//! One-shot and streaming subcommands.
+
+use anyhow::{bail, Context, Result};
+use chrono::Local;
+use colored::Colorize;
+use serde::Serialize;
+use sqlx::SqlitePool;
+use std::collections::HashMap;
+use std::io::{self, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::time::Duration;
+use tokio::time::sleep;
+
+use crate::anomaly::Anomaly;
+use crate::asb::{AsbConfig, Level};
+use crate::asblog::AsbLog;
+use crate::chain::{ChainMonitor, TxConfirmations};
+use crate::cli::{ExportFormat, OutputFormat};
+use crate::columns::TableColumn;
+use crate::csv::{self, Column};
+use crate::db::{
+    count_state_rows, fetch_all_states, fetch_history, fetch_peer_addresses, fetch_swaps,
+    open_read_only_pool, scan_swaps, Instance, PollError,
+};
+use crate::explorer::{self, Explorer};
+use crate::fiat::FiatRates;
+use crate::history::HistoryStore;
+use crate::model::{
+    build_views, earnings, AccountingRow, Activity, DateRange, EarningsOptions, Outcome, PeerStats,
+    PinnedSwaps, RateRow, Role, StuckRules, SwapDetails, SwapStats, SwapView, Watchlist,
+};
+use crate::notes::NotesStore;
+use crate::notify::Notifiers;
+use crate::parquet;
+use crate::price::PriceSource;
+use crate::remote;
+use crate::render::{
+    annotation_lines, anomaly_lines, history_lines, log_lines, plain, render_annotations,
+    render_earnings, render_header, render_peers, render_rates, render_stats, render_table,
+    render_transitions, report_error, unloaded_line, Columns, TableFilter,
+};
+use crate::schema::Schema;
+use crate::theme::Paint;
+use crate::tui;
+use crate::watch::{db_watcher, Monitor, RefreshSchedule};
+
+/// Open the database for a one-shot command. Unlike watch mode, a missing
+/// or unreadable database is an error rather than something to wait out.
+async fn open_database(db_path: Option<PathBuf>, immutable: bool) -> Result<(PathBuf, SqlitePool)> {
+    let path = db_path.context("could not resolve ASB data directory for this OS")?;
+    if !path.exists() {
+        bail!("database not found: {}", path.display());
+    }
+
+    let pool = open_read_only_pool(&path, immutable).await?;
+    Ok((path, pool))
+}
+
+/// Open one of several instances, naming it in errors when it matters.
+async fn open_instance(instance: &Instance, multi: bool) -> Result<(PathBuf, SqlitePool)> {
+    let opened = async {
+        if let (Some(remote), Some(mirror)) = (&instance.remote, &instance.db_path) {
+            remote::fetch(remote, mirror).await?;
+        }
+        open_database(instance.db_path.clone(), instance.immutable).await
+    }
+    .await;
+    if multi {
+        opened.with_context(|| format!("instance {}", instance.name))
+    } else {
+        opened
+    }
+}
+
+/// A record prefixed with its instance name when several ASBs are in play.
+#[derive(Serialize)]
+pub struct Tagged<'a, T> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub instance: Option<&'a str>,
+    #[serde(flatten)]
+    pub record: &'a T,
+}
+
+/// What `list` found, as its exit status for scripts and monitoring
+/// checks. The worst finding across all instances wins.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Health {
+    Healthy = 0,
+    /// Swaps past their stuck threshold
+    Stuck = 1,
+    /// Swaps that ended punished or refunded
+    Failed = 2,
+    /// A database that couldn't be opened or read
+    Unreachable = 3,
+}
+
+impl Health {
+    fn of(view: &SwapView) -> Self {
+        if view.state.is_punish() || view.state.is_refund() {
+            Health::Failed
+        } else if view.stuck {
+            Health::Stuck
+        } else {
+            Health::Healthy
+        }
+    }
+
+    pub fn exit_code(self) -> ExitCode {
+        ExitCode::from(self as u8)
+    }
+}
+
+/// Which swaps `list` reads and prints.
+pub struct ListOptions {
+    pub filter: TableFilter,
+    /// Finished swaps read, newest first; `None` reads them all
+    pub page_size: Option<usize>,
+    pub pins: PinnedSwaps,
+    /// Where the tags `--tag` filters on come from
+    pub notes: Option<NotesStore>,
+    /// The table's columns, from `--table-columns`
+    pub table_columns: Option<Vec<TableColumn>>,
+}
+
+/// Print a single snapshot of the latest state per swap, or with
+/// `--output nagios` a one-line check result. An unreadable database is
+/// reported and skipped so the other instances still show.
+pub async fn run_list(
+    instances: &[Instance],
+    output: OutputFormat,
+    stuck: &StuckRules,
+    watchlist: &Watchlist,
+    chain: &ChainMonitor,
+    rendezvous: &[String],
+    options: ListOptions,
+) -> Result<Health> {
+    let ListOptions {
+        filter,
+        page_size,
+        pins,
+        notes,
+        table_columns,
+    } = options;
+    let multi = instances.len() > 1;
+    let mut all_views = Vec::new();
+    let columns = Columns::for_chain(chain)
+        .with_chosen(table_columns)
+        .fit(tui::stdout_width());
+    let mut health = Health::Healthy;
+    let nagios = output == OutputFormat::Nagios;
+    let mut errors = Vec::new();
+
+    let mut loaded = Vec::new();
+    // Finished swaps `page_size` left unread, per loaded instance
+    let mut unread = Vec::new();
+    for instance in instances {
+        let read = async {
+            let (path, pool) = open_instance(instance, multi).await?;
+            let scan = scan_swaps(&pool, instance.role, page_size)
+                .await
+                .context("query swaps");
+            pool.close().await;
+            Ok::<_, anyhow::Error>((path, scan?))
+        };
+        let (path, mut scan) = match read.await {
+            Ok(read) => read,
+            Err(err) => {
+                if !nagios {
+                    report_error(output == OutputFormat::Json, &format!("{err:#}"));
+                }
+                errors.push(format!("{err:#}"));
+                health = Health::Unreachable;
+                continue;
+            }
+        };
+
+        let mut views = build_views(scan.rows, &mut HashMap::new());
+        // Before the stuck check, which is quicker for pinned swaps
+        pins.mark(&mut views);
+        stuck.mark(&mut views, instance.role);
+        watchlist.mark(&mut views);
+        scan.durations.annotate(&mut views, instance.role);
+        if let Some(ref notes) = notes {
+            if let Err(err) = notes.annotate(&mut views).await {
+                report_error(output == OutputFormat::Json, &format!("{err:#}"));
+            }
+        }
+        health = views.iter().map(Health::of).fold(health, Health::max);
+        chain.annotate(&instance.name, &mut views, instance.role);
+        loaded.push((instance, path, views, scan.anomalies));
+        unread.push(scan.unloaded);
+    }
+    if nagios {
+        println!("{}", nagios_line(health, &errors, &loaded));
+        return Ok(health);
+    }
+    // One round of lookups for everything the views asked for
+    chain.refresh().await;
+    if let Some(err) = chain.error() {
+        report_error(output == OutputFormat::Json, &err);
+    }
+
+    for (i, (instance, path, mut views, anomalies)) in loaded.into_iter().enumerate() {
+        chain.annotate(&instance.name, &mut views, instance.role);
+        if multi {
+            for view in &mut views {
+                view.instance = Some(instance.name.clone());
+            }
+        }
+        // The exit status above still covers every swap
+        let shown: Vec<SwapView> = filter
+            .apply(&views, instance.role)
+            .into_iter()
+            .cloned()
+            .collect();
+
+        if output == OutputFormat::Json {
+            all_views.extend(shown);
+            continue;
+        }
+
+        if i > 0 {
+            println!();
+        }
+        let label = multi.then_some(instance.name.as_str());
+        render_header(
+            &Some(path),
+            instance.network,
+            instance.role,
+            label,
+            chain,
+            rendezvous,
+            columns.with_peers(&shown).header_width(),
+        );
+        if views.is_empty() {
+            println!("{}", "No swaps yet.".warn());
+        } else if shown.is_empty() {
+            println!("{}", filter.empty_line());
+        } else {
+            render_table(&shown, instance.network, instance.role, &columns);
+        }
+        if let Some(line) = filter.hidden_line(&views, instance.role) {
+            println!("{line}");
+        }
+        if let Some(line) = unloaded_line(unread[i]) {
+            println!("{line}");
+        }
+        for line in anomaly_lines(&anomalies, instance.network, instance.role) {
+            println!("{line}");
+        }
+    }
+
+    if output == OutputFormat::Json {
+        println!("{}", serde_json::to_string_pretty(&all_views)?);
+    }
+    Ok(health)
+}
+
+/// `STATUS - message | perfdata`, as Nagios and Icinga plugins print it.
+/// The message names the swaps behind a warning or critical status, in
+/// full so they can be passed to `show`.
+fn nagios_line(
+    health: Health,
+    errors: &[String],
+    loaded: &[(&Instance, PathBuf, Vec<SwapView>, Vec<Anomaly>)],
+) -> String {
+    let multi = loaded.len() + errors.len() > 1;
+    let (mut total, mut in_progress, mut completed) = (0, 0, 0);
+    let mut stuck = Vec::new();
+    let mut failed = Vec::new();
+    for (instance, _, views, _) in loaded {
+        for view in views {
+            total += 1;
+            match view.state.outcome(instance.role) {
+                Outcome::Completed => completed += 1,
+                Outcome::InProgress => in_progress += 1,
+                Outcome::Failed => {}
+            }
+            let id = match multi {
+                true => format!("{}/{}", instance.name, view.swap_id),
+                false => view.swap_id.clone(),
+            };
+            match Health::of(view) {
+                Health::Failed => failed.push(format!("{id} ({})", view.state)),
+                Health::Stuck => stuck.push(format!("{id} ({})", view.state)),
+                _ => {}
+            }
+        }
+    }
+
+    let (status, message) = match health {
+        Health::Healthy => (
+            "OK",
+            format!("{total} swaps, {in_progress} in progress, none stuck"),
+        ),
+        Health::Stuck => (
+            "WARNING",
+            format!("{} stuck: {}", plural(stuck.len()), stuck.join(", ")),
+        ),
+        Health::Failed => (
+            "CRITICAL",
+            format!(
+                "{} punished or refunded: {}",
+                plural(failed.len()),
+                failed.join(", ")
+            ),
+        ),
+        Health::Unreachable => ("UNKNOWN", errors.join("; ")),
+    };
+    // Warn above 0 stuck, go critical above 0 punished or refunded
+    format!(
+        "{status} - {message} | total={total};;;0 in_progress={in_progress};;;0 \
+         completed={completed};;;0 stuck={};0;;0 failed={};;0;0",
+        stuck.len(),
+        failed.len()
+    )
+}
+
+fn plural(count: usize) -> String {
+    match count {
+        1 => "1 swap".to_string(),
+        n => format!("{n} swaps"),
+    }
+}
+
+/// Print one swap's history from whichever instance knows it.
+pub async fn run_show(
+    instances: &[Instance],
+    output: OutputFormat,
+    swap_id: &str,
+    chain: &ChainMonitor,
+    explorer: &Explorer,
+    mut asb_log: Option<AsbLog>,
+    notes: Option<&NotesStore>,
+) -> Result<()> {
+    let multi = instances.len() > 1;
+    let annotations = match notes {
+        Some(notes) => notes.annotations(Some(swap_id)).await?.pop(),
+        None => None,
+    };
+
+    for instance in instances {
+        let (_, pool) = open_instance(instance, multi).await?;
+        let history = fetch_history(&pool, swap_id)
+            .await
+            .context("query swap history")?;
+        pool.close().await;
+
+        if history.is_empty() {
+            continue;
+        }
+
+        match output {
+            OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&history)?),
+            OutputFormat::Table | OutputFormat::Nagios => {
+                if multi {
+                    println!("{}", format!("Instance: {}", instance.name).dimmed());
+                }
+                let mut details = SwapDetails::default();
+                for entry in &history {
+                    details.merge(entry.details.clone());
+                }
+                let confirmations = chain.lookup_details(&details).await.unwrap_or_else(|err| {
+                    report_error(false, &format!("Chain lookup failed: {err:#}"));
+                    TxConfirmations::default()
+                });
+                for line in history_lines(
+                    swap_id,
+                    &history,
+                    instance.network,
+                    instance.role,
+                    &confirmations,
+                    explorer,
+                ) {
+                    println!("{line}");
+                }
+                if let Some(ref annotations) = annotations {
+                    println!();
+                    for line in annotation_lines(annotations) {
+                        println!("{line}");
+                    }
+                }
+                if let Some(ref mut log) = asb_log {
+                    log.poll();
+                    println!();
+                    let lines = log_lines(
+                        log,
+                        swap_id,
+                        &history,
+                        instance.network,
+                        instance.role,
+                        usize::MAX,
+                    );
+                    for line in lines {
+                        println!("{line}");
+                    }
+                }
+            }
+        }
+        return Ok(());
+    }
+
+    bail!("no swap with id {swap_id}");
+}
+
+/// Open one of a swap's BTC transactions in the block explorer, or print
+/// its URL when there is no browser to open it in or output is piped.
+pub async fn run_open(
+    instances: &[Instance],
+    swap_id: &str,
+    tx: Option<&str>,
+    explorer: &Explorer,
+) -> Result<()> {
+    let multi = instances.len() > 1;
+
+    for instance in instances {
+        let (_, pool) = open_instance(instance, multi).await?;
+        let history = fetch_history(&pool, swap_id)
+            .await
+            .context("query swap history")?;
+        pool.close().await;
+
+        if history.is_empty() {
+            continue;
+        }
+
+        let mut details = SwapDetails::default();
+        for entry in history {
+            details.merge(entry.details);
+        }
+        let txid = match tx {
+            Some(tx) => details
+                .txids
+                .get(&format!("btc_{tx}"))
+                .with_context(|| format!("swap {swap_id} has no {tx} transaction"))?,
+            None => {
+                details
+                    .latest_btc_tx()
+                    .with_context(|| format!("swap {swap_id} has no BTC transaction yet"))?
+                    .1
+            }
+        };
+        let url = explorer.tx_url(instance.network, txid);
+
+        if !io::stdout().is_terminal() {
+            println!("{url}");
+            return Ok(());
+        }
+        match explorer::open(&url).await {
+            Ok(true) => println!("Opened {url}"),
+            Ok(false) => println!("{url}"),
+            Err(err) => {
+                report_error(false, &format!("Couldn't open a browser: {err:#}"));
+                println!("{url}");
+            }
+        }
+        return Ok(());
+    }
+
+    bail!("no swap with id {swap_id}");
+}
+
+pub async fn run_stats(
+    instances: &[Instance],
+    output: OutputFormat,
+    range: &DateRange,
+    chart: Option<Duration>,
+) -> Result<()> {
+    let multi = instances.len() > 1;
+    let mut all_stats = Vec::new();
+
+    for instance in instances {
+        let (_, pool) = open_instance(instance, multi).await?;
+        let mut rows = fetch_swaps(&pool).await.context("query swaps")?;
+        rows.retain(|row| range.contains(&row.entered_at));
+        let total_state_rows = count_state_rows(&pool).await.context("count state rows")?;
+        let schema = Schema::detect(&pool).await?;
+        pool.close().await;
+
+        all_stats.push((
+            instance,
+            SwapStats {
+                schema: Some(schema),
+                activity: chart.map(|window| {
+                    Activity::new(rows.iter().map(|row| row.started_at.as_str()), window)
+                }),
+                ..SwapStats::from_latest(&rows, total_state_rows, instance.role)
+            },
+        ));
+    }
+
+    match output {
+        OutputFormat::Json if multi => {
+            let tagged: Vec<_> = all_stats
+                .iter()
+                .map(|(instance, stats)| Tagged {
+                    instance: Some(&instance.name),
+                    record: stats,
+                })
+                .collect();
+            println!("{}", serde_json::to_string_pretty(&tagged)?);
+        }
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&all_stats[0].1)?),
+        OutputFormat::Table | OutputFormat::Nagios => {
+            for (i, (instance, stats)) in all_stats.iter().enumerate() {
+                if multi {
+                    if i > 0 {
+                        println!();
+                    }
+                    println!("{}", instance.name.bold());
+                }
+                render_stats(stats, instance.network, instance.role);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Counterparties and how many swaps each did, most first.
+pub async fn run_peers(instances: &[Instance], output: OutputFormat) -> Result<()> {
+    let multi = instances.len() > 1;
+    let mut all_peers = Vec::new();
+
+    for instance in instances {
+        let (_, pool) = open_instance(instance, multi).await?;
+        let rows = fetch_swaps(&pool).await.context("query swaps")?;
+        let mut addresses = fetch_peer_addresses(&pool)
+            .await
+            .context("query peer addresses")?;
+        pool.close().await;
+
+        let mut peers = PeerStats::from_latest(&rows, instance.role);
+        for peer in &mut peers {
+            peer.addresses = addresses.remove(&peer.peer_id).unwrap_or_default();
+        }
+        let unknown = rows.iter().filter(|row| row.peer_id.is_none()).count();
+        all_peers.push((instance, peers, unknown));
+    }
+
+    match output {
+        OutputFormat::Json => {
+            let tagged: Vec<_> = all_peers
+                .iter()
+                .flat_map(|(instance, peers, _)| {
+                    peers.iter().map(|peer| Tagged {
+                        instance: multi.then_some(instance.name.as_str()),
+                        record: peer,
+                    })
+                })
+                .collect();
+            println!("{}", serde_json::to_string_pretty(&tagged)?);
+        }
+        OutputFormat::Table | OutputFormat::Nagios => {
+            for (i, (instance, peers, unknown)) in all_peers.iter().enumerate() {
+                if multi {
+                    if i > 0 {
+                        println!();
+                    }
+                    println!("{}", instance.name.bold());
+                }
+                render_peers(peers, *unknown);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// The rate each recent swap was agreed at against the market when it
+/// started, to check the ask spread is actually captured.
+pub async fn run_rates(
+    instances: &[Instance],
+    output: OutputFormat,
+    source: &PriceSource,
+    limit: usize,
+    spread: f64,
+) -> Result<()> {
+    let multi = instances.len() > 1;
+    let mut reports = Vec::new();
+
+    for instance in instances {
+        let (_, pool) = open_instance(instance, multi).await?;
+        let rows = fetch_swaps(&pool).await.context("query swaps")?;
+        pool.close().await;
+        reports.push((instance, RateRow::recent(&rows, limit)));
+    }
+
+    // One round of lookups for every instance, so exchanges are asked for
+    // each stretch of history once
+    let times: Vec<_> = reports
+        .iter()
+        .flat_map(|(_, rows)| rows.iter().filter_map(RateRow::started))
+        .collect();
+    match source.rates_at(&times).await {
+        Ok(rates) => {
+            let mut rates = rates.into_iter();
+            for row in reports.iter_mut().flat_map(|(_, rows)| rows.iter_mut()) {
+                if row.started().is_some() {
+                    row.set_market_rate(rates.next().flatten());
+                }
+            }
+        }
+        Err(err) => report_error(output == OutputFormat::Json, &format!("{source}: {err:#}")),
+    }
+
+    match output {
+        OutputFormat::Json => {
+            let tagged: Vec<_> = reports
+                .iter()
+                .flat_map(|(instance, rows)| {
+                    rows.iter().map(|row| Tagged {
+                        instance: multi.then_some(instance.name.as_str()),
+                        record: row,
+                    })
+                })
+                .collect();
+            println!("{}", serde_json::to_string_pretty(&tagged)?);
+        }
+        OutputFormat::Table | OutputFormat::Nagios => {
+            for (i, (instance, rows)) in reports.iter().enumerate() {
+                if multi {
+                    if i > 0 {
+                        println!();
+                    }
+                    println!("{}", instance.name.bold());
+                }
+                render_rates(rows, instance.role, spread);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Realized spread of completed swaps, per period. `fiat` prices the fiat
+/// column when `--btc-price` doesn't.
+pub async fn run_earnings(
+    instances: &[Instance],
+    output: OutputFormat,
+    options: &EarningsOptions,
+    fiat: Option<&FiatRates>,
+) -> Result<()> {
+    let multi = instances.len() > 1;
+    let mut options = options.clone();
+    let mut offline_since = None;
+    if let (None, Some(rates)) = (options.btc_price, fiat) {
+        match rates.price().await {
+            Ok(price) => {
+                options.btc_price = Some(price.per_btc);
+                offline_since = price.stale.then_some(price.at);
+            }
+            Err(err) => report_error(
+                output == OutputFormat::Json,
+                &format!("fiat price: {err:#}"),
+            ),
+        }
+    }
+    let mut reports = Vec::new();
+
+    for instance in instances {
+        if instance.role == Role::Taker {
+            if multi {
+                bail!(
+                    "earnings only apply to ASB instances; {} is a taker",
+                    instance.name
+                );
+            }
+            bail!("earnings only apply to ASB (--role maker)");
+        }
+        let (_, pool) = open_instance(instance, multi).await?;
+        let rows = fetch_swaps(&pool).await.context("query swaps")?;
+        pool.close().await;
+
+        reports.push((instance, earnings(&rows, &options)));
+    }
+
+    match output {
+        OutputFormat::Json => {
+            let tagged: Vec<_> = reports
+                .iter()
+                .flat_map(|(instance, report)| {
+                    report.iter().map(|row| Tagged {
+                        instance: multi.then_some(instance.name.as_str()),
+                        record: row,
+                    })
+                })
+                .collect();
+            println!("{}", serde_json::to_string_pretty(&tagged)?);
+        }
+        OutputFormat::Table | OutputFormat::Nagios => {
+            for (i, (instance, report)) in reports.iter().enumerate() {
+                if multi {
+                    if i > 0 {
+                        println!();
+                    }
+                    println!("{}", instance.name.bold());
+                }
+                render_earnings(report, &options);
+            }
+            if let Some(at) = offline_since {
+                let at = at.with_timezone(&Local).format("%Y-%m-%d %H:%M");
+                println!(
+                    "{}",
+                    format!("Price source unreachable; using the BTC price as of {at}.").dimmed()
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+/// What `export` writes and where.
+pub struct ExportOptions {
+    pub format: ExportFormat,
+    /// Stdout when unset
+    pub file: Option<PathBuf>,
+    /// Each swap's latest state instead of the full transition log
+    pub latest: bool,
+    /// CSV and Parquet columns; empty means the default set
+    pub columns: Vec<Column>,
+    /// Market rates for the accounting layout
+    pub price_source: PriceSource,
+    /// Rows whose state was entered in this range
+    pub range: DateRange,
+}
+
+pub async fn run_export(instances: &[Instance], options: ExportOptions) -> Result<()> {
+    if options.format == ExportFormat::Accounting {
+        let (count, body) =
+            accounting_export(instances, &options.price_source, &options.range).await?;
+        return write_export(options.file, count, body.into_bytes());
+    }
+
+    let multi = instances.len() > 1;
+    let mut all_rows = Vec::new();
+
+    for instance in instances {
+        let (_, pool) = open_instance(instance, multi).await?;
+        let mut rows = if options.latest {
+            fetch_swaps(&pool).await.context("query swaps")?
+        } else {
+            fetch_all_states(&pool).await.context("query swap states")?
+        };
+        pool.close().await;
+        rows.retain(|row| options.range.contains(&row.entered_at));
+
+        let name = multi.then_some(instance.name.as_str());
+        all_rows.extend(rows.into_iter().map(|row| (name, row)));
+    }
+
+    let columns = if options.columns.is_empty() {
+        csv::DEFAULT_COLUMNS
+    } else {
+        options.columns.as_slice()
+    };
+    let body = match options.format {
+        ExportFormat::Json => {
+            let tagged: Vec<_> = all_rows
+                .iter()
+                .map(|(instance, row)| Tagged {
+                    instance: *instance,
+                    record: row,
+                })
+                .collect();
+            (serde_json::to_string_pretty(&tagged)? + "\n").into_bytes()
+        }
+        ExportFormat::Csv => csv::write(&all_rows, columns).into_bytes(),
+        ExportFormat::Parquet => parquet::write(&all_rows, columns)?,
+        ExportFormat::Accounting => unreachable!("handled above"),
+    };
+    write_export(options.file, all_rows.len(), body)
+}
+
+/// Completed swaps of every instance as trades, oldest first, with the
+/// market rate when each started.
+async fn accounting_export(
+    instances: &[Instance],
+    source: &PriceSource,
+    range: &DateRange,
+) -> Result<(usize, String)> {
+    let multi = instances.len() > 1;
+    let mut trades = Vec::new();
+    for instance in instances {
+        let (_, pool) = open_instance(instance, multi).await?;
+        let mut rows = fetch_swaps(&pool).await.context("query swaps")?;
+        pool.close().await;
+        // A completed swap's latest state is when it completed
+        rows.retain(|row| range.contains(&row.entered_at));
+
+        let name = multi.then_some(instance.name.as_str());
+        trades.extend(
+            AccountingRow::completed(&rows, instance.role)
+                .into_iter()
+                .map(|row| (name, row)),
+        );
+    }
+    trades.sort_by_key(|(_, row)| row.completed_at);
+
+    // A missing rate leaves its cells empty rather than failing the export
+    let times: Vec<_> = trades.iter().map(|(_, row)| row.started_at).collect();
+    match source.rates_at(&times).await {
+        Ok(rates) => {
+            for ((_, row), rate) in trades.iter_mut().zip(rates) {
+                row.market_rate = rate;
+            }
+        }
+        Err(err) => report_error(true, &format!("{source}: {err:#}")),
+    }
+
+    Ok((trades.len(), csv::write_accounting(&trades)))
+}
+
+fn write_export(file: Option<PathBuf>, rows: usize, body: Vec<u8>) -> Result<()> {
+    match file {
+        Some(path) => {
+            std::fs::write(&path, body).with_context(|| format!("write {}", path.display()))?;
+            eprintln!("Exported {rows} rows to {}", path.display());
+        }
+        None => std::io::stdout()
+            .write_all(&body)
+            .context("write export to stdout")?,
+    }
+
+    Ok(())
+}
+
+/// Transitions recorded in the history database, oldest first.
+pub async fn run_history(
+    instances: &[Instance],
+    output: OutputFormat,
+    history: &HistoryStore,
+    swap_id: Option<&str>,
+) -> Result<()> {
+    let multi = instances.len() > 1;
+    let mut all = Vec::new();
+    for instance in instances {
+        let mut transitions = history.transitions(&instance.name, swap_id).await?;
+        for transition in &mut transitions {
+            transition.network = instance.network;
+            transition.instance = multi.then(|| instance.name.clone());
+        }
+        all.push((instance, transitions));
+    }
+
+    match output {
+        OutputFormat::Json => {
+            let flat: Vec<_> = all.iter().flat_map(|(_, t)| t).collect();
+            println!("{}", serde_json::to_string_pretty(&flat)?);
+        }
+        OutputFormat::Table | OutputFormat::Nagios => {
+            if all.iter().all(|(_, t)| t.is_empty()) {
+                println!("{}", "No transitions recorded yet.".warn());
+                return Ok(());
+            }
+            for (instance, transitions) in &all {
+                if multi {
+                    println!("{}", format!("Instance: {}", instance.name).dimmed());
+                }
+                render_transitions(transitions, instance.role);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Fail unless one of the databases has a swap with exactly this id, so a
+/// typo doesn't leave a note nothing will ever show.
+async fn require_swap(instances: &[Instance], swap_id: &str) -> Result<()> {
+    let multi = instances.len() > 1;
+    for instance in instances {
+        let (_, pool) = open_instance(instance, multi).await?;
+        let history = fetch_history(&pool, swap_id)
+            .await
+            .context("query swap history")?;
+        pool.close().await;
+        if !history.is_empty() {
+            return Ok(());
+        }
+    }
+    bail!("no swap with id {swap_id}");
+}
+
+/// Add a note to a swap.
+pub async fn run_note(
+    instances: &[Instance],
+    notes: &NotesStore,
+    swap_id: &str,
+    text: &str,
+) -> Result<()> {
+    require_swap(instances, swap_id).await?;
+    notes.add_note(swap_id, text).await?;
+    println!("Added a note to {swap_id}");
+    Ok(())
+}
+
+/// Tag a swap, or with `remove` take the tags off again. Untagging doesn't
+/// need the swap to still be in a database.
+pub async fn run_tag(
+    instances: &[Instance],
+    notes: &NotesStore,
+    swap_id: &str,
+    tags: &[String],
+    remove: bool,
+) -> Result<()> {
+    if remove {
+        match notes.remove_tags(swap_id, tags).await? {
+            0 => bail!("swap {swap_id} has none of those tags"),
+            _ => println!("Untagged {swap_id}: {}", tags.join(", ")),
+        }
+        return Ok(());
+    }
+    require_swap(instances, swap_id).await?;
+    notes.add_tags(swap_id, tags).await?;
+    println!("Tagged {swap_id}: {}", tags.join(", "));
+    Ok(())
+}
+
+/// Every swap with notes or tags, only those with one of `tags` when set.
+pub async fn run_notes(notes: &NotesStore, output: OutputFormat, tags: &[String]) -> Result<()> {
+    let mut all = notes.annotations(None).await?;
+    if !tags.is_empty() {
+        all.retain(|a| a.tags.iter().any(|tag| tags.contains(tag)));
+    }
+    match output {
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&all)?),
+        OutputFormat::Table | OutputFormat::Nagios => {
+            if all.is_empty() {
+                let line = match tags.is_empty() {
+                    true => "No notes or tags yet.".to_string(),
+                    false => format!("No swaps tagged {}.", tags.join(" or ")),
+                };
+                println!("{}", line.warn());
+                return Ok(());
+            }
+            render_annotations(&all);
+        }
+    }
+    Ok(())
+}
+
+/// Send a test alert through the notifiers and report each one's outcome.
+/// Any failure makes the command fail too, so it can run from a script.
+pub async fn run_notify_test(
+    notifiers: &Notifiers,
+    output: OutputFormat,
+    channel: Option<&str>,
+) -> Result<()> {
+    let outcomes = notifiers.test(channel).await?;
+    match output {
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&outcomes)?),
+        OutputFormat::Table | OutputFormat::Nagios => {
+            for outcome in &outcomes {
+                match outcome.error {
+                    None => println!("{} {}", plain("✓".into()).good(), outcome.channel),
+                    Some(ref err) => {
+                        println!("{} {}: {err}", plain("✗".into()).bad(), outcome.channel)
+                    }
+                }
+            }
+        }
+    }
+
+    let failed = outcomes.iter().filter(|o| o.error.is_some()).count();
+    if failed > 0 {
+        bail!("{failed} of {} notifiers failed", outcomes.len());
+    }
+    Ok(())
+}
+
+/// Check an ASB config for common mistakes and report each finding. Any
+/// error makes the command fail, so it can gate ASB's start.
+pub async fn run_check_config(path: &Path, output: OutputFormat) -> Result<()> {
+    let findings = AsbConfig::load(path)?.check().await;
+    match output {
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&findings)?),
+        OutputFormat::Table | OutputFormat::Nagios => {
+            for finding in &findings {
+                let mark = match finding.level {
+                    Level::Ok => plain("✓".into()).good(),
+                    Level::Warning => "!".warn(),
+                    Level::Error => plain("✗".into()).bad(),
+                };
+                println!("{mark} {}: {}", finding.check, finding.message);
+            }
+        }
+    }
+
+    let errors = findings.iter().filter(|f| f.level == Level::Error).count();
+    if errors > 0 {
+        bail!("{errors} problems in {}", path.display());
+    }
+    Ok(())
+}
+
+/// Emit one NDJSON line per observed transition. Swaps already present at
+/// startup form the baseline and are not reported, unless a history
+/// database says they changed since the last run.
+pub async fn run_tail(
+    instances: &[Instance],
+    mut schedule: RefreshSchedule,
+    fs_watch: bool,
+    stuck: &StuckRules,
+    watchlist: &Watchlist,
+    pins: &PinnedSwaps,
+    history: Option<&HistoryStore>,
+) -> Result<()> {
+    let mut monitors = Monitor::for_instances(instances, stuck, watchlist, pins, history);
+    let mut watcher = db_watcher(instances, None, fs_watch);
+    let multi = monitors.len() > 1;
+
+    loop {
+        let mut activity = false;
+        let mut any_ok = false;
+        for monitor in &mut monitors {
+            match monitor.poll().await {
+                Ok(rows) => {
+                    let (_, transitions) = monitor.update(rows).await;
+                    for transition in &transitions {
+                        println!("{}", serde_json::to_string(transition)?);
+                    }
+                    activity |= !transitions.is_empty();
+                    any_ok = true;
+                }
+                // Waiting for ASB to create the database isn't worth a line per poll
+                Err(PollError::NotFound(_)) => {}
+                Err(PollError::NoDataDir) => bail!("{}", PollError::NoDataDir),
+                Err(err) if multi => {
+                    report_error(true, &format!("{}: {err}", monitor.instance.name))
+                }
+                Err(err) => report_error(true, &err.to_string()),
+            }
+        }
+        if any_ok {
+            schedule.record(activity);
+        }
+        if let Some(err) = history.and_then(HistoryStore::take_error) {
+            report_error(true, &format!("History write failed: {err}"));
+        }
+
+        let wait = schedule.next_wait(&watcher);
+        tokio::select! {
+            _ = sleep(wait) => {}
+            _ = watcher.changed() => {}
+        }
+    }
+}
@@ -0,0 +1,1089 @@
+//! Optional config file, `~/.config/wraithswap/config.toml` by default.
+//!
+//! Every key mirrors a command-line option. Flags win over environment
+//! variables, which win over the file, which wins over built-in defaults.
+
+use anyhow::{bail, Context, Result};
+use serde::{de, Deserialize, Deserializer};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::asblog::{parse_level, LogMatch, LogRule};
+use crate::bell;
+use crate::cli::{
+    parse_amount, parse_duration, parse_quiet_hours, parse_spread, parse_time, parse_time_bound,
+    Args, Clock, ColorChoice, TimeFormat, BTC_DECIMALS, XMR_DECIMALS,
+};
+use crate::columns;
+use crate::db::InstanceSpec;
+use crate::logging::LogFormat;
+use crate::model::{Network, Period, Role};
+use crate::notes;
+use crate::otel;
+use crate::rules::{Rule, Severity};
+use crate::theme::{self, ThemeName};
+use crate::toml;
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub db_path: Option<PathBuf>,
+    /// ASB's own config.toml, same as `--asb-config`
+    pub asb_config: Option<PathBuf>,
+    /// How to tell whether ASB is running
+    pub asb: AsbLivenessConfig,
+    pub reachability: ReachabilityConfig,
+    /// Multiaddresses ASB registers at, same as `--rendezvous-point`
+    pub rendezvous_points: Vec<String>,
+    pub quote: QuoteConfig,
+    /// ASB's log file, same as `--asb-log`
+    pub asb_log: Option<PathBuf>,
+    /// What in ASB's log raises an alert
+    pub log_alerts: Vec<LogAlertConfig>,
+    /// Same as `--log-alert-window`
+    #[serde(deserialize_with = "duration")]
+    pub log_alert_window: Option<Duration>,
+    pub network: Option<Network>,
+    pub role: Option<Role>,
+    /// Which swaps `list` and `watch` show, same as `--state`
+    #[serde(deserialize_with = "one_or_many")]
+    pub state: Vec<String>,
+    /// Same as `--hide-finished`
+    pub hide_finished: Option<bool>,
+    /// Same as `--board`
+    pub board: Option<bool>,
+    /// Same as `--chart` and `--chart-window`
+    pub chart: Option<bool>,
+    #[serde(deserialize_with = "duration")]
+    pub chart_window: Option<Duration>,
+    /// Same as `--ticker`
+    pub ticker: Option<usize>,
+    /// Same as `--fade`
+    #[serde(deserialize_with = "duration")]
+    pub fade: Option<Duration>,
+    /// Same as `--since` and `--until`: a date or a duration ago
+    pub since: Option<String>,
+    pub until: Option<String>,
+    /// Same as `--sort`, e.g. `"in_state"` or `"amount:asc"`
+    pub sort: Option<String>,
+    /// Same as `--table-columns`, e.g. `["id", "state", "duration", "btc"]`
+    #[serde(deserialize_with = "one_or_many")]
+    pub table_columns: Vec<String>,
+    /// Same as `--page-size`
+    pub page_size: Option<usize>,
+    /// Swaps to keep at the top of the table, same as `--pin`
+    #[serde(deserialize_with = "one_or_many")]
+    pub pin: Vec<String>,
+    /// Only swaps with one of these tags, same as `--tag`
+    #[serde(deserialize_with = "one_or_many")]
+    pub tag: Vec<String>,
+    /// Seconds between polls
+    pub interval: Option<u64>,
+    pub adaptive: Option<bool>,
+    pub fs_watch: Option<bool>,
+    pub immutable: Option<bool>,
+    /// `user@host` to read the databases from over SSH
+    pub remote: Option<String>,
+    pub color: Option<ColorChoice>,
+    /// Same as `--theme`
+    pub theme: Option<ThemeName>,
+    /// A style per state or group of states, same as `--state-color`
+    pub state_colors: BTreeMap<String, String>,
+    /// `local`, `utc` or `relative`, same as `--time`
+    pub time: Option<TimeFormat>,
+    /// `24h` or `12h`, same as `--clock`
+    pub clock: Option<Clock>,
+    /// Same as `--wide`
+    pub wide: Option<bool>,
+    /// Same as `--redact`
+    pub redact: Option<bool>,
+    /// Same as `--ascii`
+    pub ascii: Option<bool>,
+    pub instances: Vec<InstanceSpec>,
+    pub notify: NotifyConfig,
+    /// `ssl://host:port` or `tcp://host:port`
+    pub electrum: Option<String>,
+    /// Same as `--electrum-fingerprint`
+    pub electrum_fingerprint: Option<String>,
+    /// Base URL for transaction links, same as `--explorer`
+    pub explorer: Option<String>,
+    /// `kraken`, `binance` or a price server URL, same as `--price-source`
+    pub price_source: Option<String>,
+    pub monero_rpc: Option<MoneroRpcConfig>,
+    /// Account xpub or `wpkh(...)` descriptor of ASB's Bitcoin wallet
+    pub btc_wallet: Option<String>,
+    pub balance: Option<bool>,
+    /// Daemon mode logging
+    pub log: LogConfig,
+    /// Where to record observed transitions
+    pub history_db: Option<PathBuf>,
+    /// Where notes and tags are kept, same as `--notes-db`
+    pub notes_db: Option<PathBuf>,
+    pub digest: DigestConfig,
+    pub liquidity: LiquidityConfig,
+    pub fees: FeesConfig,
+    pub fiat: FiatConfig,
+    pub earnings: EarningsConfig,
+    pub serve: ServeConfig,
+    pub agent: AgentConfig,
+    pub peers: PeersConfig,
+    /// Stuck thresholds keyed by state, plus `default`
+    #[serde(deserialize_with = "duration_map")]
+    pub stuck: BTreeMap<String, Duration>,
+    /// Alert rules, tried in order
+    pub rules: Vec<RuleConfig>,
+    pub on_transition: HookConfig,
+    pub bell: BellConfig,
+    pub influx: InfluxConfig,
+    pub heartbeat: HeartbeatConfig,
+    pub otlp: OtlpConfig,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct NotifyConfig {
+    /// Desktop notifications, same as `--notify`
+    pub desktop: bool,
+    /// Alert rules: only these states trigger notifications; empty means all
+    pub states: Vec<String>,
+    pub webhooks: Vec<String>,
+    pub discord: Vec<DiscordConfig>,
+    pub webhook_timeout: Option<u64>,
+    pub telegram: Option<TelegramConfig>,
+    pub ntfy: Option<NtfyConfig>,
+    pub matrix: Option<MatrixConfig>,
+    pub pushover: Option<PushoverConfig>,
+    pub gotify: Option<GotifyConfig>,
+    pub email: Option<EmailConfig>,
+    pub pagerduty: Option<PagerDutyConfig>,
+    pub opsgenie: Option<OpsgenieConfig>,
+    pub mqtt: Option<MqttConfig>,
+    /// Default for rules without their own `cooldown`
+    #[serde(deserialize_with = "duration")]
+    pub cooldown: Option<Duration>,
+    /// `HH:MM-HH:MM` windows, same as `--quiet-hours`
+    #[serde(deserialize_with = "one_or_many")]
+    pub quiet_hours: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct NtfyConfig {
+    pub topic: String,
+    /// Self-hosted ntfy; ntfy.sh when unset
+    pub server: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DiscordConfig {
+    /// Lets rules pick this channel as `discord:<name>`
+    pub name: Option<String>,
+    pub url: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PushoverConfig {
+    /// Application token
+    pub token: String,
+    /// User or group key
+    pub user: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PagerDutyConfig {
+    /// Events API v2 integration key
+    pub routing_key: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct OpsgenieConfig {
+    pub key: String,
+    /// `https://api.eu.opsgenie.com` for EU accounts
+    pub api_url: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MqttConfig {
+    /// `mqtts://host[:port]` or `mqtt://host[:port]`
+    pub broker: String,
+    /// Template with `{placeholders}`
+    pub topic: Option<String>,
+    /// 0, 1 or 2
+    pub qos: Option<u8>,
+    #[serde(default)]
+    pub retain: bool,
+    pub user: Option<String>,
+    pub password: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EmailConfig {
+    /// `smtps://host:465` or `smtp://host:587`
+    pub server: String,
+    pub user: Option<String>,
+    pub password: Option<String>,
+    pub from: String,
+    #[serde(deserialize_with = "one_or_many")]
+    pub to: Vec<String>,
+    /// Templates with `{placeholders}`
+    pub subject: Option<String>,
+    pub body: Option<String>,
+    #[serde(default, deserialize_with = "duration")]
+    pub batch: Option<Duration>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct GotifyConfig {
+    pub server: String,
+    /// Application token
+    pub token: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MatrixConfig {
+    /// e.g. `https://matrix.example.org`
+    pub homeserver: String,
+    pub token: String,
+    /// Room id (`!abc:example.org`), not an alias
+    pub room: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TelegramConfig {
+    pub token: String,
+    /// Telegram chat ids are numbers, but quoting them is just as common
+    #[serde(deserialize_with = "string_or_number")]
+    pub chat_id: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MoneroRpcConfig {
+    pub url: String,
+    /// `user:password`, as passed to monero-wallet-rpc's `--rpc-login`
+    pub login: Option<String>,
+}
+
+/// Liquidity alert thresholds, in XMR and BTC.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LiquidityConfig {
+    #[serde(deserialize_with = "amount")]
+    pub min_xmr: Option<String>,
+    #[serde(deserialize_with = "amount")]
+    pub max_btc: Option<String>,
+}
+
+/// The fee environment, same as `--fees`, `--mempool-url` and
+/// `--max-fee-rate`.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FeesConfig {
+    pub show: Option<bool>,
+    pub mempool_url: Option<String>,
+    /// Next-block rate in sat/vB
+    pub max_rate: Option<f64>,
+}
+
+/// ASB liveness checks, same as `--asb-pid-file`, `--asb-process` and
+/// `--asb-address`.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AsbLivenessConfig {
+    pub pid_file: Option<PathBuf>,
+    pub process: Option<String>,
+    /// `host:port`
+    pub address: Option<String>,
+}
+
+/// Reachability checks, same as `--external-address` and `--tor-socks`.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ReachabilityConfig {
+    /// `host:port`, onion addresses or multiaddresses
+    pub addresses: Vec<String>,
+    pub tor_socks: Option<String>,
+}
+
+/// Quote self-check, same as `--quote-peer`, `--swap-bin` and
+/// `--quote-tolerance`.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct QuoteConfig {
+    pub peer: Option<String>,
+    pub swap_bin: Option<PathBuf>,
+    /// `0.02` or `"2%"`
+    #[serde(deserialize_with = "amount")]
+    pub tolerance: Option<String>,
+}
+
+/// Fiat values, same as `--fiat` and `--currency`.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FiatConfig {
+    pub show: Option<bool>,
+    pub currency: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EarningsConfig {
+    pub period: Option<Period>,
+    /// `0.02` or `"2%"`, like ASB's `ask_spread`
+    #[serde(deserialize_with = "amount")]
+    pub spread: Option<String>,
+    pub currency: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RuleConfig {
+    pub name: Option<String>,
+    /// A state or a list of states
+    #[serde(deserialize_with = "one_or_many")]
+    pub from: Vec<String>,
+    #[serde(deserialize_with = "one_or_many")]
+    pub to: Vec<String>,
+    /// Match stuck alerts instead, e.g. `"2h"`
+    #[serde(deserialize_with = "duration")]
+    pub stuck_for: Option<Duration>,
+    /// Match alerts about transitions the state machine doesn't allow
+    pub anomaly: bool,
+    /// Only match swaps with a peer on the watchlist
+    pub watched: bool,
+    /// Only match pinned swaps
+    pub pinned: bool,
+    #[serde(deserialize_with = "amount")]
+    pub min_btc: Option<String>,
+    #[serde(deserialize_with = "amount")]
+    pub min_xmr: Option<String>,
+    /// Notifier kinds (`desktop`, `webhook`, `telegram`, `ntfy`, `matrix`,
+    /// `discord`, `pushover`, `gotify`, `email`, `pagerduty`, `opsgenie`, `mqtt`) or
+    /// `discord:<name>`
+    #[serde(deserialize_with = "one_or_many")]
+    pub notify: Vec<String>,
+    pub severity: Severity,
+    #[serde(deserialize_with = "duration")]
+    pub cooldown: Option<Duration>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LogAlertConfig {
+    /// Defaults to the keyword or pattern
+    pub name: Option<String>,
+    /// Anywhere in the line, ignoring case
+    pub keyword: Option<String>,
+    /// A regular expression instead, like `(?i)tor.*(failed|error)`
+    pub pattern: Option<String>,
+    /// Only lines at least this severe, e.g. `"warn"`
+    pub level: Option<String>,
+    pub severity: Severity,
+    /// At most one alert per window, e.g. `"30m"`
+    #[serde(deserialize_with = "duration")]
+    pub window: Option<Duration>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DigestConfig {
+    /// `day`, `week` or `month`
+    pub every: Option<Period>,
+    /// Local time, `HH:MM`
+    pub at: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HookConfig {
+    /// Shell command, same as `--on-transition`
+    pub command: Option<String>,
+    #[serde(deserialize_with = "duration")]
+    pub timeout: Option<Duration>,
+    pub concurrency: Option<usize>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BellConfig {
+    /// States that ring the terminal bell, same as `--bell`
+    #[serde(deserialize_with = "one_or_many")]
+    pub states: Vec<String>,
+    /// A sound command per state or group of states, same as
+    /// `--bell-sound`
+    pub sounds: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct InfluxConfig {
+    /// Line-protocol write endpoint, same as `--influx-url`
+    pub url: Option<String>,
+    pub token: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HeartbeatConfig {
+    /// Ping URL, same as `--heartbeat-url`
+    pub url: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct OtlpConfig {
+    /// Collector base URL, same as `--otlp-endpoint`
+    pub endpoint: Option<String>,
+    /// `KEY=VALUE` entries, same as `--otlp-header`
+    pub headers: Vec<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LogConfig {
+    pub format: Option<LogFormat>,
+    pub file: Option<PathBuf>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServeConfig {
+    /// `host:port` for the API, e.g. `0.0.0.0:8080`
+    pub listen: Option<String>,
+    /// PEM certificate chain and private key for HTTPS
+    pub tls_cert: Option<PathBuf>,
+    pub tls_key: Option<PathBuf>,
+    /// HTTPS with a certificate made up at startup
+    pub tls_self_signed: Option<bool>,
+    /// Bearer token every API request must carry
+    pub token: Option<String>,
+    /// Secret agent reports are signed with; enables `/agent/push`
+    pub agent_token: Option<String>,
+    /// Skip local databases and show only what agents report
+    pub agents_only: Option<bool>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AgentConfig {
+    /// Base URL of the central `serve`
+    pub central: Option<String>,
+    /// Defaults to the host name
+    pub name: Option<String>,
+    /// Same secret as the central instance's `serve.agent_token`
+    pub token: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PeersConfig {
+    /// Peer ids to highlight and alert on, same as `--watch-peer`
+    pub watch: Vec<String>,
+}
+
+/// `$XDG_CONFIG_HOME/wraithswap/config.toml`, falling back to `~/.config`
+/// on every platform so the documented path works on macOS too.
+pub fn default_path() -> Option<PathBuf> {
+    let base = std::env::var_os("XDG_CONFIG_HOME")
+        .filter(|v| !v.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| dirs::home_dir().map(|home| home.join(".config")))?;
+    Some(base.join("wraithswap").join("config.toml"))
+}
+
+/// Load `path`, or the default location when `None`. A missing default file
+/// is not an error; a missing explicit one is.
+pub fn load(path: Option<&Path>) -> Result<Config> {
+    let (path, explicit) = match path {
+        Some(path) => (path.to_path_buf(), true),
+        None => match default_path() {
+            Some(path) => (path, false),
+            None => return Ok(Config::default()),
+        },
+    };
+
+    let text = match std::fs::read_to_string(&path) {
+        Ok(text) => text,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound && !explicit => {
+            return Ok(Config::default())
+        }
+        Err(err) => return Err(err).with_context(|| format!("read config {}", path.display())),
+    };
+    let value = toml::parse(&text).with_context(|| format!("parse config {}", path.display()))?;
+    serde_json::from_value(value).with_context(|| format!("invalid config {}", path.display()))
+}
+
+impl Config {
+    /// Use the file's settings as the starting point for `args`.
+    pub fn apply(self, args: &mut Args) -> Result<()> {
+        // A remote path is relative to the remote home, not ours
+        let remote = self.remote.is_some();
+        if let Some(path) = self.db_path {
+            args.db_path = Some(if remote { path } else { expand_home(path) });
+        }
+        if let Some(network) = self.network {
+            args.network = network;
+        }
+        if let Some(role) = self.role {
+            args.role = role;
+        }
+        if let Some(secs) = self.interval {
+            if secs == 0 {
+                bail!("interval must be at least 1 second");
+            }
+            args.interval = Duration::from_secs(secs);
+        }
+        if let Some(adaptive) = self.adaptive {
+            args.adaptive = adaptive;
+        }
+        if let Some(fs_watch) = self.fs_watch {
+            args.fs_watch = fs_watch;
+        }
+        if let Some(immutable) = self.immutable {
+            args.immutable = immutable;
+        }
+        if let Some(remote) = self.remote {
+            args.remote = Some(remote);
+        }
+        if let Some(color) = self.color {
+            args.color = color;
+        }
+        if let Some(theme) = self.theme {
+            args.theme = theme;
+        }
+        let mut state_colors = Vec::new();
+        for (states, style) in &self.state_colors {
+            let states = theme::parse_states(states)
+                .with_context(|| format!("invalid state_colors key {states:?}"))?;
+            let style = style
+                .parse()
+                .with_context(|| format!("invalid state_colors style for {states}"))?;
+            state_colors.push((states, style));
+        }
+        if !state_colors.is_empty() {
+            args.state_colors = state_colors;
+        }
+        if let Some(time) = self.time {
+            args.time = time;
+        }
+        if let Some(clock) = self.clock {
+            args.clock = clock;
+        }
+        if let Some(wide) = self.wide {
+            args.wide = wide;
+        }
+        if let Some(redact) = self.redact {
+            args.redact = redact;
+        }
+        if let Some(ascii) = self.ascii {
+            args.ascii = ascii;
+        }
+        args.instances = self
+            .instances
+            .into_iter()
+            .map(|spec| InstanceSpec {
+                db_path: match remote || spec.remote.is_some() {
+                    true => spec.db_path,
+                    false => spec.db_path.map(expand_home),
+                },
+                ..spec
+            })
+            .collect();
+
+        if let Some(server) = self.electrum {
+            args.electrum = Some(server.parse()?);
+        }
+        if let Some(fingerprint) = self.electrum_fingerprint {
+            args.electrum_fingerprint = Some(
+                fingerprint
+                    .parse()
+                    .context("invalid electrum_fingerprint")?,
+            );
+        }
+        if let Some(explorer) = self.explorer {
+            args.explorer = Some(explorer);
+        }
+        if let Some(source) = self.price_source {
+            args.price_source = Some(source.parse().context("invalid price_source")?);
+        }
+        if let Some(rpc) = self.monero_rpc {
+            args.monero_rpc = Some(rpc.url);
+            args.monero_rpc_login = rpc.login;
+        }
+        if let Some(wallet) = self.btc_wallet {
+            args.btc_wallet = Some(wallet.parse().context("invalid btc_wallet")?);
+        }
+        if let Some(balance) = self.balance {
+            args.balance = balance;
+        }
+        if let Some(format) = self.log.format {
+            args.log_format = Some(format);
+        }
+        if let Some(file) = self.log.file {
+            args.log_file = Some(expand_home(file));
+        }
+        if let Some(path) = self.history_db {
+            args.history_db = Some(expand_home(path));
+        }
+        if let Some(path) = self.notes_db {
+            args.notes_db = Some(expand_home(path));
+        }
+        if let Some(every) = self.digest.every {
+            args.digest = Some(every);
+        }
+        if let Some(at) = self.digest.at {
+            args.digest_at = parse_time(&at).context("invalid digest.at")?;
+        }
+        if let Some(command) = self.on_transition.command {
+            args.on_transition = Some(command);
+        }
+        if let Some(timeout) = self.on_transition.timeout {
+            args.hook_timeout = timeout.max(Duration::from_secs(1));
+        }
+        if let Some(concurrency) = self.on_transition.concurrency {
+            args.hook_concurrency = concurrency;
+        }
+        let mut bells = Vec::new();
+        for (states, command) in &self.bell.sounds {
+            let sound = bell::parse_sound(&format!("{states}={command}"))
+                .with_context(|| format!("invalid bell sound for {states:?}"))?;
+            bells.push(sound);
+        }
+        for states in &self.bell.states {
+            let states = bell::parse_states(states).context("invalid bell.states")?;
+            bells.push((states, None));
+        }
+        if !bells.is_empty() {
+            args.bell = bells;
+        }
+        if let Some(url) = self.influx.url {
+            args.influx_url = Some(url);
+        }
+        if let Some(token) = self.influx.token {
+            args.influx_token = Some(token);
+        }
+        if let Some(url) = self.heartbeat.url {
+            args.heartbeat_url = Some(url);
+        }
+        if let Some(endpoint) = self.otlp.endpoint {
+            args.otlp_endpoint = Some(endpoint);
+        }
+        for header in &self.otlp.headers {
+            args.otlp_headers
+                .extend(otel::parse_headers(header).context("invalid otlp.headers")?);
+        }
+        if let Some(min_xmr) = self.liquidity.min_xmr {
+            args.liquidity.min_xmr =
+                Some(parse_amount(&min_xmr, XMR_DECIMALS).context("invalid liquidity.min_xmr")?);
+        }
+        if let Some(path) = self.asb.pid_file {
+            args.asb.pid_file = Some(expand_home(path));
+        }
+        if let Some(name) = self.asb.process {
+            args.asb.process = Some(name);
+        }
+        if let Some(address) = self.asb.address {
+            args.asb.address = Some(address);
+        }
+        if !self.reachability.addresses.is_empty() {
+            args.external_addresses = self
+                .reachability
+                .addresses
+                .iter()
+                .map(|address| {
+                    address.parse().with_context(|| {
+                        format!("invalid reachability.addresses entry {address:?}")
+                    })
+                })
+                .collect::<Result<_>>()?;
+        }
+        if let Some(socks) = self.reachability.tor_socks {
+            args.tor_socks = socks;
+        }
+        if !self.rendezvous_points.is_empty() {
+            args.rendezvous_points = self.rendezvous_points;
+        }
+        if let Some(peer) = self.quote.peer {
+            args.quote_peer = Some(peer);
+        }
+        if let Some(bin) = self.quote.swap_bin {
+            args.swap_bin = expand_home(bin).display().to_string();
+        }
+        if let Some(tolerance) = self.quote.tolerance {
+            args.quote_tolerance = parse_spread(&tolerance).context("invalid quote.tolerance")?;
+        }
+        if let Some(path) = self.asb_log {
+            args.asb_log = Some(expand_home(path));
+        }
+        for (i, alert) in self.log_alerts.into_iter().enumerate() {
+            let (matcher, source) = match (alert.keyword, alert.pattern) {
+                (Some(keyword), None) => (LogMatch::Keyword(keyword.clone()), keyword),
+                (None, Some(pattern)) => (
+                    LogMatch::Pattern(
+                        pattern
+                            .parse()
+                            .with_context(|| format!("invalid pattern in log alert {}", i + 1))?,
+                    ),
+                    pattern,
+                ),
+                _ => bail!("log alert {} needs a keyword or a pattern, not both", i + 1),
+            };
+            let name = alert.name.unwrap_or(source);
+            args.log_alerts.push(LogRule {
+                level: alert
+                    .level
+                    .map(|level| parse_level(&level))
+                    .transpose()
+                    .with_context(|| format!("invalid level in log alert {name:?}"))?,
+                name,
+                matcher,
+                severity: alert.severity,
+                window: alert.window,
+            });
+        }
+        if let Some(window) = self.log_alert_window {
+            args.log_alert_window = window;
+        }
+        if !self.state.is_empty() {
+            args.states = self.state.join(",").parse().context("invalid state")?;
+        }
+        if let Some(hide) = self.hide_finished {
+            args.hide_finished = hide;
+        }
+        if let Some(board) = self.board {
+            args.board = board;
+        }
+        if let Some(chart) = self.chart {
+            args.chart = chart;
+        }
+        if let Some(window) = self.chart_window {
+            args.chart_window = window;
+        }
+        if let Some(ticker) = self.ticker {
+            args.ticker = ticker;
+        }
+        if let Some(fade) = self.fade {
+            args.fade = fade;
+        }
+        if let Some(since) = self.since {
+            args.range.since = Some(parse_time_bound(&since, false).context("invalid since")?);
+        }
+        if let Some(until) = self.until {
+            args.range.until = Some(parse_time_bound(&until, true).context("invalid until")?);
+        }
+        if let Some(sort) = self.sort {
+            args.sort = Some(sort.parse().context("invalid sort")?);
+        }
+        if !self.table_columns.is_empty() {
+            args.table_columns =
+                Some(columns::parse_list(&self.table_columns).context("invalid table_columns")?);
+        }
+        if let Some(size) = self.page_size {
+            args.page_size = Some(size);
+        }
+        if let Some(show) = self.fees.show {
+            args.fees = show;
+        }
+        if let Some(url) = self.fees.mempool_url {
+            args.mempool_url = Some(url);
+        }
+        if let Some(rate) = self.fees.max_rate {
+            args.max_fee_rate = Some(rate);
+        }
+        if let Some(period) = self.earnings.period {
+            args.earnings.period = period;
+        }
+        if let Some(spread) = self.earnings.spread {
+            args.earnings.spread = parse_spread(&spread).context("invalid earnings.spread")?;
+        }
+        if let Some(currency) = self.earnings.currency {
+            args.earnings.currency = currency.to_uppercase();
+        }
+        if let Some(show) = self.fiat.show {
+            args.fiat = show;
+        }
+        if let Some(currency) = self.fiat.currency {
+            args.earnings.currency = currency.to_uppercase();
+        }
+        if let Some(max_btc) = self.liquidity.max_btc {
+            args.liquidity.max_btc =
+                Some(parse_amount(&max_btc, BTC_DECIMALS).context("invalid liquidity.max_btc")?);
+        }
+        if let Some(listen) = self.serve.listen {
+            args.listen = listen
+                .parse()
+                .with_context(|| format!("invalid serve.listen address: {listen}"))?;
+        }
+        if let Some(path) = self.serve.tls_cert {
+            args.tls_cert = Some(expand_home(path));
+        }
+        if let Some(path) = self.serve.tls_key {
+            args.tls_key = Some(expand_home(path));
+        }
+        if let Some(self_signed) = self.serve.tls_self_signed {
+            args.tls_self_signed = self_signed;
+        }
+        if let Some(token) = self.serve.token {
+            args.api_token = Some(token);
+        }
+        if let Some(token) = self.serve.agent_token.or(self.agent.token) {
+            args.agent_token = Some(token);
+        }
+        if let Some(agents_only) = self.serve.agents_only {
+            args.agents_only = agents_only;
+        }
+        if let Some(central) = self.agent.central {
+            args.central = Some(central);
+        }
+        if let Some(name) = self.agent.name {
+            args.agent_name = Some(name);
+        }
+        args.watch_peers = self.peers.watch;
+        args.pins = self.pin;
+        args.tags = self
+            .tag
+            .iter()
+            .map(|tag| notes::parse_tag(tag))
+            .collect::<Result<_>>()
+            .context("invalid tag")?;
+        for (state, threshold) in self.stuck {
+            args.stuck
+                .set(Some(&state), threshold)
+                .with_context(|| format!("invalid [stuck] entry {state:?}"))?;
+        }
+        for (i, rule) in self.rules.into_iter().enumerate() {
+            let name = rule.name.unwrap_or_else(|| format!("rule {}", i + 1));
+            if rule.anomaly && rule.stuck_for.is_some() {
+                bail!("rule {name:?} can't match both stuck and anomaly alerts");
+            }
+            let amount = |value: Option<String>, decimals, key| {
+                value
+                    .map(|value| parse_amount(&value, decimals))
+                    .transpose()
+                    .with_context(|| format!("invalid {key} in rule {name:?}"))
+            };
+            args.rules.push(Rule {
+                min_btc_sats: amount(rule.min_btc, BTC_DECIMALS, "min_btc")?,
+                min_xmr_piconero: amount(rule.min_xmr, XMR_DECIMALS, "min_xmr")?,
+                name,
+                from: rule.from,
+                to: rule.to,
+                stuck_for: rule.stuck_for,
+                anomaly: rule.anomaly,
+                watched: rule.watched,
+                pinned: rule.pinned,
+                notify: rule.notify,
+                severity: rule.severity,
+                cooldown: rule.cooldown,
+            });
+        }
+
+        let notify = self.notify;
+        args.notify = notify.desktop;
+        args.notify_states = notify.states;
+        args.webhooks = notify.webhooks;
+        args.discord_webhooks = notify
+            .discord
+            .into_iter()
+            .map(|discord| (discord.name, discord.url))
+            .collect();
+        if let Some(secs) = notify.webhook_timeout {
+            args.webhook_timeout = Duration::from_secs(secs.max(1));
+        }
+        if let Some(cooldown) = notify.cooldown {
+            args.alert_cooldown = Some(cooldown);
+        }
+        args.quiet_hours = notify
+            .quiet_hours
+            .iter()
+            .map(|hours| parse_quiet_hours(hours))
+            .collect::<Result<_>>()
+            .context("invalid notify.quiet_hours")?;
+        if let Some(telegram) = notify.telegram {
+            args.telegram_token = Some(telegram.token);
+            args.telegram_chat_id = Some(telegram.chat_id);
+        }
+        if let Some(ntfy) = notify.ntfy {
+            args.ntfy_topic = Some(ntfy.topic);
+            args.ntfy_server = ntfy.server;
+        }
+        if let Some(matrix) = notify.matrix {
+            args.matrix_homeserver = Some(matrix.homeserver);
+            args.matrix_token = Some(matrix.token);
+            args.matrix_room = Some(matrix.room);
+        }
+        if let Some(pushover) = notify.pushover {
+            args.pushover_token = Some(pushover.token);
+            args.pushover_user = Some(pushover.user);
+        }
+        if let Some(pagerduty) = notify.pagerduty {
+            args.pagerduty_key = Some(pagerduty.routing_key);
+        }
+        if let Some(opsgenie) = notify.opsgenie {
+            args.opsgenie_key = Some(opsgenie.key);
+            args.opsgenie_api_url = opsgenie.api_url;
+        }
+        if let Some(email) = notify.email {
+            args.smtp_server = Some(email.server);
+            args.smtp_user = email.user;
+            args.smtp_password = email.password;
+            args.email_from = Some(email.from);
+            args.email_to = email.to;
+            args.email_subject = email.subject;
+            args.email_body = email.body;
+            args.email_batch = email.batch;
+        }
+        if let Some(mqtt) = notify.mqtt {
+            args.mqtt_broker = Some(mqtt.broker.parse()?);
+            args.mqtt_topic = mqtt.topic;
+            if let Some(qos) = mqtt.qos {
+                args.mqtt_qos = qos.to_string().parse()?;
+            }
+            args.mqtt_retain = mqtt.retain;
+            args.mqtt_user = mqtt.user;
+            args.mqtt_password = mqtt.password;
+        }
+        if let Some(gotify) = notify.gotify {
+            args.gotify_server = Some(gotify.server);
+            args.gotify_token = Some(gotify.token);
+        }
+        Ok(())
+    }
+}
+
+pub fn expand_home(path: PathBuf) -> PathBuf {
+    match (path.strip_prefix("~"), dirs::home_dir()) {
+        (Ok(rest), Some(home)) => home.join(rest),
+        _ => path,
+    }
+}
+
+fn string_or_number<'de, D: Deserializer<'de>>(deserializer: D) -> Result<String, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Id {
+        Text(String),
+        Number(i64),
+    }
+
+    Ok(match Id::deserialize(deserializer)? {
+        Id::Text(text) => text,
+        Id::Number(n) => n.to_string(),
+    })
+}
+
+/// Amounts as `"0.5"` strings or plain numbers, kept as text so they can
+/// be parsed exactly.
+pub fn amount<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<String>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Amount {
+        Text(String),
+        Number(serde_json::Number),
+    }
+
+    Ok(Some(match Amount::deserialize(deserializer)? {
+        Amount::Text(text) => text,
+        Amount::Number(n) => n.to_string(),
+    }))
+}
+
+/// A single string or a list of them.
+pub fn one_or_many<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<String>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany {
+        One(String),
+        Many(Vec<String>),
+    }
+
+    Ok(match OneOrMany::deserialize(deserializer)? {
+        OneOrMany::One(one) => vec![one],
+        OneOrMany::Many(many) => many,
+    })
+}
+
+/// A duration as a `"30m"`-style string or plain seconds.
+fn duration<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Duration>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Value {
+        Text(String),
+        Seconds(u64),
+    }
+
+    Ok(Some(match Value::deserialize(deserializer)? {
+        Value::Text(text) => parse_duration(&text).map_err(de::Error::custom)?,
+        Value::Seconds(secs) => Duration::from_secs(secs),
+    }))
+}
+
+/// Durations as `"30m"`-style strings or plain seconds.
+fn duration_map<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<BTreeMap<String, Duration>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Value {
+        Text(String),
+        Seconds(u64),
+    }
+
+    BTreeMap::<String, Value>::deserialize(deserializer)?
+        .into_iter()
+        .map(|(key, value)| {
+            let duration = match value {
+                Value::Text(text) => parse_duration(&text).map_err(de::Error::custom)?,
+                Value::Seconds(secs) => Duration::from_secs(secs),
+            };
+            Ok((key, duration))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// BIP32 test vector 1's m/0h, as a zpub
+    const ZPUB: &str = "zpub6mwJaQaUE3oZ763dJZKRbNUxW1znc5f4uqty7hKaAS5RKNscWpZrkohNNhd7BNxD8Hj5NceNPbujdF3935mRkSHHcS6yZLnpsUkrK1XoMLr";
+
+    /// The config examples in the README, which should keep up with the
+    /// keys.
+    fn readme_examples() -> Vec<String> {
+        let readme = include_str!("../README.md");
+        readme
+            .split("```toml\n")
+            .skip(1)
+            .filter_map(|rest| rest.split_once("```"))
+            // The library's Cargo.toml snippet isn't a config file
+            .filter(|(block, _)| !block.starts_with("[dependencies]"))
+            // A real key in place of the shortened one
+            .map(|(block, _)| block.replace("zpub6r...", ZPUB))
+            .collect()
+    }
+
+    #[test]
+    fn readme_examples_load() {
+        let examples = readme_examples();
+        assert!(!examples.is_empty());
+        for example in examples {
+            let value = toml::parse(&example).unwrap_or_else(|err| panic!("{err}\n{example}"));
+            let config: Config =
+                serde_json::from_value(value).unwrap_or_else(|err| panic!("{err}\n{example}"));
+            config
+                .apply(&mut Args::default())
+                .unwrap_or_else(|err| panic!("{err:#}\n{example}"));
+        }
+    }
+}
@@ -0,0 +1,192 @@
+//! CSV output for `export --format csv` and `--format accounting`.
+//!
+//! RFC 4180 quoting is all spreadsheets need here, so no csv crate.
+//!
+//! The accounting layout is Koinly's universal import format, which most
+//! crypto tax tools read too: one trade per completed swap, followed by
+//! columns the tools ignore but an accountant may want.
+
+use anyhow::{bail, Result};
+use std::str::FromStr;
+
+use crate::model::{AccountingRow, Role, SwapRow};
+
+/// Columns written when `--columns` isn't given: the transition log.
+pub const DEFAULT_COLUMNS: &[Column] = &[Column::SwapId, Column::State, Column::EnteredAt];
+
+/// A selectable column, shared with the Parquet export.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Column {
+    SwapId,
+    State,
+    EnteredAt,
+    StartedAt,
+    PeerId,
+    BtcSats,
+    XmrPiconero,
+    CancelTimelock,
+    PunishTimelock,
+    /// A transaction id by its label, e.g. `btc_lock` or `tx_redeem_txid`
+    Txid(String),
+}
+
+impl FromStr for Column {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(match s {
+            "swap_id" => Column::SwapId,
+            "state" => Column::State,
+            "entered_at" => Column::EnteredAt,
+            "started_at" => Column::StartedAt,
+            "peer_id" => Column::PeerId,
+            "btc_sats" => Column::BtcSats,
+            "xmr_piconero" => Column::XmrPiconero,
+            "cancel_timelock" => Column::CancelTimelock,
+            "punish_timelock" => Column::PunishTimelock,
+            "btc_lock" | "xmr_lock" => Column::Txid(s.to_string()),
+            txid if txid.ends_with("txid") => Column::Txid(txid.to_string()),
+            other => bail!(
+                "unknown column: {other} (expected swap_id, state, entered_at, started_at, \
+                 peer_id, btc_sats, xmr_piconero, cancel_timelock, punish_timelock, btc_lock, xmr_lock \
+                 or a *txid label)"
+            ),
+        })
+    }
+}
+
+impl Column {
+    pub fn name(&self) -> &str {
+        match self {
+            Column::SwapId => "swap_id",
+            Column::State => "state",
+            Column::EnteredAt => "entered_at",
+            Column::StartedAt => "started_at",
+            Column::PeerId => "peer_id",
+            Column::BtcSats => "btc_sats",
+            Column::XmrPiconero => "xmr_piconero",
+            Column::CancelTimelock => "cancel_timelock",
+            Column::PunishTimelock => "punish_timelock",
+            Column::Txid(label) => label,
+        }
+    }
+
+    /// The field's value; empty when the row doesn't have it.
+    fn value(&self, row: &SwapRow) -> String {
+        let details = &row.details;
+        match self {
+            Column::SwapId => row.swap_id.clone(),
+            Column::State => row.state.to_string(),
+            Column::EnteredAt => row.entered_at.clone(),
+            Column::StartedAt => row.started_at.clone(),
+            Column::PeerId => row.peer_id.clone().unwrap_or_default(),
+            Column::BtcSats => optional(details.btc_sats),
+            Column::XmrPiconero => optional(details.xmr_piconero),
+            Column::CancelTimelock => optional(details.cancel_timelock),
+            Column::PunishTimelock => optional(details.punish_timelock),
+            Column::Txid(label) => details.txids.get(label).cloned().unwrap_or_default(),
+        }
+    }
+}
+
+fn optional(value: Option<impl ToString>) -> String {
+    value.map(|v| v.to_string()).unwrap_or_default()
+}
+
+/// A header line and one line per row. Rows tagged with an instance name
+/// get a leading `instance` column.
+pub fn write(rows: &[(Option<&str>, SwapRow)], columns: &[Column]) -> String {
+    let tagged = rows.iter().any(|(instance, _)| instance.is_some());
+    let mut out = String::new();
+
+    let mut header: Vec<&str> = columns.iter().map(Column::name).collect();
+    if tagged {
+        header.insert(0, "instance");
+    }
+    push_record(&mut out, header);
+
+    for (instance, row) in rows {
+        let mut fields: Vec<String> = columns.iter().map(|c| c.value(row)).collect();
+        if tagged {
+            fields.insert(0, instance.unwrap_or_default().to_string());
+        }
+        push_record(&mut out, fields);
+    }
+    out
+}
+
+/// One line per completed swap. Amounts are decimal BTC and XMR; with
+/// several instances the instance goes into the description.
+pub fn write_accounting(rows: &[(Option<&str>, AccountingRow)]) -> String {
+    let mut out = String::new();
+    push_record(
+        &mut out,
+        vec![
+            "Date",
+            "Sent Amount",
+            "Sent Currency",
+            "Received Amount",
+            "Received Currency",
+            "Description",
+            "TxHash",
+            "Market Rate (BTC/XMR)",
+            "Spread (BTC)",
+        ],
+    );
+
+    for (instance, row) in rows {
+        let btc = decimal(row.btc_sats.into(), 8);
+        let xmr = decimal(row.xmr_piconero.into(), 12);
+        let (sent, sent_currency, received, received_currency) = match row.role {
+            Role::Maker => (xmr, "XMR", btc, "BTC"),
+            Role::Taker => (btc, "BTC", xmr, "XMR"),
+        };
+        let description = match instance {
+            Some(name) => format!("Atomic swap {} on {name}", row.swap_id),
+            None => format!("Atomic swap {}", row.swap_id),
+        };
+        push_record(
+            &mut out,
+            vec![
+                row.completed_at.format("%Y-%m-%d %H:%M:%S UTC").to_string(),
+                sent,
+                sent_currency.to_string(),
+                received,
+                received_currency.to_string(),
+                description,
+                row.txid.clone().unwrap_or_default(),
+                optional(row.market_rate.map(|rate| format!("{rate:.8}"))),
+                optional(row.spread_sats().map(|sats| decimal(sats.into(), 8))),
+            ],
+        );
+    }
+    out
+}
+
+/// `amount` atomic units with `decimals` places, exactly.
+fn decimal(amount: i128, decimals: u32) -> String {
+    let unit = 10i128.pow(decimals);
+    let sign = if amount < 0 { "-" } else { "" };
+    let amount = amount.abs();
+    format!(
+        "{sign}{}.{:0width$}",
+        amount / unit,
+        amount % unit,
+        width = decimals as usize
+    )
+}
+
+fn push_record<S: AsRef<str>>(out: &mut String, fields: Vec<S>) {
+    let fields: Vec<String> = fields.iter().map(|f| escape(f.as_ref())).collect();
+    out.push_str(&fields.join(","));
+    out.push_str("\r\n");
+}
+
+/// Quote fields containing separators, quotes or line breaks.
+fn escape(field: &str) -> String {
+    if field.contains([',', '"', '\r', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
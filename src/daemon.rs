@@ -0,0 +1,288 @@
+//! `--daemon`: watch mode for running as a service. Nothing is drawn;
+//! transitions, alerts and problems are logged through `tracing` instead,
+//! and notifications go out as in the interactive modes.
+//!
+//! Repeated failures are logged once when they start and once when they
+//! clear, so a missing database doesn't fill the log.
+//!
+//! Under systemd (`Type=notify`), the first successful poll reports
+//! readiness and every later one pings the watchdog, so a hung monitor or
+//! an unreadable database gets the service restarted.
+
+use anyhow::{bail, Result};
+use tokio::time::sleep;
+use tracing::{error, info, warn};
+
+use crate::anomaly::AnomalyKind;
+use crate::asblog::LogAlert;
+use crate::chain::LiquidityAlert;
+use crate::db::PollError;
+use crate::digest;
+use crate::liveness::AsbDown;
+use crate::mempool::FeeSpike;
+use crate::model::Transition;
+use crate::quote::QuoteAlert;
+use crate::reachability::Unreachable;
+use crate::rules::Severity;
+use crate::systemd;
+use crate::watch::{Monitor, WatchContext};
+
+pub async fn run_daemon(mut ctx: WatchContext) -> Result<()> {
+    let mut monitors = Monitor::for_instances(
+        &ctx.instances,
+        &ctx.stuck,
+        &ctx.watchlist,
+        &ctx.pins,
+        ctx.history.as_ref(),
+    );
+    let mut watcher = ctx.watcher();
+    ctx.chain.start();
+    let watchdog = systemd::watchdog_interval();
+    let mut ready = false;
+
+    for monitor in &monitors {
+        let instance = &monitor.instance;
+        info!(
+            instance = %instance.name,
+            db_path = %instance.db_path.as_deref().unwrap_or("?".as_ref()).display(),
+            network = ?instance.network,
+            role = ?instance.role,
+            remote = instance.remote.as_ref().map(|r| format!("{}:{}", r.host, r.db_path.display())),
+            "monitoring"
+        );
+    }
+
+    // Last error per instance, and from chain lookups and notifications
+    let mut poll_errors: Vec<Option<String>> = vec![None; monitors.len()];
+    let mut chain_error: Option<String> = None;
+    loop {
+        let mut activity = false;
+        let mut any_ok = false;
+        let mut swaps = 0;
+        for (monitor, last_error) in monitors.iter_mut().zip(&mut poll_errors) {
+            let name = monitor.instance.name.clone();
+            match monitor.poll().await {
+                Ok(rows) => {
+                    if last_error.take().is_some() {
+                        info!(instance = %name, swaps = rows.len(), "database readable again");
+                    }
+                    swaps += rows.len();
+                    let (views, transitions) = monitor.update(rows).await;
+                    for transition in &transitions {
+                        log_transition(&name, transition);
+                    }
+                    ctx.chain
+                        .count_in_flight(&name, &views, monitor.instance.role);
+                    activity |= !transitions.is_empty();
+                    ctx.notifiers.dispatch(&transitions);
+                    ctx.record_metrics(&monitor.instance, &views, &transitions);
+                    any_ok = true;
+                }
+                Err(PollError::NoDataDir) => bail!("{}", PollError::NoDataDir),
+                Err(err) => {
+                    let message = err.to_string();
+                    if last_error.as_ref() != Some(&message) {
+                        warn!(instance = %name, error = %message, "poll failed");
+                    }
+                    *last_error = Some(message);
+                }
+            }
+        }
+        if any_ok {
+            ctx.schedule.record(activity);
+            if !ready {
+                systemd::notify(&format!("READY=1\nSTATUS=Watching {swaps} swaps"));
+                ready = true;
+            } else {
+                systemd::notify("WATCHDOG=1");
+            }
+        }
+
+        let alerts = ctx.chain.take_alerts();
+        for alert in &alerts {
+            log_liquidity(alert);
+        }
+        ctx.notifiers.dispatch_liquidity(&alerts);
+        let spikes = ctx.chain.take_fee_alerts();
+        for spike in &spikes {
+            log_fee_spike(spike);
+        }
+        ctx.notifiers.dispatch_fees(&spikes);
+        let downs = ctx.chain.take_asb_alerts();
+        for down in &downs {
+            log_asb_down(down);
+        }
+        ctx.notifiers.dispatch_asb(&downs);
+        let unreachable = ctx.chain.take_reach_alerts();
+        for alert in &unreachable {
+            log_unreachable(alert);
+        }
+        ctx.notifiers.dispatch_reach(&unreachable);
+        let quotes = ctx.chain.take_quote_alerts();
+        for alert in &quotes {
+            log_quote(alert);
+        }
+        ctx.notifiers.dispatch_quotes(&quotes);
+        let log_alerts = ctx.take_log_alerts();
+        for alert in &log_alerts {
+            log_log_alert(alert);
+        }
+        ctx.notifiers.dispatch_log(&log_alerts);
+        digest::send_if_due(&mut ctx);
+        ctx.flush_metrics();
+        let failures: Vec<String> = monitors
+            .iter()
+            .zip(&poll_errors)
+            .filter_map(|(monitor, error)| {
+                let error = error.as_ref()?;
+                Some(format!("{}: {error}", monitor.instance.name))
+            })
+            .collect();
+        ctx.beat(&failures);
+        if let Some(err) = ctx.notifiers.take_error() {
+            error!(error = %err, "notification failed");
+        }
+        if let Some(err) = ctx.history_error() {
+            error!(error = %err, "history write failed");
+        }
+        if let Some(err) = ctx.metrics_error() {
+            error!(error = %err, "metrics write failed");
+        }
+        if let Some(err) = ctx.heartbeat_error() {
+            error!(error = %err, "heartbeat ping failed");
+        }
+        let error = ctx.chain.error();
+        if error != chain_error {
+            match error {
+                Some(ref err) => warn!(error = %err, "chain lookup failed"),
+                None => info!("chain lookups working again"),
+            }
+            chain_error = error;
+        }
+
+        // Polls have to come often enough to keep the watchdog fed
+        let wait = ctx.schedule.next_wait(&watcher);
+        let wait = watchdog.map_or(wait, |interval| wait.min(interval));
+        tokio::select! {
+            _ = sleep(wait) => {}
+            _ = watcher.changed() => {}
+            _ = ctx.chain.updated() => {}
+        }
+    }
+}
+
+pub fn log_transition(instance: &str, transition: &Transition) {
+    let swap_id = &transition.swap_id;
+    let state = transition.new_state.as_str();
+    match (transition.stuck_for_secs, &transition.old_state) {
+        (Some(stuck_for_secs), _) => {
+            warn!(instance, swap_id, state, stuck_for_secs, "swap stuck")
+        }
+        (None, Some(old_state)) if transition.anomaly.is_some() => warn!(
+            instance,
+            swap_id,
+            old_state = old_state.as_str(),
+            new_state = state,
+            anomaly = transition.anomaly.map(AnomalyKind::as_str),
+            entered_at = transition.entered_at,
+            "unexpected transition"
+        ),
+        (None, Some(old_state)) if transition.pinned => warn!(
+            instance,
+            swap_id,
+            old_state = old_state.as_str(),
+            new_state = state,
+            entered_at = transition.entered_at,
+            "pinned swap moved"
+        ),
+        (None, Some(old_state)) => info!(
+            instance,
+            swap_id,
+            old_state = old_state.as_str(),
+            new_state = state,
+            entered_at = transition.entered_at,
+            "transition"
+        ),
+        (None, None) if transition.watched => warn!(
+            instance,
+            swap_id,
+            state,
+            peer_id = transition.peer_id,
+            entered_at = transition.entered_at,
+            "new swap from watched peer"
+        ),
+        (None, None) => info!(
+            instance,
+            swap_id,
+            state,
+            entered_at = transition.entered_at,
+            "new swap"
+        ),
+    }
+}
+
+pub fn log_liquidity(alert: &LiquidityAlert) {
+    match *alert {
+        LiquidityAlert::XmrLow {
+            unlocked_piconero,
+            threshold_piconero,
+        } => warn!(unlocked_piconero, threshold_piconero, "XMR liquidity low"),
+        LiquidityAlert::BtcHigh {
+            balance_sats,
+            threshold_sats,
+        } => warn!(balance_sats, threshold_sats, "BTC piling up"),
+    }
+}
+
+pub fn log_fee_spike(spike: &FeeSpike) {
+    warn!(
+        fastest_sat_vb = spike.fastest_sat_vb,
+        threshold_sat_vb = spike.threshold_sat_vb,
+        mempool_vsize = spike.mempool_vsize,
+        "BTC fees spiking"
+    );
+}
+
+pub fn log_asb_down(down: &AsbDown) {
+    error!(
+        reason = %down.reason,
+        in_flight = down.in_flight,
+        "ASB is not running"
+    );
+}
+
+pub fn log_unreachable(alert: &Unreachable) {
+    warn!(
+        address = %alert.address,
+        via_tor = alert.via_tor,
+        reason = %alert.reason,
+        "ASB unreachable"
+    );
+}
+
+pub fn log_quote(alert: &QuoteAlert) {
+    match alert {
+        QuoteAlert::Failed { reason } => warn!(reason = %reason, "ASB gave no quote"),
+        QuoteAlert::Drift {
+            quoted,
+            expected,
+            drift,
+            tolerance,
+        } => warn!(
+            quoted = %format!("{quoted:.8}"),
+            expected = %format!("{expected:.8}"),
+            drift = %format!("{:+.2}%", drift * 100.0),
+            tolerance = %format!("{:.2}%", tolerance * 100.0),
+            "ASB quote off the market"
+        ),
+    }
+}
+
+pub fn log_log_alert(alert: &LogAlert) {
+    match alert.severity {
+        Severity::Critical => {
+            error!(rule = %alert.rule, held_back = alert.held_back, "{}", alert.message)
+        }
+        _ => warn!(rule = %alert.rule, held_back = alert.held_back, "{}", alert.message),
+    }
+}
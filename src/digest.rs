@@ -0,0 +1,299 @@
+//! Periodic digests (`--digest`): one summary per day, week or month through
+//! the configured notifiers instead of (or besides) a message per event.
+//!
+//! What happened comes from the history database: new swaps, completions,
+//! refunds and punishes observed during the period. What is going on right
+//! now comes from ASB's database: the swaps currently stuck, and the amounts
+//! behind the earnings estimate.
+
+use chrono::{DateTime, Datelike, Days, Local, Months, NaiveTime, Utc, Weekday};
+use serde::Serialize;
+use std::collections::HashMap;
+
+use crate::db::{fetch_swaps, open_read_only_pool, Instance};
+use crate::history::HistoryStore;
+use crate::model::{build_views, earnings, EarningsOptions, Outcome, Period, Role, StuckRules};
+use crate::notify::HeldAlert;
+use crate::render::{format_btc, format_duration, truncate_id};
+use crate::state::SwapState;
+use crate::watch::WatchContext;
+
+/// When digests go out: every day, every Monday or on the first of the
+/// month, at `at` local time.
+#[derive(Debug, Clone)]
+pub struct DigestSchedule {
+    pub period: Period,
+    pub at: NaiveTime,
+    /// ASB's `ask_spread`, for the earnings estimate
+    pub spread: f64,
+    next: DateTime<Local>,
+}
+
+impl DigestSchedule {
+    pub fn new(period: Period, at: NaiveTime, spread: f64) -> Self {
+        Self {
+            period,
+            at,
+            spread,
+            next: next_after(period, at, Local::now()),
+        }
+    }
+
+    /// The period to report on, once the next digest is due.
+    fn due(&mut self) -> Option<(DateTime<Utc>, DateTime<Utc>)> {
+        let now = Local::now();
+        if now < self.next {
+            return None;
+        }
+        let until = self.next;
+        let since = match self.period {
+            Period::Day => until.checked_sub_days(Days::new(1)),
+            Period::Week => until.checked_sub_days(Days::new(7)),
+            Period::Month => until.checked_sub_months(Months::new(1)),
+        }
+        .unwrap_or(until);
+        self.next = next_after(self.period, self.at, now);
+        Some((since.with_timezone(&Utc), until.with_timezone(&Utc)))
+    }
+}
+
+/// The first digest time after `now`.
+fn next_after(period: Period, at: NaiveTime, now: DateTime<Local>) -> DateTime<Local> {
+    let mut date = now.date_naive();
+    loop {
+        let starts_period = match period {
+            Period::Day => true,
+            Period::Week => date.weekday() == Weekday::Mon,
+            Period::Month => date.day() == 1,
+        };
+        // A time skipped by a DST change means no digest that day
+        if let Some(time) = date
+            .and_time(at)
+            .and_local_timezone(Local)
+            .earliest()
+            .filter(|time| starts_period && *time > now)
+        {
+            return time;
+        }
+        date = date.succ_opt().expect("date in range");
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Digest {
+    /// `day`, `week` or `month`
+    pub period: &'static str,
+    pub since: String,
+    pub until: String,
+    pub instances: Vec<InstanceDigest>,
+    /// Alerts held back during quiet hours since the last digest
+    pub held: Vec<HeldAlert>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct InstanceDigest {
+    /// Only set when more than one ASB is monitored
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub instance: Option<String>,
+    pub new_swaps: Vec<String>,
+    pub completed: Vec<String>,
+    pub refunded: Vec<String>,
+    pub punished: Vec<String>,
+    /// Spread earned on the completed swaps; makers only
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub earnings_sats: Option<u64>,
+    pub stuck: Vec<StuckSwap>,
+    /// Why part of the digest is missing, e.g. an unreadable database
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct StuckSwap {
+    pub swap_id: String,
+    pub state: SwapState,
+    pub stuck_for_secs: u64,
+}
+
+impl Digest {
+    /// Title and body for the human-readable backends.
+    pub fn text(&self) -> (String, String) {
+        let title = match self.period {
+            "day" => "Daily swap digest",
+            "week" => "Weekly swap digest",
+            _ => "Monthly swap digest",
+        };
+        let multi = self.instances.len() > 1;
+        let mut lines = Vec::new();
+        for digest in &self.instances {
+            if multi {
+                if !lines.is_empty() {
+                    lines.push(String::new());
+                }
+                lines.push(format!("[{}]", digest.instance.as_deref().unwrap_or("?")));
+            }
+            lines.push(format!(
+                "New {} · completed {} · refunded {} · punished {}",
+                digest.new_swaps.len(),
+                digest.completed.len(),
+                digest.refunded.len(),
+                digest.punished.len()
+            ));
+            if let Some(sats) = digest
+                .earnings_sats
+                .filter(|_| !digest.completed.is_empty())
+            {
+                lines.push(format!("Earned ≈ {} BTC", format_btc(sats)));
+            }
+            for swap_id in &digest.punished {
+                lines.push(format!("Punished: {swap_id}"));
+            }
+            match digest.stuck.len() {
+                0 => lines.push("No stuck swaps".to_string()),
+                n => lines.push(format!("Stuck: {n}")),
+            }
+            for stuck in &digest.stuck {
+                lines.push(format!(
+                    "  {} in {} for {}",
+                    truncate_id(&stuck.swap_id),
+                    stuck.state,
+                    format_duration(chrono::Duration::seconds(stuck.stuck_for_secs as i64))
+                ));
+            }
+            if let Some(ref err) = digest.error {
+                lines.push(format!("Incomplete: {err}"));
+            }
+        }
+        if !self.held.is_empty() {
+            lines.push(String::new());
+            lines.push(format!("Held during quiet hours: {}", self.held.len()));
+            for alert in &self.held {
+                let time = DateTime::parse_from_rfc3339(&alert.observed_at)
+                    .map(|at| at.with_timezone(&Local).format("%H:%M").to_string())
+                    .unwrap_or_default();
+                lines.push(format!("  {time} {}", alert.summary));
+            }
+        }
+        (title.to_string(), lines.join("\n"))
+    }
+}
+
+/// Build and deliver the digest if one is due. Runs in the background, so
+/// the poll loop only pays for the schedule check.
+pub fn send_if_due(ctx: &mut WatchContext) {
+    let (Some(schedule), Some(history)) = (ctx.digest.as_mut(), ctx.history.clone()) else {
+        return;
+    };
+    let Some((since, until)) = schedule.due() else {
+        return;
+    };
+    let period = schedule.period;
+    let options = EarningsOptions {
+        spread: schedule.spread,
+        ..EarningsOptions::default()
+    };
+    let instances = ctx.instances.clone();
+    let stuck = ctx.stuck.clone();
+    let notifiers = ctx.notifiers.clone();
+
+    tokio::spawn(async move {
+        let multi = instances.len() > 1;
+        let mut digests = Vec::new();
+        for instance in &instances {
+            let mut digest = build(instance, &history, &stuck, &options, since, until).await;
+            digest.instance = multi.then(|| instance.name.clone());
+            digests.push(digest);
+        }
+        let held = notifiers.take_held();
+        notifiers.dispatch_digest(Digest {
+            period: match period {
+                Period::Day => "day",
+                Period::Week => "week",
+                Period::Month => "month",
+            },
+            since: since.to_rfc3339(),
+            until: until.to_rfc3339(),
+            instances: digests,
+            held,
+        });
+    });
+}
+
+async fn build(
+    instance: &Instance,
+    history: &HistoryStore,
+    stuck: &StuckRules,
+    options: &EarningsOptions,
+    since: DateTime<Utc>,
+    until: DateTime<Utc>,
+) -> InstanceDigest {
+    let mut digest = InstanceDigest::default();
+    let role = instance.role;
+
+    match history
+        .transitions_between(&instance.name, since, until)
+        .await
+    {
+        Ok(transitions) => {
+            for transition in transitions {
+                let swap_id = transition.swap_id;
+                let state = &transition.new_state;
+                let list = if transition.old_state.is_none() {
+                    &mut digest.new_swaps
+                } else if state.is_punish() {
+                    &mut digest.punished
+                } else {
+                    match state.outcome(role) {
+                        Outcome::Completed => &mut digest.completed,
+                        Outcome::Failed if state.is_refund() => &mut digest.refunded,
+                        _ => continue,
+                    }
+                };
+                if !list.contains(&swap_id) {
+                    list.push(swap_id);
+                }
+            }
+        }
+        Err(err) => digest.error = Some(format!("{err:#}")),
+    }
+
+    let Some(path) = instance.db_path.as_deref() else {
+        return digest;
+    };
+    let rows = match open_read_only_pool(path, instance.immutable).await {
+        Ok(pool) => {
+            let rows = fetch_swaps(&pool).await;
+            pool.close().await;
+            rows
+        }
+        Err(err) => Err(err),
+    };
+    let rows = match rows {
+        Ok(rows) => rows,
+        Err(err) => {
+            digest.error.get_or_insert(format!("{err:#}"));
+            return digest;
+        }
+    };
+
+    if role == Role::Maker {
+        let completed: Vec<_> = rows
+            .iter()
+            .filter(|row| digest.completed.contains(&row.swap_id))
+            .cloned()
+            .collect();
+        digest.earnings_sats = earnings(&completed, options)
+            .last()
+            .map(|total| total.earnings_sats);
+    }
+    for view in build_views(rows, &mut HashMap::new()) {
+        if let Some(elapsed) = stuck.stuck_for(&view, role) {
+            digest.stuck.push(StuckSwap {
+                swap_id: view.swap_id,
+                state: view.state,
+                stuck_for_secs: elapsed.as_secs(),
+            });
+        }
+    }
+    digest
+}
@@ -0,0 +1,224 @@
+//! Minimal Electrum protocol client for chain lookups.
+//!
+//! Electrum speaks newline-delimited JSON-RPC over a plain or TLS socket.
+//! TLS servers have to present a certificate from a public CA for their
+//! name, or the one pinned with `--electrum-fingerprint` (see [`crate::tls`]),
+//! since the block heights and balances they report drive the alerts.
+
+use anyhow::{anyhow, bail, Context, Result};
+use serde_json::{json, Value};
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+use tokio::time::timeout;
+
+use crate::btc;
+use crate::tls::{self, Fingerprint};
+
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// `ssl://host:port` or `tcp://host:port`; a bare `host:port` means TLS.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ElectrumServer {
+    pub host: String,
+    pub port: u16,
+    pub tls: bool,
+    /// The server's own certificate, accepted in place of one from a CA
+    pub fingerprint: Option<Fingerprint>,
+}
+
+impl FromStr for ElectrumServer {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (tls, rest) = match s.split_once("://") {
+            Some(("ssl" | "tls", rest)) => (true, rest),
+            Some(("tcp", rest)) => (false, rest),
+            Some((scheme, _)) => bail!("unknown Electrum scheme {scheme}:// (expected ssl or tcp)"),
+            None => (true, s),
+        };
+        let (host, port) = rest
+            .rsplit_once(':')
+            .with_context(|| format!("Electrum server needs a port: {s}"))?;
+        Ok(ElectrumServer {
+            host: host.to_string(),
+            port: port
+                .parse()
+                .with_context(|| format!("invalid Electrum port in {s}"))?,
+            tls,
+            fingerprint: None,
+        })
+    }
+}
+
+impl fmt::Display for ElectrumServer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let scheme = if self.tls { "ssl" } else { "tcp" };
+        write!(f, "{scheme}://{}:{}", self.host, self.port)
+    }
+}
+
+/// One connection to an Electrum server. Requests are sent one at a time.
+pub struct Session {
+    reader: BufReader<Box<dyn AsyncRead + Unpin + Send>>,
+    writer: Box<dyn AsyncWrite + Unpin + Send>,
+    next_id: u64,
+    timeout: Duration,
+}
+
+impl Session {
+    pub async fn connect(server: &ElectrumServer, request_timeout: Duration) -> Result<Self> {
+        let connect = async {
+            if server.tls {
+                let stream = tls::connect(&server.host, server.port, server.fingerprint).await?;
+                let (reader, writer) = tokio::io::split(stream);
+                let reader: Box<dyn AsyncRead + Unpin + Send> = Box::new(reader);
+                let writer: Box<dyn AsyncWrite + Unpin + Send> = Box::new(writer);
+                Ok::<_, anyhow::Error>((reader, writer))
+            } else {
+                let stream = TcpStream::connect((server.host.as_str(), server.port)).await?;
+                let (reader, writer) = stream.into_split();
+                let reader: Box<dyn AsyncRead + Unpin + Send> = Box::new(reader);
+                let writer: Box<dyn AsyncWrite + Unpin + Send> = Box::new(writer);
+                Ok((reader, writer))
+            }
+        };
+
+        let (reader, writer) = timeout(request_timeout, connect)
+            .await
+            .map_err(|_| anyhow!("timed out"))?
+            .with_context(|| format!("connect to {server}"))?;
+
+        Ok(Self {
+            reader: BufReader::new(reader),
+            writer,
+            next_id: 0,
+            timeout: request_timeout,
+        })
+    }
+
+    pub async fn call(&mut self, method: &str, params: Value) -> Result<Value> {
+        self.next_id += 1;
+        let id = self.next_id;
+        let mut line = serde_json::to_string(&json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "method": method,
+            "params": params,
+        }))?;
+        line.push('\n');
+
+        let exchange = async {
+            self.writer.write_all(line.as_bytes()).await?;
+            self.writer.flush().await?;
+
+            let mut buf = String::new();
+            loop {
+                buf.clear();
+                if self.reader.read_line(&mut buf).await? == 0 {
+                    bail!("connection closed");
+                }
+                let response: Value = serde_json::from_str(&buf).context("invalid response")?;
+                // Subscriptions push notifications between responses
+                if response.get("id").and_then(Value::as_u64) != Some(id) {
+                    continue;
+                }
+                if let Some(error) = response.get("error").filter(|e| !e.is_null()) {
+                    let message = error.get("message").unwrap_or(error);
+                    bail!("{message}");
+                }
+                return Ok(response.get("result").cloned().unwrap_or(Value::Null));
+            }
+        };
+
+        timeout(self.timeout, exchange)
+            .await
+            .map_err(|_| anyhow!("timed out"))?
+            .with_context(|| method.to_string())
+    }
+
+    /// Height of the best block.
+    pub async fn tip_height(&mut self) -> Result<u64> {
+        let header = self.call("blockchain.headers.subscribe", json!([])).await?;
+        header
+            .get("height")
+            .and_then(Value::as_u64)
+            .context("Electrum headers.subscribe without a height")
+    }
+
+    /// Hash of the genesis block, which tells the networks apart.
+    pub async fn genesis_hash(&mut self) -> Result<String> {
+        let features = self.call("server.features", json!([])).await?;
+        features
+            .get("genesis_hash")
+            .and_then(Value::as_str)
+            .map(str::to_string)
+            .context("Electrum server.features without a genesis hash")
+    }
+
+    /// Confirmations of `txid` given the current tip: `Some(0)` while in the
+    /// mempool, `None` when the server doesn't know the transaction.
+    ///
+    /// Verbose `transaction.get` isn't supported everywhere (electrs), so
+    /// this looks the transaction up in its first output's script history,
+    /// which every server implements.
+    pub async fn confirmations(&mut self, txid: &str, tip: u64) -> Result<Option<u32>> {
+        let raw = match self.call("blockchain.transaction.get", json!([txid])).await {
+            Ok(Value::String(raw)) => raw,
+            Ok(_) => bail!("unexpected transaction.get response"),
+            // Unknown transactions come back as an RPC error
+            Err(_) => return Ok(None),
+        };
+        let script = btc::first_output_script(&raw).context("decode raw transaction")?;
+
+        let history = self
+            .call(
+                "blockchain.scripthash.get_history",
+                json!([btc::script_hash(&script)]),
+            )
+            .await?;
+        let height = history.as_array().and_then(|entries| {
+            entries
+                .iter()
+                .find(|e| e.get("tx_hash").and_then(Value::as_str) == Some(txid))
+                .and_then(|e| e.get("height").and_then(Value::as_i64))
+        });
+
+        Ok(match height {
+            Some(height) if height > 0 => Some((tip + 1).saturating_sub(height as u64) as u32),
+            // 0 or -1: in the mempool
+            Some(_) => Some(0),
+            None => None,
+        })
+    }
+
+    /// Whether any transaction ever touched the script.
+    pub async fn is_used(&mut self, script_hash: &str) -> Result<bool> {
+        let history = self
+            .call("blockchain.scripthash.get_history", json!([script_hash]))
+            .await?;
+        Ok(history
+            .as_array()
+            .is_some_and(|entries| !entries.is_empty()))
+    }
+
+    /// Confirmed and unconfirmed balance of a script, in satoshis. The
+    /// unconfirmed part is negative while a spend sits in the mempool.
+    pub async fn balance(&mut self, script_hash: &str) -> Result<(u64, i64)> {
+        let balance = self
+            .call("blockchain.scripthash.get_balance", json!([script_hash]))
+            .await?;
+        Ok((
+            balance
+                .get("confirmed")
+                .and_then(Value::as_u64)
+                .unwrap_or(0),
+            balance
+                .get("unconfirmed")
+                .and_then(Value::as_i64)
+                .unwrap_or(0),
+        ))
+    }
+}
@@ -0,0 +1,71 @@
+//! Block explorer links for a swap's BTC transactions (`--explorer`), and
+//! opening them in the desktop's browser through `xdg-open` or `open`.
+
+use anyhow::{bail, Context, Result};
+use std::process::Stdio;
+use tokio::process::Command;
+
+use crate::model::Network;
+
+/// Used without `--explorer`; it serves testnet under `/testnet`
+pub const DEFAULT_EXPLORER: &str = "https://mempool.space";
+
+/// What `open` takes to pick a transaction; `btc_` plus the name is its
+/// label among the swap's txids
+pub const TX_NAMES: &[&str] = &["lock", "redeem", "cancel", "refund", "punish"];
+
+#[derive(Debug, Clone, Default)]
+pub struct Explorer {
+    /// A self-hosted mempool or esplora, serving the one network it is
+    /// used with
+    base: Option<String>,
+}
+
+impl Explorer {
+    pub fn new(base: Option<String>) -> Self {
+        Self {
+            base: base.map(|base| base.trim_end_matches('/').to_string()),
+        }
+    }
+
+    /// The page of `txid` on `network`.
+    pub fn tx_url(&self, network: Network, txid: &str) -> String {
+        match (&self.base, network) {
+            (Some(base), _) => format!("{base}/tx/{txid}"),
+            (None, Network::Mainnet) => format!("{DEFAULT_EXPLORER}/tx/{txid}"),
+            (None, Network::Testnet) => format!("{DEFAULT_EXPLORER}/testnet/tx/{txid}"),
+        }
+    }
+}
+
+/// Open `url` in a browser. Returns false when there is no desktop to open
+/// it on, such as over SSH, so the caller can print it instead.
+pub async fn open(url: &str) -> Result<bool> {
+    #[cfg(target_os = "macos")]
+    let opener = "open";
+
+    #[cfg(not(target_os = "macos"))]
+    let opener = {
+        let desktop = ["DISPLAY", "WAYLAND_DISPLAY"]
+            .iter()
+            .any(|var| std::env::var_os(var).is_some_and(|v| !v.is_empty()));
+        if !desktop {
+            return Ok(false);
+        }
+        "xdg-open"
+    };
+
+    // Quiet, so nothing lands on the TUI's screen
+    let status = Command::new(opener)
+        .arg(url)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .await
+        .with_context(|| format!("spawn {opener}"))?;
+    if !status.success() {
+        bail!("{opener} exited with {status}");
+    }
+    Ok(true)
+}
@@ -0,0 +1,144 @@
+//! Fiat values for swap amounts and earnings (`--fiat`): the BTC price in
+//! `--currency`, from the `--price-source`.
+//!
+//! Prices are kept in the cache directory for a few minutes, so one-shot
+//! commands don't ask the exchange every run. When the source can't be
+//! reached the last cached price is used however old it is, marked stale,
+//! so an offline box still shows roughly what its swaps were worth.
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use crate::price::PriceSource;
+
+/// How long a cached price is used without asking the source again
+const CACHE_FOR: chrono::Duration = chrono::Duration::minutes(10);
+
+#[derive(Debug, Clone)]
+pub struct FiatRates {
+    source: PriceSource,
+    /// ISO code, upper case
+    currency: String,
+    /// `--btc-price`, used instead of any lookup
+    fixed: Option<f64>,
+    /// Last known prices, keyed by source and currency
+    cache: Option<PathBuf>,
+}
+
+/// What one BTC cost, and when.
+#[derive(Debug, Clone, Copy)]
+pub struct FiatPrice {
+    pub per_btc: f64,
+    pub at: DateTime<Utc>,
+    /// From the cache after the source failed to answer
+    pub stale: bool,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+struct CachedPrice {
+    per_btc: f64,
+    /// Unix seconds
+    at: i64,
+}
+
+impl CachedPrice {
+    fn price(self) -> Option<FiatPrice> {
+        Some(FiatPrice {
+            per_btc: self.per_btc,
+            at: DateTime::from_timestamp(self.at, 0)?,
+            stale: false,
+        })
+    }
+}
+
+impl FiatRates {
+    pub fn new(source: PriceSource, currency: String, fixed: Option<f64>) -> Self {
+        Self {
+            source,
+            currency,
+            fixed,
+            cache: dirs::cache_dir().map(|dir| dir.join("wraithswap/fiat.json")),
+        }
+    }
+
+    pub fn currency(&self) -> &str {
+        &self.currency
+    }
+
+    /// The current price: pinned, freshly cached, looked up, or failing
+    /// that the last one cached.
+    pub async fn price(&self) -> Result<FiatPrice> {
+        let now = Utc::now();
+        if let Some(per_btc) = self.fixed {
+            return Ok(FiatPrice {
+                per_btc,
+                at: now,
+                stale: false,
+            });
+        }
+
+        let key = format!("{}/{}", self.source_key(), self.currency);
+        let mut cached = self.load();
+        let last = cached.get(&key).and_then(|p| p.price());
+        if let Some(price) = last.filter(|p| now - p.at < CACHE_FOR) {
+            return Ok(price);
+        }
+        match self.source.btc_price(&self.currency).await {
+            Ok(per_btc) => {
+                cached.insert(
+                    key,
+                    CachedPrice {
+                        per_btc,
+                        at: now.timestamp(),
+                    },
+                );
+                self.store(&cached);
+                Ok(FiatPrice {
+                    per_btc,
+                    at: now,
+                    stale: false,
+                })
+            }
+            Err(err) => match last {
+                Some(price) => Ok(FiatPrice {
+                    stale: true,
+                    ..price
+                }),
+                None => Err(err),
+            },
+        }
+    }
+
+    /// Distinguishes price servers from each other, unlike `Display`.
+    fn source_key(&self) -> String {
+        match &self.source {
+            PriceSource::Url(url) => url.clone(),
+            other => other.to_string(),
+        }
+    }
+
+    fn load(&self) -> HashMap<String, CachedPrice> {
+        self.cache
+            .as_ref()
+            .and_then(|path| std::fs::read_to_string(path).ok())
+            .and_then(|body| serde_json::from_str(&body).ok())
+            .unwrap_or_default()
+    }
+
+    /// Best effort; without a writable cache every lookup goes to the
+    /// source.
+    fn store(&self, prices: &HashMap<String, CachedPrice>) {
+        let Some(ref path) = self.cache else {
+            return;
+        };
+        if let Some(dir) = path.parent() {
+            let _ = std::fs::create_dir_all(dir);
+        }
+        if let Ok(body) = serde_json::to_string_pretty(prices) {
+            let _ = std::fs::write(path, body);
+        }
+    }
+}
@@ -0,0 +1,184 @@
+//! Change notifications for the sqlite files, so watch mode can refresh as
+//! soon as ASB writes instead of polling blindly. ASB's log file (`--asb-log`)
+//! is watched the same way, so new lines are read as they come.
+//!
+//! The `notify` crate watches each database's directory (inotify on Linux,
+//! FSEvents on macOS, ReadDirectoryChangesW on Windows), and events are
+//! filtered to the database file and its `-wal` sibling; the `-shm` index
+//! also changes on reads, including ours. Databases whose directory doesn't
+//! exist yet fall back to interval polling.
+
+use notify::event::{AccessKind, AccessMode, ModifyKind};
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use std::collections::HashSet;
+use std::ffi::OsString;
+use std::path::Path;
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+/// How long the files have to be quiet before a burst of events is over
+const SETTLE: Duration = Duration::from_millis(50);
+
+pub struct DbWatcher {
+    inner: Option<Inner>,
+    /// Every database is covered, so the poll interval can stretch to a
+    /// heartbeat
+    complete: bool,
+}
+
+struct Inner {
+    /// Stops watching when dropped
+    _watcher: RecommendedWatcher,
+    events: mpsc::UnboundedReceiver<notify::Result<Event>>,
+    /// File names whose changes count. Any watched database changing
+    /// refreshes them all, so which directory it was in doesn't matter.
+    names: HashSet<OsString>,
+}
+
+impl DbWatcher {
+    pub fn new<'a>(paths: impl IntoIterator<Item = &'a Path>) -> Self {
+        let (tx, events) = mpsc::unbounded_channel();
+        let Ok(mut watcher) = notify::recommended_watcher(move |event| {
+            let _ = tx.send(event);
+        }) else {
+            return Self::disabled();
+        };
+
+        let mut names = HashSet::new();
+        let mut complete = true;
+        for path in paths {
+            let (Some(dir), Some(name)) = (path.parent(), path.file_name()) else {
+                complete = false;
+                continue;
+            };
+            let dir = match dir.as_os_str().is_empty() {
+                true => Path::new("."),
+                false => dir,
+            };
+            if watcher.watch(dir, RecursiveMode::NonRecursive).is_err() {
+                complete = false;
+                continue;
+            }
+            let mut wal = name.to_os_string();
+            wal.push("-wal");
+            names.insert(name.to_os_string());
+            names.insert(wal);
+        }
+
+        Self {
+            inner: Some(Inner {
+                _watcher: watcher,
+                events,
+                names,
+            }),
+            complete,
+        }
+    }
+
+    pub fn disabled() -> Self {
+        Self {
+            inner: None,
+            complete: false,
+        }
+    }
+
+    /// True when change events cover every database.
+    pub fn is_active(&self) -> bool {
+        self.complete && self.inner.is_some()
+    }
+
+    /// Resolves when a watched database changes; never resolves without a
+    /// working watcher. A watcher that stops sending events is disabled
+    /// for good.
+    pub async fn changed(&mut self) {
+        if let Some(ref mut inner) = self.inner {
+            if inner.changed().await {
+                return;
+            }
+            self.inner = None;
+        }
+
+        std::future::pending::<()>().await
+    }
+}
+
+impl Inner {
+    /// Wait for an event on a database file, then for the events after it
+    /// to stop so a burst of writes causes a single refresh. False when the
+    /// watcher has gone away.
+    async fn changed(&mut self) -> bool {
+        loop {
+            let Some(event) = self.events.recv().await else {
+                return false;
+            };
+            if self.is_relevant(&event) {
+                while let Ok(Some(_)) = tokio::time::timeout(SETTLE, self.events.recv()).await {}
+                return true;
+            }
+        }
+    }
+
+    fn is_relevant(&self, event: &notify::Result<Event>) -> bool {
+        let event = match event {
+            Ok(event) => event,
+            // Events may have been lost, so one of them could have counted
+            Err(_) => return true,
+        };
+        let writes = match event.kind {
+            EventKind::Create(_) | EventKind::Remove(_) => true,
+            // Reading the database can touch its access time
+            EventKind::Modify(ModifyKind::Metadata(_)) => false,
+            EventKind::Modify(_) => true,
+            EventKind::Access(AccessKind::Close(AccessMode::Write)) => true,
+            EventKind::Access(_) | EventKind::Any | EventKind::Other => false,
+        };
+        writes
+            && event
+                .paths
+                .iter()
+                .filter_map(|path| path.file_name())
+                .any(|name| self.names.contains(name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn changes(watcher: &mut DbWatcher) -> bool {
+        tokio::time::timeout(Duration::from_millis(500), watcher.changed())
+            .await
+            .is_ok()
+    }
+
+    #[tokio::test]
+    async fn writes_to_the_database_and_its_wal_count() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = dir.path().join("sqlite");
+        std::fs::write(&db, b"").unwrap();
+        let mut watcher = DbWatcher::new([db.as_path()]);
+        assert!(watcher.is_active());
+
+        std::fs::write(dir.path().join("sqlite-shm"), b"index").unwrap();
+        std::fs::write(dir.path().join("other"), b"other").unwrap();
+        assert!(!changes(&mut watcher).await);
+
+        std::fs::write(dir.path().join("sqlite-wal"), b"frame").unwrap();
+        assert!(changes(&mut watcher).await);
+        // The burst was drained with it
+        assert!(!changes(&mut watcher).await);
+
+        std::fs::write(&db, b"page").unwrap();
+        assert!(changes(&mut watcher).await);
+    }
+
+    #[tokio::test]
+    async fn missing_directories_fall_back_to_polling() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = dir.path().join("sqlite");
+        let missing = dir.path().join("mainnet/sqlite");
+        let watcher = DbWatcher::new([db.as_path(), missing.as_path()]);
+        assert!(!watcher.is_active());
+        assert!(!DbWatcher::disabled().is_active());
+    }
+}
@@ -0,0 +1,92 @@
+//! Dead man's switch for the monitor itself (`--heartbeat-url`): a
+//! Healthchecks.io check, or anything with the same API, is pinged after
+//! every poll cycle that could read all databases, and pinged at `/fail`
+//! with the errors as soon as one can't be read. If the monitor dies or
+//! hangs the pings stop, and the check raises the alarm.
+//!
+//! While nothing changes, pings go out at most once a minute, which is
+//! Healthchecks' shortest period anyway. Pings are sent in the background;
+//! a failed one surfaces like a failed notification.
+
+use anyhow::Result;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::http::{self, Request};
+
+/// Between pings that report the same thing
+const MIN_INTERVAL: Duration = Duration::from_secs(60);
+
+const ATTEMPTS: u32 = 3;
+
+#[derive(Debug)]
+pub struct Heartbeat {
+    /// The check's ping URL, e.g. `https://hc-ping.com/<uuid>`
+    pub url: String,
+    /// When the last ping went out, and whether it was a failure
+    last_ping: Option<(Instant, bool)>,
+    last_error: Arc<Mutex<Option<String>>>,
+}
+
+impl Heartbeat {
+    pub fn new(url: String) -> Self {
+        Self {
+            url,
+            last_ping: None,
+            last_error: Arc::default(),
+        }
+    }
+
+    /// Report a finished poll cycle; `failures` are the databases that
+    /// couldn't be read, one line each.
+    pub fn beat(&mut self, failures: &[String]) {
+        let failed = !failures.is_empty();
+        if let Some((at, was_failed)) = self.last_ping {
+            if was_failed == failed && at.elapsed() < MIN_INTERVAL {
+                return;
+            }
+        }
+        self.last_ping = Some((Instant::now(), failed));
+
+        let request = if failed {
+            Request::post(fail_url(&self.url))
+                .body("text/plain; charset=utf-8", failures.join("\n"))
+        } else {
+            Request::post(&self.url)
+        };
+        let url = http::redact(&self.url);
+        let last_error = Arc::clone(&self.last_error);
+        tokio::spawn(async move {
+            if let Err(err) = ping(request).await {
+                *last_error.lock().unwrap() = Some(format!("{url}: {err:#}"));
+            }
+        });
+    }
+
+    /// The most recent ping failure, cleared on read.
+    pub fn take_error(&self) -> Option<String> {
+        self.last_error.lock().unwrap().take()
+    }
+}
+
+async fn ping(request: Request) -> Result<()> {
+    request
+        .send_with_retry(ATTEMPTS)
+        .await?
+        .error_for_status()?;
+    Ok(())
+}
+
+/// `/fail` goes after the path, before any query string.
+fn fail_url(url: &str) -> String {
+    let (base, query) = match url.split_once('?') {
+        Some((base, query)) => (base, Some(query)),
+        None => (url, None),
+    };
+    let mut fail = format!("{}/fail", base.trim_end_matches('/'));
+    if let Some(query) = query {
+        fail.push('?');
+        fail.push_str(query);
+    }
+    fail
+}
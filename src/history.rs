@@ -0,0 +1,244 @@
+//! The optional history database (`--history-db`): a sqlite file of our own,
+//! separate from ASB's, that keeps every transition the monitor observes
+//! along with when it was observed. It also remembers the last state seen
+//! per swap, so after a restart the tracker carries on from there and swaps
+//! that moved while the monitor was down are still reported as transitions.
+//!
+//! Writes go through a single background task so they land in poll order;
+//! a failed write is surfaced like a failed notification and never stops
+//! the monitor.
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use sqlx::sqlite::{SqliteConnectOptions, SqliteJournalMode, SqliteRow};
+use sqlx::{Executor, Row, SqlitePool};
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use tokio::sync::mpsc;
+
+use crate::model::{Network, SwapView, Transition};
+use crate::state::SwapState;
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS transitions (
+    id INTEGER PRIMARY KEY,
+    instance TEXT NOT NULL,
+    swap_id TEXT NOT NULL,
+    old_state TEXT,
+    new_state TEXT NOT NULL,
+    entered_at TEXT NOT NULL,
+    observed_at TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS transitions_swap ON transitions (instance, swap_id);
+CREATE TABLE IF NOT EXISTS last_states (
+    instance TEXT NOT NULL,
+    swap_id TEXT NOT NULL,
+    state TEXT NOT NULL,
+    PRIMARY KEY (instance, swap_id)
+);
+";
+
+/// What one poll of one instance adds.
+struct Batch {
+    instance: String,
+    transitions: Vec<Transition>,
+    /// `(swap_id, state)` to remember as last seen
+    states: Vec<(String, SwapState)>,
+}
+
+#[derive(Clone)]
+pub struct HistoryStore {
+    pool: SqlitePool,
+    writer: mpsc::UnboundedSender<Batch>,
+    last_error: Arc<Mutex<Option<String>>>,
+}
+
+impl HistoryStore {
+    /// Open the database at `path`, creating it and its directory if needed.
+    pub async fn open(path: &Path) -> Result<Self> {
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir)
+                .with_context(|| format!("create directory {}", dir.display()))?;
+        }
+        let opts = SqliteConnectOptions::new()
+            .filename(path)
+            .create_if_missing(true)
+            .journal_mode(SqliteJournalMode::Wal);
+        let pool = SqlitePool::connect_with(opts)
+            .await
+            .with_context(|| format!("open history database {}", path.display()))?;
+        pool.execute(SCHEMA)
+            .await
+            .with_context(|| format!("set up history database {}", path.display()))?;
+
+        let (writer, mut batches) = mpsc::unbounded_channel::<Batch>();
+        let last_error = Arc::new(Mutex::new(None));
+        let store = Self {
+            pool: pool.clone(),
+            writer,
+            last_error: Arc::clone(&last_error),
+        };
+        tokio::spawn(async move {
+            while let Some(batch) = batches.recv().await {
+                if let Err(err) = write_batch(&pool, &batch).await {
+                    *last_error.lock().unwrap() = Some(format!("{err:#}"));
+                }
+            }
+        });
+        Ok(store)
+    }
+
+    /// Last state seen per swap of `instance`; empty on the first run.
+    pub async fn last_states(&self, instance: &str) -> Result<HashMap<String, SwapState>> {
+        let rows = sqlx::query("SELECT swap_id, state FROM last_states WHERE instance = ?")
+            .bind(instance)
+            .fetch_all(&self.pool)
+            .await
+            .context("read last seen states")?;
+        Ok(rows
+            .iter()
+            .map(|r| (r.get("swap_id"), SwapState::from(r.get::<&str, _>("state"))))
+            .collect())
+    }
+
+    /// Queue one poll's transitions for writing. `baseline` is every swap on
+    /// the first poll, whose states have to be remembered even though they
+    /// aren't transitions. Stuck alerts aren't state changes and are skipped.
+    pub fn record(&self, instance: &str, transitions: &[Transition], baseline: &[SwapView]) {
+        let transitions: Vec<Transition> = transitions
+            .iter()
+            .filter(|t| t.stuck_for_secs.is_none())
+            .cloned()
+            .collect();
+        let states = baseline
+            .iter()
+            .map(|v| (v.swap_id.clone(), v.state.clone()))
+            .chain(
+                transitions
+                    .iter()
+                    .map(|t| (t.swap_id.clone(), t.new_state.clone())),
+            )
+            .collect::<Vec<_>>();
+        if states.is_empty() {
+            return;
+        }
+        // Only fails once the writer is gone, i.e. at shutdown
+        let _ = self.writer.send(Batch {
+            instance: instance.to_string(),
+            transitions,
+            states,
+        });
+    }
+
+    /// Recorded transitions of `instance`, oldest first, optionally for one
+    /// swap only.
+    pub async fn transitions(
+        &self,
+        instance: &str,
+        swap_id: Option<&str>,
+    ) -> Result<Vec<Transition>> {
+        let rows = sqlx::query(
+            "SELECT swap_id, old_state, new_state, entered_at, observed_at FROM transitions \
+             WHERE instance = ?1 AND (?2 IS NULL OR swap_id = ?2) ORDER BY id",
+        )
+        .bind(instance)
+        .bind(swap_id)
+        .fetch_all(&self.pool)
+        .await
+        .context("query recorded transitions")?;
+        Ok(rows.iter().map(transition).collect())
+    }
+
+    /// The latest `limit` transitions of `instance`, oldest first.
+    pub async fn recent(&self, instance: &str, limit: usize) -> Result<Vec<Transition>> {
+        let rows = sqlx::query(
+            "SELECT swap_id, old_state, new_state, entered_at, observed_at FROM transitions \
+             WHERE instance = ? ORDER BY id DESC LIMIT ?",
+        )
+        .bind(instance)
+        .bind(limit as i64)
+        .fetch_all(&self.pool)
+        .await
+        .context("query recorded transitions")?;
+        Ok(rows.iter().rev().map(transition).collect())
+    }
+
+    /// Transitions of `instance` observed in `[since, until)`, oldest first.
+    pub async fn transitions_between(
+        &self,
+        instance: &str,
+        since: DateTime<Utc>,
+        until: DateTime<Utc>,
+    ) -> Result<Vec<Transition>> {
+        // `observed_at` is always written by `to_rfc3339` in UTC, so the
+        // text sorts chronologically
+        let rows = sqlx::query(
+            "SELECT swap_id, old_state, new_state, entered_at, observed_at FROM transitions \
+             WHERE instance = ? AND observed_at >= ? AND observed_at < ? ORDER BY id",
+        )
+        .bind(instance)
+        .bind(since.to_rfc3339())
+        .bind(until.to_rfc3339())
+        .fetch_all(&self.pool)
+        .await
+        .context("query recorded transitions")?;
+        Ok(rows.iter().map(transition).collect())
+    }
+
+    /// The most recent write failure, if any, cleared on read.
+    pub fn take_error(&self) -> Option<String> {
+        self.last_error.lock().unwrap().take()
+    }
+}
+
+fn transition(r: &SqliteRow) -> Transition {
+    Transition {
+        swap_id: r.get("swap_id"),
+        old_state: r.get::<Option<&str>, _>("old_state").map(SwapState::from),
+        new_state: SwapState::from(r.get::<&str, _>("new_state")),
+        entered_at: r.get("entered_at"),
+        observed_at: r.get("observed_at"),
+        stuck_for_secs: None,
+        anomaly: None,
+        peer_id: None,
+        watched: false,
+        pinned: false,
+        instance: None,
+        network: Network::default(),
+        btc_sats: None,
+        xmr_piconero: None,
+    }
+}
+
+async fn write_batch(pool: &SqlitePool, batch: &Batch) -> Result<()> {
+    let mut tx = pool.begin().await?;
+    for transition in &batch.transitions {
+        sqlx::query(
+            "INSERT INTO transitions \
+             (instance, swap_id, old_state, new_state, entered_at, observed_at) \
+             VALUES (?, ?, ?, ?, ?, ?)",
+        )
+        .bind(&batch.instance)
+        .bind(&transition.swap_id)
+        .bind(transition.old_state.as_ref().map(SwapState::as_str))
+        .bind(transition.new_state.as_str())
+        .bind(&transition.entered_at)
+        .bind(&transition.observed_at)
+        .execute(&mut *tx)
+        .await?;
+    }
+    for (swap_id, state) in &batch.states {
+        sqlx::query(
+            "INSERT INTO last_states (instance, swap_id, state) VALUES (?, ?, ?) \
+             ON CONFLICT (instance, swap_id) DO UPDATE SET state = excluded.state",
+        )
+        .bind(&batch.instance)
+        .bind(swap_id)
+        .bind(state.as_str())
+        .execute(&mut *tx)
+        .await?;
+    }
+    tx.commit().await?;
+    Ok(())
+}
@@ -0,0 +1,102 @@
+//! Command hooks (`--on-transition`): run a shell command for every state
+//! transition, for integrations the notifiers don't cover.
+//!
+//! The command runs through `sh -c` with the transition in its environment:
+//! `SWAP_ID`, `OLD_STATE` (empty for a new swap), `NEW_STATE`, `ENTERED_AT`,
+//! `OBSERVED_AT`, `NETWORK` and, when several ASBs are monitored, `INSTANCE`.
+//! At most `concurrency` commands run at once and the rest wait their turn;
+//! one that outlives `timeout` is killed. Hooks see every transition,
+//! regardless of alert rules and quiet hours, but not stuck alerts; a
+//! command only interested in some states can check `NEW_STATE` itself.
+
+use anyhow::{anyhow, bail, Context, Result};
+use std::process::Stdio;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::process::Command;
+use tokio::sync::Semaphore;
+
+use crate::model::Transition;
+
+pub const DEFAULT_HOOK_TIMEOUT: Duration = Duration::from_secs(30);
+pub const DEFAULT_HOOK_CONCURRENCY: usize = 4;
+
+/// How much of a failing command's stderr makes it into the error
+const STDERR_EXCERPT: usize = 200;
+
+#[derive(Debug, Clone)]
+pub struct Hook {
+    pub command: String,
+    pub timeout: Duration,
+    slots: Arc<Semaphore>,
+}
+
+impl Hook {
+    pub fn new(command: String, timeout: Duration, concurrency: usize) -> Self {
+        Self {
+            command,
+            timeout,
+            slots: Arc::new(Semaphore::new(concurrency.max(1))),
+        }
+    }
+
+    /// Start the command for `transition` in the background; failures end up
+    /// in `last_error`.
+    pub fn run(&self, transition: &Transition, last_error: &Arc<Mutex<Option<String>>>) {
+        if transition.stuck_for_secs.is_some() || transition.anomaly.is_some() {
+            return;
+        }
+        let hook = self.clone();
+        let transition = transition.clone();
+        let last_error = Arc::clone(last_error);
+        tokio::spawn(async move {
+            let Ok(_slot) = hook.slots.acquire().await else {
+                return;
+            };
+            if let Err(err) = hook.execute(&transition).await {
+                *last_error.lock().unwrap() = Some(format!("on_transition hook: {err:#}"));
+            }
+        });
+    }
+
+    async fn execute(&self, transition: &Transition) -> Result<()> {
+        let mut command = Command::new("sh");
+        command
+            .arg("-c")
+            .arg(&self.command)
+            .env("SWAP_ID", &transition.swap_id)
+            .env(
+                "OLD_STATE",
+                transition
+                    .old_state
+                    .as_ref()
+                    .map(|s| s.as_str())
+                    .unwrap_or(""),
+            )
+            .env("NEW_STATE", transition.new_state.as_str())
+            .env("ENTERED_AT", &transition.entered_at)
+            .env("OBSERVED_AT", &transition.observed_at)
+            .env("NETWORK", transition.network.dir_name())
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true);
+        if let Some(ref instance) = transition.instance {
+            command.env("INSTANCE", instance);
+        }
+
+        let output = tokio::time::timeout(self.timeout, command.output())
+            .await
+            .map_err(|_| anyhow!("killed after {}s", self.timeout.as_secs()))?
+            .context("spawn sh")?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            let stderr: String = stderr.trim().chars().take(STDERR_EXCERPT).collect();
+            if stderr.is_empty() {
+                bail!("command exited with {}", output.status);
+            }
+            bail!("command exited with {}: {stderr}", output.status);
+        }
+        Ok(())
+    }
+}
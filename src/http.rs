@@ -0,0 +1,385 @@
+//! Small request/response wrapper over reqwest, for the notifiers, price
+//! feeds and integrations.
+//!
+//! One client is shared by every request, so connections to the same host
+//! are reused. TLS is rustls with the Mozilla roots, and the usual
+//! `HTTPS_PROXY`/`HTTP_PROXY`/`NO_PROXY` variables apply. Digest auth, which
+//! monero-wallet-rpc uses, answers the server's challenge and sends the
+//! request again.
+
+use anyhow::{anyhow, bail, Context, Result};
+use reqwest::header::{AUTHORIZATION, WWW_AUTHENTICATE};
+use reqwest::{Client, Method, StatusCode};
+use serde::Serialize;
+use std::sync::OnceLock;
+use std::time::Duration;
+use tokio::time::sleep;
+
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone)]
+pub struct Request {
+    method: &'static str,
+    url: String,
+    headers: Vec<(String, String)>,
+    body: Option<Vec<u8>>,
+    timeout: Duration,
+    /// `user:password` for HTTP digest auth
+    digest_login: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Response {
+    pub status: u16,
+    pub body: String,
+}
+
+impl Response {
+    pub fn is_success(&self) -> bool {
+        (200..300).contains(&self.status)
+    }
+
+    /// Whether a failed request is worth retrying: rate limits and server
+    /// errors usually clear up, client errors don't.
+    fn is_transient(&self) -> bool {
+        self.status == 429 || self.status >= 500
+    }
+
+    pub fn error_for_status(self) -> Result<Self> {
+        if self.is_success() {
+            return Ok(self);
+        }
+        let snippet: String = self.body.chars().take(200).collect();
+        bail!("HTTP {}: {}", self.status, snippet.trim());
+    }
+}
+
+impl Request {
+    pub fn new(method: &'static str, url: impl Into<String>) -> Self {
+        Self {
+            method,
+            url: url.into(),
+            headers: Vec::new(),
+            body: None,
+            timeout: DEFAULT_TIMEOUT,
+            digest_login: None,
+        }
+    }
+
+    pub fn post(url: impl Into<String>) -> Self {
+        Self::new("POST", url)
+    }
+
+    pub fn header(mut self, name: &str, value: impl Into<String>) -> Self {
+        self.headers.push((name.to_string(), value.into()));
+        self
+    }
+
+    pub fn body(mut self, content_type: &str, body: impl Into<Vec<u8>>) -> Self {
+        self.body = Some(body.into());
+        self.header("Content-Type", content_type)
+    }
+
+    pub fn json<T: Serialize>(self, value: &T) -> Result<Self> {
+        let body = serde_json::to_vec(value).context("serialize request body")?;
+        Ok(self.body("application/json", body))
+    }
+
+    pub fn digest_auth(mut self, login: &str) -> Self {
+        self.digest_login = Some(login.to_string());
+        self
+    }
+
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub async fn send(&self) -> Result<Response> {
+        let response = self.attempt(None).await?;
+        let challenge = match self.digest_login {
+            Some(ref login) if response.status() == StatusCode::UNAUTHORIZED => response
+                .headers()
+                .get(WWW_AUTHENTICATE)
+                .and_then(|value| value.to_str().ok())
+                .map(|challenge| (login, challenge.to_string())),
+            _ => None,
+        };
+        let response = match challenge {
+            Some((login, challenge)) => {
+                let authorization = self.digest(login, &challenge)?;
+                self.attempt(Some(authorization)).await?
+            }
+            None => response,
+        };
+
+        let status = response.status().as_u16();
+        let body = response
+            .text()
+            .await
+            .map_err(|err| self.error(err))
+            .context("read the response")?;
+        Ok(Response { status, body })
+    }
+
+    async fn attempt(&self, authorization: Option<String>) -> Result<reqwest::Response> {
+        let method = Method::from_bytes(self.method.as_bytes()).context("HTTP method")?;
+        let mut request = client()?.request(method, &self.url).timeout(self.timeout);
+        for (name, value) in &self.headers {
+            request = request.header(name, value);
+        }
+        if let Some(authorization) = authorization {
+            request = request.header(AUTHORIZATION, authorization);
+        }
+        if let Some(ref body) = self.body {
+            request = request.body(body.clone());
+        }
+        request.send().await.map_err(|err| self.error(err))
+    }
+
+    /// The `Authorization` header answering a digest challenge.
+    fn digest(&self, login: &str, challenge: &str) -> Result<String> {
+        let (user, password) = login.split_once(':').unwrap_or((login, ""));
+        let url = reqwest::Url::parse(&self.url).context("invalid URL")?;
+        let uri = match url.query() {
+            Some(query) => format!("{}?{query}", url.path()),
+            None => url.path().to_string(),
+        };
+        let context = digest_auth::AuthContext::new_with_method(
+            user,
+            password,
+            uri,
+            self.body.as_deref(),
+            digest_auth::HttpMethod::from(self.method),
+        );
+        let mut prompt = digest_auth::parse(challenge)
+            .map_err(|err| anyhow!("{}: bad digest challenge: {err}", redact(&self.url)))?;
+        let answer = prompt
+            .respond(&context)
+            .map_err(|err| anyhow!("{}: digest auth: {err}", redact(&self.url)))?;
+        Ok(answer.to_string())
+    }
+
+    /// reqwest's errors name the URL, which may hold a token.
+    fn error(&self, err: reqwest::Error) -> anyhow::Error {
+        let reason = match err.is_timeout() {
+            true => format!("timed out after {}s", self.timeout.as_secs()),
+            false => format!("{:#}", anyhow::Error::from(err.without_url())),
+        };
+        anyhow!("{} {}: {reason}", self.method, redact(&self.url))
+    }
+
+    /// Send with exponential backoff (1s, 2s, 4s, ...) on transport errors
+    /// and transient HTTP statuses. Returns the first non-transient response.
+    pub async fn send_with_retry(&self, attempts: u32) -> Result<Response> {
+        let mut delay = Duration::from_secs(1);
+        let mut attempt = 1;
+
+        loop {
+            let result = self.send().await;
+            let retry = match result {
+                Ok(ref response) => response.is_transient(),
+                Err(_) => true,
+            };
+            if !retry || attempt >= attempts {
+                return result;
+            }
+
+            sleep(delay).await;
+            delay *= 2;
+            attempt += 1;
+        }
+    }
+}
+
+fn client() -> Result<&'static Client> {
+    static CLIENT: OnceLock<Client> = OnceLock::new();
+    if let Some(client) = CLIENT.get() {
+        return Ok(client);
+    }
+    let client = Client::builder()
+        .user_agent(concat!("wraithswap/", env!("CARGO_PKG_VERSION")))
+        .build()
+        .context("set up the HTTP client")?;
+    Ok(CLIENT.get_or_init(|| client))
+}
+
+/// Percent-encode everything but unreserved characters, for a value
+/// going into a URL path segment.
+pub fn encode_path_segment(value: &str) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                encoded.push(byte as char)
+            }
+            _ => encoded.push_str(&format!("%{byte:02X}")),
+        }
+    }
+    encoded
+}
+
+/// Drop the path and query from a URL before it goes into an error message;
+/// several services embed tokens there.
+pub fn redact(url: &str) -> String {
+    let Some(scheme_end) = url.find("://") else {
+        return url.to_string();
+    };
+    let host_end = url[scheme_end + 3..]
+        .find('/')
+        .map(|i| scheme_end + 3 + i)
+        .unwrap_or(url.len());
+    format!("{}/…", &url[..host_end])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::TcpListener;
+
+    /// A server on localhost that answers each connection with the next of
+    /// `replies` and hands back what it was sent.
+    async fn server(replies: Vec<&'static str>) -> (String, tokio::task::JoinHandle<Vec<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let handle = tokio::spawn(async move {
+            let mut requests = Vec::new();
+            for reply in replies {
+                let (stream, _) = listener.accept().await.unwrap();
+                let mut stream = BufReader::new(stream);
+                let mut request = String::new();
+                let mut length = 0;
+                loop {
+                    let mut line = String::new();
+                    stream.read_line(&mut line).await.unwrap();
+                    if let Some((name, value)) = line.split_once(':') {
+                        if name.eq_ignore_ascii_case("content-length") {
+                            length = value.trim().parse().unwrap();
+                        }
+                    }
+                    request.push_str(&line);
+                    if line == "\r\n" {
+                        break;
+                    }
+                }
+                let mut body = vec![0; length];
+                stream.read_exact(&mut body).await.unwrap();
+                request.push_str(&String::from_utf8(body).unwrap());
+                requests.push(request);
+                stream.write_all(reply.as_bytes()).await.unwrap();
+                stream.shutdown().await.unwrap();
+            }
+            requests
+        });
+        (url, handle)
+    }
+
+    #[tokio::test]
+    async fn requests_carry_headers_and_body() {
+        let (url, server) = server(vec![
+            "HTTP/1.1 201 Created\r\nContent-Length: 2\r\nConnection: close\r\n\r\nok",
+        ])
+        .await;
+        let response = Request::post(format!("{url}/hook?token=secret"))
+            .header("X-Title", "Swap")
+            .json(&serde_json::json!({"state": "BtcLocked"}))
+            .unwrap()
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status, 201);
+        assert_eq!(response.body, "ok");
+        assert!(response.error_for_status().is_ok());
+
+        let request = &server.await.unwrap()[0];
+        assert!(
+            request.starts_with("POST /hook?token=secret HTTP/1.1\r\n"),
+            "{request}"
+        );
+        let lower = request.to_ascii_lowercase();
+        assert!(lower.contains("x-title: swap\r\n"), "{request}");
+        assert!(
+            lower.contains("content-type: application/json\r\n"),
+            "{request}"
+        );
+        assert!(request.ends_with(r#"{"state":"BtcLocked"}"#), "{request}");
+    }
+
+    #[tokio::test]
+    async fn digest_challenges_are_answered() {
+        let (url, server) = server(vec![
+            "HTTP/1.1 401 Unauthorized\r\nWWW-Authenticate: Digest realm=\"monero-rpc\", \
+             qop=\"auth\", algorithm=MD5, nonce=\"abc\"\r\nContent-Length: 0\r\n\
+             Connection: close\r\n\r\n",
+            "HTTP/1.1 200 OK\r\nContent-Length: 2\r\nConnection: close\r\n\r\n{}",
+        ])
+        .await;
+        let response = Request::post(format!("{url}/json_rpc"))
+            .body("application/json", "{}")
+            .digest_auth("monitor:secret")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status, 200);
+
+        let requests = server.await.unwrap();
+        assert!(!requests[0].to_ascii_lowercase().contains("authorization"));
+        let authorization = requests[1]
+            .lines()
+            .find(|line| line.to_ascii_lowercase().starts_with("authorization:"))
+            .unwrap();
+        for part in [
+            "Digest username=\"monitor\"",
+            "realm=\"monero-rpc\"",
+            "uri=\"/json_rpc\"",
+            "nonce=\"abc\"",
+        ] {
+            assert!(authorization.contains(part), "{authorization}");
+        }
+        assert!(!authorization.contains("secret"), "{authorization}");
+    }
+
+    #[tokio::test]
+    async fn transient_failures_are_retried() {
+        let (url, server) = server(vec![
+            "HTTP/1.1 503 Service Unavailable\r\nContent-Length: 4\r\nConnection: close\r\n\r\nbusy",
+            "HTTP/1.1 400 Bad Request\r\nContent-Length: 3\r\nConnection: close\r\n\r\nbad",
+        ])
+        .await;
+        let response = Request::new("GET", &url).send_with_retry(3).await.unwrap();
+        assert_eq!(response.status, 400);
+        assert_eq!(server.await.unwrap().len(), 2);
+        let err = response.error_for_status().unwrap_err();
+        assert_eq!(err.to_string(), "HTTP 400: bad");
+    }
+
+    #[tokio::test]
+    async fn errors_leave_the_path_out() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        drop(listener);
+        let err = Request::post(format!("http://{addr}/hook/secret-token"))
+            .send()
+            .await
+            .unwrap_err();
+        let message = format!("{err:#}");
+        assert!(
+            message.starts_with(&format!("POST http://{addr}/…: ")),
+            "{message}"
+        );
+        assert!(!message.contains("secret-token"), "{message}");
+    }
+
+    #[test]
+    fn urls_are_redacted_to_the_host() {
+        assert_eq!(
+            redact("https://hooks.example.org/services/T0/B0/secret"),
+            "https://hooks.example.org/…"
+        );
+        assert_eq!(
+            encode_path_segment("!room:example.org"),
+            "%21room%3Aexample.org"
+        );
+    }
+}
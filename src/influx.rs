@@ -0,0 +1,163 @@
+//! Metrics sink for InfluxDB and anything else that accepts line protocol
+//! over HTTP (`--influx-url`), so swap activity and liquidity can be graphed
+//! in an existing time-series setup.
+//!
+//! Every poll writes the number of swaps per state and instance, and the
+//! latest wallet balances when they're being looked up. Every transition
+//! adds a point of its own. Points are collected during a poll and posted
+//! together in the background; a failed write is dropped, not retried, and
+//! surfaces like a failed notification.
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
+use crate::chain::BalanceSample;
+use crate::db::Instance;
+use crate::http::Request;
+use crate::model::{SwapView, Transition};
+
+#[derive(Debug)]
+pub struct InfluxSink {
+    /// The full write endpoint, e.g. `http://host:8086/api/v2/write?org=o&bucket=b`
+    /// or `http://host:8086/write?db=wraithswap`
+    pub url: String,
+    /// Sent as `Authorization: Token ...` (InfluxDB 2 and later)
+    pub token: Option<String>,
+    lines: Vec<String>,
+    last_error: Arc<Mutex<Option<String>>>,
+}
+
+impl InfluxSink {
+    pub fn new(url: String, token: Option<String>) -> Self {
+        Self {
+            url,
+            token,
+            lines: Vec::new(),
+            last_error: Arc::default(),
+        }
+    }
+
+    /// Gauges for one instance's poll, and a point per transition in it.
+    pub fn record_poll(
+        &mut self,
+        instance: &Instance,
+        views: &[SwapView],
+        transitions: &[Transition],
+    ) {
+        let now = Utc::now();
+        let tags = format!(
+            "instance={},network={}",
+            escape_tag(&instance.name),
+            instance.network.dir_name()
+        );
+
+        let mut states: BTreeMap<&str, u64> = BTreeMap::new();
+        for view in views {
+            *states.entry(view.state.as_str()).or_default() += 1;
+        }
+        for (state, count) in states {
+            self.push(
+                format!(
+                    "wraithswap_swaps,{tags},state={} count={count}i",
+                    escape_tag(state)
+                ),
+                now,
+            );
+        }
+        let stuck = views.iter().filter(|v| v.stuck).count();
+        self.push(
+            format!(
+                "wraithswap_summary,{tags} total={}i,stuck={stuck}i",
+                views.len()
+            ),
+            now,
+        );
+
+        // Stuck alerts are already in the summary, and anomaly alerts repeat
+        // a transition
+        for transition in transitions
+            .iter()
+            .filter(|t| t.stuck_for_secs.is_none() && t.anomaly.is_none())
+        {
+            self.push(
+                format!(
+                    "wraithswap_transition,{tags},old_state={},new_state={} swap_id=\"{}\",entered_at=\"{}\"",
+                    escape_tag(transition.old_state.as_ref().map(|s| s.as_str()).unwrap_or("none")),
+                    escape_tag(transition.new_state.as_str()),
+                    escape_string(&transition.swap_id),
+                    escape_string(&transition.entered_at),
+                ),
+                now,
+            );
+        }
+    }
+
+    /// Wallet balances as of their last lookup. Writing the same sample
+    /// twice overwrites the earlier point.
+    pub fn record_balances(&mut self, sample: &BalanceSample) {
+        let mut fields = Vec::new();
+        if let Some(btc) = sample.btc {
+            fields.push(format!("btc_confirmed_sats={}i", btc.confirmed));
+            fields.push(format!("btc_unconfirmed_sats={}i", btc.unconfirmed));
+        }
+        if let Some(xmr) = sample.xmr {
+            fields.push(format!("xmr_piconero={}i", xmr.balance));
+            fields.push(format!("xmr_unlocked_piconero={}i", xmr.unlocked));
+        }
+        if !fields.is_empty() {
+            self.push(
+                format!("wraithswap_balance {}", fields.join(",")),
+                sample.at,
+            );
+        }
+    }
+
+    /// Post everything recorded since the last flush.
+    pub fn flush(&mut self) {
+        if self.lines.is_empty() {
+            return;
+        }
+        let mut body = std::mem::take(&mut self.lines).join("\n");
+        body.push('\n');
+        let mut request = Request::post(&self.url).body("text/plain; charset=utf-8", body);
+        if let Some(ref token) = self.token {
+            request = request.header("Authorization", format!("Token {token}"));
+        }
+        let last_error = Arc::clone(&self.last_error);
+        tokio::spawn(async move {
+            if let Err(err) = write(request).await {
+                *last_error.lock().unwrap() = Some(format!("{err:#}"));
+            }
+        });
+    }
+
+    /// The most recent write failure, cleared on read.
+    pub fn take_error(&self) -> Option<String> {
+        self.last_error.lock().unwrap().take()
+    }
+
+    fn push(&mut self, line: String, at: DateTime<Utc>) {
+        let nanos = at.timestamp_nanos_opt().unwrap_or_default();
+        self.lines.push(format!("{line} {nanos}"));
+    }
+}
+
+async fn write(request: Request) -> Result<()> {
+    request.send().await?.error_for_status()?;
+    Ok(())
+}
+
+/// Tag values can't contain unescaped commas, equals signs or spaces.
+fn escape_tag(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace(',', "\\,")
+        .replace('=', "\\=")
+        .replace(' ', "\\ ")
+}
+
+fn escape_string(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"")
+}
@@ -0,0 +1,138 @@
+//! Whether ASB itself is running, as opposed to its database merely
+//! existing: by its pid file (`--asb-pid-file`), by looking for its process
+//! (`--asb-process`), or by connecting to the port it listens on for peers
+//! (`--asb-address`). Every configured check has to pass.
+//!
+//! A stopped ASB leaves its database behind looking perfectly healthy, but
+//! swaps in flight can't move on without it, and a taker who locked BTC
+//! waits for XMR that never comes until the cancel timelock runs out.
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::net::TcpStream;
+use tokio::time::timeout;
+
+/// How long a connection to ASB's port may take
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Longest process name the kernel keeps, without the terminating nul
+const COMM_LEN: usize = 15;
+
+/// How to tell that ASB is running.
+#[derive(Debug, Clone, Default)]
+pub struct AsbProbe {
+    /// Written by whatever starts ASB, e.g. a service manager
+    pub pid_file: Option<PathBuf>,
+    /// Process name, e.g. `asb`; found through `/proc`, so Linux only
+    pub process: Option<String>,
+    /// `host:port` ASB accepts peers on
+    pub address: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AsbStatus {
+    /// The pid, when a pid file or process scan found one
+    Running { pid: Option<u32> },
+    /// The first check that failed, and how
+    Down { reason: String },
+}
+
+/// ASB stopped with swaps in flight.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct AsbDown {
+    pub reason: String,
+    pub in_flight: usize,
+}
+
+impl AsbProbe {
+    pub fn is_empty(&self) -> bool {
+        self.pid_file.is_none() && self.process.is_none() && self.address.is_none()
+    }
+
+    /// Run every configured check, stopping at the first failure.
+    pub async fn check(&self) -> AsbStatus {
+        let down = |reason: String| AsbStatus::Down { reason };
+        let mut pid = None;
+        if let Some(ref path) = self.pid_file {
+            match read_pid(path) {
+                Ok(found) if is_alive(found) => pid = Some(found),
+                Ok(found) => {
+                    return down(format!(
+                        "pid {found} from {} is not running",
+                        path.display()
+                    ))
+                }
+                Err(err) => return down(format!("{err:#}")),
+            }
+        }
+        if let Some(ref name) = self.process {
+            match find_process(name) {
+                Some(found) => {
+                    pid.get_or_insert(found);
+                }
+                None => return down(format!("no {name} process")),
+            }
+        }
+        if let Some(ref address) = self.address {
+            match timeout(CONNECT_TIMEOUT, TcpStream::connect(address.as_str())).await {
+                Ok(Ok(_)) => {}
+                Ok(Err(err)) => return down(format!("{address}: {}", err.kind())),
+                Err(_) => return down(format!("{address}: no answer")),
+            }
+        }
+        AsbStatus::Running { pid }
+    }
+}
+
+fn read_pid(path: &Path) -> Result<u32> {
+    let text = std::fs::read_to_string(path)
+        .with_context(|| format!("read pid file {}", path.display()))?;
+    text.trim()
+        .parse()
+        .with_context(|| format!("invalid pid file {}", path.display()))
+}
+
+/// Signal 0 only checks that the process exists; one owned by another
+/// user answers with a permission error, which is still an answer.
+fn is_alive(pid: u32) -> bool {
+    let Ok(pid) = libc::pid_t::try_from(pid) else {
+        return false;
+    };
+    let signalled = unsafe { libc::kill(pid, 0) } == 0;
+    signalled || std::io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
+/// The lowest pid whose name is `name`. The kernel cuts names to 15 bytes,
+/// so only that much is compared.
+fn find_process(name: &str) -> Option<u32> {
+    let name = &name.as_bytes()[..name.len().min(COMM_LEN)];
+    std::fs::read_dir("/proc")
+        .ok()?
+        .flatten()
+        .filter_map(|entry| entry.file_name().to_str()?.parse::<u32>().ok())
+        .filter(|pid| {
+            std::fs::read(format!("/proc/{pid}/comm"))
+                .is_ok_and(|comm| comm.strip_suffix(b"\n").unwrap_or(&comm) == name)
+        })
+        .min()
+}
+
+/// `host:port` to reach a listen multiaddress like `/ip4/0.0.0.0/tcp/9939`
+/// from this machine; unspecified addresses mean loopback.
+pub fn listen_address(addr: &str) -> Option<String> {
+    let mut parts = addr.split('/').skip(1);
+    let (kind, host) = (parts.next()?, parts.next()?);
+    let port = match (parts.next()?, parts.next()?) {
+        ("tcp", port) => port.parse::<u16>().ok()?,
+        _ => return None,
+    };
+    match (kind, host) {
+        ("ip4", "0.0.0.0") => Some(format!("127.0.0.1:{port}")),
+        ("ip6", "::") => Some(format!("[::1]:{port}")),
+        ("ip6", host) => Some(format!("[{host}]:{port}")),
+        ("ip4" | "dns" | "dns4" | "dns6", host) => Some(format!("{host}:{port}")),
+        _ => None,
+    }
+}
@@ -0,0 +1,203 @@
+//! Log output for daemon mode: `tracing-subscriber`'s fmt layer writing one
+//! line per event, as plain text or JSON, to stdout or a file. Under
+//! systemd, stdout defaults to journald's format: no timestamp, since the
+//! journal adds one, and a `<N>` syslog priority prefix it turns into the
+//! log level.
+//!
+//! The log layer and the `otel` one sit side by side on one registry, each
+//! with its own filter. Which events are logged is up to `RUST_LOG`; by
+//! default our own from INFO up and dependencies' (sqlx mostly) from WARN.
+
+use anyhow::{bail, Context, Result};
+use std::fmt;
+use std::fs::OpenOptions;
+use std::io;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::Mutex;
+use tracing::{Event, Level, Subscriber};
+use tracing_subscriber::fmt::format::{Format, Full, Writer};
+use tracing_subscriber::fmt::time::ChronoUtc;
+use tracing_subscriber::fmt::writer::BoxMakeWriter;
+use tracing_subscriber::fmt::{FmtContext, FormatEvent, FormatFields};
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::{EnvFilter, Layer, Registry};
+
+use crate::otel::Tracer;
+use crate::systemd;
+
+/// What's logged when `RUST_LOG` isn't set
+const DEFAULT_FILTER: &str = "warn,wraithswap_poc=info,wraithswap_core=info";
+/// RFC 3339 in UTC with milliseconds
+const TIMESTAMP: &str = "%Y-%m-%dT%H:%M:%S%.3fZ";
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// `2026-01-01T00:00:00.000Z  INFO message key=value…`
+    #[default]
+    Text,
+    /// One JSON object per line
+    Json,
+    /// `<6>message key=value…`
+    Journald,
+}
+
+impl FromStr for LogFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            "journald" => Ok(LogFormat::Journald),
+            other => bail!("unknown log format: {other} (expected text, json or journald)"),
+        }
+    }
+}
+
+/// Install the global subscriber. `file` is appended to; stdout otherwise.
+/// Without an explicit format, journald's is used when stdout is the journal.
+pub fn init(format: Option<LogFormat>, file: Option<&Path>, tracer: Option<&Tracer>) -> Result<()> {
+    let format = format.unwrap_or(if file.is_none() && systemd::stdout_is_journal() {
+        LogFormat::Journald
+    } else {
+        LogFormat::Text
+    });
+    let writer = match file {
+        Some(path) => BoxMakeWriter::new(Mutex::new(
+            OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .with_context(|| format!("open log file {}", path.display()))?,
+        )),
+        None => BoxMakeWriter::new(io::stdout),
+    };
+    let filter = match std::env::var(EnvFilter::DEFAULT_ENV) {
+        Ok(directives) => EnvFilter::try_new(directives).context("parse RUST_LOG")?,
+        Err(_) => EnvFilter::new(DEFAULT_FILTER),
+    };
+    let subscriber = Registry::default()
+        .with(tracer.map(Tracer::layer))
+        .with(layer(format, writer).with_filter(filter));
+    tracing::subscriber::set_global_default(subscriber).context("install logger")
+}
+
+/// The fmt layer writing `format` lines to `writer`.
+fn layer<S>(format: LogFormat, writer: BoxMakeWriter) -> Box<dyn Layer<S> + Send + Sync>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    let layer = tracing_subscriber::fmt::layer()
+        .with_writer(writer)
+        .with_ansi(false);
+    match format {
+        LogFormat::Text => layer
+            .with_target(false)
+            .with_timer(ChronoUtc::new(TIMESTAMP.into()))
+            .boxed(),
+        LogFormat::Json => layer
+            .json()
+            .flatten_event(true)
+            .with_timer(ChronoUtc::new(TIMESTAMP.into()))
+            .boxed(),
+        LogFormat::Journald => layer
+            .event_format(Journald(
+                Format::default()
+                    .without_time()
+                    .with_level(false)
+                    .with_target(false),
+            ))
+            .boxed(),
+    }
+}
+
+/// The text format behind a `<N>` priority prefix instead of the level.
+struct Journald(Format<Full, ()>);
+
+impl<S, N> FormatEvent<S, N> for Journald
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+{
+    fn format_event(
+        &self,
+        ctx: &FmtContext<'_, S, N>,
+        mut writer: Writer<'_>,
+        event: &Event<'_>,
+    ) -> fmt::Result {
+        write!(writer, "<{}>", priority(event.metadata().level()))?;
+        self.0.format_event(ctx, writer, event)
+    }
+}
+
+/// syslog priority as in `sd-daemon(3)`.
+fn priority(level: &Level) -> u8 {
+    match *level {
+        Level::ERROR => 3,
+        Level::WARN => 4,
+        Level::INFO => 6,
+        _ => 7,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    /// The lines `format` writes for a warning inside a span.
+    fn logged(format: LogFormat) -> String {
+        let out = Arc::new(Mutex::new(Vec::new()));
+        let sink = Arc::clone(&out);
+        let writer = BoxMakeWriter::new(move || Sink(Arc::clone(&sink)));
+        let subscriber = Registry::default().with(layer(format, writer));
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info_span!("poll", instance = "testnet").in_scope(|| {
+                tracing::warn!(error = "database is locked", "poll failed");
+            });
+        });
+        let out = out.lock().unwrap();
+        String::from_utf8(out.clone()).unwrap()
+    }
+
+    struct Sink(Arc<Mutex<Vec<u8>>>);
+
+    impl io::Write for Sink {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn journald_lines_carry_the_priority() {
+        assert_eq!(
+            logged(LogFormat::Journald),
+            "<4>poll{instance=\"testnet\"}: poll failed error=\"database is locked\"\n"
+        );
+    }
+
+    #[test]
+    fn text_and_json_lines_have_a_timestamp() {
+        let text = logged(LogFormat::Text);
+        assert!(
+            text.ends_with(
+                "Z  WARN poll{instance=\"testnet\"}: poll failed error=\"database is locked\"\n"
+            ),
+            "{text}"
+        );
+
+        let json: serde_json::Value = serde_json::from_str(&logged(LogFormat::Json)).unwrap();
+        assert_eq!(json["level"], "WARN");
+        assert_eq!(json["message"], "poll failed");
+        assert_eq!(json["error"], "database is locked");
+        assert_eq!(json["span"]["instance"], "testnet");
+        assert!(json["timestamp"].as_str().unwrap().ends_with('Z'));
+    }
+}
@@ -0,0 +1,272 @@
+//! Alert mail through lettre's SMTP transport.
+//!
+//! `smtps://` servers get TLS from the start; `smtp://` ones have to offer
+//! STARTTLS or nothing is sent, so credentials never cross the wire in the
+//! clear. Addresses are parsed as mailboxes, which can't carry a line break
+//! into the headers.
+
+use anyhow::{bail, Context, Result};
+use lettre::message::header::ContentType;
+use lettre::message::Mailbox;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Tokio1Executor};
+use std::time::Duration;
+
+#[derive(Debug, Clone)]
+pub struct SmtpServer {
+    /// `smtps://host:465` or `smtp://host:587`
+    pub url: String,
+    /// `user:password`, if the server wants a login
+    pub login: Option<String>,
+}
+
+/// Per message, including the TLS handshake and login
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+pub struct Message<'a> {
+    pub from: &'a str,
+    pub to: &'a [String],
+    pub subject: &'a str,
+    pub body: &'a str,
+}
+
+impl SmtpServer {
+    pub async fn send(&self, message: &Message<'_>) -> Result<()> {
+        let email = build(message)?;
+        let transport = self.transport()?;
+        tokio::time::timeout(TIMEOUT, transport.send(email))
+            .await
+            .with_context(|| format!("no answer from {} in {}s", self.url, TIMEOUT.as_secs()))?
+            .context("send mail")?;
+        Ok(())
+    }
+
+    fn transport(&self) -> Result<AsyncSmtpTransport<Tokio1Executor>> {
+        let (host, port) = self.host_port()?;
+        let builder = match port {
+            Port::Tls(_) => AsyncSmtpTransport::<Tokio1Executor>::relay(host),
+            Port::StartTls(_) => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(host),
+        }
+        .with_context(|| format!("SMTP server {:?}", self.url))?;
+        let mut builder = builder.port(port.number()).timeout(Some(TIMEOUT));
+        if let Some(ref login) = self.login {
+            let (user, password) = login.split_once(':').unwrap_or((login, ""));
+            builder = builder.credentials(Credentials::new(user.into(), password.into()));
+        }
+        Ok(builder.build())
+    }
+
+    fn host_port(&self) -> Result<(&str, Port)> {
+        let (rest, tls) = match (
+            self.url.strip_prefix("smtps://"),
+            self.url.strip_prefix("smtp://"),
+        ) {
+            (Some(rest), _) => (rest, true),
+            (_, Some(rest)) => (rest, false),
+            _ => bail!("expected an smtps:// or smtp:// URL, got {:?}", self.url),
+        };
+        let rest = rest.trim_end_matches('/');
+        let (host, port) = match rest.strip_prefix('[') {
+            Some(v6) => {
+                let (host, after) = v6
+                    .split_once(']')
+                    .with_context(|| format!("unclosed [ in {:?}", self.url))?;
+                (host, after.strip_prefix(':'))
+            }
+            None => match rest.rsplit_once(':') {
+                Some((host, port)) => (host, Some(port)),
+                None => (rest, None),
+            },
+        };
+        if host.is_empty() {
+            bail!("no host in {:?}", self.url);
+        }
+        let port = match port {
+            Some(port) => port
+                .parse()
+                .with_context(|| format!("bad port in {:?}", self.url))?,
+            None if tls => 465,
+            None => 587,
+        };
+        match tls {
+            true => Ok((host, Port::Tls(port))),
+            false => Ok((host, Port::StartTls(port))),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Port {
+    /// TLS from the first byte
+    Tls(u16),
+    /// Plain text until STARTTLS, which is required
+    StartTls(u16),
+}
+
+impl Port {
+    fn number(self) -> u16 {
+        match self {
+            Port::Tls(port) | Port::StartTls(port) => port,
+        }
+    }
+}
+
+/// `user@host` or `Name <user@host>`; anything that would break out of the
+/// header it goes in is an error.
+pub fn mailbox(value: &str) -> Result<Mailbox> {
+    value
+        .trim()
+        .parse()
+        .with_context(|| format!("invalid mail address {value:?}"))
+}
+
+/// The message as sent. lettre encodes a subject that isn't plain ASCII,
+/// such as one with a "→" in it, and picks the body's transfer encoding.
+fn build(message: &Message<'_>) -> Result<lettre::Message> {
+    let mut builder = lettre::Message::builder()
+        .from(mailbox(message.from)?)
+        .subject(message.subject.replace(['\r', '\n'], " "))
+        .header(ContentType::TEXT_PLAIN);
+    for to in message.to {
+        builder = builder.to(mailbox(to)?);
+    }
+    builder.body(message.body.to_string()).context("build mail")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn server(url: &str) -> SmtpServer {
+        SmtpServer {
+            url: url.to_string(),
+            login: None,
+        }
+    }
+
+    #[test]
+    fn ports_follow_the_scheme() {
+        let cases = [
+            (
+                "smtps://mail.example.org",
+                "mail.example.org",
+                Port::Tls(465),
+            ),
+            (
+                "smtps://mail.example.org:2465/",
+                "mail.example.org",
+                Port::Tls(2465),
+            ),
+            (
+                "smtp://mail.example.org",
+                "mail.example.org",
+                Port::StartTls(587),
+            ),
+            ("smtp://[::1]:2525", "::1", Port::StartTls(2525)),
+            ("smtps://[::1]", "::1", Port::Tls(465)),
+        ];
+        for (url, host, port) in cases {
+            assert_eq!(server(url).host_port().unwrap(), (host, port), "{url}");
+        }
+        for url in ["http://mail.example.org", "smtp://", "smtp://host:port"] {
+            assert!(server(url).host_port().is_err(), "{url}");
+        }
+    }
+
+    #[test]
+    fn messages_carry_the_headers() {
+        let to = [
+            "ops@example.org".to_string(),
+            "Alice <a@example.org>".to_string(),
+        ];
+        let message = Message {
+            from: "Monitor <monitor@example.org>",
+            to: &to,
+            subject: "Swap 1234 → BtcPunished\r\nBcc: x@example.org",
+            body: "line one\nline two",
+        };
+        let raw = String::from_utf8(build(&message).unwrap().formatted()).unwrap();
+        let (headers, body) = raw.split_once("\r\n\r\n").unwrap();
+        assert!(
+            headers.contains("From: Monitor <monitor@example.org>"),
+            "{headers:?}"
+        );
+        assert!(
+            headers.contains("To: ops@example.org, Alice <a@example.org>"),
+            "{headers:?}"
+        );
+        assert!(
+            headers.contains("Subject: Swap 1234 =?utf-8?b?4oaS?= BtcPunished"),
+            "{headers}"
+        );
+        assert!(!headers.contains("\r\nBcc:"), "{headers}");
+        assert!(headers.contains("Content-Type: text/plain; charset=utf-8"));
+        assert_eq!(body, "line one\r\nline two");
+    }
+
+    #[test]
+    fn line_breaks_in_addresses_are_rejected() {
+        for address in [
+            "monitor@example.org\r\nBcc: x@example.org",
+            "Name\n <monitor@example.org>",
+            "not an address",
+        ] {
+            assert!(mailbox(address).is_err(), "{address:?}");
+        }
+        let to = ["ops@example.org\r\nBcc: x@example.org".to_string()];
+        let message = Message {
+            from: "monitor@example.org",
+            to: &to,
+            subject: "",
+            body: "",
+        };
+        assert!(build(&message).is_err());
+    }
+
+    #[tokio::test]
+    async fn servers_without_starttls_get_nothing() {
+        use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let (read, mut write) = stream.into_split();
+            write.write_all(b"220 fake ESMTP\r\n").await.unwrap();
+            let mut commands = Vec::new();
+            let mut lines = BufReader::new(read).lines();
+            while let Ok(Some(line)) = lines.next_line().await {
+                let reply: &[u8] = match line.get(..4) {
+                    Some("EHLO") => b"250-fake\r\n250 AUTH PLAIN\r\n",
+                    Some("QUIT") => b"221 bye\r\n",
+                    _ => b"250 ok\r\n",
+                };
+                commands.push(line);
+                if write.write_all(reply).await.is_err() {
+                    break;
+                }
+            }
+            commands
+        });
+
+        let smtp = SmtpServer {
+            url: format!("smtp://127.0.0.1:{port}"),
+            login: Some("monitor:secret".into()),
+        };
+        let to = ["ops@example.org".to_string()];
+        let message = Message {
+            from: "monitor@example.org",
+            to: &to,
+            subject: "test",
+            body: "test",
+        };
+        assert!(smtp.send(&message).await.is_err());
+        let commands = server.await.unwrap();
+        assert!(
+            commands
+                .iter()
+                .all(|c| c.starts_with("EHLO") || c == "QUIT"),
+            "{commands:?}"
+        );
+    }
+}
@@ -1,233 +1,486 @@
 use anyhow::{Context, Result};
-use chrono::Local;
-use colored::Colorize;
-use dirs::home_dir;
-use sqlx::sqlite::SqliteConnectOptions;
-use sqlx::{Row, SqlitePool};
-use std::collections::HashMap;
-use std::path::{Path, PathBuf};
-use std::str::FromStr;
-use std::time::Duration;
-use tokio::time::sleep;
+use std::io::{self, IsTerminal};
+use std::process::ExitCode;
 
-#[derive(Debug, Clone)]
-struct SwapRow {
-    swap_id: String,
-    state: String,
-    entered_at: String,
-}
+use asblog::AsbLog;
+use bell::Bell;
+use chain::ChainMonitor;
+use cli::{parse_args, ColorChoice, Command, OutputFormat, DEFAULT_PAGE_SIZE};
+use digest::DigestSchedule;
+use explorer::Explorer;
+use fiat::FiatRates;
+use heartbeat::Heartbeat;
+use history::HistoryStore;
+use hooks::Hook;
+use influx::InfluxSink;
+use mail::SmtpServer;
+use mempool::Mempool;
+use model::{PinnedSwaps, Watchlist};
+use monero::MoneroRpc;
+use notes::{NotesStore, NO_NOTES_DB};
+use notify::{
+    DesktopNotifier, DiscordNotifier, EmailNotifier, GotifyNotifier, IncidentNotifier,
+    IncidentService, MatrixNotifier, MqttNotifier, Notifier, Notifiers, NtfyNotifier,
+    PushoverNotifier, StateFilter, TelegramNotifier, WebhookNotifier, NTFY_DEFAULT_SERVER,
+    OPSGENIE_DEFAULT_API_URL, TELEGRAM_DEFAULT_STATES,
+};
+use otel::{Exporter, Tracer};
+use price::PriceSource;
+use quote::QuoteProbe;
+use render::TableFilter;
+use theme::ThemeName;
+use watch::{RefreshSchedule, WatchContext};
+use wraithswap_core::db::InstanceDefaults;
 
-#[derive(Debug, Clone)]
-struct SwapView {
-    swap_id: String,
-    state: String,
-    entered_at: String,
-    changed: bool,
-}
+// Swap data, database access and alert rules live in the library crate;
+// importing the modules here keeps `crate::model` and friends working
+use wraithswap_core::{anomaly, btc, db, eta, model, rules, schema, state};
 
-#[tokio::main]
-async fn main() -> Result<()> {
-    let db_path = resolve_asb_db_path();
-    let mut previous_states: HashMap<String, String> = HashMap::new();
-    let mut pool: Option<SqlitePool> = None;
+mod agent;
+mod asb;
+mod asblog;
+mod bell;
+mod bip32;
+mod board;
+mod chain;
+mod cli;
+mod clipboard;
+mod columns;
+mod commands;
+mod config;
+mod csv;
+mod daemon;
+mod digest;
+mod electrum;
+mod explorer;
+mod fiat;
+mod fswatch;
+mod heartbeat;
+mod history;
+mod hooks;
+mod http;
+mod influx;
+mod liveness;
+mod logging;
+mod mail;
+mod mempool;
+mod monero;
+mod mqtt;
+mod notes;
+mod notify;
+mod otel;
+mod parquet;
+mod pattern;
+mod price;
+mod quote;
+mod reachability;
+mod remote;
+mod render;
+mod search;
+mod serve;
+mod sort;
+mod systemd;
+mod theme;
+mod tls;
+mod toml;
+mod tui;
+mod wallet;
+mod watch;
+mod websocket;
 
-    loop {
-        clear_screen();
-        render_header(&db_path);
+#[tokio::main]
+async fn main() -> Result<ExitCode> {
+    let args = parse_args()?;
+    // NO_COLOR rules out color, not bold or reverse video: on a terminal
+    // the monochrome theme keeps those, so the selection stays visible
+    let no_color = std::env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty());
+    match args.color {
+        ColorChoice::Auto if no_color => {
+            colored::control::set_override(io::stdout().is_terminal());
+            theme::set(ThemeName::Monochrome, Vec::new());
+        }
+        ColorChoice::Auto => theme::set(args.theme, args.state_colors.clone()),
+        ColorChoice::Always => {
+            colored::control::set_override(true);
+            theme::set(args.theme, args.state_colors.clone());
+        }
+        ColorChoice::Never => colored::control::set_override(false),
+    }
+    render::set_time_format(args.time, args.clock);
+    render::set_display(args.wide, args.redact, args.ascii);
+    // A central instance fed only by agents has no database of its own
+    let agents_only = args.agents_only && args.command == Command::Serve;
+    let instances = db::resolve_instances(
+        args.instances,
+        args.db_path,
+        args.network,
+        &InstanceDefaults {
+            role: args.role,
+            immutable: args.immutable,
+            remote: args.remote,
+        },
+    );
+    let instances = if agents_only { Vec::new() } else { instances };
 
-        match db_path {
-            Some(ref path) if path.exists() => {
-                // Open pool once; reuse across iterations
-                if pool.is_none() {
-                    match open_read_only_pool(path).await {
-                        Ok(p) => pool = Some(p),
-                        Err(err) => {
-                            render_error(&format!("Failed to connect (read-only): {err}"));
-                            sleep(Duration::from_secs(2)).await;
-                            continue;
-                        }
-                    }
-                }
+    let schedule = RefreshSchedule::new(args.interval, args.adaptive);
 
-                match fetch_swaps(pool.as_ref().unwrap()).await {
-                    Ok(rows) => {
-                        if rows.is_empty() {
-                            println!("{}", "No swaps yet.".yellow());
-                        } else {
-                            let views = build_views(rows, &mut previous_states);
-                            render_table(&views);
-                        }
-                        println!();
-                        println!("{}", "Watching for changes... (Ctrl+C to exit)".dimmed());
-                    }
-                    Err(err) => {
-                        render_error(&format!("Failed to query swaps: {err}"));
-                        // Drop the pool so we reconnect next iteration
-                        pool = None;
-                    }
-                }
-            }
-            Some(ref path) => {
-                render_error(&format!("Database not found yet: {}", path.display()));
-                println!("{}", "Start ASB first: ./bin/asb --testnet start".dimmed());
-            }
-            None => {
-                render_error("Could not resolve ASB data directory for this OS.");
-            }
-        }
+    let explicit_filter = !args.notify_states.is_empty();
+    let filter = StateFilter::new(args.notify_states);
+    let mut notifiers = Vec::new();
+    if args.notify {
+        notifiers.push(Notifier::Desktop(DesktopNotifier {
+            filter: filter.clone(),
+        }));
+    }
+    for url in args.webhooks {
+        notifiers.push(Notifier::Webhook(WebhookNotifier {
+            url,
+            timeout: args.webhook_timeout,
+            filter: filter.clone(),
+        }));
+    }
+    for (name, url) in args.discord_webhooks {
+        notifiers.push(Notifier::Discord(DiscordNotifier {
+            name,
+            url,
+            filter: filter.clone(),
+        }));
+    }
+    if let (Some(bot_token), Some(chat_id)) = (args.telegram_token, args.telegram_chat_id) {
+        // Telegram defaults to alarms only; an explicit --notify-states wins
+        let filter = if explicit_filter {
+            filter.clone()
+        } else {
+            StateFilter::new(
+                TELEGRAM_DEFAULT_STATES
+                    .iter()
+                    .map(|s| s.to_string())
+                    .collect(),
+            )
+        };
+        notifiers.push(Notifier::Telegram(TelegramNotifier {
+            bot_token,
+            chat_id,
+            filter,
+        }));
+    }
 
-        sleep(Duration::from_secs(2)).await;
+    if let Some(topic) = args.ntfy_topic {
+        notifiers.push(Notifier::Ntfy(NtfyNotifier {
+            server: args
+                .ntfy_server
+                .unwrap_or_else(|| NTFY_DEFAULT_SERVER.to_string()),
+            topic,
+            filter: filter.clone(),
+        }));
     }
-}
 
-fn resolve_asb_db_path() -> Option<PathBuf> {
-    let home = home_dir()?;
-    #[cfg(target_os = "macos")]
-    {
-        Some(home.join("Library/Application Support/xmr-btc-swap/asb/testnet/sqlite"))
+    if let (Some(token), Some(user)) = (args.pushover_token, args.pushover_user) {
+        notifiers.push(Notifier::Pushover(PushoverNotifier {
+            token,
+            user,
+            filter: filter.clone(),
+        }));
+    }
+    if let (Some(server), Some(token)) = (args.gotify_server, args.gotify_token) {
+        notifiers.push(Notifier::Gotify(GotifyNotifier {
+            server,
+            token,
+            filter: filter.clone(),
+        }));
+    }
+    if let Some(routing_key) = args.pagerduty_key {
+        notifiers.push(Notifier::Incident(IncidentNotifier::new(
+            IncidentService::PagerDuty { routing_key },
+            filter.clone(),
+        )));
     }
-    #[cfg(not(target_os = "macos"))]
+    if let Some(api_key) = args.opsgenie_key {
+        let api_url = args
+            .opsgenie_api_url
+            .unwrap_or_else(|| OPSGENIE_DEFAULT_API_URL.to_string());
+        notifiers.push(Notifier::Incident(IncidentNotifier::new(
+            IncidentService::Opsgenie { api_key, api_url },
+            filter.clone(),
+        )));
+    }
+    if let (Some(url), Some(from)) = (args.smtp_server, args.email_from) {
+        let login = args
+            .smtp_user
+            .map(|user| format!("{user}:{}", args.smtp_password.unwrap_or_default()));
+        let mut email = EmailNotifier::new(
+            SmtpServer { url, login },
+            from,
+            args.email_to,
+            filter.clone(),
+        );
+        email.subject = args.email_subject.unwrap_or(email.subject);
+        email.body = args.email_body.unwrap_or(email.body);
+        email.batch = args.email_batch;
+        notifiers.push(Notifier::Email(email));
+    }
+    if let Some(mut broker) = args.mqtt_broker {
+        broker.login = args
+            .mqtt_user
+            .map(|user| (user, args.mqtt_password.unwrap_or_default()));
+        let mut mqtt = MqttNotifier::new(broker, filter.clone());
+        mqtt.topic = args.mqtt_topic.unwrap_or(mqtt.topic);
+        mqtt.qos = args.mqtt_qos;
+        mqtt.retain = args.mqtt_retain;
+        notifiers.push(Notifier::Mqtt(mqtt));
+    }
+    if let (Some(homeserver), Some(access_token), Some(room_id)) =
+        (args.matrix_homeserver, args.matrix_token, args.matrix_room)
     {
-        Some(home.join(".local/share/xmr-btc-swap/asb/testnet/sqlite"))
+        notifiers.push(Notifier::Matrix(MatrixNotifier {
+            homeserver,
+            access_token,
+            room_id,
+            filter,
+        }));
     }
-}
 
-async fn open_read_only_pool(db_path: &Path) -> Result<SqlitePool> {
-    let opts = SqliteConnectOptions::from_str(&format!("sqlite:{}", db_path.display()))?
-        .read_only(true)
-        .create_if_missing(false);
+    let monero_rpc = args.monero_rpc.map(|url| MoneroRpc {
+        url,
+        login: args.monero_rpc_login,
+        timeout: http::DEFAULT_TIMEOUT,
+    });
 
-    SqlitePool::connect_with(opts)
-        .await
-        .with_context(|| format!("open database at {}", db_path.display()))
-}
-
-async fn fetch_swaps(pool: &SqlitePool) -> Result<Vec<SwapRow>> {
-    // Get the latest state per swap_id from the swap_states table
-    let rows = sqlx::query(
-        "SELECT swap_id, state, entered_at \
-         FROM swap_states \
-         WHERE id IN (SELECT MAX(id) FROM swap_states GROUP BY swap_id) \
-         ORDER BY entered_at DESC",
-    )
-    .fetch_all(pool)
-    .await?;
-
-    Ok(rows
-        .iter()
-        .map(|r| SwapRow {
-            swap_id: r.get("swap_id"),
-            state: r.get("state"),
-            entered_at: r.get("entered_at"),
-        })
-        .collect())
-}
-
-fn build_views(rows: Vec<SwapRow>, prev: &mut HashMap<String, String>) -> Vec<SwapView> {
-    rows.into_iter()
-        .map(|row| {
-            let prev_state = prev.get(&row.swap_id).cloned();
-            let changed = prev_state.is_some() && prev_state.as_deref() != Some(row.state.as_str());
-            prev.insert(row.swap_id.clone(), row.state.clone());
-
-            SwapView {
-                swap_id: row.swap_id,
-                state: row.state,
-                entered_at: row.entered_at,
-                changed,
-            }
-        })
-        .collect()
-}
-
-fn render_header(db_path: &Option<PathBuf>) {
-    let title = "WraithSwap ASB Monitor";
-    println!("╔══════════════════════════════════════════════════════════════╗");
-    println!("║{:^62}║", title);
-    println!("╠══════════════════════════════════════════════════════════════╣");
-
-    let status = if db_path.as_ref().map(|p| p.exists()).unwrap_or(false) {
-        "Connected".green()
-    } else {
-        "Disconnected".red()
+    let history = match args.history_db {
+        Some(ref path) => Some(HistoryStore::open(path).await?),
+        None => None,
     };
 
-    let db_display = db_path
-        .as_ref()
-        .map(|p| {
-            let s = p.display().to_string();
-            if let Some(home) = home_dir() {
-                let home_str = home.display().to_string();
-                if s.starts_with(&home_str) {
-                    return format!("~{}", &s[home_str.len()..]);
-                }
-            }
-            s
+    let tracer = args
+        .otlp_endpoint
+        .map(|endpoint| {
+            Tracer::new(Exporter {
+                endpoint,
+                headers: args.otlp_headers,
+                service_name: std::env::var(otel::SERVICE_NAME_ENV)
+                    .ok()
+                    .filter(|v| !v.is_empty())
+                    .unwrap_or_else(|| env!("CARGO_PKG_NAME").to_string()),
+            })
         })
-        .unwrap_or_else(|| "unknown".to_string());
-
-    let last_updated = Local::now().format("%Y-%m-%d %H:%M:%S").to_string();
-
-    println!("║ Status: {:<52}║", status);
-    println!("║ Database: {:<49}║", db_display);
-    println!("║ Last updated: {:<47}║", last_updated);
-    println!("╚══════════════════════════════════════════════════════════════╝");
-    println!();
-}
-
-fn render_table(views: &[SwapView]) {
-    println!("┌──────────┬─────────────────────────┬─────────────────────────┐");
-    println!("│ Swap ID  │ State                   │ Entered At              │");
-    println!("├──────────┼─────────────────────────┼─────────────────────────┤");
-
-    for view in views {
-        let swap_id = truncate_id(&view.swap_id);
-        let state = format_state(&view.state, view.changed);
-        let entered = if view.entered_at.len() > 23 {
-            &view.entered_at[..23]
-        } else {
-            &view.entered_at
-        };
-
-        println!("│ {:<8} │ {:<23} │ {:<23} │", swap_id, state, entered);
+        .transpose()?;
+    // The daemon's logger goes next to the tracer's layer; everything else
+    // only needs the tracer
+    let daemon =
+        args.daemon && matches!(args.command, Command::Watch) || args.command == Command::Agent;
+    if let (Some(ref tracer), false) = (&tracer, daemon) {
+        otel::install(tracer)?;
     }
 
-    println!("└──────────┴─────────────────────────┴─────────────────────────┘");
-}
+    let fiat = args.fiat.then(|| {
+        FiatRates::new(
+            args.price_source.clone().unwrap_or(PriceSource::Kraken),
+            args.earnings.currency.clone(),
+            args.earnings.btc_price,
+        )
+    });
 
-fn format_state(state: &str, changed: bool) -> String {
-    let base = match state {
-        "Started" => state.cyan(),
-        "BtcLockProofReceived" => state.blue(),
-        "XmrLockProofSent" => state.blue(),
-        "EncSigSent" => state.yellow(),
-        "BtcRedeemed" => format!("{state} ✓").green(),
-        "XmrRefunded" => state.magenta(),
-        "BtcCancelled" => state.magenta(),
-        "BtcPunished" => state.red(),
-        "SafelyAborted" => state.dimmed(),
-        _ => state.normal(),
+    let ctx = WatchContext {
+        instances,
+        schedule,
+        notifiers: Notifiers::new(notifiers)
+            .with_rules(args.rules)?
+            .with_cooldown(args.alert_cooldown)
+            .with_quiet_hours(args.quiet_hours)
+            .with_hook(
+                args.on_transition
+                    .map(|command| Hook::new(command, args.hook_timeout, args.hook_concurrency)),
+            ),
+        fs_watch: args.fs_watch,
+        stuck: args.stuck,
+        watchlist: Watchlist::new(args.watch_peers),
+        pins: PinnedSwaps::new(args.pins),
+        explorer: Explorer::new(args.explorer),
+        rendezvous_points: args.rendezvous_points.clone(),
+        chain: ChainMonitor::new(args.electrum, monero_rpc)
+            .with_balances(args.balance, args.btc_wallet)
+            .with_liquidity(args.liquidity)
+            .with_fees(
+                (args.fees || args.mempool_url.is_some() || args.max_fee_rate.is_some())
+                    .then(|| Mempool::new(args.mempool_url, args.network)),
+                args.max_fee_rate,
+            )
+            .with_price(args.price_source.clone())
+            .with_fiat(fiat.clone())
+            .with_asb(args.asb)
+            .with_reachability(args.external_addresses, args.tor_socks)
+            .with_quotes(
+                args.quote_peer.map(|peer_id| QuoteProbe {
+                    peer_id,
+                    swap_bin: args.swap_bin,
+                    rendezvous_points: args.rendezvous_points,
+                    network: args.network,
+                }),
+                args.earnings.spread,
+                args.quote_tolerance,
+            ),
+        history,
+        notes: args
+            .notes_db
+            .or_else(NotesStore::default_path)
+            .map(NotesStore::new),
+        filter: TableFilter {
+            states: args.states,
+            hide_finished: args.hide_finished,
+            range: args.range,
+            search: args.swap_search,
+            tags: args.tags,
+            sort: args.sort,
+        },
+        page_size: Some(args.page_size.unwrap_or(DEFAULT_PAGE_SIZE)).filter(|&size| size > 0),
+        board: args.board,
+        table_columns: args.table_columns,
+        chart: args.chart,
+        chart_window: args.chart_window,
+        ticker: args.ticker,
+        fade: args.fade,
+        asb_log: args
+            .asb_log
+            .map(|path| AsbLog::new(path).with_alerts(args.log_alerts, args.log_alert_window)),
+        digest: args
+            .digest
+            .map(|period| DigestSchedule::new(period, args.digest_at, args.earnings.spread)),
+        metrics: args
+            .influx_url
+            .map(|url| InfluxSink::new(url, args.influx_token)),
+        heartbeat: args.heartbeat_url.map(Heartbeat::new),
+        bell: Bell::new(args.bell),
     };
 
-    if changed {
-        base.bold().to_string()
-    } else {
-        base.to_string()
-    }
-}
-
-fn truncate_id(id: &str) -> String {
-    if id.len() <= 8 {
-        id.to_string()
-    } else {
-        format!("{}..", &id[0..6])
+    // Only list has anything to say beyond success or failure
+    let mut status = ExitCode::SUCCESS;
+    let result = match args.command {
+        Command::Watch if args.daemon => {
+            logging::init(args.log_format, args.log_file.as_deref(), tracer.as_ref())?;
+            daemon::run_daemon(ctx).await
+        }
+        Command::Watch if args.output == OutputFormat::Table && io::stdout().is_terminal() => {
+            watch::run_tui(ctx).await
+        }
+        Command::Watch => watch::run_watch(ctx, args.output).await,
+        Command::List => commands::run_list(
+            &ctx.instances,
+            args.output,
+            &ctx.stuck,
+            &ctx.watchlist,
+            &ctx.chain,
+            &ctx.rendezvous_points,
+            commands::ListOptions {
+                filter: ctx.filter.clone(),
+                page_size: args.page_size.filter(|&size| size > 0),
+                pins: ctx.pins.clone(),
+                notes: ctx.notes.clone(),
+                table_columns: ctx.table_columns.clone(),
+            },
+        )
+        .await
+        .map(|health| status = health.exit_code()),
+        Command::Show { swap_id } => {
+            commands::run_show(
+                &ctx.instances,
+                args.output,
+                &swap_id,
+                &ctx.chain,
+                &ctx.explorer,
+                ctx.asb_log,
+                ctx.notes.as_ref(),
+            )
+            .await
+        }
+        Command::Open { swap_id, tx } => {
+            commands::run_open(&ctx.instances, &swap_id, tx.as_deref(), &ctx.explorer).await
+        }
+        Command::Stats => {
+            let chart = args.chart.then_some(args.chart_window);
+            commands::run_stats(&ctx.instances, args.output, &args.range, chart).await
+        }
+        Command::Peers => commands::run_peers(&ctx.instances, args.output).await,
+        Command::Export => {
+            let options = commands::ExportOptions {
+                format: args.export_format,
+                file: args.file,
+                latest: args.latest,
+                columns: args.columns,
+                price_source: args.price_source.unwrap_or(PriceSource::Kraken),
+                range: args.range,
+            };
+            commands::run_export(&ctx.instances, options).await
+        }
+        Command::Earnings => {
+            commands::run_earnings(&ctx.instances, args.output, &args.earnings, fiat.as_ref()).await
+        }
+        Command::Rates => {
+            commands::run_rates(
+                &ctx.instances,
+                args.output,
+                &args.price_source.unwrap_or(PriceSource::Kraken),
+                args.limit,
+                args.earnings.spread,
+            )
+            .await
+        }
+        Command::Serve => {
+            let tls = match (args.tls_cert, args.tls_key) {
+                (Some(cert), Some(key)) => Some(serve::Tls::Files { cert, key }),
+                _ => args.tls_self_signed.then_some(serve::Tls::SelfSigned),
+            };
+            serve::run_serve(ctx, args.listen, tls, args.api_token, args.agent_token).await
+        }
+        Command::Agent => {
+            logging::init(args.log_format, args.log_file.as_deref(), tracer.as_ref())?;
+            let options = agent::AgentOptions {
+                // parse_args rejects `agent` without these
+                central: args.central.context("no central instance")?,
+                name: args.agent_name.context("no agent name")?,
+                token: args.agent_token.context("no agent token")?,
+            };
+            agent::run_agent(ctx, options).await
+        }
+        Command::Tail => {
+            commands::run_tail(
+                &ctx.instances,
+                ctx.schedule,
+                ctx.fs_watch,
+                &ctx.stuck,
+                &ctx.watchlist,
+                &ctx.pins,
+                ctx.history.as_ref(),
+            )
+            .await
+        }
+        Command::History { swap_id } => {
+            // parse_args rejects `history` without a database
+            let history = ctx.history.as_ref().context("no history database")?;
+            commands::run_history(&ctx.instances, args.output, history, swap_id.as_deref()).await
+        }
+        Command::Note { swap_id, text } => {
+            let notes = ctx.notes.as_ref().context(NO_NOTES_DB)?;
+            commands::run_note(&ctx.instances, notes, &swap_id, &text.join(" ")).await
+        }
+        Command::Tag { swap_id, tags } => {
+            let notes = ctx.notes.as_ref().context(NO_NOTES_DB)?;
+            commands::run_tag(&ctx.instances, notes, &swap_id, &tags, false).await
+        }
+        Command::Untag { swap_id, tags } => {
+            let notes = ctx.notes.as_ref().context(NO_NOTES_DB)?;
+            commands::run_tag(&ctx.instances, notes, &swap_id, &tags, true).await
+        }
+        Command::Notes => {
+            let notes = ctx.notes.as_ref().context(NO_NOTES_DB)?;
+            commands::run_notes(notes, args.output, &ctx.filter.tags).await
+        }
+        Command::CheckConfig { path } => commands::run_check_config(&path, args.output).await,
+        Command::NotifyTest { channel } => {
+            commands::run_notify_test(&ctx.notifiers, args.output, channel.as_deref()).await
+        }
+    };
+    if let Some(tracer) = tracer {
+        tracer.flush().await;
     }
-}
-
-fn clear_screen() {
-    print!("\x1B[2J\x1B[1;1H");
-}
-
-fn render_error(message: &str) {
-    println!("{}", format!("Error: {message}").red());
+    result.map(|()| status)
 }
@@ -0,0 +1,91 @@
+//! The fee environment from a mempool.space instance (`--mempool-url`):
+//! recommended fee rates and how much is waiting in the mempool.
+//!
+//! Redeems, cancels and refunds all pay fees, so when the next block costs
+//! several times the usual rate an operator may rather let a redeem wait.
+//! Only mempool's API serves recommended fees; a plain esplora doesn't.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::explorer::DEFAULT_EXPLORER;
+use crate::http::Request;
+use crate::model::Network;
+
+#[derive(Debug, Clone)]
+pub struct Mempool {
+    /// API root, e.g. `https://mempool.space/api`
+    api: String,
+    /// The one network the instance serves
+    network: Network,
+}
+
+/// Fee rates in sat/vB, as mempool recommends them, and the mempool's
+/// depth at the time.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FeeEnvironment {
+    /// Next block
+    pub fastest: f64,
+    /// Within half an hour
+    pub half_hour: f64,
+    /// Within an hour
+    pub hour: f64,
+    /// Total size of the unconfirmed transactions, in vbytes
+    pub mempool_vsize: u64,
+}
+
+/// The next-block fee rate rose above the `--max-fee-rate` threshold.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct FeeSpike {
+    pub fastest_sat_vb: f64,
+    pub threshold_sat_vb: f64,
+    pub mempool_vsize: u64,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RecommendedFees {
+    fastest_fee: f64,
+    half_hour_fee: f64,
+    hour_fee: f64,
+}
+
+#[derive(Deserialize)]
+struct MempoolInfo {
+    vsize: u64,
+}
+
+impl Mempool {
+    /// `base` is the instance's web address, as for `--explorer`; without
+    /// one, mempool.space, which serves testnet under `/testnet`.
+    pub fn new(base: Option<String>, network: Network) -> Self {
+        let api = match (base, network) {
+            (Some(base), _) => format!("{}/api", base.trim_end_matches('/')),
+            (None, Network::Mainnet) => format!("{DEFAULT_EXPLORER}/api"),
+            (None, Network::Testnet) => format!("{DEFAULT_EXPLORER}/testnet/api"),
+        };
+        Self { api, network }
+    }
+
+    pub fn network(&self) -> Network {
+        self.network
+    }
+
+    /// Current recommended fees and mempool depth.
+    pub async fn fees(&self) -> Result<FeeEnvironment> {
+        let fees: RecommendedFees = self.get("v1/fees/recommended").await?;
+        let mempool: MempoolInfo = self.get("mempool").await?;
+        Ok(FeeEnvironment {
+            fastest: fees.fastest_fee,
+            half_hour: fees.half_hour_fee,
+            hour: fees.hour_fee,
+            mempool_vsize: mempool.vsize,
+        })
+    }
+
+    async fn get<T: for<'de> Deserialize<'de>>(&self, path: &str) -> Result<T> {
+        let url = format!("{}/{path}", self.api);
+        let response = Request::new("GET", &url).send().await?.error_for_status()?;
+        serde_json::from_str(&response.body).with_context(|| format!("invalid response from {url}"))
+    }
+}
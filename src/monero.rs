@@ -0,0 +1,116 @@
+//! monero-wallet-rpc client for XMR lock confirmations.
+//!
+//! Point it at the wallet ASB (or the swap CLI) runs, which knows the lock
+//! transfer it sent. Calls go through the `http` module, which answers the
+//! digest auth challenge of `--rpc-login`.
+
+use anyhow::{bail, Context, Result};
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::time::Duration;
+
+use crate::http::Request;
+use crate::model::XmrTransfer;
+
+/// Outputs can't be spent until they are this deep
+const STANDARD_UNLOCK: u64 = 10;
+
+/// `unlock_time` values at or above this are timestamps, not heights
+const UNLOCK_TIME_IS_TIMESTAMP: u64 = 500_000_000;
+
+#[derive(Debug, Clone)]
+pub struct MoneroRpc {
+    /// Base URL, e.g. `http://127.0.0.1:18083`
+    pub url: String,
+    /// `user:password` from monero-wallet-rpc's `--rpc-login`
+    pub login: Option<String>,
+    pub timeout: Duration,
+}
+
+/// Balance of the wallet's primary account, in piconero.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct XmrBalance {
+    pub balance: u64,
+    /// Spendable now; the rest waits for 10 confirmations
+    pub unlocked: u64,
+}
+
+impl MoneroRpc {
+    async fn call(&self, method: &str, params: Value) -> Result<Value> {
+        let url = format!("{}/json_rpc", self.url.trim_end_matches('/'));
+        let mut request = Request::post(url)
+            .json(&json!({ "jsonrpc": "2.0", "id": "0", "method": method, "params": params }))?
+            .timeout(self.timeout);
+        if let Some(ref login) = self.login {
+            request = request.digest_auth(login);
+        }
+
+        let response = request.send().await?.error_for_status()?;
+        let body: Value =
+            serde_json::from_str(&response.body).context("invalid monero-wallet-rpc response")?;
+        if let Some(error) = body.get("error") {
+            let message = error.get("message").and_then(Value::as_str).unwrap_or("");
+            bail!("{method}: {message}");
+        }
+        body.get("result")
+            .cloned()
+            .with_context(|| format!("{method}: response without a result"))
+    }
+
+    /// The RPC version as `major.minor`; answers without an open wallet.
+    pub async fn version(&self) -> Result<String> {
+        let result = self.call("get_version", json!({})).await?;
+        let version = result
+            .get("version")
+            .and_then(Value::as_u64)
+            .context("get_version: response without a version")?;
+        Ok(format!("{}.{}", version >> 16, version & 0xffff))
+    }
+
+    /// `None` when the wallet has no transfer with this txid.
+    pub async fn transfer(&self, txid: &str) -> Result<Option<XmrTransfer>> {
+        let result = match self
+            .call("get_transfer_by_txid", json!({ "txid": txid }))
+            .await
+        {
+            Ok(result) => result,
+            Err(err) if err.to_string().contains("not found") => return Ok(None),
+            Err(err) => return Err(err),
+        };
+        let Some(transfer) = result.get("transfer") else {
+            return Ok(None);
+        };
+
+        let confirmations = transfer
+            .get("confirmations")
+            .and_then(Value::as_u64)
+            .unwrap_or(0);
+        let mut unlocks_in = STANDARD_UNLOCK.saturating_sub(confirmations);
+
+        let unlock_time = transfer
+            .get("unlock_time")
+            .and_then(Value::as_u64)
+            .unwrap_or(0);
+        if unlock_time > 0 && unlock_time < UNLOCK_TIME_IS_TIMESTAMP {
+            let height = self.call("get_height", json!({})).await?;
+            let height = height.get("height").and_then(Value::as_u64).unwrap_or(0);
+            unlocks_in = unlocks_in.max(unlock_time.saturating_sub(height));
+        }
+
+        Ok(Some(XmrTransfer {
+            confirmations,
+            unlocks_in,
+        }))
+    }
+
+    pub async fn balance(&self) -> Result<XmrBalance> {
+        let result = self
+            .call("get_balance", json!({ "account_index": 0 }))
+            .await?;
+        let field = |name: &str| result.get(name).and_then(Value::as_u64).unwrap_or(0);
+        Ok(XmrBalance {
+            balance: field("balance"),
+            unlocked: field("unlocked_balance"),
+        })
+    }
+}
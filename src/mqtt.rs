@@ -0,0 +1,534 @@
+//! Minimal MQTT 3.1.1 publisher.
+//!
+//! One connection is opened on the first publish and kept for the ones
+//! after it. A connection that has been idle for half the keep-alive is
+//! pinged before use, since the broker drops it after one and a half; one
+//! that turns out to be gone is replaced and the message sent again. TLS is
+//! rustls, verified against the public roots (see [`crate::tls`]), as a
+//! login may cross the connection.
+
+use anyhow::{anyhow, bail, Context, Result};
+use std::fmt;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::sync::Mutex;
+use tokio::time::timeout;
+
+use crate::tls;
+
+/// Per message, including the connection and handshake
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+const KEEP_ALIVE_SECS: u16 = 60;
+
+/// Keeps client ids unique, since brokers drop the older of two
+/// connections with the same id
+static CLIENT_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// `mqtts://host[:port]` or `mqtt://host[:port]`; a bare `host[:port]`
+/// means TLS.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MqttBroker {
+    pub host: String,
+    pub port: u16,
+    pub tls: bool,
+    /// `(user, password)`, if the broker wants a login
+    pub login: Option<(String, String)>,
+}
+
+impl FromStr for MqttBroker {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (tls, rest) = match s.split_once("://") {
+            Some(("mqtts" | "ssl", rest)) => (true, rest),
+            Some(("mqtt" | "tcp", rest)) => (false, rest),
+            Some((scheme, _)) => bail!("unknown MQTT scheme {scheme}:// (expected mqtts or mqtt)"),
+            None => (true, s),
+        };
+        let rest = rest.trim_end_matches('/');
+        let (host, port) = match rest.rsplit_once(':') {
+            Some((host, port)) => (
+                host,
+                port.parse()
+                    .with_context(|| format!("invalid MQTT port in {s}"))?,
+            ),
+            None => (rest, if tls { 8883 } else { 1883 }),
+        };
+        if host.is_empty() {
+            bail!("MQTT broker needs a host: {s}");
+        }
+        Ok(MqttBroker {
+            host: host.to_string(),
+            port,
+            tls,
+            login: None,
+        })
+    }
+}
+
+impl fmt::Display for MqttBroker {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let scheme = if self.tls { "mqtts" } else { "mqtt" };
+        write!(f, "{scheme}://{}:{}", self.host, self.port)
+    }
+}
+
+/// Delivery guarantee of a message: at most once, at least once or
+/// exactly once.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum QoS {
+    #[default]
+    AtMost = 0,
+    AtLeast = 1,
+    Exactly = 2,
+}
+
+impl FromStr for QoS {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "0" => Ok(QoS::AtMost),
+            "1" => Ok(QoS::AtLeast),
+            "2" => Ok(QoS::Exactly),
+            other => bail!("MQTT QoS must be 0, 1 or 2, got {other:?}"),
+        }
+    }
+}
+
+/// Packet types, as the high nibble of the fixed header
+const CONNECT: u8 = 1;
+const CONNACK: u8 = 2;
+const PUBLISH: u8 = 3;
+const PUBACK: u8 = 4;
+const PUBREC: u8 = 5;
+const PUBREL: u8 = 6;
+const PUBCOMP: u8 = 7;
+const PINGREQ: u8 = 12;
+const PINGRESP: u8 = 13;
+
+trait Stream: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> Stream for T {}
+
+/// Publishes to one broker over a connection kept between messages.
+/// Clones share the connection.
+#[derive(Clone)]
+pub struct MqttClient {
+    broker: MqttBroker,
+    session: Arc<Mutex<Option<Session>>>,
+}
+
+struct Session {
+    stream: Box<dyn Stream>,
+    last_sent: Instant,
+    last_packet_id: u16,
+}
+
+impl MqttClient {
+    pub fn new(broker: MqttBroker) -> Self {
+        Self {
+            broker,
+            session: Arc::default(),
+        }
+    }
+
+    pub async fn publish(&self, topic: &str, payload: &[u8], qos: QoS, retain: bool) -> Result<()> {
+        let mut session = self.session.lock().await;
+        let publish = async {
+            if let Some(ref mut reused) = *session {
+                if reused.publish(topic, payload, qos, retain).await.is_ok() {
+                    return Ok(());
+                }
+            }
+            // Never connected, or the broker has dropped the connection
+            let fresh = session.insert(self.broker.connect().await?);
+            fresh.publish(topic, payload, qos, retain).await
+        };
+        let result = timeout(DEFAULT_TIMEOUT, publish)
+            .await
+            .map_err(|_| anyhow!("timed out"))
+            .and_then(|result| result)
+            .with_context(|| format!("publish to {}", self.broker));
+        if result.is_err() {
+            *session = None;
+        }
+        result
+    }
+}
+
+impl fmt::Display for MqttClient {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.broker.fmt(f)
+    }
+}
+
+impl fmt::Debug for MqttClient {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "MqttClient({})", self.broker)
+    }
+}
+
+impl Session {
+    async fn publish(&mut self, topic: &str, payload: &[u8], qos: QoS, retain: bool) -> Result<()> {
+        if self.last_sent.elapsed() >= Duration::from_secs(u64::from(KEEP_ALIVE_SECS / 2)) {
+            self.send(&packet(PINGREQ << 4, &[])).await?;
+            let (kind, _) = read_packet(&mut self.stream).await?;
+            if kind != PINGRESP {
+                bail!("expected PINGRESP from the broker");
+            }
+        }
+
+        let mut header = PUBLISH << 4 | (qos as u8) << 1;
+        if retain {
+            header |= 1;
+        }
+        let mut body = encode_string(topic);
+        let id = match self.last_packet_id {
+            u16::MAX => 1,
+            id => id + 1,
+        };
+        if qos != QoS::AtMost {
+            self.last_packet_id = id;
+            body.extend_from_slice(&id.to_be_bytes());
+        }
+        body.extend_from_slice(payload);
+        self.send(&packet(header, &body)).await?;
+
+        match qos {
+            QoS::AtMost => {}
+            QoS::AtLeast => expect_ack(&mut self.stream, PUBACK, id).await?,
+            QoS::Exactly => {
+                expect_ack(&mut self.stream, PUBREC, id).await?;
+                // PUBREL has a reserved flag bit set
+                self.send(&packet(PUBREL << 4 | 0b10, &id.to_be_bytes()))
+                    .await?;
+                expect_ack(&mut self.stream, PUBCOMP, id).await?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn send(&mut self, packet: &[u8]) -> Result<()> {
+        self.stream.write_all(packet).await?;
+        self.stream.flush().await?;
+        self.last_sent = Instant::now();
+        Ok(())
+    }
+}
+
+impl MqttBroker {
+    async fn connect(&self) -> Result<Session> {
+        let stream: Box<dyn Stream> = match self.tls {
+            true => Box::new(tls::connect(&self.host, self.port, None).await?),
+            false => Box::new(
+                TcpStream::connect((self.host.as_str(), self.port))
+                    .await
+                    .context("connect")?,
+            ),
+        };
+        let mut session = Session {
+            stream,
+            last_sent: Instant::now(),
+            last_packet_id: 0,
+        };
+        session.send(&self.connect_packet()).await?;
+        let (kind, body) = read_packet(&mut session.stream).await?;
+        if kind != CONNACK || body.len() < 2 {
+            bail!("expected CONNACK from the broker");
+        }
+        match body[1] {
+            0 => Ok(session),
+            1 => bail!("broker doesn't support MQTT 3.1.1"),
+            2 => bail!("broker rejected the client id"),
+            3 => bail!("broker unavailable"),
+            4 => bail!("bad user name or password"),
+            5 => bail!("not authorized"),
+            code => bail!("broker refused the connection (code {code})"),
+        }
+    }
+
+    fn connect_packet(&self) -> Vec<u8> {
+        let client_id = format!(
+            "wraithswap-{}-{}",
+            std::process::id(),
+            CLIENT_COUNTER.fetch_add(1, Ordering::Relaxed)
+        );
+        // Clean session: nothing is kept between connections
+        let mut flags = 0b10;
+        if self.login.is_some() {
+            flags |= 0b1100_0000;
+        }
+        let mut body = encode_string("MQTT");
+        body.push(4);
+        body.push(flags);
+        body.extend_from_slice(&KEEP_ALIVE_SECS.to_be_bytes());
+        body.extend(encode_string(&client_id));
+        if let Some((ref user, ref password)) = self.login {
+            body.extend(encode_string(user));
+            body.extend(encode_string(password));
+        }
+        packet(CONNECT << 4, &body)
+    }
+}
+
+async fn expect_ack(reader: &mut (impl AsyncRead + Unpin), expected: u8, id: u16) -> Result<()> {
+    let (kind, body) = read_packet(reader).await?;
+    if kind != expected || body.get(..2) != Some(&id.to_be_bytes()[..]) {
+        bail!("unexpected packet type {kind} from the broker");
+    }
+    Ok(())
+}
+
+/// One packet's type and body.
+async fn read_packet(reader: &mut (impl AsyncRead + Unpin)) -> Result<(u8, Vec<u8>)> {
+    let header = reader
+        .read_u8()
+        .await
+        .context("broker closed the connection")?;
+    let mut len = 0usize;
+    for shift in 0..4 {
+        let byte = reader.read_u8().await?;
+        len |= usize::from(byte & 0x7f) << (7 * shift);
+        if byte & 0x80 == 0 {
+            let mut body = vec![0; len];
+            reader.read_exact(&mut body).await?;
+            return Ok((header >> 4, body));
+        }
+    }
+    bail!("malformed packet length from the broker")
+}
+
+/// Fixed header, variable-length remaining length and body.
+fn packet(header: u8, body: &[u8]) -> Vec<u8> {
+    let mut packet = vec![header];
+    let mut len = body.len();
+    loop {
+        let mut byte = (len % 128) as u8;
+        len /= 128;
+        if len > 0 {
+            byte |= 0x80;
+        }
+        packet.push(byte);
+        if len == 0 {
+            break;
+        }
+    }
+    packet.extend_from_slice(body);
+    packet
+}
+
+/// Length-prefixed UTF-8, as used for topics, client ids and logins.
+fn encode_string(value: &str) -> Vec<u8> {
+    let mut encoded = (value.len() as u16).to_be_bytes().to_vec();
+    encoded.extend_from_slice(value.as_bytes());
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::TcpListener;
+
+    #[test]
+    fn brokers_parse() {
+        let broker: MqttBroker = "broker.local".parse().unwrap();
+        assert_eq!(broker.to_string(), "mqtts://broker.local:8883");
+        let broker: MqttBroker = "tcp://10.0.0.2:1884/".parse().unwrap();
+        assert_eq!((broker.tls, broker.port), (false, 1884));
+        assert_eq!(
+            "mqtt://broker.local".parse::<MqttBroker>().unwrap().port,
+            1883
+        );
+        assert!("ws://broker.local".parse::<MqttBroker>().is_err());
+        assert!("mqtt://:1883".parse::<MqttBroker>().is_err());
+        assert!("broker.local:mqtt".parse::<MqttBroker>().is_err());
+        assert!("3".parse::<QoS>().is_err());
+    }
+
+    #[test]
+    fn remaining_lengths_use_seven_bits_a_byte() {
+        for (len, encoded) in [
+            (0, &[0x00][..]),
+            (127, &[0x7f]),
+            (128, &[0x80, 0x01]),
+            (16_383, &[0xff, 0x7f]),
+            (16_384, &[0x80, 0x80, 0x01]),
+        ] {
+            let packet = packet(PUBLISH << 4, &vec![0; len]);
+            assert_eq!(&packet[1..1 + encoded.len()], encoded, "{len}");
+            assert_eq!(packet.len(), 1 + encoded.len() + len);
+        }
+        assert_eq!(encode_string("MQTT"), b"\x00\x04MQTT");
+    }
+
+    #[tokio::test]
+    async fn packets_read_back() {
+        let body = vec![9; 300];
+        let bytes = packet(PUBACK << 4, &body);
+        assert_eq!(read_packet(&mut &bytes[..]).await.unwrap(), (PUBACK, body));
+
+        let endless = [PUBACK << 4, 0xff, 0xff, 0xff, 0xff];
+        let err = read_packet(&mut &endless[..]).await.unwrap_err();
+        assert!(err.to_string().contains("malformed"), "{err}");
+        assert!(read_packet(&mut &[][..]).await.is_err());
+    }
+
+    #[test]
+    fn logins_set_the_connect_flags() {
+        let mut broker: MqttBroker = "mqtt://broker.local".parse().unwrap();
+        let anonymous = broker.connect_packet();
+        // Header, length, "MQTT", level 4, then the flags
+        assert_eq!(&anonymous[2..9], b"\x00\x04MQTT\x04");
+        assert_eq!(anonymous[9], 0b10);
+
+        broker.login = Some(("user".into(), "secret".into()));
+        let login = broker.connect_packet();
+        assert_eq!(login[9], 0b1100_0010);
+        assert!(login.ends_with(b"\x00\x04user\x00\x06secret"));
+        assert_eq!(usize::from(login[1]), login.len() - 2);
+    }
+
+    /// A broker that takes `connections` connections one after another and
+    /// acknowledges what is published on each at `qos`, returning the
+    /// packets it saw per connection. A connection ends when the client goes
+    /// quiet for a moment, or straight after its first publish with
+    /// `hang_up`.
+    async fn broker(
+        listener: TcpListener,
+        connections: usize,
+        qos: QoS,
+        hang_up: bool,
+    ) -> Vec<Vec<(u8, Vec<u8>)>> {
+        let mut all = Vec::new();
+        for _ in 0..connections {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut seen = vec![read_packet(&mut stream).await.unwrap()];
+            stream
+                .write_all(&packet(CONNACK << 4, &[0, 0]))
+                .await
+                .unwrap();
+            let quiet = Duration::from_millis(300);
+            while let Ok(Ok((kind, body))) = timeout(quiet, read_packet(&mut stream)).await {
+                let ack = match kind {
+                    PUBLISH if qos != QoS::AtMost => {
+                        let topic = usize::from(u16::from_be_bytes([body[0], body[1]]));
+                        let id = &body[2 + topic..4 + topic];
+                        let kind = if qos == QoS::AtLeast { PUBACK } else { PUBREC };
+                        Some(packet(kind << 4, id))
+                    }
+                    PUBREL => Some(packet(PUBCOMP << 4, &body[..2])),
+                    PINGREQ => Some(packet(PINGRESP << 4, &[])),
+                    _ => None,
+                };
+                if let Some(ack) = ack {
+                    stream.write_all(&ack).await.unwrap();
+                }
+                seen.push((kind, body));
+                if kind == PUBLISH && hang_up {
+                    break;
+                }
+            }
+            all.push(seen);
+        }
+        all
+    }
+
+    async fn listen() -> (TcpListener, MqttClient) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let broker: MqttBroker = format!("mqtt://127.0.0.1:{port}").parse().unwrap();
+        (listener, MqttClient::new(broker))
+    }
+
+    #[tokio::test]
+    async fn messages_share_one_connection() {
+        let (listener, client) = listen().await;
+        let server = tokio::spawn(broker(listener, 1, QoS::Exactly, false));
+        for topic in ["swaps/a", "swaps/b"] {
+            client
+                .publish(topic, b"{}", QoS::Exactly, true)
+                .await
+                .unwrap();
+        }
+
+        let connections = server.await.unwrap();
+        assert_eq!(connections.len(), 1);
+        let seen = &connections[0];
+        let kinds: Vec<u8> = seen.iter().map(|(kind, _)| *kind).collect();
+        assert_eq!(kinds, [CONNECT, PUBLISH, PUBREL, PUBLISH, PUBREL]);
+        assert_eq!(seen[1].1, b"\x00\x07swaps/a\x00\x01{}");
+        assert_eq!(seen[3].1, b"\x00\x07swaps/b\x00\x02{}");
+    }
+
+    #[tokio::test]
+    async fn dropped_connections_are_replaced() {
+        let (listener, client) = listen().await;
+        let server = tokio::spawn(broker(listener, 2, QoS::AtLeast, true));
+        client
+            .publish("swaps/a", b"1", QoS::AtLeast, false)
+            .await
+            .unwrap();
+        client
+            .publish("swaps/b", b"2", QoS::AtLeast, false)
+            .await
+            .unwrap();
+
+        let connections = server.await.unwrap();
+        let published: Vec<&[u8]> = connections
+            .iter()
+            .flat_map(|seen| seen.iter().filter(|(kind, _)| *kind == PUBLISH))
+            .map(|(_, body)| &body[body.len() - 1..])
+            .collect();
+        assert_eq!(published, [b"1", b"2"]);
+    }
+
+    #[tokio::test]
+    async fn idle_connections_are_pinged_first() {
+        let (listener, client) = listen().await;
+        let server = tokio::spawn(broker(listener, 1, QoS::AtMost, false));
+        client
+            .publish("swaps/a", b"1", QoS::AtMost, false)
+            .await
+            .unwrap();
+        let idle = Instant::now() - Duration::from_secs(u64::from(KEEP_ALIVE_SECS));
+        client.session.lock().await.as_mut().unwrap().last_sent = idle;
+        client
+            .publish("swaps/b", b"2", QoS::AtMost, false)
+            .await
+            .unwrap();
+
+        let kinds: Vec<u8> = server.await.unwrap()[0]
+            .iter()
+            .map(|(kind, _)| *kind)
+            .collect();
+        assert_eq!(kinds, [CONNECT, PUBLISH, PINGREQ, PUBLISH]);
+    }
+
+    #[tokio::test]
+    async fn refusals_are_explained() {
+        let (listener, client) = listen().await;
+        let server = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            read_packet(&mut stream).await.unwrap();
+            stream
+                .write_all(&packet(CONNACK << 4, &[0, 4]))
+                .await
+                .unwrap();
+        });
+        let err = client
+            .publish("swaps/new", b"{}", QoS::AtMost, false)
+            .await
+            .unwrap_err();
+        assert!(
+            format!("{err:#}").contains("bad user name or password"),
+            "{err:#}"
+        );
+        server.await.unwrap();
+    }
+}
@@ -0,0 +1,227 @@
+//! Notes and tags on swaps (`note`, `tag`, `untag`): operational context
+//! such as a support ticket or a suspicion, kept in a sqlite file of our own
+//! since ASB's database is only ever read. The file is created by the first
+//! note or tag; until then every swap simply has none.
+//!
+//! Tags show in `show`, the detail pane and JSON output, and `--tag`
+//! narrows the tables down to the swaps carrying one.
+
+use anyhow::{bail, Context, Result};
+use chrono::{SecondsFormat, Utc};
+use serde::Serialize;
+use sqlx::sqlite::{SqliteConnectOptions, SqliteJournalMode};
+use sqlx::{Executor, Row, SqlitePool};
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::sync::OnceCell;
+
+use crate::model::SwapView;
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS notes (
+    id INTEGER PRIMARY KEY,
+    swap_id TEXT NOT NULL,
+    text TEXT NOT NULL,
+    added_at TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS notes_swap ON notes (swap_id);
+CREATE TABLE IF NOT EXISTS tags (
+    swap_id TEXT NOT NULL,
+    tag TEXT NOT NULL,
+    PRIMARY KEY (swap_id, tag)
+);
+";
+
+/// One swap's tags and notes, oldest note first.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Annotations {
+    pub swap_id: String,
+    pub tags: Vec<String>,
+    pub notes: Vec<Note>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Note {
+    pub text: String,
+    pub added_at: String,
+}
+
+/// When there is neither `--notes-db` nor a data directory to default to.
+pub const NO_NOTES_DB: &str = "nowhere to keep notes: set a notes database (--notes-db)";
+
+#[derive(Clone)]
+pub struct NotesStore {
+    path: PathBuf,
+    pool: Arc<OnceCell<SqlitePool>>,
+}
+
+impl NotesStore {
+    /// The database at `path`, opened on first use.
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            pool: Arc::new(OnceCell::new()),
+        }
+    }
+
+    /// `~/.local/share/wraithswap/notes.sqlite` on Linux.
+    pub fn default_path() -> Option<PathBuf> {
+        Some(dirs::data_dir()?.join("wraithswap/notes.sqlite"))
+    }
+
+    /// The pool, creating the database and its directory if `create`.
+    /// Without it, a database that doesn't exist yet is `None`.
+    async fn pool(&self, create: bool) -> Result<Option<&SqlitePool>> {
+        if self.pool.get().is_none() && !create && !self.path.exists() {
+            return Ok(None);
+        }
+        let path = &self.path;
+        let pool = self
+            .pool
+            .get_or_try_init(|| async {
+                if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+                    std::fs::create_dir_all(dir)
+                        .with_context(|| format!("create directory {}", dir.display()))?;
+                }
+                let opts = SqliteConnectOptions::new()
+                    .filename(path)
+                    .create_if_missing(true)
+                    .journal_mode(SqliteJournalMode::Wal);
+                let pool = SqlitePool::connect_with(opts)
+                    .await
+                    .with_context(|| format!("open notes database {}", path.display()))?;
+                pool.execute(SCHEMA)
+                    .await
+                    .with_context(|| format!("set up notes database {}", path.display()))?;
+                anyhow::Ok(pool)
+            })
+            .await?;
+        Ok(Some(pool))
+    }
+
+    pub async fn add_note(&self, swap_id: &str, text: &str) -> Result<()> {
+        let Some(pool) = self.pool(true).await? else {
+            return Ok(());
+        };
+        sqlx::query("INSERT INTO notes (swap_id, text, added_at) VALUES (?, ?, ?)")
+            .bind(swap_id)
+            .bind(text)
+            .bind(Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true))
+            .execute(pool)
+            .await
+            .context("write note")?;
+        Ok(())
+    }
+
+    /// Tag `swap_id`; tags it already has are left alone.
+    pub async fn add_tags(&self, swap_id: &str, tags: &[String]) -> Result<()> {
+        let Some(pool) = self.pool(true).await? else {
+            return Ok(());
+        };
+        for tag in tags {
+            sqlx::query("INSERT OR IGNORE INTO tags (swap_id, tag) VALUES (?, ?)")
+                .bind(swap_id)
+                .bind(tag)
+                .execute(pool)
+                .await
+                .context("write tag")?;
+        }
+        Ok(())
+    }
+
+    /// Take `tags` off `swap_id`, returning how many it had.
+    pub async fn remove_tags(&self, swap_id: &str, tags: &[String]) -> Result<u64> {
+        let Some(pool) = self.pool(false).await? else {
+            return Ok(0);
+        };
+        let mut removed = 0;
+        for tag in tags {
+            removed += sqlx::query("DELETE FROM tags WHERE swap_id = ? AND tag = ?")
+                .bind(swap_id)
+                .bind(tag)
+                .execute(pool)
+                .await
+                .context("remove tag")?
+                .rows_affected();
+        }
+        Ok(removed)
+    }
+
+    /// Every annotated swap's tags and notes, by swap id, or only
+    /// `swap_id`'s.
+    pub async fn annotations(&self, swap_id: Option<&str>) -> Result<Vec<Annotations>> {
+        let Some(pool) = self.pool(false).await? else {
+            return Ok(Vec::new());
+        };
+        let mut all: BTreeMap<String, Annotations> = BTreeMap::new();
+        let tags = sqlx::query(
+            "SELECT swap_id, tag FROM tags WHERE ?1 IS NULL OR swap_id = ?1 ORDER BY tag",
+        )
+        .bind(swap_id)
+        .fetch_all(pool)
+        .await
+        .context("read tags")?;
+        for row in &tags {
+            let swap_id: String = row.get("swap_id");
+            entry(&mut all, swap_id).tags.push(row.get("tag"));
+        }
+        let notes = sqlx::query(
+            "SELECT swap_id, text, added_at FROM notes WHERE ?1 IS NULL OR swap_id = ?1 \
+             ORDER BY id",
+        )
+        .bind(swap_id)
+        .fetch_all(pool)
+        .await
+        .context("read notes")?;
+        for row in &notes {
+            let swap_id: String = row.get("swap_id");
+            entry(&mut all, swap_id).notes.push(Note {
+                text: row.get("text"),
+                added_at: row.get("added_at"),
+            });
+        }
+        Ok(all.into_values().collect())
+    }
+
+    /// Fill in the tags of `views`.
+    pub async fn annotate(&self, views: &mut [SwapView]) -> Result<()> {
+        let Some(pool) = self.pool(false).await? else {
+            return Ok(());
+        };
+        let rows = sqlx::query("SELECT swap_id, tag FROM tags ORDER BY tag")
+            .fetch_all(pool)
+            .await
+            .context("read tags")?;
+        let mut tags: HashMap<String, Vec<String>> = HashMap::new();
+        for row in &rows {
+            tags.entry(row.get("swap_id"))
+                .or_default()
+                .push(row.get("tag"));
+        }
+        for view in views {
+            view.tags = tags.remove(&view.swap_id).unwrap_or_default();
+        }
+        Ok(())
+    }
+}
+
+fn entry(all: &mut BTreeMap<String, Annotations>, swap_id: String) -> &mut Annotations {
+    all.entry(swap_id.clone()).or_insert_with(|| Annotations {
+        swap_id,
+        ..Annotations::default()
+    })
+}
+
+/// Tags are single lower-case words, so `--tag` and the table can show
+/// them as they are.
+pub fn parse_tag(raw: &str) -> Result<String> {
+    let tag = raw.trim().to_lowercase();
+    if tag.is_empty() {
+        bail!("tags must not be empty");
+    }
+    if tag.contains(char::is_whitespace) || tag.contains(',') {
+        bail!("a tag is a single word without commas, got {raw:?}");
+    }
+    Ok(tag)
+}
@@ -0,0 +1,1882 @@
+//! Outbound notifications for detected state transitions, stuck swaps, low
+//! liquidity, fee spikes and periodic digests.
+//!
+//! Each backend is a variant of [`Notifier`]; [`Notifiers`] fans a batch of
+//! events out to every configured backend on background tasks so a slow
+//! endpoint never stalls the poll loop.
+
+use anyhow::{bail, Context, Result};
+use chrono::Local;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::field::Empty;
+use tracing::{info_span, Instrument};
+
+use crate::anomaly::AnomalyKind;
+use crate::asblog::LogAlert;
+use crate::chain::LiquidityAlert;
+use crate::digest::Digest;
+use crate::hooks::Hook;
+use crate::http::{self, Request};
+use crate::liveness::AsbDown;
+use crate::mail::{Message, SmtpServer};
+use crate::mempool::FeeSpike;
+use crate::model::{Network, Transition};
+use crate::mqtt::{MqttBroker, MqttClient, QoS};
+use crate::quote::QuoteAlert;
+use crate::reachability::Unreachable;
+use crate::render::{
+    format_btc, format_duration, format_fee_rate, format_vsize, format_xmr, truncate_id,
+};
+use crate::rules::{self, QuietHours, Rule, Severity};
+
+/// Restricts a notifier to transitions *into* particular states. An empty
+/// filter lets everything through.
+#[derive(Debug, Clone, Default)]
+pub struct StateFilter(Vec<String>);
+
+impl StateFilter {
+    pub fn new(states: Vec<String>) -> Self {
+        Self(states)
+    }
+
+    pub fn matches(&self, state: &str) -> bool {
+        self.0.is_empty() || self.0.iter().any(|s| s == state)
+    }
+}
+
+/// Something a notifier is asked to deliver.
+#[derive(Debug, Clone)]
+pub enum Event {
+    Transition(Transition),
+    Liquidity(LiquidityAlert),
+    FeeSpike(FeeSpike),
+    AsbDown(AsbDown),
+    Unreachable(Unreachable),
+    Quote(QuoteAlert),
+    Log(LogAlert),
+    Digest(Digest),
+    /// From `notify-test`, to check a backend's settings
+    Test,
+}
+
+impl Event {
+    /// What makes two events "the same alert" for deduplication, and
+    /// whether repeats are held back even without a cooldown. Stuck
+    /// reminders are; liquidity, fee, ASB, reachability and quote alerts
+    /// already fire once per crossing, and log alerts once per window, so
+    /// only a cooldown keeps a flapping value quiet.
+    /// Transitions never repeat.
+    fn dedup_key(&self) -> Option<(String, bool)> {
+        match self {
+            Event::Transition(t) if t.stuck_for_secs.is_some() => {
+                Some((format!("{}/{}", swap_key(t), t.new_state), true))
+            }
+            Event::Liquidity(LiquidityAlert::XmrLow { .. }) => {
+                Some(("liquidity/xmr_low".to_string(), false))
+            }
+            Event::Liquidity(LiquidityAlert::BtcHigh { .. }) => {
+                Some(("liquidity/btc_high".to_string(), false))
+            }
+            Event::FeeSpike(_) => Some(("fees/spike".to_string(), false)),
+            Event::AsbDown(_) => Some(("asb/down".to_string(), false)),
+            Event::Unreachable(alert) => Some((format!("reachability/{}", alert.address), false)),
+            Event::Quote(QuoteAlert::Failed { .. }) => Some(("quote/failed".to_string(), false)),
+            Event::Quote(QuoteAlert::Drift { .. }) => Some(("quote/drift".to_string(), false)),
+            Event::Log(alert) => Some((format!("log/{}", alert.rule), false)),
+            Event::Transition(_) | Event::Digest(_) | Event::Test => None,
+        }
+    }
+
+    /// Title and body for the human-readable backends.
+    fn text(&self) -> (String, String) {
+        match self {
+            Event::Transition(transition) => DesktopNotifier::describe(transition),
+            Event::Liquidity(alert) => {
+                let (title, body) = liquidity_text(alert);
+                (title.to_string(), body)
+            }
+            Event::FeeSpike(spike) => fee_spike_text(spike),
+            Event::AsbDown(down) => asb_down_text(down),
+            Event::Unreachable(alert) => unreachable_text(alert),
+            Event::Quote(alert) => quote_text(alert),
+            Event::Log(alert) => log_text(alert),
+            Event::Digest(digest) => digest.text(),
+            Event::Test => (
+                "WraithSwap test alert".to_string(),
+                "Sent by notify-test. If you can read this, alerts reach you.".to_string(),
+            ),
+        }
+    }
+
+    /// Severity when no rule says otherwise: punishes, stuck swaps and ASB
+    /// going down are critical, cancels, refunds, anomalies, watched peers,
+    /// any step of a pinned swap, liquidity, fee spikes, unreachable
+    /// addresses and bad quotes need a look, log alerts are as severe as
+    /// their rule says, the rest is informational.
+    /// Test alerts are critical so they take the same path as a punish.
+    fn severity(&self) -> Severity {
+        match self {
+            Event::Transition(t) if t.anomaly.is_some() || t.is_watched_arrival() => {
+                Severity::Warning
+            }
+            Event::Transition(t) if t.stuck_for_secs.is_some() || t.new_state.is_punish() => {
+                Severity::Critical
+            }
+            Event::Transition(t) if t.new_state.is_cancel_path() || t.pinned => Severity::Warning,
+            Event::Test | Event::AsbDown(_) => Severity::Critical,
+            Event::Log(alert) => alert.severity,
+            Event::Liquidity(_) | Event::FeeSpike(_) | Event::Unreachable(_) | Event::Quote(_) => {
+                Severity::Warning
+            }
+            Event::Transition(_) | Event::Digest(_) => Severity::Info,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Notifier {
+    Desktop(DesktopNotifier),
+    Webhook(WebhookNotifier),
+    Telegram(TelegramNotifier),
+    Ntfy(NtfyNotifier),
+    Matrix(MatrixNotifier),
+    Discord(DiscordNotifier),
+    Pushover(PushoverNotifier),
+    Gotify(GotifyNotifier),
+    Email(EmailNotifier),
+    Incident(IncidentNotifier),
+    Mqtt(MqttNotifier),
+}
+
+impl Notifier {
+    /// Stuck, liquidity, fee, ASB, reachability, quote and log alerts carry
+    /// their own thresholds, anomalies, watched peers and pinned swaps
+    /// matter whatever the state, and digests are asked for explicitly, so
+    /// state filters only apply to other transitions.
+    fn accepts(&self, event: &Event) -> bool {
+        let transition = match event {
+            Event::Transition(t)
+                if t.stuck_for_secs.is_none()
+                    && t.anomaly.is_none()
+                    && !t.is_watched_arrival()
+                    && !t.pinned =>
+            {
+                t
+            }
+            _ => return true,
+        };
+        match self {
+            Notifier::Desktop(n) => n.filter.matches(transition.new_state.as_str()),
+            Notifier::Webhook(n) => n.filter.matches(transition.new_state.as_str()),
+            Notifier::Telegram(n) => n.filter.matches(transition.new_state.as_str()),
+            Notifier::Ntfy(n) => n.filter.matches(transition.new_state.as_str()),
+            Notifier::Matrix(n) => n.filter.matches(transition.new_state.as_str()),
+            Notifier::Discord(n) => n.filter.matches(transition.new_state.as_str()),
+            Notifier::Pushover(n) => n.filter.matches(transition.new_state.as_str()),
+            Notifier::Gotify(n) => n.filter.matches(transition.new_state.as_str()),
+            Notifier::Email(n) => n.filter.matches(transition.new_state.as_str()),
+            Notifier::Incident(n) => n.filter.matches(transition.new_state.as_str()),
+            Notifier::Mqtt(n) => n.filter.matches(transition.new_state.as_str()),
+        }
+    }
+
+    async fn send(&self, event: &Event, severity: Severity, rule: &str) -> Result<()> {
+        match self {
+            Notifier::Desktop(n) => n.send(event, severity).await,
+            Notifier::Webhook(n) => n.send(event, severity).await,
+            Notifier::Telegram(n) => n.send(event, severity).await,
+            Notifier::Ntfy(n) => n.send(event, severity).await,
+            Notifier::Matrix(n) => n.send(event, severity).await,
+            Notifier::Discord(n) => n.send(event, severity).await,
+            Notifier::Pushover(n) => n.send(event, severity).await,
+            Notifier::Gotify(n) => n.send(event, severity).await,
+            Notifier::Email(n) => n.send(event, severity).await,
+            Notifier::Incident(n) => n.send(event, severity, rule).await,
+            Notifier::Mqtt(n) => n.send(event, severity).await,
+        }
+    }
+
+    /// What alert rules call this backend in their `notify` lists.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Notifier::Desktop(_) => "desktop",
+            Notifier::Webhook(_) => "webhook",
+            Notifier::Telegram(_) => "telegram",
+            Notifier::Ntfy(_) => "ntfy",
+            Notifier::Matrix(_) => "matrix",
+            Notifier::Discord(_) => "discord",
+            Notifier::Pushover(_) => "pushover",
+            Notifier::Gotify(_) => "gotify",
+            Notifier::Email(_) => "email",
+            Notifier::Incident(n) => match n.service {
+                IncidentService::PagerDuty { .. } => "pagerduty",
+                IncidentService::Opsgenie { .. } => "opsgenie",
+            },
+            Notifier::Mqtt(_) => "mqtt",
+        }
+    }
+
+    /// Distinguishes notifiers of the same kind in rules, as `kind:label`.
+    pub fn label(&self) -> Option<&str> {
+        match self {
+            Notifier::Discord(n) => n.name.as_deref(),
+            _ => None,
+        }
+    }
+
+    fn name(&self) -> String {
+        match self {
+            Notifier::Desktop(_) => "desktop".to_string(),
+            Notifier::Webhook(n) => format!("webhook {}", http::redact(&n.url)),
+            Notifier::Telegram(_) => "telegram".to_string(),
+            Notifier::Ntfy(n) => format!("ntfy {}", http::redact(&n.server)),
+            Notifier::Matrix(n) => format!("matrix {}", n.room_id),
+            Notifier::Discord(n) => match n.name {
+                Some(ref name) => format!("discord {name}"),
+                None => format!("discord {}", http::redact(&n.url)),
+            },
+            Notifier::Pushover(_) => "pushover".to_string(),
+            Notifier::Gotify(n) => format!("gotify {}", http::redact(&n.server)),
+            Notifier::Email(n) => format!("email {}", n.server.url),
+            Notifier::Incident(_) => self.kind().to_string(),
+            Notifier::Mqtt(n) => format!("mqtt {}", n.client),
+        }
+    }
+}
+
+/// Identifies a swap across instances.
+fn swap_key(transition: &Transition) -> String {
+    format!(
+        "{}/{}",
+        transition.instance.as_deref().unwrap_or(""),
+        transition.swap_id
+    )
+}
+
+/// What has been delivered, for holding back repeats.
+#[derive(Debug, Default)]
+struct Sent {
+    /// Last delivery per rule (empty without rules), notifier and alert
+    alerts: HashMap<(String, usize, String), (Instant, Severity)>,
+    /// Notifiers told a swap is stuck, until it moves on
+    stuck: HashMap<String, Vec<usize>>,
+}
+
+/// An alert held back during quiet hours, for the next digest.
+#[derive(Debug, Clone, Serialize)]
+pub struct HeldAlert {
+    pub severity: Severity,
+    pub summary: String,
+    pub observed_at: String,
+}
+
+/// How one notifier fared with `notify-test`.
+#[derive(Debug, Serialize)]
+pub struct TestOutcome {
+    pub channel: String,
+    /// Why delivery failed; `None` means it went through
+    pub error: Option<String>,
+}
+
+/// The set of active notifiers. Cheap to clone; delivery failures are kept
+/// so the UI can surface the most recent one.
+#[derive(Debug, Clone, Default)]
+pub struct Notifiers {
+    notifiers: Arc<Vec<Notifier>>,
+    rules: Arc<Vec<Rule>>,
+    /// How soon an alert may repeat at the same severity, for rules that
+    /// don't set their own
+    cooldown: Option<Duration>,
+    sent: Arc<Mutex<Sent>>,
+    quiet_hours: Arc<Vec<QuietHours>>,
+    held: Arc<Mutex<Vec<HeldAlert>>>,
+    hook: Option<Hook>,
+    last_error: Arc<Mutex<Option<String>>>,
+}
+
+impl Notifiers {
+    pub fn new(notifiers: Vec<Notifier>) -> Self {
+        Self {
+            notifiers: Arc::new(notifiers),
+            rules: Arc::default(),
+            cooldown: None,
+            sent: Arc::default(),
+            quiet_hours: Arc::default(),
+            held: Arc::default(),
+            hook: None,
+            last_error: Arc::default(),
+        }
+    }
+
+    pub fn with_hook(mut self, hook: Option<Hook>) -> Self {
+        self.hook = hook;
+        self
+    }
+
+    pub fn with_quiet_hours(mut self, quiet_hours: Vec<QuietHours>) -> Self {
+        self.quiet_hours = Arc::new(quiet_hours);
+        self
+    }
+
+    pub fn with_cooldown(mut self, cooldown: Option<Duration>) -> Self {
+        self.cooldown = cooldown;
+        self
+    }
+
+    /// Route swap events by `rules` instead of the notifiers' own filters.
+    /// Every notifier a rule names has to be configured.
+    pub fn with_rules(mut self, rules: Vec<Rule>) -> Result<Self> {
+        for rule in &rules {
+            for kind in &rule.notify {
+                if !self
+                    .notifiers
+                    .iter()
+                    .any(|n| rules::targets(kind, n.kind(), n.label()))
+                {
+                    bail!(
+                        "rule {:?} notifies {kind}, which isn't configured",
+                        rule.name
+                    );
+                }
+            }
+        }
+        self.rules = Arc::new(rules);
+        Ok(self)
+    }
+
+    pub fn dispatch(&self, transitions: &[Transition]) {
+        for transition in transitions {
+            if let Some(ref hook) = self.hook {
+                hook.run(transition, &self.last_error);
+            }
+            self.send(Event::Transition(transition.clone()));
+        }
+    }
+
+    pub fn dispatch_liquidity(&self, alerts: &[LiquidityAlert]) {
+        for &alert in alerts {
+            self.send(Event::Liquidity(alert));
+        }
+    }
+
+    pub fn dispatch_fees(&self, spikes: &[FeeSpike]) {
+        for &spike in spikes {
+            self.send(Event::FeeSpike(spike));
+        }
+    }
+
+    pub fn dispatch_asb(&self, alerts: &[AsbDown]) {
+        for alert in alerts {
+            self.send(Event::AsbDown(alert.clone()));
+        }
+    }
+
+    pub fn dispatch_reach(&self, alerts: &[Unreachable]) {
+        for alert in alerts {
+            self.send(Event::Unreachable(alert.clone()));
+        }
+    }
+
+    pub fn dispatch_quotes(&self, alerts: &[QuoteAlert]) {
+        for alert in alerts {
+            self.send(Event::Quote(alert.clone()));
+        }
+    }
+
+    pub fn dispatch_log(&self, alerts: &[LogAlert]) {
+        for alert in alerts {
+            self.send(Event::Log(alert.clone()));
+        }
+    }
+
+    pub fn dispatch_digest(&self, digest: Digest) {
+        self.send(Event::Digest(digest));
+    }
+
+    /// Alerts held back during quiet hours since the last call, oldest first.
+    pub fn take_held(&self) -> Vec<HeldAlert> {
+        std::mem::take(&mut *self.held.lock().unwrap())
+    }
+
+    /// Send a test alert through every notifier `channel` picks (a kind
+    /// such as `telegram`, or `discord:NAME`), or all of them, waiting for
+    /// each to finish. Filters, rules and quiet hours don't apply.
+    pub async fn test(&self, channel: Option<&str>) -> Result<Vec<TestOutcome>> {
+        let picked: Vec<&Notifier> = self
+            .notifiers
+            .iter()
+            .filter(|n| channel.is_none_or(|c| rules::targets(c, n.kind(), n.label())))
+            .collect();
+        match channel {
+            _ if self.notifiers.is_empty() => bail!("no notifiers are configured"),
+            Some(channel) if picked.is_empty() => {
+                bail!("no configured notifier matches {channel:?}")
+            }
+            _ => {}
+        }
+
+        // All at once, so one slow backend doesn't hold up the report
+        let tasks: Vec<_> = picked
+            .into_iter()
+            .map(|notifier| {
+                let name = notifier.name();
+                let notifier = notifier.clone();
+                let task = tokio::spawn(async move {
+                    let event = Event::Test;
+                    notifier.send(&event, event.severity(), "").await
+                });
+                (name, task)
+            })
+            .collect();
+        let mut outcomes = Vec::with_capacity(tasks.len());
+        for (channel, task) in tasks {
+            let error = match task.await {
+                Ok(Ok(())) => None,
+                Ok(Err(err)) => Some(format!("{err:#}")),
+                Err(err) => Some(err.to_string()),
+            };
+            outcomes.push(TestOutcome { channel, error });
+        }
+        Ok(outcomes)
+    }
+
+    fn send(&self, event: Event) {
+        let mut routes = self.route(&event);
+        // Digests are where held alerts end up, so they always go out, and
+        // a pinned swap is being babysat
+        let now = Local::now().time();
+        let through = match event {
+            Event::Digest(_) => true,
+            Event::Transition(ref t) => t.pinned,
+            _ => false,
+        };
+        if !through && self.quiet_hours.iter().any(|q| q.contains(now)) {
+            // Incident backends only page for critical alerts anyway, and
+            // need the rest to resolve incidents
+            let muted = |&(notifier, severity, _): &(&Notifier, Severity, &str)| {
+                severity < Severity::Critical && !matches!(notifier, Notifier::Incident(_))
+            };
+            let held = routes
+                .iter()
+                .filter(|route| muted(route))
+                .map(|&(_, severity, _)| severity)
+                .max();
+            if let Some(severity) = held {
+                routes.retain(|route| !muted(route));
+                self.held.lock().unwrap().push(HeldAlert {
+                    severity,
+                    summary: summary(&event),
+                    observed_at: observed_now(),
+                });
+            }
+        }
+        for (notifier, severity, rule) in routes {
+            let notifier = notifier.clone();
+            let event = event.clone();
+            let rule = rule.to_string();
+            let last_error = Arc::clone(&self.last_error);
+            let span = info_span!(
+                "notify",
+                notifier = %notifier.name(),
+                kind = notifier.kind(),
+                ?severity,
+                rule,
+                error = Empty,
+                otel.status_code = Empty,
+            );
+
+            let delivery = span.clone();
+
+            tokio::spawn(
+                async move {
+                    if let Err(err) = notifier.send(&event, severity, &rule).await {
+                        let err = format!("{err:#}");
+                        delivery
+                            .record("error", err.as_str())
+                            .record("otel.status_code", "error");
+                        *last_error.lock().unwrap() = Some(format!("{}: {err}", notifier.name()));
+                    }
+                }
+                .instrument(span),
+            );
+        }
+    }
+
+    /// The notifiers `event` goes to, at what severity and by which rule
+    /// (empty without one). An alert that
+    /// was already sent is held back unless its severity went up or the
+    /// cooldown ran out; the transition that moves a stuck swap on always
+    /// reaches the notifiers that reported it stuck.
+    fn route(&self, event: &Event) -> Vec<(&Notifier, Severity, &str)> {
+        // Rule name, its cooldown, notifier index and severity
+        let mut candidates: Vec<(&str, Option<Duration>, usize, Severity)> = Vec::new();
+        match event {
+            Event::Transition(t) if !self.rules.is_empty() => {
+                for rule in self.rules.iter().filter(|r| r.matches(t)) {
+                    for (i, notifier) in self.notifiers.iter().enumerate() {
+                        let taken = candidates.iter().any(|&(_, _, n, _)| n == i);
+                        if !taken && rule.routes_to(notifier.kind(), notifier.label()) {
+                            let cooldown = rule.cooldown.or(self.cooldown);
+                            candidates.push((&rule.name, cooldown, i, rule.severity));
+                        }
+                    }
+                }
+            }
+            _ => {
+                for (i, notifier) in self.notifiers.iter().enumerate() {
+                    if notifier.accepts(event) {
+                        candidates.push(("", self.cooldown, i, event.severity()));
+                    }
+                }
+            }
+        }
+
+        let mut sent = self.sent.lock().unwrap();
+        let now = Instant::now();
+        let mut routed: Vec<(usize, Severity, &str)> = Vec::new();
+        for (rule, cooldown, i, severity) in candidates {
+            if let Some((key, hold_always)) = event.dedup_key() {
+                let key = (rule.to_string(), i, key);
+                let held = match sent.alerts.get(&key) {
+                    Some(&(at, last)) if severity <= last => match cooldown {
+                        Some(cooldown) => now.duration_since(at) < cooldown,
+                        None => hold_always,
+                    },
+                    _ => false,
+                };
+                if held {
+                    continue;
+                }
+                sent.alerts.insert(key, (now, severity));
+            }
+            routed.push((i, severity, rule));
+        }
+
+        if let Event::Transition(t) = event {
+            let swap = swap_key(t);
+            if t.stuck_for_secs.is_some() {
+                let told = sent.stuck.entry(swap).or_default();
+                for &(i, _, _) in &routed {
+                    if !told.contains(&i) {
+                        told.push(i);
+                    }
+                }
+            } else if t.anomaly.is_some() {
+                // Comes with the transition that already resolved any stuck
+                // spell
+            } else if let Some(told) = sent.stuck.remove(&swap) {
+                for i in told {
+                    if !routed.iter().any(|&(n, _, _)| n == i) {
+                        routed.push((i, event.severity(), ""));
+                    }
+                }
+                let prefix = format!("{swap}/");
+                sent.alerts
+                    .retain(|(_, _, key), _| !key.starts_with(&prefix));
+            }
+        }
+        routed
+            .into_iter()
+            .map(|(i, severity, rule)| (&self.notifiers[i], severity, rule))
+            .collect()
+    }
+
+    /// Most recent delivery failure, cleared on read.
+    pub fn take_error(&self) -> Option<String> {
+        self.last_error.lock().unwrap().take()
+    }
+}
+
+/// Native desktop notifications through notify-rust: D-Bus on Linux and the
+/// BSDs, Notification Center on macOS.
+#[derive(Debug, Clone, Default)]
+pub struct DesktopNotifier {
+    pub filter: StateFilter,
+}
+
+impl DesktopNotifier {
+    async fn send(&self, event: &Event, severity: Severity) -> Result<()> {
+        let (title, body) = event.text();
+        let notification = Self::notification(&title, &body, severity);
+
+        #[cfg(all(unix, not(target_os = "macos")))]
+        notification
+            .show_async()
+            .await
+            .context("show desktop notification")?;
+
+        // Elsewhere showing one blocks until the system takes it
+        #[cfg(not(all(unix, not(target_os = "macos"))))]
+        tokio::task::spawn_blocking(move || notification.show())
+            .await?
+            .context("show desktop notification")?;
+        Ok(())
+    }
+
+    fn notification(title: &str, body: &str, severity: Severity) -> notify_rust::Notification {
+        let mut notification = notify_rust::Notification::new();
+        notification.appname("WraithSwap").summary(title).body(body);
+        #[cfg(all(unix, not(target_os = "macos")))]
+        notification.urgency(match severity >= Severity::Warning {
+            true => notify_rust::Urgency::Critical,
+            false => notify_rust::Urgency::Normal,
+        });
+        #[cfg(not(all(unix, not(target_os = "macos"))))]
+        let _ = severity;
+        notification
+    }
+
+    fn describe(transition: &Transition) -> (String, String) {
+        let mut title = match transition.stuck_for_secs {
+            Some(_) => format!(
+                "Swap {} stuck in {}",
+                truncate_id(&transition.swap_id),
+                transition.new_state
+            ),
+            None => format!(
+                "Swap {}: {}",
+                truncate_id(&transition.swap_id),
+                transition.new_state
+            ),
+        };
+        if let Some(ref instance) = transition.instance {
+            title = format!("[{instance}] {title}");
+        }
+        if transition.anomaly.is_some() {
+            title = format!("{title} (unexpected)");
+        }
+        if transition.watched {
+            title = format!("{title} (watched peer)");
+        }
+        if transition.pinned {
+            title = format!("{title} (pinned)");
+        }
+        let body = match (transition.stuck_for_secs, &transition.old_state) {
+            (Some(secs), _) => format!("No progress for {}", stuck_duration(secs)),
+            (None, Some(old)) if transition.anomaly.is_some() => format!(
+                "{old} → {}: {}",
+                transition.new_state,
+                transition.anomaly.map_or("", AnomalyKind::explain)
+            ),
+            (None, Some(old)) => format!("{old} → {}", transition.new_state),
+            (None, None) => match transition.peer_id {
+                Some(ref peer_id) if transition.watched => format!(
+                    "New swap in {} from watched peer {peer_id}",
+                    transition.new_state
+                ),
+                _ => format!("New swap in {}", transition.new_state),
+            },
+        };
+        (title, body)
+    }
+}
+
+/// Attempts per webhook delivery, including the first
+const WEBHOOK_ATTEMPTS: u32 = 3;
+
+/// POSTs each transition as JSON to a URL. The generic building block for
+/// alerting stacks that don't have a dedicated backend here.
+#[derive(Debug, Clone)]
+pub struct WebhookNotifier {
+    pub url: String,
+    pub timeout: Duration,
+    pub filter: StateFilter,
+}
+
+#[derive(Serialize)]
+struct WebhookPayload<'a> {
+    /// `transition`, `stuck` or `anomaly`
+    event: &'static str,
+    swap_id: &'a str,
+    old_state: Option<&'a str>,
+    new_state: &'a str,
+    entered_at: &'a str,
+    observed_at: &'a str,
+    network: Network,
+    severity: Severity,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stuck_for_secs: Option<u64>,
+    /// `skipped` or `impossible` on anomaly alerts
+    #[serde(skip_serializing_if = "Option::is_none")]
+    anomaly: Option<AnomalyKind>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    peer_id: Option<&'a str>,
+    /// Set when the peer is on the watchlist
+    #[serde(skip_serializing_if = "is_false")]
+    watched: bool,
+    /// Set when the swap is pinned
+    #[serde(skip_serializing_if = "is_false")]
+    pinned: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    instance: Option<&'a str>,
+}
+
+impl<'a> WebhookPayload<'a> {
+    fn new(transition: &'a Transition, severity: Severity) -> Self {
+        Self {
+            event: match (transition.stuck_for_secs, transition.anomaly) {
+                (Some(_), _) => "stuck",
+                (None, Some(_)) => "anomaly",
+                (None, None) => "transition",
+            },
+            swap_id: &transition.swap_id,
+            old_state: transition.old_state.as_ref().map(|s| s.as_str()),
+            new_state: transition.new_state.as_str(),
+            entered_at: &transition.entered_at,
+            observed_at: &transition.observed_at,
+            network: transition.network,
+            severity,
+            stuck_for_secs: transition.stuck_for_secs,
+            anomaly: transition.anomaly,
+            peer_id: transition.peer_id.as_deref(),
+            watched: transition.watched,
+            pinned: transition.pinned,
+            instance: transition.instance.as_deref(),
+        }
+    }
+}
+
+fn is_false(value: &bool) -> bool {
+    !value
+}
+
+/// Digests cover many swaps and get their own shape too.
+#[derive(Serialize)]
+struct DigestPayload<'a> {
+    /// Always `digest`
+    event: &'static str,
+    #[serde(flatten)]
+    digest: &'a Digest,
+    observed_at: String,
+    severity: Severity,
+}
+
+/// Liquidity alerts aren't about a swap, so they get their own shape.
+#[derive(Serialize)]
+struct LiquidityPayload<'a> {
+    /// Always `liquidity`
+    event: &'static str,
+    #[serde(flatten)]
+    alert: &'a LiquidityAlert,
+    observed_at: String,
+    severity: Severity,
+}
+
+#[derive(Serialize)]
+struct FeeSpikePayload<'a> {
+    /// Always `fee_spike`
+    event: &'static str,
+    #[serde(flatten)]
+    spike: &'a FeeSpike,
+    observed_at: String,
+    severity: Severity,
+}
+
+#[derive(Serialize)]
+struct AsbDownPayload<'a> {
+    /// Always `asb_down`
+    event: &'static str,
+    #[serde(flatten)]
+    alert: &'a AsbDown,
+    observed_at: String,
+    severity: Severity,
+}
+
+#[derive(Serialize)]
+struct UnreachablePayload<'a> {
+    /// Always `unreachable`
+    event: &'static str,
+    #[serde(flatten)]
+    alert: &'a Unreachable,
+    observed_at: String,
+    severity: Severity,
+}
+
+#[derive(Serialize)]
+struct QuotePayload<'a> {
+    /// Always `quote`
+    event: &'static str,
+    #[serde(flatten)]
+    alert: &'a QuoteAlert,
+    observed_at: String,
+    severity: Severity,
+}
+
+#[derive(Serialize)]
+struct LogPayload<'a> {
+    /// Always `log`
+    event: &'static str,
+    #[serde(flatten)]
+    alert: &'a LogAlert,
+    observed_at: String,
+    severity: Severity,
+}
+
+impl WebhookNotifier {
+    async fn send(&self, event: &Event, severity: Severity) -> Result<()> {
+        let transition = match event {
+            Event::Transition(transition) => transition,
+            Event::Liquidity(alert) => {
+                return self
+                    .post(&LiquidityPayload {
+                        event: "liquidity",
+                        alert,
+                        observed_at: observed_now(),
+                        severity,
+                    })
+                    .await
+            }
+            Event::FeeSpike(spike) => {
+                return self
+                    .post(&FeeSpikePayload {
+                        event: "fee_spike",
+                        spike,
+                        observed_at: observed_now(),
+                        severity,
+                    })
+                    .await
+            }
+            Event::AsbDown(alert) => {
+                return self
+                    .post(&AsbDownPayload {
+                        event: "asb_down",
+                        alert,
+                        observed_at: observed_now(),
+                        severity,
+                    })
+                    .await
+            }
+            Event::Unreachable(alert) => {
+                return self
+                    .post(&UnreachablePayload {
+                        event: "unreachable",
+                        alert,
+                        observed_at: observed_now(),
+                        severity,
+                    })
+                    .await
+            }
+            Event::Quote(alert) => {
+                return self
+                    .post(&QuotePayload {
+                        event: "quote",
+                        alert,
+                        observed_at: observed_now(),
+                        severity,
+                    })
+                    .await
+            }
+            Event::Log(alert) => {
+                return self
+                    .post(&LogPayload {
+                        event: "log",
+                        alert,
+                        observed_at: observed_now(),
+                        severity,
+                    })
+                    .await
+            }
+            Event::Digest(digest) => {
+                return self
+                    .post(&DigestPayload {
+                        event: "digest",
+                        digest,
+                        observed_at: observed_now(),
+                        severity,
+                    })
+                    .await
+            }
+            Event::Test => {
+                return self
+                    .post(&serde_json::json!({
+                        "event": "test",
+                        "observed_at": observed_now(),
+                        "severity": severity,
+                    }))
+                    .await
+            }
+        };
+        self.post(&WebhookPayload::new(transition, severity)).await
+    }
+
+    async fn post(&self, payload: &impl Serialize) -> Result<()> {
+        Request::post(&self.url)
+            .json(payload)?
+            .timeout(self.timeout)
+            .send_with_retry(WEBHOOK_ATTEMPTS)
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+}
+
+/// States worth waking an operator for when no explicit filter is given.
+pub const TELEGRAM_DEFAULT_STATES: &[&str] = &["BtcPunished", "XmrRefunded", "BtcCancelled"];
+
+/// Messages a chat through the Telegram Bot API.
+#[derive(Debug, Clone)]
+pub struct TelegramNotifier {
+    pub bot_token: String,
+    pub chat_id: String,
+    pub filter: StateFilter,
+}
+
+impl TelegramNotifier {
+    async fn send(&self, event: &Event, severity: Severity) -> Result<()> {
+        let text = match event {
+            Event::Transition(transition) => Self::describe(transition, severity),
+            Event::Liquidity(alert) => {
+                let (title, body) = liquidity_text(alert);
+                format!("💧 {title}\n{body}")
+            }
+            Event::FeeSpike(spike) => {
+                let (title, body) = fee_spike_text(spike);
+                format!("⛽ {title}\n{body}")
+            }
+            Event::AsbDown(alert) => {
+                let (title, body) = asb_down_text(alert);
+                format!("🛑 {title}\n{body}")
+            }
+            Event::Unreachable(alert) => {
+                let (title, body) = unreachable_text(alert);
+                format!("🔌 {title}\n{body}")
+            }
+            Event::Quote(alert) => {
+                let (title, body) = quote_text(alert);
+                format!("💱 {title}\n{body}")
+            }
+            Event::Log(alert) => {
+                let (title, body) = log_text(alert);
+                format!("📜 {title}\n{body}")
+            }
+            Event::Digest(digest) => {
+                let (title, body) = digest.text();
+                format!("📋 {title}\n{body}")
+            }
+            Event::Test => {
+                let (title, body) = event.text();
+                format!("🧪 {title}\n{body}")
+            }
+        };
+
+        let url = format!("https://api.telegram.org/bot{}/sendMessage", self.bot_token);
+        Request::post(url)
+            .json(&serde_json::json!({ "chat_id": self.chat_id, "text": text }))?
+            .send_with_retry(WEBHOOK_ATTEMPTS)
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+
+    fn describe(transition: &Transition, severity: Severity) -> String {
+        let mut text = match (transition.stuck_for_secs, transition.anomaly) {
+            (Some(secs), _) => format!(
+                "⏳ Swap {} stuck in {} for {}\nNetwork: {}\nEntered at: {}",
+                transition.swap_id,
+                transition.new_state,
+                stuck_duration(secs),
+                transition.network.dir_name(),
+                transition.entered_at,
+            ),
+            (None, Some(kind)) => format!(
+                "🧩 Swap {} made an unexpected transition\n{} → {}: {}\nNetwork: {}\nEntered at: {}",
+                transition.swap_id,
+                transition
+                    .old_state
+                    .as_ref()
+                    .map(|s| s.as_str())
+                    .unwrap_or("(new)"),
+                transition.new_state,
+                kind.explain(),
+                transition.network.dir_name(),
+                transition.entered_at,
+            ),
+            (None, None) => format!(
+                "{} Swap {} entered {}\n{} → {}\nNetwork: {}\nEntered at: {}",
+                match severity {
+                    Severity::Critical => "🚨",
+                    Severity::Warning => "⚠️",
+                    Severity::Info => "ℹ️",
+                },
+                transition.swap_id,
+                transition.new_state,
+                transition
+                    .old_state
+                    .as_ref()
+                    .map(|s| s.as_str())
+                    .unwrap_or("(new)"),
+                transition.new_state,
+                transition.network.dir_name(),
+                transition.entered_at,
+            ),
+        };
+        if let Some(ref peer_id) = transition.peer_id {
+            let watched = if transition.watched {
+                " 👀 watched"
+            } else {
+                ""
+            };
+            text.push_str(&format!("\nPeer: {peer_id}{watched}"));
+        }
+        if let Some(ref instance) = transition.instance {
+            text.push_str(&format!("\nInstance: {instance}"));
+        }
+        text
+    }
+}
+
+pub const NTFY_DEFAULT_SERVER: &str = "https://ntfy.sh";
+
+/// Publishes to an ntfy topic, so alerts reach the ntfy phone app without
+/// an account. Severity picks the message priority: routine transitions
+/// arrive silently, critical ones as urgent.
+#[derive(Debug, Clone)]
+pub struct NtfyNotifier {
+    pub server: String,
+    pub topic: String,
+    pub filter: StateFilter,
+}
+
+impl NtfyNotifier {
+    async fn send(&self, event: &Event, severity: Severity) -> Result<()> {
+        let (title, message, tag) = match event {
+            Event::Transition(transition) => {
+                let (title, body) = DesktopNotifier::describe(transition);
+                let tag = match (transition.stuck_for_secs, transition.anomaly) {
+                    (Some(_), _) => "hourglass",
+                    (None, Some(_)) => "jigsaw",
+                    (None, None) => "arrows_counterclockwise",
+                };
+                (title, body, tag)
+            }
+            Event::Liquidity(alert) => {
+                let (title, body) = liquidity_text(alert);
+                (title.to_string(), body, "droplet")
+            }
+            Event::FeeSpike(spike) => {
+                let (title, body) = fee_spike_text(spike);
+                (title, body, "fuelpump")
+            }
+            Event::AsbDown(alert) => {
+                let (title, body) = asb_down_text(alert);
+                (title, body, "stop_sign")
+            }
+            Event::Unreachable(alert) => {
+                let (title, body) = unreachable_text(alert);
+                (title, body, "electric_plug")
+            }
+            Event::Quote(alert) => {
+                let (title, body) = quote_text(alert);
+                (title, body, "currency_exchange")
+            }
+            Event::Log(alert) => {
+                let (title, body) = log_text(alert);
+                (title, body, "scroll")
+            }
+            Event::Digest(digest) => {
+                let (title, body) = digest.text();
+                (title, body, "clipboard")
+            }
+            Event::Test => {
+                let (title, body) = event.text();
+                (title, body, "test_tube")
+            }
+        };
+        let (priority, severity_tag) = match severity {
+            Severity::Critical => (5, "rotating_light"),
+            Severity::Warning => (4, "warning"),
+            Severity::Info => (2, "information_source"),
+        };
+
+        // Publishing as JSON goes to the server root and names the topic
+        Request::post(self.server.trim_end_matches('/'))
+            .json(&serde_json::json!({
+                "topic": self.topic,
+                "title": title,
+                "message": message,
+                "priority": priority,
+                "tags": [severity_tag, tag],
+            }))?
+            .send_with_retry(WEBHOOK_ATTEMPTS)
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+}
+
+/// Posts embeds to a Discord channel through one of its webhooks. Several
+/// can be configured, and naming them lets rules send different alerts to
+/// different channels.
+#[derive(Debug, Clone)]
+pub struct DiscordNotifier {
+    pub name: Option<String>,
+    pub url: String,
+    pub filter: StateFilter,
+}
+
+impl DiscordNotifier {
+    async fn send(&self, event: &Event, severity: Severity) -> Result<()> {
+        let color = match severity {
+            Severity::Critical => 0xd32f2f,
+            Severity::Warning => 0xf57c00,
+            Severity::Info => 0x1976d2,
+        };
+        let embed = match event {
+            Event::Transition(transition) => Self::describe(transition, color),
+            Event::Liquidity(alert) => {
+                let (title, body) = liquidity_text(alert);
+                serde_json::json!({ "title": title, "description": body, "color": color })
+            }
+            Event::FeeSpike(spike) => {
+                let (title, body) = fee_spike_text(spike);
+                serde_json::json!({ "title": title, "description": body, "color": color })
+            }
+            Event::AsbDown(alert) => {
+                let (title, body) = asb_down_text(alert);
+                serde_json::json!({ "title": title, "description": body, "color": color })
+            }
+            Event::Unreachable(alert) => {
+                let (title, body) = unreachable_text(alert);
+                serde_json::json!({ "title": title, "description": body, "color": color })
+            }
+            Event::Quote(alert) => {
+                let (title, body) = quote_text(alert);
+                serde_json::json!({ "title": title, "description": body, "color": color })
+            }
+            Event::Log(alert) => {
+                let (title, body) = log_text(alert);
+                serde_json::json!({ "title": title, "description": body, "color": color })
+            }
+            Event::Test => {
+                let (title, body) = event.text();
+                serde_json::json!({ "title": title, "description": body, "color": color })
+            }
+            Event::Digest(digest) => {
+                let (title, body) = digest.text();
+                // Discord rejects descriptions over 4096 characters
+                let body: String = body.chars().take(4000).collect();
+                serde_json::json!({ "title": title, "description": body, "color": color })
+            }
+        };
+        Request::post(&self.url)
+            .json(&serde_json::json!({ "username": "WraithSwap", "embeds": [embed] }))?
+            .send_with_retry(WEBHOOK_ATTEMPTS)
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+
+    fn describe(transition: &Transition, color: u32) -> serde_json::Value {
+        let (title, _) = DesktopNotifier::describe(transition);
+        let field = |name: &str, value: String, inline: bool| serde_json::json!({ "name": name, "value": value, "inline": inline });
+        let mut fields = vec![field("Swap", format!("`{}`", transition.swap_id), false)];
+        match transition.stuck_for_secs {
+            Some(secs) => {
+                fields.push(field("State", transition.new_state.to_string(), true));
+                fields.push(field("Stuck for", stuck_duration(secs), true));
+            }
+            None => fields.push(field(
+                "Transition",
+                format!(
+                    "{} → {}",
+                    transition
+                        .old_state
+                        .as_ref()
+                        .map(|s| s.as_str())
+                        .unwrap_or("(new)"),
+                    transition.new_state
+                ),
+                false,
+            )),
+        }
+        if let Some(kind) = transition.anomaly {
+            fields.push(field("Anomaly", kind.explain().to_string(), false));
+        }
+        if let Some(ref peer_id) = transition.peer_id {
+            let watched = if transition.watched { " (watched)" } else { "" };
+            fields.push(field("Peer", format!("`{peer_id}`{watched}"), false));
+        }
+        if let Some(sats) = transition.btc_sats {
+            fields.push(field("BTC", format_btc(sats), true));
+        }
+        if let Some(piconero) = transition.xmr_piconero {
+            fields.push(field("XMR", format_xmr(piconero, 4), true));
+        }
+        fields.push(field(
+            "Network",
+            transition.network.dir_name().to_string(),
+            true,
+        ));
+        if let Some(ref instance) = transition.instance {
+            fields.push(field("Instance", instance.clone(), true));
+        }
+        serde_json::json!({
+            "title": title,
+            "color": color,
+            "fields": fields,
+            "timestamp": transition.observed_at,
+        })
+    }
+}
+
+/// Pushes to the Pushover apps of one user or group. Critical alerts go out
+/// at high priority, which breaks through the phone's quiet hours; routine
+/// ones quietly.
+#[derive(Debug, Clone)]
+pub struct PushoverNotifier {
+    /// Application token
+    pub token: String,
+    /// User or group key
+    pub user: String,
+    pub filter: StateFilter,
+}
+
+impl PushoverNotifier {
+    async fn send(&self, event: &Event, severity: Severity) -> Result<()> {
+        let (title, body) = event.text();
+        let priority = match severity {
+            Severity::Critical => 1,
+            Severity::Warning => 0,
+            Severity::Info => -1,
+        };
+        // Pushover caps messages at 1024 characters
+        let message: String = body.chars().take(1024).collect();
+        Request::post("https://api.pushover.net/1/messages.json")
+            .json(&serde_json::json!({
+                "token": self.token,
+                "user": self.user,
+                "title": title,
+                "message": message,
+                "priority": priority,
+            }))?
+            .send_with_retry(WEBHOOK_ATTEMPTS)
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+}
+
+/// Pushes through a self-hosted Gotify server. Priorities follow the
+/// Android app's levels: silent for routine events, a popup for critical.
+#[derive(Debug, Clone)]
+pub struct GotifyNotifier {
+    pub server: String,
+    /// Application token
+    pub token: String,
+    pub filter: StateFilter,
+}
+
+impl GotifyNotifier {
+    async fn send(&self, event: &Event, severity: Severity) -> Result<()> {
+        let (title, message) = event.text();
+        let priority = match severity {
+            Severity::Critical => 8,
+            Severity::Warning => 5,
+            Severity::Info => 2,
+        };
+        Request::post(format!("{}/message", self.server.trim_end_matches('/')))
+            .header("X-Gotify-Key", &self.token)
+            .json(&serde_json::json!({
+                "title": title,
+                "message": message,
+                "priority": priority,
+            }))?
+            .send_with_retry(WEBHOOK_ATTEMPTS)
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+}
+
+pub const MQTT_DEFAULT_TOPIC: &str = "wraithswap/{network}/{swap_id}/state";
+
+/// Publishes transitions to an MQTT broker, as the same JSON webhooks
+/// receive, for home automation and dashboards. The topic is a template
+/// with `{network}`, `{swap_id}`, `{state}`, `{old_state}` and `{instance}`.
+/// Stuck, liquidity, fee and digest alerts aren't state changes and aren't
+/// published.
+#[derive(Debug, Clone)]
+pub struct MqttNotifier {
+    pub client: MqttClient,
+    pub topic: String,
+    pub qos: QoS,
+    /// Keep the last message per topic on the broker for new subscribers
+    pub retain: bool,
+    pub filter: StateFilter,
+}
+
+impl MqttNotifier {
+    pub fn new(broker: MqttBroker, filter: StateFilter) -> Self {
+        Self {
+            client: MqttClient::new(broker),
+            topic: MQTT_DEFAULT_TOPIC.to_string(),
+            qos: QoS::default(),
+            retain: false,
+            filter,
+        }
+    }
+
+    async fn send(&self, event: &Event, severity: Severity) -> Result<()> {
+        let (topic, payload, retain) = match event {
+            Event::Transition(t) if t.stuck_for_secs.is_none() && t.anomaly.is_none() => {
+                let topic = fill(&self.topic, |key| {
+                    Some(match key {
+                        "network" => t.network.dir_name().to_string(),
+                        "swap_id" => t.swap_id.clone(),
+                        "state" => t.new_state.to_string(),
+                        "old_state" => t
+                            .old_state
+                            .as_ref()
+                            .map(ToString::to_string)
+                            .unwrap_or_default(),
+                        "instance" => t.instance.clone().unwrap_or_default(),
+                        _ => return None,
+                    })
+                });
+                let payload = serde_json::to_vec(&WebhookPayload::new(t, severity))?;
+                (topic, payload, self.retain)
+            }
+            // Every placeholder reads `test`, and nothing is retained
+            Event::Test => {
+                let topic = fill(&self.topic, |key| {
+                    matches!(
+                        key,
+                        "network" | "swap_id" | "state" | "old_state" | "instance"
+                    )
+                    .then(|| "test".to_string())
+                });
+                let payload = serde_json::to_vec(&serde_json::json!({
+                    "event": "test",
+                    "observed_at": observed_now(),
+                    "severity": severity,
+                }))?;
+                (topic, payload, false)
+            }
+            _ => return Ok(()),
+        };
+        self.client
+            .publish(&topic, &payload, self.qos, retain)
+            .await
+    }
+}
+
+pub const OPSGENIE_DEFAULT_API_URL: &str = "https://api.opsgenie.com";
+
+#[derive(Debug, Clone)]
+pub enum IncidentService {
+    /// Events API v2 with an integration's routing key
+    PagerDuty { routing_key: String },
+    /// Alert API with an API integration's key; EU accounts use
+    /// `https://api.eu.opsgenie.com`
+    Opsgenie { api_key: String, api_url: String },
+}
+
+/// Opens incidents in an on-call service for critical alerts, one per swap,
+/// rule and alert so repeats land on the same incident. Incidents opened
+/// for a stuck swap are resolved by the transition that moves it on; the
+/// rest, like a punish, are left for a human to close.
+#[derive(Debug, Clone)]
+pub struct IncidentNotifier {
+    pub service: IncidentService,
+    pub filter: StateFilter,
+    /// Incident keys per swap still waiting to be resolved
+    open: Arc<Mutex<HashMap<String, Vec<String>>>>,
+}
+
+/// `wraithswap/[instance/]swap_id`, the start of a swap's incident keys.
+fn incident_prefix(transition: &Transition) -> String {
+    match transition.instance {
+        Some(ref instance) => format!("wraithswap/{instance}/{}", transition.swap_id),
+        None => format!("wraithswap/{}", transition.swap_id),
+    }
+}
+
+impl IncidentNotifier {
+    pub fn new(service: IncidentService, filter: StateFilter) -> Self {
+        Self {
+            service,
+            filter,
+            open: Arc::default(),
+        }
+    }
+
+    async fn send(&self, event: &Event, severity: Severity, rule: &str) -> Result<()> {
+        let rule = if rule.is_empty() { "default" } else { rule };
+        if let Event::Transition(t) = event {
+            if t.stuck_for_secs.is_none() && t.anomaly.is_none() {
+                let resolved = self.open.lock().unwrap().remove(&swap_key(t));
+                for key in resolved.unwrap_or_default() {
+                    self.resolve(&key, "Swap moved on").await?;
+                }
+            }
+        }
+        if severity < Severity::Critical {
+            return Ok(());
+        }
+
+        let key = match event {
+            Event::Transition(t) if t.stuck_for_secs.is_some() => {
+                let key = format!("{}/{rule}/stuck", incident_prefix(t));
+                let mut open = self.open.lock().unwrap();
+                let keys = open.entry(swap_key(t)).or_default();
+                if !keys.contains(&key) {
+                    keys.push(key.clone());
+                }
+                key
+            }
+            Event::Transition(t) if t.anomaly.is_some() => {
+                format!("{}/{rule}/anomaly/{}", incident_prefix(t), t.new_state)
+            }
+            Event::Transition(t) => format!("{}/{rule}/{}", incident_prefix(t), t.new_state),
+            Event::Liquidity(alert) => match alert {
+                LiquidityAlert::XmrLow { .. } => "wraithswap/liquidity/xmr_low".to_string(),
+                LiquidityAlert::BtcHigh { .. } => "wraithswap/liquidity/btc_high".to_string(),
+            },
+            Event::FeeSpike(_) => "wraithswap/fees/spike".to_string(),
+            Event::AsbDown(_) => "wraithswap/asb/down".to_string(),
+            Event::Unreachable(alert) => format!("wraithswap/reachability/{}", alert.address),
+            Event::Quote(QuoteAlert::Failed { .. }) => "wraithswap/quote/failed".to_string(),
+            Event::Quote(QuoteAlert::Drift { .. }) => "wraithswap/quote/drift".to_string(),
+            Event::Log(alert) => format!("wraithswap/log/{}", alert.rule),
+            // Opened and closed straight away, so nobody has to clean up
+            Event::Test => {
+                let key = "wraithswap/test";
+                let (title, body) = event.text();
+                self.trigger(key, &title, &body).await?;
+                return self.resolve(key, "Test alert").await;
+            }
+            Event::Digest(_) => return Ok(()),
+        };
+        let (title, body) = event.text();
+        self.trigger(&key, &title, &body).await
+    }
+
+    async fn trigger(&self, key: &str, title: &str, body: &str) -> Result<()> {
+        let request = match self.service {
+            IncidentService::PagerDuty { ref routing_key } => Request::post(
+                "https://events.pagerduty.com/v2/enqueue",
+            )
+            .json(&serde_json::json!({
+                "routing_key": routing_key,
+                "event_action": "trigger",
+                "dedup_key": key,
+                "payload": {
+                    "summary": title,
+                    "source": "wraithswap",
+                    "severity": "critical",
+                    "custom_details": { "details": body },
+                },
+            }))?,
+            IncidentService::Opsgenie {
+                ref api_key,
+                ref api_url,
+            } => Request::post(format!("{}/v2/alerts", api_url.trim_end_matches('/')))
+                .header("Authorization", format!("GenieKey {api_key}"))
+                .json(&serde_json::json!({
+                    "message": title,
+                    "alias": key,
+                    "description": body,
+                    "priority": "P1",
+                    "source": "wraithswap",
+                }))?,
+        };
+        request
+            .send_with_retry(WEBHOOK_ATTEMPTS)
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+
+    async fn resolve(&self, key: &str, note: &str) -> Result<()> {
+        let request = match self.service {
+            IncidentService::PagerDuty { ref routing_key } => Request::post(
+                "https://events.pagerduty.com/v2/enqueue",
+            )
+            .json(&serde_json::json!({
+                "routing_key": routing_key,
+                "event_action": "resolve",
+                "dedup_key": key,
+            }))?,
+            IncidentService::Opsgenie {
+                ref api_key,
+                ref api_url,
+            } => Request::post(format!(
+                "{}/v2/alerts/{}/close?identifierType=alias",
+                api_url.trim_end_matches('/'),
+                http::encode_path_segment(key)
+            ))
+            .header("Authorization", format!("GenieKey {api_key}"))
+            .json(&serde_json::json!({ "source": "wraithswap", "note": note }))?,
+        };
+        request
+            .send_with_retry(WEBHOOK_ATTEMPTS)
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+}
+
+pub const EMAIL_DEFAULT_SUBJECT: &str = "[WraithSwap] {title}";
+pub const EMAIL_DEFAULT_BODY: &str = "{body}\n\n{details}";
+
+/// Mails alerts through an SMTP server. Subject and body are templates with
+/// `{placeholders}`, listed on `render`. With a batch window,
+/// alerts below critical are collected and mailed together once it closes.
+#[derive(Debug, Clone)]
+pub struct EmailNotifier {
+    pub server: SmtpServer,
+    pub from: String,
+    pub to: Vec<String>,
+    pub subject: String,
+    pub body: String,
+    pub batch: Option<Duration>,
+    pub filter: StateFilter,
+    /// Rendered `(subject, body)` waiting for the batch window to close
+    pending: Arc<Mutex<Vec<(String, String)>>>,
+}
+
+impl EmailNotifier {
+    pub fn new(server: SmtpServer, from: String, to: Vec<String>, filter: StateFilter) -> Self {
+        Self {
+            server,
+            from,
+            to,
+            subject: EMAIL_DEFAULT_SUBJECT.to_string(),
+            body: EMAIL_DEFAULT_BODY.to_string(),
+            batch: None,
+            filter,
+            pending: Arc::default(),
+        }
+    }
+
+    async fn send(&self, event: &Event, severity: Severity) -> Result<()> {
+        let (subject, body) = self.render(event, severity);
+        // Digests are batches already, and critical alerts can't wait
+        let batch = match self.batch {
+            Some(batch) if severity < Severity::Critical && !matches!(event, Event::Digest(_)) => {
+                batch
+            }
+            _ => return self.mail(&subject, &body).await,
+        };
+
+        let first = {
+            let mut pending = self.pending.lock().unwrap();
+            pending.push((subject, body));
+            pending.len() == 1
+        };
+        // Whoever opened the window sends the batch
+        if !first {
+            return Ok(());
+        }
+        tokio::time::sleep(batch).await;
+        let mut pending = std::mem::take(&mut *self.pending.lock().unwrap());
+        if pending.len() == 1 {
+            let (subject, body) = pending.remove(0);
+            return self.mail(&subject, &body).await;
+        }
+        // Only the title makes sense for a whole batch
+        let title = format!("{} alerts", pending.len());
+        let subject = fill(&self.subject, |key| match key {
+            "title" => Some(title.clone()),
+            _ => Some(String::new()),
+        });
+        let body = pending
+            .iter()
+            .map(|(subject, body)| format!("{subject}\n\n{body}"))
+            .collect::<Vec<_>>()
+            .join("\n\n---\n\n");
+        self.mail(&subject, &body).await
+    }
+
+    async fn mail(&self, subject: &str, body: &str) -> Result<()> {
+        self.server
+            .send(&Message {
+                from: &self.from,
+                to: &self.to,
+                subject,
+                body,
+            })
+            .await
+    }
+
+    /// Fill in the templates. `{title}` and `{body}` are the same text the
+    /// other backends send, `{details}` lists the swap's id, network and
+    /// entry time, and `{severity}`, `{swap_id}`, `{old_state}`,
+    /// `{new_state}`, `{entered_at}`, `{network}` and `{instance}` are
+    /// available on their own. Swap fields are empty for liquidity, fee,
+    /// ASB, reachability, quote and log alerts and digests.
+    fn render(&self, event: &Event, severity: Severity) -> (String, String) {
+        let (title, body) = event.text();
+        let transition = match event {
+            Event::Transition(transition) => Some(transition),
+            _ => None,
+        };
+        let value = |key: &str| {
+            let t = transition;
+            Some(match key {
+                "title" => title.clone(),
+                "body" => body.clone(),
+                "severity" => match severity {
+                    Severity::Critical => "critical",
+                    Severity::Warning => "warning",
+                    Severity::Info => "info",
+                }
+                .to_string(),
+                "details" => t.map_or(String::new(), |t| {
+                    format!(
+                        "Swap: {}\nNetwork: {}\nEntered at: {}",
+                        t.swap_id,
+                        t.network.dir_name(),
+                        t.entered_at
+                    )
+                }),
+                "swap_id" => t.map_or(String::new(), |t| t.swap_id.clone()),
+                "old_state" => t
+                    .and_then(|t| t.old_state.as_ref())
+                    .map(ToString::to_string)
+                    .unwrap_or_default(),
+                "new_state" => t.map_or(String::new(), |t| t.new_state.to_string()),
+                "entered_at" => t.map_or(String::new(), |t| t.entered_at.clone()),
+                "network" => t.map_or(String::new(), |t| t.network.dir_name().to_string()),
+                "instance" => t.and_then(|t| t.instance.clone()).unwrap_or_default(),
+                _ => return None,
+            })
+        };
+        (
+            fill(&self.subject, value),
+            fill(&self.body, value).trim_end().to_string(),
+        )
+    }
+}
+
+/// Replace each `{key}` in `template` for which `value` has something;
+/// unknown ones are left alone.
+fn fill(template: &str, value: impl Fn(&str) -> Option<String>) -> String {
+    let mut text = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        text.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        match after
+            .find('}')
+            .and_then(|end| Some((end, value(&after[..end])?)))
+        {
+            Some((end, value)) => {
+                text.push_str(&value);
+                rest = &after[end + 1..];
+            }
+            None => {
+                text.push('{');
+                rest = after;
+            }
+        }
+    }
+    text.push_str(rest);
+    text
+}
+
+/// Makes Matrix transaction ids unique within the same millisecond
+static MATRIX_TXN: AtomicU64 = AtomicU64::new(0);
+
+/// Posts to a Matrix room as a bot user. Routine events go out as plain
+/// notices; warnings and critical alerts as highlighted messages, which
+/// clients treat as worth a ping.
+#[derive(Debug, Clone)]
+pub struct MatrixNotifier {
+    pub homeserver: String,
+    pub access_token: String,
+    pub room_id: String,
+    pub filter: StateFilter,
+}
+
+impl MatrixNotifier {
+    async fn send(&self, event: &Event, severity: Severity) -> Result<()> {
+        let (title, body) = event.text();
+        let plain = format!("{title}\n{body}");
+        let message = match severity {
+            Severity::Info => serde_json::json!({ "msgtype": "m.notice", "body": plain }),
+            Severity::Warning | Severity::Critical => {
+                let (icon, color) = match severity {
+                    Severity::Critical => ("🚨", "#d32f2f"),
+                    _ => ("⚠️", "#f57c00"),
+                };
+                let html = format!(
+                    "<strong><font color=\"{color}\" data-mx-color=\"{color}\">{icon} {}</font></strong><br>{}",
+                    html_escape(&title),
+                    html_escape(&body).replace('\n', "<br>")
+                );
+                serde_json::json!({
+                    "msgtype": "m.text",
+                    "body": format!("{icon} {plain}"),
+                    "format": "org.matrix.custom.html",
+                    "formatted_body": html,
+                })
+            }
+        };
+
+        // The transaction id makes retries of the same message idempotent
+        let txn = format!(
+            "wraithswap-{}-{}",
+            chrono::Utc::now().timestamp_millis(),
+            MATRIX_TXN.fetch_add(1, Ordering::Relaxed)
+        );
+        let url = format!(
+            "{}/_matrix/client/v3/rooms/{}/send/m.room.message/{txn}",
+            self.homeserver.trim_end_matches('/'),
+            http::encode_path_segment(&self.room_id)
+        );
+        Request::new("PUT", url)
+            .header("Authorization", format!("Bearer {}", self.access_token))
+            .json(&message)?
+            .send_with_retry(WEBHOOK_ATTEMPTS)
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+}
+
+fn html_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Title and body shared by the human-readable backends.
+fn liquidity_text(alert: &LiquidityAlert) -> (&'static str, String) {
+    match *alert {
+        LiquidityAlert::XmrLow {
+            unlocked_piconero,
+            threshold_piconero,
+        } => (
+            "XMR liquidity low",
+            format!(
+                "Unlocked XMR {} is below {}; new swaps may fail to lock",
+                format_xmr(unlocked_piconero, 4),
+                format_xmr(threshold_piconero, 4)
+            ),
+        ),
+        LiquidityAlert::BtcHigh {
+            balance_sats,
+            threshold_sats,
+        } => (
+            "BTC piling up",
+            format!(
+                "BTC wallet holds {}, above {}",
+                format_btc(balance_sats),
+                format_btc(threshold_sats)
+            ),
+        ),
+    }
+}
+
+fn fee_spike_text(spike: &FeeSpike) -> (String, String) {
+    (
+        "BTC fees spiking".to_string(),
+        format!(
+            "Next-block fee rate {} is above {}, with {} waiting in the mempool; \
+             redeems and refunds cost more right now",
+            format_fee_rate(spike.fastest_sat_vb),
+            format_fee_rate(spike.threshold_sat_vb),
+            format_vsize(spike.mempool_vsize)
+        ),
+    )
+}
+
+fn asb_down_text(alert: &AsbDown) -> (String, String) {
+    let swaps = match alert.in_flight {
+        1 => "1 swap".to_string(),
+        n => format!("{n} swaps"),
+    };
+    (
+        "ASB is not running".to_string(),
+        format!(
+            "{} with {swaps} in flight; they can't move on until it is back",
+            alert.reason
+        ),
+    )
+}
+
+fn unreachable_text(alert: &Unreachable) -> (String, String) {
+    let via = if alert.via_tor { " through Tor" } else { "" };
+    (
+        "ASB unreachable".to_string(),
+        format!(
+            "Couldn't connect to {}{via}: {}; takers can't swap with it there",
+            alert.address, alert.reason
+        ),
+    )
+}
+
+fn quote_text(alert: &QuoteAlert) -> (String, String) {
+    match alert {
+        QuoteAlert::Failed { reason } => (
+            "ASB gave no quote".to_string(),
+            format!("Asking for a quote like a taker failed: {reason}; takers likely can't either"),
+        ),
+        QuoteAlert::Drift {
+            quoted,
+            expected,
+            drift,
+            tolerance,
+        } => (
+            "ASB quote off the market".to_string(),
+            format!(
+                "Quoted {quoted:.8} BTC/XMR, {:+.2}% from the {expected:.8} market rate plus spread \
+                 would give (tolerance {:.2}%)",
+                drift * 100.0,
+                tolerance * 100.0
+            ),
+        ),
+    }
+}
+
+fn log_text(alert: &LogAlert) -> (String, String) {
+    let mut body = format!("{} {}", alert.level, alert.message);
+    if alert.held_back > 0 {
+        body.push_str(&format!(" ({} more since the last alert)", alert.held_back));
+    }
+    (format!("ASB log: {}", alert.rule), body.trim().to_string())
+}
+
+/// One line for the digest's list of held alerts.
+fn summary(event: &Event) -> String {
+    let (title, body) = event.text();
+    format!("{title} ({body})")
+}
+
+/// Same format as `Transition::observed_at`.
+fn observed_now() -> String {
+    chrono::Utc::now().to_rfc3339()
+}
+
+fn stuck_duration(secs: u64) -> String {
+    format_duration(chrono::Duration::seconds(secs as i64))
+}
+
+// Only the D-Bus notifications carry an urgency
+#[cfg(all(test, unix, not(target_os = "macos")))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn desktop_urgency_follows_severity() {
+        use notify_rust::{Hint, Urgency};
+
+        let notification =
+            DesktopNotifier::notification("Swap 1234", "BtcPunished", Severity::Critical);
+        assert_eq!(
+            (notification.appname.as_str(), notification.summary.as_str()),
+            ("WraithSwap", "Swap 1234")
+        );
+        assert!(notification
+            .hints
+            .contains(&Hint::Urgency(Urgency::Critical)));
+        let notification = DesktopNotifier::notification("Swap 1234", "BtcLocked", Severity::Info);
+        assert!(notification.hints.contains(&Hint::Urgency(Urgency::Normal)));
+    }
+}
@@ -0,0 +1,295 @@
+//! OpenTelemetry traces of the monitor itself (`--otlp-endpoint`): polls,
+//! database queries and notification deliveries become spans, exported
+//! over OTLP/HTTP in its JSON encoding so slow queries and notifier
+//! timeouts show up in an existing observability stack.
+//!
+//! `tracing-opentelemetry` turns spans into OpenTelemetry ones, as a layer
+//! next to the log output, and `opentelemetry-otlp` sends them. Only our own
+//! spans are recorded, including the library crate's. Events logged inside
+//! a span are attached to it, and an error event or an `otel.status_code`
+//! of `error` marks the span as failed. Finished spans go out in batches
+//! every few seconds from a thread of the SDK's; if the collector is
+//! unreachable that's logged once, and spans queue up to a limit beyond
+//! which the newest are dropped.
+//!
+//! The standard `OTEL_EXPORTER_OTLP_ENDPOINT`, `OTEL_EXPORTER_OTLP_HEADERS`
+//! and `OTEL_SERVICE_NAME` variables are honored.
+
+use anyhow::{anyhow, Context, Result};
+use opentelemetry::trace::TracerProvider as _;
+use opentelemetry::KeyValue;
+use opentelemetry_otlp::{Protocol, WithExportConfig, WithHttpConfig};
+use opentelemetry_sdk::error::OTelSdkResult;
+use opentelemetry_sdk::trace::{
+    BatchConfigBuilder, BatchSpanProcessor, SdkTracerProvider, SpanData, SpanExporter,
+};
+use opentelemetry_sdk::Resource;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use tracing::{Level, Subscriber};
+use tracing_subscriber::filter::Targets;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::{Layer, Registry};
+
+use crate::http;
+
+pub const ENDPOINT_ENV: &str = "OTEL_EXPORTER_OTLP_ENDPOINT";
+pub const HEADERS_ENV: &str = "OTEL_EXPORTER_OTLP_HEADERS";
+pub const SERVICE_NAME_ENV: &str = "OTEL_SERVICE_NAME";
+
+const EXPORT_INTERVAL: Duration = Duration::from_secs(5);
+/// Finished spans kept while the collector can't be reached
+const MAX_PENDING: usize = 4096;
+
+/// Where spans go: the collector's base URL, `/v1/traces` being added
+/// unless it's already there.
+#[derive(Debug, Clone)]
+pub struct Exporter {
+    pub endpoint: String,
+    pub headers: Vec<(String, String)>,
+    pub service_name: String,
+}
+
+impl Exporter {
+    fn url(&self) -> String {
+        let endpoint = self.endpoint.trim_end_matches('/');
+        if endpoint.ends_with("/v1/traces") {
+            endpoint.to_string()
+        } else {
+            format!("{endpoint}/v1/traces")
+        }
+    }
+}
+
+/// Exports spans in the background. Cheap to clone; clones share the queue.
+#[derive(Clone)]
+pub struct Tracer {
+    provider: SdkTracerProvider,
+    endpoint: String,
+}
+
+impl fmt::Debug for Tracer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Tracer")
+            .field("endpoint", &http::redact(&self.endpoint))
+            .finish_non_exhaustive()
+    }
+}
+
+impl Tracer {
+    pub fn new(exporter: Exporter) -> Result<Self> {
+        let otlp = opentelemetry_otlp::SpanExporter::builder()
+            .with_http()
+            .with_protocol(Protocol::HttpJson)
+            .with_endpoint(exporter.url())
+            .with_headers(exporter.headers.into_iter().collect())
+            .with_timeout(http::DEFAULT_TIMEOUT)
+            .build()
+            .context("set up the OTLP exporter")?;
+        let spans = Reporting {
+            otlp,
+            endpoint: http::redact(&exporter.endpoint),
+            failing: AtomicBool::new(false),
+        };
+        let batches = BatchConfigBuilder::default()
+            .with_scheduled_delay(EXPORT_INTERVAL)
+            .with_max_queue_size(MAX_PENDING)
+            .build();
+        let resource = Resource::builder_empty()
+            .with_service_name(exporter.service_name)
+            .with_attribute(KeyValue::new("service.version", env!("CARGO_PKG_VERSION")))
+            .build();
+        let provider = SdkTracerProvider::builder()
+            .with_span_processor(
+                BatchSpanProcessor::builder(spans)
+                    .with_batch_config(batches)
+                    .build(),
+            )
+            .with_resource(resource)
+            .build();
+        Ok(Tracer {
+            provider,
+            endpoint: exporter.endpoint,
+        })
+    }
+
+    /// The layer recording our own spans from INFO up for export.
+    pub fn layer<S>(&self) -> impl Layer<S>
+    where
+        S: Subscriber + for<'a> LookupSpan<'a>,
+    {
+        let tracer = self.provider.tracer(env!("CARGO_CRATE_NAME"));
+        let ours = Targets::new()
+            .with_target(env!("CARGO_CRATE_NAME"), Level::INFO)
+            .with_target("wraithswap_core", Level::INFO);
+        tracing_opentelemetry::layer()
+            .with_tracer(tracer)
+            .with_filter(ours)
+    }
+
+    /// Send every finished span now, e.g. before exiting.
+    pub async fn flush(&self) {
+        let provider = self.provider.clone();
+        // The SDK's thread does the sending; wait for it off the runtime.
+        // A failure has been logged by then.
+        let _ = tokio::task::spawn_blocking(move || provider.force_flush()).await;
+    }
+}
+
+/// The OTLP exporter, logging when exports start failing, not every time.
+#[derive(Debug)]
+struct Reporting {
+    otlp: opentelemetry_otlp::SpanExporter,
+    endpoint: String,
+    failing: AtomicBool,
+}
+
+impl SpanExporter for Reporting {
+    async fn export(&self, batch: Vec<SpanData>) -> OTelSdkResult {
+        let result = self.otlp.export(batch).await;
+        match result {
+            Ok(()) => self.failing.store(false, Ordering::Relaxed),
+            Err(ref err) if !self.failing.swap(true, Ordering::Relaxed) => {
+                tracing::warn!(endpoint = self.endpoint, error = %err, "trace export failed");
+            }
+            Err(_) => {}
+        }
+        result
+    }
+
+    fn set_resource(&mut self, resource: &Resource) {
+        self.otlp.set_resource(resource);
+    }
+}
+
+/// Install the tracer alone as the global subscriber, for when nothing is
+/// logged.
+pub fn install(tracer: &Tracer) -> Result<()> {
+    let subscriber = Registry::default().with(tracer.layer());
+    tracing::subscriber::set_global_default(subscriber).context("install tracer")
+}
+
+/// `key=value` pairs separated by commas, values URL-encoded, as in
+/// `OTEL_EXPORTER_OTLP_HEADERS`.
+pub fn parse_headers(value: &str) -> Result<Vec<(String, String)>> {
+    value
+        .split(',')
+        .filter(|pair| !pair.trim().is_empty())
+        .map(|pair| {
+            let (name, value) = pair
+                .split_once('=')
+                .ok_or_else(|| anyhow!("OTLP header must be KEY=VALUE, got {pair:?}"))?;
+            Ok((name.trim().to_string(), percent_decode(value.trim())))
+        })
+        .collect()
+}
+
+fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .and_then(|h| std::str::from_utf8(h).ok())
+            .and_then(|h| u8::from_str_radix(h, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::TcpListener;
+
+    /// A collector on localhost that takes one export and hands it back.
+    async fn collector() -> (String, tokio::task::JoinHandle<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let handle = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut stream = BufReader::new(stream);
+            let mut request = String::new();
+            let mut length = 0;
+            loop {
+                let mut line = String::new();
+                stream.read_line(&mut line).await.unwrap();
+                if let Some((name, value)) = line.split_once(':') {
+                    if name.eq_ignore_ascii_case("content-length") {
+                        length = value.trim().parse().unwrap();
+                    }
+                }
+                request.push_str(&line);
+                if line == "\r\n" {
+                    break;
+                }
+            }
+            let mut body = vec![0; length];
+            stream.read_exact(&mut body).await.unwrap();
+            request.push_str(&String::from_utf8(body).unwrap());
+            let reply = "HTTP/1.1 200 OK\r\nContent-Length: 0\r\nConnection: close\r\n\r\n";
+            stream.write_all(reply.as_bytes()).await.unwrap();
+            request
+        });
+        (url, handle)
+    }
+
+    #[tokio::test]
+    async fn our_spans_reach_the_collector() {
+        let (url, collector) = collector().await;
+        let tracer = Tracer::new(Exporter {
+            endpoint: format!("{url}/"),
+            headers: vec![("x-api-key".into(), "secret".into())],
+            service_name: "asb-monitor".into(),
+        })
+        .unwrap();
+        let subscriber = Registry::default().with(tracer.layer());
+        tracing::subscriber::with_default(subscriber, || {
+            let poll = tracing::info_span!(
+                "poll",
+                rows = 3,
+                error = tracing::field::Empty,
+                otel.status_code = tracing::field::Empty
+            );
+            poll.record("error", "database is locked")
+                .record("otel.status_code", "error");
+            poll.in_scope(|| tracing::info_span!("fetch_swaps").in_scope(|| {}));
+            tracing::info_span!(target: "sqlx::query", "query").in_scope(|| {});
+        });
+        tracer.flush().await;
+
+        let request = collector.await.unwrap();
+        assert!(request.starts_with("POST /v1/traces "), "{request}");
+        assert!(request.to_lowercase().contains("x-api-key: secret"));
+        let body: serde_json::Value =
+            serde_json::from_str(&request[request.find("\r\n\r\n").unwrap() + 4..]).unwrap();
+        let resource = &body["resourceSpans"][0];
+        assert!(resource["resource"].to_string().contains("asb-monitor"));
+        let spans = resource["scopeSpans"][0]["spans"].as_array().unwrap();
+        let name = |span: &serde_json::Value| span["name"].as_str().unwrap().to_string();
+        let mut names: Vec<_> = spans.iter().map(name).collect();
+        names.sort();
+        assert_eq!(names, ["fetch_swaps", "poll"]);
+        let poll = spans.iter().find(|span| name(span) == "poll").unwrap();
+        let fetch = spans
+            .iter()
+            .find(|span| name(span) == "fetch_swaps")
+            .unwrap();
+        assert_eq!(fetch["parentSpanId"], poll["spanId"]);
+        assert_eq!(fetch["traceId"], poll["traceId"]);
+        assert_eq!(poll["status"]["code"], 2, "{poll}");
+    }
+}
@@ -0,0 +1,194 @@
+//! Parquet output for `export --format parquet`.
+//!
+//! The rows become one Arrow record batch, which the `parquet` crate writes
+//! as a single uncompressed row group. Every column is nullable so missing
+//! fields become nulls.
+
+use anyhow::Result;
+use arrow_array::{
+    ArrayRef, Int32Array, Int64Array, RecordBatch, StringArray, TimestampMillisecondArray,
+};
+use arrow_schema::{Field, Schema};
+use parquet::arrow::ArrowWriter;
+use parquet::file::properties::WriterProperties;
+use std::sync::Arc;
+
+use crate::csv::Column;
+use crate::model::{parse_entered_at, SwapRow};
+
+/// One column's values, nulls where a row has none.
+fn column_array(column: &Column, rows: &[&SwapRow]) -> ArrayRef {
+    let timestamps = |raw: fn(&SwapRow) -> &str| {
+        let millis = rows
+            .iter()
+            .map(|r| parse_entered_at(raw(r)).map(|t| t.timestamp_millis()));
+        Arc::new(TimestampMillisecondArray::from_iter(millis).with_timezone("UTC")) as ArrayRef
+    };
+    match column {
+        Column::SwapId => Arc::new(StringArray::from_iter_values(
+            rows.iter().map(|r| &r.swap_id),
+        )),
+        Column::State => Arc::new(StringArray::from_iter_values(
+            rows.iter().map(|r| r.state.as_str()),
+        )),
+        Column::PeerId => Arc::new(StringArray::from_iter(
+            rows.iter().map(|r| r.peer_id.as_deref()),
+        )),
+        Column::Txid(label) => Arc::new(StringArray::from_iter(
+            rows.iter().map(|r| r.details.txids.get(label)),
+        )),
+        Column::EnteredAt => timestamps(|r| &r.entered_at),
+        Column::StartedAt => timestamps(|r| &r.started_at),
+        Column::BtcSats => Arc::new(Int64Array::from_iter(
+            rows.iter().map(|r| r.details.btc_sats.map(|v| v as i64)),
+        )),
+        Column::XmrPiconero => Arc::new(Int64Array::from_iter(
+            rows.iter()
+                .map(|r| r.details.xmr_piconero.map(|v| v as i64)),
+        )),
+        Column::CancelTimelock => Arc::new(Int32Array::from_iter(
+            rows.iter()
+                .map(|r| r.details.cancel_timelock.map(|v| v as i32)),
+        )),
+        Column::PunishTimelock => Arc::new(Int32Array::from_iter(
+            rows.iter()
+                .map(|r| r.details.punish_timelock.map(|v| v as i32)),
+        )),
+    }
+}
+
+/// A whole Parquet file. Rows tagged with an instance name get a leading
+/// `instance` column, as in the CSV export.
+pub fn write(rows: &[(Option<&str>, SwapRow)], columns: &[Column]) -> Result<Vec<u8>> {
+    let swap_rows: Vec<&SwapRow> = rows.iter().map(|(_, row)| row).collect();
+    let mut named: Vec<(&str, ArrayRef)> = Vec::new();
+    if rows.iter().any(|(instance, _)| instance.is_some()) {
+        let instances = StringArray::from_iter(rows.iter().map(|(instance, _)| *instance));
+        named.push(("instance", Arc::new(instances)));
+    }
+    for column in columns {
+        named.push((column.name(), column_array(column, &swap_rows)));
+    }
+
+    let fields: Vec<Field> = named
+        .iter()
+        .map(|(name, array)| Field::new(*name, array.data_type().clone(), true))
+        .collect();
+    let arrays = named.into_iter().map(|(_, array)| array).collect();
+    let batch = RecordBatch::try_new(Arc::new(Schema::new(fields)), arrays)?;
+
+    let properties = WriterProperties::builder()
+        .set_created_by(concat!("wraithswap-poc ", env!("CARGO_PKG_VERSION")).into())
+        .build();
+    let mut writer = ArrowWriter::try_new(Vec::new(), batch.schema(), Some(properties))?;
+    // An empty export has no row group at all
+    if batch.num_rows() > 0 {
+        writer.write(&batch)?;
+    }
+    Ok(writer.into_inner()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow_array::Array;
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+
+    fn row(swap_id: &str, btc_sats: Option<u64>) -> SwapRow {
+        let mut row = SwapRow {
+            swap_id: swap_id.to_string(),
+            state: "BtcRedeemed".into(),
+            entered_at: "2026-01-01 12:00:00".to_string(),
+            started_at: "2026-01-01 11:00:00 +01:00".to_string(),
+            peer_id: None,
+            details: Default::default(),
+        };
+        row.details.btc_sats = btc_sats;
+        row.details
+            .txids
+            .insert("btc_lock".to_string(), format!("{swap_id}-lock"));
+        row
+    }
+
+    fn read(file: Vec<u8>) -> Vec<RecordBatch> {
+        ParquetRecordBatchReaderBuilder::try_new(bytes::Bytes::from(file))
+            .unwrap()
+            .build()
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap()
+    }
+
+    fn columns(names: &[&str]) -> Vec<Column> {
+        names.iter().map(|name| name.parse().unwrap()).collect()
+    }
+
+    #[test]
+    fn rows_read_back() {
+        let rows = [(None, row("a", Some(50_000))), (None, row("b", None))];
+        let columns = columns(&["swap_id", "state", "entered_at", "started_at", "btc_sats"]);
+        let batches = read(write(&rows, &columns).unwrap());
+        assert_eq!(batches.len(), 1);
+        let batch = &batches[0];
+        let names: Vec<&str> = batch
+            .schema_ref()
+            .fields()
+            .iter()
+            .map(|f| f.name().as_str())
+            .collect();
+        assert_eq!(
+            names,
+            ["swap_id", "state", "entered_at", "started_at", "btc_sats"]
+        );
+
+        let text = |i: usize| {
+            batch
+                .column(i)
+                .as_any()
+                .downcast_ref::<StringArray>()
+                .unwrap()
+        };
+        assert_eq!(text(0).value(1), "b");
+        assert_eq!(text(1).value(0), "BtcRedeemed");
+
+        let time = |i: usize| {
+            let column = batch.column(i).as_any();
+            column.downcast_ref::<TimestampMillisecondArray>().unwrap()
+        };
+        assert_eq!(time(2).value(0), 1_767_268_800_000);
+        // Local times are stored in UTC
+        assert_eq!(time(3).value(0), 1_767_261_600_000);
+
+        let sats = batch
+            .column(4)
+            .as_any()
+            .downcast_ref::<Int64Array>()
+            .unwrap();
+        assert_eq!((sats.value(0), sats.is_null(1)), (50_000, true));
+    }
+
+    #[test]
+    fn instances_get_a_leading_column() {
+        let rows = [
+            (Some("main"), row("a", None)),
+            (Some("backup"), row("b", None)),
+        ];
+        let batches = read(write(&rows, &columns(&["swap_id", "btc_lock"])).unwrap());
+        let batch = &batches[0];
+        assert_eq!(batch.schema_ref().field(0).name(), "instance");
+        let lock = batch
+            .column(2)
+            .as_any()
+            .downcast_ref::<StringArray>()
+            .unwrap();
+        assert_eq!(lock.value(1), "b-lock");
+    }
+
+    #[test]
+    fn empty_exports_keep_their_schema() {
+        let file = write(&[], &columns(&["swap_id", "btc_sats"])).unwrap();
+        let builder = ParquetRecordBatchReaderBuilder::try_new(bytes::Bytes::from(file)).unwrap();
+        assert_eq!(builder.metadata().num_row_groups(), 0);
+        assert_eq!(builder.schema().fields().len(), 2);
+    }
+}
@@ -0,0 +1,87 @@
+//! Regular expressions for log alert rules, in the `regex` crate's syntax:
+//! classes, groups with `|`, the usual quantifiers, anchors and flags such
+//! as a leading `(?i)` to ignore case. Matching takes time linear in the
+//! line whatever the pattern, so a rule can't stall the loop that polls
+//! ASB's log.
+
+use anyhow::{Context, Result};
+use regex::{Regex, RegexBuilder};
+use std::fmt;
+use std::str::FromStr;
+
+/// Compiled size a pattern may reach; `x{1000}{1000}` and the like are
+/// refused rather than built
+const SIZE_LIMIT: usize = 1 << 20;
+
+#[derive(Clone)]
+pub struct Pattern(Regex);
+
+impl Pattern {
+    /// Whether the pattern matches anywhere in `text`.
+    pub fn is_match(&self, text: &str) -> bool {
+        self.0.is_match(text)
+    }
+}
+
+impl FromStr for Pattern {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        RegexBuilder::new(s)
+            .size_limit(SIZE_LIMIT)
+            .build()
+            .map(Self)
+            .with_context(|| format!("invalid pattern {s:?}"))
+    }
+}
+
+impl fmt::Display for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.0.as_str())
+    }
+}
+
+impl fmt::Debug for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Pattern({:?})", self.0.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, Instant};
+
+    fn pattern(s: &str) -> Pattern {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn lines_match_anywhere() {
+        let tor = pattern(r"(?i)\btor\b.*(fail|error)");
+        assert!(tor.is_match("WARN Tor bootstrap failed"));
+        assert!(tor.is_match("ERROR connecting over tor: error 5"));
+        assert!(!tor.is_match("WARN monitor failed"));
+        assert!(!tor.is_match("INFO tor ready"));
+
+        let anchored = pattern(r"^ERROR \d+ swaps? [a-z]+$");
+        assert!(anchored.is_match("ERROR 2 swaps stuck"));
+        assert!(!anchored.is_match("WARN ERROR 2 swaps stuck"));
+        assert!(!anchored.is_match("ERROR 2 swaps stuck!"));
+
+        for bad in ["(unclosed", "[z-a]", "*", r"\q", "x{1000}{1000}"] {
+            assert!(bad.parse::<Pattern>().is_err(), "{bad}");
+        }
+    }
+
+    #[test]
+    fn pathological_patterns_stay_fast() {
+        let line = "a".repeat(100_000);
+        let started = Instant::now();
+        for s in [r"(a|aa)*c", r"(.*)*x", r"(a|b)*$", r"(a*)*b", r"(a+)+$"] {
+            let matches = s != r"(a|b)*$" && s != r"(a+)+$";
+            assert_eq!(pattern(s).is_match(&line), !matches, "{s}");
+        }
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+}
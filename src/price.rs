@@ -0,0 +1,283 @@
+//! The XMR/BTC market rate, from Kraken, Binance or a self-hosted price
+//! server (`--price-source`), now and at the time past swaps were agreed.
+//!
+//! Exchanges are asked for hourly candles and a swap is compared against
+//! the candle it started in. A price server answers `GET <URL>` with
+//! `{"price": <BTC per XMR>}`, `GET <URL>?at=<unix seconds>` with the
+//! price at that time, or `null` when it doesn't know, and
+//! `GET <URL>?currency=EUR` with the price of one BTC in that currency.
+
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use serde_json::Value;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::http::Request;
+
+const KRAKEN_API: &str = "https://api.kraken.com/0/public";
+const BINANCE_API: &str = "https://api.binance.com/api/v3";
+
+/// Candle length, in seconds, for the rate at a point in time
+const CANDLE_SECS: i64 = 3600;
+
+/// Most candles Binance returns per request
+const BINANCE_LIMIT: usize = 1000;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PriceSource {
+    /// The `XMRXBT` pair; hourly history only reaches back 30 days
+    Kraken,
+    /// The `XMRBTC` pair
+    Binance,
+    /// A self-hosted price server
+    Url(String),
+}
+
+impl FromStr for PriceSource {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "kraken" => Ok(Self::Kraken),
+            "binance" => Ok(Self::Binance),
+            url if url.starts_with("http://") || url.starts_with("https://") => {
+                Ok(Self::Url(url.to_string()))
+            }
+            other => bail!("expected kraken, binance or an http(s) URL, got {other:?}"),
+        }
+    }
+}
+
+impl fmt::Display for PriceSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Kraken => f.write_str("kraken"),
+            Self::Binance => f.write_str("binance"),
+            Self::Url(_) => f.write_str("price server"),
+        }
+    }
+}
+
+/// One hourly candle: when it opened and its volume-weighted price.
+#[derive(Debug, Clone, Copy)]
+struct Candle {
+    open: i64,
+    rate: f64,
+}
+
+#[derive(Deserialize)]
+struct ServerPrice {
+    price: Option<f64>,
+}
+
+impl PriceSource {
+    /// BTC per XMR right now.
+    pub async fn current(&self) -> Result<f64> {
+        let rate = match self {
+            Self::Kraken => {
+                let result = kraken(&format!("{KRAKEN_API}/Ticker?pair=XMRXBT")).await?;
+                // Last trade: [price, volume]
+                let last = pair_data(&result)?
+                    .pointer("/c/0")
+                    .and_then(Value::as_str)
+                    .context("Kraken ticker without a last trade")?;
+                parse_rate(last)?
+            }
+            Self::Binance => {
+                let ticker: Value =
+                    get(&format!("{BINANCE_API}/ticker/price?symbol=XMRBTC")).await?;
+                let price = ticker
+                    .get("price")
+                    .and_then(Value::as_str)
+                    .context("Binance ticker without a price")?;
+                parse_rate(price)?
+            }
+            Self::Url(url) => {
+                let price: ServerPrice = get(url).await?;
+                price
+                    .price
+                    .context("the price server has no current price")?
+            }
+        };
+        Ok(rate)
+    }
+
+    /// What one BTC costs in `currency`, an ISO code like `EUR`.
+    pub async fn btc_price(&self, currency: &str) -> Result<f64> {
+        match self {
+            Self::Kraken => {
+                let result = kraken(&format!("{KRAKEN_API}/Ticker?pair=XBT{currency}")).await?;
+                let last = pair_data(&result)?
+                    .pointer("/c/0")
+                    .and_then(Value::as_str)
+                    .context("Kraken ticker without a last trade")?;
+                parse_rate(last)
+            }
+            Self::Binance => {
+                // Binance quotes dollars in USDT
+                let quote = match currency {
+                    "USD" => "USDT",
+                    other => other,
+                };
+                let ticker: Value =
+                    get(&format!("{BINANCE_API}/ticker/price?symbol=BTC{quote}")).await?;
+                let price = ticker
+                    .get("price")
+                    .and_then(Value::as_str)
+                    .context("Binance ticker without a price")?;
+                parse_rate(price)
+            }
+            Self::Url(url) => {
+                let separator = if url.contains('?') { '&' } else { '?' };
+                let price: ServerPrice =
+                    get(&format!("{url}{separator}currency={currency}")).await?;
+                price
+                    .price
+                    .with_context(|| format!("the price server has no {currency} price"))
+            }
+        }
+    }
+
+    /// The rate at each of `times`, `None` where the source has no data.
+    pub async fn rates_at(&self, times: &[DateTime<Utc>]) -> Result<Vec<Option<f64>>> {
+        match self {
+            Self::Kraken => {
+                let Some(earliest) = times.iter().min() else {
+                    return Ok(Vec::new());
+                };
+                let candles = kraken_candles(earliest.timestamp() - CANDLE_SECS).await?;
+                Ok(times.iter().map(|t| candle_at(&candles, *t)).collect())
+            }
+            Self::Binance => {
+                let mut sorted = times.to_vec();
+                sorted.sort();
+                // Each request covers the next thousand hours, so swaps
+                // close together share one
+                let mut candles = Vec::new();
+                for at in sorted {
+                    if candle_at(&candles, at).is_none() {
+                        candles.extend(binance_candles(at).await?);
+                    }
+                }
+                Ok(times.iter().map(|t| candle_at(&candles, *t)).collect())
+            }
+            Self::Url(url) => {
+                let separator = if url.contains('?') { '&' } else { '?' };
+                let mut rates = Vec::with_capacity(times.len());
+                for at in times {
+                    let price: ServerPrice =
+                        get(&format!("{url}{separator}at={}", at.timestamp())).await?;
+                    rates.push(price.price);
+                }
+                Ok(rates)
+            }
+        }
+    }
+}
+
+/// The candle `at` falls in.
+fn candle_at(candles: &[Candle], at: DateTime<Utc>) -> Option<f64> {
+    let at = at.timestamp();
+    candles
+        .iter()
+        .find(|c| c.open <= at && at < c.open + CANDLE_SECS)
+        .map(|c| c.rate)
+}
+
+async fn kraken_candles(since: i64) -> Result<Vec<Candle>> {
+    let result = kraken(&format!(
+        "{KRAKEN_API}/OHLC?pair=XMRXBT&interval={}&since={since}",
+        CANDLE_SECS / 60
+    ))
+    .await?;
+    let rows = pair_data(&result)?
+        .as_array()
+        .context("Kraken OHLC without candles")?;
+    // [time, open, high, low, close, vwap, volume, count]
+    rows.iter()
+        .map(|row| {
+            let open = row.get(0).and_then(Value::as_i64);
+            let close = row.get(4).and_then(Value::as_str);
+            let vwap = row.get(5).and_then(Value::as_str);
+            let (Some(open), Some(close), Some(vwap)) = (open, close, vwap) else {
+                bail!("unexpected Kraken candle: {row}");
+            };
+            // A candle without trades has a zero vwap
+            let vwap = parse_rate(vwap)?;
+            let rate = if vwap > 0.0 { vwap } else { parse_rate(close)? };
+            Ok(Candle { open, rate })
+        })
+        .collect()
+}
+
+async fn binance_candles(from: DateTime<Utc>) -> Result<Vec<Candle>> {
+    let start = from.timestamp() - from.timestamp().rem_euclid(CANDLE_SECS);
+    let rows: Vec<Vec<Value>> = get(&format!(
+        "{BINANCE_API}/klines?symbol=XMRBTC&interval=1h&startTime={}&limit={BINANCE_LIMIT}",
+        start * 1000
+    ))
+    .await?;
+    // [open time, open, high, low, close, volume, close time, quote volume, ...]
+    rows.iter()
+        .map(|row| {
+            let open = row.first().and_then(Value::as_i64);
+            let close = row.get(4).and_then(Value::as_str);
+            let volume = row.get(5).and_then(Value::as_str);
+            let quote = row.get(7).and_then(Value::as_str);
+            let (Some(open), Some(close), Some(volume), Some(quote)) = (open, close, volume, quote)
+            else {
+                bail!("unexpected Binance candle: {row:?}");
+            };
+            let volume = parse_rate(volume)?;
+            let rate = if volume > 0.0 {
+                parse_rate(quote)? / volume
+            } else {
+                parse_rate(close)?
+            };
+            Ok(Candle {
+                open: open / 1000,
+                rate,
+            })
+        })
+        .collect()
+}
+
+/// Kraken's `result`, or its errors.
+async fn kraken(url: &str) -> Result<Value> {
+    let body: Value = get(url).await?;
+    if let Some(errors) = body.get("error").and_then(Value::as_array) {
+        if !errors.is_empty() {
+            let errors: Vec<&str> = errors.iter().filter_map(Value::as_str).collect();
+            bail!("Kraken: {}", errors.join(", "));
+        }
+    }
+    body.get("result")
+        .cloned()
+        .context("Kraken response without a result")
+}
+
+/// Kraken keys results by its own pair name, `XXMRXXBT`, next to `last`.
+fn pair_data(result: &Value) -> Result<&Value> {
+    result
+        .as_object()
+        .and_then(|pairs| {
+            pairs
+                .iter()
+                .find(|(name, _)| name.as_str() != "last")
+                .map(|(_, data)| data)
+        })
+        .context("Kraken result without the XMR/BTC pair")
+}
+
+fn parse_rate(value: &str) -> Result<f64> {
+    value
+        .parse()
+        .with_context(|| format!("invalid price {value:?}"))
+}
+
+async fn get<T: for<'de> Deserialize<'de>>(url: &str) -> Result<T> {
+    let response = Request::new("GET", url).send().await?.error_for_status()?;
+    serde_json::from_str(&response.body).with_context(|| format!("invalid response from {url}"))
+}
@@ -0,0 +1,190 @@
+//! Asking our own ASB for a quote the way a taker does (`--quote-peer`):
+//! through the swap CLI's `list-sellers`, which finds makers at a
+//! rendezvous point and requests a quote from each over libp2p. The
+//! monitor doesn't speak libp2p itself, so the `swap` binary has to be
+//! installed (`--swap-bin`).
+//!
+//! A quote that comes back proves more than any other check: ASB is
+//! registered, reachable, its wallets are loaded and it is willing to
+//! trade. Its price is compared with the market rate times the spread, so
+//! a stale price feed or a misconfigured spread shows up too.
+
+use anyhow::{bail, Context, Result};
+use serde::Serialize;
+use serde_json::Value;
+use std::process::Stdio;
+use std::time::{Duration, Instant};
+use tokio::process::Command;
+use tokio::time::timeout;
+
+use crate::model::Network;
+
+pub const DEFAULT_SWAP_BIN: &str = "swap";
+
+/// How far a quote may be from market × (1 + spread) before it alerts
+pub const DEFAULT_TOLERANCE: f64 = 0.02;
+
+/// For the rendezvous lookup and every maker's quote
+const TIMEOUT: Duration = Duration::from_secs(180);
+
+#[derive(Debug, Clone)]
+pub struct QuoteProbe {
+    /// ASB's libp2p peer id, `12D3KooW...`
+    pub peer_id: String,
+    pub swap_bin: String,
+    /// Where to find ASB, tried in order
+    pub rendezvous_points: Vec<String>,
+    pub network: Network,
+}
+
+/// What ASB offered, and how long asking took.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Quote {
+    /// BTC per XMR
+    pub price: f64,
+    pub min_btc: Option<f64>,
+    pub max_btc: Option<f64>,
+    /// The whole `list-sellers` run, rendezvous lookup included
+    pub latency: Duration,
+}
+
+/// The last self-check.
+#[derive(Debug, Clone, PartialEq)]
+pub struct QuoteCheck {
+    pub quote: Result<Quote, String>,
+    /// From market × (1 + spread), as a signed fraction, when the market
+    /// rate is known
+    pub drift: Option<f64>,
+}
+
+/// A self-check that failed, or a quote too far from the market.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum QuoteAlert {
+    Failed {
+        reason: String,
+    },
+    Drift {
+        /// BTC per XMR
+        quoted: f64,
+        expected: f64,
+        /// Signed, as a fraction of `expected`
+        drift: f64,
+        tolerance: f64,
+    },
+}
+
+impl QuoteProbe {
+    /// Ask for a quote through each rendezvous point until one finds ASB.
+    pub async fn request(&self) -> Result<Quote> {
+        if self.rendezvous_points.is_empty() {
+            bail!("no rendezvous point to find ASB through");
+        }
+        let mut missing = Vec::new();
+        for point in &self.rendezvous_points {
+            let started = Instant::now();
+            let sellers = self.list_sellers(point).await?;
+            let latency = started.elapsed();
+            match find_seller(&sellers, &self.peer_id) {
+                Some(seller) => return quote(seller, latency),
+                None => missing.push(point.as_str()),
+            }
+        }
+        bail!("not registered at {}", missing.join(", "))
+    }
+
+    /// Every JSON value `list-sellers` printed, from its result and logs.
+    async fn list_sellers(&self, point: &str) -> Result<Vec<Value>> {
+        let mut command = Command::new(&self.swap_bin);
+        if self.network == Network::Testnet {
+            command.arg("--testnet");
+        }
+        let output = command
+            .arg("--json")
+            .arg("list-sellers")
+            .arg("--rendezvous-point")
+            .arg(point)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .output();
+        let output = timeout(TIMEOUT, output)
+            .await
+            .context("list-sellers timed out")?
+            .with_context(|| format!("spawn {} (is the swap CLI installed?)", self.swap_bin))?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            let last = stderr.lines().last().unwrap_or("").trim();
+            bail!("list-sellers failed: {last}");
+        }
+        let text = [output.stdout, output.stderr].concat();
+        Ok(String::from_utf8_lossy(&text)
+            .lines()
+            .filter_map(|line| serde_json::from_str(line.trim()).ok())
+            .collect())
+    }
+}
+
+/// The object describing the seller whose address ends in `peer_id`,
+/// however deep: the CLI prints it in a `sellers` list and logs it with
+/// its fields under `fields`, depending on the release. Log lines about
+/// dialing it come first, so the last mention wins unless an earlier one
+/// carries a quote.
+fn find_seller<'a>(values: &'a [Value], peer_id: &str) -> Option<&'a Value> {
+    fn walk<'a>(value: &'a Value, peer_id: &str, found: &mut Vec<&'a Value>) {
+        match value {
+            Value::Object(map) => {
+                let address = map
+                    .get("multiaddr")
+                    .or_else(|| map.get("address"))
+                    .and_then(Value::as_str);
+                if address.is_some_and(|a| a.trim_end_matches('/').ends_with(peer_id)) {
+                    found.push(value);
+                }
+                map.values().for_each(|v| walk(v, peer_id, found));
+            }
+            Value::Array(items) => items.iter().for_each(|v| walk(v, peer_id, found)),
+            _ => {}
+        }
+    }
+    let mut found = Vec::new();
+    values.iter().for_each(|v| walk(v, peer_id, &mut found));
+    found
+        .iter()
+        .rev()
+        .find(|seller| quote(seller, Duration::ZERO).is_ok())
+        .or(found.last())
+        .copied()
+}
+
+/// `{"status": {"Online": {"price": ...}}}` in results, `{"status":
+/// "Online", "price": ...}` in logs; amounts in satoshis or like
+/// `0.0065 BTC`.
+fn quote(seller: &Value, latency: Duration) -> Result<Quote> {
+    let fields = seller
+        .pointer("/status/Online")
+        .or_else(|| seller.pointer("/status/online"))
+        .unwrap_or(seller);
+    let Some(price) = fields.get("price").and_then(btc) else {
+        let status = seller.get("status").map(|s| s.to_string());
+        bail!(
+            "found but gave no quote (status {})",
+            status.as_deref().unwrap_or("unknown")
+        );
+    };
+    Ok(Quote {
+        price,
+        min_btc: fields.get("min_quantity").and_then(btc),
+        max_btc: fields.get("max_quantity").and_then(btc),
+        latency,
+    })
+}
+
+fn btc(value: &Value) -> Option<f64> {
+    match value {
+        Value::Number(sats) => Some(sats.as_f64()? / 100_000_000.0),
+        Value::String(text) => text.trim().trim_end_matches("BTC").trim().parse().ok(),
+        _ => None,
+    }
+}
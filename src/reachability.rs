@@ -0,0 +1,171 @@
+//! Whether takers can reach ASB at the addresses it advertises
+//! (`--external-address`): a plain TCP connection for IP and DNS
+//! addresses, and one through a local Tor SOCKS proxy (`--tor-socks`) for
+//! onion addresses.
+//!
+//! An ASB nobody can connect to still registers at its rendezvous points
+//! and looks fine from its own box; it just never gets a swap. Connecting
+//! to a public address from the same machine may be answered by the router
+//! without going outside, so the direct check is most telling from another
+//! host, e.g. through an agent.
+
+use anyhow::{bail, Context, Result};
+use serde::Serialize;
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::time::timeout;
+
+/// Tor's default SOCKS port
+pub const DEFAULT_TOR_SOCKS: &str = "127.0.0.1:9050";
+
+/// How long a direct connection may take
+const DIRECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Building a circuit to a hidden service takes a while
+const ONION_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// An address ASB advertises to takers.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Target {
+    /// `host:port`, connected to directly
+    Direct(String),
+    /// A v3 onion address without `.onion`, reached through Tor
+    Onion { host: String, port: u16 },
+}
+
+/// An advertised address stopped accepting connections.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Unreachable {
+    pub address: String,
+    pub via_tor: bool,
+    pub reason: String,
+}
+
+/// The last check of one address.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Reach {
+    pub target: Target,
+    /// Why it couldn't be reached, if it couldn't
+    pub error: Option<String>,
+}
+
+impl FromStr for Target {
+    type Err = anyhow::Error;
+
+    /// `host:port`, `<onion>.onion:port`, or a multiaddress like
+    /// `/ip4/203.0.113.7/tcp/9939` or `/onion3/<onion>:9939`.
+    fn from_str(s: &str) -> Result<Self> {
+        let parts: Vec<&str> = s.split('/').collect();
+        let (host, port) = match parts.as_slice() {
+            ["", "onion3", address, ..] => address
+                .rsplit_once(':')
+                .map(|(host, port)| (format!("{host}.onion"), port))
+                .context("onion3 address without a port")?,
+            ["", "ip6", host, "tcp", port, ..] => (format!("[{host}]"), *port),
+            ["", "ip4" | "dns" | "dns4" | "dns6", host, "tcp", port, ..] => {
+                (host.to_string(), *port)
+            }
+            ["", ..] => bail!("expected a TCP or onion3 multiaddress"),
+            _ => s
+                .rsplit_once(':')
+                .map(|(host, port)| (host.to_string(), port))
+                .context("expected host:port")?,
+        };
+        let port: u16 = port
+            .parse()
+            .with_context(|| format!("invalid port {port:?}"))?;
+        if host.is_empty() {
+            bail!("expected host:port");
+        }
+        Ok(match host.strip_suffix(".onion") {
+            Some(onion) => Self::Onion {
+                host: onion.to_string(),
+                port,
+            },
+            None => Self::Direct(format!("{host}:{port}")),
+        })
+    }
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Direct(address) => f.write_str(address),
+            Self::Onion { host, port } => write!(f, "{host}.onion:{port}"),
+        }
+    }
+}
+
+impl Target {
+    pub fn is_onion(&self) -> bool {
+        matches!(self, Self::Onion { .. })
+    }
+
+    /// Short enough for the header: onion addresses lose all but the
+    /// start of their 56 characters.
+    pub fn label(&self) -> String {
+        match self {
+            Self::Direct(address) => address.clone(),
+            Self::Onion { host, port } => {
+                let start: String = host.chars().take(8).collect();
+                format!("{start}….onion:{port}")
+            }
+        }
+    }
+
+    /// Connect once, directly or through the SOCKS proxy at `socks`.
+    pub async fn check(&self, socks: &str) -> Result<()> {
+        match self {
+            Self::Direct(address) => {
+                timeout(DIRECT_TIMEOUT, TcpStream::connect(address.as_str()))
+                    .await
+                    .map_err(|_| anyhow::anyhow!("no answer"))?
+                    .map_err(|err| anyhow::anyhow!("{}", err.kind()))?;
+            }
+            Self::Onion { host, port } => {
+                let host = format!("{host}.onion");
+                timeout(ONION_TIMEOUT, socks_connect(socks, &host, *port))
+                    .await
+                    .map_err(|_| anyhow::anyhow!("no answer through Tor"))??;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A SOCKS5 CONNECT by host name, without authentication, which is what
+/// Tor expects for onion addresses.
+async fn socks_connect(proxy: &str, host: &str, port: u16) -> Result<()> {
+    let mut stream = TcpStream::connect(proxy)
+        .await
+        .with_context(|| format!("Tor SOCKS proxy {proxy}"))?;
+    stream.write_all(&[5, 1, 0]).await?;
+    let mut choice = [0u8; 2];
+    stream.read_exact(&mut choice).await?;
+    if choice != [5, 0] {
+        bail!("{proxy} is not a SOCKS5 proxy without authentication");
+    }
+
+    let mut request = vec![5, 1, 0, 3, host.len() as u8];
+    request.extend_from_slice(host.as_bytes());
+    request.extend_from_slice(&port.to_be_bytes());
+    stream.write_all(&request).await?;
+    // VER REP RSV ATYP, then the bound address, which isn't needed
+    let mut reply = [0u8; 4];
+    stream.read_exact(&mut reply).await?;
+    match reply[1] {
+        0 => Ok(()),
+        1 => bail!("Tor couldn't connect"),
+        4 => bail!("hidden service unreachable"),
+        5 => bail!("connection refused"),
+        6 => bail!("timed out in Tor"),
+        // Tor's own codes for onion services, with ExtendedErrors
+        0xf0 => bail!("no descriptor for the hidden service; is it published?"),
+        0xf6 => bail!("invalid onion address"),
+        0xf1..=0xf7 => bail!("hidden service unreachable"),
+        code => bail!("SOCKS error {code}"),
+    }
+}
@@ -0,0 +1,144 @@
+//! Reading an ASB database on another machine over SSH (`--remote`), through
+//! the system `ssh` binary and whatever keys and `~/.ssh/config` it already
+//! uses. Nothing is installed or left behind on the server, and no port is
+//! opened: every poll asks it for a fresh copy of the database and reads
+//! that locally.
+//!
+//! With `sqlite3` installed on the server, the copy is taken with its
+//! `.backup` command, which is consistent even while ASB writes. Without it
+//! the database file and its WAL are copied as they are, and a copy caught
+//! in the middle of a write fails to read until the next poll. The WAL is
+//! folded into the local copy, so the monitor reads one plain file.
+//!
+//! The SSH connection is shared between polls for a minute
+//! (`ControlPersist`), so polling doesn't log in every time.
+
+use anyhow::{bail, Context, Result};
+use sqlx::sqlite::SqliteConnectOptions;
+use sqlx::{ConnectOptions, Connection};
+use std::path::Path;
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::process::Command;
+use tokio::time::timeout;
+
+use crate::db::Remote;
+
+/// For the login and the whole transfer
+const TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Refresh `mirror` from the database on `remote`. The file is only
+/// rewritten when something changed.
+pub async fn fetch(remote: &Remote, mirror: &Path) -> Result<()> {
+    let control_path = std::env::temp_dir().join("wraithswap-ssh-%C");
+    let output = Command::new("ssh")
+        .arg("-o")
+        .arg("BatchMode=yes")
+        .arg("-o")
+        .arg("ConnectTimeout=15")
+        .arg("-o")
+        .arg("ControlMaster=auto")
+        .arg("-o")
+        .arg(format!("ControlPath={}", control_path.display()))
+        .arg("-o")
+        .arg("ControlPersist=60")
+        .arg(&remote.host)
+        .arg(script(&remote.db_path))
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .output();
+    let output = timeout(TIMEOUT, output)
+        .await
+        .with_context(|| format!("ssh {}: timed out", remote.host))?
+        .context("spawn ssh (is it installed?)")?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        bail!("ssh {}: {}", remote.host, stderr.trim());
+    }
+
+    let (db, wal) =
+        split(&output.stdout).with_context(|| format!("ssh {}: unexpected output", remote.host))?;
+    let copy = fold_wal(db, wal).await.context("read remote copy")?;
+    if std::fs::read(mirror).ok().as_deref() == Some(&copy[..]) {
+        return Ok(());
+    }
+    if let Some(dir) = mirror.parent() {
+        std::fs::create_dir_all(dir).with_context(|| format!("create {}", dir.display()))?;
+    }
+    std::fs::write(mirror, copy).with_context(|| format!("write {}", mirror.display()))
+}
+
+/// Runs on the server: print the sizes of the database and WAL copies on
+/// one line, then both copies.
+fn script(db_path: &Path) -> String {
+    format!(
+        r#"set -e
+db={}
+dir=$(mktemp -d)
+trap 'rm -rf "$dir"' EXIT
+if command -v sqlite3 >/dev/null 2>&1; then
+  sqlite3 -readonly "$db" ".backup '$dir/db'"
+  : > "$dir/wal"
+else
+  cp "$db" "$dir/db"
+  cp "$db-wal" "$dir/wal" 2>/dev/null || : > "$dir/wal"
+fi
+echo $(wc -c < "$dir/db") $(wc -c < "$dir/wal")
+cat "$dir/db" "$dir/wal"
+"#,
+        remote_path(db_path)
+    )
+}
+
+/// `db_path` quoted for the remote shell; `~/` means the remote home.
+fn remote_path(db_path: &Path) -> String {
+    let path = db_path.to_string_lossy();
+    match path.strip_prefix("~/") {
+        Some(rest) => format!("\"$HOME\"/{}", sh_quote(rest)),
+        None => sh_quote(&path),
+    }
+}
+
+fn sh_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
+
+/// The database and WAL copies from the script's output.
+fn split(output: &[u8]) -> Result<(&[u8], &[u8])> {
+    let newline = output
+        .iter()
+        .position(|&b| b == b'\n')
+        .context("no size line")?;
+    let sizes = std::str::from_utf8(&output[..newline])?;
+    let (db_len, wal_len) = sizes.trim().split_once(' ').context("no WAL size")?;
+    let (db_len, wal_len): (usize, usize) = (db_len.parse()?, wal_len.parse()?);
+    let body = &output[newline + 1..];
+    if body.len() != db_len + wal_len {
+        bail!("expected {} bytes, got {}", db_len + wal_len, body.len());
+    }
+    Ok(body.split_at(db_len))
+}
+
+/// Merge `wal` into `db` and switch it out of WAL mode, so the copy can be
+/// read without any files next to it.
+async fn fold_wal(db: &[u8], wal: &[u8]) -> Result<Vec<u8>> {
+    let dir = tempfile::tempdir().context("create temporary directory")?;
+    let path = dir.path().join("sqlite");
+    std::fs::write(&path, db)?;
+    if !wal.is_empty() {
+        std::fs::write(dir.path().join("sqlite-wal"), wal)?;
+    }
+    let mut conn = SqliteConnectOptions::new()
+        .filename(&path)
+        .connect()
+        .await
+        .context("open copy")?;
+    sqlx::query("PRAGMA journal_mode = DELETE")
+        .execute(&mut conn)
+        .await
+        .context("merge WAL")?;
+    conn.close().await?;
+    Ok(std::fs::read(&path)?)
+}
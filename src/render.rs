@@ -0,0 +1,1893 @@
+//! Plain-text rendering shared by the table, TUI, and one-shot commands.
+
+use chrono::{DateTime, Local, Utc};
+use colored::{ColoredString, Colorize};
+use dirs::home_dir;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+use crate::anomaly::Anomaly;
+use crate::asb::multiaddr_host;
+use crate::asblog::AsbLog;
+use crate::chain::{ChainMonitor, TxConfirmations};
+use crate::cli::{Clock, TimeFormat};
+use crate::columns::TableColumn;
+use crate::eta::Eta;
+use crate::explorer::Explorer;
+use crate::liveness::AsbStatus;
+use crate::model::{
+    average_premium, parse_entered_at, Activity, ActivityUnit, DateRange, EarningsOptions,
+    EarningsRow, Highlight, HistoryRow, Network, Outcome, PeerStats, RateRow, Role, SwapStats,
+    SwapSummary, SwapView, Timelock, Transition, XmrTransfer, FLAG_AFTER_FAILURES,
+};
+use crate::notes::Annotations;
+use crate::search::SwapSearch;
+use crate::sort::SortOrder;
+use crate::state::{StateSelection, SwapState};
+use crate::theme::{self, Paint};
+
+/// How timestamps are shown, from `--time` and `--clock`; the full-screen
+/// view switches the format with `T`.
+static TIME: Mutex<(TimeFormat, Clock)> = Mutex::new((TimeFormat::Local, Clock::TwentyFour));
+
+pub fn set_time_format(format: TimeFormat, clock: Clock) {
+    *TIME.lock().unwrap() = (format, clock);
+}
+
+pub fn time_settings() -> (TimeFormat, Clock) {
+    *TIME.lock().unwrap()
+}
+
+/// `--wide`: whole swap ids and timestamps, however wide the terminal.
+static WIDE: AtomicBool = AtomicBool::new(false);
+
+/// `--redact`: swap ids, peer ids, amounts and txids masked on screen, for
+/// screenshots and screen sharing. Machine-readable output and
+/// notifications are left alone.
+static REDACT: AtomicBool = AtomicBool::new(false);
+
+/// `--ascii`: plain ASCII in place of box drawing, marks, arrows and bars,
+/// for consoles and log captures that mangle anything else.
+static ASCII: AtomicBool = AtomicBool::new(false);
+
+pub fn set_display(wide: bool, redact: bool, ascii: bool) {
+    WIDE.store(wide, Ordering::Relaxed);
+    REDACT.store(redact, Ordering::Relaxed);
+    ASCII.store(ascii, Ordering::Relaxed);
+}
+
+fn wide() -> bool {
+    WIDE.load(Ordering::Relaxed)
+}
+
+fn redacting() -> bool {
+    REDACT.load(Ordering::Relaxed)
+}
+
+pub fn ascii() -> bool {
+    ASCII.load(Ordering::Relaxed)
+}
+
+/// `text` as it goes on screen: with `--ascii`, every box-drawing
+/// character, mark, arrow and bar becomes an ASCII one. Each character
+/// stands in for exactly one, so columns stay lined up.
+pub fn plain(text: String) -> String {
+    if !ascii() || text.is_ascii() {
+        return text;
+    }
+    text.chars()
+        .map(|c| match c {
+            '─' => '-',
+            '│' | '║' => '|',
+            '┌' | '┬' | '┐' | '├' | '┼' | '┤' | '└' | '┴' | '┘' => '+',
+            '╔' | '╗' | '╠' | '╣' | '╚' | '╝' => '+',
+            '═' => '=',
+            '·' | '—' => '-',
+            '✓' => '+',
+            '✗' => 'x',
+            '⚠' => '!',
+            '…' => '.',
+            '→' => '>',
+            '←' => '<',
+            '↑' => '^',
+            '↓' => 'v',
+            '●' => '*',
+            '█' | '▇' => '#',
+            '▆' | '▅' => '=',
+            '▄' | '▃' => '-',
+            '▂' | '▁' => '_',
+            '░' => '.',
+            '▏' | '▎' | '▍' | '▌' | '▋' | '▊' | '▉' => '|',
+            c => c,
+        })
+        .collect()
+}
+
+pub fn plain_lines(lines: Vec<String>) -> Vec<String> {
+    lines.into_iter().map(plain).collect()
+}
+
+/// A swap id as the screen shows it: its start, or all of it with `--wide`.
+pub fn display_id(id: &str) -> String {
+    match wide() {
+        true => redact_id(id),
+        false => short_id(id),
+    }
+}
+
+/// The start of a swap id, whatever `--wide` says, for where the room is
+/// fixed.
+pub fn short_id(id: &str) -> String {
+    truncate_id(&redact_id(id))
+}
+
+/// `3f******-****-…` with `--redact`: the first two characters are kept to
+/// tell swaps apart in conversation, the rest masked. Separators stay so the
+/// id keeps its shape.
+pub fn redact_id(id: &str) -> String {
+    if !redacting() {
+        return id.to_string();
+    }
+    id.chars()
+        .enumerate()
+        .map(|(i, c)| match i < 2 || !c.is_ascii_alphanumeric() {
+            true => c,
+            false => '*',
+        })
+        .collect()
+}
+
+fn display_peer(id: &str) -> String {
+    truncate_peer_id(&redact_id(id))
+}
+
+/// An amount with its digits masked under `--redact`, keeping its width.
+fn redact_amount(amount: String) -> String {
+    match redacting() {
+        true => amount
+            .chars()
+            .map(|c| if c.is_ascii_digit() { '*' } else { c })
+            .collect(),
+        false => amount,
+    }
+}
+
+fn display_btc(sats: u64) -> String {
+    redact_amount(format_btc(sats))
+}
+
+fn display_xmr(piconero: u64, decimals: usize) -> String {
+    redact_amount(format_xmr(piconero, decimals))
+}
+
+/// A line of free text, like the ASB's log, with `ids` masked and, under
+/// `--redact`, anything that looks like another id, a txid, an address or
+/// an amount.
+fn redact_text(text: &str, ids: &[&str]) -> String {
+    if !redacting() {
+        return text.to_string();
+    }
+    let mut text = text.to_string();
+    for id in ids {
+        text = text.replace(id, &redact_id(id));
+    }
+    let mut out = String::with_capacity(text.len());
+    let mut word = String::new();
+    let flush = |word: &mut String, out: &mut String| {
+        let long = word.chars().filter(char::is_ascii_alphanumeric).count() >= 16;
+        let amount = word.contains('.') && word.chars().any(|c| c.is_ascii_digit());
+        if long || amount {
+            let masked = std::mem::take(word);
+            out.push_str(&redact_amount(redact_id(&masked)));
+        } else {
+            out.push_str(word);
+            word.clear();
+        }
+    };
+    for c in text.chars() {
+        if c.is_ascii_alphanumeric() || c == '.' {
+            word.push(c);
+        } else {
+            flush(&mut word, &mut out);
+            out.push(c);
+        }
+    }
+    flush(&mut word, &mut out);
+    out
+}
+
+pub fn network_label(network: Network) -> ColoredString {
+    match network {
+        Network::Mainnet => "mainnet".bad().bold(),
+        Network::Testnet => "testnet (BTC testnet / XMR stagenet)".info(),
+    }
+}
+
+pub fn render_header(
+    db_path: &Option<PathBuf>,
+    network: Network,
+    role: Role,
+    instance: Option<&str>,
+    chain: &ChainMonitor,
+    rendezvous: &[String],
+    width: usize,
+) {
+    for line in header_lines(db_path, network, role, instance, chain, rendezvous, width) {
+        println!("{line}");
+    }
+}
+
+/// The boxed status header, `width` columns wide. `instance` names the
+/// section when several ASBs are rendered one after another. The fee
+/// environment, market rate and fiat price show once their sources have
+/// answered; the rendezvous points when ASB's config names any.
+pub fn header_lines(
+    db_path: &Option<PathBuf>,
+    network: Network,
+    role: Role,
+    instance: Option<&str>,
+    chain: &ChainMonitor,
+    rendezvous: &[String],
+    width: usize,
+) -> Vec<String> {
+    let title = match instance {
+        Some(name) => format!("{} · {name}", role.title()),
+        None => role.title().to_string(),
+    };
+
+    let status = if db_path.as_ref().map(|p| p.exists()).unwrap_or(false) {
+        "Connected".good()
+    } else {
+        "Disconnected".bad()
+    };
+
+    let db_display = db_path
+        .as_ref()
+        .map(|p| {
+            let s = p.display().to_string();
+            if let Some(home) = home_dir() {
+                let home_str = home.display().to_string();
+                if s.starts_with(&home_str) {
+                    return format!("~{}", &s[home_str.len()..]);
+                }
+            }
+            s
+        })
+        .unwrap_or_else(|| "unknown".to_string());
+
+    let last_updated = format_instant(Utc::now(), TimeFormat::Local, false);
+
+    let mut rows = vec![
+        ("Status", status),
+        ("Network", network_label(network)),
+        ("Database", db_display.normal()),
+    ];
+    // Whether ASB runs, apart from whether its database is there
+    if let Some(status) = chain.asb() {
+        let text = match status {
+            AsbStatus::Running { pid: Some(pid) } => format!("running (pid {pid})").good(),
+            AsbStatus::Running { pid: None } => "running".good(),
+            AsbStatus::Down { reason } => format!("not running · {reason}").bad().bold(),
+        };
+        rows.push(("ASB", text));
+    }
+    let reach = chain.reach();
+    if !reach.is_empty() {
+        let failed: Vec<String> = reach
+            .iter()
+            .filter_map(|r| Some(format!("{}: {}", r.target.label(), r.error.as_ref()?)))
+            .collect();
+        let text = match failed.first() {
+            None => {
+                let labels: Vec<String> = reach.iter().map(|r| r.target.label()).collect();
+                format!("yes · {}", labels.join(", ")).good()
+            }
+            Some(first) => {
+                let text = match failed.len() {
+                    1 => format!("no · {first}"),
+                    n => format!("{n} of {} not · {first}", reach.len()),
+                };
+                text.bad().bold()
+            }
+        };
+        rows.push(("Reachable", text));
+    }
+    if let Some(check) = chain.quote() {
+        let text = match check.quote {
+            Ok(quote) => {
+                let mut text = format!("{:.8} BTC/XMR", quote.price);
+                if let Some(drift) = check.drift {
+                    text.push_str(&format!(" · {:+.2}% vs market", drift * 100.0));
+                }
+                text.push_str(&format!(" · {:.1}s", quote.latency.as_secs_f64()));
+                match check.drift {
+                    Some(drift) if drift.abs() > chain.quote_tolerance() => text.bad().bold(),
+                    _ => text.good(),
+                }
+            }
+            Err(reason) => format!("failed · {reason}").bad().bold(),
+        };
+        rows.push(("Quote", text));
+    }
+    if let Some(fees) = chain.fees(network) {
+        let text = format!(
+            "{}/{}/{} sat/vB (next/30m/1h) · mempool {}",
+            fee_rate_value(fees.fastest),
+            fee_rate_value(fees.half_hour),
+            fee_rate_value(fees.hour),
+            format_vsize(fees.mempool_vsize)
+        );
+        let text = match chain.max_fee_rate() {
+            Some(threshold) if fees.fastest > threshold => text.bad().bold(),
+            _ => text.normal(),
+        };
+        rows.push(("Fees", text));
+    }
+    if let Some((rate, source)) = chain.market() {
+        rows.push(("Market", format!("{rate:.8} BTC/XMR · {source}").normal()));
+    }
+    if let Some((price, currency)) = chain.fiat() {
+        let text = format!("{:.2} {currency}", price.per_btc);
+        let text = if price.stale {
+            let at = price.at.with_timezone(&Local).format("%Y-%m-%d %H:%M");
+            format!("{text} · offline, as of {at}").warn()
+        } else {
+            text.normal()
+        };
+        rows.push(("BTC price", text));
+    }
+    if !rendezvous.is_empty() {
+        let hosts: Vec<&str> = rendezvous.iter().map(|a| multiaddr_host(a)).collect();
+        rows.push(("Rendezvous", hosts.join(", ").normal()));
+    }
+    rows.push(("Last updated", last_updated.normal()));
+
+    let mut lines = boxed(&title, rows, width);
+    lines.push(String::new());
+    lines
+}
+
+/// A double-lined box `width` columns wide around `title` and `Label: value`
+/// rows; values that don't fit are cut.
+fn boxed(title: &str, rows: Vec<(&str, ColoredString)>, width: usize) -> Vec<String> {
+    let inner = width.saturating_sub(2);
+    let title: String = title.chars().take(inner).collect();
+    let mut lines = vec![
+        format!("╔{}╗", "═".repeat(inner)),
+        format!("║{title:^inner$}║"),
+        format!("╠{}╣", "═".repeat(inner)),
+    ];
+    for (label, mut value) in rows {
+        let room = inner.saturating_sub(label.chars().count() + 3);
+        value.input = value.input.chars().take(room).collect();
+        lines.push(format!("║ {label}: {value:<room$}║"));
+    }
+    lines.push(format!("╚{}╝", "═".repeat(inner)));
+    plain_lines(lines)
+}
+
+/// The header box's width when there is no terminal to fit it to.
+pub const HEADER_WIDTH: usize = 64;
+
+/// Swap table width in the compact layout: id, state and time in state.
+const COMPACT_WIDTH: usize = 8 + 2 + 23 + 2 + 8;
+
+/// How the swap table is laid out, from the width of the terminal.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Layout {
+    /// Two lines a swap, without the box, for terminals too narrow for it
+    Compact,
+    #[default]
+    Normal,
+    /// Full swap ids and timestamps, when there is room for them
+    Wide,
+}
+
+impl Layout {
+    /// Lines each swap takes up.
+    pub fn rows_per_swap(self) -> usize {
+        match self {
+            Layout::Compact => 2,
+            _ => 1,
+        }
+    }
+}
+
+/// The swap table's columns: those chosen with `--table-columns`, or the
+/// usual ones along with those the configured sources fill in.
+#[derive(Debug, Clone, Default)]
+pub struct Columns {
+    /// BTC confirmations and timelock countdown, need an Electrum server
+    pub chain: bool,
+    /// XMR lock confirmations, need monero-wallet-rpc
+    pub monero: bool,
+    /// Counterparty, for databases that record one
+    pub peer: bool,
+    /// The BTC amount in fiat, with `--fiat`
+    pub fiat: bool,
+    /// Terminal columns to fit the table in; unknown when the output isn't
+    /// a terminal, which gets the normal layout
+    pub room: Option<usize>,
+    /// The columns to show, in order; `None` for the usual ones
+    pub chosen: Option<Vec<TableColumn>>,
+}
+
+impl Columns {
+    pub fn for_chain(chain: &ChainMonitor) -> Self {
+        Self {
+            chain: chain.has_electrum(),
+            monero: chain.has_monero(),
+            peer: false,
+            fiat: chain.has_fiat(),
+            room: None,
+            chosen: None,
+        }
+    }
+
+    /// Add the peer column when any of `views` has a known counterparty.
+    pub fn with_peers(&self, views: &[SwapView]) -> Self {
+        Self {
+            peer: views.iter().any(|v| v.peer_id.is_some()),
+            ..self.clone()
+        }
+    }
+
+    /// Show `chosen` columns instead of the usual ones, when given.
+    pub fn with_chosen(&self, chosen: Option<Vec<TableColumn>>) -> Self {
+        Self {
+            chosen,
+            ..self.clone()
+        }
+    }
+
+    /// Lay the table out to fit `width` terminal columns.
+    pub fn fit(&self, width: Option<usize>) -> Self {
+        Self {
+            room: width,
+            ..self.clone()
+        }
+    }
+
+    /// Whether the configured sources fill `column` in.
+    pub fn available(&self, column: TableColumn) -> bool {
+        match column {
+            TableColumn::Value => self.fiat,
+            TableColumn::BtcConf | TableColumn::Timelock => self.chain,
+            TableColumn::XmrConf => self.monero,
+            _ => true,
+        }
+    }
+
+    /// The columns shown, in order. Chosen ones that would only ever say
+    /// `-` are left out.
+    pub fn shown(&self) -> Vec<TableColumn> {
+        if let Some(ref chosen) = self.chosen {
+            return chosen
+                .iter()
+                .copied()
+                .filter(|&column| self.available(column))
+                .collect();
+        }
+        let mut shown = vec![
+            TableColumn::Id,
+            TableColumn::State,
+            TableColumn::Progress,
+            TableColumn::EnteredAt,
+            TableColumn::Duration,
+            TableColumn::Eta,
+            TableColumn::Btc,
+            TableColumn::Xmr,
+        ];
+        if self.fiat {
+            shown.push(TableColumn::Value);
+        }
+        if self.peer {
+            shown.push(TableColumn::Peer);
+        }
+        if self.chain {
+            shown.push(TableColumn::BtcConf);
+            shown.push(TableColumn::Timelock);
+        }
+        if self.monero {
+            shown.push(TableColumn::XmrConf);
+        }
+        shown
+    }
+
+    /// The widest layout that fits the terminal.
+    pub fn layout(&self) -> Layout {
+        if wide() {
+            return Layout::Wide;
+        }
+        let Some(room) = self.room else {
+            return Layout::Normal;
+        };
+        [Layout::Wide, Layout::Normal]
+            .into_iter()
+            .find(|&layout| self.width_in(layout) <= room)
+            .unwrap_or(Layout::Compact)
+    }
+
+    /// How wide the table is drawn.
+    pub fn width(&self) -> usize {
+        self.width_in(self.layout())
+    }
+
+    fn width_in(&self, layout: Layout) -> usize {
+        match layout {
+            Layout::Compact => COMPACT_WIDTH,
+            _ => {
+                let shown = self.shown();
+                let widths = shown.iter().map(|&column| column_width(column, layout));
+                widths.map(|width| width + 3).sum::<usize>() + 1
+            }
+        }
+    }
+
+    /// The header box: as wide as the table, but no narrower than usual
+    /// unless the terminal is.
+    pub fn header_width(&self) -> usize {
+        match self.room {
+            None => HEADER_WIDTH,
+            Some(room) => self.width().max(HEADER_WIDTH).min(room),
+        }
+    }
+
+    fn rule(&self, left: &str, mid: &str, right: &str) -> String {
+        let layout = self.layout();
+        if layout == Layout::Compact {
+            return plain("─".repeat(COMPACT_WIDTH));
+        }
+        let segments: Vec<String> = self
+            .shown()
+            .into_iter()
+            .map(|column| "─".repeat(column_width(column, layout) + 2))
+            .collect();
+        plain(format!("{left}{}{right}", segments.join(mid)))
+    }
+}
+
+/// Content width of a column, without the padding.
+fn column_width(column: TableColumn, layout: Layout) -> usize {
+    let wide = layout == Layout::Wide;
+    match column {
+        TableColumn::Id if wide => 36,
+        TableColumn::Id => 8,
+        TableColumn::State => 23,
+        TableColumn::Progress => 12,
+        TableColumn::EnteredAt if wide => 29,
+        TableColumn::EnteredAt => 23,
+        TableColumn::Duration | TableColumn::Eta | TableColumn::XmrConf => 8,
+        TableColumn::Btc | TableColumn::Xmr | TableColumn::Peer => 10,
+        TableColumn::BtcConf | TableColumn::Timelock => 10,
+        TableColumn::Value => 14,
+        TableColumn::Txid => 16,
+    }
+}
+
+/// Numbers and countdowns line up on the right.
+fn right_aligned(column: TableColumn) -> bool {
+    matches!(
+        column,
+        TableColumn::Duration
+            | TableColumn::Eta
+            | TableColumn::Btc
+            | TableColumn::Xmr
+            | TableColumn::Value
+            | TableColumn::BtcConf
+            | TableColumn::Timelock
+            | TableColumn::XmrConf
+    )
+}
+
+pub fn render_table(views: &[SwapView], network: Network, role: Role, columns: &Columns) {
+    let columns = &columns.with_peers(views);
+    for line in table_top(columns) {
+        println!("{line}");
+    }
+    for (i, view) in views.iter().enumerate() {
+        // Below the pinned swaps
+        if i > 0 && views[i - 1].pinned && !view.pinned {
+            println!("{}", table_divider(columns));
+        }
+        for line in table_row(view, network, role, false, columns) {
+            println!("{line}");
+        }
+    }
+    println!("{}", table_bottom(columns));
+}
+
+pub fn table_top(columns: &Columns) -> Vec<String> {
+    let layout = columns.layout();
+    if layout == Layout::Compact {
+        let titles = format!("{:<8}  {:<23}  {:>8}", "Swap ID", "State", "In State");
+        return vec![titles, columns.rule("", "", "")];
+    }
+    let titles: Vec<String> = columns
+        .shown()
+        .into_iter()
+        .map(|column| {
+            let width = column_width(column, layout);
+            format!(" {:<width$} ", column.title())
+        })
+        .collect();
+    vec![
+        columns.rule("┌", "┬", "┐"),
+        plain(format!("│{}│", titles.join("│"))),
+        columns.rule("├", "┼", "┤"),
+    ]
+}
+
+/// The lines of one swap: one in the table, two in the compact layout.
+pub fn table_row(
+    view: &SwapView,
+    network: Network,
+    role: Role,
+    selected: bool,
+    columns: &Columns,
+) -> Vec<String> {
+    let layout = columns.layout();
+    let cell = |column| table_cell(column, view, network, role, selected, layout);
+
+    if layout == Layout::Compact {
+        // The rest of the columns, those with something to show, under the
+        // state, as many as fit. The usual entry time is the first to go, as
+        // the time in state says much the same.
+        let mut rest: Vec<TableColumn> = columns
+            .shown()
+            .into_iter()
+            .filter(|column| {
+                ![TableColumn::Id, TableColumn::State, TableColumn::Duration].contains(column)
+            })
+            .collect();
+        if columns.chosen.is_none() {
+            rest.retain(|&column| column != TableColumn::EnteredAt);
+            rest.push(TableColumn::EnteredAt);
+        }
+        let mut left = columns
+            .room
+            .map_or(usize::MAX, |room| room.saturating_sub(10));
+        let details: Vec<String> = rest
+            .into_iter()
+            .map(cell)
+            .filter(|detail| !detail.is_empty() && &**detail != "-")
+            .take_while(|detail| {
+                // With the " · " before the next one
+                let width = detail.chars().count() + 3;
+                let fits = width <= left.saturating_add(3);
+                left = left.saturating_sub(width);
+                fits
+            })
+            .map(|detail| detail.to_string())
+            .collect();
+        return plain_lines(vec![
+            format!(
+                "{}  {:<23}  {:>8}",
+                cell(TableColumn::Id),
+                cell(TableColumn::State),
+                cell(TableColumn::Duration)
+            ),
+            format!("{:10}{}", "", details.join(&" · ".dimmed().to_string())),
+        ]);
+    }
+
+    let cells: Vec<String> = columns
+        .shown()
+        .into_iter()
+        .map(|column| {
+            let width = column_width(column, layout);
+            match right_aligned(column) {
+                true => format!(" {:>width$} ", cell(column)),
+                false => format!(" {:<width$} ", cell(column)),
+            }
+        })
+        .collect();
+    vec![plain(format!("│{}│", cells.join("│")))]
+}
+
+/// One swap's value for a column. The compact layout's amounts come with
+/// their unit, as there is no heading to say it.
+fn table_cell(
+    column: TableColumn,
+    view: &SwapView,
+    network: Network,
+    role: Role,
+    selected: bool,
+    layout: Layout,
+) -> ColoredString {
+    let compact = layout == Layout::Compact;
+    match column {
+        TableColumn::Id => {
+            let swap_id = match layout {
+                Layout::Wide => format!("{:<36}", redact_id(&view.swap_id)),
+                _ => format!("{:<8}", short_id(&view.swap_id)),
+            };
+            if selected {
+                swap_id.reversed()
+            } else if view.pinned {
+                swap_id.warn().bold()
+            } else if view.watched {
+                swap_id.accent().bold()
+            } else {
+                swap_id.normal()
+            }
+        }
+        TableColumn::State if view.stuck => view.state.as_str().bad().bold(),
+        TableColumn::State => format_state(&view.state, view.highlight, network, role),
+        TableColumn::Progress => format_progress(&view.state, role),
+        TableColumn::EnteredAt if layout == Layout::Wide => {
+            format_timestamp_wide(&view.entered_at).normal()
+        }
+        TableColumn::EnteredAt => format_timestamp(&view.entered_at).normal(),
+        TableColumn::Duration => {
+            let in_state = time_in_state(&view.entered_at)
+                .map(format_duration)
+                .unwrap_or_else(|| "?".to_string());
+            match view.stuck {
+                true => in_state.bad().bold(),
+                false => in_state.normal(),
+            }
+        }
+        TableColumn::Eta => format_eta(view.eta),
+        TableColumn::Btc => match view.details.btc_sats.map(display_btc) {
+            Some(btc) if compact => format!("{btc} BTC").normal(),
+            Some(btc) => btc.normal(),
+            None => "-".normal(),
+        },
+        TableColumn::Xmr => match view.details.xmr_piconero.map(|p| display_xmr(p, 4)) {
+            Some(xmr) if compact => format!("{xmr} XMR").normal(),
+            Some(xmr) => xmr.normal(),
+            None => "-".normal(),
+        },
+        TableColumn::Value => match view.fiat {
+            Some(ref fiat) => {
+                redact_amount(format!("{:.2} {}", fiat.value, fiat.currency)).normal()
+            }
+            None => "-".normal(),
+        },
+        TableColumn::Peer => {
+            let peer = view.peer_id.as_deref().map(display_peer);
+            let peer = peer.unwrap_or_else(|| "-".to_string());
+            match view.watched {
+                true => peer.accent().bold(),
+                false => peer.normal(),
+            }
+        }
+        TableColumn::Txid => match view.details.latest_btc_tx() {
+            Some((label, txid)) => format_txid(label, txid).normal(),
+            None => "-".normal(),
+        },
+        TableColumn::BtcConf => format_confirmations(view.chain.latest()),
+        TableColumn::Timelock => format_timelock(view.chain.timelock),
+        TableColumn::XmrConf => format_xmr_lock(view.chain.xmr_lock),
+    }
+}
+
+/// `redeem 3f9a2c1e`: a transaction by what it does and the start of its
+/// id, in the table's 16 columns.
+fn format_txid(label: &str, txid: &str) -> String {
+    let label = label.trim_start_matches("btc_").trim_end_matches("_txid");
+    let label: String = label.chars().take(7).collect();
+    format!("{label} {}", redact_id(&txid[..txid.len().min(8)]))
+}
+
+/// `████░░░░ 4/8`: steps taken along the happy path, green once the swap
+/// completed. Blank off the happy path, where the state says it all.
+pub fn format_progress(state: &SwapState, role: Role) -> ColoredString {
+    const CELLS: usize = 8;
+    let Some((step, steps)) = state.progress(role) else {
+        return "".normal();
+    };
+    let filled = step * CELLS / steps;
+    let bar = format!(
+        "{}{} {step}/{steps}",
+        "█".repeat(filled),
+        "░".repeat(CELLS - filled)
+    );
+    if step == steps {
+        bar.good()
+    } else {
+        bar.normal()
+    }
+}
+
+/// `~18m` until the swap likely completes; `overdue` in red once it has
+/// been in its state longer than 95% of earlier swaps.
+pub fn format_eta(eta: Option<Eta>) -> ColoredString {
+    match eta {
+        Some(Eta { overdue: true, .. }) => "overdue".bad(),
+        Some(Eta {
+            remaining: Some(remaining),
+            ..
+        }) => format!(
+            "~{}",
+            format_duration(chrono::Duration::seconds(remaining.as_secs() as i64))
+        )
+        .normal(),
+        _ => "-".normal(),
+    }
+}
+
+/// `3/10` in yellow until the lock unlocks, then the plain count.
+pub fn format_xmr_lock(transfer: Option<XmrTransfer>) -> ColoredString {
+    match transfer {
+        None => "-".normal(),
+        Some(t) if t.unlocks_in > 0 => {
+            format!("{}/{}", t.confirmations, t.confirmations + t.unlocks_in).warn()
+        }
+        Some(t) => t.confirmations.to_string().normal(),
+    }
+}
+
+/// `lock 3`, `redeem 0`…; yellow while still in the mempool.
+pub fn format_confirmations(latest: Option<(&str, u32)>) -> ColoredString {
+    let Some((label, count)) = latest else {
+        return "-".normal();
+    };
+    let count = count.to_string();
+    let label: String = label
+        .trim_start_matches("btc_")
+        .trim_end_matches("_txid")
+        .chars()
+        .take(9usize.saturating_sub(count.len()))
+        .collect();
+    let text = format!("{label} {count}");
+    if count == "0" {
+        text.warn()
+    } else {
+        text.normal()
+    }
+}
+
+/// Countdown to the next timelock; red once ASB could punish.
+pub fn format_timelock(timelock: Option<Timelock>) -> ColoredString {
+    match timelock {
+        None => "-".normal(),
+        Some(Timelock::Cancel(left)) if left <= 6 => format!("cancel {left}").warn(),
+        Some(Timelock::Cancel(left)) => format!("cancel {left}").normal(),
+        Some(Timelock::Punish(left)) => format!("punish {left}").warn(),
+        Some(Timelock::Punishable) => "punishable".bad().bold(),
+    }
+}
+
+/// Satoshis as BTC with all eight decimals.
+pub fn format_btc(sats: u64) -> String {
+    format!("{}.{:08}", sats / 100_000_000, sats % 100_000_000)
+}
+
+/// Piconero as XMR, cut (not rounded) to `decimals` places.
+pub fn format_xmr(piconero: u64, decimals: usize) -> String {
+    let frac = format!("{:012}", piconero % 1_000_000_000_000);
+    format!(
+        "{}.{}",
+        piconero / 1_000_000_000_000,
+        &frac[..decimals.min(12)]
+    )
+}
+
+/// A fee rate in sat/vB, with one decimal only below 10.
+pub fn format_fee_rate(rate: f64) -> String {
+    format!("{} sat/vB", fee_rate_value(rate))
+}
+
+fn fee_rate_value(rate: f64) -> String {
+    if rate < 10.0 && rate.fract() != 0.0 {
+        format!("{rate:.1}")
+    } else {
+        format!("{rate:.0}")
+    }
+}
+
+/// Virtual bytes as kvB or MvB.
+pub fn format_vsize(vsize: u64) -> String {
+    match vsize {
+        0..1_000 => format!("{vsize} vB"),
+        1_000..1_000_000 => format!("{} kvB", vsize / 1_000),
+        _ => format!("{:.1} MvB", vsize as f64 / 1_000_000.0),
+    }
+}
+
+pub fn truncate_timestamp(raw: &str) -> &str {
+    if raw.len() > 23 {
+        &raw[..23]
+    } else {
+        raw
+    }
+}
+
+/// A stored UTC timestamp as `--time` and `--clock` ask for it: local time
+/// by default, `3m ago` when relative. At most 23 characters, like the
+/// table's column; one that doesn't parse is shown as stored.
+pub fn format_timestamp(raw: &str) -> String {
+    match parse_entered_at(raw) {
+        Some(at) => format_instant(at, time_settings().0, false),
+        None => truncate_timestamp(raw).to_string(),
+    }
+}
+
+/// Like [`format_timestamp`], with the UTC offset of local times and
+/// nothing left out, in at most 29 characters for the wide table.
+pub fn format_timestamp_wide(raw: &str) -> String {
+    match parse_entered_at(raw) {
+        Some(at) => format_instant(at, time_settings().0, true),
+        None => raw.chars().take(29).collect(),
+    }
+}
+
+fn format_instant(at: DateTime<Utc>, format: TimeFormat, wide: bool) -> String {
+    let clock = time_settings().1;
+    let pattern = match (format, clock, wide) {
+        (TimeFormat::Relative, _, _) => {
+            return format!("{} ago", format_duration(Utc::now() - at));
+        }
+        (TimeFormat::Local, Clock::TwentyFour, false) => "%Y-%m-%d %H:%M:%S",
+        (TimeFormat::Local, Clock::TwentyFour, true) => "%Y-%m-%d %H:%M:%S %:z",
+        (TimeFormat::Local, Clock::Twelve, false) => "%Y-%m-%d %-I:%M:%S %p",
+        (TimeFormat::Local, Clock::Twelve, true) => "%Y-%m-%d %-I:%M:%S %p %:z",
+        (TimeFormat::Utc, Clock::TwentyFour, _) => "%Y-%m-%d %H:%M:%S UTC",
+        // Without the seconds, to fit the column
+        (TimeFormat::Utc, Clock::Twelve, false) => "%Y-%m-%d %-I:%M %p UTC",
+        (TimeFormat::Utc, Clock::Twelve, true) => "%Y-%m-%d %-I:%M:%S %p UTC",
+    };
+    match format {
+        TimeFormat::Utc => at.format(pattern).to_string(),
+        _ => at.with_timezone(&Local).format(pattern).to_string(),
+    }
+}
+
+/// Between the pinned swaps and the rest.
+pub fn table_divider(columns: &Columns) -> String {
+    columns.rule("├", "┼", "┤")
+}
+
+pub fn table_bottom(columns: &Columns) -> String {
+    columns.rule("└", "┴", "┘")
+}
+
+pub fn format_state(
+    state: &SwapState,
+    highlight: Highlight,
+    network: Network,
+    role: Role,
+) -> ColoredString {
+    let name = state.as_str();
+    let base = match state {
+        SwapState::Started | SwapState::SwapSetupCompleted => name.info(),
+        SwapState::BtcLockTransactionSeen
+        | SwapState::BtcLocked
+        | SwapState::XmrLockTransactionSent
+        | SwapState::XmrLockProofReceived
+        | SwapState::XmrLocked
+        | SwapState::XmrLockTransferProofSent => name.active(),
+        SwapState::EncSigSent => name.warn(),
+        _ if state.outcome(role) == Outcome::Completed => format!("{name} ✓").good(),
+        // ASB is done once it redeems the BTC; the taker still has to
+        // redeem its XMR at that point
+        SwapState::BtcRedeemed => format!("{name} …").warn(),
+        // A mainnet punish means real funds were lost; make it impossible to miss
+        SwapState::BtcPunished if network == Network::Mainnet => format!("{name} !").alarm(),
+        SwapState::BtcPunished => name.bad(),
+        SwapState::SafelyAborted => name.dimmed(),
+        _ if state.is_cancel_path() => name.accent(),
+        _ => name.normal(),
+    };
+    // A state given a color of its own keeps its mark
+    let mut base = match theme::state_style(state, role) {
+        Some(style) => style.apply(base.input.clone().normal()),
+        None => base,
+    };
+    base.input = plain(base.input);
+
+    match highlight {
+        Highlight::Bright => base.bold().underline(),
+        Highlight::Dim => base.bold(),
+        Highlight::None => base,
+    }
+}
+
+pub fn truncate_id(id: &str) -> String {
+    if id.len() <= 8 {
+        id.to_string()
+    } else {
+        format!("{}..", &id[0..6])
+    }
+}
+
+/// `..Vz3cQkPe`: libp2p ids of ed25519 keys all start with `12D3KooW`, so
+/// the tail is what tells them apart.
+pub fn truncate_peer_id(id: &str) -> String {
+    match id.char_indices().rev().nth(7) {
+        Some((i, _)) if i > 0 => format!("..{}", &id[i..]),
+        _ => id.to_string(),
+    }
+}
+
+/// How long a swap has been sitting in the state it entered at `entered_at`.
+pub fn time_in_state(entered_at: &str) -> Option<chrono::Duration> {
+    parse_entered_at(entered_at).map(|entered| Utc::now() - entered)
+}
+
+/// Compact human duration: "45s", "12m", "3h 4m", "2d 5h".
+pub fn format_duration(duration: chrono::Duration) -> String {
+    let secs = duration.num_seconds().max(0);
+    let (days, hours, minutes) = (secs / 86_400, secs % 86_400 / 3600, secs % 3600 / 60);
+
+    if days > 0 {
+        format!("{days}d {hours}h")
+    } else if hours > 0 {
+        format!("{hours}h {minutes}m")
+    } else if minutes > 0 {
+        format!("{minutes}m")
+    } else {
+        format!("{secs}s")
+    }
+}
+
+/// Full transition history for one swap, oldest first, with how long the
+/// swap sat in each state.
+pub fn history_lines(
+    swap_id: &str,
+    history: &[HistoryRow],
+    network: Network,
+    role: Role,
+    confirmations: &TxConfirmations,
+    explorer: &Explorer,
+) -> Vec<String> {
+    let mut lines = vec![format!("Swap {}", redact_id(swap_id).bold())];
+    if let Some(peer_id) = history.iter().rev().find_map(|h| h.peer_id.as_deref()) {
+        lines.push(format!("Peer {}", redact_id(peer_id)));
+    }
+
+    // Later states carry the same amounts; txids accumulate as the swap moves
+    let btc = history.iter().rev().find_map(|h| h.details.btc_sats);
+    let xmr = history.iter().rev().find_map(|h| h.details.xmr_piconero);
+    if btc.is_some() || xmr.is_some() {
+        lines.push(format!(
+            "BTC {}  ·  XMR {}",
+            btc.map(display_btc).unwrap_or_else(|| "?".to_string()),
+            xmr.map(|p| display_xmr(p, 12))
+                .unwrap_or_else(|| "?".to_string())
+        ));
+    }
+    let mut txids = BTreeMap::new();
+    for entry in history {
+        txids.extend(entry.details.txids.iter());
+    }
+    for (label, txid) in txids {
+        // BTC transactions link to the explorer; the XMR lock stays a txid,
+        // and so does everything redacted, a link giving the txid away
+        let shown = match label.as_str() {
+            _ if redacting() => redact_id(txid),
+            "xmr_lock" => txid.clone(),
+            _ => explorer.tx_url(network, txid),
+        };
+        let line = match confirmations.describe(txid) {
+            Some(status) => format!("{label}: {shown} ({status})"),
+            None => format!("{label}: {shown}"),
+        };
+        lines.push(line.dimmed().to_string());
+    }
+    lines.push(String::new());
+
+    lines.push(
+        "┌────┬─────────────────────────┬─────────────────────────┬────────────┐".to_string(),
+    );
+    lines.push(
+        "│  # │ State                   │ Entered At              │ Duration   │".to_string(),
+    );
+    lines.push(
+        "├────┼─────────────────────────┼─────────────────────────┼────────────┤".to_string(),
+    );
+
+    let now = Utc::now();
+    for (i, entry) in history.iter().enumerate() {
+        let entered = parse_entered_at(&entry.entered_at);
+        let left = match history.get(i + 1) {
+            Some(next) => parse_entered_at(&next.entered_at),
+            None => Some(now),
+        };
+        let duration = match (entered, left) {
+            (Some(from), Some(to)) => format_duration(to - from),
+            _ => "?".to_string(),
+        };
+        let duration = if i + 1 == history.len() {
+            format!("{duration} …")
+        } else {
+            duration
+        };
+
+        lines.push(format!(
+            "│ {:>2} │ {:<23} │ {:<23} │ {:<10} │",
+            i + 1,
+            format_state(&entry.state, Highlight::None, network, role),
+            format_timestamp(&entry.entered_at),
+            duration
+        ));
+    }
+
+    lines.push(
+        "└────┴─────────────────────────┴─────────────────────────┴────────────┘".to_string(),
+    );
+    plain_lines(lines)
+}
+
+/// A swap's tags and notes, for below its history; empty when it has none.
+pub fn annotation_lines(annotations: &Annotations) -> Vec<String> {
+    let mut lines = Vec::new();
+    if !annotations.tags.is_empty() {
+        lines.push(format!("Tags {}", format_tags(&annotations.tags)));
+    }
+    for note in &annotations.notes {
+        lines.push(format!(
+            "{} {}",
+            format!("Note {}", note.added_at).dimmed(),
+            note.text
+        ));
+    }
+    plain_lines(lines)
+}
+
+fn format_tags(tags: &[String]) -> String {
+    let tags: Vec<String> = tags.iter().map(|tag| tag.info().to_string()).collect();
+    plain(tags.join(" · "))
+}
+
+/// The `notes` table: each annotated swap's tags and latest note.
+pub fn render_annotations(all: &[Annotations]) {
+    println!(
+        "{}",
+        format!("{:<36}  {:<5}  Tags / Latest Note", "Swap", "Notes").bold()
+    );
+    for annotations in all {
+        println!(
+            "{:<36}  {:<5}  {}",
+            annotations.swap_id,
+            annotations.notes.len(),
+            format_tags(&annotations.tags)
+        );
+        if let Some(note) = annotations.notes.last() {
+            println!("{:<36}  {:<5}  {}", "", "", note.text.dimmed());
+        }
+    }
+}
+
+/// The swap's lines from ASB's log, under the state the swap was in when
+/// each was written, keeping the newest when they don't all fit in `rows`.
+pub fn log_lines(
+    log: &AsbLog,
+    swap_id: &str,
+    history: &[HistoryRow],
+    network: Network,
+    role: Role,
+    rows: usize,
+) -> Vec<String> {
+    enum Row {
+        /// The swap entered `history[i]`
+        Entered(usize),
+        Line(String),
+    }
+
+    let title = format!("ASB log · {}", log.path().display())
+        .bold()
+        .to_string();
+    if let Some(err) = log.error() {
+        return plain_lines(vec![title, format!("Error: {err}").bad().to_string()]);
+    }
+    let entered: Vec<_> = history
+        .iter()
+        .map(|h| parse_entered_at(&h.entered_at))
+        .collect();
+    let mut ids = vec![swap_id];
+    ids.extend(history.iter().filter_map(|h| h.peer_id.as_deref()));
+    let mut body = Vec::new();
+    let mut state = None;
+    for line in log.for_swap(swap_id) {
+        // Lines without a time stay with the state before them
+        if let Some(at) = line.at {
+            let current = entered.iter().rposition(|e| e.is_some_and(|e| e <= at));
+            if let Some(index) = current.filter(|_| current != state) {
+                body.push(Row::Entered(index));
+            }
+            state = current;
+        }
+        let at = line
+            .at
+            .map(|at| at.format("%Y-%m-%d %H:%M:%S").to_string())
+            .unwrap_or_default();
+        let level = match line.level.as_str() {
+            "ERROR" => line.level.bad().bold(),
+            "WARN" => line.level.warn(),
+            "DEBUG" | "TRACE" => line.level.dimmed(),
+            _ => line.level.normal(),
+        };
+        body.push(Row::Line(format!(
+            "{:<19}  {:<5}  {}",
+            at.dimmed(),
+            level,
+            redact_text(&line.message, &ids)
+        )));
+    }
+    if body.is_empty() {
+        return plain_lines(vec![
+            title,
+            "Nothing about this swap in the log.".dimmed().to_string(),
+        ]);
+    }
+
+    let rule = |index: usize| {
+        let entry = &history[index];
+        format!(
+            "── {} · {} ──",
+            format_state(&entry.state, Highlight::None, network, role),
+            format_timestamp(&entry.entered_at)
+        )
+        .info()
+        .to_string()
+    };
+    let mut lines = vec![title];
+    let room = rows.saturating_sub(1);
+    if body.len() > room {
+        // A cut-off start still says which state its lines belong to
+        let start = body.len() - room.saturating_sub(1).max(1);
+        let state = body[..start].iter().rev().find_map(|row| match row {
+            Row::Entered(index) => Some(*index),
+            Row::Line(_) => None,
+        });
+        if let (Some(index), Row::Line(_)) = (state, &body[start]) {
+            lines.push(rule(index));
+        }
+        body.drain(..start);
+    }
+    lines.extend(body.into_iter().map(|row| match row {
+        Row::Entered(index) => rule(index),
+        Row::Line(text) => text,
+    }));
+    plain_lines(lines)
+}
+
+/// Samples drawn in each balance graph
+const SPARKLINE_WIDTH: usize = 30;
+
+/// Anomalies beyond this many are summed up in one line
+const ANOMALY_LINES: usize = 5;
+
+/// The warning panel for steps the state machine doesn't allow, newest
+/// last. Empty when there are none.
+pub fn anomaly_lines(anomalies: &[Anomaly], network: Network, role: Role) -> Vec<String> {
+    if anomalies.is_empty() {
+        return Vec::new();
+    }
+    let mut lines = vec![format!(
+        "⚠ {} — damaged database, clock jump or unsupported ASB version?",
+        match anomalies.len() {
+            1 => "1 unexpected transition".to_string(),
+            n => format!("{n} unexpected transitions"),
+        }
+    )
+    .warn()
+    .bold()
+    .to_string()];
+    let hidden = anomalies.len().saturating_sub(ANOMALY_LINES);
+    if hidden > 0 {
+        lines.push(format!("  … {hidden} earlier").dimmed().to_string());
+    }
+    for anomaly in &anomalies[hidden..] {
+        lines.push(format!(
+            "  {:<8}  {} → {}  {}  {}",
+            short_id(&anomaly.swap_id),
+            format_state(&anomaly.from, Highlight::None, network, role),
+            format_state(&anomaly.to, Highlight::None, network, role),
+            anomaly.kind.as_str().warn(),
+            format_timestamp(&anomaly.entered_at).dimmed(),
+        ));
+    }
+    plain_lines(lines)
+}
+
+/// The balance panel: one line per wallet with the latest balance and a
+/// graph of the history since the monitor started. Empty when disabled.
+pub fn balance_lines(chain: &ChainMonitor) -> Vec<String> {
+    let (btc, xmr) = chain.balance_sources();
+    if !btc && !xmr {
+        return Vec::new();
+    }
+    let samples = chain.balance_history();
+    let span = samples
+        .first()
+        .filter(|_| samples.len() > 1)
+        .map(|first| format!("last {}", format_duration(Utc::now() - first.at)))
+        .unwrap_or_default();
+
+    let mut lines = Vec::new();
+    if btc {
+        let line = match samples.iter().rev().find_map(|s| s.btc) {
+            Some(balance) => {
+                let pending = match balance.unconfirmed {
+                    0 => String::new(),
+                    n if n > 0 => format!("+{} pending", display_btc(n as u64)),
+                    n => format!("-{} pending", display_btc(n.unsigned_abs())),
+                };
+                format!(
+                    "{:<16} {} {} {}",
+                    display_btc(balance.confirmed),
+                    format!("{pending:<24}").warn(),
+                    sparkline(samples.iter().filter_map(|s| s.btc.map(|b| b.total()))),
+                    span.dimmed(),
+                )
+            }
+            None => "-".dimmed().to_string(),
+        };
+        lines.push(format!("BTC wallet  {line}"));
+    }
+    if xmr {
+        let line = match samples.iter().rev().find_map(|s| s.xmr) {
+            Some(balance) => {
+                let locked = match balance.balance.saturating_sub(balance.unlocked) {
+                    0 => String::new(),
+                    n => format!("{} locked", display_xmr(n, 4)),
+                };
+                format!(
+                    "{:<16} {} {} {}",
+                    display_xmr(balance.balance, 4),
+                    format!("{locked:<24}").warn(),
+                    sparkline(samples.iter().filter_map(|s| s.xmr.map(|b| b.balance))),
+                    span.dimmed(),
+                )
+            }
+            None => "-".dimmed().to_string(),
+        };
+        lines.push(format!("XMR wallet  {line}"));
+    }
+    plain_lines(lines)
+}
+
+/// Block-character graph of `values`, thinned to `SPARKLINE_WIDTH` and
+/// scaled between their minimum and maximum.
+fn sparkline(values: impl Iterator<Item = u64>) -> String {
+    const BARS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+    let values: Vec<u64> = values.collect();
+    let width = values.len().min(SPARKLINE_WIDTH);
+    // Evenly spaced samples, always ending with the latest
+    let points: Vec<u64> = (1..=width)
+        .map(|i| values[i * values.len() / width - 1])
+        .collect();
+    let (Some(&min), Some(&max)) = (points.iter().min(), points.iter().max()) else {
+        return String::new();
+    };
+    points
+        .iter()
+        .map(|&v| match max - min {
+            0 => BARS[0],
+            range => BARS[((v - min) * 7 / range) as usize],
+        })
+        .collect::<String>()
+        + &" ".repeat(SPARKLINE_WIDTH - width)
+}
+
+/// One-line overview shown above the table in watch mode.
+pub fn summary_line(views: &[SwapView], role: Role) -> String {
+    let summary = SwapSummary::from_states(
+        views.iter().map(|v| (&v.state, v.started_at.as_str())),
+        role,
+    );
+    plain(format!(
+        "{} swaps · {} · {} · {} · {} new today",
+        views.len(),
+        format!("{} completed", summary.completed).good(),
+        format!("{} failed", summary.failed).bad(),
+        format!("{} in progress", summary.in_progress).warn(),
+        summary.new_today
+    ))
+}
+
+/// `14d` or `48h`: how far back `activity` goes.
+fn activity_span(activity: &Activity) -> String {
+    match activity.per {
+        ActivityUnit::Hour => format!("{}h", activity.buckets.len()),
+        ActivityUnit::Day => format!("{}d", activity.buckets.len()),
+    }
+}
+
+/// A block per hour or day, scaled from none up to the busiest, with `·`
+/// where nothing started so that a quiet spell stands out.
+fn activity_sparkline(activity: &Activity) -> String {
+    const BARS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+    let max = activity
+        .buckets
+        .iter()
+        .map(|b| b.started)
+        .max()
+        .unwrap_or(0);
+    activity
+        .buckets
+        .iter()
+        .map(|b| match b.started {
+            0 if ascii() => '.',
+            0 => '·',
+            n => BARS[(n * 8 - 1) / max],
+        })
+        .collect()
+}
+
+/// The line above the watch table with `--chart`:
+/// `Started per day  ▂▅▃·█▇▆  42 in 14d · 3 today`.
+pub fn activity_line(activity: &Activity) -> String {
+    let (per, current) = match activity.per {
+        ActivityUnit::Hour => ("hour", "this hour"),
+        ActivityUnit::Day => ("day", "today"),
+    };
+    plain(format!(
+        "Started per {per}  {}  {} in {} · {} {current}",
+        activity_sparkline(activity).info(),
+        activity.total(),
+        activity_span(activity),
+        activity.current()
+    ))
+}
+
+/// `stats --chart`: a bar per hour or day, days with their weekday so that
+/// weekly patterns show.
+pub fn render_activity(activity: &Activity) {
+    const BAR_WIDTH: usize = 40;
+    const EIGHTHS: [&str; 8] = ["", "▏", "▎", "▍", "▌", "▋", "▊", "▉"];
+
+    let per = match activity.per {
+        ActivityUnit::Hour => "hour",
+        ActivityUnit::Day => "day",
+    };
+    println!(
+        "{}",
+        format!("Swaps started per {per}, last {}", activity_span(activity)).bold()
+    );
+    let max = activity
+        .buckets
+        .iter()
+        .map(|b| b.started)
+        .max()
+        .unwrap_or(0);
+    for bucket in &activity.buckets {
+        let label = match chrono::NaiveDate::parse_from_str(&bucket.start, "%Y-%m-%d") {
+            Ok(day) => format!("{} {}", bucket.start, day.format("%a")),
+            Err(_) => bucket.start.clone(),
+        };
+        let eighths = match max {
+            0 => 0,
+            max => bucket.started * BAR_WIDTH * 8 / max,
+        };
+        let bar = "█".repeat(eighths / 8) + EIGHTHS[eighths % 8];
+        let bar = format!("{bar:<BAR_WIDTH$}");
+        let count = format!("{:>5}", bucket.started);
+        let count = match bucket.started {
+            0 => count.dimmed(),
+            _ => count.normal(),
+        };
+        println!("  {label:<16} {} {count}", plain(bar).info());
+    }
+    println!(
+        "  {} started, {:.1} per {per} on average",
+        activity.total(),
+        activity.total() as f64 / activity.buckets.len().max(1) as f64
+    );
+}
+
+/// Which swaps the tables show: `--state`'s selection when there is one,
+/// and otherwise every swap, or only unfinished ones with `--hide-finished`;
+/// either way only those `--since`, `--until` and `--tag` let through, and
+/// of those the best matches for `--swap`; and in which order.
+#[derive(Debug, Clone, Default)]
+pub struct TableFilter {
+    pub states: StateSelection,
+    pub hide_finished: bool,
+    pub range: DateRange,
+    pub search: Option<SwapSearch>,
+    /// Swaps with any of these tags; empty lets every swap through
+    pub tags: Vec<String>,
+    /// Newest first when unset
+    pub sort: Option<SortOrder>,
+}
+
+impl TableFilter {
+    /// The swaps of `views` the table shows, in table order. Pinned swaps
+    /// come first whatever the states, dates and order, though a search
+    /// still narrows them down.
+    pub fn apply<'a>(&self, views: &'a [SwapView], role: Role) -> Vec<&'a SwapView> {
+        let shown: Vec<&SwapView> = views
+            .iter()
+            .filter(|view| view.pinned || self.shows(view, role))
+            .collect();
+        let mut shown = match self.search {
+            Some(ref search) => search.best(shown, |view| &view.swap_id),
+            None => shown,
+        };
+        if let Some(sort) = self.sort {
+            sort.sort(&mut shown, role);
+        }
+        // Stable, so both parts keep the order
+        shown.sort_by_key(|view| !view.pinned);
+        shown
+    }
+
+    fn shows(&self, view: &SwapView, role: Role) -> bool {
+        let state = match self.states.is_empty() {
+            true => !(self.hide_finished && view.state.is_terminal(role)),
+            false => self.states.matches(&view.state, role),
+        };
+        state
+            && self.range.contains(&view.entered_at)
+            && (self.tags.is_empty() || view.tags.iter().any(|tag| self.tags.contains(tag)))
+    }
+
+    /// `state failed · since 24h ago`, when the states or dates narrow the
+    /// table down.
+    pub fn narrowed_by(&self) -> Option<String> {
+        let mut parts = Vec::new();
+        if !self.states.is_empty() {
+            parts.push(format!("state {}", self.states));
+        }
+        if !self.range.is_empty() {
+            parts.push(self.range.to_string());
+        }
+        if let Some(ref search) = self.search {
+            parts.push(format!("swap {search}"));
+        }
+        if !self.tags.is_empty() {
+            parts.push(format!("tag {}", self.tags.join(", ")));
+        }
+        (!parts.is_empty()).then(|| parts.join(" · "))
+    }
+
+    /// What the table left out of `views`, for a line below it.
+    pub fn hidden_line(&self, views: &[SwapView], role: Role) -> Option<String> {
+        let shown = self.apply(views, role).len();
+        let hidden = views.len() - shown;
+        let line = if let Some(narrowed) = self.narrowed_by() {
+            format!("Showing {shown} of {} swaps · {narrowed}", views.len())
+        } else if hidden > 0 {
+            let failed = views
+                .iter()
+                .filter(|view| !view.pinned && view.state.outcome(role) == Outcome::Failed)
+                .count();
+            format!(
+                "{hidden} finished swaps hidden · {} completed · {failed} failed",
+                hidden - failed
+            )
+        } else {
+            return None;
+        };
+        (!views.is_empty()).then(|| plain(line).dimmed().to_string())
+    }
+
+    /// In place of a table the filter emptied.
+    pub fn empty_line(&self) -> String {
+        let line = match (&self.search, self.states.is_empty(), self.range.is_empty()) {
+            (Some(search), ..) => format!("No swap matches {search}."),
+            _ if !self.tags.is_empty() => format!("No swaps tagged {}.", self.tags.join(" or ")),
+            (None, true, true) => "No swaps in flight.".to_string(),
+            (None, false, true) => format!("No {} swaps.", self.states),
+            (None, _, false) => format!("No swaps {}.", self.range),
+        };
+        line.warn().to_string()
+    }
+}
+
+/// Below the table when `--page-size` left older finished swaps unread.
+pub fn unloaded_line(unloaded: usize) -> Option<String> {
+    (unloaded > 0).then(|| {
+        plain(
+            format!("{unloaded} older finished swaps not read · --page-size 0 reads them all")
+                .dimmed()
+                .to_string(),
+        )
+    })
+}
+
+pub fn clear_screen() {
+    print!("\x1B[2J\x1B[1;1H");
+}
+
+pub fn render_error(message: &str) {
+    println!("{}", plain(format!("Error: {message}")).bad());
+}
+
+/// Errors go to stderr in machine-readable modes so stdout stays parseable.
+pub fn report_error(machine: bool, message: &str) {
+    if machine {
+        eprintln!("Error: {message}");
+    } else {
+        render_error(message);
+    }
+}
+
+pub fn render_stats(stats: &SwapStats, network: Network, role: Role) {
+    let summary = &stats.summary;
+    println!("Swaps:            {}", stats.total_swaps);
+    println!("  completed:      {}", summary.completed);
+    println!("  failed:         {}", summary.failed);
+    println!("  in progress:    {}", summary.in_progress);
+    println!("New today:        {}", summary.new_today);
+    println!("State rows:       {}", stats.total_state_rows);
+    if let Some(ref schema) = stats.schema {
+        println!("Schema:           {schema}");
+    }
+    println!();
+    if let Some(ref activity) = stats.activity {
+        render_activity(activity);
+        println!();
+    }
+
+    let mut by_state: Vec<_> = stats.by_state.iter().collect();
+    by_state.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+    for (state, count) in by_state {
+        println!(
+            "  {:<23} {:>6}",
+            format_state(
+                &SwapState::from(state.as_str()),
+                Highlight::None,
+                network,
+                role
+            ),
+            count
+        );
+    }
+}
+
+/// The `peers` listing, with the addresses each peer was reached at below
+/// it. Peers with repeated failures are red. `unknown` counts swaps without
+/// a recorded peer.
+pub fn render_peers(peers: &[PeerStats], unknown: usize) {
+    if peers.is_empty() {
+        println!("{}", "No peers recorded.".warn());
+    } else {
+        println!(
+            "{}",
+            format!(
+                "{:<52} {:>6} {:>6} {:>6} {:>6} {:>6} {:>6} {:>6}  {:<23}",
+                "Peer",
+                "Swaps",
+                "Done",
+                "Refund",
+                "Punish",
+                "Abort",
+                "Active",
+                "Done%",
+                "Last Swap"
+            )
+            .bold()
+        );
+        for peer in peers {
+            let line = format!(
+                "{:<52} {:>6} {:>6} {:>6} {:>6} {:>6} {:>6} {:>6}  {:<23}",
+                redact_id(&peer.peer_id),
+                peer.swaps,
+                peer.summary.completed,
+                peer.refunded,
+                peer.punished,
+                peer.aborted,
+                peer.summary.in_progress,
+                format_rate(peer.completion_rate),
+                format_timestamp(&peer.last_swap),
+            );
+            if peer.flagged {
+                println!("{}", line.bad().bold());
+            } else if peer.swaps > 1 {
+                println!("{line}");
+            } else {
+                println!("{}", line.dimmed());
+            }
+            for address in &peer.addresses {
+                println!("  {}", address.dimmed());
+            }
+        }
+        let repeat = peers.iter().filter(|p| p.swaps > 1).count();
+        println!(
+            "{}",
+            format!("{} peers, {repeat} with more than one swap", peers.len()).dimmed()
+        );
+        let flagged = peers.iter().filter(|p| p.flagged).count();
+        if flagged > 0 {
+            println!(
+                "{}",
+                format!(
+                    "{flagged} with {FLAG_AFTER_FAILURES} or more failed swaps, a sign of griefing"
+                )
+                .bad()
+            );
+        }
+    }
+    if unknown > 0 {
+        println!(
+            "{}",
+            format!("{unknown} swaps have no recorded peer").dimmed()
+        );
+    }
+}
+
+/// `67%`, or `-` before any swap finished.
+fn format_rate(rate: Option<f64>) -> String {
+    match rate {
+        Some(rate) => format!("{:.0}%", rate * 100.0),
+        None => "-".to_string(),
+    }
+}
+
+/// The `rates` listing. An ASB's premium is red below the market and
+/// yellow when it kept less than half of the ask `spread`; for a taker it
+/// is what was paid over the market.
+pub fn render_rates(rows: &[RateRow], role: Role, spread: f64) {
+    if rows.is_empty() {
+        println!("{}", "No swaps with known amounts yet.".warn());
+        return;
+    }
+    println!(
+        "{}",
+        format!(
+            "{:<8}  {:<23}  {:<28} {:>12} {:>10} {:>10} {:>10} {:>8}",
+            "Swap", "Started", "State", "BTC", "XMR", "Rate", "Market", "Premium"
+        )
+        .bold()
+    );
+    for row in rows {
+        let market = row
+            .market_rate
+            .map_or("-".to_string(), |rate| format!("{rate:.8}"));
+        let premium = match row.premium {
+            Some(premium) => {
+                let text = format!("{:>+8.2}%", premium * 100.0);
+                match role {
+                    Role::Maker if premium < 0.0 => text.bad(),
+                    Role::Maker if premium < spread / 2.0 => text.warn(),
+                    _ => text.normal(),
+                }
+            }
+            None => format!("{:>9}", "-").dimmed(),
+        };
+        println!(
+            "{:<8}  {:<23}  {:<28} {:>12} {:>10} {:>10.8} {:>10}{premium}",
+            short_id(&row.swap_id),
+            format_timestamp(&row.started_at),
+            row.state.as_str(),
+            display_btc(row.btc_sats),
+            display_xmr(row.xmr_piconero, 4),
+            row.rate,
+            market,
+        );
+    }
+    let priced = rows.iter().filter(|row| row.premium.is_some()).count();
+    let summary = match (average_premium(rows), role) {
+        (Some(average), Role::Maker) => format!(
+            "Average premium {:+.2}% over {priced} swaps with a market rate, against a {}% ask spread.",
+            average * 100.0,
+            (spread * 10_000.0).round() / 100.0
+        ),
+        (Some(average), Role::Taker) => format!(
+            "Paid {:+.2}% over the market on average across {priced} swaps.",
+            average * 100.0
+        ),
+        (None, _) => "No market rates for these swaps.".to_string(),
+    };
+    println!("{}", summary.dimmed());
+}
+
+/// The `history` listing: when each transition was observed and what changed.
+pub fn render_transitions(transitions: &[Transition], role: Role) {
+    println!(
+        "{}",
+        format!("{:<23}  {:<8}  Transition", "Observed At", "Swap").bold()
+    );
+    for transition in transitions {
+        let new_state = format_state(
+            &transition.new_state,
+            Highlight::None,
+            transition.network,
+            role,
+        );
+        let change = match &transition.old_state {
+            Some(old) => plain(format!(
+                "{} → {new_state}",
+                format_state(old, Highlight::None, transition.network, role)
+            )),
+            None => format!("{new_state} {}", "(new)".dimmed()),
+        };
+        println!(
+            "{:<23}  {:<8}  {change}",
+            format_timestamp(&transition.observed_at),
+            short_id(&transition.swap_id)
+        );
+    }
+}
+
+/// A line of the full-screen view's ticker:
+/// `12:04:31  3f9a2c..  XmrLockProofSent → EncSigSent`, with the date
+/// instead of the seconds for transitions observed before today.
+pub fn ticker_line(transition: &Transition, network: Network, role: Role) -> String {
+    let (format, clock) = time_settings();
+    let observed = match parse_entered_at(&transition.observed_at) {
+        Some(at) if format == TimeFormat::Relative => {
+            format!("{} ago", format_duration(Utc::now() - at))
+        }
+        Some(at) => {
+            let (at, today) = match format {
+                TimeFormat::Utc => (at.naive_utc(), Utc::now().date_naive()),
+                _ => (
+                    at.with_timezone(&Local).naive_local(),
+                    Local::now().date_naive(),
+                ),
+            };
+            let pattern = match (at.date() == today, clock) {
+                (true, Clock::TwentyFour) => "%H:%M:%S",
+                (true, Clock::Twelve) => "%-I:%M:%S %p",
+                (false, Clock::TwentyFour) => "%m-%d %H:%M",
+                (false, Clock::Twelve) => "%m-%d %-I:%M %p",
+            };
+            at.format(pattern).to_string()
+        }
+        None => truncate_timestamp(&transition.observed_at).to_string(),
+    };
+    let new_state = format_state(&transition.new_state, Highlight::None, network, role);
+    let change = match &transition.old_state {
+        Some(old) => format!(
+            "{} → {new_state}",
+            format_state(old, Highlight::None, network, role)
+        ),
+        None => format!("{new_state} {}", "(new)".dimmed()),
+    };
+    plain(format!(
+        "{}  {:<8}  {change}",
+        format!(
+            "{observed:>width$}",
+            width = if clock == Clock::Twelve { 14 } else { 11 }
+        )
+        .dimmed(),
+        short_id(&transition.swap_id)
+    ))
+}
+
+/// The `earnings` table; the last row is the total.
+pub fn render_earnings(report: &[EarningsRow], options: &EarningsOptions) {
+    if report.len() == 1 {
+        println!("{}", "No completed swaps yet.".warn());
+        return;
+    }
+
+    let fiat = options.btc_price.is_some();
+    let mut header = format!(
+        "{:<10} {:>6} {:>14} {:>16} {:>14}",
+        "Period", "Swaps", "BTC received", "XMR sent", "Earned BTC"
+    );
+    if fiat {
+        header.push_str(&format!(" {:>14}", format!("Earned {}", options.currency)));
+    }
+    println!("{}", header.bold());
+
+    for (i, row) in report.iter().enumerate() {
+        let mut line = format!(
+            "{:<10} {:>6} {:>14} {:>16} {:>14}",
+            row.period,
+            row.swaps,
+            display_btc(row.btc_received_sats),
+            display_xmr(row.xmr_sent_piconero, 6),
+            display_btc(row.earnings_sats),
+        );
+        if let Some(value) = row.earnings_fiat {
+            line.push_str(&format!(" {:>14}", redact_amount(format!("{value:.2}"))));
+        }
+        if i == report.len() - 1 {
+            println!("{}", plain("─".repeat(line.chars().count())).dimmed());
+            println!("{}", line.bold());
+        } else {
+            println!("{line}");
+        }
+    }
+    println!(
+        "{}",
+        format!(
+            "Earnings assume a {}% ask spread over the market price.",
+            (options.spread * 10_000.0).round() / 100.0
+        )
+        .dimmed()
+    );
+}
@@ -0,0 +1,82 @@
+//! Finding a swap by a piece of its id (`--swap`, `/` in the full-screen
+//! view), the way a user pastes it into a support chat: the start of it,
+//! any part of it, or a part with one character wrong. Case and dashes
+//! don't matter.
+
+use std::fmt;
+
+/// Shortest query a one-character slip is forgiven in; below it nearly
+/// every id would match
+const MIN_FUZZY: usize = 6;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SwapSearch {
+    /// As typed
+    query: String,
+    /// Lowercase, without dashes
+    needle: Vec<char>,
+}
+
+impl SwapSearch {
+    pub fn new(query: &str) -> Self {
+        Self {
+            query: query.trim().to_string(),
+            needle: normalize(query),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.needle.is_empty()
+    }
+
+    /// How well `swap_id` matches, lower being better: 0 for a prefix, 1
+    /// anywhere in the id, 2 with one character wrong.
+    pub fn rank(&self, swap_id: &str) -> Option<u8> {
+        let id = normalize(swap_id);
+        let needle = &self.needle;
+        if id.starts_with(needle) {
+            return Some(0);
+        }
+        if id.len() < needle.len() {
+            return None;
+        }
+        let windows = || id.windows(needle.len());
+        if windows().any(|window| window == needle.as_slice()) {
+            return Some(1);
+        }
+        let near = |window: &[char]| window.iter().zip(needle).filter(|(a, b)| a != b).count() <= 1;
+        (needle.len() >= MIN_FUZZY && windows().any(near)).then_some(2)
+    }
+
+    /// Those of `items` that match best, in their order: prefix matches
+    /// when there are any, and near misses only when nothing matches
+    /// exactly.
+    pub fn best<T>(&self, items: Vec<T>, id: impl Fn(&T) -> &str) -> Vec<T> {
+        let ranked: Vec<(Option<u8>, T)> = items
+            .into_iter()
+            .map(|item| (self.rank(id(&item)), item))
+            .collect();
+        let Some(best) = ranked.iter().filter_map(|(rank, _)| *rank).min() else {
+            return Vec::new();
+        };
+        ranked
+            .into_iter()
+            .filter(|(rank, _)| *rank == Some(best))
+            .map(|(_, item)| item)
+            .collect()
+    }
+}
+
+impl fmt::Display for SwapSearch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.query)
+    }
+}
+
+fn normalize(text: &str) -> Vec<char> {
+    text.trim()
+        .chars()
+        .filter(|&c| c != '-')
+        .map(|c| c.to_ascii_lowercase())
+        .collect()
+}
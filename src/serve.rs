@@ -0,0 +1,911 @@
+//! `serve`: a read-only JSON API over the same polling loop as watch mode.
+//!
+//! The loop keeps the latest views per instance in memory and requests are
+//! answered from there; only `/swaps/{id}/history` and `/stats` go back to
+//! the database. HTTP is the bare minimum of HTTP/1.1: GET only, no
+//! keep-alive, one response per connection. `/ws` upgrades to a WebSocket
+//! and `/events` streams Server-Sent Events, both pushing each transition
+//! as it is detected.
+//!
+//! With a token configured every request needs it, either as
+//! `Authorization: Bearer` or, for browser WebSockets that can't set
+//! headers, an `access_token` query parameter. With a certificate, or one
+//! made up at startup, every connection is TLS; otherwise the token crosses
+//! the network in the clear.
+//!
+//! With an agent token, `POST /agent/push` takes reports from agents (see
+//! [`crate::agent`]), which sign them instead of sending the bearer token.
+//! Their instances show up next to the local ones as `AGENT/INSTANCE`, and
+//! the transitions they report go out to subscribers and notifiers as if
+//! they had been seen here. Their history stays on the agent's machine.
+
+use anyhow::{anyhow, bail, Context, Result};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use sqlx::SqlitePool;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::BufReader;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, WriteHalf};
+use tokio::net::TcpListener;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio::sync::mpsc;
+use tokio::time::{sleep, timeout};
+use tokio_rustls::rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use tokio_rustls::rustls::ServerConfig;
+use tokio_rustls::TlsAcceptor;
+
+use crate::agent::{self, Push, KEEPALIVE, SIGNATURE_HEADER};
+use crate::commands::Tagged;
+use crate::db::{count_state_rows, fetch_history, Instance, PollError};
+use crate::digest;
+use crate::model::{SwapRow, SwapStats, SwapView, Transition};
+use crate::render::report_error;
+use crate::schema::Schema;
+use crate::watch::{Monitor, WatchContext};
+use crate::websocket::{self, Frame, OPCODE_CLOSE, OPCODE_PING, OPCODE_PONG, OPCODE_TEXT};
+
+/// Requests whose head doesn't fit are rejected
+const MAX_REQUEST_HEAD: usize = 8 * 1024;
+
+/// Agent reports larger than this are rejected
+const MAX_PUSH_BODY: usize = 16 * 1024 * 1024;
+
+/// Slow clients are dropped after this long
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Transitions buffered per subscriber; a client further behind skips ahead
+const EVENT_BACKLOG: usize = 256;
+
+/// Comment lines on idle event streams, so proxies don't time them out and
+/// closed clients are noticed
+const SSE_KEEPALIVE: Duration = Duration::from_secs(15);
+
+/// What the poll loop last saw for one instance.
+struct Snapshot {
+    instance: Instance,
+    rows: Vec<SwapRow>,
+    views: Vec<SwapView>,
+    pool: Option<SqlitePool>,
+    /// Set while the latest poll failed; `rows` and `views` are then stale
+    error: Option<String>,
+    last_poll: Option<DateTime<Utc>>,
+}
+
+/// What an agent last reported for one of its instances.
+struct AgentSnapshot {
+    /// `AGENT/INSTANCE`
+    name: String,
+    /// Views as the agent serialized them, tagged with `name`
+    swaps: Vec<Value>,
+    stats: Value,
+    error: Option<String>,
+    last_push: DateTime<Utc>,
+}
+
+impl AgentSnapshot {
+    /// Agents report at least every [`KEEPALIVE`]; one that missed a few has
+    /// lost its connection or stopped.
+    fn problem(&self, now: DateTime<Utc>) -> Option<String> {
+        let silent = (now - self.last_push).to_std().unwrap_or_default();
+        if silent > 3 * KEEPALIVE {
+            return Some(format!(
+                "no report from the agent for {}s",
+                silent.as_secs()
+            ));
+        }
+        self.error.clone()
+    }
+}
+
+/// State shared between the poll loop and request handlers.
+struct Server {
+    snapshots: RwLock<Vec<Snapshot>>,
+    agents: RwLock<Vec<AgentSnapshot>>,
+    /// Secret agent reports are signed with; `None` turns `/agent/push` off
+    agent_token: Option<String>,
+    /// Latest signature timestamp accepted per agent, so a report can't be
+    /// replayed
+    agent_clock: Mutex<HashMap<String, i64>>,
+    /// Transitions agents reported, for the poll loop's notifiers
+    pushed: mpsc::UnboundedSender<Vec<Transition>>,
+    /// Each transition as a JSON object, in `tail`'s shape
+    events: broadcast::Sender<String>,
+    multi: bool,
+    /// SHA-256 of the API token; comparing digests keeps the check from
+    /// leaking how much of a guess matched
+    token: Option<[u8; 32]>,
+}
+
+impl Server {
+    fn authorized(&self, request: &Request) -> bool {
+        let Some(ref expected) = self.token else {
+            return true;
+        };
+        let presented = request
+            .header("authorization")
+            .and_then(bearer_token)
+            .or_else(|| request.query_param("access_token"));
+        presented.is_some_and(|token| Sha256::digest(token.trim()).as_slice() == expected)
+    }
+}
+
+/// The token in an `Authorization` header, whichever way the client spelled
+/// the scheme; auth schemes are case-insensitive (RFC 7235).
+fn bearer_token(header: &str) -> Option<&str> {
+    let (scheme, token) = header.trim().split_once(' ')?;
+    scheme.eq_ignore_ascii_case("bearer").then(|| token.trim())
+}
+
+type Shared = Arc<Server>;
+
+/// A client connection, plain TCP or TLS on top of it.
+trait Connection: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> Connection for T {}
+
+type Stream = Box<dyn Connection>;
+
+/// Where `serve` gets the certificate it speaks HTTPS with.
+pub enum Tls {
+    /// PEM files: the certificate chain, leaf first, and its private key
+    Files { cert: PathBuf, key: PathBuf },
+    /// A certificate made up at startup for `localhost` and the listen
+    /// address, which clients have to be told to trust
+    SelfSigned,
+}
+
+impl Tls {
+    fn acceptor(&self, listen: SocketAddr) -> Result<TlsAcceptor> {
+        let (chain, key) = match self {
+            Tls::Files { cert, key } => (read_certs(cert)?, read_key(key)?),
+            Tls::SelfSigned => self_signed(listen)?,
+        };
+        let config = ServerConfig::builder()
+            .with_no_client_auth()
+            .with_single_cert(chain, key)
+            .context("the TLS certificate doesn't go with its key")?;
+        Ok(TlsAcceptor::from(Arc::new(config)))
+    }
+}
+
+fn read_certs(path: &Path) -> Result<Vec<CertificateDer<'static>>> {
+    let file = File::open(path).with_context(|| format!("open {}", path.display()))?;
+    let chain = rustls_pemfile::certs(&mut BufReader::new(file))
+        .collect::<Result<Vec<_>, _>>()
+        .with_context(|| format!("read certificates from {}", path.display()))?;
+    if chain.is_empty() {
+        bail!("no PEM certificates in {}", path.display());
+    }
+    Ok(chain)
+}
+
+fn read_key(path: &Path) -> Result<PrivateKeyDer<'static>> {
+    let file = File::open(path).with_context(|| format!("open {}", path.display()))?;
+    rustls_pemfile::private_key(&mut BufReader::new(file))
+        .with_context(|| format!("read the private key from {}", path.display()))?
+        .with_context(|| format!("no PEM private key in {}", path.display()))
+}
+
+/// A fresh certificate for `localhost` and the address `serve` listens on.
+/// Its fingerprint goes to stderr so clients can pin it.
+fn self_signed(
+    listen: SocketAddr,
+) -> Result<(Vec<CertificateDer<'static>>, PrivateKeyDer<'static>)> {
+    let mut names = vec!["localhost".to_string()];
+    if !listen.ip().is_unspecified() {
+        names.push(listen.ip().to_string());
+    }
+    names.extend(agent::hostname());
+    let certified =
+        rcgen::generate_simple_self_signed(names).context("generate a self-signed certificate")?;
+    let cert = certified.cert.der().clone();
+    eprintln!(
+        "Self-signed certificate, SHA-256 fingerprint {}",
+        fingerprint(&cert)
+    );
+    let key = PrivateKeyDer::try_from(certified.key_pair.serialize_der())
+        .map_err(|err| anyhow!("self-signed key: {err}"))?;
+    Ok((vec![cert], key))
+}
+
+/// `AB:CD:…`, as browsers and `openssl x509 -fingerprint` show it.
+fn fingerprint(cert: &[u8]) -> String {
+    Sha256::digest(cert)
+        .iter()
+        .map(|byte| format!("{byte:02X}"))
+        .collect::<Vec<_>>()
+        .join(":")
+}
+
+struct Request {
+    method: String,
+    /// Without the query string
+    path: String,
+    query: String,
+    headers: Vec<(String, String)>,
+}
+
+impl Request {
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// A raw query parameter; tokens never need percent-decoding.
+    fn query_param(&self, name: &str) -> Option<&str> {
+        self.query
+            .split('&')
+            .filter_map(|pair| pair.split_once('='))
+            .find(|(key, _)| *key == name)
+            .map(|(_, value)| value)
+    }
+}
+
+pub async fn run_serve(
+    mut ctx: WatchContext,
+    listen: SocketAddr,
+    tls: Option<Tls>,
+    token: Option<String>,
+    agent_token: Option<String>,
+) -> Result<()> {
+    let acceptor = tls.map(|tls| tls.acceptor(listen)).transpose()?;
+    let listener = TcpListener::bind(listen)
+        .await
+        .with_context(|| format!("listen on {listen}"))?;
+    let scheme = if acceptor.is_some() { "https" } else { "http" };
+    eprintln!("Serving the API on {scheme}://{}", listener.local_addr()?);
+    if token.is_none() && !listen.ip().is_loopback() {
+        eprintln!("Warning: the API is reachable beyond localhost without a token (--api-token)");
+    }
+
+    let mut monitors = Monitor::for_instances(
+        &ctx.instances,
+        &ctx.stuck,
+        &ctx.watchlist,
+        &ctx.pins,
+        ctx.history.as_ref(),
+    )
+    .into_iter()
+    .map(|monitor| monitor.with_notes(ctx.notes.clone()))
+    .collect::<Vec<_>>();
+    let mut watcher = ctx.watcher();
+    // Agents' instances are always tagged, and so are local ones next to them
+    let multi = monitors.len() > 1 || agent_token.is_some();
+    let (pushed_tx, mut pushed) = mpsc::unbounded_channel();
+    let shared: Shared = Arc::new(Server {
+        snapshots: RwLock::new(
+            ctx.instances
+                .iter()
+                .map(|instance| Snapshot {
+                    instance: instance.clone(),
+                    rows: Vec::new(),
+                    views: Vec::new(),
+                    pool: None,
+                    error: None,
+                    last_poll: None,
+                })
+                .collect(),
+        ),
+        agents: RwLock::new(Vec::new()),
+        agent_token,
+        agent_clock: Mutex::new(HashMap::new()),
+        pushed: pushed_tx,
+        events: broadcast::channel(EVENT_BACKLOG).0,
+        multi,
+        token: token.map(|token| Sha256::digest(token.trim()).into()),
+    });
+    tokio::spawn(accept(listener, acceptor, shared.clone()));
+    ctx.chain.start();
+
+    loop {
+        let mut activity = false;
+        let mut any_ok = false;
+        for (i, monitor) in monitors.iter_mut().enumerate() {
+            let role = monitor.instance.role;
+            let polled = match monitor.poll().await {
+                Ok(rows) => Ok((rows.clone(), monitor.update(rows).await)),
+                Err(err) => Err(err),
+            };
+            let mut snapshots = shared.snapshots.write().unwrap();
+            let snapshot = &mut snapshots[i];
+            match polled {
+                Ok((rows, (mut views, transitions))) => {
+                    activity |= !transitions.is_empty();
+                    ctx.notifiers.dispatch(&transitions);
+                    ctx.record_metrics(&monitor.instance, &views, &transitions);
+                    for transition in &transitions {
+                        // Fails only while nobody is subscribed
+                        let _ = shared.events.send(serde_json::to_string(transition)?);
+                    }
+                    ctx.chain.annotate(&monitor.instance.name, &mut views, role);
+                    any_ok = true;
+
+                    snapshot.rows = rows;
+                    snapshot.views = views;
+                    snapshot.pool = monitor.source.pool.clone();
+                    snapshot.error = None;
+                    snapshot.last_poll = Some(Utc::now());
+                }
+                Err(err) => {
+                    let message = match multi {
+                        true => format!("{}: {err}", monitor.instance.name),
+                        false => err.to_string(),
+                    };
+                    // Waiting for ASB to create the database isn't worth a line per poll
+                    if !matches!(err, PollError::NotFound(_)) {
+                        report_error(true, &message);
+                    }
+                    snapshot.error = Some(err.to_string());
+                }
+            }
+        }
+        if any_ok {
+            ctx.schedule.record(activity);
+        }
+        ctx.notifiers.dispatch_liquidity(&ctx.chain.take_alerts());
+        ctx.notifiers.dispatch_fees(&ctx.chain.take_fee_alerts());
+        ctx.notifiers.dispatch_asb(&ctx.chain.take_asb_alerts());
+        ctx.notifiers.dispatch_reach(&ctx.chain.take_reach_alerts());
+        ctx.notifiers
+            .dispatch_quotes(&ctx.chain.take_quote_alerts());
+        let log_alerts = ctx.take_log_alerts();
+        ctx.notifiers.dispatch_log(&log_alerts);
+        digest::send_if_due(&mut ctx);
+        ctx.flush_metrics();
+        let failures: Vec<String> = monitors
+            .iter()
+            .zip(shared.snapshots.read().unwrap().iter())
+            .filter_map(|(monitor, snapshot)| {
+                let error = snapshot.error.as_ref()?;
+                Some(format!("{}: {error}", monitor.instance.name))
+            })
+            .collect();
+        ctx.beat(&failures);
+        if let Some(err) = ctx.notifiers.take_error() {
+            report_error(true, &format!("Notification failed: {err}"));
+        }
+        if let Some(err) = ctx.history_error() {
+            report_error(true, &format!("History write failed: {err}"));
+        }
+        if let Some(err) = ctx.metrics_error() {
+            report_error(true, &format!("Metrics write failed: {err}"));
+        }
+        if let Some(err) = ctx.heartbeat_error() {
+            report_error(true, &format!("Heartbeat ping failed: {err}"));
+        }
+
+        let wait = ctx.schedule.next_wait(&watcher);
+        tokio::select! {
+            _ = sleep(wait) => {}
+            _ = watcher.changed() => {}
+            _ = ctx.chain.updated() => {}
+            Some(transitions) = pushed.recv() => ctx.notifiers.dispatch(&transitions),
+        }
+    }
+}
+
+async fn accept(listener: TcpListener, acceptor: Option<TlsAcceptor>, shared: Shared) {
+    loop {
+        match listener.accept().await {
+            Ok((stream, _)) => {
+                let Some(ref acceptor) = acceptor else {
+                    tokio::spawn(handle(Box::new(stream), shared.clone()));
+                    continue;
+                };
+                let (acceptor, shared) = (acceptor.clone(), shared.clone());
+                tokio::spawn(async move {
+                    // A failed handshake is the client's problem (often one
+                    // that doesn't trust the certificate); nothing to answer
+                    if let Ok(Ok(stream)) = timeout(REQUEST_TIMEOUT, acceptor.accept(stream)).await
+                    {
+                        handle(Box::new(stream), shared).await;
+                    }
+                });
+            }
+            Err(err) => report_error(true, &format!("Accept failed: {err}")),
+        }
+    }
+}
+
+async fn handle(mut stream: Stream, shared: Shared) {
+    let (request, body) = match timeout(REQUEST_TIMEOUT, read_head(&mut stream)).await {
+        Ok(Some((head, body))) => (parse_request(&head), body),
+        Ok(None) => (None, Vec::new()),
+        Err(_) => return,
+    };
+    let response = match request {
+        // Agents sign their reports instead of sending the bearer token
+        Some(request) if request.method == "POST" && request.path == "/agent/push" => {
+            match timeout(REQUEST_TIMEOUT, read_body(&mut stream, &request, body)).await {
+                Ok(Ok(body)) => receive_push(&shared, &request, &body),
+                Ok(Err(response)) => response,
+                Err(_) => return,
+            }
+        }
+        Some(request) if !shared.authorized(&request) => {
+            Response::error(401, "missing or wrong bearer token")
+        }
+        Some(request) if request.method == "GET" && request.path == "/ws" => {
+            return serve_websocket(stream, &request, &shared).await;
+        }
+        Some(request) if request.method == "GET" && request.path == "/events" => {
+            return serve_events(stream, &shared).await;
+        }
+        Some(request) => route(&request, &shared).await,
+        None => Response::error(400, "malformed request"),
+    };
+    // The client may already be gone; nothing useful to do about it
+    let _ = stream.write_all(&response.into_bytes()).await;
+    let _ = stream.shutdown().await;
+}
+
+/// Everything up to the blank line ending the headers, and whatever part of
+/// the body came with it.
+async fn read_head(stream: &mut Stream) -> Option<(String, Vec<u8>)> {
+    let mut head = Vec::new();
+    let mut buf = [0u8; 1024];
+    let end = loop {
+        if let Some(i) = head.windows(4).position(|w| w == b"\r\n\r\n") {
+            break i;
+        }
+        let n = stream.read(&mut buf).await.ok()?;
+        if n == 0 || head.len() + n > MAX_REQUEST_HEAD {
+            return None;
+        }
+        head.extend_from_slice(&buf[..n]);
+    };
+    let body = head.split_off(end + 4);
+    head.truncate(end);
+    Some((String::from_utf8(head).ok()?, body))
+}
+
+/// A body announced by `Content-Length`, starting with the part that came
+/// with the head.
+async fn read_body(
+    stream: &mut Stream,
+    request: &Request,
+    mut body: Vec<u8>,
+) -> Result<Vec<u8>, Response> {
+    let Some(length) = request
+        .header("content-length")
+        .and_then(|value| value.parse::<usize>().ok())
+    else {
+        return Err(Response::error(411, "a Content-Length is required"));
+    };
+    if length > MAX_PUSH_BODY {
+        return Err(Response::error(413, "report too large"));
+    }
+    let mut buf = [0u8; 8192];
+    while body.len() < length {
+        match stream.read(&mut buf).await {
+            Ok(0) | Err(_) => return Err(Response::error(400, "body ended early")),
+            Ok(n) => body.extend_from_slice(&buf[..n]),
+        }
+    }
+    body.truncate(length);
+    Ok(body)
+}
+
+fn parse_request(head: &str) -> Option<Request> {
+    let mut lines = head.lines();
+    let mut parts = lines.next()?.split_whitespace();
+    let method = parts.next()?.to_string();
+    let target = parts.next()?;
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let headers = lines
+        .filter_map(|line| line.split_once(':'))
+        .map(|(key, value)| (key.trim().to_string(), value.trim().to_string()))
+        .collect();
+    Some(Request {
+        method,
+        path: path.to_string(),
+        query: query.to_string(),
+        headers,
+    })
+}
+
+/// Push every transition to the client until it closes or falls off.
+async fn serve_websocket(mut stream: Stream, request: &Request, shared: &Shared) {
+    let upgrade = request
+        .header("upgrade")
+        .is_some_and(|value| value.eq_ignore_ascii_case("websocket"));
+    let Some(key) = request.header("sec-websocket-key").filter(|_| upgrade) else {
+        let response = Response::error(400, "expected a WebSocket upgrade");
+        let _ = stream.write_all(&response.into_bytes()).await;
+        return;
+    };
+
+    let events = shared.events.subscribe();
+    if stream
+        .write_all(websocket::handshake_response(key).as_bytes())
+        .await
+        .is_err()
+    {
+        return;
+    }
+
+    // Reads aren't cancel-safe, so a task owns the read half and hands
+    // complete frames over
+    let (mut reader, writer) = tokio::io::split(stream);
+    let (frames_tx, frames) = mpsc::channel(8);
+    let reader_task = tokio::spawn(async move {
+        while let Ok(frame) = websocket::read_frame(&mut reader).await {
+            if frames_tx.send(frame).await.is_err() {
+                break;
+            }
+        }
+    });
+    pump_websocket(writer, events, frames).await;
+    reader_task.abort();
+}
+
+async fn pump_websocket(
+    mut writer: WriteHalf<Stream>,
+    mut events: broadcast::Receiver<String>,
+    mut frames: mpsc::Receiver<Frame>,
+) {
+    loop {
+        tokio::select! {
+            event = events.recv() => match event {
+                Ok(json) => {
+                    if websocket::write_frame(&mut writer, OPCODE_TEXT, json.as_bytes()).await.is_err() {
+                        return;
+                    }
+                }
+                // A client this far behind just misses the oldest events
+                Err(RecvError::Lagged(_)) => {}
+                Err(RecvError::Closed) => return,
+            },
+            frame = frames.recv() => match frame {
+                Some(Frame { opcode: OPCODE_PING, payload }) => {
+                    if websocket::write_frame(&mut writer, OPCODE_PONG, &payload).await.is_err() {
+                        return;
+                    }
+                }
+                Some(Frame { opcode: OPCODE_CLOSE, payload }) => {
+                    let _ = websocket::write_frame(&mut writer, OPCODE_CLOSE, &payload).await;
+                    return;
+                }
+                // Clients have nothing to say besides pings and closes
+                Some(_) => {}
+                None => return,
+            },
+        }
+    }
+}
+
+/// Stream every transition as an SSE `transition` event until the client
+/// goes away.
+async fn serve_events(mut stream: Stream, shared: &Shared) {
+    let mut events = shared.events.subscribe();
+    let head = "HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nCache-Control: no-cache\r\n\
+                Connection: close\r\n\r\n";
+    if stream.write_all(head.as_bytes()).await.is_err() {
+        return;
+    }
+
+    loop {
+        let chunk = match timeout(SSE_KEEPALIVE, events.recv()).await {
+            Ok(Ok(json)) => format!("event: transition\ndata: {json}\n\n"),
+            // A client this far behind just misses the oldest events
+            Ok(Err(RecvError::Lagged(_))) => continue,
+            Ok(Err(RecvError::Closed)) => return,
+            Err(_) => ": keepalive\n\n".to_string(),
+        };
+        if stream.write_all(chunk.as_bytes()).await.is_err() {
+            return;
+        }
+    }
+}
+
+async fn route(request: &Request, shared: &Shared) -> Response {
+    if request.method != "GET" {
+        return Response::error(405, "only GET is supported");
+    }
+    let segments: Vec<&str> = request.path.split('/').filter(|s| !s.is_empty()).collect();
+    match segments.as_slice() {
+        ["health"] => health(shared),
+        ["swaps"] => match all_views(shared) {
+            Ok(views) => Response::json(200, &views),
+            Err(err) => Response::error(500, &err.to_string()),
+        },
+        ["swaps", swap_id] => match all_views(shared) {
+            Ok(views) => match views.iter().find(|view| view["swap_id"] == *swap_id) {
+                Some(view) => Response::json(200, view),
+                None => Response::error(404, &format!("no swap with id {swap_id}")),
+            },
+            Err(err) => Response::error(500, &err.to_string()),
+        },
+        ["swaps", swap_id, "history"] => history(shared, swap_id).await,
+        ["stats"] => stats(shared).await,
+        _ => Response::error(404, "not found"),
+    }
+}
+
+/// Local views, then every agent's.
+fn all_views(shared: &Shared) -> serde_json::Result<Vec<Value>> {
+    let mut views = Vec::new();
+    for view in shared
+        .snapshots
+        .read()
+        .unwrap()
+        .iter()
+        .flat_map(|s| &s.views)
+    {
+        views.push(serde_json::to_value(view)?);
+    }
+    let agents = shared.agents.read().unwrap();
+    views.extend(agents.iter().flat_map(|a| a.swaps.iter().cloned()));
+    Ok(views)
+}
+
+/// 200 while every instance's latest poll worked and every agent reports
+/// in, 503 otherwise.
+fn health(shared: &Shared) -> Response {
+    let snapshots = shared.snapshots.read().unwrap();
+    let agents = shared.agents.read().unwrap();
+    let now = Utc::now();
+    let healthy = snapshots
+        .iter()
+        .all(|s| s.error.is_none() && s.last_poll.is_some())
+        && agents.iter().all(|a| a.problem(now).is_none());
+    let instances: Vec<_> = snapshots
+        .iter()
+        .map(|s| {
+            json!({
+                "name": s.instance.name,
+                "ok": s.error.is_none() && s.last_poll.is_some(),
+                "error": s.error,
+                "last_poll": s.last_poll.map(|t| t.to_rfc3339()),
+                "swaps": s.views.len(),
+            })
+        })
+        .chain(agents.iter().map(|a| {
+            let problem = a.problem(now);
+            json!({
+                "name": a.name,
+                "ok": problem.is_none(),
+                "error": problem,
+                "last_poll": a.last_push.to_rfc3339(),
+                "swaps": a.swaps.len(),
+            })
+        }))
+        .collect();
+    let status = if healthy { "ok" } else { "degraded" };
+    Response::json(
+        if healthy { 200 } else { 503 },
+        &json!({ "status": status, "instances": instances }),
+    )
+}
+
+async fn history(shared: &Shared, swap_id: &str) -> Response {
+    let pool = {
+        let snapshots = shared.snapshots.read().unwrap();
+        snapshots
+            .iter()
+            .find(|s| s.rows.iter().any(|row| row.swap_id == swap_id))
+            .and_then(|s| s.pool.clone())
+    };
+    let Some(pool) = pool else {
+        let agents = shared.agents.read().unwrap();
+        if agents
+            .iter()
+            .any(|a| a.swaps.iter().any(|view| view["swap_id"] == swap_id))
+        {
+            return Response::error(404, "the history of an agent's swap is on the agent");
+        }
+        return Response::error(404, &format!("no swap with id {swap_id}"));
+    };
+    match fetch_history(&pool, swap_id).await {
+        Ok(history) => Response::json(200, &history),
+        Err(err) => Response::error(500, &format!("query swap history: {err:#}")),
+    }
+}
+
+/// Same shape as `stats --output json`.
+async fn stats(shared: &Shared) -> Response {
+    let loaded: Vec<_> = {
+        let snapshots = shared.snapshots.read().unwrap();
+        snapshots
+            .iter()
+            .map(|s| (s.instance.clone(), s.rows.clone(), s.pool.clone()))
+            .collect()
+    };
+
+    let mut all_stats = Vec::new();
+    for (instance, rows, pool) in loaded {
+        let (total_state_rows, schema) = match pool {
+            Some(pool) => match count_state_rows(&pool).await {
+                Ok(count) => (count, Schema::detect(&pool).await.ok()),
+                Err(err) => return Response::error(500, &format!("count state rows: {err:#}")),
+            },
+            None => (0, None),
+        };
+        all_stats.push((
+            instance.name,
+            SwapStats {
+                schema,
+                ..SwapStats::from_latest(&rows, total_state_rows, instance.role)
+            },
+        ));
+    }
+
+    if shared.multi {
+        let mut tagged = Vec::new();
+        for (name, stats) in &all_stats {
+            tagged.push(json!(Tagged {
+                instance: Some(name.as_str()),
+                record: stats,
+            }));
+        }
+        let agents = shared.agents.read().unwrap();
+        for agent in agents.iter().filter(|a| !a.stats.is_null()) {
+            tagged.push(json!(Tagged {
+                instance: Some(agent.name.as_str()),
+                record: &agent.stats,
+            }));
+        }
+        Response::json(200, &tagged)
+    } else {
+        Response::json(200, &all_stats[0].1)
+    }
+}
+
+/// `POST /agent/push`: check the signature and replace everything the
+/// agent reported before.
+fn receive_push(shared: &Shared, request: &Request, body: &[u8]) -> Response {
+    let Some(ref token) = shared.agent_token else {
+        return Response::error(404, "not accepting agent reports (no --agent-token)");
+    };
+    let Some(signature) = request.header(SIGNATURE_HEADER) else {
+        return Response::error(403, &format!("missing {SIGNATURE_HEADER} header"));
+    };
+    let signed_at = match agent::verify(token, signature, body, Utc::now().timestamp_millis()) {
+        Ok(signed_at) => signed_at,
+        Err(err) => return Response::error(403, &err.to_string()),
+    };
+    let push: Push = match serde_json::from_slice(body) {
+        Ok(push) => push,
+        Err(err) => return Response::error(400, &format!("malformed report: {err}")),
+    };
+    if push.agent.is_empty() || push.agent.contains('/') {
+        return Response::error(400, "agent names can't be empty or contain '/'");
+    }
+    {
+        let mut clock = shared.agent_clock.lock().unwrap();
+        let last = clock.entry(push.agent.clone()).or_default();
+        if signed_at <= *last {
+            return Response::error(403, "report is older than the last one accepted");
+        }
+        *last = signed_at;
+    }
+
+    let mut transitions: Vec<Transition> = Vec::new();
+    for pushed in push.transitions {
+        let mut transition = pushed.into_transition();
+        let instance = transition.instance.take().unwrap_or_default();
+        transition.instance = Some(format!("{}/{instance}", push.agent));
+        // Fails only while nobody is subscribed
+        if let Ok(json) = serde_json::to_string(&transition) {
+            let _ = shared.events.send(json);
+        }
+        transitions.push(transition);
+    }
+    let accepted = transitions.len();
+    if !transitions.is_empty() {
+        // The poll loop outlives every connection
+        let _ = shared.pushed.send(transitions);
+    }
+
+    let now = Utc::now();
+    let prefix = format!("{}/", push.agent);
+    let mut agents = shared.agents.write().unwrap();
+    agents.retain(|a| !a.name.starts_with(&prefix));
+    for report in push.instances {
+        let name = format!("{prefix}{}", report.name);
+        let swaps = report
+            .swaps
+            .into_iter()
+            .map(|mut view| {
+                if let Some(view) = view.as_object_mut() {
+                    view.insert("instance".to_string(), Value::String(name.clone()));
+                }
+                view
+            })
+            .collect();
+        agents.push(AgentSnapshot {
+            name,
+            swaps,
+            stats: report.stats,
+            error: report.error,
+            last_push: now,
+        });
+    }
+    agents.sort_by(|a, b| a.name.cmp(&b.name));
+    Response::json(200, &json!({ "accepted": accepted }))
+}
+
+struct Response {
+    status: u16,
+    body: String,
+}
+
+impl Response {
+    fn json(status: u16, body: &impl Serialize) -> Response {
+        match serde_json::to_string_pretty(body) {
+            Ok(body) => Response { status, body },
+            Err(err) => Response::error(500, &err.to_string()),
+        }
+    }
+
+    fn error(status: u16, message: &str) -> Response {
+        Response {
+            status,
+            body: json!({ "error": message }).to_string(),
+        }
+    }
+
+    fn into_bytes(self) -> Vec<u8> {
+        let reason = match self.status {
+            200 => "OK",
+            400 => "Bad Request",
+            401 => "Unauthorized",
+            403 => "Forbidden",
+            404 => "Not Found",
+            405 => "Method Not Allowed",
+            411 => "Length Required",
+            413 => "Content Too Large",
+            503 => "Service Unavailable",
+            _ => "Internal Server Error",
+        };
+        let extra = match self.status {
+            401 => "WWW-Authenticate: Bearer realm=\"wraithswap\"\r\n",
+            405 => "Allow: GET\r\n",
+            _ => "",
+        };
+        let body = self.body + "\n";
+        format!(
+            "HTTP/1.1 {} {reason}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\
+             {extra}Connection: close\r\n\r\n{body}",
+            self.status,
+            body.len(),
+        )
+        .into_bytes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bearer_scheme_is_case_insensitive() {
+        assert_eq!(bearer_token("Bearer abc"), Some("abc"));
+        assert_eq!(bearer_token("bearer abc"), Some("abc"));
+        assert_eq!(bearer_token("BEARER  abc "), Some("abc"));
+        assert_eq!(bearer_token("Basic abc"), None);
+        assert_eq!(bearer_token("Bearer"), None);
+    }
+
+    #[test]
+    fn self_signed_certificates_load() {
+        let listen = "127.0.0.1:8443".parse().unwrap();
+        assert!(Tls::SelfSigned.acceptor(listen).is_ok());
+    }
+
+    #[test]
+    fn fingerprints_look_like_openssl() {
+        let print = fingerprint(b"");
+        assert_eq!(print.len(), 32 * 3 - 1);
+        assert!(print.starts_with("E3:B0:C4:42"));
+    }
+}
@@ -0,0 +1,183 @@
+//! The order of the swap table (`--sort`, `s` and `S` in the full-screen
+//! view). Newest first by default, which buries a swap that has been stuck
+//! for days under everything that came after it; sorting by time in state
+//! brings it back to the top.
+//!
+//! The full-screen view remembers the last order picked there, in the cache
+//! directory, for the next start without `--sort`.
+
+use anyhow::{bail, Result};
+use std::cmp::Ordering;
+use std::fmt;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use crate::model::{parse_entered_at, Role, SwapView};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortKey {
+    /// When the swap entered its latest state
+    Entered,
+    /// Along the happy path, then the cancel and refund states by name
+    State,
+    /// How long the swap has been in its latest state
+    InState,
+    /// The BTC amount
+    Amount,
+}
+
+const KEYS: [SortKey; 4] = [
+    SortKey::Entered,
+    SortKey::State,
+    SortKey::InState,
+    SortKey::Amount,
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SortOrder {
+    pub key: SortKey,
+    pub descending: bool,
+}
+
+impl Default for SortOrder {
+    fn default() -> Self {
+        SortKey::Entered.into()
+    }
+}
+
+/// In the direction that puts what needs attention first: newest, least
+/// far along, longest waiting, biggest.
+impl From<SortKey> for SortOrder {
+    fn from(key: SortKey) -> Self {
+        Self {
+            key,
+            descending: key != SortKey::State,
+        }
+    }
+}
+
+impl SortKey {
+    fn name(self) -> &'static str {
+        match self {
+            SortKey::Entered => "entered",
+            SortKey::State => "state",
+            SortKey::InState => "in_state",
+            SortKey::Amount => "amount",
+        }
+    }
+}
+
+impl SortOrder {
+    /// Stable, so swaps that compare equal stay newest first.
+    pub fn sort(&self, views: &mut [&SwapView], role: Role) {
+        if *self == Self::default() {
+            return;
+        }
+        views.sort_by(|a, b| {
+            let order = match self.key {
+                SortKey::Entered => entered(a).cmp(&entered(b)),
+                // Longer in state is an earlier entry
+                SortKey::InState => entered(b).cmp(&entered(a)),
+                SortKey::State => state_rank(a, role).cmp(&state_rank(b, role)),
+                // Swaps without a known amount go last either way
+                SortKey::Amount => match (a.details.btc_sats, b.details.btc_sats) {
+                    (Some(a), Some(b)) => a.cmp(&b),
+                    (Some(_), None) => return Ordering::Less,
+                    (None, Some(_)) => return Ordering::Greater,
+                    (None, None) => Ordering::Equal,
+                },
+            };
+            match self.descending {
+                true => order.reverse(),
+                false => order,
+            }
+        });
+    }
+
+    /// The next key, in its own default direction.
+    pub fn next_key(self) -> Self {
+        let at = KEYS.iter().position(|&key| key == self.key).unwrap_or(0);
+        KEYS[(at + 1) % KEYS.len()].into()
+    }
+
+    pub fn reversed(self) -> Self {
+        Self {
+            descending: !self.descending,
+            ..self
+        }
+    }
+
+    /// The order last picked in the full-screen view, if any.
+    pub fn remembered() -> Option<Self> {
+        let body = std::fs::read_to_string(remember_path()?).ok()?;
+        body.trim().parse().ok()
+    }
+
+    /// Best effort, like the fiat price cache; an order that isn't
+    /// remembered only costs a key press next time.
+    pub fn remember(&self) {
+        let Some(path) = remember_path() else {
+            return;
+        };
+        if let Some(dir) = path.parent() {
+            let _ = std::fs::create_dir_all(dir);
+        }
+        let _ = std::fs::write(path, format!("{self}\n"));
+    }
+}
+
+impl FromStr for SortOrder {
+    type Err = anyhow::Error;
+
+    /// `KEY` or `KEY:asc` / `KEY:desc`, e.g. `in_state` or `amount:asc`.
+    fn from_str(s: &str) -> Result<Self> {
+        let (key, direction) = match s.trim().split_once(':') {
+            Some((key, direction)) => (key, Some(direction)),
+            None => (s.trim(), None),
+        };
+        let key = match key.to_ascii_lowercase().replace('-', "_").as_str() {
+            "entered" | "entered_at" => SortKey::Entered,
+            "state" => SortKey::State,
+            "in_state" | "duration" => SortKey::InState,
+            "amount" | "btc" => SortKey::Amount,
+            other => {
+                bail!("unknown sort key {other:?} (expected entered, state, in_state or amount)")
+            }
+        };
+        let mut order = SortOrder::from(key);
+        match direction.map(str::to_ascii_lowercase).as_deref() {
+            None => {}
+            Some("asc") => order.descending = false,
+            Some("desc") => order.descending = true,
+            Some(other) => bail!("unknown sort direction {other:?} (expected asc or desc)"),
+        }
+        Ok(order)
+    }
+}
+
+impl fmt::Display for SortOrder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let direction = match self.descending {
+            true => "desc",
+            false => "asc",
+        };
+        write!(f, "{}:{direction}", self.key.name())
+    }
+}
+
+fn entered(view: &SwapView) -> Option<chrono::DateTime<chrono::Utc>> {
+    parse_entered_at(&view.entered_at)
+}
+
+/// Happy-path steps in order, then everything off it by name.
+fn state_rank(view: &SwapView, role: Role) -> (usize, &str) {
+    let step = view
+        .state
+        .progress(role)
+        .map_or(usize::MAX, |(step, _)| step);
+    (step, view.state.as_str())
+}
+
+fn remember_path() -> Option<PathBuf> {
+    Some(dirs::cache_dir()?.join("wraithswap/sort"))
+}
@@ -0,0 +1,63 @@
+//! The parts of systemd's service protocol daemon mode speaks: `sd_notify`
+//! messages for readiness and the watchdog, and detecting a journald
+//! stdout. All of it is a no-op outside systemd.
+
+use std::os::unix::net::UnixDatagram;
+use std::time::Duration;
+
+/// Send `state` (e.g. `READY=1`) to `$NOTIFY_SOCKET`. Failures are ignored,
+/// as with `sd_notify(3)`: the service must run without a manager too.
+pub fn notify(state: &str) {
+    let Some(path) = std::env::var_os("NOTIFY_SOCKET") else {
+        return;
+    };
+    let Ok(socket) = UnixDatagram::unbound() else {
+        return;
+    };
+    let path = path.to_string_lossy();
+    // A leading `@` names a socket in Linux's abstract namespace
+    let _ = match path.strip_prefix('@') {
+        Some(name) => send_abstract(&socket, name, state),
+        None => socket.send_to(state.as_bytes(), &*path).map(drop),
+    };
+}
+
+#[cfg(target_os = "linux")]
+fn send_abstract(socket: &UnixDatagram, name: &str, state: &str) -> std::io::Result<()> {
+    use std::os::linux::net::SocketAddrExt;
+    let addr = std::os::unix::net::SocketAddr::from_abstract_name(name)?;
+    socket.send_to_addr(state.as_bytes(), &addr).map(drop)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn send_abstract(_: &UnixDatagram, _: &str, _: &str) -> std::io::Result<()> {
+    Ok(())
+}
+
+/// How often to ping the watchdog: half of `WatchdogSec=`, as systemd
+/// recommends. `None` when the watchdog is off or meant for another process.
+pub fn watchdog_interval() -> Option<Duration> {
+    let usec: u64 = std::env::var("WATCHDOG_USEC").ok()?.parse().ok()?;
+    if let Ok(pid) = std::env::var("WATCHDOG_PID") {
+        if pid.parse() != Ok(std::process::id()) {
+            return None;
+        }
+    }
+    (usec > 0).then(|| Duration::from_micros(usec / 2))
+}
+
+/// Whether stdout is connected to the journal, per `$JOURNAL_STREAM`.
+pub fn stdout_is_journal() -> bool {
+    let Some((dev, ino)) = std::env::var("JOURNAL_STREAM").ok().and_then(|stream| {
+        let (dev, ino) = stream.split_once(':')?;
+        Some((dev.parse::<u64>().ok()?, ino.parse::<u64>().ok()?))
+    }) else {
+        return false;
+    };
+    // SAFETY: fstat only writes into the zeroed struct we hand it
+    let mut stat: libc::stat = unsafe { std::mem::zeroed() };
+    if unsafe { libc::fstat(libc::STDOUT_FILENO, &mut stat) } != 0 {
+        return false;
+    }
+    stat.st_dev as u64 == dev && stat.st_ino as u64 == ino
+}
@@ -0,0 +1,329 @@
+//! Color themes (`--theme`) and per-state colors (`--state-color`,
+//! `[state_colors]` in the config file). The default palette is made for
+//! dark terminals; its yellows and cyans are hard to read on light ones,
+//! and its reds and greens are hard to tell apart for many people.
+//!
+//! Everything drawn in color goes through a [`Tone`], which the theme turns
+//! into a color. `NO_COLOR` picks the monochrome theme, which keeps bold,
+//! underline and reverse video, so the selection stays visible; `--color
+//! never` drops those too.
+
+use anyhow::{bail, Result};
+use colored::{Color, ColoredString, Colorize};
+use serde::Deserialize;
+use std::str::FromStr;
+use std::sync::Mutex;
+
+use crate::model::Role;
+use crate::state::{StateSelection, SwapState};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub enum ThemeName {
+    #[default]
+    Default,
+    /// Colored backgrounds, readable on dark and light terminals alike
+    HighContrast,
+    /// Blue for success and red for failure, never red against green
+    ColorblindSafe,
+    /// Bold, underline and reverse video only
+    Monochrome,
+}
+
+impl FromStr for ThemeName {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().replace('_', "-").as_str() {
+            "default" => Ok(ThemeName::Default),
+            "high-contrast" => Ok(ThemeName::HighContrast),
+            "colorblind-safe" | "colorblind" => Ok(ThemeName::ColorblindSafe),
+            "monochrome" | "mono" => Ok(ThemeName::Monochrome),
+            other => bail!(
+                "unknown theme: {other} (expected default, high-contrast, colorblind-safe or \
+                 monochrome)"
+            ),
+        }
+    }
+}
+
+impl TryFrom<String> for ThemeName {
+    type Error = anyhow::Error;
+
+    fn try_from(s: String) -> Result<Self> {
+        s.parse()
+    }
+}
+
+/// What a piece of text says, which the theme picks a color for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Tone {
+    /// Completed swaps, healthy checks
+    Good,
+    /// Things to keep an eye on
+    Warn,
+    /// Failures and errors
+    Bad,
+    /// A mainnet punish, where real funds were lost
+    Alarm,
+    /// Swap setup, headings
+    Info,
+    /// Funds being locked
+    Active,
+    /// The cancel path, watched peers
+    Accent,
+}
+
+/// Colors and attributes, as `--state-color` takes them: `red`, `bold
+/// white on red`, `bright-blue underline`, or `plain`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Style {
+    fg: Option<Color>,
+    bg: Option<Color>,
+    bold: bool,
+    dimmed: bool,
+    italic: bool,
+    underline: bool,
+    reversed: bool,
+}
+
+impl Style {
+    const fn fg(color: Color) -> Self {
+        Self {
+            fg: Some(color),
+            bg: None,
+            bold: false,
+            dimmed: false,
+            italic: false,
+            underline: false,
+            reversed: false,
+        }
+    }
+
+    const fn on(self, color: Color) -> Self {
+        Self {
+            bg: Some(color),
+            ..self
+        }
+    }
+
+    const fn bold(self) -> Self {
+        Self { bold: true, ..self }
+    }
+
+    const fn plain() -> Self {
+        Self {
+            fg: None,
+            bg: None,
+            bold: false,
+            dimmed: false,
+            italic: false,
+            underline: false,
+            reversed: false,
+        }
+    }
+
+    pub fn apply(&self, text: ColoredString) -> ColoredString {
+        let mut text = text;
+        if let Some(fg) = self.fg {
+            text = text.color(fg);
+        }
+        if let Some(bg) = self.bg {
+            text = text.on_color(bg);
+        }
+        if self.bold {
+            text = text.bold();
+        }
+        if self.dimmed {
+            text = text.dimmed();
+        }
+        if self.italic {
+            text = text.italic();
+        }
+        if self.underline {
+            text = text.underline();
+        }
+        if self.reversed {
+            text = text.reversed();
+        }
+        text
+    }
+}
+
+impl FromStr for Style {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut style = Style::plain();
+        let mut words = s.split_whitespace().map(|w| w.to_ascii_lowercase());
+        let mut background = false;
+        while let Some(word) = words.next() {
+            match word.as_str() {
+                "plain" | "none" | "normal" => {}
+                "bold" => style.bold = true,
+                "dim" | "dimmed" => style.dimmed = true,
+                "italic" => style.italic = true,
+                "underline" => style.underline = true,
+                "reverse" | "reversed" => style.reversed = true,
+                "on" => background = true,
+                _ => {
+                    // `bright-red`, `bright_red` or `bright red`
+                    let name = match word.as_str() {
+                        "bright" => match words.next() {
+                            Some(next) => format!("bright {next}"),
+                            None => bail!("bright what? (in {s:?})"),
+                        },
+                        other => other.replace(['-', '_'], " "),
+                    };
+                    let Ok(color) = name.parse::<Color>() else {
+                        bail!(
+                            "unknown color or attribute {name:?} in {s:?} (expected colors like \
+                             red or bright-blue, `on <color>`, bold, dim, italic, underline, \
+                             reverse or plain)"
+                        );
+                    };
+                    match std::mem::take(&mut background) {
+                        true => style.bg = Some(color),
+                        false => style.fg = Some(color),
+                    }
+                }
+            }
+        }
+        if background {
+            bail!("`on` needs a background color (in {s:?})");
+        }
+        Ok(style)
+    }
+}
+
+/// The theme in use, and the states given colors of their own.
+struct Theme {
+    name: ThemeName,
+    states: Vec<(StateSelection, Style)>,
+}
+
+static THEME: Mutex<Theme> = Mutex::new(Theme {
+    name: ThemeName::Default,
+    states: Vec::new(),
+});
+
+pub fn set(name: ThemeName, states: Vec<(StateSelection, Style)>) {
+    *THEME.lock().unwrap() = Theme { name, states };
+}
+
+/// The color a state was given with `--state-color`, the first that
+/// matches in the order given.
+pub fn state_style(state: &SwapState, role: Role) -> Option<Style> {
+    let theme = THEME.lock().unwrap();
+    theme
+        .states
+        .iter()
+        .find(|(states, _)| states.matches(state, role))
+        .map(|(_, style)| style.clone())
+}
+
+fn tone_style(tone: Tone) -> Style {
+    use Color::*;
+    let name = THEME.lock().unwrap().name;
+    match (name, tone) {
+        (ThemeName::Default, Tone::Good) => Style::fg(Green),
+        (ThemeName::Default, Tone::Warn) => Style::fg(Yellow),
+        (ThemeName::Default, Tone::Bad) => Style::fg(Red),
+        (ThemeName::Default, Tone::Alarm) => Style::fg(White).on(Red).bold(),
+        (ThemeName::Default, Tone::Info) => Style::fg(Cyan),
+        (ThemeName::Default, Tone::Active) => Style::fg(Blue),
+        (ThemeName::Default, Tone::Accent) => Style::fg(Magenta),
+
+        (ThemeName::HighContrast, Tone::Good) => Style::fg(Black).on(Green),
+        (ThemeName::HighContrast, Tone::Warn) => Style::fg(Black).on(Yellow),
+        (ThemeName::HighContrast, Tone::Bad) => Style::fg(BrightWhite).on(Red),
+        (ThemeName::HighContrast, Tone::Alarm) => Style::fg(BrightWhite).on(Red).bold(),
+        (ThemeName::HighContrast, Tone::Info) => Style::fg(Black).on(Cyan),
+        (ThemeName::HighContrast, Tone::Active) => Style::fg(BrightWhite).on(Blue),
+        (ThemeName::HighContrast, Tone::Accent) => Style::fg(BrightWhite).on(Magenta),
+
+        (ThemeName::ColorblindSafe, Tone::Good) => Style::fg(BrightBlue),
+        (ThemeName::ColorblindSafe, Tone::Warn) => Style::fg(Yellow),
+        (ThemeName::ColorblindSafe, Tone::Bad) => Style::fg(BrightRed).bold(),
+        (ThemeName::ColorblindSafe, Tone::Alarm) => Style::fg(Black).on(BrightYellow).bold(),
+        (ThemeName::ColorblindSafe, Tone::Info) => Style::fg(Cyan),
+        (ThemeName::ColorblindSafe, Tone::Active) => Style::fg(BrightCyan),
+        (ThemeName::ColorblindSafe, Tone::Accent) => Style::fg(Magenta),
+
+        (ThemeName::Monochrome, Tone::Warn) => Style {
+            underline: true,
+            ..Style::plain()
+        },
+        (ThemeName::Monochrome, Tone::Bad) => Style::plain().bold(),
+        (ThemeName::Monochrome, Tone::Alarm) => Style {
+            reversed: true,
+            ..Style::plain().bold()
+        },
+        (ThemeName::Monochrome, Tone::Accent) => Style {
+            italic: true,
+            ..Style::plain()
+        },
+        (ThemeName::Monochrome, _) => Style::plain(),
+    }
+}
+
+/// Color text by what it says rather than with a fixed color.
+pub trait Paint: Sized {
+    fn tone(self, tone: Tone) -> ColoredString;
+
+    fn good(self) -> ColoredString {
+        self.tone(Tone::Good)
+    }
+
+    fn warn(self) -> ColoredString {
+        self.tone(Tone::Warn)
+    }
+
+    fn bad(self) -> ColoredString {
+        self.tone(Tone::Bad)
+    }
+
+    fn alarm(self) -> ColoredString {
+        self.tone(Tone::Alarm)
+    }
+
+    fn info(self) -> ColoredString {
+        self.tone(Tone::Info)
+    }
+
+    fn active(self) -> ColoredString {
+        self.tone(Tone::Active)
+    }
+
+    fn accent(self) -> ColoredString {
+        self.tone(Tone::Accent)
+    }
+}
+
+impl Paint for ColoredString {
+    fn tone(self, tone: Tone) -> ColoredString {
+        tone_style(tone).apply(self)
+    }
+}
+
+impl Paint for &str {
+    fn tone(self, tone: Tone) -> ColoredString {
+        self.normal().tone(tone)
+    }
+}
+
+/// `STATES=STYLE`, as `--state-color` takes it.
+pub fn parse_state_color(value: &str) -> Result<(StateSelection, Style)> {
+    let Some((states, style)) = value.split_once('=') else {
+        bail!("expected STATES=STYLE, got {value:?}");
+    };
+    Ok((parse_states(states)?, style.parse()?))
+}
+
+/// States to color, like `--state` takes them.
+pub fn parse_states(value: &str) -> Result<StateSelection> {
+    if value.split(',').all(|name| name.trim().is_empty()) {
+        bail!("name the states to color, like BtcPunished, failed or all");
+    }
+    value.parse()
+}
@@ -0,0 +1,215 @@
+//! TLS for the monitor's own connections (Electrum, MQTT), through rustls.
+//!
+//! Servers are checked against the Mozilla roots in `webpki-roots` and the
+//! name dialled. A server with a self-signed certificate, common for a
+//! personal Electrum server, can be pinned by its SHA-256 fingerprint
+//! instead; then that exact certificate is accepted and nothing else.
+
+use anyhow::{bail, Context, Result};
+use sha2::{Digest, Sha256};
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+use tokio::net::TcpStream;
+use tokio_rustls::client::TlsStream;
+use tokio_rustls::rustls::client::danger::{
+    HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier,
+};
+use tokio_rustls::rustls::crypto::{self, CryptoProvider};
+use tokio_rustls::rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+use tokio_rustls::rustls::{
+    CertificateError, ClientConfig, DigitallySignedStruct, Error, RootCertStore, SignatureScheme,
+};
+use tokio_rustls::TlsConnector;
+
+/// SHA-256 of a server's certificate, as `openssl x509 -fingerprint
+/// -sha256` prints it; the colons are optional.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Fingerprint([u8; 32]);
+
+impl FromStr for Fingerprint {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let digits: String = s.trim().chars().filter(|&c| c != ':').collect();
+        let bytes = hex::decode(&digits).ok().and_then(|b| b.try_into().ok());
+        match bytes {
+            Some(bytes) => Ok(Self(bytes)),
+            None => bail!("expected a SHA-256 fingerprint (64 hex digits), got {s:?}"),
+        }
+    }
+}
+
+impl fmt::Display for Fingerprint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let hex: Vec<String> = self.0.iter().map(|byte| format!("{byte:02X}")).collect();
+        f.write_str(&hex.join(":"))
+    }
+}
+
+impl fmt::Debug for Fingerprint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Fingerprint({self})")
+    }
+}
+
+/// Open a TLS connection to `host:port`, verified against the public roots
+/// or, with `pin`, against that one certificate.
+pub async fn connect(
+    host: &str,
+    port: u16,
+    pin: Option<Fingerprint>,
+) -> Result<TlsStream<TcpStream>> {
+    let name = ServerName::try_from(host.to_string())
+        .with_context(|| format!("{host:?} isn't a valid TLS server name"))?;
+    let stream = TcpStream::connect((host, port)).await?;
+    connector(pin)
+        .connect(name, stream)
+        .await
+        .context("TLS handshake")
+}
+
+fn connector(pin: Option<Fingerprint>) -> TlsConnector {
+    let provider = Arc::new(crypto::ring::default_provider());
+    let builder = ClientConfig::builder_with_provider(provider.clone())
+        .with_safe_default_protocol_versions()
+        .expect("ring supports the default protocol versions");
+    let config = match pin {
+        Some(fingerprint) => builder
+            .dangerous()
+            .with_custom_certificate_verifier(Arc::new(Pinned {
+                fingerprint,
+                provider,
+            })),
+        None => {
+            let roots = RootCertStore {
+                roots: webpki_roots::TLS_SERVER_ROOTS.to_vec(),
+            };
+            builder.with_root_certificates(roots)
+        }
+    };
+    TlsConnector::from(Arc::new(config.with_no_client_auth()))
+}
+
+/// Accepts the one certificate with the pinned fingerprint, whatever its
+/// issuer, names or dates; the handshake still has to be signed by its key.
+#[derive(Debug)]
+struct Pinned {
+    fingerprint: Fingerprint,
+    provider: Arc<CryptoProvider>,
+}
+
+impl ServerCertVerifier for Pinned {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, Error> {
+        let presented: [u8; 32] = Sha256::digest(end_entity).into();
+        match presented == self.fingerprint.0 {
+            true => Ok(ServerCertVerified::assertion()),
+            false => Err(Error::InvalidCertificate(
+                CertificateError::ApplicationVerificationFailure,
+            )),
+        }
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, Error> {
+        crypto::verify_tls12_signature(
+            message,
+            cert,
+            dss,
+            &self.provider.signature_verification_algorithms,
+        )
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, Error> {
+        crypto::verify_tls13_signature(
+            message,
+            cert,
+            dss,
+            &self.provider.signature_verification_algorithms,
+        )
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.provider
+            .signature_verification_algorithms
+            .supported_schemes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio_rustls::rustls::pki_types::PrivateKeyDer;
+    use tokio_rustls::rustls::ServerConfig;
+    use tokio_rustls::TlsAcceptor;
+
+    #[test]
+    fn fingerprints_parse_with_or_without_colons() {
+        let hex = "AB".repeat(32);
+        let colons = vec!["ab"; 32].join(":");
+        let a: Fingerprint = hex.parse().unwrap();
+        assert_eq!(a, colons.parse().unwrap());
+        assert_eq!(a.to_string(), vec!["AB"; 32].join(":"));
+        for bad in ["", "AB:CD", &"AB".repeat(33), &"ZZ".repeat(32)] {
+            assert!(bad.parse::<Fingerprint>().is_err(), "{bad}");
+        }
+    }
+
+    /// A server on localhost with a fresh self-signed certificate, answering
+    /// one connection with "ok".
+    async fn self_signed_server() -> (u16, Fingerprint) {
+        let certified = rcgen::generate_simple_self_signed(vec!["localhost".into()]).unwrap();
+        let cert = certified.cert.der().clone();
+        let fingerprint = Fingerprint(Sha256::digest(&cert).into());
+        let key = PrivateKeyDer::try_from(certified.key_pair.serialize_der()).unwrap();
+        let config = ServerConfig::builder()
+            .with_no_client_auth()
+            .with_single_cert(vec![cert], key)
+            .unwrap();
+        let acceptor = TlsAcceptor::from(Arc::new(config));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                if let Ok(mut tls) = acceptor.accept(stream).await {
+                    let _ = tls.write_all(b"ok").await;
+                    let _ = tls.shutdown().await;
+                }
+            }
+        });
+        (port, fingerprint)
+    }
+
+    #[tokio::test]
+    async fn self_signed_servers_need_a_pin() {
+        let (port, fingerprint) = self_signed_server().await;
+        let rejected = |pin| async move {
+            let err = connect("localhost", port, pin).await.err().unwrap();
+            format!("{err:#}").contains("certificate")
+        };
+        assert!(rejected(None).await);
+        assert!(rejected(Some("00".repeat(32).parse().unwrap())).await);
+
+        let mut stream = connect("localhost", port, Some(fingerprint)).await.unwrap();
+        let mut reply = String::new();
+        stream.read_to_string(&mut reply).await.unwrap();
+        assert_eq!(reply, "ok");
+    }
+}
@@ -0,0 +1,454 @@
+//! Parser for the subset of TOML the config file needs.
+//!
+//! Handles tables, arrays of tables, dotted keys, basic and literal strings,
+//! integers, floats, booleans, arrays and inline tables. Dates and multi-line
+//! strings are rejected. The result is a JSON value so serde can deserialize
+//! it like any other input.
+
+use anyhow::{anyhow, bail, Result};
+use serde_json::{Map, Number, Value};
+
+pub fn parse(input: &str) -> Result<Value> {
+    let mut parser = Parser {
+        chars: input.chars().collect(),
+        pos: 0,
+    };
+    parser.document().map_err(|err| {
+        let line = parser.chars[..parser.pos.min(parser.chars.len())]
+            .iter()
+            .filter(|&&c| c == '\n')
+            .count()
+            + 1;
+        anyhow!("line {line}: {err}")
+    })
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl Parser {
+    fn document(&mut self) -> Result<Value> {
+        let mut root = Map::new();
+        let mut current: Vec<String> = Vec::new();
+
+        loop {
+            self.skip_blank_lines();
+            match self.peek() {
+                None => break,
+                Some('[') => {
+                    let array = self.peek_at(1) == Some('[');
+                    self.pos += if array { 2 } else { 1 };
+                    let path = self.key_path()?;
+                    self.expect(']')?;
+                    if array {
+                        self.expect(']')?;
+                    }
+                    self.end_of_line()?;
+
+                    if array {
+                        let (last, parent) = path.split_last().expect("key path is never empty");
+                        let entry = table_mut(&mut root, parent)?
+                            .entry(last.clone())
+                            .or_insert_with(|| Value::Array(Vec::new()));
+                        match entry {
+                            Value::Array(items) => items.push(Value::Object(Map::new())),
+                            _ => bail!("`{last}` is already defined and is not an array"),
+                        }
+                    } else {
+                        table_mut(&mut root, &path)?;
+                    }
+                    current = path;
+                }
+                Some(_) => {
+                    let path = self.key_path()?;
+                    self.expect('=')?;
+                    let value = self.value()?;
+                    self.end_of_line()?;
+                    insert(table_mut(&mut root, &current)?, &path, value)?;
+                }
+            }
+        }
+
+        Ok(Value::Object(root))
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn peek_at(&self, offset: usize) -> Option<char> {
+        self.chars.get(self.pos + offset).copied()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += 1;
+        Some(c)
+    }
+
+    fn expect(&mut self, want: char) -> Result<()> {
+        self.skip_spaces();
+        match self.next() {
+            Some(c) if c == want => Ok(()),
+            Some(c) => bail!("expected `{want}`, found `{c}`"),
+            None => bail!("expected `{want}`, found end of file"),
+        }
+    }
+
+    fn skip_spaces(&mut self) {
+        while matches!(self.peek(), Some(' ' | '\t')) {
+            self.pos += 1;
+        }
+    }
+
+    fn skip_comment(&mut self) {
+        if self.peek() == Some('#') {
+            while !matches!(self.peek(), None | Some('\n')) {
+                self.pos += 1;
+            }
+        }
+    }
+
+    /// Whitespace, newlines and comments, as allowed between statements and
+    /// inside arrays.
+    fn skip_blank_lines(&mut self) {
+        loop {
+            self.skip_spaces();
+            self.skip_comment();
+            match self.peek() {
+                Some('\n' | '\r') => self.pos += 1,
+                _ => return,
+            }
+        }
+    }
+
+    fn end_of_line(&mut self) -> Result<()> {
+        self.skip_spaces();
+        self.skip_comment();
+        if self.peek() == Some('\r') {
+            self.pos += 1;
+        }
+        match self.next() {
+            None | Some('\n') => Ok(()),
+            Some(c) => bail!("expected end of line, found `{c}`"),
+        }
+    }
+
+    fn key_path(&mut self) -> Result<Vec<String>> {
+        let mut path = Vec::new();
+        loop {
+            self.skip_spaces();
+            path.push(self.key()?);
+            self.skip_spaces();
+            if self.peek() == Some('.') {
+                self.pos += 1;
+            } else {
+                return Ok(path);
+            }
+        }
+    }
+
+    fn key(&mut self) -> Result<String> {
+        match self.peek() {
+            Some('"') => self.basic_string(),
+            Some('\'') => self.literal_string(),
+            _ => {
+                let start = self.pos;
+                while matches!(self.peek(), Some(c) if c.is_ascii_alphanumeric() || c == '_' || c == '-')
+                {
+                    self.pos += 1;
+                }
+                if self.pos == start {
+                    match self.peek() {
+                        Some(c) => bail!("expected a key, found `{c}`"),
+                        None => bail!("expected a key, found end of file"),
+                    }
+                }
+                Ok(self.chars[start..self.pos].iter().collect())
+            }
+        }
+    }
+
+    fn value(&mut self) -> Result<Value> {
+        self.skip_spaces();
+        match self.peek() {
+            Some('"') => self.basic_string().map(Value::String),
+            Some('\'') => self.literal_string().map(Value::String),
+            Some('[') => self.array(),
+            Some('{') => self.inline_table(),
+            Some('t' | 'f') => {
+                let word = self.word();
+                match word.as_str() {
+                    "true" => Ok(Value::Bool(true)),
+                    "false" => Ok(Value::Bool(false)),
+                    other => bail!("invalid value `{other}` (strings need quotes)"),
+                }
+            }
+            Some(_) => self.number(),
+            None => bail!("expected a value, found end of file"),
+        }
+    }
+
+    fn word(&mut self) -> String {
+        let start = self.pos;
+        while matches!(self.peek(), Some(c) if !c.is_whitespace() && !",]}#".contains(c)) {
+            self.pos += 1;
+        }
+        self.chars[start..self.pos].iter().collect()
+    }
+
+    fn number(&mut self) -> Result<Value> {
+        let word = self.word();
+        let digits = word.replace('_', "");
+        if let Ok(n) = digits.parse::<i64>() {
+            return Ok(Value::Number(n.into()));
+        }
+        digits
+            .parse::<f64>()
+            .ok()
+            .and_then(Number::from_f64)
+            .map(Value::Number)
+            .ok_or_else(|| anyhow!("invalid value `{word}` (strings need quotes)"))
+    }
+
+    fn basic_string(&mut self) -> Result<String> {
+        if self.peek_at(1) == Some('"') && self.peek_at(2) == Some('"') {
+            bail!("multi-line strings are not supported");
+        }
+        self.pos += 1;
+        let mut out = String::new();
+        loop {
+            match self.next() {
+                None => bail!("unterminated string"),
+                Some('\n') => {
+                    // Report the string's line, not the next one
+                    self.pos -= 1;
+                    bail!("unterminated string");
+                }
+                Some('"') => return Ok(out),
+                Some('\\') => match self.next() {
+                    Some('"') => out.push('"'),
+                    Some('\\') => out.push('\\'),
+                    Some('n') => out.push('\n'),
+                    Some('t') => out.push('\t'),
+                    Some('r') => out.push('\r'),
+                    Some('b') => out.push('\u{8}'),
+                    Some('f') => out.push('\u{c}'),
+                    Some(kind @ ('u' | 'U')) => {
+                        let len = if kind == 'u' { 4 } else { 8 };
+                        let hex: String = (0..len).filter_map(|_| self.next()).collect();
+                        let c = u32::from_str_radix(&hex, 16)
+                            .ok()
+                            .and_then(char::from_u32)
+                            .ok_or_else(|| anyhow!("invalid unicode escape \\{kind}{hex}"))?;
+                        out.push(c);
+                    }
+                    Some(c) => bail!("invalid escape \\{c}"),
+                    None => bail!("unterminated string"),
+                },
+                Some(c) => out.push(c),
+            }
+        }
+    }
+
+    fn literal_string(&mut self) -> Result<String> {
+        if self.peek_at(1) == Some('\'') && self.peek_at(2) == Some('\'') {
+            bail!("multi-line strings are not supported");
+        }
+        self.pos += 1;
+        let mut out = String::new();
+        loop {
+            match self.next() {
+                None => bail!("unterminated string"),
+                Some('\n') => {
+                    // Report the string's line, not the next one
+                    self.pos -= 1;
+                    bail!("unterminated string");
+                }
+                Some('\'') => return Ok(out),
+                Some(c) => out.push(c),
+            }
+        }
+    }
+
+    fn array(&mut self) -> Result<Value> {
+        self.pos += 1;
+        let mut items = Vec::new();
+        loop {
+            self.skip_blank_lines();
+            if self.peek() == Some(']') {
+                self.pos += 1;
+                return Ok(Value::Array(items));
+            }
+            items.push(self.value()?);
+            self.skip_blank_lines();
+            match self.next() {
+                Some(',') => {}
+                Some(']') => return Ok(Value::Array(items)),
+                Some(c) => bail!("expected `,` or `]` in array, found `{c}`"),
+                None => bail!("unterminated array"),
+            }
+        }
+    }
+
+    fn inline_table(&mut self) -> Result<Value> {
+        self.pos += 1;
+        let mut table = Map::new();
+        self.skip_spaces();
+        if self.peek() == Some('}') {
+            self.pos += 1;
+            return Ok(Value::Object(table));
+        }
+        loop {
+            let path = self.key_path()?;
+            self.expect('=')?;
+            let value = self.value()?;
+            insert(&mut table, &path, value)?;
+            self.skip_spaces();
+            match self.next() {
+                Some(',') => {}
+                Some('}') => return Ok(Value::Object(table)),
+                Some(c) => bail!("expected `,` or `}}` in inline table, found `{c}`"),
+                None => bail!("unterminated inline table"),
+            }
+        }
+    }
+}
+
+/// Walk to the table at `path`, creating missing tables along the way. An
+/// array of tables resolves to its most recent entry.
+fn table_mut<'a>(
+    root: &'a mut Map<String, Value>,
+    path: &[String],
+) -> Result<&'a mut Map<String, Value>> {
+    let mut table = root;
+    for key in path {
+        let entry = table
+            .entry(key.clone())
+            .or_insert_with(|| Value::Object(Map::new()));
+        table = match entry {
+            Value::Object(map) => map,
+            Value::Array(items) => match items.last_mut() {
+                Some(Value::Object(map)) => map,
+                _ => bail!("`{key}` is already defined and is not a table"),
+            },
+            _ => bail!("`{key}` is already defined and is not a table"),
+        };
+    }
+    Ok(table)
+}
+
+fn insert(table: &mut Map<String, Value>, path: &[String], value: Value) -> Result<()> {
+    let (last, parent) = path.split_last().expect("key path is never empty");
+    let table = table_mut(table, parent)?;
+    if table.contains_key(last) {
+        bail!("duplicate key `{last}`");
+    }
+    table.insert(last.clone(), value);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn tables_and_values() {
+        let value = parse(
+            r#"
+# A comment
+interval = 5
+spread = 0.02   # trailing comment
+big = 1_000_000
+negative = -3
+enabled = true
+quiet = false
+name = "testnet \"asb\"\t\u00e9"
+path = 'C:\Users\asb'
+ports = [1, 2,
+  3, # in an array
+]
+empty = []
+
+[serve]
+listen = "0.0.0.0:8080"
+tls.cert = "cert.pem"
+
+[notify.discord]
+channels = { alerts = "https://a", "ops team" = "https://b" }
+"#,
+        )
+        .unwrap();
+        assert_eq!(
+            value,
+            json!({
+                "interval": 5,
+                "spread": 0.02,
+                "big": 1_000_000,
+                "negative": -3,
+                "enabled": true,
+                "quiet": false,
+                "name": "testnet \"asb\"\té",
+                "path": "C:\\Users\\asb",
+                "ports": [1, 2, 3],
+                "empty": [],
+                "serve": { "listen": "0.0.0.0:8080", "tls": { "cert": "cert.pem" } },
+                "notify": {
+                    "discord": {
+                        "channels": { "alerts": "https://a", "ops team": "https://b" }
+                    }
+                },
+            })
+        );
+    }
+
+    #[test]
+    fn arrays_of_tables() {
+        let value = parse(
+            "[[instances]]\nname = \"a\"\n[[instances]]\nname = \"b\"\n\
+             [instances.ssh]\nhost = \"box\"\n",
+        )
+        .unwrap();
+        assert_eq!(
+            value,
+            json!({ "instances": [{ "name": "a" }, { "name": "b", "ssh": { "host": "box" } }] })
+        );
+    }
+
+    #[test]
+    fn crlf_line_endings() {
+        let value = parse("[serve]\r\nlisten = \"x\"\r\n").unwrap();
+        assert_eq!(value, json!({ "serve": { "listen": "x" } }));
+    }
+
+    #[test]
+    fn errors_name_the_line() {
+        let err = parse("a = 1\nb = nope\n").unwrap_err().to_string();
+        assert!(err.starts_with("line 2:"), "{err}");
+        assert!(err.contains("strings need quotes"), "{err}");
+        let err = parse("a = 1\n\n\nc = \"open\n").unwrap_err().to_string();
+        assert!(err.starts_with("line 4:"), "{err}");
+    }
+
+    #[test]
+    fn unsupported_and_invalid_input() {
+        for input in [
+            "a = \"\"\"multi\"\"\"",
+            "a = '''multi'''",
+            "a = 1979-05-27",
+            "a = 1\na = 2",
+            "a = 1\n[a]",
+            "a = 1 b = 2",
+            "a = [1 2]",
+            "a = { b = 1",
+            "a = \"\\q\"",
+            "= 1",
+            "[table",
+        ] {
+            assert!(parse(input).is_err(), "{input:?}");
+        }
+    }
+}
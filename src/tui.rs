@@ -0,0 +1,272 @@
+//! Full-screen terminal handling for watch mode, on crossterm and ratatui.
+//!
+//! Crossterm sets up raw mode and the alternate screen and reads the keys;
+//! ratatui draws each frame, only rewriting the cells that changed since the
+//! last one, so slow terminals don't flicker. Frames are still built as
+//! lines with ANSI colors, as the plain table prints them, and parsed into
+//! ratatui text here.
+
+use ansi_to_tui::IntoText;
+use anyhow::{Context, Result};
+use crossterm::clipboard::CopyToClipboard;
+use crossterm::cursor::{Hide, Show};
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use crossterm::execute;
+use crossterm::style::ResetColor;
+use crossterm::terminal::{
+    disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen,
+};
+use ratatui::backend::CrosstermBackend;
+use ratatui::text::Text;
+use ratatui::widgets::Paragraph;
+use std::io::{self, IsTerminal, Stdout, Write};
+use tokio::sync::mpsc;
+
+use crate::render;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Key {
+    Up,
+    Down,
+    Left,
+    Right,
+    PageUp,
+    PageDown,
+    Home,
+    End,
+    Enter,
+    Tab,
+    /// Shift+Tab
+    BackTab,
+    Esc,
+    Backspace,
+    Char(char),
+    /// Ctrl+C; raw mode turns it into a key instead of SIGINT
+    Interrupt,
+}
+
+/// Puts the terminal into raw mode on the alternate screen and restores it on
+/// drop, including when unwinding from a panic.
+pub struct Terminal {
+    inner: ratatui::Terminal<CrosstermBackend<Stdout>>,
+}
+
+impl Terminal {
+    pub fn enter() -> Result<Self> {
+        let inner = ratatui::Terminal::new(CrosstermBackend::new(io::stdout()))?;
+        enable_raw_mode().context("enable raw mode")?;
+        // From here on, dropping it restores the terminal
+        let mut terminal = Self { inner };
+        execute!(io::stdout(), EnterAlternateScreen, Hide)?;
+        terminal.inner.clear()?;
+        Ok(terminal)
+    }
+
+    /// Terminal size as (columns, rows), falling back to 80x24.
+    pub fn size(&self) -> (usize, usize) {
+        self.inner
+            .size()
+            .map_or((80, 24), |size| (size.width.into(), size.height.into()))
+    }
+
+    /// Draw a full frame. Lines wider than the terminal are cut so they
+    /// never wrap and push the layout down.
+    pub fn draw(&mut self, lines: &[String]) -> Result<()> {
+        let text = frame_text(lines)?;
+        self.inner
+            .draw(|frame| frame.render_widget(Paragraph::new(text), frame.area()))?;
+        Ok(())
+    }
+
+    pub fn bell(&mut self) -> Result<()> {
+        let mut stdout = io::stdout().lock();
+        stdout.write_all(b"\x07")?;
+        stdout.flush()?;
+        Ok(())
+    }
+
+    /// Ask the terminal to put `text` on its clipboard (OSC 52). Terminals
+    /// that don't support it ignore the request, so this can't tell.
+    pub fn copy(&mut self, text: &str) -> Result<()> {
+        execute!(io::stdout(), CopyToClipboard::to_clipboard_from(text))?;
+        Ok(())
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        let _ = execute!(io::stdout(), ResetColor, Show, LeaveAlternateScreen);
+        let _ = disable_raw_mode();
+    }
+}
+
+/// A frame's lines as ratatui text, with `--ascii` applied.
+fn frame_text(lines: &[String]) -> Result<Text<'static>> {
+    let plain: Vec<String> = lines.iter().cloned().map(render::plain).collect();
+    plain
+        .join("\n")
+        .into_text()
+        .context("parse the frame's colors")
+}
+
+/// Read keys on a dedicated thread; reading events blocks and would
+/// otherwise stall the runtime. Resizes are left to SIGWINCH.
+pub fn spawn_key_reader() -> mpsc::UnboundedReceiver<Key> {
+    let (tx, rx) = mpsc::unbounded_channel();
+
+    std::thread::spawn(move || {
+        while let Ok(event) = event::read() {
+            let Event::Key(event) = event else {
+                continue;
+            };
+            if let Some(key) = key(event) {
+                if tx.send(key).is_err() {
+                    return;
+                }
+            }
+        }
+    });
+
+    rx
+}
+
+/// The key watch mode knows for a key press, if any.
+fn key(event: KeyEvent) -> Option<Key> {
+    if event.kind == KeyEventKind::Release {
+        return None;
+    }
+    let ctrl = event.modifiers.contains(KeyModifiers::CONTROL);
+    let alt = event.modifiers.contains(KeyModifiers::ALT);
+    Some(match event.code {
+        KeyCode::Char('c') if ctrl => Key::Interrupt,
+        KeyCode::Char(_) if ctrl || alt => return None,
+        KeyCode::Char(c) => Key::Char(c),
+        KeyCode::Up => Key::Up,
+        KeyCode::Down => Key::Down,
+        KeyCode::Left => Key::Left,
+        KeyCode::Right => Key::Right,
+        KeyCode::PageUp => Key::PageUp,
+        KeyCode::PageDown => Key::PageDown,
+        KeyCode::Home => Key::Home,
+        KeyCode::End => Key::End,
+        KeyCode::Enter => Key::Enter,
+        KeyCode::Tab => Key::Tab,
+        KeyCode::BackTab => Key::BackTab,
+        KeyCode::Esc => Key::Esc,
+        KeyCode::Backspace => Key::Backspace,
+        _ => return None,
+    })
+}
+
+/// Columns of the terminal stdout goes to, for laying out the table
+/// outside the full-screen view; none when it isn't a terminal.
+pub fn stdout_width() -> Option<usize> {
+    if !io::stdout().is_terminal() {
+        return None;
+    }
+    let (columns, _) = crossterm::terminal::size().ok()?;
+    (columns > 0).then_some(columns.into())
+}
+
+/// Cut `line` to `width` visible columns, leaving ANSI escapes intact.
+pub fn truncate_visible(line: &str, width: usize) -> String {
+    let mut out = String::with_capacity(line.len());
+    let mut visible = 0;
+    let mut chars = line.chars();
+    let mut truncated = false;
+
+    while let Some(c) = chars.next() {
+        if c == '\x1B' {
+            out.push(c);
+            // Copy the whole escape sequence through its final byte
+            for next in chars.by_ref() {
+                out.push(next);
+                if next.is_ascii_alphabetic() {
+                    break;
+                }
+            }
+            continue;
+        }
+
+        if visible == width {
+            truncated = true;
+            break;
+        }
+        out.push(c);
+        visible += 1;
+    }
+
+    if truncated {
+        out.push_str("\x1B[0m");
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ratatui::backend::TestBackend;
+    use ratatui::style::{Color, Modifier};
+
+    fn press(code: KeyCode, modifiers: KeyModifiers) -> Option<Key> {
+        key(KeyEvent::new(code, modifiers))
+    }
+
+    #[test]
+    fn keys_map_to_watch_keys() {
+        let none = KeyModifiers::NONE;
+        assert_eq!(press(KeyCode::Char('j'), none), Some(Key::Char('j')));
+        assert_eq!(
+            press(KeyCode::Char('G'), KeyModifiers::SHIFT),
+            Some(Key::Char('G'))
+        );
+        assert_eq!(
+            press(KeyCode::Char('c'), KeyModifiers::CONTROL),
+            Some(Key::Interrupt)
+        );
+        assert_eq!(press(KeyCode::Char('x'), KeyModifiers::CONTROL), None);
+        assert_eq!(press(KeyCode::Char('x'), KeyModifiers::ALT), None);
+        assert_eq!(
+            press(KeyCode::BackTab, KeyModifiers::SHIFT),
+            Some(Key::BackTab)
+        );
+        assert_eq!(press(KeyCode::PageDown, none), Some(Key::PageDown));
+        assert_eq!(press(KeyCode::F(1), none), None);
+
+        let mut release = KeyEvent::new(KeyCode::Enter, none);
+        release.kind = KeyEventKind::Release;
+        assert_eq!(key(release), None);
+    }
+
+    #[test]
+    fn frames_keep_colors_and_are_cut_to_the_width() {
+        let lines = [
+            "\x1B[32mBtcRedeemed\x1B[0m ok".to_string(),
+            "a line longer than the terminal".to_string(),
+        ];
+        let text = frame_text(&lines).unwrap();
+
+        let mut terminal = ratatui::Terminal::new(TestBackend::new(12, 3)).unwrap();
+        terminal
+            .draw(|frame| frame.render_widget(Paragraph::new(text), frame.area()))
+            .unwrap();
+        let buffer = terminal.backend().buffer();
+        let row = |y: u16| -> String { (0..12).map(|x| buffer[(x, y)].symbol()).collect() };
+        assert_eq!(row(0), "BtcRedeemed ");
+        assert_eq!(row(1), "a line longe");
+        assert_eq!(row(2), " ".repeat(12));
+        assert_eq!(buffer[(0, 0)].fg, Color::Green);
+        assert_eq!(buffer[(0, 1)].fg, Color::Reset);
+        assert!(!buffer[(0, 0)].modifier.contains(Modifier::BOLD));
+    }
+
+    #[test]
+    fn truncation_keeps_escapes() {
+        assert_eq!(
+            truncate_visible("\x1B[31mred\x1B[0m", 2),
+            "\x1B[31mre\x1B[0m"
+        );
+        assert_eq!(truncate_visible("short", 10), "short");
+        assert_eq!(truncate_visible("", 0), "");
+    }
+}
@@ -0,0 +1,217 @@
+//! Watch-only view of the ASB's Bitcoin wallet, for the balance panel.
+//!
+//! ASB keeps a BIP84 (P2WPKH) descriptor wallet. Given its account xpub or
+//! the descriptor printed by `asb export-bitcoin-wallet`, the monitor
+//! derives receive and change scripts and asks Electrum for their balances,
+//! scanning each chain until `GAP_LIMIT` unused scripts in a row.
+
+use anyhow::{bail, ensure, Context, Result};
+use serde::Serialize;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+
+use crate::bip32::{ExtendedKey, HARDENED};
+use crate::btc;
+use crate::electrum::Session;
+
+/// Unused scripts in a row after which a chain is assumed to end, as in
+/// BIP44 and BDK
+const GAP_LIMIT: u32 = 20;
+
+#[derive(Debug, Clone)]
+pub struct BtcWallet {
+    /// Public keys whose children are the wallet's scripts: receive, then
+    /// change. Private keys from a descriptor are dropped after parsing.
+    chains: Vec<ExtendedKey>,
+    /// Script hashes derived so far, per chain; derivation is the slow part
+    scripts: Arc<Mutex<Vec<Vec<String>>>>,
+}
+
+/// Satoshis held by the wallet.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct BtcBalance {
+    pub confirmed: u64,
+    /// Negative while an outgoing transaction sits in the mempool
+    pub unconfirmed: i64,
+}
+
+impl BtcBalance {
+    pub fn total(&self) -> u64 {
+        (self.confirmed as i64 + self.unconfirmed).max(0) as u64
+    }
+}
+
+impl FromStr for BtcWallet {
+    type Err = anyhow::Error;
+
+    /// A bare account-level extended key, or `wpkh(KEY/PATH/*)` with an
+    /// optional `[fingerprint/origin]` and `#checksum`.
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.trim();
+        let s = s.split_once('#').map_or(s, |(descriptor, _)| descriptor);
+        let inner = match s.strip_prefix("wpkh(") {
+            Some(rest) => rest
+                .strip_suffix(')')
+                .context("unbalanced parentheses in descriptor")?,
+            None if s.contains('(') => bail!("only wpkh() descriptors are supported"),
+            None => s,
+        };
+        let inner = match inner.strip_prefix('[') {
+            Some(rest) => rest.split_once(']').context("unterminated key origin")?.1,
+            None => inner,
+        };
+
+        let mut parts = inner.split('/');
+        let key: ExtendedKey = parts.next().unwrap_or_default().parse()?;
+        let steps: Vec<&str> = parts.collect();
+        let paths = match steps.split_last() {
+            // An account key: receive and change chains below it
+            None => vec![vec![0], vec![1]],
+            Some((&"*", prefix)) => {
+                let prefix = prefix
+                    .iter()
+                    .map(|step| parse_step(step))
+                    .collect::<Result<Vec<u32>>>()?;
+                // A receive descriptor implies its change sibling
+                match prefix.split_last() {
+                    Some((0, account)) => vec![prefix.clone(), [account, &[1]].concat()],
+                    _ => vec![prefix],
+                }
+            }
+            Some(_) => bail!("descriptor path must end in /*"),
+        };
+
+        let chains = paths
+            .iter()
+            .map(|path| key.derive(path)?.neuter())
+            .collect::<Result<Vec<_>>>()?;
+        Ok(BtcWallet {
+            scripts: Arc::new(Mutex::new(vec![Vec::new(); chains.len()])),
+            chains,
+        })
+    }
+}
+
+/// `84'`, `84h` or `0`.
+fn parse_step(step: &str) -> Result<u32> {
+    let (index, hardened) = match step.strip_suffix(['\'', 'h']) {
+        Some(index) => (index, true),
+        None => (step, false),
+    };
+    let index: u32 = index
+        .parse()
+        .with_context(|| format!("invalid derivation step {step}"))?;
+    ensure!(index < HARDENED, "derivation step out of range: {step}");
+    Ok(if hardened { index + HARDENED } else { index })
+}
+
+impl BtcWallet {
+    fn script_hash(&self, chain: usize, index: u32) -> Result<String> {
+        if let Some(hash) = self.scripts.lock().unwrap()[chain].get(index as usize) {
+            return Ok(hash.clone());
+        }
+        let key = self.chains[chain].child(index)?;
+        let hash = btc::script_hash(&btc::p2wpkh_script(&key.public_key()?));
+        let mut scripts = self.scripts.lock().unwrap();
+        if scripts[chain].len() == index as usize {
+            scripts[chain].push(hash.clone());
+        }
+        Ok(hash)
+    }
+
+    /// Sum of every used script's balance.
+    pub async fn balance(&self, session: &mut Session) -> Result<BtcBalance> {
+        let mut balance = BtcBalance::default();
+        for chain in 0..self.chains.len() {
+            let mut unused = 0;
+            let mut index = 0;
+            while unused < GAP_LIMIT {
+                let hash = self.script_hash(chain, index)?;
+                if session.is_used(&hash).await? {
+                    unused = 0;
+                    let (confirmed, unconfirmed) = session.balance(&hash).await?;
+                    balance.confirmed += confirmed;
+                    balance.unconfirmed += unconfirmed;
+                } else {
+                    unused += 1;
+                }
+                index += 1;
+            }
+        }
+        Ok(balance)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// BIP32 test vector 1's master key
+    const XPRV: &str = "xprv9s21ZrQH143K3QTDL4LXw2F7HEK3wJUD2nW2nRk4stbPy6cq3jPPqjiChkVvvNKmPGJxWUtg6LnF5kejMRNNU3TGtRBeJgk33yuGBxrMPHi";
+    /// Its m/0h, as a mainnet and a testnet account key
+    const ZPUB: &str = "zpub6mwJaQaUE3oZ763dJZKRbNUxW1znc5f4uqty7hKaAS5RKNscWpZrkohNNhd7BNxD8Hj5NceNPbujdF3935mRkSHHcS6yZLnpsUkrK1XoMLr";
+    const VPUB: &str = "vpub5UcFMjtodKddhuH9y8Avm26wp9Qzqbh5FPp5z7k2eQZu6ychWBucGZ4pHsnmBkLXVjFrNiG8YxVY66atAJ7NZVYt95KHDhWsnaWGkhF4DrT";
+
+    /// The script hash Electrum knows an address by.
+    fn address(address: &str) -> String {
+        btc::script_hash(&btc::address_script(address).unwrap())
+    }
+
+    #[test]
+    fn account_keys_derive_known_addresses() {
+        // m/0h/1/0: the first change address
+        let mainnet: BtcWallet = ZPUB.parse().unwrap();
+        assert_eq!(
+            mainnet.script_hash(1, 0).unwrap(),
+            address("bc1qhdjn79g0f5yq7tl2snz937tc6jst753vqv98ux")
+        );
+        let testnet: BtcWallet = VPUB.parse().unwrap();
+        let hash = testnet.script_hash(1, 0).unwrap();
+        assert_eq!(hash, address("tb1qhdjn79g0f5yq7tl2snz937tc6jst753v227584"));
+        assert_eq!(
+            hash,
+            address("bcrt1qhdjn79g0f5yq7tl2snz937tc6jst753vgr8esu")
+        );
+        assert_ne!(testnet.script_hash(0, 0).unwrap(), hash);
+    }
+
+    #[test]
+    fn descriptors_name_their_chains() {
+        let expected = address("bc1qhdjn79g0f5yq7tl2snz937tc6jst753vqv98ux");
+
+        // A receive descriptor brings its change chain along
+        let receive: BtcWallet = format!("wpkh([d34db33f/84h/0h/0h]{XPRV}/0h/0/*)#abcdefgh")
+            .parse()
+            .unwrap();
+        assert_eq!(receive.chains.len(), 2);
+        assert_eq!(receive.script_hash(1, 0).unwrap(), expected);
+
+        let change: BtcWallet = format!("wpkh({XPRV}/0'/1/*)").parse().unwrap();
+        assert_eq!(change.chains.len(), 1);
+        assert_eq!(change.script_hash(0, 0).unwrap(), expected);
+    }
+
+    #[test]
+    fn unsupported_descriptors_are_rejected() {
+        for descriptor in [
+            format!("pkh({ZPUB}/0/*)"),
+            format!("wpkh({ZPUB}/0/*"),
+            format!("wpkh({ZPUB}/0/1)"),
+            format!("wpkh([d34db33f{ZPUB}/0/*)"),
+            format!("wpkh({ZPUB}/x/*)"),
+            format!("wpkh({ZPUB}/2147483648/*)"),
+        ] {
+            assert!(descriptor.parse::<BtcWallet>().is_err(), "{descriptor}");
+        }
+    }
+
+    #[test]
+    fn scripts_are_cached_in_order() {
+        let wallet: BtcWallet = ZPUB.parse().unwrap();
+        let first = wallet.script_hash(0, 0).unwrap();
+        // Out of order: derived, but not cached
+        wallet.script_hash(0, 5).unwrap();
+        assert_eq!(wallet.scripts.lock().unwrap()[0].len(), 1);
+        assert_eq!(wallet.script_hash(0, 0).unwrap(), first);
+    }
+}
@@ -0,0 +1,1707 @@
+//! Long-running watch mode: the full-screen TUI and the plain redraw loop.
+
+use anyhow::{Context, Result};
+use colored::Colorize;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::io::{self, IsTerminal};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::time::{sleep, sleep_until, Instant};
+
+use crate::anomaly::Anomaly;
+use crate::asblog::{AsbLog, LogAlert};
+use crate::bell::Bell;
+use crate::board::Board;
+use crate::chain::ChainMonitor;
+use crate::cli::OutputFormat;
+use crate::clipboard::Clipboard;
+use crate::columns::{self, TableColumn};
+use crate::db::{Instance, PollError, SwapSource};
+use crate::digest::{self, DigestSchedule};
+use crate::explorer::{self, Explorer};
+use crate::fswatch::DbWatcher;
+use crate::heartbeat::Heartbeat;
+use crate::history::HistoryStore;
+use crate::influx::InfluxSink;
+use crate::model::{
+    Activity, Highlight, HistoryRow, Network, Outcome, PinnedSwaps, Role, StuckRules, SwapDetails,
+    SwapRow, SwapTracker, SwapView, Transition, Watchlist,
+};
+use crate::notes::{self, Annotations, NotesStore};
+use crate::notify::Notifiers;
+use crate::remote;
+use crate::render::{
+    activity_line, annotation_lines, anomaly_lines, balance_lines, clear_screen, display_id,
+    header_lines, history_lines, log_lines, render_header, render_table, report_error,
+    set_time_format, short_id, summary_line, table_bottom, table_divider, table_row, table_top,
+    ticker_line, time_settings, unloaded_line, Columns, TableFilter,
+};
+use crate::search::SwapSearch;
+use crate::sort::SortOrder;
+use crate::state::StateSelection;
+use crate::theme::Paint;
+use crate::tui::{self, Key, Terminal};
+
+/// Quiet polls tolerated before adaptive mode starts stretching the interval
+const IDLE_POLLS_BEFORE_BACKOFF: u32 = 5;
+
+/// Adaptive mode never waits longer than this multiple of the base interval
+const MAX_BACKOFF_FACTOR: u32 = 16;
+
+/// Safety-net poll interval while filesystem events drive refreshes
+const HEARTBEAT: Duration = Duration::from_secs(30);
+
+/// How often the full-screen view redraws while highlights fade
+const FADE_TICK: Duration = Duration::from_secs(1);
+
+/// Decides how long to sleep between polls.
+///
+/// With `adaptive` off this is just the fixed interval. With it on, the delay
+/// doubles after a run of quiet polls and snaps back to the base interval as
+/// soon as anything changes.
+#[derive(Debug)]
+pub struct RefreshSchedule {
+    base: Duration,
+    current: Duration,
+    adaptive: bool,
+    idle_polls: u32,
+}
+
+impl RefreshSchedule {
+    pub fn new(base: Duration, adaptive: bool) -> Self {
+        Self {
+            base,
+            current: base,
+            adaptive,
+            idle_polls: 0,
+        }
+    }
+
+    pub fn record(&mut self, activity: bool) {
+        if !self.adaptive {
+            return;
+        }
+
+        if activity {
+            self.idle_polls = 0;
+            self.current = self.base;
+            return;
+        }
+
+        self.idle_polls += 1;
+        if self.idle_polls >= IDLE_POLLS_BEFORE_BACKOFF {
+            self.idle_polls = 0;
+            self.current = (self.current * 2).min(self.base * MAX_BACKOFF_FACTOR);
+        }
+    }
+
+    pub fn current(&self) -> Duration {
+        self.current
+    }
+
+    /// Delay before the next poll. With a working filesystem watcher this
+    /// stretches to a slow heartbeat, since changes trigger a poll anyway.
+    pub fn next_wait(&self, watcher: &DbWatcher) -> Duration {
+        if watcher.is_active() {
+            self.current.max(HEARTBEAT)
+        } else {
+            self.current
+        }
+    }
+
+    /// "on change" or "every 2s", for status lines.
+    pub fn describe(&self, watcher: &DbWatcher) -> String {
+        if watcher.is_active() {
+            "on change".to_string()
+        } else {
+            format!("every {}s", self.current.as_secs())
+        }
+    }
+}
+
+/// Everything a watch loop needs besides the terminal.
+pub struct WatchContext {
+    pub instances: Vec<Instance>,
+    pub schedule: RefreshSchedule,
+    pub notifiers: Notifiers,
+    /// Refresh on database file changes instead of blind polling
+    pub fs_watch: bool,
+    pub stuck: StuckRules,
+    pub watchlist: Watchlist,
+    /// Swaps pinned with `--pin` or in the config file
+    pub pins: PinnedSwaps,
+    pub chain: ChainMonitor,
+    /// Where transaction links point
+    pub explorer: Explorer,
+    /// ASB's rendezvous points, from `--asb-config`
+    pub rendezvous_points: Vec<String>,
+    /// Where observed transitions are kept across restarts, if anywhere
+    pub history: Option<HistoryStore>,
+    /// Notes and tags on swaps; `None` when there is nowhere to keep them
+    pub notes: Option<NotesStore>,
+    /// Which swaps the tables show, from `--state` and `--hide-finished`
+    pub filter: TableFilter,
+    /// Finished swaps read at a time, from `--page-size`; `None` reads
+    /// them all
+    pub page_size: Option<usize>,
+    /// Start the full-screen view on the board, from `--board`
+    pub board: bool,
+    /// The table's columns, from `--table-columns`
+    pub table_columns: Option<Vec<TableColumn>>,
+    /// Show swaps started per hour or day above the table, from `--chart`
+    pub chart: bool,
+    /// How far back that goes, from `--chart-window`
+    pub chart_window: Duration,
+    /// Latest transitions listed under the full-screen table, from
+    /// `--ticker`
+    pub ticker: usize,
+    /// How long a changed state stays highlighted, from `--fade`
+    pub fade: Duration,
+    /// ASB's log, shown under a swap's history
+    pub asb_log: Option<AsbLog>,
+    pub digest: Option<DigestSchedule>,
+    /// Where per-poll metrics are written, if anywhere
+    pub metrics: Option<InfluxSink>,
+    /// Pinged after every poll cycle, if set
+    pub heartbeat: Option<Heartbeat>,
+    /// Sounds for transitions into chosen states, from `--bell` and
+    /// `--bell-sound`
+    pub bell: Bell,
+}
+
+impl WatchContext {
+    /// The most recent history database write failure, cleared on read.
+    pub fn history_error(&self) -> Option<String> {
+        self.history.as_ref().and_then(HistoryStore::take_error)
+    }
+
+    pub fn record_metrics(
+        &mut self,
+        instance: &Instance,
+        views: &[SwapView],
+        transitions: &[Transition],
+    ) {
+        if let Some(ref mut metrics) = self.metrics {
+            metrics.record_poll(instance, views, transitions);
+        }
+    }
+
+    /// Add the latest balances and write out what this poll recorded.
+    pub fn flush_metrics(&mut self) {
+        if let Some(ref mut metrics) = self.metrics {
+            if let Some(sample) = self.chain.balance_history().last() {
+                metrics.record_balances(sample);
+            }
+            metrics.flush();
+        }
+    }
+
+    /// The most recent metrics write failure, cleared on read.
+    pub fn metrics_error(&self) -> Option<String> {
+        self.metrics.as_ref().and_then(InfluxSink::take_error)
+    }
+
+    /// Tell the heartbeat check how the poll cycle went; `failures` are
+    /// the instances whose database couldn't be read.
+    pub fn beat(&mut self, failures: &[String]) {
+        if let Some(ref mut heartbeat) = self.heartbeat {
+            heartbeat.beat(failures);
+        }
+    }
+
+    /// The most recent heartbeat ping failure, cleared on read.
+    pub fn heartbeat_error(&self) -> Option<String> {
+        self.heartbeat.as_ref().and_then(Heartbeat::take_error)
+    }
+
+    /// The most recent bell sound failure, cleared on read.
+    pub fn bell_error(&self) -> Option<String> {
+        self.bell.take_error()
+    }
+
+    /// Wakes the loop when a database changes, or ASB logs something.
+    pub fn watcher(&self) -> DbWatcher {
+        let log = self.asb_log.as_ref().map(AsbLog::path);
+        db_watcher(&self.instances, log, self.fs_watch)
+    }
+
+    /// Catch up with ASB's log, returning what its alert rules raised.
+    pub fn take_log_alerts(&mut self) -> Vec<LogAlert> {
+        let Some(ref mut log) = self.asb_log else {
+            return Vec::new();
+        };
+        log.poll();
+        log.take_alerts()
+    }
+}
+
+/// A watcher over every instance's database and ASB's log, if given, or
+/// a disabled one when `enabled` is false. Remote databases only change
+/// when a poll fetches them, so any remote instance disables it too.
+pub fn db_watcher(instances: &[Instance], log: Option<&Path>, enabled: bool) -> DbWatcher {
+    if !enabled || instances.iter().any(|i| i.remote.is_some()) {
+        return DbWatcher::disabled();
+    }
+    DbWatcher::new(
+        instances
+            .iter()
+            .filter_map(|i| i.db_path.as_deref())
+            .chain(log),
+    )
+}
+
+/// Polling state for one instance.
+pub struct Monitor {
+    pub instance: Instance,
+    pub source: SwapSource,
+    pub tracker: SwapTracker,
+    history: Option<HistoryStore>,
+    /// Whether the tracker has been seeded from the history database yet
+    restored: bool,
+    /// Where the views' tags come from
+    notes: Option<NotesStore>,
+}
+
+impl Monitor {
+    /// One monitor per instance. Output is only tagged with instance names
+    /// when there is more than one.
+    pub fn for_instances(
+        instances: &[Instance],
+        stuck: &StuckRules,
+        watchlist: &Watchlist,
+        pins: &PinnedSwaps,
+        history: Option<&HistoryStore>,
+    ) -> Vec<Monitor> {
+        let multi = instances.len() > 1;
+        instances
+            .iter()
+            .map(|instance| Monitor {
+                source: SwapSource::new(instance.db_path.clone())
+                    .with_role(instance.role)
+                    .with_immutable(instance.immutable),
+                tracker: SwapTracker::new(instance.network, multi.then(|| instance.name.clone()))
+                    .with_stuck_rules(instance.role, stuck.clone())
+                    .with_watchlist(watchlist.clone())
+                    .with_pins(pins.clone()),
+                instance: instance.clone(),
+                history: history.cloned(),
+                restored: false,
+                notes: None,
+            })
+            .collect()
+    }
+
+    /// Read finished swaps `size` at a time; see [`SwapSource::with_page_size`].
+    pub fn with_page_size(mut self, size: Option<usize>) -> Self {
+        self.source = self.source.with_page_size(size);
+        self
+    }
+
+    /// Tag the views from the notes database on every update.
+    pub fn with_notes(mut self, notes: Option<NotesStore>) -> Self {
+        self.notes = notes;
+        self
+    }
+
+    /// Read the database, fetching a fresh copy first for remote instances.
+    pub async fn poll(&mut self) -> std::result::Result<Vec<SwapRow>, PollError> {
+        if let (Some(remote), Some(mirror)) = (&self.instance.remote, &self.instance.db_path) {
+            remote::fetch(remote, mirror)
+                .await
+                .map_err(PollError::Connect)?;
+        }
+        self.source.poll().await
+    }
+
+    /// Feed a poll's rows to the tracker, recording what changed in the
+    /// history database. The first call seeds the tracker from the states
+    /// the previous run saw.
+    pub async fn update(&mut self, rows: Vec<SwapRow>) -> (Vec<SwapView>, Vec<Transition>) {
+        // Older swaps read in on scrolling are no news
+        let (paged_in, anomalies) = self.source.take_paged_in();
+        self.tracker.baseline(&paged_in, anomalies);
+        let Some(history) = &self.history else {
+            let (mut views, mut transitions) = self.tracker.update(rows);
+            self.source
+                .durations()
+                .annotate(&mut views, self.instance.role);
+            self.tag(&mut views).await;
+            transitions.extend(self.tracker.anomalies(self.source.anomalies()));
+            return (views, transitions);
+        };
+        let first = !self.restored;
+        if first {
+            // Unreadable history only costs the transitions missed while down
+            if let Ok(states) = history.last_states(&self.instance.name).await {
+                self.tracker.restore(states);
+            }
+            self.restored = true;
+        }
+        let (mut views, mut transitions) = self.tracker.update(rows);
+        let baseline = if first { &views[..] } else { &[] };
+        history.record(&self.instance.name, &transitions, baseline);
+        self.source
+            .durations()
+            .annotate(&mut views, self.instance.role);
+        self.tag(&mut views).await;
+        transitions.extend(self.tracker.anomalies(self.source.anomalies()));
+        (views, transitions)
+    }
+
+    async fn tag(&self, views: &mut [SwapView]) {
+        if let Some(ref notes) = self.notes {
+            // Unreadable notes only cost the tags
+            let _ = notes.annotate(views).await;
+        }
+    }
+}
+
+pub async fn run_watch(mut ctx: WatchContext, output: OutputFormat) -> Result<()> {
+    let mut monitors: Vec<Monitor> = Monitor::for_instances(
+        &ctx.instances,
+        &ctx.stuck,
+        &ctx.watchlist,
+        &ctx.pins,
+        ctx.history.as_ref(),
+    )
+    .into_iter()
+    .map(|monitor| {
+        monitor
+            .with_page_size(ctx.page_size)
+            .with_notes(ctx.notes.clone())
+    })
+    .collect();
+    let mut watcher = ctx.watcher();
+    let multi = monitors.len() > 1;
+    let columns = Columns::for_chain(&ctx.chain).with_chosen(ctx.table_columns.clone());
+    ctx.chain.start();
+    // JSON mode prints one array per poll on its own line; no screen chrome
+    let json = output == OutputFormat::Json;
+
+    loop {
+        if !json {
+            clear_screen();
+        }
+        // Refitted every pass, in case the terminal was resized
+        let columns = columns.fit(tui::stdout_width());
+
+        let mut all_views = Vec::new();
+        let mut any_ok = false;
+        let mut activity = false;
+        let mut failures = Vec::new();
+        for monitor in &mut monitors {
+            let network = monitor.instance.network;
+            let role = monitor.instance.role;
+            if !json {
+                let label = multi.then_some(monitor.instance.name.as_str());
+                render_header(
+                    &monitor.instance.db_path,
+                    network,
+                    role,
+                    label,
+                    &ctx.chain,
+                    &ctx.rendezvous_points,
+                    columns.header_width(),
+                );
+            }
+
+            match monitor.poll().await {
+                Ok(rows) => {
+                    let empty = rows.is_empty();
+                    let (mut views, transitions) = monitor.update(rows).await;
+                    activity |= !transitions.is_empty();
+                    ctx.notifiers.dispatch(&transitions);
+                    // The table goes to stdout, which may well be a file
+                    if ctx.bell.ring(&transitions, role) && io::stderr().is_terminal() {
+                        eprint!("\x07");
+                    }
+                    ctx.record_metrics(&monitor.instance, &views, &transitions);
+                    ctx.chain.annotate(&monitor.instance.name, &mut views, role);
+                    any_ok = true;
+                    let shown: Vec<SwapView> = ctx
+                        .filter
+                        .apply(&views, role)
+                        .into_iter()
+                        .cloned()
+                        .collect();
+
+                    if json {
+                        all_views.extend(shown);
+                    } else {
+                        if empty {
+                            println!("{}", "No swaps yet.".warn());
+                        } else {
+                            // The summary counts every swap, shown or not
+                            println!("{}", summary_line(&views, role));
+                            if ctx.chart {
+                                println!(
+                                    "{}",
+                                    chart_line(&views, ctx.chart_window, monitor.source.unloaded())
+                                );
+                            }
+                            match shown.is_empty() {
+                                true => println!("{}", ctx.filter.empty_line()),
+                                false => render_table(&shown, network, role, &columns),
+                            }
+                        }
+                        if let Some(line) = ctx.filter.hidden_line(&views, role) {
+                            println!("{line}");
+                        }
+                        if let Some(line) = unloaded_line(monitor.source.unloaded()) {
+                            println!("{line}");
+                        }
+                        for line in anomaly_lines(monitor.source.anomalies(), network, role) {
+                            println!("{line}");
+                        }
+                        println!();
+                    }
+                }
+                Err(err) => {
+                    let message = match multi {
+                        true => format!("{}: {err}", monitor.instance.name),
+                        false => err.to_string(),
+                    };
+                    report_error(json, &message);
+                    if matches!(err, PollError::NotFound(_)) && !json {
+                        println!("{}", role.start_hint(network).dimmed());
+                    }
+                    if !json && multi {
+                        println!();
+                    }
+                    failures.push(format!("{}: {err}", monitor.instance.name));
+                }
+            }
+        }
+        if any_ok {
+            ctx.schedule.record(activity);
+        }
+        ctx.notifiers.dispatch_liquidity(&ctx.chain.take_alerts());
+        ctx.notifiers.dispatch_fees(&ctx.chain.take_fee_alerts());
+        ctx.notifiers.dispatch_asb(&ctx.chain.take_asb_alerts());
+        ctx.notifiers.dispatch_reach(&ctx.chain.take_reach_alerts());
+        ctx.notifiers
+            .dispatch_quotes(&ctx.chain.take_quote_alerts());
+        let log_alerts = ctx.take_log_alerts();
+        ctx.notifiers.dispatch_log(&log_alerts);
+        digest::send_if_due(&mut ctx);
+        ctx.flush_metrics();
+        ctx.beat(&failures);
+        let balances = balance_lines(&ctx.chain);
+        if !json && !balances.is_empty() {
+            for line in balances {
+                println!("{line}");
+            }
+            println!();
+        }
+
+        if json {
+            if any_ok {
+                println!("{}", serde_json::to_string(&all_views)?);
+            }
+            if let Some(err) = ctx.notifiers.take_error() {
+                report_error(true, &format!("Notification failed: {err}"));
+            }
+            if let Some(err) = ctx.history_error() {
+                report_error(true, &format!("History write failed: {err}"));
+            }
+            if let Some(err) = ctx.metrics_error() {
+                report_error(true, &format!("Metrics write failed: {err}"));
+            }
+            if let Some(err) = ctx.heartbeat_error() {
+                report_error(true, &format!("Heartbeat ping failed: {err}"));
+            }
+            if let Some(err) = ctx.bell_error() {
+                report_error(true, &format!("Bell sound failed: {err}"));
+            }
+        } else if any_ok {
+            if let Some(err) = ctx.notifiers.take_error() {
+                println!("{}", format!("Notification failed: {err}").bad());
+            }
+            if let Some(err) = ctx.history_error() {
+                println!("{}", format!("History write failed: {err}").bad());
+            }
+            if let Some(err) = ctx.metrics_error() {
+                println!("{}", format!("Metrics write failed: {err}").bad());
+            }
+            if let Some(err) = ctx.heartbeat_error() {
+                println!("{}", format!("Heartbeat ping failed: {err}").bad());
+            }
+            if let Some(err) = ctx.bell_error() {
+                println!("{}", format!("Bell sound failed: {err}").bad());
+            }
+            if let Some(err) = ctx.chain.error() {
+                println!("{}", err.bad());
+            }
+            let status = if watcher.is_active() {
+                "Watching for database changes... (Ctrl+C to exit)".to_string()
+            } else {
+                format!(
+                    "Watching for changes every {}s... (Ctrl+C to exit)",
+                    ctx.schedule.current().as_secs()
+                )
+            };
+            println!("{}", status.dimmed());
+        }
+
+        let wait = ctx.schedule.next_wait(&watcher);
+        tokio::select! {
+            _ = sleep(wait) => {}
+            _ = watcher.changed() => {}
+            _ = ctx.chain.updated() => {}
+        }
+    }
+}
+
+/// Full-screen interactive watch mode, used when stdout is a terminal.
+pub async fn run_tui(mut ctx: WatchContext) -> Result<()> {
+    let mut terminal = Terminal::enter()?;
+    let mut clipboard = Clipboard::default();
+    let mut keys = tui::spawn_key_reader();
+    let mut resized = signal(SignalKind::window_change()).context("listen for SIGWINCH")?;
+
+    let pins = PinnedSwaps::new(ctx.pins.iter().map(String::from).chain(remembered_pins()));
+    let mut tabs: Vec<(Monitor, TuiState)> = Monitor::for_instances(
+        &ctx.instances,
+        &ctx.stuck,
+        &ctx.watchlist,
+        &pins,
+        ctx.history.as_ref(),
+    )
+    .into_iter()
+    .map(|monitor| {
+        let monitor = monitor
+            .with_page_size(ctx.page_size)
+            .with_notes(ctx.notes.clone());
+        let mut filter = ctx.filter.clone();
+        filter.sort = filter.sort.or_else(SortOrder::remembered);
+        let state = TuiState {
+            role: monitor.instance.role,
+            filter,
+            configured: ctx.filter.states.clone(),
+            board: ctx.board,
+            chart: ctx.chart,
+            chart_window: ctx.chart_window,
+            ticker_size: ctx.ticker,
+            fade: ctx.fade,
+            ..TuiState::default()
+        };
+        (monitor, state)
+    })
+    .collect();
+    // Carry on from the transitions recorded before this start
+    if let Some(ref history) = ctx.history {
+        for (monitor, state) in &mut tabs {
+            if let Ok(recent) = history.recent(&monitor.instance.name, ctx.ticker).await {
+                state.observe(&recent);
+            }
+        }
+    }
+    let mut watcher = ctx.watcher();
+    let multi = tabs.len() > 1;
+    // Chosen with --table-columns, or picked with C last time
+    let chosen = ctx.table_columns.clone().or_else(columns::remembered);
+    let mut columns = Columns::for_chain(&ctx.chain).with_chosen(chosen);
+    ctx.chain.start();
+    let mut active = 0;
+    // Last notifier failure and history or metrics write failure, shown
+    // above the key legend until the next one
+    let mut notify_error: Option<String> = None;
+    let mut write_error: Option<String> = None;
+    // What the last key did, when it has something to say; gone with the
+    // next key
+    let mut notice: Option<String> = None;
+    let mut next_poll = Instant::now();
+    // Redraw every FADE_TICK while a highlight is fading
+    let mut fading = false;
+
+    // The balance panel and the blank line after it, when shown
+    let panel_rows = match balance_lines(&ctx.chain).len() {
+        0 => 0,
+        n => n + 1,
+    };
+
+    loop {
+        let (width, height) = terminal.size();
+        // The tab bar costs one row when several instances are shown
+        let chrome = usize::from(multi) + panel_rows;
+        for (monitor, state) in &mut tabs {
+            // The anomaly panel and the blank line after it, when shown
+            let anomaly_rows = match state.anomaly_panel(&monitor.instance).len() {
+                0 => 0,
+                n => n + 1,
+            };
+            let chart_rows = usize::from(state.chart);
+            let ticker_rows = match state.ticker.len() {
+                0 => 0,
+                n => n + 2,
+            };
+            state.page = table_capacity(
+                height.saturating_sub(chrome + anomaly_rows + chart_rows + ticker_rows),
+            );
+            state.width = width;
+            state.columns = columns.fit(Some(width));
+        }
+
+        tokio::select! {
+            _ = sleep_until(next_poll) => {
+                let mut activity = false;
+                let mut ring = false;
+                let mut any_ok = false;
+                let mut failures = Vec::new();
+                for (monitor, state) in &mut tabs {
+                    match monitor.poll().await {
+                        Ok(rows) => {
+                            let (views, transitions) = monitor.update(rows).await;
+                            activity |= !transitions.is_empty();
+                            any_ok = true;
+                            ctx.notifiers.dispatch(&transitions);
+                            ring |= ctx.bell.ring(&transitions, monitor.instance.role);
+                            ctx.record_metrics(&monitor.instance, &views, &transitions);
+                            state.observe(&transitions);
+                            state.views = views;
+                            state.record_changes();
+                            state.anomalies = monitor.source.anomalies().to_vec();
+                            state.unloaded = monitor.source.unloaded();
+                            state.last_error = None;
+                        }
+                        Err(err) => {
+                            failures.push(format!("{}: {err}", monitor.instance.name));
+                            state.last_error = Some(err);
+                        }
+                    }
+                    if let Some(ref mut detail) = state.detail {
+                        detail.history = monitor
+                            .source
+                            .history(&detail.swap_id)
+                            .await
+                            .map_err(|e| e.to_string());
+                        detail.annotations = load_annotations(ctx.notes.as_ref(), &detail.swap_id).await;
+                    }
+                }
+                if any_ok {
+                    ctx.schedule.record(activity);
+                }
+                digest::send_if_due(&mut ctx);
+                ctx.flush_metrics();
+                ctx.beat(&failures);
+                if let Some(err) = ctx.notifiers.take_error() {
+                    notify_error = Some(err);
+                }
+                if let Some(err) = ctx.history_error() {
+                    write_error = Some(format!("History write failed: {err}"));
+                }
+                if let Some(err) = ctx.metrics_error() {
+                    write_error = Some(format!("Metrics write failed: {err}"));
+                }
+                if let Some(err) = ctx.heartbeat_error() {
+                    write_error = Some(format!("Heartbeat ping failed: {err}"));
+                }
+                if let Some(err) = ctx.bell_error() {
+                    write_error = Some(format!("Bell sound failed: {err}"));
+                }
+                if ring {
+                    terminal.bell()?;
+                }
+                next_poll = Instant::now() + ctx.schedule.next_wait(&watcher);
+            }
+            _ = watcher.changed() => {
+                // Poll straight away on the next pass instead of redrawing
+                next_poll = Instant::now();
+                continue;
+            }
+            key = keys.recv() => {
+                let Some(key) = key else { break };
+                notice = None;
+                // Typing a search or a note owns every key
+                let switch = multi && !tabs[active].1.typing();
+                match key {
+                    Key::Tab if switch => active = (active + 1) % tabs.len(),
+                    Key::BackTab if switch => active = (active + tabs.len() - 1) % tabs.len(),
+                    Key::Char(c @ '1'..='9') if switch && (c as usize - '1' as usize) < tabs.len() => {
+                        active = c as usize - '1' as usize;
+                    }
+                    _ => {
+                        let (monitor, state) = &mut tabs[active];
+                        match state.handle_key(key) {
+                            KeyOutcome::Quit => break,
+                            KeyOutcome::Refresh => next_poll = Instant::now(),
+                            KeyOutcome::OpenDetail(swap_id) => {
+                                let history = monitor
+                                    .source
+                                    .history(&swap_id)
+                                    .await
+                                    .map_err(|e| e.to_string());
+                                let annotations = load_annotations(ctx.notes.as_ref(), &swap_id).await;
+                                state.detail = Some(DetailPane {
+                                    swap_id,
+                                    history,
+                                    annotations,
+                                    copied_tx: None,
+                                });
+                            }
+                            KeyOutcome::Copy(text, what, hint) => {
+                                let done = copy(&mut clipboard, &mut terminal, &text, &what);
+                                notice = Some(match hint {
+                                    Some(hint) => format!("{done} · {hint}"),
+                                    None => done,
+                                });
+                            }
+                            KeyOutcome::OpenTx => {
+                                notice = Some(open_latest_tx(state, &ctx.explorer, monitor.instance.network).await);
+                            }
+                            KeyOutcome::TogglePin(swap_id) => {
+                                let pins = monitor.tracker.pins();
+                                let pinned = pins.toggle(&swap_id);
+                                pins.mark(&mut state.views);
+                                remember_pin(&swap_id, pinned);
+                                let verb = match pinned {
+                                    true => "Pinned",
+                                    false => "Unpinned",
+                                };
+                                notice = Some(format!("{verb} {}", display_id(&swap_id)));
+                            }
+                            KeyOutcome::Annotate(kind, swap_id, input) => {
+                                match annotate(ctx.notes.as_ref(), kind, &swap_id, &input).await {
+                                    Ok(done) => notice = Some(done),
+                                    Err(err) => write_error = Some(format!("Notes write failed: {err:#}")),
+                                }
+                                if let Some(ref mut detail) = state.detail {
+                                    detail.annotations = load_annotations(ctx.notes.as_ref(), &swap_id).await;
+                                }
+                                // The table's tags come with the next poll
+                                next_poll = Instant::now();
+                            }
+                            KeyOutcome::SetColumns(chosen) => {
+                                columns::remember(&chosen);
+                                columns = columns.with_chosen(Some(chosen));
+                                state.columns = columns.fit(state.columns.room);
+                            }
+                            KeyOutcome::Redraw => {}
+                        }
+                        // Near the end of what is read, read the next page
+                        if state.wants_more() {
+                            monitor.source.load_more();
+                            next_poll = Instant::now();
+                        }
+                    }
+                }
+            }
+            _ = resized.recv() => {}
+            _ = sleep(FADE_TICK), if fading => {}
+            // Fresh confirmations; the redraw below picks them up
+            _ = ctx.chain.updated() => {}
+        }
+        ctx.notifiers.dispatch_liquidity(&ctx.chain.take_alerts());
+        ctx.notifiers.dispatch_fees(&ctx.chain.take_fee_alerts());
+        ctx.notifiers.dispatch_asb(&ctx.chain.take_asb_alerts());
+        ctx.notifiers.dispatch_reach(&ctx.chain.take_reach_alerts());
+        ctx.notifiers
+            .dispatch_quotes(&ctx.chain.take_quote_alerts());
+        let log_alerts = ctx.take_log_alerts();
+        ctx.notifiers.dispatch_log(&log_alerts);
+
+        fading = false;
+        for (_, state) in &mut tabs {
+            fading |= state.fade_highlights();
+        }
+
+        let mut frame = Vec::new();
+        if multi {
+            frame.push(tab_bar(&tabs, active));
+        }
+        let (monitor, state) = &mut tabs[active];
+        let (network, role) = (monitor.instance.network, monitor.instance.role);
+        // Countdowns move with the chain, not with the database
+        ctx.chain
+            .annotate(&monitor.instance.name, &mut state.views, role);
+        frame.extend(header_lines(
+            &monitor.instance.db_path,
+            network,
+            role,
+            None,
+            &ctx.chain,
+            &ctx.rendezvous_points,
+            state.columns.with_peers(&state.views).header_width(),
+        ));
+        match state.detail {
+            Some(ref detail) => match detail.history {
+                Ok(ref history) => {
+                    let confirmations = ctx
+                        .chain
+                        .cached(history.iter().flat_map(|h| h.details.txids.values()));
+                    frame.extend(history_lines(
+                        &detail.swap_id,
+                        history,
+                        network,
+                        role,
+                        &confirmations,
+                        &ctx.explorer,
+                    ));
+                    if let Some(ref annotations) = detail.annotations {
+                        frame.push(String::new());
+                        frame.extend(annotation_lines(annotations));
+                    }
+                    // Below the history, down to the status line, when there
+                    // is room for a line or two
+                    let rows = height.saturating_sub(frame.len() + 3);
+                    if let Some(log) = ctx.asb_log.as_ref().filter(|_| rows >= 3) {
+                        frame.push(String::new());
+                        frame.extend(log_lines(
+                            log,
+                            &detail.swap_id,
+                            history,
+                            network,
+                            role,
+                            rows,
+                        ));
+                    }
+                }
+                Err(ref err) => frame.push(format!("Error: {err}").bad().to_string()),
+            },
+            None => {
+                let balances = balance_lines(&ctx.chain);
+                if !balances.is_empty() {
+                    frame.extend(balances);
+                    frame.push(String::new());
+                }
+                let anomalies = state.anomaly_panel(&monitor.instance);
+                if !anomalies.is_empty() {
+                    frame.extend(anomalies);
+                    frame.push(String::new());
+                }
+                frame.extend(state.list_lines(network, role, &ctx.schedule.describe(&watcher)));
+                // Down at the bottom, above the status line
+                let ticker = state.ticker_panel(network, role);
+                if !ticker.is_empty() {
+                    frame.resize(height.saturating_sub(2 + ticker.len()), String::new());
+                    frame.extend(ticker);
+                }
+            }
+        }
+
+        // Pin the key legend to the last row
+        let legend = match (state.detail.is_some(), multi) {
+            _ if state.search_input.is_some() => {
+                " type part of a swap id  Enter keep (opens a single match)  Esc cancel "
+            }
+            _ if state.annotating.is_some() => {
+                " Enter save  Esc cancel  (tags are separated by spaces; -TAG takes one off) "
+            }
+            _ if state.picker.is_some() => {
+                " ↑/↓ j/k select  Space show/hide  J/K move down/up  d usual columns  Enter keep  Esc cancel "
+            }
+            (true, _) => " Esc/h back  o open in explorer  y/Y copy id/txid  p pin  n note  t tag  T time  r refresh  q quit ",
+            (false, false) if state.board => {
+                " ←/→ h/l column  ↑/↓ j/k card  Enter details  / search  f filter  a finished  b table  c chart  C columns  T time  p pin  y copy id  r refresh  q quit "
+            }
+            (false, true) if state.board => {
+                " ←/→ h/l column  ↑/↓ j/k card  Enter details  / search  f filter  a finished  b table  c chart  C columns  T time  p pin  y copy id  Tab/1-9 instance  r refresh  q quit "
+            }
+            (false, false) => {
+                " ↑/↓ j/k select  PgUp/PgDn page  g/G top/bottom  Enter details  / search  f filter  a finished  s/S sort  b board  c chart  C columns  T time  p pin  y copy id  r refresh  q quit "
+            }
+            (false, true) => {
+                " ↑/↓ j/k select  PgUp/PgDn page  g/G top/bottom  Enter details  / search  f filter  a finished  s/S sort  b board  c chart  C columns  T time  p pin  y copy id  Tab/1-9 instance  r refresh  q quit "
+            }
+        };
+        frame.resize(height.saturating_sub(2), String::new());
+        let status = match (&notify_error, &write_error, ctx.chain.error()) {
+            _ if state.search_input.is_some() => {
+                format!("/{}▏", state.search_input.as_deref().unwrap_or_default())
+            }
+            _ if state.annotating.is_some() => match state.annotating {
+                Some((Annotating::Note, ref input)) => format!("Note: {input}▏"),
+                Some((Annotating::Tags, ref input)) => format!("Tags: {input}▏"),
+                None => String::new(),
+            },
+            (Some(err), _, _) => format!("Notification failed: {err}").bad().to_string(),
+            (None, Some(err), _) => err.bad().to_string(),
+            (None, None, Some(err)) => err.bad().to_string(),
+            (None, None, None) => notice.as_deref().unwrap_or_default().dimmed().to_string(),
+        };
+        frame.push(status);
+        frame.push(legend.reversed().to_string());
+        terminal.draw(&frame)?;
+    }
+
+    Ok(())
+}
+
+/// Swaps pinned with `p` in earlier runs.
+fn remembered_pins() -> Vec<String> {
+    let Some(body) = pins_path().and_then(|path| std::fs::read_to_string(path).ok()) else {
+        return Vec::new();
+    };
+    body.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+/// Keep a pin made with `p` for the next start, or drop one. Best effort,
+/// like the remembered sort order.
+fn remember_pin(swap_id: &str, pinned: bool) {
+    let Some(path) = pins_path() else {
+        return;
+    };
+    let mut pins = PinnedSwaps::new(remembered_pins());
+    if pins.contains(swap_id) != pinned {
+        pins.toggle(swap_id);
+    }
+    if let Some(dir) = path.parent() {
+        let _ = std::fs::create_dir_all(dir);
+    }
+    let body: String = pins.iter().map(|id| format!("{id}\n")).collect();
+    let _ = std::fs::write(path, body);
+}
+
+fn pins_path() -> Option<PathBuf> {
+    Some(dirs::cache_dir()?.join("wraithswap/pins"))
+}
+
+/// The detail pane's tags and notes; unreadable notes only leave them out.
+async fn load_annotations(notes: Option<&NotesStore>, swap_id: &str) -> Option<Annotations> {
+    let annotations = notes?.annotations(Some(swap_id)).await.ok()?;
+    annotations.into_iter().next()
+}
+
+/// Save what was typed after `n` or `t`, saying what was done for the
+/// status line. Tags are separated by spaces and `-TAG` takes one off.
+async fn annotate(
+    notes: Option<&NotesStore>,
+    kind: Annotating,
+    swap_id: &str,
+    input: &str,
+) -> Result<String> {
+    let notes = notes.context(notes::NO_NOTES_DB)?;
+    if kind == Annotating::Note {
+        notes.add_note(swap_id, input.trim()).await?;
+        return Ok(format!("Added a note to {}", display_id(swap_id)));
+    }
+    let (mut added, mut removed) = (Vec::new(), Vec::new());
+    for word in input.split_whitespace() {
+        match word.strip_prefix('-') {
+            Some(tag) => removed.push(notes::parse_tag(tag)?),
+            None => added.push(notes::parse_tag(word)?),
+        }
+    }
+    notes.add_tags(swap_id, &added).await?;
+    if notes.remove_tags(swap_id, &removed).await? == 0 {
+        removed.clear();
+    }
+    let mut done = Vec::new();
+    if !added.is_empty() {
+        done.push(format!("tagged {}", added.join(", ")));
+    }
+    if !removed.is_empty() {
+        done.push(format!("untagged {}", removed.join(", ")));
+    }
+    if done.is_empty() {
+        done.push("had none of those tags".to_string());
+    }
+    Ok(format!("{} {}", display_id(swap_id), done.join(" · ")))
+}
+
+/// Put `text` on the clipboard, through the terminal when there is no
+/// desktop clipboard to use, and say how that went.
+fn copy(clipboard: &mut Clipboard, terminal: &mut Terminal, text: &str, what: &str) -> String {
+    match clipboard.copy(text) {
+        Ok(true) => format!("Copied {what}"),
+        Ok(false) => match terminal.copy(text) {
+            Ok(()) => format!("Sent {what} to the terminal's clipboard"),
+            Err(err) => format!("Couldn't copy {what}: {err:#}"),
+        },
+        Err(err) => format!("Couldn't copy {what}: {err:#}"),
+    }
+}
+
+/// Open the latest BTC transaction of the swap in the detail pane, saying
+/// how that went for the status line.
+async fn open_latest_tx(state: &TuiState, explorer: &Explorer, network: Network) -> String {
+    let Some(DetailPane {
+        history: Ok(ref history),
+        ..
+    }) = state.detail
+    else {
+        return String::new();
+    };
+    let mut details = SwapDetails::default();
+    for entry in history {
+        details.merge(entry.details.clone());
+    }
+    let Some((label, txid)) = details.latest_btc_tx() else {
+        return "No BTC transaction yet".to_string();
+    };
+    let url = explorer.tx_url(network, txid);
+    match explorer::open(&url).await {
+        Ok(true) => format!("Opened {label} in the browser"),
+        Ok(false) => format!("No browser here; {url}"),
+        Err(err) => format!("Couldn't open a browser ({err:#}); {url}"),
+    }
+}
+
+/// One tab per instance with a connection dot; the active tab is reversed.
+fn tab_bar(tabs: &[(Monitor, TuiState)], active: usize) -> String {
+    tabs.iter()
+        .enumerate()
+        .map(|(i, (monitor, state))| {
+            let label = format!(" {} {} ", i + 1, monitor.instance.name);
+            let label = if i == active {
+                label.reversed()
+            } else {
+                label.normal()
+            };
+            let dot = match state.last_error {
+                None => "●".good(),
+                Some(_) => "●".bad(),
+            };
+            format!("{label}{dot} ")
+        })
+        .collect()
+}
+
+#[derive(Default)]
+pub struct TuiState {
+    pub views: Vec<SwapView>,
+    pub last_error: Option<PollError>,
+    /// Tracked by id so the cursor stays on the same swap when new ones
+    /// arrive above it
+    pub selected_id: Option<String>,
+    pub scroll: usize,
+    pub page: usize,
+    pub detail: Option<DetailPane>,
+    /// Unexpected transitions found in the database so far
+    pub anomalies: Vec<Anomaly>,
+    pub role: Role,
+    /// Which swaps the table shows; `f` cycles through the states and `a`
+    /// shows or hides finished swaps
+    pub filter: TableFilter,
+    /// `--state`'s selection, one of the choices when it is none of the
+    /// others
+    pub configured: StateSelection,
+    /// What is typed after `/`, while it is
+    pub search_input: Option<String>,
+    /// What is typed after `n` or `t` in the detail pane, while it is
+    pub annotating: Option<(Annotating, String)>,
+    /// The column picker, while open
+    pub picker: Option<ColumnPicker>,
+    /// Finished swaps older than those read so far
+    pub unloaded: usize,
+    /// Cards in a column per state instead of the table; `b` switches
+    pub board: bool,
+    /// Swaps started per hour or day above the table; `c` switches
+    pub chart: bool,
+    pub chart_window: Duration,
+    /// The latest transitions observed, oldest first, kept across polls
+    pub ticker: VecDeque<Transition>,
+    /// How many of them to keep
+    pub ticker_size: usize,
+    /// When each swap's state last changed, while it is highlighted
+    pub changed_at: HashMap<String, Instant>,
+    pub fade: Duration,
+    /// Terminal columns, for laying out the board
+    pub width: usize,
+    /// The table's columns, fitted to the terminal
+    pub columns: Columns,
+}
+
+pub struct DetailPane {
+    pub swap_id: String,
+    pub history: std::result::Result<Vec<HistoryRow>, String>,
+    /// The swap's tags and notes, when it has any
+    pub annotations: Option<Annotations>,
+    /// Which of its transactions `Y` copied last
+    pub copied_tx: Option<usize>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Annotating {
+    Note,
+    Tags,
+}
+
+pub enum KeyOutcome {
+    Redraw,
+    Refresh,
+    Quit,
+    OpenDetail(String),
+    /// Open the detail pane's latest BTC transaction in the explorer
+    OpenTx,
+    /// Pin the swap, or unpin it
+    TogglePin(String),
+    /// Add a note to the swap, or tags as typed
+    Annotate(Annotating, String, String),
+    /// Copy the text to the clipboard; then what it is, for the notice,
+    /// and what else to say there
+    Copy(String, String, Option<&'static str>),
+    /// Show these columns in the table, in this order
+    SetColumns(Vec<TableColumn>),
+}
+
+/// Choosing the table's columns with `C`: every column, those shown first
+/// and in their order.
+pub struct ColumnPicker {
+    /// Each column and whether it is shown
+    pub columns: Vec<(TableColumn, bool)>,
+    /// The one under the cursor
+    pub at: usize,
+}
+
+impl ColumnPicker {
+    fn new(columns: &Columns) -> Self {
+        let shown = columns.shown();
+        let hidden = columns::ALL.into_iter().filter(|c| !shown.contains(c));
+        Self {
+            columns: shown
+                .iter()
+                .map(|&column| (column, true))
+                .chain(hidden.map(|column| (column, false)))
+                .collect(),
+            at: 0,
+        }
+    }
+
+    fn lines(&self, columns: &Columns) -> Vec<String> {
+        let mut lines = vec!["Table columns".bold().to_string()];
+        for (i, &(column, shown)) in self.columns.iter().enumerate() {
+            let mark = if shown { "[x]" } else { "[ ]" };
+            let line = format!(" {mark} {:<10}  {:<10}", column.title(), column.name());
+            let line = match i == self.at {
+                true => line.reversed().to_string(),
+                false => line,
+            };
+            let needs = match column {
+                _ if columns.available(column) => None,
+                TableColumn::Value => Some("needs --fiat"),
+                TableColumn::XmrConf => Some("needs --monero-rpc"),
+                _ => Some("needs --electrum"),
+            };
+            lines.push(match needs {
+                Some(needs) => format!("{line}  {}", needs.dimmed()),
+                None => line,
+            });
+        }
+        lines
+    }
+}
+
+impl TuiState {
+    /// The detail pane's transaction after the one `Y` copied last, in the
+    /// order the pane lists them, what it is, and whether there are others.
+    fn next_txid(&mut self) -> Option<(String, String, bool)> {
+        let detail = self.detail.as_mut()?;
+        let history = detail.history.as_ref().ok()?;
+        let mut txids = BTreeMap::new();
+        for entry in history {
+            txids.extend(entry.details.txids.iter());
+        }
+        if txids.is_empty() {
+            return None;
+        }
+        let count = txids.len();
+        let next = detail.copied_tx.map_or(0, |i| (i + 1) % count);
+        detail.copied_tx = Some(next);
+        let (label, txid) = txids.into_iter().nth(next)?;
+        let what = format!("{label} txid {}", short_id(txid));
+        Some((txid.clone(), what, count > 1))
+    }
+
+    /// Add what a poll observed to the ticker. Stuck and anomaly alerts
+    /// aren't transitions of their own.
+    fn observe(&mut self, transitions: &[Transition]) {
+        let observed = transitions
+            .iter()
+            .filter(|t| t.stuck_for_secs.is_none() && t.anomaly.is_none());
+        self.ticker.extend(observed.cloned());
+        let excess = self.ticker.len().saturating_sub(self.ticker_size);
+        self.ticker.drain(..excess);
+    }
+
+    /// Note when the swaps that changed in this poll did so.
+    fn record_changes(&mut self) {
+        let now = Instant::now();
+        for view in self.views.iter().filter(|v| v.changed) {
+            self.changed_at.insert(view.swap_id.clone(), now);
+        }
+    }
+
+    /// Highlight recently changed states, brightly for the first half of
+    /// `fade` and dimly for the second. Without a fade the highlight lasts
+    /// until the next poll. Whether any highlight is still fading.
+    fn fade_highlights(&mut self) -> bool {
+        if self.fade.is_zero() {
+            return false;
+        }
+        let now = Instant::now();
+        self.changed_at.retain(|_, at| now - *at < self.fade);
+        for view in &mut self.views {
+            view.highlight = match self.changed_at.get(&view.swap_id) {
+                Some(at) if now - *at < self.fade / 2 => Highlight::Bright,
+                Some(_) => Highlight::Dim,
+                None => Highlight::None,
+            };
+        }
+        !self.changed_at.is_empty()
+    }
+
+    /// A blank line, a title and the ticker, or nothing before the first
+    /// transition.
+    fn ticker_panel(&self, network: Network, role: Role) -> Vec<String> {
+        if self.ticker.is_empty() {
+            return Vec::new();
+        }
+        let mut lines = vec![String::new(), "Recent transitions".bold().to_string()];
+        lines.extend(self.ticker.iter().map(|t| ticker_line(t, network, role)));
+        lines
+    }
+
+    fn anomaly_panel(&self, instance: &Instance) -> Vec<String> {
+        anomaly_lines(&self.anomalies, instance.network, instance.role)
+    }
+
+    /// Whether the selection is on the table's last page, or near the end
+    /// of a finished column of the board, while older swaps are still
+    /// unread.
+    fn wants_more(&self) -> bool {
+        if self.unloaded == 0 || self.filter.hide_finished || self.detail.is_some() {
+            return false;
+        }
+        let shown = self.shown();
+        match self.board {
+            true => Board::new(&shown, self.role).near_end(self.selected_id.as_deref(), self.page),
+            false => self.selected_index() + self.swaps_per_page() >= shown.len(),
+        }
+    }
+
+    /// Swaps that fit on a page of the table, which takes two lines for
+    /// each in the compact layout.
+    fn swaps_per_page(&self) -> usize {
+        let layout = self.columns.with_peers(&self.views).layout();
+        (self.page / layout.rows_per_swap()).max(1)
+    }
+
+    /// Moving between the board's cards. False for keys that do the same
+    /// on the board as in the table.
+    fn board_key(&mut self, key: Key) -> bool {
+        let page = self.page as isize;
+        let (lanes, rows) = match key {
+            Key::Left | Key::Char('h') => (-1, 0),
+            Key::Right | Key::Char('l') => (1, 0),
+            Key::Up | Key::Char('k') => (0, -1),
+            Key::Down | Key::Char('j') => (0, 1),
+            Key::PageUp => (0, -page),
+            Key::PageDown => (0, page),
+            Key::Home | Key::Char('g') => (0, isize::MIN),
+            Key::End | Key::Char('G') => (0, isize::MAX),
+            _ => return false,
+        };
+        let shown = self.shown();
+        let next = Board::new(&shown, self.role)
+            .moved(self.selected_id.as_deref(), lanes, rows)
+            .map(String::from);
+        self.selected_id = next;
+        true
+    }
+
+    /// Whether keys are going into a search or a note rather than moving
+    /// around.
+    fn typing(&self) -> bool {
+        self.search_input.is_some() || self.annotating.is_some() || self.picker.is_some()
+    }
+
+    /// The swaps the filter lets through, in table order.
+    fn shown(&self) -> Vec<&SwapView> {
+        self.filter.apply(&self.views, self.role)
+    }
+
+    pub fn selected_index(&self) -> usize {
+        self.selected_id
+            .as_ref()
+            .and_then(|id| self.shown().iter().position(|v| &v.swap_id == id))
+            .unwrap_or(0)
+    }
+
+    pub fn select(&mut self, index: usize) {
+        let shown = self.shown();
+        let index = index.min(shown.len().saturating_sub(1));
+        self.selected_id = shown.get(index).map(|v| v.swap_id.clone());
+    }
+
+    /// Typing after `/` narrows the table as it goes. Enter keeps the
+    /// search and opens the swap when only one is left; Esc drops it.
+    fn search_key(&mut self, mut input: String, key: Key) -> KeyOutcome {
+        match key {
+            Key::Interrupt => return KeyOutcome::Quit,
+            Key::Esc => {
+                self.filter.search = None;
+                return KeyOutcome::Redraw;
+            }
+            Key::Enter => {
+                let shown = self.shown();
+                if let [only] = shown.as_slice() {
+                    return KeyOutcome::OpenDetail(only.swap_id.clone());
+                }
+                return KeyOutcome::Redraw;
+            }
+            Key::Backspace => {
+                input.pop();
+            }
+            Key::Char(c) => input.push(c),
+            _ => {}
+        }
+        let search = SwapSearch::new(&input);
+        self.filter.search = (!search.is_empty()).then_some(search);
+        self.search_input = Some(input);
+        self.scroll = 0;
+        self.select(0);
+        KeyOutcome::Redraw
+    }
+
+    /// Typing after `n` or `t` in the detail pane. Enter saves what is
+    /// typed, Esc drops it.
+    fn annotate_key(&mut self, kind: Annotating, mut input: String, key: Key) -> KeyOutcome {
+        match key {
+            Key::Interrupt => return KeyOutcome::Quit,
+            Key::Esc => return KeyOutcome::Redraw,
+            Key::Enter => {
+                let swap_id = self.detail.as_ref().map(|d| d.swap_id.clone());
+                return match swap_id.filter(|_| !input.trim().is_empty()) {
+                    Some(swap_id) => KeyOutcome::Annotate(kind, swap_id, input),
+                    None => KeyOutcome::Redraw,
+                };
+            }
+            Key::Backspace => {
+                input.pop();
+            }
+            Key::Char(c) => input.push(c),
+            _ => {}
+        }
+        self.annotating = Some((kind, input));
+        KeyOutcome::Redraw
+    }
+
+    /// In the column picker: Space shows or hides a column and `J`/`K`
+    /// move it. Enter keeps the choice, Esc drops it.
+    fn picker_key(&mut self, mut picker: ColumnPicker, key: Key) -> KeyOutcome {
+        let (at, last) = (picker.at, picker.columns.len() - 1);
+        match key {
+            Key::Interrupt | Key::Char('q') => return KeyOutcome::Quit,
+            Key::Esc => return KeyOutcome::Redraw,
+            Key::Enter => {
+                let chosen: Vec<TableColumn> = picker
+                    .columns
+                    .iter()
+                    .filter(|(_, shown)| *shown)
+                    .map(|&(column, _)| column)
+                    .collect();
+                if !chosen.is_empty() {
+                    return KeyOutcome::SetColumns(chosen);
+                }
+            }
+            Key::Up | Key::Char('k') => picker.at = at.saturating_sub(1),
+            Key::Down | Key::Char('j') => picker.at = (at + 1).min(last),
+            Key::Home | Key::Char('g') => picker.at = 0,
+            Key::End | Key::Char('G') => picker.at = last,
+            Key::Char(' ') | Key::Char('x') => picker.columns[at].1 ^= true,
+            Key::Char('K') if at > 0 => {
+                picker.columns.swap(at, at - 1);
+                picker.at -= 1;
+            }
+            Key::Char('J') if at < last => {
+                picker.columns.swap(at, at + 1);
+                picker.at += 1;
+            }
+            // Back to the usual columns, to keep with Enter
+            Key::Char('d') => {
+                let usual = self.columns.with_chosen(None).with_peers(&self.views);
+                picker = ColumnPicker::new(&usual);
+            }
+            _ => {}
+        }
+        self.picker = Some(picker);
+        KeyOutcome::Redraw
+    }
+
+    /// Everything, then active, failed and completed swaps, then
+    /// `--state`'s selection.
+    fn next_filter(&mut self) {
+        let mut choices = vec![
+            StateSelection::default(),
+            StateSelection::only(Outcome::InProgress),
+            StateSelection::only(Outcome::Failed),
+            StateSelection::only(Outcome::Completed),
+        ];
+        if !choices.contains(&self.configured) {
+            choices.push(self.configured.clone());
+        }
+        let current = choices.iter().position(|f| *f == self.filter.states);
+        self.filter.states = choices[current.map_or(0, |i| (i + 1) % choices.len())].clone();
+        self.scroll = 0;
+    }
+
+    pub fn handle_key(&mut self, key: Key) -> KeyOutcome {
+        if let Some(input) = self.search_input.take() {
+            return self.search_key(input, key);
+        }
+        if let Some((kind, input)) = self.annotating.take() {
+            return self.annotate_key(kind, input, key);
+        }
+        if let Some(picker) = self.picker.take() {
+            return self.picker_key(picker, key);
+        }
+        match key {
+            Key::Interrupt | Key::Char('q') => return KeyOutcome::Quit,
+            Key::Char('r') => return KeyOutcome::Refresh,
+            Key::Char('T') => {
+                let (format, clock) = time_settings();
+                set_time_format(format.next(), clock);
+                return KeyOutcome::Redraw;
+            }
+            _ => {}
+        }
+
+        if self.detail.is_some() {
+            match key {
+                Key::Esc | Key::Backspace | Key::Char('h') => self.detail = None,
+                Key::Char('o') => return KeyOutcome::OpenTx,
+                Key::Char('y') => {
+                    let swap_id = self.detail.as_ref().map(|d| d.swap_id.clone());
+                    let swap_id = swap_id.unwrap_or_default();
+                    let what = format!("swap id {}", display_id(&swap_id));
+                    return KeyOutcome::Copy(swap_id, what, None);
+                }
+                Key::Char('Y') => {
+                    return match self.next_txid() {
+                        Some((txid, what, more)) => {
+                            KeyOutcome::Copy(txid, what, more.then_some("Y for the next"))
+                        }
+                        None => KeyOutcome::Redraw,
+                    };
+                }
+                Key::Char('n') => self.annotating = Some((Annotating::Note, String::new())),
+                Key::Char('t') => self.annotating = Some((Annotating::Tags, String::new())),
+                Key::Char('p') => {
+                    let swap_id = self.detail.as_ref().map(|d| d.swap_id.clone());
+                    return KeyOutcome::TogglePin(swap_id.unwrap_or_default());
+                }
+                _ => {}
+            }
+            return KeyOutcome::Redraw;
+        }
+
+        if self.board && self.board_key(key) {
+            return KeyOutcome::Redraw;
+        }
+        let current = self.selected_index();
+        match key {
+            Key::Up | Key::Char('k') => self.select(current.saturating_sub(1)),
+            Key::Down | Key::Char('j') => self.select(current + 1),
+            Key::PageUp => self.select(current.saturating_sub(self.swaps_per_page())),
+            Key::PageDown => self.select(current + self.swaps_per_page()),
+            Key::Home | Key::Char('g') => self.select(0),
+            Key::End | Key::Char('G') => self.select(usize::MAX),
+            Key::Char('f') => self.next_filter(),
+            Key::Char('p') => {
+                if let Some(view) = self.shown().get(current) {
+                    return KeyOutcome::TogglePin(view.swap_id.clone());
+                }
+            }
+            Key::Char('y') => {
+                if let Some(view) = self.shown().get(current) {
+                    let what = format!("swap id {}", display_id(&view.swap_id));
+                    return KeyOutcome::Copy(view.swap_id.clone(), what, None);
+                }
+            }
+            Key::Char('s') | Key::Char('S') => {
+                let sort = self.filter.sort.unwrap_or_default();
+                let sort = match key {
+                    Key::Char('s') => sort.next_key(),
+                    _ => sort.reversed(),
+                };
+                sort.remember();
+                self.filter.sort = Some(sort);
+                self.scroll = 0;
+            }
+            Key::Char('/') => {
+                let current = self.filter.search.as_ref().map(|s| s.to_string());
+                self.search_input = Some(current.unwrap_or_default());
+            }
+            Key::Esc => self.filter.search = None,
+            Key::Char('a') => {
+                self.filter.hide_finished = !self.filter.hide_finished;
+                self.scroll = 0;
+            }
+            Key::Char('b') => {
+                self.board = !self.board;
+                self.scroll = 0;
+            }
+            Key::Char('c') => self.chart = !self.chart,
+            Key::Char('C') => {
+                self.picker = Some(ColumnPicker::new(&self.columns.with_peers(&self.views)));
+            }
+            Key::Enter | Key::Char('l') => {
+                if let Some(view) = self.shown().get(current) {
+                    return KeyOutcome::OpenDetail(view.swap_id.clone());
+                }
+            }
+            _ => {}
+        }
+        KeyOutcome::Redraw
+    }
+
+    pub fn list_lines(&mut self, network: Network, role: Role, refresh: &str) -> Vec<String> {
+        if let Some(ref picker) = self.picker {
+            return picker.lines(&self.columns.with_peers(&self.views));
+        }
+        let mut lines = Vec::new();
+
+        if let Some(ref err) = self.last_error {
+            lines.push(format!("Error: {err}").bad().to_string());
+            if matches!(err, PollError::NotFound(_)) {
+                lines.push(role.start_hint(network).dimmed().to_string());
+            }
+            return lines;
+        }
+
+        if self.views.is_empty() {
+            lines.push("No swaps yet.".warn().to_string());
+            return lines;
+        }
+
+        // The summary counts every swap, shown or not
+        lines.push(summary_line(&self.views, role));
+        if self.chart {
+            lines.push(chart_line(&self.views, self.chart_window, self.unloaded));
+        }
+        let shown = self.shown();
+        if shown.is_empty() {
+            lines.push(self.filter.empty_line());
+            lines.extend(self.filter.hidden_line(&self.views, role));
+            return lines;
+        }
+
+        if self.board {
+            let board = Board::new(&shown, role);
+            // Something is always selected on the board
+            let selected = board.moved(self.selected_id.as_deref(), 0, 0);
+            // One more row than the table; the board's chrome is a row shorter
+            lines.extend(board.lines(self.width, self.page + 1, selected, network, role));
+            lines.push(
+                format!(
+                    "{} swaps{} · refresh {refresh}",
+                    shown.len(),
+                    self.footer_notes()
+                )
+                .dimmed()
+                .to_string(),
+            );
+            self.selected_id = selected.map(String::from);
+            return lines;
+        }
+
+        // Pinned swaps stay put above the rows that scroll, with a divider
+        // between them
+        let pinned = shown.iter().take_while(|view| view.pinned).count();
+        let rest = shown.len() - pinned;
+        let page = self.swaps_per_page();
+        let rows = match (pinned, rest) {
+            (0, _) => page,
+            (_, 0) => 0,
+            _ => page.saturating_sub(pinned + 1).max(1),
+        };
+
+        // Keep the selection inside the visible window
+        let selected = self.selected_index();
+        let mut scroll = self.scroll;
+        if let Some(at) = selected.checked_sub(pinned) {
+            if at < scroll {
+                scroll = at;
+            } else if at >= scroll + rows {
+                scroll = at + 1 - rows;
+            }
+        }
+        scroll = scroll.min(rest.saturating_sub(rows));
+
+        let columns = &self.columns.with_peers(&self.views);
+        lines.extend(table_top(columns));
+        for (i, view) in shown.iter().enumerate().take(pinned) {
+            lines.extend(table_row(view, network, role, i == selected, columns));
+        }
+        if pinned > 0 && rest > 0 {
+            lines.push(table_divider(columns));
+        }
+        for (i, view) in shown.iter().enumerate().skip(pinned + scroll).take(rows) {
+            lines.extend(table_row(view, network, role, i == selected, columns));
+        }
+        lines.push(table_bottom(columns));
+        let pins = match pinned {
+            0 => String::new(),
+            n => format!(" · {n} pinned"),
+        };
+        let first = match scroll {
+            0 => 1,
+            scroll => pinned + scroll + 1,
+        };
+        lines.push(
+            format!(
+                "Swaps {first}-{} of {}{pins}{} · refresh {refresh}",
+                pinned + (scroll + rows).min(rest),
+                shown.len(),
+                self.footer_notes(),
+            )
+            .dimmed()
+            .to_string(),
+        );
+        self.scroll = scroll;
+        lines
+    }
+
+    /// ` · 9500 older to load · state failed · 12 in all · by amount`: what
+    /// is left to read, how the swaps are narrowed down and their order,
+    /// for the footer.
+    fn footer_notes(&self) -> String {
+        let hidden = self.views.len() - self.shown().len();
+        let filter = match (self.filter.narrowed_by(), hidden) {
+            (Some(narrowed), _) => format!(" · {narrowed} · {} in all", self.views.len()),
+            (None, 0) => String::new(),
+            (None, hidden) => format!(" · {hidden} finished hidden"),
+        };
+        let unloaded = match self.unloaded {
+            0 => String::new(),
+            n => format!(" · {n} older to load"),
+        };
+        let sort = match self
+            .filter
+            .sort
+            .filter(|sort| *sort != SortOrder::default())
+        {
+            Some(sort) => format!(" · by {sort}"),
+            None => String::new(),
+        };
+        format!("{unloaded}{filter}{sort}")
+    }
+}
+
+/// Rows of the swap table that fit below the header and above the footer.
+/// The activity line for `views`, which only counts the swaps read so far
+/// while older ones are still unread.
+fn chart_line(views: &[SwapView], window: Duration, unloaded: usize) -> String {
+    let activity = Activity::new(views.iter().map(|v| v.started_at.as_str()), window);
+    match unloaded {
+        0 => activity_line(&activity),
+        _ => format!(
+            "{} {}",
+            activity_line(&activity),
+            "(of the swaps read)".dimmed()
+        ),
+    }
+}
+
+pub fn table_capacity(height: usize) -> usize {
+    // header (7 + blank), summary strip, table chrome (3 top + 1 bottom),
+    // status, notification line, footer
+    const CHROME: usize = 8 + 1 + 4 + 1 + 1 + 1;
+    height.saturating_sub(CHROME).max(1)
+}
@@ -0,0 +1,172 @@
+//! The server side of RFC 6455, as far as pushing JSON to browsers needs:
+//! the upgrade handshake, unmasked text frames out, and masked control
+//! frames in. Fragmented and oversized client messages are not supported;
+//! clients only ever send pings and closes here.
+
+use anyhow::{bail, Result};
+use base64::Engine;
+use sha1::{Digest, Sha1};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+/// Appended to the client's key before hashing, per the RFC
+const HANDSHAKE_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+/// Client frames above this are treated as a protocol error
+const MAX_CLIENT_PAYLOAD: u64 = 64 * 1024;
+
+pub const OPCODE_TEXT: u8 = 0x1;
+pub const OPCODE_CLOSE: u8 = 0x8;
+pub const OPCODE_PING: u8 = 0x9;
+pub const OPCODE_PONG: u8 = 0xA;
+
+/// `Sec-WebSocket-Accept` for a client's `Sec-WebSocket-Key`.
+pub fn accept_key(key: &str) -> String {
+    let digest = Sha1::new()
+        .chain_update(key.trim())
+        .chain_update(HANDSHAKE_GUID)
+        .finalize();
+    base64::engine::general_purpose::STANDARD.encode(digest)
+}
+
+/// The `101 Switching Protocols` response completing the handshake.
+pub fn handshake_response(key: &str) -> String {
+    format!(
+        "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+         Sec-WebSocket-Accept: {}\r\n\r\n",
+        accept_key(key)
+    )
+}
+
+#[derive(Debug)]
+pub struct Frame {
+    pub opcode: u8,
+    pub payload: Vec<u8>,
+}
+
+/// One unfragmented frame; servers never mask.
+pub async fn write_frame<W: AsyncWrite + Unpin>(
+    writer: &mut W,
+    opcode: u8,
+    payload: &[u8],
+) -> Result<()> {
+    let mut frame = vec![0x80 | opcode];
+    match payload.len() {
+        len @ 0..=125 => frame.push(len as u8),
+        len @ 126..=0xFFFF => {
+            frame.push(126);
+            frame.extend((len as u16).to_be_bytes());
+        }
+        len => {
+            frame.push(127);
+            frame.extend((len as u64).to_be_bytes());
+        }
+    }
+    frame.extend(payload);
+    writer.write_all(&frame).await?;
+    Ok(())
+}
+
+/// The next frame from a client, unmasked.
+pub async fn read_frame<R: AsyncRead + Unpin>(reader: &mut R) -> Result<Frame> {
+    let mut head = [0u8; 2];
+    reader.read_exact(&mut head).await?;
+    let opcode = head[0] & 0x0F;
+    if head[1] & 0x80 == 0 {
+        bail!("unmasked client frame");
+    }
+    let len = match head[1] & 0x7F {
+        126 => u64::from(reader.read_u16().await?),
+        127 => reader.read_u64().await?,
+        len => u64::from(len),
+    };
+    if len > MAX_CLIENT_PAYLOAD {
+        bail!("client frame too large ({len} bytes)");
+    }
+
+    let mut mask = [0u8; 4];
+    reader.read_exact(&mut mask).await?;
+    let mut payload = vec![0u8; len as usize];
+    reader.read_exact(&mut payload).await?;
+    for (i, byte) in payload.iter_mut().enumerate() {
+        *byte ^= mask[i % 4];
+    }
+    Ok(Frame { opcode, payload })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A client frame with RFC 6455's example mask.
+    fn masked(opcode: u8, len_bytes: &[u8], payload: &[u8]) -> Vec<u8> {
+        let mask = [0x37, 0xfa, 0x21, 0x3d];
+        let mut frame = vec![0x80 | opcode];
+        frame.extend(len_bytes);
+        frame[1] |= 0x80;
+        frame.extend(mask);
+        frame.extend(payload.iter().enumerate().map(|(i, b)| b ^ mask[i % 4]));
+        frame
+    }
+
+    async fn written(payload: &[u8]) -> Vec<u8> {
+        let mut out = Vec::new();
+        write_frame(&mut out, OPCODE_TEXT, payload).await.unwrap();
+        out
+    }
+
+    #[test]
+    fn accept_key_matches_the_rfc() {
+        assert_eq!(
+            accept_key("dGhlIHNhbXBsZSBub25jZQ==\r\n"),
+            "s3pPLMBiTxaQ9kYGzzhZRbK+xOo="
+        );
+        assert!(handshake_response("dGhlIHNhbXBsZSBub25jZQ==")
+            .contains("\r\nSec-WebSocket-Accept: s3pPLMBiTxaQ9kYGzzhZRbK+xOo=\r\n\r\n"));
+    }
+
+    #[tokio::test]
+    async fn lengths_pick_the_smallest_encoding() {
+        assert_eq!(written(b"Hello").await, b"\x81\x05Hello");
+        let out = written(&[b'x'; 126]).await;
+        assert_eq!(out[..4], [0x81, 126, 0x00, 126]);
+        assert_eq!(out.len(), 4 + 126);
+        let out = written(&[b'x'; 0x10000]).await;
+        assert_eq!(out[..10], [0x81, 127, 0, 0, 0, 0, 0, 1, 0, 0]);
+        assert_eq!(out.len(), 10 + 0x10000);
+    }
+
+    #[tokio::test]
+    async fn client_frames_are_unmasked() {
+        // The RFC's masked "Hello"
+        let hello = b"\x81\x85\x37\xfa\x21\x3d\x7f\x9f\x4d\x51\x58";
+        let frame = read_frame(&mut &hello[..]).await.unwrap();
+        assert_eq!(
+            (frame.opcode, frame.payload),
+            (OPCODE_TEXT, b"Hello".to_vec())
+        );
+
+        let payload = vec![7; 300];
+        let bytes = masked(OPCODE_PING, &[126, 0x01, 0x2c], &payload);
+        let frame = read_frame(&mut &bytes[..]).await.unwrap();
+        assert_eq!((frame.opcode, frame.payload), (OPCODE_PING, payload));
+
+        let bytes = masked(OPCODE_CLOSE, &[0], b"");
+        let frame = read_frame(&mut &bytes[..]).await.unwrap();
+        assert_eq!((frame.opcode, frame.payload.len()), (OPCODE_CLOSE, 0));
+    }
+
+    #[tokio::test]
+    async fn bad_client_frames_are_errors() {
+        let unmasked = read_frame(&mut &b"\x81\x05Hello"[..]).await;
+        assert!(unmasked.unwrap_err().to_string().contains("unmasked"));
+
+        let mut huge = vec![0x82, 0x80 | 127];
+        huge.extend((MAX_CLIENT_PAYLOAD + 1).to_be_bytes());
+        let err = read_frame(&mut &huge[..]).await.unwrap_err();
+        assert!(err.to_string().contains("too large"), "{err}");
+
+        // Cut off mid-payload
+        let bytes = masked(OPCODE_TEXT, &[5], b"Hello");
+        assert!(read_frame(&mut &bytes[..8]).await.is_err());
+    }
+}